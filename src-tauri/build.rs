@@ -1,3 +1,5 @@
 fn main() {
+    // The headless engine has no app to configure
+    #[cfg(feature = "app")]
     tauri_build::build()
 }
@@ -0,0 +1,138 @@
+// Background removal
+//
+// The local path treats everything connected to the canvas border with a
+// color close to the dominant border color as background, which covers the
+// common case of sprites on a flat backdrop. Photos and busy backgrounds
+// go through the configured provider and come back as an alpha matte.
+
+use crate::engine::tools::{color_distance, FULLY_SELECTED};
+use crate::engine::{PixelBuffer, Selection};
+use std::collections::{HashMap, VecDeque};
+
+/// Estimate background pixels by flood-filling inward from the border
+pub fn estimate_background_mask(buffer: &PixelBuffer, tolerance: u8) -> Selection {
+    let mut mask = Selection::new(buffer.width, buffer.height);
+    let width = buffer.width;
+    let height = buffer.height;
+
+    if width == 0 || height == 0 {
+        return mask;
+    }
+
+    let reference = dominant_border_color(buffer);
+
+    let mut queue = VecDeque::new();
+    for x in 0..width {
+        queue.push_back((x, 0));
+        queue.push_back((x, height - 1));
+    }
+    for y in 0..height {
+        queue.push_back((0, y));
+        queue.push_back((width - 1, y));
+    }
+
+    while let Some((px, py)) = queue.pop_front() {
+        if mask.is_selected(px, py) {
+            continue;
+        }
+
+        let is_background = match buffer.get_pixel(px, py) {
+            Some(color) => color[3] == 0 || color_distance(color, reference) <= tolerance,
+            None => false,
+        };
+        if !is_background {
+            continue;
+        }
+
+        mask.select_pixel(px, py, true);
+
+        if px > 0 {
+            queue.push_back((px - 1, py));
+        }
+        if px < width - 1 {
+            queue.push_back((px + 1, py));
+        }
+        if py > 0 {
+            queue.push_back((px, py - 1));
+        }
+        if py < height - 1 {
+            queue.push_back((px, py + 1));
+        }
+    }
+
+    mask.update_bounds();
+    mask
+}
+
+/// Turn a provider's alpha matte into a background mask (mostly transparent = background)
+pub fn mask_from_alpha(matte: &PixelBuffer) -> Selection {
+    let mut mask = Selection::new(matte.width, matte.height);
+
+    for (index, pixel) in matte.pixels().enumerate() {
+        mask.mask[index] = if pixel[3] < 128 { FULLY_SELECTED } else { 0 };
+    }
+
+    mask.update_bounds();
+    mask
+}
+
+/// Most frequent opaque color along the border
+fn dominant_border_color(buffer: &PixelBuffer) -> [u8; 4] {
+    let mut counts: HashMap<[u8; 4], usize> = HashMap::new();
+
+    let mut count = |x: u32, y: u32| {
+        if let Some(color) = buffer.get_pixel(x, y) {
+            if color[3] > 0 {
+                *counts.entry(color).or_insert(0) += 1;
+            }
+        }
+    };
+
+    for x in 0..buffer.width {
+        count(x, 0);
+        count(x, buffer.height - 1);
+    }
+    for y in 0..buffer.height {
+        count(0, y);
+        count(buffer.width - 1, y);
+    }
+
+    counts
+        .into_iter()
+        .max_by_key(|(_, count)| *count)
+        .map(|(color, _)| color)
+        .unwrap_or([0, 0, 0, 0])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flat_background_is_detected() {
+        let mut buffer = PixelBuffer::new(5, 5);
+        buffer.clear([255, 255, 255, 255]);
+        buffer.set_pixel(2, 2, [255, 0, 0, 255]).unwrap();
+
+        let mask = estimate_background_mask(&buffer, 0);
+
+        assert!(mask.is_selected(0, 0));
+        assert!(mask.is_selected(4, 4));
+        assert!(!mask.is_selected(2, 2));
+    }
+
+    #[test]
+    fn test_enclosed_background_color_is_kept() {
+        // A white pixel surrounded by an outline is part of the sprite
+        let mut buffer = PixelBuffer::new(5, 5);
+        buffer.clear([255, 255, 255, 255]);
+        for (x, y) in [(1, 1), (2, 1), (3, 1), (1, 2), (3, 2), (1, 3), (2, 3), (3, 3)] {
+            buffer.set_pixel(x, y, [0, 0, 0, 255]).unwrap();
+        }
+
+        let mask = estimate_background_mask(&buffer, 0);
+
+        assert!(!mask.is_selected(2, 2));
+        assert!(mask.is_selected(0, 2));
+    }
+}
@@ -0,0 +1,127 @@
+// Selection inpainting
+//
+// The provider receives the selection's bounding box (plus some surrounding
+// context) and a matching mask. Its output is only ever written back inside
+// the selection, and snapped to the project palette when one is set.
+
+use crate::engine::{Palette, PixelBuffer, Selection};
+use crate::error::{AipixError, Result};
+
+/// Pixels of context included around the selection so the model can match surroundings
+pub const CONTEXT_MARGIN: u32 = 8;
+
+/// A rectangular region of the canvas sent to the provider
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InpaintRegion {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Region to send for a selection: its bounds grown by `margin`, clamped to the canvas
+pub fn region_for_selection(selection: &Selection, margin: u32) -> Option<InpaintRegion> {
+    let bounds = selection.bounds.as_ref()?;
+
+    let x = bounds.min_x.saturating_sub(margin);
+    let y = bounds.min_y.saturating_sub(margin);
+    let max_x = (bounds.max_x + margin).min(selection.width - 1);
+    let max_y = (bounds.max_y + margin).min(selection.height - 1);
+
+    Some(InpaintRegion {
+        x,
+        y,
+        width: max_x - x + 1,
+        height: max_y - y + 1,
+    })
+}
+
+/// Copy a region of the canvas into its own buffer
+pub fn crop(buffer: &PixelBuffer, region: InpaintRegion) -> PixelBuffer {
+    let mut cropped = PixelBuffer::new(region.width, region.height);
+
+    for y in 0..region.height {
+        for x in 0..region.width {
+            if let Some(color) = buffer.get_pixel(region.x + x, region.y + y) {
+                let _ = cropped.set_pixel(x, y, color);
+            }
+        }
+    }
+
+    cropped
+}
+
+/// Black/white mask image of the selection within the region (white = repaint)
+pub fn mask_image(selection: &Selection, region: InpaintRegion) -> PixelBuffer {
+    let mut mask = PixelBuffer::new(region.width, region.height);
+    mask.clear([0, 0, 0, 255]);
+
+    for y in 0..region.height {
+        for x in 0..region.width {
+            if selection.is_selected(region.x + x, region.y + y) {
+                let _ = mask.set_pixel(x, y, [255, 255, 255, 255]);
+            }
+        }
+    }
+
+    mask
+}
+
+/// Write the provider's result back into the canvas, only inside the selection
+pub fn composite(
+    dest: &mut PixelBuffer,
+    result: &PixelBuffer,
+    region: InpaintRegion,
+    selection: &Selection,
+    palette: Option<&Palette>,
+) -> Result<()> {
+    if result.width != region.width || result.height != region.height {
+        return Err(AipixError::ProviderError(
+            "Provider returned an image with different dimensions".to_string(),
+        ));
+    }
+
+    for y in 0..region.height {
+        for x in 0..region.width {
+            let (cx, cy) = (region.x + x, region.y + y);
+            if !selection.is_selected(cx, cy) {
+                continue;
+            }
+
+            if let Some(color) = result.get_pixel(x, y) {
+                let color = match palette {
+                    Some(palette) => palette.quantize(color),
+                    None => color,
+                };
+                dest.set_pixel(cx, cy, color)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::tools::{select_rectangle, SelectionMode};
+
+    #[test]
+    fn test_composite_stays_inside_selection() {
+        let mut canvas = PixelBuffer::new(10, 10);
+        let mut selection = Selection::new(10, 10);
+        select_rectangle(&mut selection, 4, 4, 5, 5, SelectionMode::Replace);
+
+        let region = region_for_selection(&selection, 2).unwrap();
+        assert_eq!(region, InpaintRegion { x: 2, y: 2, width: 6, height: 6 });
+
+        let mut result = PixelBuffer::new(region.width, region.height);
+        result.clear([250, 10, 10, 255]);
+
+        let palette = Palette::from_colors(vec![[255, 0, 0, 255], [0, 0, 255, 255]]);
+        composite(&mut canvas, &result, region, &selection, Some(&palette)).unwrap();
+
+        assert_eq!(canvas.get_pixel(4, 4).unwrap(), [255, 0, 0, 255]);
+        assert_eq!(canvas.get_pixel(3, 3).unwrap(), [0, 0, 0, 0]);
+    }
+}
@@ -0,0 +1,10 @@
+// AI-assisted features
+// Local heuristics live next to the client for remote providers so every
+// feature keeps working offline, with a model-backed path when configured.
+
+pub mod provider;
+pub mod background;
+pub mod palette;
+pub mod inpaint;
+
+pub use provider::AiProviderConfig;
@@ -0,0 +1,196 @@
+// Palette suggestion
+//
+// Candidate palettes come from three places: median-cut extraction of a
+// reference image, color harmonies built around its dominant color, and
+// (when a provider is configured) a text prompt sent to the model. Without
+// a provider, prompts fall back to harmonies around any color name they
+// mention.
+
+use crate::engine::tools::{hsl_to_rgb, rgb_to_hsl};
+use crate::engine::PixelBuffer;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PaletteSuggestion {
+    pub name: String,
+    pub colors: Vec<[u8; 4]>,
+}
+
+/// Reduce an image to at most `count` representative colors using median cut
+pub fn extract_palette(buffer: &PixelBuffer, count: usize) -> Vec<[u8; 4]> {
+    let pixels: Vec<[u8; 3]> = buffer
+        .pixels()
+        .filter(|p| p[3] > 0)
+        .map(|p| [p[0], p[1], p[2]])
+        .collect();
+
+    if pixels.is_empty() || count == 0 {
+        return Vec::new();
+    }
+
+    let mut boxes = vec![pixels];
+
+    while boxes.len() < count {
+        // Split the box with the widest channel range
+        let (index, channel, range) = boxes
+            .iter()
+            .enumerate()
+            .map(|(i, b)| {
+                let (channel, range) = widest_channel(b);
+                (i, channel, range)
+            })
+            .max_by_key(|(_, _, range)| *range)
+            .unwrap();
+
+        if range == 0 {
+            break; // Every box is a single color
+        }
+
+        let mut colors = boxes.swap_remove(index);
+        colors.sort_unstable_by_key(|c| c[channel]);
+        let upper = colors.split_off(colors.len() / 2);
+        boxes.push(colors);
+        boxes.push(upper);
+    }
+
+    let mut palette: Vec<[u8; 4]> = boxes.iter().map(|b| average(b)).collect();
+    palette.sort_by(|a, b| luminance(*a).total_cmp(&luminance(*b)));
+    palette.dedup();
+    palette
+}
+
+/// Build harmony palettes (analogous, complementary, triadic, shades) around a base color
+pub fn harmonies(base: [u8; 4], count: usize) -> Vec<PaletteSuggestion> {
+    let (hue, saturation, _) = rgb_to_hsl([base[0], base[1], base[2]]);
+    let count = count.max(2);
+
+    let ramp = |hues: &[f32]| -> Vec<[u8; 4]> {
+        (0..count)
+            .map(|i| {
+                let h = hues[i % hues.len()];
+                // Spread lightness so every entry stays distinguishable
+                let t = i as f32 / (count - 1) as f32;
+                let l = (0.2 + 0.6 * t).clamp(0.0, 1.0);
+                let [r, g, b] = hsl_to_rgb(h, saturation.max(0.35), l);
+                [r, g, b, 255]
+            })
+            .collect()
+    };
+
+    vec![
+        PaletteSuggestion {
+            name: "Shades".to_string(),
+            colors: (0..count)
+                .map(|i| {
+                    let t = i as f32 / (count - 1) as f32;
+                    // Shift hue slightly toward warm highlights and cool shadows
+                    let h = hue + (t - 0.5) * 30.0;
+                    let [r, g, b] = hsl_to_rgb(h, saturation, 0.1 + 0.8 * t);
+                    [r, g, b, 255]
+                })
+                .collect(),
+        },
+        PaletteSuggestion {
+            name: "Analogous".to_string(),
+            colors: ramp(&[hue - 30.0, hue, hue + 30.0]),
+        },
+        PaletteSuggestion {
+            name: "Complementary".to_string(),
+            colors: ramp(&[hue, hue + 180.0]),
+        },
+        PaletteSuggestion {
+            name: "Triadic".to_string(),
+            colors: ramp(&[hue, hue + 120.0, hue + 240.0]),
+        },
+    ]
+    .into_iter()
+    .map(|mut suggestion| {
+        suggestion.colors.dedup();
+        suggestion
+    })
+    .collect()
+}
+
+/// Find the first basic color name in a prompt (offline fallback for prompt-based suggestions)
+pub fn color_from_prompt(prompt: &str) -> Option<[u8; 4]> {
+    const NAMED: [(&str, [u8; 4]); 12] = [
+        ("red", [200, 40, 40, 255]),
+        ("orange", [230, 130, 30, 255]),
+        ("yellow", [235, 210, 60, 255]),
+        ("green", [60, 160, 70, 255]),
+        ("forest", [40, 110, 50, 255]),
+        ("teal", [40, 150, 150, 255]),
+        ("blue", [50, 90, 200, 255]),
+        ("ocean", [30, 100, 170, 255]),
+        ("purple", [130, 60, 170, 255]),
+        ("pink", [230, 120, 170, 255]),
+        ("brown", [120, 80, 50, 255]),
+        ("gray", [128, 128, 128, 255]),
+    ];
+
+    let prompt = prompt.to_lowercase();
+    NAMED
+        .iter()
+        .filter_map(|(name, color)| prompt.find(name).map(|pos| (pos, *color)))
+        .min_by_key(|(pos, _)| *pos)
+        .map(|(_, color)| color)
+}
+
+fn widest_channel(colors: &[[u8; 3]]) -> (usize, u8) {
+    (0..3)
+        .map(|channel| {
+            let min = colors.iter().map(|c| c[channel]).min().unwrap_or(0);
+            let max = colors.iter().map(|c| c[channel]).max().unwrap_or(0);
+            (channel, max - min)
+        })
+        .max_by_key(|(_, range)| *range)
+        .unwrap()
+}
+
+fn average(colors: &[[u8; 3]]) -> [u8; 4] {
+    let mut sum = [0u64; 3];
+    for c in colors {
+        for channel in 0..3 {
+            sum[channel] += c[channel] as u64;
+        }
+    }
+    let n = colors.len().max(1) as u64;
+    [
+        (sum[0] / n) as u8,
+        (sum[1] / n) as u8,
+        (sum[2] / n) as u8,
+        255,
+    ]
+}
+
+fn luminance(c: [u8; 4]) -> f32 {
+    0.2126 * c[0] as f32 + 0.7152 * c[1] as f32 + 0.0722 * c[2] as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_palette_finds_distinct_colors() {
+        let mut buffer = PixelBuffer::new(4, 4);
+        for y in 0..4 {
+            for x in 0..4 {
+                let color = if x < 2 { [255, 0, 0, 255] } else { [0, 0, 255, 255] };
+                buffer.set_pixel(x, y, color).unwrap();
+            }
+        }
+
+        let palette = extract_palette(&buffer, 4);
+
+        assert_eq!(palette.len(), 2);
+        assert!(palette.contains(&[255, 0, 0, 255]));
+        assert!(palette.contains(&[0, 0, 255, 255]));
+    }
+
+    #[test]
+    fn test_color_from_prompt() {
+        assert_eq!(color_from_prompt("a moody BLUE night"), Some([50, 90, 200, 255]));
+        assert_eq!(color_from_prompt("something"), None);
+    }
+}
@@ -0,0 +1,88 @@
+// Remote AI provider client
+//
+// Model-backed features talk to a user-configured HTTP endpoint. Images are
+// exchanged as base64-encoded PNGs inside JSON bodies:
+//
+//   POST {endpoint}/{route}   { "model": "...", "image": "<png>", ...params }
+//   200                       { "image": "<png>" }
+
+use crate::engine::PixelBuffer;
+use crate::error::{AipixError, Result};
+use crate::fileio;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AiProviderConfig {
+    pub endpoint: String,
+    pub api_key: Option<String>,
+    pub model: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ImageResponse {
+    image: String,
+}
+
+/// Send an image (plus extra parameters) to the provider and decode the image it returns
+pub async fn request_image(
+    config: &AiProviderConfig,
+    route: &str,
+    image: &PixelBuffer,
+    params: Value,
+) -> Result<PixelBuffer> {
+    let mut body = json!({
+        "model": config.model,
+        "image": encode_image(image)?,
+    });
+    if let (Some(body), Value::Object(extra)) = (body.as_object_mut(), params) {
+        body.extend(extra);
+    }
+
+    let response: ImageResponse = post_json(config, route, &body).await?;
+
+    let bytes = STANDARD
+        .decode(response.image)
+        .map_err(|_| AipixError::ProviderError("Provider returned invalid base64".to_string()))?;
+    fileio::decode_png(&bytes)
+        .map_err(|e| AipixError::ProviderError(format!("Provider returned an invalid image: {}", e)))
+}
+
+/// Encode an image as a base64 PNG string for request bodies
+pub fn encode_image(image: &PixelBuffer) -> Result<String> {
+    let png = fileio::encode_png(image)?;
+    Ok(STANDARD.encode(png))
+}
+
+/// POST a JSON body to `{endpoint}/{route}` and parse the JSON response
+pub async fn post_json<T: for<'de> Deserialize<'de>>(
+    config: &AiProviderConfig,
+    route: &str,
+    body: &Value,
+) -> Result<T> {
+    let url = format!(
+        "{}/{}",
+        config.endpoint.trim_end_matches('/'),
+        route.trim_start_matches('/')
+    );
+
+    tracing::debug!(%url, "sending AI provider request");
+
+    let mut request = reqwest::Client::new().post(&url).json(body);
+    if let Some(key) = &config.api_key {
+        request = request.bearer_auth(key);
+    }
+
+    let response = request
+        .send()
+        .await
+        .map_err(|e| AipixError::ProviderError(format!("Failed to reach {}: {}", url, e)))?
+        .error_for_status()
+        .map_err(|e| AipixError::ProviderError(e.to_string()))
+        .inspect_err(|e| tracing::warn!(%url, error = %e, "AI provider request failed"))?;
+
+    response.json::<T>().await.map_err(|e| {
+        AipixError::ProviderError(format!("Provider returned an unexpected response: {}", e))
+    })
+}
@@ -0,0 +1,70 @@
+// Editing time tracking
+//
+// Every pixel change (see events::emit_changes) is a heartbeat for its
+// project. Time between two heartbeats counts as editing when they are at
+// most IDLE_GAP apart; a longer pause starts a new session and isn't
+// counted. Time is added up in memory per project and local day, and written
+// to the database whenever a project is saved and before it is reported.
+
+use crate::database::{DailyActivity, Database};
+use crate::error::{AipixError, Result};
+use crate::AppState;
+use chrono::Local;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use tauri::State;
+
+/// Longest pause between edits still counted as editing
+pub const IDLE_GAP: Duration = Duration::from_secs(120);
+
+#[derive(Debug, Default)]
+pub struct ActivityTracker {
+    last_edit: HashMap<String, Instant>,
+    pending: HashMap<(String, String), Duration>, // By project id and day; not yet in the database
+}
+
+impl ActivityTracker {
+    /// Note an edit to `project_id` now
+    pub fn heartbeat(&mut self, project_id: &str) {
+        let now = Instant::now();
+        let Some(last) = self.last_edit.insert(project_id.to_string(), now) else {
+            return;
+        };
+        let elapsed = now.duration_since(last);
+        if elapsed <= IDLE_GAP {
+            let day = Local::now().format("%Y-%m-%d").to_string();
+            *self.pending.entry((project_id.to_string(), day)).or_default() += elapsed;
+        }
+    }
+
+    /// Write the whole seconds counted so far to the database, keeping the remainders
+    pub fn flush(&mut self, db: &Database) -> Result<()> {
+        for ((project_id, day), pending) in self.pending.iter_mut() {
+            let seconds = pending.as_secs();
+            if seconds > 0 {
+                db.add_project_activity(project_id, day, seconds)?;
+                *pending -= Duration::from_secs(seconds);
+            }
+        }
+        self.pending.retain(|_, pending| !pending.is_zero());
+        Ok(())
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct TimeSpent {
+    pub total_seconds: u64,
+    pub days: Vec<DailyActivity>, // Oldest first; days without edits are left out
+}
+
+/// Active editing time on a project, in total and per local day
+#[tauri::command]
+pub fn get_time_spent(state: State<AppState>, project_id: String) -> Result<TimeSpent> {
+    let db_guard = state.db.lock().unwrap();
+    let db = db_guard.as_ref().ok_or(AipixError::DatabaseNotInitialized)?;
+    state.activity.lock().unwrap().flush(db)?;
+
+    let days = db.get_project_activity(&project_id)?;
+    Ok(TimeSpent { total_seconds: days.iter().map(|day| day.seconds).sum(), days })
+}
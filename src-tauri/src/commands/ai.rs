@@ -0,0 +1,459 @@
+// Tauri commands for AI-assisted features
+//
+// Canvas data is copied out of the lock before any provider round-trip so a
+// slow network call never blocks drawing commands.
+
+use crate::ai::{self, AiProviderConfig};
+use crate::ai::palette::PaletteSuggestion;
+use crate::commands::events::{self, Changes};
+use crate::database::PromptHistoryEntry;
+use crate::engine::{self, PixelBuffer, Selection};
+use crate::error::{AipixError, Result};
+use crate::journal::Operation;
+use crate::{fileio, AppState};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::path::Path;
+use tauri::{AppHandle, State};
+
+/// Longest side of thumbnails stored in the prompt history
+const HISTORY_THUMBNAIL_SIZE: u32 = 64;
+
+/// Configure (or clear) the remote AI provider
+#[tauri::command]
+pub fn set_ai_provider(
+    state: State<'_, AppState>,
+    config: Option<AiProviderConfig>,
+) -> Result<()> {
+    *state.ai_provider.lock().unwrap() = config;
+    Ok(())
+}
+
+/// Compute the background mask for a canvas without modifying it
+///
+/// Recorded in the prompt history either way, like the other generations.
+#[tauri::command]
+pub async fn preview_background_removal(
+    state: State<'_, AppState>,
+    project_id: String,
+    tolerance: u8,
+    use_provider: bool,
+) -> Result<Selection> {
+    preview_background_recorded(&state, BackgroundArgs { project_id, tolerance, use_provider }).await
+}
+
+async fn preview_background_recorded(state: &AppState, args: BackgroundArgs) -> Result<Selection> {
+    let buffer = state.document(&args.project_id)?.lock().unwrap().history.buffer.clone();
+    let mask = run_background_preview(state, &args, &buffer).await?;
+
+    // The canvas as it would look with the background removed
+    let mut preview = buffer;
+    engine::tools::delete_selection(&mut preview, &mask);
+    let thumbnail = fileio::encode_thumbnail(&preview, HISTORY_THUMBNAIL_SIZE).ok();
+    record_generation(
+        state,
+        "remove_background",
+        String::new(),
+        Some(args.project_id.clone()),
+        &args,
+        thumbnail,
+    );
+
+    Ok(mask)
+}
+
+async fn run_background_preview(state: &AppState, args: &BackgroundArgs, buffer: &PixelBuffer) -> Result<Selection> {
+    if !args.use_provider {
+        return Ok(ai::background::estimate_background_mask(buffer, args.tolerance));
+    }
+
+    let config = state
+        .ai_provider
+        .lock()
+        .unwrap()
+        .clone()
+        .ok_or(AipixError::ProviderNotConfigured)?;
+
+    let matte = ai::provider::request_image(&config, "remove-background", buffer, json!({}))
+        .await?;
+
+    if matte.width != buffer.width || matte.height != buffer.height {
+        return Err(AipixError::ProviderError(
+            "Provider returned a matte with different dimensions".to_string(),
+        ));
+    }
+
+    Ok(ai::background::mask_from_alpha(&matte))
+}
+
+/// Make the pixels of a (previewed, possibly edited) background mask transparent
+///
+/// The mask comes from the frontend, so its data is checked against its
+/// size and its bounds are worked out again rather than trusted.
+#[tauri::command]
+pub fn remove_background(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    project_id: String,
+    mut mask: Selection,
+) -> Result<()> {
+    let document = state.document(&project_id)?;
+    let mut document = document.lock().unwrap();
+    let history = &mut document.history;
+
+    if mask.width != history.buffer.width || mask.height != history.buffer.height {
+        return Err(AipixError::InvalidInput("Mask size does not match canvas".to_string()));
+    }
+    if mask.mask.len() != mask.width as usize * mask.height as usize {
+        return Err(AipixError::InvalidInput(format!(
+            "Mask has {} entries, not one per pixel of {}x{}",
+            mask.mask.len(),
+            mask.width,
+            mask.height
+        )));
+    }
+    mask.update_bounds();
+
+    history.push_state();
+    engine::tools::delete_selection(&mut history.buffer, &mask);
+
+    state.record(&project_id, &document, Operation::PushState);
+    state.record_with(&project_id, &document, || {
+        Operation::patch(&document.history.buffer, mask.bounds)
+    });
+
+    events::emit_changes(&app, &project_id, &document, Changes::EDIT);
+    Ok(())
+}
+
+#[derive(Deserialize)]
+struct PaletteResponse {
+    palettes: Vec<PaletteSuggestion>,
+}
+
+/// Arguments of a palette suggestion, stored in the prompt history for re-runs
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct PaletteArgs {
+    prompt: Option<String>,
+    project_id: Option<String>,
+    image_path: Option<String>,
+    count: usize,
+}
+
+/// Arguments of a background removal preview, stored in the prompt history for re-runs
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct BackgroundArgs {
+    project_id: String,
+    tolerance: u8,
+    use_provider: bool,
+}
+
+/// Arguments of an inpainting request, stored in the prompt history for re-runs
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct InpaintArgs {
+    project_id: String,
+    prompt: String,
+}
+
+/// Suggest candidate palettes from a text prompt and/or a reference image
+///
+/// The reference image is either an open canvas (`project_id`) or a file on disk
+/// (`image_path`). Returned palettes can be inserted with `add_palette_colors`.
+#[tauri::command]
+pub async fn suggest_palette(
+    state: State<'_, AppState>,
+    prompt: Option<String>,
+    project_id: Option<String>,
+    image_path: Option<String>,
+    count: usize,
+) -> Result<Vec<PaletteSuggestion>> {
+    let args = PaletteArgs {
+        prompt,
+        project_id,
+        image_path,
+        count,
+    };
+    suggest_palette_recorded(&state, args).await
+}
+
+/// Repaint the active selection from a prompt using the configured provider
+///
+/// The result is composited back only inside the selection and quantized to
+/// the project palette (if it has one). The operation is a single undo step.
+#[tauri::command]
+pub async fn inpaint_selection(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    project_id: String,
+    prompt: String,
+) -> Result<()> {
+    inpaint_recorded(&app, &state, InpaintArgs { project_id, prompt }).await
+}
+
+/// List recorded generation requests, newest first
+#[tauri::command]
+pub fn get_prompt_history(
+    state: State<'_, AppState>,
+    project_id: Option<String>,
+    limit: Option<u32>,
+) -> Result<Vec<PromptHistoryEntry>> {
+    let db_guard = state.db.lock().unwrap();
+    let db = db_guard.as_ref().ok_or(AipixError::DatabaseNotInitialized)?;
+
+    db.get_prompt_history(project_id.as_deref(), limit.unwrap_or(100))
+}
+
+#[tauri::command]
+pub fn delete_prompt_history_entry(
+    state: State<'_, AppState>,
+    entry_id: String,
+) -> Result<()> {
+    let db_guard = state.db.lock().unwrap();
+    let db = db_guard.as_ref().ok_or(AipixError::DatabaseNotInitialized)?;
+
+    db.delete_prompt_history_entry(&entry_id)
+}
+
+/// Re-run a recorded generation with its original arguments and the current provider
+///
+/// Returns the same payload the original command returned (palettes for
+/// suggestions, the mask for background removal, `null` for inpainting).
+/// The re-run is recorded as a new entry.
+#[tauri::command]
+pub async fn rerun_prompt(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    entry_id: String,
+) -> Result<Value> {
+    let entry = {
+        let db_guard = state.db.lock().unwrap();
+        let db = db_guard.as_ref().ok_or(AipixError::DatabaseNotInitialized)?;
+        db.get_prompt_history_entry(&entry_id)?
+            .ok_or(AipixError::NotFound("Prompt history entry"))?
+    };
+
+    match entry.kind.as_str() {
+        "suggest_palette" => {
+            let args: PaletteArgs = serde_json::from_str(&entry.parameters)?;
+            let palettes = suggest_palette_recorded(&state, args).await?;
+            Ok(serde_json::to_value(palettes)?)
+        }
+        "remove_background" => {
+            let args: BackgroundArgs = serde_json::from_str(&entry.parameters)?;
+            let mask = preview_background_recorded(&state, args).await?;
+            Ok(serde_json::to_value(mask)?)
+        }
+        "inpaint" => {
+            let args: InpaintArgs = serde_json::from_str(&entry.parameters)?;
+            inpaint_recorded(&app, &state, args).await?;
+            Ok(Value::Null)
+        }
+        other => Err(AipixError::InvalidInput(format!(
+            "Cannot re-run generation of kind {}",
+            other
+        ))),
+    }
+}
+
+async fn suggest_palette_recorded(
+    state: &AppState,
+    args: PaletteArgs,
+) -> Result<Vec<PaletteSuggestion>> {
+    let suggestions = run_suggest_palette(state, &args).await?;
+
+    // Swatch strip of the first suggestion as a preview
+    let thumbnail = suggestions.first().and_then(|suggestion| {
+        let mut swatch = PixelBuffer::new(suggestion.colors.len() as u32, 1);
+        for (x, color) in suggestion.colors.iter().enumerate() {
+            let _ = swatch.set_pixel(x as u32, 0, *color);
+        }
+        fileio::encode_thumbnail(&swatch, HISTORY_THUMBNAIL_SIZE).ok()
+    });
+
+    record_generation(
+        state,
+        "suggest_palette",
+        args.prompt.clone().unwrap_or_default(),
+        args.project_id.clone(),
+        &args,
+        thumbnail,
+    );
+
+    Ok(suggestions)
+}
+
+async fn inpaint_recorded(app: &AppHandle, state: &AppState, args: InpaintArgs) -> Result<()> {
+    let result = run_inpaint(app, state, &args).await?;
+
+    let thumbnail = fileio::encode_thumbnail(&result, HISTORY_THUMBNAIL_SIZE).ok();
+    record_generation(
+        state,
+        "inpaint",
+        args.prompt.clone(),
+        Some(args.project_id.clone()),
+        &args,
+        thumbnail,
+    );
+
+    Ok(())
+}
+
+/// Store a generation request in the prompt history (best effort - never fails the generation)
+fn record_generation<T: Serialize>(
+    state: &AppState,
+    kind: &str,
+    prompt: String,
+    project_id: Option<String>,
+    args: &T,
+    thumbnail: Option<Vec<u8>>,
+) {
+    let provider = state.ai_provider.lock().unwrap().as_ref().map(|config| {
+        match &config.model {
+            Some(model) => format!("{} ({})", config.endpoint, model),
+            None => config.endpoint.clone(),
+        }
+    });
+
+    let entry = PromptHistoryEntry {
+        id: uuid::Uuid::new_v4().to_string(),
+        project_id,
+        kind: kind.to_string(),
+        prompt,
+        provider,
+        parameters: serde_json::to_string(args).unwrap_or_else(|_| "{}".to_string()),
+        thumbnail,
+        created_at: chrono::Utc::now(),
+    };
+
+    if let Some(db) = state.db.lock().unwrap().as_ref() {
+        if let Err(e) = db.add_prompt_history(&entry) {
+            tracing::warn!(error = %e, "failed to record prompt history");
+        }
+    }
+}
+
+async fn run_suggest_palette(
+    state: &AppState,
+    args: &PaletteArgs,
+) -> Result<Vec<PaletteSuggestion>> {
+    let count = args.count.clamp(2, 64);
+
+    let reference: Option<PixelBuffer> = if let Some(project_id) = &args.project_id {
+        Some(state.document(project_id)?.lock().unwrap().history.buffer.clone())
+    } else if let Some(path) = &args.image_path {
+        let img = fileio::load_image(Path::new(path))?;
+        Some(fileio::image_to_buffer(&img)?)
+    } else {
+        None
+    };
+
+    let mut suggestions = Vec::new();
+
+    if let Some(buffer) = &reference {
+        let extracted = ai::palette::extract_palette(buffer, count);
+        if let Some(&dominant) = extracted.get(extracted.len() / 2) {
+            suggestions.push(PaletteSuggestion {
+                name: "Extracted".to_string(),
+                colors: extracted,
+            });
+            suggestions.extend(ai::palette::harmonies(dominant, count));
+        }
+    }
+
+    if let Some(prompt) = args.prompt.as_ref().filter(|p| !p.trim().is_empty()) {
+        let config = state.ai_provider.lock().unwrap().clone();
+
+        match config {
+            Some(config) => {
+                let body = json!({
+                    "model": config.model,
+                    "prompt": prompt,
+                    "count": count,
+                });
+                let response: PaletteResponse =
+                    ai::provider::post_json(&config, "suggest-palette", &body)
+                        .await?;
+                suggestions.extend(response.palettes);
+            }
+            None => {
+                if let Some(base) = ai::palette::color_from_prompt(prompt) {
+                    suggestions.extend(ai::palette::harmonies(base, count));
+                } else if reference.is_none() {
+                    return Err(AipixError::ProviderNotConfigured);
+                }
+            }
+        }
+    }
+
+    Ok(suggestions)
+}
+
+/// Inpaint the selection and return the provider's raw output for the region
+async fn run_inpaint(
+    app: &AppHandle,
+    state: &AppState,
+    args: &InpaintArgs,
+) -> Result<PixelBuffer> {
+    let project_id = &args.project_id;
+
+    let config = state
+        .ai_provider
+        .lock()
+        .unwrap()
+        .clone()
+        .ok_or(AipixError::ProviderNotConfigured)?;
+
+    let document = state.document(project_id)?;
+
+    // Snapshot everything the request needs, then release the document for the round-trip
+    let (selection, buffer, palette) = {
+        let document = document.lock().unwrap();
+        let selection = document
+            .selection
+            .clone()
+            .ok_or(AipixError::NotFound("Selection"))?;
+        let palette = Some(document.history.palette.clone()).filter(|palette| !palette.is_empty());
+        (selection, document.history.buffer.clone(), palette)
+    };
+
+    let region = ai::inpaint::region_for_selection(&selection, ai::inpaint::CONTEXT_MARGIN)
+        .ok_or_else(|| AipixError::InvalidState("Nothing is selected".to_string()))?;
+
+    let image = ai::inpaint::crop(&buffer, region);
+    let size = (buffer.width, buffer.height);
+
+    if size != (selection.width, selection.height) {
+        return Err(AipixError::InvalidInput("Selection size does not match canvas".to_string()));
+    }
+
+    let mask = ai::inpaint::mask_image(&selection, region);
+    let params = json!({
+        "prompt": args.prompt,
+        "mask": ai::provider::encode_image(&mask)?,
+    });
+
+    let result = ai::provider::request_image(&config, "inpaint", &image, params).await?;
+
+    let mut document = document.lock().unwrap();
+    let history = &mut document.history;
+
+    if (history.buffer.width, history.buffer.height) != size {
+        return Err(AipixError::Conflict(
+            "Canvas was resized while inpainting".to_string(),
+        ));
+    }
+
+    history.push_state();
+    state.profiler.time("composite", || {
+        ai::inpaint::composite(&mut history.buffer, &result, region, &selection, palette.as_ref())
+    })?;
+
+    state.record(project_id, &document, Operation::PushState);
+    state.record_with(project_id, &document, || {
+        Operation::patch(&document.history.buffer, selection.bounds)
+    });
+
+    events::emit_changes(app, project_id, &document, Changes::EDIT);
+
+    Ok(result)
+}
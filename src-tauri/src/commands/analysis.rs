@@ -0,0 +1,56 @@
+// Canvas analysis commands
+//
+// Reports for checking a sprite sheet before export, such as colors that
+// slipped in from outside the project palette.
+
+use crate::engine::tools::rgba_to_hex;
+use crate::engine::SheetLayout;
+use crate::error::Result;
+use crate::AppState;
+use serde::Serialize;
+use tauri::State;
+
+/// Colors used by one frame of a sprite sheet
+#[derive(Debug, Serialize)]
+pub struct FrameColors {
+    pub frame: u32,
+    pub colors: usize,
+    pub off_palette: Vec<OffPaletteColor>, // Rarest first
+}
+
+#[derive(Debug, Serialize)]
+pub struct OffPaletteColor {
+    pub color: String,
+    pub pixels: usize,
+}
+
+/// Color counts and colors missing from the project palette for every frame
+///
+/// Frames are read from the canvas as cells of `frame_width` x
+/// `frame_height`, as for sprite sheet export. Colors are compared by RGB
+/// and transparent pixels are ignored.
+#[tauri::command]
+pub fn analyze_frames(
+    state: State<AppState>,
+    project_id: String,
+    frame_width: u32,
+    frame_height: u32,
+) -> Result<Vec<FrameColors>> {
+    let document = state.document(&project_id)?;
+    let document = document.lock().unwrap();
+    let history = &document.history;
+    let layout = SheetLayout::new(history.buffer.width, history.buffer.height, frame_width, frame_height)?;
+
+    Ok((0..layout.frame_count())
+        .filter_map(|frame| {
+            let (x, y, width, height) = layout.frame_rect(frame)?;
+            let usage = history.palette.usage(&history.buffer.copy_region(x, y, width, height));
+            let off_palette = usage
+                .off_palette
+                .into_iter()
+                .map(|([r, g, b], pixels)| OffPaletteColor { color: rgba_to_hex([r, g, b, 255]), pixels })
+                .collect();
+            Some(FrameColors { frame, colors: usage.colors, off_palette })
+        })
+        .collect())
+}
@@ -0,0 +1,131 @@
+// Profile picture commands
+//
+// Imported avatars are cropped, shrunk and saved as PNGs in the app data
+// directory; User.profile_picture holds the saved file's path. Other values
+// (e.g. a remote URL) are left alone.
+
+use crate::database::User;
+use crate::error::{AipixError, Result};
+use crate::{fileio, AppState};
+use chrono::Utc;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tauri::{AppHandle, Manager, State};
+
+/// Side of a stored avatar in pixels
+const AVATAR_SIZE: u32 = 256;
+
+/// Unreferenced avatars younger than this may belong to an import in progress
+const ORPHAN_MIN_AGE: Duration = Duration::from_secs(60);
+
+fn avatar_dir(app: &AppHandle) -> Result<PathBuf> {
+    let dir = app
+        .path()
+        .app_data_dir()
+        .map_err(|e| AipixError::Internal(e.to_string()))?
+        .join("avatars");
+    std::fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+/// Delete `picture` if it is an avatar file this module saved
+fn remove_avatar_file(dir: &Path, picture: &str) {
+    let path = Path::new(picture);
+    if path.parent() != Some(dir) {
+        return;
+    }
+    if let Err(e) = std::fs::remove_file(path) {
+        tracing::warn!(path = %path.display(), error = %e, "failed to delete avatar");
+    }
+}
+
+fn get_user(state: &AppState, user_id: &str) -> Result<User> {
+    let db_guard = state.db.lock().unwrap();
+    let db = db_guard.as_ref().ok_or(AipixError::DatabaseNotInitialized)?;
+    db.get_user(user_id)?.ok_or(AipixError::NotFound("User"))
+}
+
+/// Replace a user's profile picture with `picture`, deleting the old avatar file
+fn set_profile_picture(dir: &Path, state: &AppState, user_id: &str, picture: Option<String>) -> Result<User> {
+    let mut user = get_user(state, user_id)?;
+    let previous = std::mem::replace(&mut user.profile_picture, picture);
+    user.updated_at = Utc::now();
+    {
+        let db_guard = state.db.lock().unwrap();
+        let db = db_guard.as_ref().ok_or(AipixError::DatabaseNotInitialized)?;
+        db.update_user(&user)?;
+    }
+
+    if let Some(previous) = previous.filter(|previous| Some(previous) != user.profile_picture.as_ref()) {
+        remove_avatar_file(dir, &previous);
+    }
+    Ok(user)
+}
+
+/// Import an image file as a user's profile picture
+///
+/// The image is center-cropped to a square and shrunk to AVATAR_SIZE.
+#[tauri::command]
+pub fn import_profile_picture(
+    app: AppHandle,
+    state: State<AppState>,
+    user_id: String,
+    path: String,
+) -> Result<User> {
+    get_user(&state, &user_id)?;
+    let avatar = fileio::square_avatar(&fileio::load_image(Path::new(&path))?, AVATAR_SIZE);
+
+    let dir = avatar_dir(&app)?;
+    let file = dir.join(format!("{}.png", uuid::Uuid::new_v4()));
+    fileio::save_image(&file, &avatar)?;
+
+    let result = set_profile_picture(&dir, &state, &user_id, Some(file.to_string_lossy().into_owned()));
+    if result.is_err() {
+        let _ = std::fs::remove_file(&file);
+    }
+    result
+}
+
+/// Remove a user's profile picture
+#[tauri::command]
+pub fn remove_profile_picture(app: AppHandle, state: State<AppState>, user_id: String) -> Result<User> {
+    set_profile_picture(&avatar_dir(&app)?, &state, &user_id, None)
+}
+
+/// A user's stored avatar as PNG bytes; `None` without one (or for a remote picture)
+#[tauri::command]
+pub fn get_profile_picture(app: AppHandle, state: State<AppState>, user_id: String) -> Result<Option<Vec<u8>>> {
+    let dir = avatar_dir(&app)?;
+    match get_user(&state, &user_id)?.profile_picture {
+        Some(picture) if Path::new(&picture).parent() == Some(dir.as_path()) => Ok(Some(std::fs::read(picture)?)),
+        _ => Ok(None),
+    }
+}
+
+/// Delete avatar files no user refers to (left by deleted users or failed imports)
+///
+/// Returns the number of files removed.
+#[tauri::command]
+pub fn clean_up_avatars(app: AppHandle, state: State<AppState>) -> Result<usize> {
+    let dir = avatar_dir(&app)?;
+    let in_use: HashSet<PathBuf> = {
+        let db_guard = state.db.lock().unwrap();
+        let db = db_guard.as_ref().ok_or(AipixError::DatabaseNotInitialized)?;
+        db.get_profile_pictures()?.into_iter().map(PathBuf::from).collect()
+    };
+
+    let mut removed = 0;
+    for entry in std::fs::read_dir(&dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let age = entry.metadata()?.modified()?.elapsed().unwrap_or_default();
+        if path.is_file() && age >= ORPHAN_MIN_AGE && !in_use.contains(&path) {
+            std::fs::remove_file(&path)?;
+            removed += 1;
+        }
+    }
+
+    tracing::info!(removed, "cleaned up avatars");
+    Ok(removed)
+}
@@ -0,0 +1,183 @@
+// Custom brush commands
+//
+// Besides the built-in shapes, brushes can be captured from a selection:
+// the selected opaque pixels become a bitmap brush that the pencil and
+// eraser stamp in their own color (pass its id as `brush_id`). Captured
+// brushes are shared by every open project and kept until exit.
+
+use super::events::{self, Changes};
+use crate::engine::{self, BitmapBrush, BrushShape, SelectionBounds};
+use crate::error::{AipixError, Result};
+use crate::journal::Operation;
+use crate::{fileio, AppState};
+use serde::Serialize;
+use std::sync::Arc;
+use tauri::{AppHandle, State};
+
+/// Longest side of brush thumbnails
+const BRUSH_THUMBNAIL_SIZE: u32 = 64;
+
+#[derive(Debug, Clone)]
+pub struct CustomBrush {
+    pub id: u64,
+    pub name: String,
+    pub brush: Arc<BitmapBrush>, // Shared so strokes don't hold the library locked
+}
+
+#[derive(Debug, Default)]
+pub struct BrushLibrary {
+    brushes: Vec<CustomBrush>,
+    next_id: u64,
+}
+
+impl BrushLibrary {
+    pub fn add(&mut self, name: String, brush: BitmapBrush) -> &CustomBrush {
+        self.next_id += 1;
+        self.brushes.push(CustomBrush { id: self.next_id, name, brush: Arc::new(brush) });
+        self.brushes.last().expect("just pushed")
+    }
+
+    pub fn get(&self, id: u64) -> Result<Arc<BitmapBrush>> {
+        self.brushes
+            .iter()
+            .find(|custom| custom.id == id)
+            .map(|custom| custom.brush.clone())
+            .ok_or(AipixError::NotFound("Brush"))
+    }
+
+    pub fn remove(&mut self, id: u64) -> Result<()> {
+        let index = self.brushes.iter().position(|custom| custom.id == id).ok_or(AipixError::NotFound("Brush"))?;
+        self.brushes.remove(index);
+        Ok(())
+    }
+}
+
+/// A custom brush as listed to the UI
+#[derive(Debug, Serialize)]
+pub struct BrushInfo {
+    pub id: u64,
+    pub name: String,
+    pub width: u32,
+    pub height: u32,
+    pub thumbnail: Option<Vec<u8>>, // PNG, in black
+}
+
+impl From<&CustomBrush> for BrushInfo {
+    fn from(custom: &CustomBrush) -> Self {
+        BrushInfo {
+            id: custom.id,
+            name: custom.name.clone(),
+            width: custom.brush.width,
+            height: custom.brush.height,
+            thumbnail: fileio::encode_thumbnail(&custom.brush.to_pixels([0, 0, 0, 255]), BRUSH_THUMBNAIL_SIZE).ok(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct BrushList {
+    pub shapes: Vec<BrushShape>, // Built in, drawn at any size
+    pub custom: Vec<BrushInfo>,  // Oldest first
+}
+
+/// Capture the selected opaque pixels of an open document as a new brush
+#[tauri::command]
+pub fn create_brush(state: State<AppState>, project_id: String, name: String) -> Result<BrushInfo> {
+    let document = state.document(&project_id)?;
+    let document = document.lock().unwrap();
+    let selection = document.selection.as_ref().ok_or(AipixError::NotFound("Selection"))?;
+
+    let (pixels, _, _) = engine::tools::extract_selection(&document.history.buffer, selection)
+        .ok_or_else(|| AipixError::InvalidState("No selection to make a brush from".to_string()))?;
+    let brush = BitmapBrush::from_pixels(&pixels)?;
+
+    let mut brushes = state.brushes.lock().unwrap();
+    Ok(BrushInfo::from(brushes.add(name, brush)))
+}
+
+#[tauri::command]
+pub fn list_brushes(state: State<AppState>) -> Result<BrushList> {
+    let brushes = state.brushes.lock().unwrap();
+    Ok(BrushList {
+        shapes: BrushShape::ALL.to_vec(),
+        custom: brushes.brushes.iter().map(BrushInfo::from).collect(),
+    })
+}
+
+#[tauri::command]
+pub fn delete_brush(state: State<AppState>, brush_id: u64) -> Result<()> {
+    state.brushes.lock().unwrap().remove(brush_id)
+}
+
+/// Edges of the pixels a brush stamp covers, for drawing a cursor matching it
+///
+/// The built-in `shape` at `size` (1-64, default 1), or custom brush
+/// `brush_id`. Each polygon is a closed list of pixel corners relative to
+/// the top-left corner of the pixel under the pointer; fill them with the
+/// even-odd rule so holes in custom brushes stay open.
+#[tauri::command]
+pub fn get_brush_outline(
+    state: State<AppState>,
+    size: Option<u32>,
+    shape: Option<BrushShape>,
+    brush_id: Option<u64>,
+) -> Result<Vec<Vec<(i32, i32)>>> {
+    if let Some(brush_id) = brush_id {
+        let brush = state.brushes.lock().unwrap().get(brush_id)?;
+        return Ok(engine::brush::outline(&*brush));
+    }
+    let brush = engine::Brush { size: size.unwrap_or(1), shape: shape.unwrap_or_default() };
+    brush.validate()?;
+    Ok(engine::brush::outline(&brush))
+}
+
+/// Where draw stamps a custom brush
+#[derive(Debug, Clone, Copy)]
+pub struct Stamp {
+    pub brush_id: u64,
+    pub at: (u32, u32),
+    pub connect: bool, // Continue the stroke from the last pencil or eraser point
+}
+
+/// Stamp a custom brush in `color`, compositing it with `blend` if given
+///
+/// Recorded as the resulting pixels, since the brush isn't part of the journal.
+pub fn draw(
+    app: &AppHandle,
+    state: &AppState,
+    project_id: &str,
+    stamp: Stamp,
+    color: [u8; 4],
+    blend: Option<engine::BlendMode>,
+) -> Result<()> {
+    let Stamp { brush_id, at: (x, y), connect } = stamp;
+    let brush = state.brushes.lock().unwrap().get(brush_id)?;
+    let document = state.document(project_id)?;
+    let mut document = document.lock().unwrap();
+
+    let from = document.history.stroke_end.filter(|_| connect);
+    let (from, painted) =
+        document.draw_blended(blend, |buffer| engine::tools::pencil_stroke(buffer, from, x, y, color, &*brush))?;
+    document.history.stroke_end = Some((x, y));
+
+    let (x0, y0) = from.unwrap_or((x, y));
+    let changed = stroke_bounds(&document.history.buffer, &brush, (x0.min(x), y0.min(y)), (x0.max(x), y0.max(y)));
+    state.record_painted(project_id, &document, painted, || {
+        Operation::patch(&document.history.buffer, Some(changed))
+    });
+
+    events::emit_changes(app, project_id, &document, Changes::PIXELS);
+    Ok(())
+}
+
+/// Canvas area a stroke of `brush` between the corners `min` and `max` may touch
+fn stroke_bounds(buffer: &engine::PixelBuffer, brush: &BitmapBrush, min: (u32, u32), max: (u32, u32)) -> SelectionBounds {
+    let (left, top) = ((brush.width as i32 - 1) / 2, (brush.height as i32 - 1) / 2);
+    let (right, bottom) = (brush.width as i32 - 1 - left, brush.height as i32 - 1 - top);
+    SelectionBounds {
+        min_x: min.0.saturating_sub(left as u32),
+        min_y: min.1.saturating_sub(top as u32),
+        max_x: (max.0 + right as u32).min(buffer.width - 1),
+        max_y: (max.1 + bottom as u32).min(buffer.height - 1),
+    }
+}
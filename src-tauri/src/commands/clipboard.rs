@@ -0,0 +1,233 @@
+// Clipboard history
+//
+// Copies and cuts are kept as a short history, newest first, shared by every
+// open project and lost on exit. A plain paste uses the newest entry;
+// paste_clipboard_entry pastes any other by id. Ids never repeat within a
+// session, so an entry keeps its id while newer copies push it down the list.
+//
+// A paste bigger than the view can be placed first: begin_paste_preview shows
+// the entry ghosted over rendered viewports, move_paste_preview follows the
+// cursor, and commit_paste writes it as one undo step. Only the overlay
+// changes while placing, announced by paste_preview:changed events.
+
+use super::events::{self, Changes, PastePreviewChanged};
+use super::RendererState;
+use crate::engine::{Document, PixelBuffer};
+use crate::error::{AipixError, Result};
+use crate::journal::Operation;
+use crate::{fileio, AppState};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::VecDeque;
+use tauri::{AppHandle, Emitter, State};
+
+/// Copies kept before the oldest is dropped
+pub const CLIPBOARD_HISTORY_LEN: usize = 10;
+
+/// Longest side of the thumbnails listed with each entry
+const CLIPBOARD_THUMBNAIL_SIZE: u32 = 64;
+
+#[derive(Debug, Clone)]
+pub struct ClipboardEntry {
+    pub id: u64,
+    pub buffer: PixelBuffer,
+    pub x: u32, // Where the pixels were copied from
+    pub y: u32,
+    pub copied_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Default)]
+pub struct Clipboard {
+    entries: VecDeque<ClipboardEntry>, // Newest first
+    next_id: u64,
+}
+
+impl Clipboard {
+    /// Add a copy of `buffer` taken at `x`, `y`, dropping the oldest past the limit
+    pub fn push(&mut self, (buffer, x, y): (PixelBuffer, u32, u32)) {
+        self.next_id += 1;
+        self.entries.push_front(ClipboardEntry { id: self.next_id, buffer, x, y, copied_at: Utc::now() });
+        self.entries.truncate(CLIPBOARD_HISTORY_LEN);
+    }
+
+    /// The newest entry, or the one with `id`
+    pub fn get(&self, id: Option<u64>) -> Result<&ClipboardEntry> {
+        match id {
+            Some(id) => self.entries.iter().find(|entry| entry.id == id).ok_or(AipixError::NotFound("Clipboard entry")),
+            None => self.entries.front().ok_or_else(|| AipixError::InvalidState("Clipboard is empty".to_string())),
+        }
+    }
+
+    pub fn memory_bytes(&self) -> usize {
+        self.entries.iter().map(|entry| entry.buffer.byte_len()).sum()
+    }
+}
+
+/// A clipboard entry being placed, with its top-left at (x, y) on the canvas
+#[derive(Debug, Clone)]
+pub struct PastePreview {
+    pub pixels: PixelBuffer,
+    pub x: i32, // May lie off the canvas; only the overlap is pasted
+    pub y: i32,
+}
+
+/// A clipboard history entry as listed to the UI
+#[derive(Debug, Serialize)]
+pub struct ClipboardEntryInfo {
+    pub id: u64,
+    pub width: u32,
+    pub height: u32,
+    pub x: u32,
+    pub y: u32,
+    pub copied_at: DateTime<Utc>,
+    pub thumbnail: Option<Vec<u8>>, // PNG
+}
+
+/// The clipboard history, newest first
+#[tauri::command]
+pub fn get_clipboard_history(state: State<AppState>) -> Result<Vec<ClipboardEntryInfo>> {
+    let clipboard = state.clipboard.lock().unwrap();
+    Ok(clipboard
+        .entries
+        .iter()
+        .map(|entry| ClipboardEntryInfo {
+            id: entry.id,
+            width: entry.buffer.width,
+            height: entry.buffer.height,
+            x: entry.x,
+            y: entry.y,
+            copied_at: entry.copied_at,
+            thumbnail: fileio::encode_thumbnail(&entry.buffer, CLIPBOARD_THUMBNAIL_SIZE).ok(),
+        })
+        .collect())
+}
+
+/// Paste a clipboard history entry at `x`, `y`, or where it was copied from when omitted
+#[tauri::command]
+pub fn paste_clipboard_entry(
+    app: AppHandle,
+    state: State<AppState>,
+    project_id: String,
+    entry_id: u64,
+    x: Option<u32>,
+    y: Option<u32>,
+) -> Result<()> {
+    paste(&app, &state, &project_id, Some(entry_id), x.zip(y))
+}
+
+/// Paste the clipboard entry `entry_id` (the newest by default) at `at`, or where it was copied from
+pub fn paste(
+    app: &AppHandle,
+    state: &AppState,
+    project_id: &str,
+    entry_id: Option<u64>,
+    at: Option<(u32, u32)>,
+) -> Result<()> {
+    let document = state.document(project_id)?;
+    let mut document = document.lock().unwrap();
+
+    let clipboard = state.clipboard.lock().unwrap();
+    let entry = clipboard.get(entry_id)?;
+    let (x, y) = at.unwrap_or((entry.x, entry.y));
+
+    paste_pixels(app, state, project_id, &mut document, &entry.buffer, x, y)
+}
+
+/// Paste `pixels` at `x`, `y` as one undo step
+fn paste_pixels(
+    app: &AppHandle,
+    state: &AppState,
+    project_id: &str,
+    document: &mut Document,
+    pixels: &PixelBuffer,
+    x: u32,
+    y: u32,
+) -> Result<()> {
+    document.history.push_state();
+    let ((), painted) = document.paint(|canvas| crate::engine::tools::paste_buffer(canvas, pixels, x, y))?;
+
+    state.record(project_id, document, Operation::PushState);
+    state.record_painted(project_id, document, painted, || Operation::paste(pixels, x, y));
+
+    events::emit_changes(app, project_id, document, Changes::EDIT);
+    Ok(())
+}
+
+fn emit_preview_changed(app: &AppHandle, project_id: &str, preview: Option<&PastePreview>) {
+    let placement = preview.map(|preview| (preview.x, preview.y, preview.pixels.width, preview.pixels.height));
+    let _ = app.emit(
+        events::PASTE_PREVIEW_CHANGED,
+        PastePreviewChanged { project_id: project_id.to_string(), placement },
+    );
+}
+
+/// Start placing clipboard entry `entry_id` (the newest by default) with its top-left at `x`, `y`
+///
+/// Replaces any paste already being placed.
+#[tauri::command]
+pub fn begin_paste_preview(
+    app: AppHandle,
+    state: State<AppState>,
+    renderer: State<RendererState>,
+    project_id: String,
+    entry_id: Option<u64>,
+    x: i32,
+    y: i32,
+) -> Result<()> {
+    state.document(&project_id)?;
+    let pixels = state.clipboard.lock().unwrap().get(entry_id)?.buffer.clone();
+    let preview = PastePreview { pixels, x, y };
+    emit_preview_changed(&app, &project_id, Some(&preview));
+    *renderer.paste_preview.lock().unwrap() = Some((project_id, preview));
+    Ok(())
+}
+
+/// Move the paste being placed so its top-left is at `x`, `y`
+#[tauri::command]
+pub fn move_paste_preview(
+    app: AppHandle,
+    renderer: State<RendererState>,
+    project_id: String,
+    x: i32,
+    y: i32,
+) -> Result<()> {
+    let mut shown = renderer.paste_preview.lock().unwrap();
+    let preview = match shown.as_mut() {
+        Some((shown, preview)) if *shown == project_id => preview,
+        _ => return Err(AipixError::InvalidState("No paste is being placed".to_string())),
+    };
+    (preview.x, preview.y) = (x, y);
+    emit_preview_changed(&app, &project_id, Some(preview));
+    Ok(())
+}
+
+/// Paste the entry being placed where it is shown, ending the preview
+#[tauri::command]
+pub fn commit_paste(
+    app: AppHandle,
+    state: State<AppState>,
+    renderer: State<RendererState>,
+    project_id: String,
+) -> Result<()> {
+    let document = state.document(&project_id)?;
+    let mut document = document.lock().unwrap();
+    let preview = renderer
+        .clear_paste_preview(&project_id)
+        .ok_or_else(|| AipixError::InvalidState("No paste is being placed".to_string()))?;
+    emit_preview_changed(&app, &project_id, None);
+
+    // Drop the part left or above the canvas, so the rest lands at a valid offset
+    let (skip_x, skip_y) = (preview.x.min(0).unsigned_abs(), preview.y.min(0).unsigned_abs());
+    let pixels = preview.pixels.copy_region(skip_x, skip_y, preview.pixels.width, preview.pixels.height);
+    let (x, y) = (preview.x.max(0) as u32, preview.y.max(0) as u32);
+    paste_pixels(&app, &state, &project_id, &mut document, &pixels, x, y)
+}
+
+/// Stop placing a paste without changing the canvas
+#[tauri::command]
+pub fn cancel_paste_preview(app: AppHandle, renderer: State<RendererState>, project_id: String) -> Result<()> {
+    if renderer.clear_paste_preview(&project_id).is_some() {
+        emit_preview_changed(&app, &project_id, None);
+    }
+    Ok(())
+}
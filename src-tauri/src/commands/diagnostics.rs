@@ -0,0 +1,118 @@
+// Diagnostics commands
+//
+// A snapshot of operation timings and memory held by open documents, so a
+// slow setup can be diagnosed from the app (and attached to bug reports).
+
+use super::RendererState;
+use crate::engine::Document;
+use crate::error::Result;
+use crate::profiling::OperationStats;
+use crate::AppState;
+use serde::Serialize;
+use tauri::State;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DocumentMemory {
+    pub project_id: String,
+    pub width: u32,
+    pub height: u32,
+    pub canvas_bytes: usize,
+    pub undo_bytes: usize,
+    pub redo_bytes: usize,
+    pub undo_count: usize,
+    pub redo_count: usize,
+    pub selection_bytes: usize,
+}
+
+impl DocumentMemory {
+    pub fn of(project_id: String, document: &Document) -> Self {
+        let history = &document.history;
+        let (undo_bytes, redo_bytes) = history.snapshot_bytes();
+        Self {
+            project_id,
+            width: history.buffer.width,
+            height: history.buffer.height,
+            canvas_bytes: history.buffer.byte_len(),
+            undo_bytes,
+            redo_bytes,
+            undo_count: history.undo_count(),
+            redo_count: history.redo_count(),
+            selection_bytes: document
+                .selection
+                .as_ref()
+                .map_or(0, |selection| selection.mask.len()),
+        }
+    }
+
+    pub fn total_bytes(&self) -> usize {
+        self.canvas_bytes + self.undo_bytes + self.redo_bytes + self.selection_bytes
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MemoryReport {
+    pub documents: Vec<DocumentMemory>,
+    pub clipboard_bytes: usize,
+    pub renderer_bytes: usize,
+    pub total_bytes: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PerformanceReport {
+    pub operations: Vec<OperationStats>,
+    pub memory: MemoryReport,
+}
+
+/// Timing histograms for recent engine operations plus memory usage
+#[tauri::command]
+pub fn get_performance_report(
+    state: State<'_, AppState>,
+    renderer_state: State<'_, RendererState>,
+) -> Result<PerformanceReport> {
+    // Copy the handles out so no document is locked while holding the map
+    let handles: Vec<_> = state
+        .documents
+        .read()
+        .unwrap()
+        .iter()
+        .map(|(id, handle)| (id.clone(), handle.clone()))
+        .collect();
+
+    let mut documents: Vec<DocumentMemory> = handles
+        .into_iter()
+        .map(|(project_id, handle)| DocumentMemory::of(project_id, &handle.lock().unwrap()))
+        .collect();
+    documents.sort_by(|a, b| a.project_id.cmp(&b.project_id));
+
+    let clipboard_bytes = state.clipboard.lock().unwrap().memory_bytes();
+    let renderer_bytes = renderer_state
+        .renderer
+        .lock()
+        .unwrap()
+        .as_ref()
+        .map_or(0, |renderer| renderer.memory_bytes());
+
+    let total_bytes = documents
+        .iter()
+        .map(DocumentMemory::total_bytes)
+        .sum::<usize>()
+        + clipboard_bytes
+        + renderer_bytes;
+
+    Ok(PerformanceReport {
+        operations: state.profiler.stats(),
+        memory: MemoryReport {
+            documents,
+            clipboard_bytes,
+            renderer_bytes,
+            total_bytes,
+        },
+    })
+}
+
+/// Discard collected timings (e.g. before reproducing a slowdown)
+#[tauri::command]
+pub fn reset_performance_stats(state: State<'_, AppState>) -> Result<()> {
+    state.profiler.reset();
+    Ok(())
+}
@@ -0,0 +1,553 @@
+// Project open/close commands
+//
+// Opening a project loads its saved canvas from the database into a new
+// document (history, selection and renderer included) in one step; closing
+// saves the canvas back and drops the document. The lower-level commands
+// (create_canvas, create_selection, init_renderer) remain for canvases that
+// aren't stored projects.
+//
+// Once the user turns it on with set_idle_unload_minutes, documents left
+// untouched for a while are saved and unloaded in the background; the
+// frontend reopens them with open_project when needed. It's off by default
+// because unloading drops the undo history and saves edits the user may not
+// have meant to keep.
+
+use super::diagnostics::DocumentMemory;
+use super::events::{self, Changes};
+use super::export;
+use super::RendererState;
+use crate::database::{Project, ProjectMetadata, UserSettings};
+use crate::engine::{self, Document, PixelRenderer};
+use crate::error::{AipixError, Result};
+use crate::fileio::compression::{self, PixelCodec};
+use crate::{fileio, AppState};
+use chrono::Utc;
+use serde::Serialize;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter, Manager, State};
+
+/// Longest side of the preview stored with a project
+const PROJECT_THUMBNAIL_SIZE: u32 = 256;
+
+/// How often the background sweep looks for idle documents
+const IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Serialize)]
+pub struct OpenedProject {
+    pub project: Project,
+    pub width: u32, // Of the canvas, which may differ from a stale project record
+    pub height: u32,
+    pub metadata: ProjectMetadata,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct OpenDocument {
+    #[serde(flatten)]
+    pub memory: DocumentMemory,
+    pub total_bytes: usize,
+    pub idle_seconds: u64,
+    pub stored: bool, // Saved as a project; only stored documents are unloaded when idle
+}
+
+/// Whether `project_id` has a project record to save into
+fn is_stored(state: &AppState, project_id: &str) -> Result<bool> {
+    match state.db.lock().unwrap().as_ref() {
+        Some(db) => Ok(db.get_project(project_id)?.is_some()),
+        None => Ok(false),
+    }
+}
+
+/// Clean up after a document removed from the open documents
+///
+/// Recovery data is discarded, since the document was either saved or
+/// deliberately dropped.
+fn forget_document(app: &AppHandle, project_id: &str, idle: bool) {
+    let state = app.state::<AppState>();
+    state.document_access.lock().unwrap().remove(project_id);
+    state.thumbnails.lock().unwrap().remove(project_id);
+    if let Some(journal) = state.journal.lock().unwrap().as_mut() {
+        if let Err(e) = journal.discard(project_id) {
+            tracing::warn!(project_id, error = %e, "failed to discard recovery data");
+        }
+    }
+
+    let renderer = app.state::<RendererState>();
+    let mut guides = renderer.guides.lock().unwrap();
+    if guides.as_ref().is_some_and(|(shown, _)| shown == project_id) {
+        *guides = None;
+    }
+    renderer.clear_floating(project_id);
+    renderer.clear_paste_preview(project_id);
+    let mut cycling = renderer.cycling.lock().unwrap();
+    if cycling.as_ref().is_some_and(|(shown, ..)| shown == project_id) {
+        *cycling = None;
+    }
+
+    let _ = app.emit(
+        events::DOCUMENT_CLOSED,
+        events::DocumentClosed { project_id: project_id.to_string(), idle },
+    );
+}
+
+/// Save a document's canvas to its project, updating the project's size and thumbnail
+///
+/// The canvas is encoded with the codec the project's owner chose, and the
+/// palette stored in the project metadata. Does nothing more when the
+/// stored canvas is already identical; otherwise the project's linked
+/// export, if any, is re-run.
+pub fn persist_document(state: &AppState, project_id: &str, document: &Document) -> Result<()> {
+    let buffer = &document.history.buffer;
+    let (mut project, codec) = {
+        let db_guard = state.db.lock().unwrap();
+        let db = db_guard.as_ref().ok_or(AipixError::DatabaseNotInitialized)?;
+        let project = db.get_project(project_id)?.ok_or(AipixError::NotFound("Project"))?;
+        let settings = db.get_user_settings(&project.user_id)?;
+        (project, settings.map(|settings| settings.pixel_codec).unwrap_or_default())
+    };
+    let data = compression::encode_pixels(buffer, codec)?;
+
+    {
+        let db_guard = state.db.lock().unwrap();
+        let db = db_guard.as_ref().ok_or(AipixError::DatabaseNotInitialized)?;
+        if let Err(e) = state.activity.lock().unwrap().flush(db) {
+            tracing::warn!(error = %e, "failed to save editing time");
+        }
+        let mut metadata = db.get_project_metadata(project_id)?;
+        if metadata.palette.colors != document.history.palette.colors {
+            metadata.palette = document.history.palette.clone();
+            db.set_project_metadata(project_id, &metadata)?;
+        }
+        let stored = db.get_project_pixels(project_id)?;
+        if stored.is_some_and(|(stored, stored_codec)| stored_codec == codec && stored == data) {
+            return Ok(());
+        }
+
+        db.set_project_pixels(project_id, &data, codec)?;
+        let now = Utc::now();
+        project.width = buffer.width;
+        project.height = buffer.height;
+        project.thumbnail = fileio::encode_thumbnail(buffer, PROJECT_THUMBNAIL_SIZE).ok();
+        project.updated_at = now;
+        project.last_modified = now;
+        db.update_project(&project)?;
+    }
+
+    tracing::debug!(project_id, "saved project canvas");
+    export::run_linked_export(state, project_id, buffer, &document.history.palette);
+    Ok(())
+}
+
+/// Outcome of regenerate_thumbnails
+#[derive(Debug, Serialize)]
+pub struct ThumbnailRegeneration {
+    pub regenerated: usize,
+    pub skipped: usize,      // Projects whose canvas was never saved
+    pub failed: Vec<String>, // Ids of projects whose saved canvas couldn't be read
+}
+
+/// Rebuild the thumbnails of all of a user's projects from their saved canvases
+///
+/// For after a thumbnail format change, or when thumbnails were corrupted.
+/// Unsaved edits in open documents aren't included. A project that fails
+/// is reported and the rest carry on.
+#[tauri::command]
+pub async fn regenerate_thumbnails(state: State<'_, AppState>, user_id: String) -> Result<ThumbnailRegeneration> {
+    let projects = {
+        let db_guard = state.db.lock().unwrap();
+        let db = db_guard.as_ref().ok_or(AipixError::DatabaseNotInitialized)?;
+        db.get_projects_by_user(&user_id, None)?
+    };
+
+    let mut outcome = ThumbnailRegeneration { regenerated: 0, skipped: 0, failed: Vec::new() };
+    for project in projects {
+        // The database is locked per project, so edits can be saved in between
+        let regenerated = (|| {
+            let db_guard = state.db.lock().unwrap();
+            let db = db_guard.as_ref().ok_or(AipixError::DatabaseNotInitialized)?;
+            let Some((data, codec)) = db.get_project_pixels(&project.id)? else {
+                return Ok(false);
+            };
+            let buffer = compression::decode_pixels(&data, codec)?;
+            let thumbnail = fileio::encode_thumbnail(&buffer, PROJECT_THUMBNAIL_SIZE)?;
+            db.set_project_thumbnail(&project.id, Some(&thumbnail))?;
+            Ok::<_, AipixError>(true)
+        })();
+
+        match regenerated {
+            Ok(true) => outcome.regenerated += 1,
+            Ok(false) => outcome.skipped += 1,
+            Err(AipixError::DatabaseNotInitialized) => return Err(AipixError::DatabaseNotInitialized),
+            Err(e) => {
+                tracing::warn!(project_id = %project.id, error = %e, "could not regenerate thumbnail");
+                outcome.failed.push(project.id);
+            }
+        }
+    }
+
+    tracing::info!(%user_id, regenerated = outcome.regenerated, failed = outcome.failed.len(), "rebuilt thumbnails");
+    Ok(outcome)
+}
+
+/// Difference between an open document and its saved version
+#[derive(Debug, Serialize)]
+pub struct VersionComparison {
+    pub image: Vec<u8>, // PNG with changed pixels highlighted over the faded canvas
+    pub changed_pixels: usize,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Compare an open document's canvas with the version last saved to the database
+///
+/// Changed pixels are drawn in `highlight_color` (magenta by default). A
+/// canvas resized since the save is compared over the larger of both sizes.
+#[tauri::command]
+pub fn compare_versions(
+    state: State<AppState>,
+    project_id: String,
+    highlight_color: Option<String>,
+) -> Result<VersionComparison> {
+    let highlight = engine::tools::hex_to_rgba(highlight_color.as_deref().unwrap_or("#FF00FF"))?;
+    let (data, codec) = {
+        let db_guard = state.db.lock().unwrap();
+        let db = db_guard.as_ref().ok_or(AipixError::DatabaseNotInitialized)?;
+        db.get_project_pixels(&project_id)?.ok_or(AipixError::NotFound("Saved version"))?
+    };
+    let saved = compression::decode_pixels(&data, codec)?;
+
+    let document = state.document(&project_id)?;
+    let (picture, changed_pixels) = document.lock().unwrap().history.buffer.diff(&saved, highlight);
+    Ok(VersionComparison {
+        image: fileio::encode_png(&picture)?,
+        changed_pixels,
+        width: picture.width,
+        height: picture.height,
+    })
+}
+
+/// The document of a stored project, loading its saved canvas and palette
+/// when it isn't open yet
+///
+/// Every path that opens stored projects (open_project, deep links, MCP)
+/// goes through here, so none of them starts a project blank and later
+/// saves that over its pixels.
+pub fn load_document(state: &AppState, project: &Project) -> Result<Arc<Mutex<Document>>> {
+    if let Some(handle) = state.documents.read().unwrap().get(&project.id).cloned() {
+        return Ok(handle);
+    }
+
+    let (pixels, metadata) = {
+        let db_guard = state.db.lock().unwrap();
+        let db = db_guard.as_ref().ok_or(AipixError::DatabaseNotInitialized)?;
+        (db.get_project_pixels(&project.id)?, db.get_project_metadata(&project.id)?)
+    };
+    let buffer = match pixels {
+        Some((data, codec)) => compression::decode_pixels(&data, codec)?,
+        None => engine::PixelBuffer::new(project.width, project.height),
+    };
+    state.validate_canvas_size(buffer.width, buffer.height)?;
+
+    let mut document = Document::new(buffer.width, buffer.height);
+    document.selection = Some(engine::Selection::new(buffer.width, buffer.height));
+    document.history.buffer = buffer;
+    document.history.palette = metadata.palette;
+
+    tracing::info!(project_id = %project.id, "opened project");
+    Ok(state
+        .documents
+        .write()
+        .unwrap()
+        .entry(project.id.clone())
+        .or_insert_with(|| Arc::new(Mutex::new(document)))
+        .clone())
+}
+
+/// Open a stored project for editing; an already open project is returned as is
+#[tauri::command]
+pub fn open_project(
+    app: AppHandle,
+    state: State<AppState>,
+    renderer: State<RendererState>,
+    project_id: String,
+) -> Result<OpenedProject> {
+    let (project, metadata) = {
+        let db_guard = state.db.lock().unwrap();
+        let db = db_guard.as_ref().ok_or(AipixError::DatabaseNotInitialized)?;
+        let project = db.get_project(&project_id)?.ok_or(AipixError::NotFound("Project"))?;
+        (project, db.get_project_metadata(&project_id)?)
+    };
+    let handle = load_document(&state, &project)?;
+
+    let document = handle.lock().unwrap();
+    let buffer = &document.history.buffer;
+    let (width, height) = (buffer.width, buffer.height);
+
+    let mut pixel_renderer = PixelRenderer::new(width as i32, height as i32)?;
+    pixel_renderer.load_pixels(width as i32, height as i32, &buffer.to_rgba())?;
+    *renderer.renderer.lock().unwrap() = Some(pixel_renderer);
+
+    state.checkpoint(&project_id, &document);
+    events::emit_changes(&app, &project_id, &document, Changes::ALL);
+
+    Ok(OpenedProject { project, width, height, metadata })
+}
+
+/// Create a project from the user's new-project defaults
+///
+/// The canvas is sized and filled with the default background, and its
+/// document is loaded with the default palette for open_project to show. A
+/// user without saved settings gets the built-in defaults.
+#[tauri::command]
+pub fn create_project_with_defaults(
+    state: State<AppState>,
+    user_id: String,
+    name: String,
+    folder_id: Option<String>,
+) -> Result<Project> {
+    let settings = {
+        let db_guard = state.db.lock().unwrap();
+        let db = db_guard.as_ref().ok_or(AipixError::DatabaseNotInitialized)?;
+        db.get_user_settings(&user_id)?.unwrap_or_else(|| UserSettings::new(&user_id))
+    };
+    let (width, height) = (settings.default_width, settings.default_height);
+    state.validate_canvas_size(width, height)?;
+    let background = engine::tools::hex_to_rgba(&settings.default_background_color)?;
+    let palette = settings
+        .default_palette
+        .iter()
+        .map(|color| engine::tools::hex_to_rgba(color))
+        .collect::<Result<Vec<_>>>()?;
+
+    let now = Utc::now();
+    let project = Project {
+        id: uuid::Uuid::new_v4().to_string(),
+        user_id,
+        folder_id,
+        name,
+        width,
+        height,
+        color_mode: settings.default_color_mode,
+        background_color: settings.default_background_color,
+        pixel_aspect_ratio: "1:1".to_string(),
+        thumbnail: None,
+        created_at: now,
+        updated_at: now,
+        last_modified: now,
+        synced_at: None,
+        revision: 0,
+        archived: false,
+    };
+    {
+        let db_guard = state.db.lock().unwrap();
+        let db = db_guard.as_ref().ok_or(AipixError::DatabaseNotInitialized)?;
+        db.create_project(&project)?;
+    }
+
+    let mut document = Document::new(width, height);
+    document.selection = Some(engine::Selection::new(width, height));
+    document.history.buffer.fill_rect(0, 0, width, height, background);
+    document.history.palette = engine::Palette::from_colors(palette);
+    persist_document(&state, &project.id, &document)?;
+
+    tracing::info!(project_id = %project.id, width, height, "created project from defaults");
+    state.documents.write().unwrap().insert(project.id.clone(), Arc::new(Mutex::new(document)));
+
+    let db_guard = state.db.lock().unwrap();
+    let db = db_guard.as_ref().ok_or(AipixError::DatabaseNotInitialized)?;
+    db.get_project(&project.id)?.ok_or(AipixError::NotFound("Project"))
+}
+
+/// Save an open project's canvas without closing it
+#[tauri::command]
+pub fn save_project(state: State<AppState>, project_id: String) -> Result<()> {
+    let handle = state.document(&project_id)?;
+    let document = handle.lock().unwrap();
+    persist_document(&state, &project_id, &document)
+}
+
+/// Save a project's canvas and close it
+///
+/// With `save: false` unsaved changes are dropped.
+#[tauri::command]
+pub fn close_project(
+    app: AppHandle,
+    state: State<AppState>,
+    project_id: String,
+    save: Option<bool>,
+) -> Result<()> {
+    let handle = state.document(&project_id)?;
+    if save.unwrap_or(true) {
+        persist_document(&state, &project_id, &handle.lock().unwrap())?;
+    }
+
+    state.documents.write().unwrap().remove(&project_id);
+    forget_document(&app, &project_id, false);
+
+    tracing::info!(%project_id, "closed project");
+    Ok(())
+}
+
+/// Close any open document, saving it first if it is a stored project
+///
+/// Unlike close_project this also closes canvases that were never saved as
+/// a project, whose pixels are then gone.
+#[tauri::command]
+pub fn close_document(
+    app: AppHandle,
+    state: State<AppState>,
+    project_id: String,
+    save: Option<bool>,
+) -> Result<()> {
+    let handle = state.document(&project_id)?;
+    if save.unwrap_or(true) && is_stored(&state, &project_id)? {
+        persist_document(&state, &project_id, &handle.lock().unwrap())?;
+    }
+
+    state.documents.write().unwrap().remove(&project_id);
+    forget_document(&app, &project_id, false);
+
+    tracing::info!(%project_id, "closed document");
+    Ok(())
+}
+
+/// Open documents with their memory use, most recently used first
+#[tauri::command]
+pub fn get_open_documents(state: State<AppState>) -> Result<Vec<OpenDocument>> {
+    // Read the map directly: state.document() would count as a use
+    let handles: Vec<_> = state
+        .documents
+        .read()
+        .unwrap()
+        .iter()
+        .map(|(id, handle)| (id.clone(), handle.clone()))
+        .collect();
+
+    let now = Instant::now();
+    let mut documents = handles
+        .into_iter()
+        .map(|(project_id, handle)| {
+            let last_used = state.document_access.lock().unwrap().get(&project_id).copied();
+            let stored = is_stored(&state, &project_id)?;
+            let memory = DocumentMemory::of(project_id, &handle.lock().unwrap());
+            Ok(OpenDocument {
+                total_bytes: memory.total_bytes(),
+                idle_seconds: last_used.map_or(0, |time| now.duration_since(time).as_secs()),
+                stored,
+                memory,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    documents.sort_by_key(|document| document.idle_seconds);
+    Ok(documents)
+}
+
+/// Minutes a document may go unused before it is unloaded, if idle unloading is on
+#[tauri::command]
+pub fn get_idle_unload_minutes(state: State<AppState>) -> Result<Option<u64>> {
+    Ok(state.idle_unload.lock().unwrap().map(|timeout| timeout.as_secs() / 60))
+}
+
+/// Set the idle unload timeout in minutes (`None`, the default, keeps documents loaded)
+///
+/// Unloading saves the document and drops its undo history.
+#[tauri::command]
+pub fn set_idle_unload_minutes(state: State<AppState>, minutes: Option<u64>) -> Result<()> {
+    if minutes == Some(0) {
+        return Err(AipixError::InvalidInput("Idle timeout must be at least a minute".to_string()));
+    }
+    *state.idle_unload.lock().unwrap() = minutes.map(|minutes| Duration::from_secs(minutes * 60));
+    Ok(())
+}
+
+/// Codec a user's project canvases are saved with
+#[tauri::command]
+pub fn get_pixel_codec(state: State<AppState>, user_id: String) -> Result<PixelCodec> {
+    let db_guard = state.db.lock().unwrap();
+    let db = db_guard.as_ref().ok_or(AipixError::DatabaseNotInitialized)?;
+    Ok(db.get_user_settings(&user_id)?.map(|settings| settings.pixel_codec).unwrap_or_default())
+}
+
+/// Choose how a user's project canvases are saved from now on, kept in their settings
+///
+/// Each saved canvas records its codec, so projects saved with another
+/// setting still open; they switch codec the next time they are saved.
+#[tauri::command]
+pub fn set_pixel_codec(state: State<AppState>, user_id: String, codec: PixelCodec) -> Result<()> {
+    let db_guard = state.db.lock().unwrap();
+    let db = db_guard.as_ref().ok_or(AipixError::DatabaseNotInitialized)?;
+    let mut settings = db.get_user_settings(&user_id)?.unwrap_or_else(|| UserSettings::new(&user_id));
+    settings.pixel_codec = codec;
+    settings.updated_at = Utc::now();
+    db.save_user_settings(&settings)
+}
+
+/// Save and unload stored documents unused for longer than the idle timeout
+///
+/// Documents in use (locked, or held by a running command or job) are left
+/// for a later sweep, as are canvases that aren't stored projects. Returns
+/// the number unloaded.
+pub fn unload_idle_documents(app: &AppHandle) -> usize {
+    let state = app.state::<AppState>();
+    let Some(timeout) = *state.idle_unload.lock().unwrap() else {
+        return 0;
+    };
+
+    let now = Instant::now();
+    let idle: Vec<_> = {
+        let documents = state.documents.read().unwrap();
+        let mut access = state.document_access.lock().unwrap();
+        documents
+            .iter()
+            .filter(|(id, _)| {
+                // Documents added without a lookup start their idle time now
+                let last_used = *access.entry((*id).clone()).or_insert(now);
+                now.duration_since(last_used) >= timeout
+            })
+            .map(|(id, handle)| (id.clone(), handle.clone()))
+            .collect()
+    };
+
+    let mut unloaded = 0;
+    for (project_id, handle) in idle {
+        let Ok(document) = handle.try_lock() else {
+            continue;
+        };
+        match is_stored(&state, &project_id) {
+            Ok(true) => {}
+            Ok(false) => continue,
+            Err(e) => {
+                tracing::warn!(%project_id, error = %e, "failed to check idle document");
+                continue;
+            }
+        }
+        if let Err(e) = persist_document(&state, &project_id, &document) {
+            tracing::warn!(%project_id, error = %e, "failed to save idle document");
+            continue;
+        }
+
+        {
+            // Nobody can take a new handle under the write lock; one taken
+            // earlier (the map's and ours make two) means it's about to be used
+            let mut documents = state.documents.write().unwrap();
+            if Arc::strong_count(&handle) > 2 {
+                continue;
+            }
+            documents.remove(&project_id);
+        }
+        drop(document);
+        forget_document(app, &project_id, true);
+
+        tracing::info!(%project_id, "unloaded idle document");
+        unloaded += 1;
+    }
+    unloaded
+}
+
+/// Sweep for idle documents periodically for the life of the app
+pub fn spawn_idle_unloader(app: AppHandle) {
+    std::thread::spawn(move || loop {
+        std::thread::sleep(IDLE_CHECK_INTERVAL);
+        unload_idle_documents(&app);
+    });
+}
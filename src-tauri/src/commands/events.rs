@@ -0,0 +1,157 @@
+// Canvas change events
+//
+// Mutating commands announce what they changed so the frontend can react
+// instead of polling get_canvas_data / can_undo / get_selection after every
+// action.
+//
+// Selection events carry only the bounds and a revision; the mask can be
+// as large as the canvas, so listeners fetch it with get_selection when
+// they need it and drop fetches older than the latest revision.
+
+use crate::engine::{Document, SelectionBounds, TilemapDocument};
+use crate::AppState;
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tauri::{AppHandle, Emitter, Manager};
+
+pub const DOCUMENT_CHANGED: &str = "document:changed";
+pub const HISTORY_CHANGED: &str = "history:changed";
+pub const SELECTION_CHANGED: &str = "selection:changed";
+pub const NAVIGATE: &str = "app:navigate";
+pub const TILEMAP_CHANGED: &str = "tilemap:changed";
+pub const DOCUMENT_CLOSED: &str = "document:closed";
+pub const PASTE_PREVIEW_CHANGED: &str = "paste_preview:changed";
+
+/// Which parts of a document an operation touched
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Changes {
+    pub pixels: bool,
+    pub history: bool,
+    pub selection: bool,
+}
+
+impl Changes {
+    /// Pixels edited without an undo snapshot (e.g. a pencil dab mid-stroke)
+    pub const PIXELS: Changes = Changes { pixels: true, history: false, selection: false };
+    /// Pixels edited as an undoable step
+    pub const EDIT: Changes = Changes { pixels: true, history: true, selection: false };
+    pub const HISTORY: Changes = Changes { pixels: false, history: true, selection: false };
+    pub const SELECTION: Changes = Changes { pixels: false, history: false, selection: true };
+    pub const ALL: Changes = Changes { pixels: true, history: true, selection: true };
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DocumentChanged {
+    pub project_id: String,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HistoryChanged {
+    pub project_id: String,
+    pub can_undo: bool,
+    pub can_redo: bool,
+    pub undo_count: usize,
+    pub redo_count: usize,
+}
+
+/// Bumped on every selection change of any document
+static SELECTION_REVISION: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SelectionChanged {
+    pub project_id: String,
+    pub bounds: Option<SelectionBounds>, // None when nothing is selected
+    pub revision: u64, // Higher for later changes
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TilemapChanged {
+    pub tilemap_id: String,
+    pub tile_count: usize,
+    pub can_undo: bool,
+    pub can_redo: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DocumentClosed {
+    pub project_id: String,
+    pub idle: bool, // Unloaded for inactivity rather than closed by the user
+}
+
+/// A paste being placed moved, started or ended, so viewports showing it need rendering again
+#[derive(Debug, Clone, Serialize)]
+pub struct PastePreviewChanged {
+    pub project_id: String,
+    pub placement: Option<(i32, i32, u32, u32)>, // x, y, width, height; None once committed or cancelled
+}
+
+/// Ask the frontend to show a document opened from outside (deep link, file association)
+#[derive(Debug, Clone, Serialize)]
+pub struct Navigation {
+    pub project_id: String,
+    pub name: String,
+    pub width: u32,
+    pub height: u32,
+    pub path: Option<String>, // File it was opened from
+}
+
+/// Emit one event per changed part of `document`
+///
+/// Emission failures are ignored: events are a notification, the command's
+/// own result is what callers rely on. Pixel changes are also forwarded to
+/// clients of the local HTTP API when it is running, mark the project's
+/// cached thumbnails dirty and count towards its editing time.
+pub fn emit_changes(app: &AppHandle, project_id: &str, document: &Document, changes: Changes) {
+    if changes.pixels {
+        app.state::<AppState>().thumbnails.lock().unwrap().invalidate(project_id);
+        app.state::<AppState>().activity.lock().unwrap().heartbeat(project_id);
+        let changed = DocumentChanged {
+            project_id: project_id.to_string(),
+            width: document.history.buffer.width,
+            height: document.history.buffer.height,
+        };
+        if let Some(server) = app.state::<AppState>().http_api.lock().unwrap().as_ref() {
+            server.publish(changed.clone());
+        }
+        let _ = app.emit(DOCUMENT_CHANGED, changed);
+    }
+
+    if changes.history {
+        let _ = app.emit(
+            HISTORY_CHANGED,
+            HistoryChanged {
+                project_id: project_id.to_string(),
+                can_undo: document.history.can_undo(),
+                can_redo: document.history.can_redo(),
+                undo_count: document.history.undo_count(),
+                redo_count: document.history.redo_count(),
+            },
+        );
+    }
+
+    if changes.selection {
+        let _ = app.emit(
+            SELECTION_CHANGED,
+            SelectionChanged {
+                project_id: project_id.to_string(),
+                bounds: document.selection.as_ref().and_then(|selection| selection.bounds),
+                revision: SELECTION_REVISION.fetch_add(1, Ordering::Relaxed) + 1,
+            },
+        );
+    }
+}
+
+/// Tilemaps change as a whole (cells, tiles and history), so one event covers them
+pub fn emit_tilemap_changed(app: &AppHandle, tilemap_id: &str, tilemap: &TilemapDocument) {
+    let _ = app.emit(
+        TILEMAP_CHANGED,
+        TilemapChanged {
+            tilemap_id: tilemap_id.to_string(),
+            tile_count: tilemap.tileset.len(),
+            can_undo: tilemap.can_undo(),
+            can_redo: tilemap.can_redo(),
+        },
+    );
+}
@@ -0,0 +1,663 @@
+// Sprite sheet and export profile commands
+//
+// Writes the canvas as a sheet PNG plus a JSON description in the hash
+// format Aseprite, Phaser and TexturePacker importers understand, with
+// the pivots, hitboxes, slices and palette cycles stored in the project
+// metadata.
+// Export profiles save the settings of recurring exports (format, scale,
+// trimming, palette snapping, background, frame size, destination and file
+// names) under a name. A project can link one, e.g. pointed at a game's
+// assets directory, to have it re-run whenever the project is saved.
+
+use super::jobs::spawn_canvas_job;
+use super::metadata::update_metadata;
+use crate::database::{ExportFormat, ExportProfile, ExportSettings, ProjectMetadata};
+use crate::engine::{tools, upscale, FrameSelection, Hitbox, Palette, PixelBuffer, Progress, SheetLayout, Untracked};
+use crate::error::{AipixError, Result};
+use crate::fileio;
+use crate::AppState;
+use chrono::Utc;
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use tauri::{AppHandle, State};
+
+const DEFAULT_FRAME_DURATION_MS: u32 = 100;
+
+/// Largest export scale factor
+pub const MAX_EXPORT_SCALE: u32 = 16;
+
+/// Export the canvas as a sprite sheet of `frame_width` x `frame_height` frames
+///
+/// Frames are read left to right, top to bottom. Pivots are written
+/// normalized to the frame size, as engines expect; slice pivots stay in
+/// pixels, as in Aseprite. Hitboxes are listed per frame in pixels from
+/// the frame's top-left. With `frames` only that range (or tag) is
+/// exported, on a sheet of its own. Returns the path of the JSON file.
+#[tauri::command]
+pub fn export_sprite_sheet(
+    state: State<AppState>,
+    project_id: String,
+    path: String,
+    frame_width: u32,
+    frame_height: u32,
+    frame_duration_ms: Option<u32>,
+    frames: Option<FrameSelection>,
+) -> Result<String> {
+    let metadata = {
+        let db_guard = state.db.lock().unwrap();
+        let db = db_guard.as_ref().ok_or(AipixError::DatabaseNotInitialized)?;
+        db.get_project_metadata(&project_id)?
+    };
+    let (canvas, palette) = {
+        let document = state.document(&project_id)?;
+        let history = &document.lock().unwrap().history;
+        (history.buffer.clone(), history.palette.clone())
+    };
+    let layout = SheetLayout::new(canvas.width, canvas.height, frame_width, frame_height)?;
+    let frames = frames.map(|frames| frames.resolve(&metadata.tags, &layout)).transpose()?;
+
+    let mut image_path = PathBuf::from(&path);
+    if image_path.extension().is_none() {
+        image_path.set_extension("png");
+    }
+    let options =
+        SheetOptions { frames, frame_duration_ms: frame_duration_ms.unwrap_or(DEFAULT_FRAME_DURATION_MS), scale: 1 };
+    let json_path = write_sprite_sheet(&canvas, &palette, &metadata, layout, &image_path, options)?;
+
+    Ok(json_path.to_string_lossy().into_owned())
+}
+
+/// Largest zoom of the frames in an exported viewer
+pub const MAX_VIEWER_ZOOM: u32 = 32;
+
+/// The optional parts of an export_viewer; every field may be left out
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ViewerOptions {
+    pub frame_duration_ms: Option<u32>,
+    pub frames: Option<FrameSelection>,
+    pub zoom: Option<u32>, // 4 when not set
+}
+
+/// Export the animation as a standalone HTML viewer that plays it in a browser
+///
+/// Frames are cells of `frame_width` x `frame_height` read as for sprite
+/// sheets, optionally limited to the options' `frames`; each is shown for
+/// `frame_duration_ms` at `zoom` times its size. Returns the path written.
+#[tauri::command]
+pub fn export_viewer(
+    state: State<AppState>,
+    project_id: String,
+    path: String,
+    frame_width: u32,
+    frame_height: u32,
+    options: Option<ViewerOptions>,
+) -> Result<String> {
+    let ViewerOptions { frame_duration_ms, frames, zoom } = options.unwrap_or_default();
+    let zoom = zoom.unwrap_or(4);
+    if !(1..=MAX_VIEWER_ZOOM).contains(&zoom) {
+        return Err(AipixError::InvalidInput(format!("Zoom must be between 1 and {}", MAX_VIEWER_ZOOM)));
+    }
+    let (name, tags) = {
+        let db_guard = state.db.lock().unwrap();
+        let db = db_guard.as_ref().ok_or(AipixError::DatabaseNotInitialized)?;
+        let project = db.get_project(&project_id)?.ok_or(AipixError::NotFound("Project"))?;
+        (project.name, db.get_project_metadata(&project_id)?.tags)
+    };
+    let canvas = state.document(&project_id)?.lock().unwrap().history.buffer.clone();
+    let layout = SheetLayout::new(canvas.width, canvas.height, frame_width, frame_height)?;
+    let frames = frames.map(|frames| frames.resolve(&tags, &layout)).transpose()?.unwrap_or_else(|| all_frames(layout));
+
+    let images: Vec<PixelBuffer> = frames
+        .filter_map(|index| layout.frame_rect(index))
+        .map(|(x, y, w, h)| canvas.copy_region(x, y, w, h))
+        .collect();
+    let durations = vec![frame_duration_ms.unwrap_or(DEFAULT_FRAME_DURATION_MS); images.len()];
+    let html = fileio::viewer::viewer_html(&name, &images, &durations, zoom)?;
+
+    let mut path = PathBuf::from(path);
+    if path.extension().is_none() {
+        path.set_extension("html");
+    }
+    std::fs::write(&path, html)?;
+    Ok(path.to_string_lossy().into_owned())
+}
+
+/// The sheet an export writes: `canvas` itself, or the `frames` of it packed on their own
+fn export_sheet(
+    canvas: &PixelBuffer,
+    layout: SheetLayout,
+    frames: Option<RangeInclusive<u32>>,
+) -> (PixelBuffer, SheetLayout, RangeInclusive<u32>) {
+    match frames {
+        Some(frames) => {
+            let (sheet, packed) = layout.pack(canvas, frames.clone());
+            (sheet, packed, frames)
+        }
+        None => (canvas.clone(), layout, all_frames(layout)),
+    }
+}
+
+fn all_frames(layout: SheetLayout) -> RangeInclusive<u32> {
+    0..=layout.frame_count().saturating_sub(1)
+}
+
+/// Which frames of a sheet write_sprite_sheet writes, and how
+struct SheetOptions {
+    frames: Option<RangeInclusive<u32>>, // All of them when not set
+    frame_duration_ms: u32,
+    scale: u32,
+}
+
+/// Write `canvas` scaled by the options' `scale` to `image_path` and its description next to it
+///
+/// Everything in the description is in scaled pixels except the
+/// normalized frame pivots. Palette cycles are written with the colors of
+/// their entries, since the PNG itself carries no palette. With `frames`
+/// only those frames are written, numbered from 0 with tags moved to match;
+/// slices, which are placed on the whole canvas, are then left out. Returns
+/// the path of the JSON file.
+fn write_sprite_sheet(
+    canvas: &PixelBuffer,
+    palette: &Palette,
+    metadata: &ProjectMetadata,
+    layout: SheetLayout,
+    image_path: &Path,
+    options: SheetOptions,
+) -> Result<PathBuf> {
+    let SheetOptions { frames, frame_duration_ms, scale } = options;
+    let json_path = image_path.with_extension("json");
+    let whole_canvas = frames.is_none();
+    let (sheet, sheet_layout, frames) = export_sheet(canvas, layout, frames);
+    let image = if scale > 1 { upscale::nearest(&sheet, scale) } else { sheet };
+    fileio::save_image(image_path, &fileio::buffer_to_image(&image)?)?;
+    let first = *frames.start();
+
+    let stem = image_path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    let mut described = Map::new();
+    for (position, index) in frames.clone().enumerate() {
+        let Some((x, y, w, h)) = sheet_layout.frame_rect(position as u32) else { break };
+        let (x, y, w, h) = (x * scale, y * scale, w * scale, h * scale);
+        let mut frame = json!({
+            "frame": { "x": x, "y": y, "w": w, "h": h },
+            "rotated": false,
+            "trimmed": false,
+            "spriteSourceSize": { "x": 0, "y": 0, "w": w, "h": h },
+            "sourceSize": { "w": w, "h": h },
+            "duration": frame_duration_ms,
+        });
+        if let Some(frame_metadata) = metadata.frames.get(&index) {
+            if let Some(pivot) = frame_metadata.pivot {
+                let (frame_width, frame_height) = (layout.frame_width as f64, layout.frame_height as f64);
+                frame["pivot"] = json!({ "x": pivot.x as f64 / frame_width, "y": pivot.y as f64 / frame_height });
+            }
+            if !frame_metadata.hitboxes.is_empty() {
+                let hitboxes: Vec<Hitbox> = frame_metadata
+                    .hitboxes
+                    .iter()
+                    .map(|hitbox| Hitbox { name: hitbox.name.clone(), shape: hitbox.shape.scaled(scale) })
+                    .collect();
+                frame["hitboxes"] = json!(hitboxes);
+            }
+        }
+        described.insert(format!("{} {}", stem, position), frame);
+    }
+
+    let tags: Vec<Value> = metadata
+        .tags
+        .iter()
+        .filter(|tag| tag.from <= *frames.end() && tag.to >= first)
+        .map(|tag| {
+            let (from, to) = (tag.from.max(first) - first, tag.to.min(*frames.end()) - first);
+            json!({ "name": tag.name, "from": from, "to": to, "direction": "forward" })
+        })
+        .collect();
+
+    let slices: Vec<Value> = metadata
+        .slices
+        .iter()
+        .filter(|_| whole_canvas)
+        .map(|slice| {
+            let mut key = json!({
+                "frame": 0,
+                "bounds": {
+                    "x": slice.x * scale,
+                    "y": slice.y * scale,
+                    "w": slice.width * scale,
+                    "h": slice.height * scale,
+                },
+            });
+            if let Some(nine_slice) = slice.nine_slice {
+                let (x, y, w, h) = nine_slice.center(slice.width, slice.height);
+                key["center"] = json!({ "x": x * scale, "y": y * scale, "w": w * scale, "h": h * scale });
+            }
+            if let Some(pivot) = slice.pivot {
+                key["pivot"] = json!({ "x": pivot.x * scale as i32, "y": pivot.y * scale as i32 });
+            }
+            json!({ "name": slice.name, "color": "#0000ffff", "keys": [key] })
+        })
+        .collect();
+
+    // Cycles past the end of the palette have no colors to cycle
+    let palette_cycles: Vec<Value> = metadata
+        .palette_cycles
+        .iter()
+        .filter_map(|cycle| {
+            let colors = palette.colors.get(cycle.start..=cycle.end)?;
+            let colors: Vec<String> = colors
+                .iter()
+                .map(|[r, g, b, a]| format!("#{:02x}{:02x}{:02x}{:02x}", r, g, b, a))
+                .collect();
+            Some(json!({
+                "name": cycle.name,
+                "from": cycle.start,
+                "to": cycle.end,
+                "rate": cycle.rate,
+                "direction": if cycle.reverse { "reverse" } else { "forward" },
+                "colors": colors,
+            }))
+        })
+        .collect();
+
+    let mut description = json!({
+        "frames": described,
+        "meta": {
+            "app": "AIPIX",
+            "version": env!("CARGO_PKG_VERSION"),
+            "image": image_path.file_name().map(|name| name.to_string_lossy().into_owned()),
+            "format": "RGBA8888",
+            "size": { "w": image.width, "h": image.height },
+            "scale": scale.to_string(),
+            "frameTags": tags,
+            "slices": slices,
+        },
+    });
+    if !palette_cycles.is_empty() {
+        description["meta"]["paletteCycles"] = json!(palette_cycles);
+    }
+    std::fs::write(&json_path, serde_json::to_vec_pretty(&description)?)?;
+
+    Ok(json_path)
+}
+
+/// Check the settings that decide the exported pixels (not where they are written)
+fn validate_render_settings(settings: &ExportSettings) -> Result<()> {
+    let invalid = |message: &str| Err(AipixError::InvalidInput(message.to_string()));
+    if settings.scale == 0 || settings.scale > MAX_EXPORT_SCALE {
+        return Err(AipixError::InvalidInput(format!(
+            "Export scale must be between 1 and {}",
+            MAX_EXPORT_SCALE
+        )));
+    }
+    if settings.frame_width.is_some() != settings.frame_height.is_some() {
+        return invalid("Set both frame width and height, or neither");
+    }
+    if let Some(background) = &settings.background {
+        tools::hex_to_rgba(background)?;
+    }
+    match settings.format {
+        ExportFormat::SpriteSheet if settings.frame_width.is_none() => {
+            invalid("Sprite sheet exports need a frame size")
+        }
+        ExportFormat::SpriteSheet if settings.trim => invalid("Sprite sheets can't be trimmed"),
+        _ => Ok(()),
+    }
+}
+
+/// Frame grid of an export, if the settings give a frame size
+///
+/// Exporting only some frames (`needs_frames`) needs one.
+fn export_layout(canvas: &PixelBuffer, settings: &ExportSettings, needs_frames: bool) -> Result<Option<SheetLayout>> {
+    match (settings.frame_width, settings.frame_height) {
+        (Some(frame_width), Some(frame_height)) => {
+            Ok(Some(SheetLayout::new(canvas.width, canvas.height, frame_width, frame_height)?))
+        }
+        _ if needs_frames => Err(AipixError::InvalidInput("Exporting a frame range needs a frame size".to_string())),
+        _ => Ok(None),
+    }
+}
+
+fn validate_export_settings(settings: &ExportSettings) -> Result<()> {
+    validate_render_settings(settings)?;
+    let invalid = |message: &str| Err(AipixError::InvalidInput(message.to_string()));
+    if settings.destination.trim().is_empty() {
+        return invalid("Export destination cannot be empty");
+    }
+    if settings.filename_pattern.trim().is_empty() {
+        return invalid("File name pattern cannot be empty");
+    }
+    if settings.format == ExportFormat::Png
+        && settings.frame_width.is_some()
+        && !settings.filename_pattern.contains("{frame}")
+    {
+        return invalid("Exporting frames separately needs {frame} in the file name pattern");
+    }
+    Ok(())
+}
+
+/// `image` with the palette snapping and background of `settings` applied
+fn apply_export_colors(image: &PixelBuffer, settings: &ExportSettings, palette: &Palette) -> Result<PixelBuffer> {
+    let background = settings.background.as_deref().map(tools::hex_to_rgba).transpose()?;
+    let snap = settings.palette && !palette.is_empty();
+    if !snap && background.is_none() {
+        return Ok(image.clone());
+    }
+
+    let mut image = image.clone();
+    for pixel in image.pixels_mut() {
+        let mut color = [pixel[0], pixel[1], pixel[2], pixel[3]];
+        if snap {
+            color = palette.quantize(color);
+        }
+        if let Some(background) = background {
+            color = composite_over(color, background);
+        }
+        pixel.copy_from_slice(&color);
+    }
+    Ok(image)
+}
+
+/// `color` drawn over `background` (both straight alpha)
+fn composite_over(color: [u8; 4], background: [u8; 4]) -> [u8; 4] {
+    let (alpha, behind) = (color[3] as u32, background[3] as u32 * (255 - color[3] as u32) / 255);
+    let out_alpha = alpha + behind;
+    if out_alpha == 0 {
+        return [0, 0, 0, 0];
+    }
+    let channel = |i: usize| ((color[i] as u32 * alpha + background[i] as u32 * behind) / out_alpha) as u8;
+    [channel(0), channel(1), channel(2), out_alpha as u8]
+}
+
+/// An image as an export with `settings` writes it: trimmed, recolored, then scaled
+fn render_export_image(image: &PixelBuffer, settings: &ExportSettings, palette: &Palette) -> Result<PixelBuffer> {
+    let image = match image.content_bounds().filter(|_| settings.trim) {
+        Some((x0, y0, x1, y1)) => image.copy_region(x0, y0, x1 - x0 + 1, y1 - y0 + 1),
+        None => image.clone(),
+    };
+    let image = apply_export_colors(&image, settings, palette)?;
+    Ok(if settings.scale > 1 { upscale::nearest(&image, settings.scale) } else { image })
+}
+
+/// Render what exporting an open document with `settings` would write, as PNG bytes
+///
+/// Nothing is written to disk, and the destination and file name pattern
+/// are ignored. Sprite sheets are previewed as the whole sheet; frames
+/// exported separately as the frame at `frame_index` (the first by default,
+/// counted within `frames` when given).
+#[tauri::command]
+pub fn render_export_preview(
+    state: State<AppState>,
+    project_id: String,
+    settings: ExportSettings,
+    frames: Option<FrameSelection>,
+    frame_index: Option<u32>,
+) -> Result<Vec<u8>> {
+    validate_render_settings(&settings)?;
+    let tags = match &frames {
+        Some(_) => {
+            let db_guard = state.db.lock().unwrap();
+            let db = db_guard.as_ref().ok_or(AipixError::DatabaseNotInitialized)?;
+            db.get_project_metadata(&project_id)?.tags
+        }
+        None => Vec::new(),
+    };
+    let (canvas, palette) = {
+        let document = state.document(&project_id)?;
+        let history = &document.lock().unwrap().history;
+        (history.buffer.clone(), history.palette.clone())
+    };
+    let layout = export_layout(&canvas, &settings, frames.is_some())?;
+    let frames = frames.zip(layout).map(|(frames, layout)| frames.resolve(&tags, &layout)).transpose()?;
+
+    let image = match (settings.format, layout) {
+        (ExportFormat::SpriteSheet, Some(layout)) => {
+            let (sheet, ..) = export_sheet(&apply_export_colors(&canvas, &settings, &palette)?, layout, frames);
+            if settings.scale > 1 { upscale::nearest(&sheet, settings.scale) } else { sheet }
+        }
+        (ExportFormat::Png, Some(layout)) => {
+            let mut frames = frames.unwrap_or_else(|| all_frames(layout));
+            let index = frames.nth(frame_index.unwrap_or(0) as usize).ok_or(AipixError::NotFound("Frame"))?;
+            let (x, y, w, h) = layout.frame_rect(index).ok_or(AipixError::NotFound("Frame"))?;
+            render_export_image(&canvas.copy_region(x, y, w, h), &settings, &palette)?
+        }
+        _ => render_export_image(&canvas, &settings, &palette)?,
+    };
+    Ok(fileio::encode_png(&image)?)
+}
+
+/// Saved export profiles, by name
+#[tauri::command]
+pub fn get_export_profiles(state: State<AppState>) -> Result<Vec<ExportProfile>> {
+    let db_guard = state.db.lock().unwrap();
+    let db = db_guard.as_ref().ok_or(AipixError::DatabaseNotInitialized)?;
+
+    db.get_export_profiles()
+}
+
+/// Create a profile, or update it when `profile_id` is given
+#[tauri::command]
+pub fn save_export_profile(
+    state: State<AppState>,
+    profile_id: Option<String>,
+    name: String,
+    settings: ExportSettings,
+) -> Result<ExportProfile> {
+    if name.trim().is_empty() {
+        return Err(AipixError::InvalidInput("Profile name cannot be empty".to_string()));
+    }
+    validate_export_settings(&settings)?;
+
+    let db_guard = state.db.lock().unwrap();
+    let db = db_guard.as_ref().ok_or(AipixError::DatabaseNotInitialized)?;
+
+    let now = Utc::now();
+    let profile = match profile_id {
+        Some(profile_id) => {
+            let existing = db.get_export_profile(&profile_id)?.ok_or(AipixError::NotFound("Export profile"))?;
+            ExportProfile { name, settings, updated_at: now, ..existing }
+        }
+        None => ExportProfile {
+            id: uuid::Uuid::new_v4().to_string(),
+            name,
+            settings,
+            created_at: now,
+            updated_at: now,
+        },
+    };
+    db.save_export_profile(&profile)?;
+    Ok(profile)
+}
+
+#[tauri::command]
+pub fn delete_export_profile(state: State<AppState>, profile_id: String) -> Result<()> {
+    let db_guard = state.db.lock().unwrap();
+    let db = db_guard.as_ref().ok_or(AipixError::DatabaseNotInitialized)?;
+
+    db.delete_export_profile(&profile_id)
+}
+
+/// Export a canvas with a saved profile as a background job; finishes with the paths of the written files
+///
+/// File name tokens: {name} (the project name), {frame} (frame index, for
+/// frames exported separately), {scale} and {date} (YYYY-MM-DD). A `.png`
+/// extension is added when the pattern has none. With `frames` only that
+/// range (or tag) is exported, and {frame} counts from 0 within it.
+/// Progress is reported per file; files already written when the job is
+/// cancelled are kept.
+#[tauri::command]
+pub async fn export_with_profile(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    project_id: String,
+    profile_id: String,
+    frames: Option<FrameSelection>,
+) -> Result<String> {
+    let profile = {
+        let db_guard = state.db.lock().unwrap();
+        let db = db_guard.as_ref().ok_or(AipixError::DatabaseNotInitialized)?;
+        db.get_export_profile(&profile_id)?.ok_or(AipixError::NotFound("Export profile"))?
+    };
+    validate_export_settings(&profile.settings)?;
+
+    Ok(spawn_canvas_job(app, project_id.clone(), "export", move |_app, state, job| {
+        let (canvas, palette) = {
+            let document = state.document(&project_id)?;
+            let history = &document.lock().unwrap().history;
+            (history.buffer.clone(), history.palette.clone())
+        };
+
+        let written = export_canvas(state, &project_id, &canvas, &palette, &profile, frames, job)?;
+        let written: Vec<String> = written.into_iter().map(|path| path.to_string_lossy().into_owned()).collect();
+        Ok(serde_json::to_value(written)?)
+    }))
+}
+
+/// Export `canvas` and `palette`, those of `project_id`, with `profile`,
+/// optionally only `frames`
+///
+/// Takes the canvas rather than the document so it can run while the
+/// document is locked (e.g. from a save). Reports progress per file written.
+pub fn export_canvas(
+    state: &AppState,
+    project_id: &str,
+    canvas: &PixelBuffer,
+    palette: &Palette,
+    profile: &ExportProfile,
+    frames: Option<FrameSelection>,
+    progress: &dyn Progress,
+) -> Result<Vec<PathBuf>> {
+    let settings = &profile.settings;
+    validate_export_settings(settings)?;
+
+    let (project_name, metadata) = {
+        let db_guard = state.db.lock().unwrap();
+        let db = db_guard.as_ref().ok_or(AipixError::DatabaseNotInitialized)?;
+        let name = db.get_project(project_id)?.map(|project| project.name);
+        (name.unwrap_or_else(|| project_id.to_string()), db.get_project_metadata(project_id)?)
+    };
+    let layout = export_layout(canvas, settings, frames.is_some())?;
+    let frames = frames.zip(layout).map(|(frames, layout)| frames.resolve(&metadata.tags, &layout)).transpose()?;
+
+    let destination = PathBuf::from(&settings.destination);
+    std::fs::create_dir_all(&destination)?;
+    let file_path = |frame: Option<u32>| -> Result<PathBuf> {
+        let tokens = [
+            ("name", project_name.clone()),
+            ("frame", frame.map(|frame| frame.to_string()).unwrap_or_default()),
+            ("scale", settings.scale.to_string()),
+            ("date", Utc::now().format("%Y-%m-%d").to_string()),
+        ];
+        let mut path = destination.join(fileio::expand_filename_pattern(&settings.filename_pattern, &tokens)?);
+        if path.extension().is_none() {
+            path.set_extension("png");
+        }
+        Ok(path)
+    };
+    let write_image = |image: &PixelBuffer, path: &Path| -> Result<()> {
+        let image = render_export_image(image, settings, palette)?;
+        fileio::save_image(path, &fileio::buffer_to_image(&image)?)?;
+        Ok(())
+    };
+
+    let mut written = Vec::new();
+    match (settings.format, layout) {
+        (ExportFormat::SpriteSheet, Some(layout)) => {
+            progress.update(0, 1)?;
+            let image_path = file_path(None)?;
+            let json_path = write_sprite_sheet(
+                &apply_export_colors(canvas, settings, palette)?,
+                palette,
+                &metadata,
+                layout,
+                &image_path,
+                SheetOptions { frames, frame_duration_ms: DEFAULT_FRAME_DURATION_MS, scale: settings.scale },
+            )?;
+            written.extend([image_path, json_path]);
+        }
+        (ExportFormat::Png, Some(layout)) => {
+            let frames = frames.unwrap_or_else(|| all_frames(layout));
+            let total = frames.clone().count() as u64;
+            for (position, index) in frames.enumerate() {
+                progress.update(position as u64, total)?;
+                let Some((x, y, w, h)) = layout.frame_rect(index) else { break };
+                let path = file_path(Some(position as u32))?;
+                write_image(&canvas.copy_region(x, y, w, h), &path)?;
+                written.push(path);
+            }
+        }
+        _ => {
+            progress.update(0, 1)?;
+            let path = file_path(None)?;
+            write_image(canvas, &path)?;
+            written.push(path);
+        }
+    }
+
+    progress.update(1, 1)?;
+    tracing::info!(project_id, profile = %profile.name, files = written.len(), "exported with profile");
+    Ok(written)
+}
+
+/// Re-run a project's linked export after it was saved
+///
+/// Best-effort: a failed export is logged but never fails the save.
+/// Canvases that aren't stored projects have no link.
+pub fn run_linked_export(state: &AppState, project_id: &str, canvas: &PixelBuffer, palette: &Palette) {
+    let profile = {
+        let db_guard = state.db.lock().unwrap();
+        let Some(db) = db_guard.as_ref() else {
+            return;
+        };
+        db.get_project_metadata(project_id).and_then(|metadata| match metadata.linked_export {
+            Some(profile_id) => db.get_export_profile(&profile_id),
+            None => Ok(None),
+        })
+    };
+
+    let result = match profile {
+        Ok(Some(profile)) => export_canvas(state, project_id, canvas, palette, &profile, None, &Untracked).map(drop),
+        Ok(None) => Ok(()), // Not linked, or the profile was deleted
+        Err(e) => Err(e),
+    };
+    if let Err(e) = result {
+        tracing::warn!(project_id, error = %e, "linked export failed");
+    }
+}
+
+/// Export profile a project re-runs on every save
+#[tauri::command]
+pub fn get_linked_export(state: State<AppState>, project_id: String) -> Result<Option<ExportProfile>> {
+    let db_guard = state.db.lock().unwrap();
+    let db = db_guard.as_ref().ok_or(AipixError::DatabaseNotInitialized)?;
+
+    match db.get_project_metadata(&project_id)?.linked_export {
+        Some(profile_id) => db.get_export_profile(&profile_id),
+        None => Ok(None),
+    }
+}
+
+/// Link an export profile to a project, or unlink it with `None`
+#[tauri::command]
+pub fn set_linked_export(
+    state: State<AppState>,
+    project_id: String,
+    profile_id: Option<String>,
+) -> Result<()> {
+    if let Some(profile_id) = &profile_id {
+        let db_guard = state.db.lock().unwrap();
+        let db = db_guard.as_ref().ok_or(AipixError::DatabaseNotInitialized)?;
+        db.get_export_profile(profile_id)?.ok_or(AipixError::NotFound("Export profile"))?;
+    }
+
+    update_metadata(&state, &project_id, |metadata| {
+        metadata.linked_export = profile_id;
+        Ok(())
+    })?;
+    Ok(())
+}
@@ -0,0 +1,329 @@
+// Export queue
+//
+// Batch exports (many projects, or several frame ranges of one) go through
+// a queue instead of each being its own blocking command. At most
+// `concurrency` exports run at a time as background canvas jobs (see
+// jobs.rs); the rest wait in the order they were queued. Every change of a
+// job's status is emitted as an `export-job-status` event, and
+// get_export_queue lists all jobs until clear_finished_exports drops the
+// finished ones. A queued export's id is its canvas job id, so running ones
+// report `canvas-job-progress` and stop on cancel_export or cancel_job.
+//
+// Open projects are exported from their document as it is when the job
+// starts, closed ones from their saved canvas and palette.
+
+use super::export;
+use super::jobs::{self, Job};
+use crate::database::ExportProfile;
+use crate::engine::FrameSelection;
+use crate::error::{AipixError, Result};
+use crate::fileio::compression;
+use crate::AppState;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::sync::atomic::Ordering;
+use tauri::{AppHandle, Emitter, Manager, State};
+
+/// Event emitted whenever a queued export changes status
+pub const EXPORT_STATUS_EVENT: &str = "export-job-status";
+
+/// Exports running at once unless set_export_concurrency says otherwise
+pub const DEFAULT_CONCURRENCY: usize = 2;
+
+/// Most exports set_export_concurrency allows at once
+pub const MAX_CONCURRENCY: usize = 8;
+
+/// One export to queue: a project, the profile to export it with, and
+/// optionally the frames (or tag) to limit it to
+#[derive(Debug, Clone, Deserialize)]
+pub struct ExportRequest {
+    pub project_id: String,
+    pub profile_id: String,
+    pub frames: Option<FrameSelection>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportStatus {
+    Queued,
+    Running,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+impl ExportStatus {
+    fn is_finished(self) -> bool {
+        matches!(self, ExportStatus::Completed | ExportStatus::Failed | ExportStatus::Cancelled)
+    }
+}
+
+/// A queued export; also the payload of `export-job-status`
+#[derive(Debug, Clone, Serialize)]
+pub struct ExportJob {
+    pub job_id: String,
+    pub project_id: String,
+    pub profile_id: String,
+    pub frames: Option<FrameSelection>,
+    pub status: ExportStatus,
+    pub paths: Vec<String>, // Written files, once completed
+    pub error: Option<Value>, // Serialized AipixError, once failed
+}
+
+#[derive(Debug)]
+pub struct ExportQueue {
+    jobs: Vec<ExportJob>, // In the order they were queued
+    running: usize,
+    concurrency: usize,
+}
+
+impl Default for ExportQueue {
+    fn default() -> Self {
+        Self { jobs: Vec::new(), running: 0, concurrency: DEFAULT_CONCURRENCY }
+    }
+}
+
+impl ExportQueue {
+    fn job_mut(&mut self, job_id: &str) -> Option<&mut ExportJob> {
+        self.jobs.iter_mut().find(|job| job.job_id == job_id)
+    }
+
+    /// Mark the oldest queued jobs running while there's room; returns them
+    fn start_ready(&mut self) -> Vec<ExportJob> {
+        let mut started = Vec::new();
+        for job in self.jobs.iter_mut().filter(|job| job.status == ExportStatus::Queued) {
+            if self.running >= self.concurrency {
+                break;
+            }
+            job.status = ExportStatus::Running;
+            self.running += 1;
+            started.push(job.clone());
+        }
+        started
+    }
+
+    /// Record the outcome of a running job; returns it as updated
+    ///
+    /// A `Cancelled` error marks the job cancelled rather than failed.
+    fn finish(&mut self, job_id: &str, outcome: &Result<Vec<String>>) -> Option<ExportJob> {
+        self.running = self.running.saturating_sub(1);
+        let job = self.job_mut(job_id)?;
+        match outcome {
+            Ok(paths) => {
+                job.status = ExportStatus::Completed;
+                job.paths = paths.clone();
+            }
+            Err(AipixError::Cancelled) => job.status = ExportStatus::Cancelled,
+            Err(e) => {
+                job.status = ExportStatus::Failed;
+                job.error = serde_json::to_value(e).ok();
+            }
+        }
+        Some(job.clone())
+    }
+}
+
+/// Start as many queued exports as the concurrency limit allows
+fn start_ready(app: &AppHandle) {
+    let started = app.state::<AppState>().export_queue.lock().unwrap().start_ready();
+    for job in started {
+        let _ = app.emit(EXPORT_STATUS_EVENT, &job);
+        let (job_id, project_id) = (job.job_id.clone(), job.project_id.clone());
+        jobs::spawn_reserved_job(app.clone(), job_id, project_id, "export", move |app, state, handle| {
+            let outcome = export_project(state, &job, handle).map(|paths| {
+                paths.into_iter().map(|path| path.to_string_lossy().into_owned()).collect::<Vec<_>>()
+            });
+
+            let finished = state.export_queue.lock().unwrap().finish(&job.job_id, &outcome);
+            if let Some(finished) = finished {
+                let _ = app.emit(EXPORT_STATUS_EVENT, &finished);
+            }
+            start_ready(app);
+            Ok(serde_json::to_value(outcome?)?)
+        });
+    }
+}
+
+fn export_project(state: &AppState, job: &ExportJob, progress: &Job) -> Result<Vec<std::path::PathBuf>> {
+    let profile = export_profile(state, &job.profile_id)?;
+    let open = state.documents.read().unwrap().get(&job.project_id).cloned();
+    let (canvas, palette) = match open {
+        Some(document) => {
+            let history = &document.lock().unwrap().history;
+            (history.buffer.clone(), history.palette.clone())
+        }
+        None => {
+            let ((data, codec), metadata) = {
+                let db_guard = state.db.lock().unwrap();
+                let db = db_guard.as_ref().ok_or(AipixError::DatabaseNotInitialized)?;
+                let pixels = db.get_project_pixels(&job.project_id)?.ok_or(AipixError::NotFound("Saved canvas"))?;
+                (pixels, db.get_project_metadata(&job.project_id)?)
+            };
+            (compression::decode_pixels(&data, codec)?, metadata.palette)
+        }
+    };
+
+    export::export_canvas(state, &job.project_id, &canvas, &palette, &profile, job.frames.clone(), progress)
+}
+
+fn export_profile(state: &AppState, profile_id: &str) -> Result<ExportProfile> {
+    let db_guard = state.db.lock().unwrap();
+    let db = db_guard.as_ref().ok_or(AipixError::DatabaseNotInitialized)?;
+    db.get_export_profile(profile_id)?.ok_or(AipixError::NotFound("Export profile"))
+}
+
+/// Queue exports to run in the background; returns their job ids, in order
+///
+/// Projects and profiles are checked up front, so nothing is queued when
+/// one of them doesn't exist.
+#[tauri::command]
+pub fn queue_exports(app: AppHandle, state: State<AppState>, exports: Vec<ExportRequest>) -> Result<Vec<String>> {
+    {
+        let db_guard = state.db.lock().unwrap();
+        let db = db_guard.as_ref().ok_or(AipixError::DatabaseNotInitialized)?;
+        for request in &exports {
+            db.get_project(&request.project_id)?.ok_or(AipixError::NotFound("Project"))?;
+            db.get_export_profile(&request.profile_id)?.ok_or(AipixError::NotFound("Export profile"))?;
+        }
+    }
+
+    let queued: Vec<ExportJob> = exports
+        .into_iter()
+        .map(|request| ExportJob {
+            job_id: jobs::reserve_job(&state),
+            project_id: request.project_id,
+            profile_id: request.profile_id,
+            frames: request.frames,
+            status: ExportStatus::Queued,
+            paths: Vec::new(),
+            error: None,
+        })
+        .collect();
+    for job in &queued {
+        let _ = app.emit(EXPORT_STATUS_EVENT, job);
+    }
+    tracing::debug!(count = queued.len(), "exports queued");
+
+    let job_ids = queued.iter().map(|job| job.job_id.clone()).collect();
+    state.export_queue.lock().unwrap().jobs.extend(queued);
+    start_ready(&app);
+    Ok(job_ids)
+}
+
+/// Every queued, running and finished export, in the order they were queued
+#[tauri::command]
+pub fn get_export_queue(state: State<AppState>) -> Vec<ExportJob> {
+    state.export_queue.lock().unwrap().jobs.clone()
+}
+
+/// Take a waiting export off the queue, or stop a running one
+///
+/// A running export becomes `cancelled` once it notices, keeping the files it already wrote.
+#[tauri::command]
+pub fn cancel_export(app: AppHandle, state: State<AppState>, job_id: String) -> Result<()> {
+    let cancelled = {
+        let mut queue = state.export_queue.lock().unwrap();
+        let job = queue.job_mut(&job_id).ok_or(AipixError::NotFound("Export job"))?;
+        match job.status {
+            ExportStatus::Queued => {
+                job.status = ExportStatus::Cancelled;
+                state.jobs.lock().unwrap().remove(&job_id);
+                job.clone()
+            }
+            ExportStatus::Running => {
+                if let Some(cancelled) = state.jobs.lock().unwrap().get(&job_id) {
+                    cancelled.store(true, Ordering::Relaxed);
+                }
+                return Ok(());
+            }
+            _ => return Err(AipixError::InvalidState("Export already finished".to_string())),
+        }
+    };
+    let _ = app.emit(EXPORT_STATUS_EVENT, &cancelled);
+    Ok(())
+}
+
+/// Drop completed, failed and cancelled exports from the queue
+#[tauri::command]
+pub fn clear_finished_exports(state: State<AppState>) {
+    state.export_queue.lock().unwrap().jobs.retain(|job| !job.status.is_finished());
+}
+
+/// How many exports run at once, from 1 to MAX_CONCURRENCY
+///
+/// Raising it starts waiting exports right away; lowering it lets running
+/// ones finish.
+#[tauri::command]
+pub fn set_export_concurrency(app: AppHandle, state: State<AppState>, concurrency: usize) -> Result<()> {
+    if !(1..=MAX_CONCURRENCY).contains(&concurrency) {
+        return Err(AipixError::InvalidInput(format!(
+            "Export concurrency must be between 1 and {}",
+            MAX_CONCURRENCY
+        )));
+    }
+    state.export_queue.lock().unwrap().concurrency = concurrency;
+    start_ready(&app);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn queue_of(count: usize, concurrency: usize) -> ExportQueue {
+        let jobs = (0..count)
+            .map(|i| ExportJob {
+                job_id: i.to_string(),
+                project_id: "project".to_string(),
+                profile_id: "profile".to_string(),
+                frames: None,
+                status: ExportStatus::Queued,
+                paths: Vec::new(),
+                error: None,
+            })
+            .collect();
+        ExportQueue { jobs, running: 0, concurrency }
+    }
+
+    fn ids(jobs: &[ExportJob]) -> Vec<&str> {
+        jobs.iter().map(|job| job.job_id.as_str()).collect()
+    }
+
+    #[test]
+    fn test_start_ready_respects_concurrency_and_order() {
+        let mut queue = queue_of(4, 2);
+        assert_eq!(ids(&queue.start_ready()), ["0", "1"]);
+        assert!(queue.start_ready().is_empty()); // Full until one finishes
+
+        queue.finish("1", &Ok(vec!["out.png".to_string()])).unwrap();
+        assert_eq!(ids(&queue.start_ready()), ["2"]);
+        assert_eq!(queue.running, 2);
+
+        // Lowering the limit lets running jobs finish before more start
+        queue.concurrency = 1;
+        queue.finish("0", &Ok(Vec::new())).unwrap();
+        assert!(queue.start_ready().is_empty());
+        queue.finish("2", &Ok(Vec::new())).unwrap();
+        assert_eq!(ids(&queue.start_ready()), ["3"]);
+    }
+
+    #[test]
+    fn test_finish_records_outcome() {
+        let mut queue = queue_of(3, 3);
+        queue.jobs[1].status = ExportStatus::Cancelled; // Cancelled while waiting
+        assert_eq!(ids(&queue.start_ready()), ["0", "2"]);
+
+        let done = queue.finish("0", &Ok(vec!["out.png".to_string()])).unwrap();
+        assert_eq!((done.status, done.paths), (ExportStatus::Completed, vec!["out.png".to_string()]));
+        let cancelled = queue.finish("2", &Err(AipixError::Cancelled)).unwrap();
+        assert_eq!((cancelled.status, cancelled.error), (ExportStatus::Cancelled, None));
+        assert_eq!(queue.running, 0);
+
+        let mut queue = queue_of(1, 1);
+        queue.start_ready();
+        let failed = queue.finish("0", &Err(AipixError::NotFound("Saved canvas"))).unwrap();
+        assert_eq!(failed.status, ExportStatus::Failed);
+        assert!(failed.error.is_some());
+        assert!(queue.finish("missing", &Ok(Vec::new())).is_none());
+    }
+}
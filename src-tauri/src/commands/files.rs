@@ -0,0 +1,63 @@
+// Opening and saving project files
+//
+// The same paths the OS uses for deep links and file associations, for
+// links and files the user opens from inside the app.
+
+use super::events::Navigation;
+use super::export;
+use crate::deep_link::{self, OpenTarget};
+use crate::error::{AipixError, Result};
+use crate::fileio::project_file;
+use crate::AppState;
+use std::path::PathBuf;
+use tauri::{AppHandle, State};
+
+/// Open an `aipix://` link
+#[tauri::command]
+pub fn open_link(app: AppHandle, url: String) -> Result<Navigation> {
+    match deep_link::parse(&url) {
+        Some(target @ (OpenTarget::Project(_) | OpenTarget::NewCanvas { .. })) => deep_link::open(&app, &target),
+        _ => Err(AipixError::InvalidInput(format!("Not an AIPIX link: {}", url))),
+    }
+}
+
+/// Open a .aipix project or a PNG
+#[tauri::command]
+pub fn open_file(app: AppHandle, path: String) -> Result<Navigation> {
+    match deep_link::parse(&path) {
+        Some(target @ OpenTarget::File(_)) => deep_link::open(&app, &target),
+        _ => Err(AipixError::InvalidInput(format!("Unsupported file: {}", path))),
+    }
+}
+
+/// The document opened at launch, if the frontend hasn't seen it yet
+#[tauri::command]
+pub fn take_pending_navigation(state: State<AppState>) -> Result<Option<Navigation>> {
+    Ok(state.pending_navigation.lock().unwrap().take())
+}
+
+/// Save a canvas as a .aipix project file
+#[tauri::command]
+pub fn save_project_file(
+    state: State<AppState>,
+    project_id: String,
+    path: String,
+    name: Option<String>,
+) -> Result<()> {
+    let mut path = PathBuf::from(path);
+    if path.extension().is_none() {
+        path.set_extension(project_file::EXTENSION);
+    }
+
+    let document = state.document(&project_id)?;
+    let document = document.lock().unwrap();
+    project_file::save(&path, &document, name.as_deref())?;
+    export::run_linked_export(&state, &project_id, &document.history.buffer, &document.history.palette);
+    drop(document);
+
+    // Opening the file later focuses this document instead of loading a copy
+    if let Ok(path) = path.canonicalize() {
+        state.opened_files.lock().unwrap().insert(path, project_id);
+    }
+    Ok(())
+}
@@ -0,0 +1,62 @@
+// Local HTTP API commands
+//
+// The server is opt-in, bound to localhost and protected by a per-start
+// token that the UI shows so the user can paste it into their tools.
+
+use crate::error::{AipixError, Result};
+use crate::http_api;
+use crate::AppState;
+use serde::Serialize;
+use tauri::{AppHandle, State};
+
+/// Default port for the HTTP API
+pub const DEFAULT_PORT: u16 = 7358;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HttpApiStatus {
+    pub running: bool,
+    pub url: Option<String>,
+    pub token: Option<String>,
+}
+
+fn status(server: Option<&http_api::ServerHandle>) -> HttpApiStatus {
+    HttpApiStatus {
+        running: server.is_some(),
+        url: server.map(|handle| format!("http://{}", handle.addr)),
+        token: server.map(|handle| handle.token.clone()),
+    }
+}
+
+/// Start the HTTP API and return its URL and token
+#[tauri::command]
+pub fn start_http_api(
+    app: AppHandle,
+    state: State<AppState>,
+    port: Option<u16>,
+) -> Result<HttpApiStatus> {
+    let mut server = state.http_api.lock().unwrap();
+    if let Some(handle) = server.as_ref() {
+        return Err(AipixError::Conflict(format!(
+            "HTTP API is already running at http://{}",
+            handle.addr
+        )));
+    }
+
+    let handle = http_api::start(app, port.unwrap_or(DEFAULT_PORT))?;
+    let started = status(Some(&handle));
+    *server = Some(handle);
+    Ok(started)
+}
+
+#[tauri::command]
+pub fn stop_http_api(state: State<AppState>) -> Result<()> {
+    if let Some(handle) = state.http_api.lock().unwrap().take() {
+        handle.stop();
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_http_api_status(state: State<AppState>) -> Result<HttpApiStatus> {
+    Ok(status(state.http_api.lock().unwrap().as_ref()))
+}
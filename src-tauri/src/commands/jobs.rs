@@ -0,0 +1,473 @@
+// Background canvas jobs
+//
+// Operations that touch every pixel (fills, color replacement, magic wand,
+// upscaling, rotation, filters including dithering) and profile exports run
+// on the blocking pool instead of the IPC handler.
+// The command validates its arguments, returns a job id right away, reports
+// progress through `canvas-job-progress` events and the outcome through a
+// `canvas-job-finished` event. `cancel_job` asks a job to stop; engine
+// operations given the job as their Progress check it as they go.
+//
+// Sync has no long-running backend step: the frontend pushes and pulls one
+// record at a time through the database commands, so it isn't a job.
+
+use super::events::{self, Changes};
+use crate::engine::{self, Progress};
+use crate::error::{AipixError, Result};
+use crate::journal::Operation;
+use crate::AppState;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Arc;
+use tauri::{AppHandle, Emitter, Manager, State};
+
+/// Event emitted as a background canvas job makes progress
+pub const JOB_PROGRESS_EVENT: &str = "canvas-job-progress";
+
+/// Event emitted when a background canvas job completes (successfully or not)
+pub const JOB_FINISHED_EVENT: &str = "canvas-job-finished";
+
+/// Payload of `canvas-job-progress`
+#[derive(Debug, Clone, Serialize)]
+pub struct JobProgress {
+    pub job_id: String,
+    pub project_id: String,
+    pub operation: &'static str,
+    pub percent: u32,
+}
+
+/// Payload of `canvas-job-finished`
+#[derive(Debug, Clone, Serialize)]
+pub struct JobFinished {
+    pub job_id: String,
+    pub project_id: String,
+    pub operation: &'static str,
+    pub result: Option<Value>,
+    pub error: Option<Value>, // Serialized AipixError; code `cancelled` after cancel_job
+}
+
+/// A running job, passed to engine operations as their Progress
+pub struct Job {
+    pub id: String,
+    pub project_id: String,
+    pub operation: &'static str,
+    app: AppHandle,
+    cancelled: Arc<AtomicBool>,
+    percent: AtomicU32, // Last reported, so events go out once per percent
+}
+
+impl Job {
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+impl Progress for Job {
+    fn update(&self, done: u64, total: u64) -> Result<()> {
+        if self.is_cancelled() {
+            return Err(AipixError::Cancelled);
+        }
+
+        let percent = (done.min(total) * 100).checked_div(total).unwrap_or(100) as u32;
+        if self.percent.swap(percent, Ordering::Relaxed) != percent {
+            let _ = self.app.emit(
+                JOB_PROGRESS_EVENT,
+                JobProgress {
+                    job_id: self.id.clone(),
+                    project_id: self.project_id.clone(),
+                    operation: self.operation,
+                    percent,
+                },
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Run `job` on the blocking pool and emit its outcome; returns the job id
+pub fn spawn_canvas_job<F>(
+    app: AppHandle,
+    project_id: String,
+    operation: &'static str,
+    job: F,
+) -> String
+where
+    F: FnOnce(&AppHandle, &AppState, &Job) -> Result<Value> + Send + 'static,
+{
+    let job_id = reserve_job(&app.state::<AppState>());
+    spawn_reserved_job(app, job_id.clone(), project_id, operation, job);
+    job_id
+}
+
+/// Register a job id ahead of running it, so cancel_job can stop it while it waits (e.g. in the export queue)
+pub fn reserve_job(state: &AppState) -> String {
+    let job_id = uuid::Uuid::new_v4().to_string();
+    state.jobs.lock().unwrap().insert(job_id.clone(), Arc::default());
+    job_id
+}
+
+/// Run `job` under an id from `reserve_job`, as spawn_canvas_job does
+///
+/// A job cancelled before it starts finishes with `cancelled` without running.
+pub fn spawn_reserved_job<F>(app: AppHandle, job_id: String, project_id: String, operation: &'static str, job: F)
+where
+    F: FnOnce(&AppHandle, &AppState, &Job) -> Result<Value> + Send + 'static,
+{
+    let cancelled = app.state::<AppState>().jobs.lock().unwrap().entry(job_id.clone()).or_default().clone();
+
+    tracing::debug!(%job_id, %project_id, operation, "canvas job queued");
+
+    let handle = Job {
+        id: job_id,
+        project_id,
+        operation,
+        app: app.clone(),
+        cancelled,
+        percent: AtomicU32::new(0),
+    };
+    tauri::async_runtime::spawn_blocking(move || {
+        let state = app.state::<AppState>();
+        let start = std::time::Instant::now();
+        let outcome = if handle.is_cancelled() {
+            Err(AipixError::Cancelled)
+        } else {
+            job(&app, &state, &handle)
+        };
+        state.jobs.lock().unwrap().remove(&handle.id);
+
+        let (result, error) = match outcome {
+            Ok(value) => (Some(value), None),
+            Err(e) => {
+                tracing::warn!(job_id = %handle.id, operation, error = %e, "canvas job failed");
+                (None, serde_json::to_value(&e).ok())
+            }
+        };
+        tracing::debug!(
+            job_id = %handle.id,
+            operation,
+            elapsed_ms = start.elapsed().as_millis() as u64,
+            "canvas job finished"
+        );
+
+        let _ = app.emit(
+            JOB_FINISHED_EVENT,
+            JobFinished {
+                job_id: handle.id,
+                project_id: handle.project_id,
+                operation,
+                result,
+                error,
+            },
+        );
+    });
+}
+
+/// Ask a waiting or running job to stop; it finishes with a `cancelled` error once it notices
+#[tauri::command]
+pub fn cancel_job(state: State<AppState>, job_id: String) -> Result<()> {
+    let jobs = state.jobs.lock().unwrap();
+    let cancelled = jobs.get(&job_id).ok_or(AipixError::NotFound("Job"))?;
+    cancelled.store(true, Ordering::Relaxed);
+    tracing::debug!(%job_id, "canvas job cancellation requested");
+    Ok(())
+}
+
+/// The optional parts of a draw_fill; every field may be left out
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(default)]
+pub struct FillOptions {
+    pub contiguous: Option<bool>, // On unless turned off
+    pub tolerance: u8,
+    pub blend: Option<engine::BlendMode>,
+}
+
+/// Flood fill from (x, y); finishes with `null`
+///
+/// Pixels within `tolerance` of the clicked color (0, the default, for an
+/// exact match) are filled. With `contiguous` off, every such pixel is
+/// painted instead of only the connected ones, inside the selection if there
+/// is one. With a `blend` mode the color is composited onto the filled pixels.
+#[tauri::command]
+pub async fn draw_fill(
+    app: AppHandle,
+    project_id: String,
+    x: u32,
+    y: u32,
+    color: String,
+    options: Option<FillOptions>,
+) -> Result<String> {
+    let FillOptions { contiguous, tolerance, blend } = options.unwrap_or_default();
+    let rgba = app.state::<AppState>().tool_color(&project_id, &color)?;
+    let blend = engine::BlendMode::for_colors(blend, &[rgba]);
+    if !contiguous.unwrap_or(true) {
+        return Ok(spawn_canvas_job(app, project_id.clone(), "fill", move |app, state, _job| {
+            fill_global(app, state, &project_id, (x, y), rgba, tolerance, blend)
+        }));
+    }
+
+    Ok(spawn_canvas_job(app, project_id.clone(), "fill", move |app, state, _job| {
+        let document = state.document(&project_id)?;
+        let mut document = document.lock().unwrap();
+        let history = &mut document.history;
+
+        // Save state before filling (for undo)
+        history.push_state();
+
+        let ((), painted) = state.profiler.time("fill", || {
+            document.draw_blended(blend, |buffer| engine::tools::fill(buffer, x, y, rgba, tolerance))
+        })?;
+
+        state.record(&project_id, &document, Operation::PushState);
+        state.record_painted(&project_id, &document, painted, || {
+            Ok(Operation::Fill { x, y, color: rgba, tolerance })
+        });
+
+        events::emit_changes(app, &project_id, &document, Changes::EDIT);
+        Ok(Value::Null)
+    }))
+}
+
+/// The non-contiguous mode of draw_fill
+fn fill_global(
+    app: &AppHandle,
+    state: &AppState,
+    project_id: &str,
+    (x, y): (u32, u32),
+    color: [u8; 4],
+    tolerance: u8,
+    blend: Option<engine::BlendMode>,
+) -> Result<Value> {
+    let document = state.document(project_id)?;
+    let mut document = document.lock().unwrap();
+    let selection = document.selection.clone();
+
+    document.history.push_state();
+    let (changed, painted) = state.profiler.time("fill", || {
+        let selection = selection.as_ref();
+        document.draw_blended(blend, |buffer| engine::tools::fill_global(buffer, x, y, color, tolerance, selection))
+    })?;
+
+    // Depends on the tolerance and selection, so the result is recorded;
+    // blending can change pixels the fill found already in its color
+    state.record(project_id, &document, Operation::PushState);
+    if changed.is_some() || blend.is_some() {
+        state.record_painted(project_id, &document, painted, || {
+            Operation::patch(&document.history.buffer, changed)
+        });
+    }
+
+    events::emit_changes(app, project_id, &document, Changes::EDIT);
+    Ok(Value::Null)
+}
+
+/// Replace every pixel of one color; finishes with `null`
+#[tauri::command]
+pub async fn replace_color(
+    app: AppHandle,
+    project_id: String,
+    target_color: String,
+    new_color: String,
+) -> Result<String> {
+    let target_rgba = engine::tools::hex_to_rgba(&target_color)?;
+    let new_rgba = engine::tools::hex_to_rgba(&new_color)?;
+
+    Ok(spawn_canvas_job(app, project_id.clone(), "replace_color", move |app, state, _job| {
+        let document = state.document(&project_id)?;
+        let mut document = document.lock().unwrap();
+
+        let ((), painted) = document.paint(|buffer| {
+            engine::tools::replace_all_color(buffer, target_rgba, new_rgba);
+            Ok(())
+        })?;
+        state.record_painted(&project_id, &document, painted, || {
+            Ok(Operation::ReplaceColor { target: target_rgba, replacement: new_rgba })
+        });
+
+        events::emit_changes(app, &project_id, &document, Changes::PIXELS);
+        Ok(Value::Null)
+    }))
+}
+
+/// Magic wand selection; finishes with the updated selection
+#[tauri::command]
+pub async fn select_magic_wand(
+    app: AppHandle,
+    project_id: String,
+    x: u32,
+    y: u32,
+    tolerance: u8,
+    mode: engine::SelectionMode,
+) -> Result<String> {
+    Ok(spawn_canvas_job(app, project_id.clone(), "magic_wand", move |app, state, _job| {
+        let document = state.document(&project_id)?;
+        let mut document = document.lock().unwrap();
+        let engine::Document { history, selection, .. } = &mut *document;
+
+        let selection = selection
+            .as_mut()
+            .ok_or(AipixError::NotFound("Selection"))?;
+
+        engine::tools::select_magic_wand(&history.buffer, selection, x, y, tolerance, mode)?;
+        let selection = serde_json::to_value(&*selection)?;
+
+        events::emit_changes(app, &project_id, &document, Changes::SELECTION);
+        Ok(selection)
+    }))
+}
+
+/// Upscale the project canvas in place; finishes with the new `[width, height]`
+#[tauri::command]
+pub async fn upscale_layer(
+    app: AppHandle,
+    project_id: String,
+    algorithm: engine::UpscaleAlgorithm,
+    factor: u32,
+) -> Result<String> {
+    Ok(spawn_canvas_job(app, project_id.clone(), "upscale", move |app, state, job| {
+        let document = state.document(&project_id)?;
+        let mut document = document.lock().unwrap();
+        let history = &mut document.history;
+
+        let (width, height) = (history.buffer.width, history.buffer.height);
+        let too_large = || {
+            AipixError::InvalidInput(format!(
+                "Upscaling {}x{} by {} exceeds the maximum canvas size",
+                width, height, factor
+            ))
+        };
+        state.validate_canvas_size(
+            width.checked_mul(factor).ok_or_else(too_large)?,
+            height.checked_mul(factor).ok_or_else(too_large)?,
+        )?;
+
+        let upscaled = state.profiler.time("upscale", || {
+            engine::upscale::upscale_with_progress(&history.buffer, algorithm, factor, job)
+        })?;
+        let size = (upscaled.width, upscaled.height);
+
+        history.push_state();
+        history.buffer = upscaled;
+
+        // The selection mask is canvas-sized, so start over with an empty one
+        if let Some(selection) = document.selection.as_mut() {
+            *selection = engine::Selection::new(size.0, size.1);
+        }
+
+        state.record(&project_id, &document, Operation::PushState);
+        state.record(&project_id, &document, Operation::Upscale { algorithm, factor });
+
+        events::emit_changes(app, &project_id, &document, Changes::ALL);
+        Ok(serde_json::to_value(size)?)
+    }))
+}
+
+/// Rotate the whole canvas by `degrees` clockwise about its center with RotSprite; finishes with `null`
+#[tauri::command]
+pub async fn rotate_layer(app: AppHandle, project_id: String, degrees: f32) -> Result<String> {
+    Ok(spawn_canvas_job(app, project_id.clone(), "rotate", move |app, state, job| {
+        let document = state.document(&project_id)?;
+        let mut document = document.lock().unwrap();
+        let history = &mut document.history;
+
+        let (center_x, center_y) = (history.buffer.width as f32 / 2.0, history.buffer.height as f32 / 2.0);
+        let rotated = state.profiler.time("rotate", || {
+            engine::rotsprite::rotsprite(&history.buffer, degrees, center_x, center_y, job)
+        })?;
+
+        history.push_state();
+        history.buffer = rotated;
+
+        state.record(&project_id, &document, Operation::PushState);
+        state.record_with(&project_id, &document, || Operation::patch(&document.history.buffer, None));
+
+        events::emit_changes(app, &project_id, &document, Changes::EDIT);
+        Ok(Value::Null)
+    }))
+}
+
+/// Rotate the selected pixels by `degrees` clockwise about the selection's
+/// center with RotSprite; finishes with the selection, which now covers the
+/// rotated pixels
+#[tauri::command]
+pub async fn rotate_selection(app: AppHandle, project_id: String, degrees: f32) -> Result<String> {
+    Ok(spawn_canvas_job(app, project_id.clone(), "rotate_selection", move |app, state, job| {
+        let document = state.document(&project_id)?;
+        let mut document = document.lock().unwrap();
+        let engine::Document { history, selection, .. } = &mut *document;
+
+        let selection = selection
+            .as_mut()
+            .ok_or(AipixError::NotFound("Selection"))?;
+        let (extracted, x, y) = engine::tools::extract_selection(&history.buffer, selection)
+            .ok_or_else(|| AipixError::InvalidState("No selection to rotate".to_string()))?;
+
+        // Rotate on a canvas-sized buffer so the result can land anywhere on the canvas
+        let mut floating = engine::PixelBuffer::new(history.buffer.width, history.buffer.height);
+        floating.blit(&extracted, x as i32, y as i32);
+        let center_x = x as f32 + extracted.width as f32 / 2.0;
+        let center_y = y as f32 + extracted.height as f32 / 2.0;
+        let rotated = state.profiler.time("rotate", || {
+            engine::rotsprite::rotsprite(&floating, degrees, center_x, center_y, job)
+        })?;
+
+        history.push_state();
+        engine::tools::delete_selection(&mut history.buffer, selection);
+        engine::tools::paste_buffer(&mut history.buffer, &rotated, 0, 0)?;
+
+        selection.clear();
+        for (i, pixel) in rotated.pixels().enumerate() {
+            if pixel[3] > 0 {
+                selection.mask[i] = engine::tools::FULLY_SELECTED;
+            }
+        }
+        selection.update_bounds();
+        let selection = serde_json::to_value(&*selection)?;
+
+        state.record(&project_id, &document, Operation::PushState);
+        state.record_with(&project_id, &document, || Operation::patch(&document.history.buffer, None));
+
+        events::emit_changes(app, &project_id, &document, Changes::ALL);
+        Ok(selection)
+    }))
+}
+
+/// Apply a color filter (HSL, levels, dither, outline or noise) as one undoable step; finishes with `null`
+///
+/// `scope` defaults to the selection, or the whole canvas without one; the
+/// `layer` scope is the whole canvas. A cancelled filter leaves the canvas and its history untouched.
+#[tauri::command]
+pub async fn apply_pixel_filter(
+    app: AppHandle,
+    project_id: String,
+    filter: engine::PixelFilter,
+    scope: Option<engine::FilterScope>,
+) -> Result<String> {
+    filter.validate()?;
+    Ok(spawn_canvas_job(app, project_id.clone(), "filter", move |app, state, job| {
+        let document = state.document(&project_id)?;
+        let mut document = document.lock().unwrap();
+
+        let mut filtered = document.history.buffer.clone();
+        let changed = state.profiler.time("filter", || {
+            let scope = scope.unwrap_or_default();
+            engine::filters::apply_filter_with_progress(&mut filtered, document.selection.as_ref(), scope, &filter, job)
+        })?;
+
+        document.history.push_state();
+        let ((), painted) = document.paint(|buffer| {
+            *buffer = filtered;
+            Ok(())
+        })?;
+
+        state.record(&project_id, &document, Operation::PushState);
+        if let Some(changed) = changed {
+            state.record_painted(&project_id, &document, painted, || {
+                Operation::patch(&document.history.buffer, Some(changed))
+            });
+        }
+
+        events::emit_changes(app, &project_id, &document, Changes::EDIT);
+        Ok(Value::Null)
+    }))
+}
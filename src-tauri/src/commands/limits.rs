@@ -0,0 +1,28 @@
+// Canvas size limit commands
+//
+// The limits start at CanvasLimits::default(); the settings screen can raise
+// or lower them for machines with more or less memory.
+
+use crate::engine::CanvasLimits;
+use crate::error::Result;
+use crate::AppState;
+use tauri::State;
+
+/// Current maximum canvas dimensions
+#[tauri::command]
+pub fn get_canvas_limits(state: State<'_, AppState>) -> Result<CanvasLimits> {
+    Ok(*state.canvas_limits.lock().unwrap())
+}
+
+/// Replace the canvas limits (`None` restores the defaults)
+#[tauri::command]
+pub fn set_canvas_limits(
+    state: State<'_, AppState>,
+    limits: Option<CanvasLimits>,
+) -> Result<CanvasLimits> {
+    let limits = limits.unwrap_or_default();
+    limits.check_sane()?;
+
+    *state.canvas_limits.lock().unwrap() = limits;
+    Ok(limits)
+}
@@ -0,0 +1,20 @@
+// Log access commands
+
+use crate::error::Result;
+use crate::logging;
+
+/// Lines returned by get_log_tail when no count is given
+const DEFAULT_TAIL_LINES: usize = 200;
+
+/// Last lines of the current log file, oldest first
+#[tauri::command]
+pub fn get_log_tail(lines: Option<usize>) -> Result<Vec<String>> {
+    logging::tail(lines.unwrap_or(DEFAULT_TAIL_LINES))
+}
+
+/// Set the log filter (`error`..`trace`, or per-module directives); returns the new filter
+#[tauri::command]
+pub fn set_log_level(level: String) -> Result<String> {
+    logging::set_level(&level)?;
+    logging::level()
+}
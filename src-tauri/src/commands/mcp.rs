@@ -0,0 +1,88 @@
+// MCP server commands
+//
+// The WebSocket server is opt-in, bound to localhost and protected by a
+// per-start token that the UI shows for the user to give their agent.
+// Agents can only export into a directory the user picked here.
+
+use crate::error::{AipixError, Result};
+use crate::mcp::{self, transport};
+use crate::AppState;
+use serde::Serialize;
+use std::path::PathBuf;
+use tauri::{AppHandle, State};
+
+/// Default WebSocket port for MCP clients
+pub const DEFAULT_PORT: u16 = 7357;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct McpStatus {
+    pub running: bool,
+    pub url: Option<String>,
+    pub token: Option<String>,
+    pub export_dir: Option<String>,
+}
+
+fn url(handle: &mcp::ServerHandle) -> String {
+    format!("ws://{}", handle.addr)
+}
+
+fn status(state: &AppState, server: Option<&mcp::ServerHandle>) -> McpStatus {
+    McpStatus {
+        running: server.is_some(),
+        url: server.map(url),
+        token: server.map(|handle| handle.token.clone()),
+        export_dir: state.mcp_export_dir.lock().unwrap().as_ref().map(|dir| dir.to_string_lossy().into_owned()),
+    }
+}
+
+/// Start the WebSocket MCP server and return its URL and token
+#[tauri::command]
+pub fn start_mcp_server(
+    app: AppHandle,
+    state: State<AppState>,
+    port: Option<u16>,
+) -> Result<McpStatus> {
+    let mut server = state.mcp_server.lock().unwrap();
+    if let Some(handle) = server.as_ref() {
+        return Err(AipixError::Conflict(format!(
+            "MCP server is already running at {}",
+            url(handle)
+        )));
+    }
+
+    let handle = transport::start(app, port.unwrap_or(DEFAULT_PORT))?;
+    let started = status(&state, Some(&handle));
+    *server = Some(handle);
+    Ok(started)
+}
+
+#[tauri::command]
+pub fn stop_mcp_server(state: State<AppState>) -> Result<()> {
+    if let Some(handle) = state.mcp_server.lock().unwrap().take() {
+        handle.stop();
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_mcp_status(state: State<AppState>) -> Result<McpStatus> {
+    let server = state.mcp_server.lock().unwrap();
+    Ok(status(&state, server.as_ref()))
+}
+
+/// Let MCP agents export into `dir` (an existing directory); None stops all exports
+#[tauri::command]
+pub fn set_mcp_export_dir(state: State<AppState>, dir: Option<String>) -> Result<()> {
+    let dir = match dir {
+        Some(dir) => {
+            let dir = PathBuf::from(dir).canonicalize()?;
+            if !dir.is_dir() {
+                return Err(AipixError::InvalidInput(format!("{} is not a directory", dir.display())));
+            }
+            Some(dir)
+        }
+        None => None,
+    };
+    *state.mcp_export_dir.lock().unwrap() = dir;
+    Ok(())
+}
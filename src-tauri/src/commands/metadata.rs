@@ -0,0 +1,414 @@
+// Project metadata commands
+//
+// Slices, nine-slice insets, pivots, hitboxes, guides, palette cycles and
+// frame tags are stored with the project rather than the open document, so they survive
+// closing the canvas and are available to exports. Slice geometry is validated against
+// the current canvas size; pivots, hitboxes and guides may lie past it.
+
+use crate::commands::RendererState;
+use crate::database::{FrameMetadata, ProjectMetadata};
+use crate::engine::{FrameTag, Guide, GuideOrientation, Hitbox, NineSlice, PaletteCycle, Pivot, Slice};
+use crate::error::{AipixError, Result};
+use crate::fileio;
+use crate::AppState;
+use serde_json::json;
+use std::path::PathBuf;
+use tauri::State;
+
+/// Canvas size of a project: the open document if any, else the stored project
+fn canvas_size(state: &AppState, project_id: &str) -> Result<(u32, u32)> {
+    if let Ok(document) = state.document(project_id) {
+        let buffer = &document.lock().unwrap().history.buffer;
+        return Ok((buffer.width, buffer.height));
+    }
+
+    let db_guard = state.db.lock().unwrap();
+    let db = db_guard.as_ref().ok_or(AipixError::DatabaseNotInitialized)?;
+    let project = db.get_project(project_id)?.ok_or(AipixError::NotFound("Project"))?;
+    Ok((project.width, project.height))
+}
+
+/// Read-modify-write the metadata while holding the database
+pub(crate) fn update_metadata(
+    state: &AppState,
+    project_id: &str,
+    f: impl FnOnce(&mut ProjectMetadata) -> Result<()>,
+) -> Result<ProjectMetadata> {
+    let db_guard = state.db.lock().unwrap();
+    let db = db_guard.as_ref().ok_or(AipixError::DatabaseNotInitialized)?;
+
+    let mut metadata = db.get_project_metadata(project_id)?;
+    f(&mut metadata)?;
+    db.set_project_metadata(project_id, &metadata)?;
+    Ok(metadata)
+}
+
+#[tauri::command]
+pub fn get_project_metadata(state: State<AppState>, project_id: String) -> Result<ProjectMetadata> {
+    let db_guard = state.db.lock().unwrap();
+    let db = db_guard.as_ref().ok_or(AipixError::DatabaseNotInitialized)?;
+
+    db.get_project_metadata(&project_id)
+}
+
+/// Mark the whole canvas as a nine-slice, or clear it with `None`
+#[tauri::command]
+pub fn set_nine_slice(
+    state: State<AppState>,
+    project_id: String,
+    nine_slice: Option<NineSlice>,
+) -> Result<ProjectMetadata> {
+    if let Some(insets) = &nine_slice {
+        let (width, height) = canvas_size(&state, &project_id)?;
+        insets.validate(width, height)?;
+    }
+
+    update_metadata(&state, &project_id, |metadata| {
+        metadata.nine_slice = nine_slice;
+        Ok(())
+    })
+}
+
+/// Add a slice, or replace the one with the same name
+#[tauri::command]
+pub fn set_slice(state: State<AppState>, project_id: String, slice: Slice) -> Result<ProjectMetadata> {
+    let (width, height) = canvas_size(&state, &project_id)?;
+    slice.validate(width, height)?;
+
+    update_metadata(&state, &project_id, |metadata| {
+        match metadata.slices.iter_mut().find(|s| s.name == slice.name) {
+            Some(existing) => *existing = slice,
+            None => metadata.slices.push(slice),
+        }
+        Ok(())
+    })
+}
+
+#[tauri::command]
+pub fn delete_slice(state: State<AppState>, project_id: String, name: String) -> Result<ProjectMetadata> {
+    update_metadata(&state, &project_id, |metadata| {
+        let count = metadata.slices.len();
+        metadata.slices.retain(|s| s.name != name);
+        if metadata.slices.len() == count {
+            return Err(AipixError::NotFound("Slice"));
+        }
+        Ok(())
+    })
+}
+
+#[tauri::command]
+pub fn get_frame_pivot(state: State<AppState>, project_id: String, frame_index: u32) -> Result<Option<Pivot>> {
+    let db_guard = state.db.lock().unwrap();
+    let db = db_guard.as_ref().ok_or(AipixError::DatabaseNotInitialized)?;
+
+    let metadata = db.get_project_metadata(&project_id)?;
+    Ok(metadata.frames.get(&frame_index).and_then(|frame| frame.pivot))
+}
+
+/// Set a frame's pivot, relative to the frame's top-left; `None` clears it
+#[tauri::command]
+pub fn set_frame_pivot(
+    state: State<AppState>,
+    project_id: String,
+    frame_index: u32,
+    pivot: Option<Pivot>,
+) -> Result<ProjectMetadata> {
+    update_metadata(&state, &project_id, |metadata| {
+        let frame = metadata.frames.entry(frame_index).or_insert_with(FrameMetadata::default);
+        frame.pivot = pivot;
+        if frame.is_empty() {
+            metadata.frames.remove(&frame_index);
+        }
+        Ok(())
+    })
+}
+
+#[tauri::command]
+pub fn get_slice_pivot(state: State<AppState>, project_id: String, name: String) -> Result<Option<Pivot>> {
+    let db_guard = state.db.lock().unwrap();
+    let db = db_guard.as_ref().ok_or(AipixError::DatabaseNotInitialized)?;
+
+    let metadata = db.get_project_metadata(&project_id)?;
+    let slice = metadata.slices.iter().find(|s| s.name == name).ok_or(AipixError::NotFound("Slice"))?;
+    Ok(slice.pivot)
+}
+
+/// Set a slice's pivot, relative to the slice's top-left; `None` clears it
+#[tauri::command]
+pub fn set_slice_pivot(
+    state: State<AppState>,
+    project_id: String,
+    name: String,
+    pivot: Option<Pivot>,
+) -> Result<ProjectMetadata> {
+    update_metadata(&state, &project_id, |metadata| {
+        let slice = metadata.slices.iter_mut().find(|s| s.name == name).ok_or(AipixError::NotFound("Slice"))?;
+        slice.pivot = pivot;
+        Ok(())
+    })
+}
+
+#[tauri::command]
+pub fn get_frame_hitboxes(state: State<AppState>, project_id: String, frame_index: u32) -> Result<Vec<Hitbox>> {
+    let db_guard = state.db.lock().unwrap();
+    let db = db_guard.as_ref().ok_or(AipixError::DatabaseNotInitialized)?;
+
+    let metadata = db.get_project_metadata(&project_id)?;
+    Ok(metadata.frames.get(&frame_index).map(|frame| frame.hitboxes.clone()).unwrap_or_default())
+}
+
+/// Add a hitbox to a frame, or replace the one with the same name
+#[tauri::command]
+pub fn set_hitbox(
+    state: State<AppState>,
+    project_id: String,
+    frame_index: u32,
+    hitbox: Hitbox,
+) -> Result<ProjectMetadata> {
+    hitbox.validate()?;
+
+    update_metadata(&state, &project_id, |metadata| {
+        let frame = metadata.frames.entry(frame_index).or_insert_with(FrameMetadata::default);
+        match frame.hitboxes.iter_mut().find(|h| h.name == hitbox.name) {
+            Some(existing) => *existing = hitbox,
+            None => frame.hitboxes.push(hitbox),
+        }
+        Ok(())
+    })
+}
+
+#[tauri::command]
+pub fn delete_hitbox(
+    state: State<AppState>,
+    project_id: String,
+    frame_index: u32,
+    name: String,
+) -> Result<ProjectMetadata> {
+    update_metadata(&state, &project_id, |metadata| {
+        let frame = metadata.frames.get_mut(&frame_index).ok_or(AipixError::NotFound("Hitbox"))?;
+        let count = frame.hitboxes.len();
+        frame.hitboxes.retain(|h| h.name != name);
+        if frame.hitboxes.len() == count {
+            return Err(AipixError::NotFound("Hitbox"));
+        }
+        if frame.is_empty() {
+            metadata.frames.remove(&frame_index);
+        }
+        Ok(())
+    })
+}
+
+/// Change a project's guides and refresh the viewport overlay
+fn update_guides(
+    state: &AppState,
+    renderer: &RendererState,
+    project_id: &str,
+    f: impl FnOnce(&mut Vec<Guide>) -> Result<()>,
+) -> Result<ProjectMetadata> {
+    let metadata = update_metadata(state, project_id, |metadata| f(&mut metadata.guides))?;
+    renderer.update_guides(project_id, &metadata.guides);
+    Ok(metadata)
+}
+
+#[tauri::command]
+pub fn add_guide(
+    state: State<AppState>,
+    renderer: State<RendererState>,
+    project_id: String,
+    orientation: GuideOrientation,
+    position: i32,
+) -> Result<ProjectMetadata> {
+    update_guides(&state, &renderer, &project_id, |guides| {
+        guides.push(Guide::new(orientation, position));
+        Ok(())
+    })
+}
+
+#[tauri::command]
+pub fn move_guide(
+    state: State<AppState>,
+    renderer: State<RendererState>,
+    project_id: String,
+    id: String,
+    position: i32,
+) -> Result<ProjectMetadata> {
+    update_guides(&state, &renderer, &project_id, |guides| {
+        let guide = guides.iter_mut().find(|g| g.id == id).ok_or(AipixError::NotFound("Guide"))?;
+        guide.position = position;
+        Ok(())
+    })
+}
+
+#[tauri::command]
+pub fn delete_guide(
+    state: State<AppState>,
+    renderer: State<RendererState>,
+    project_id: String,
+    id: String,
+) -> Result<ProjectMetadata> {
+    update_guides(&state, &renderer, &project_id, |guides| {
+        let count = guides.len();
+        guides.retain(|g| g.id != id);
+        if guides.len() == count {
+            return Err(AipixError::NotFound("Guide"));
+        }
+        Ok(())
+    })
+}
+
+#[tauri::command]
+pub fn clear_guides(
+    state: State<AppState>,
+    renderer: State<RendererState>,
+    project_id: String,
+) -> Result<ProjectMetadata> {
+    update_guides(&state, &renderer, &project_id, |guides| {
+        guides.clear();
+        Ok(())
+    })
+}
+
+/// Distance in pixels within which tools snap to guides; 0 turns it off
+#[tauri::command]
+pub fn set_guide_snap_threshold(
+    state: State<AppState>,
+    project_id: String,
+    threshold: u32,
+) -> Result<ProjectMetadata> {
+    update_metadata(&state, &project_id, |metadata| {
+        metadata.guide_snap_threshold = threshold;
+        Ok(())
+    })
+}
+
+/// Add a palette cycle, or replace the one with the same name
+///
+/// Indices aren't checked against the palette, which may change; cycles
+/// past its end are skipped when shown.
+#[tauri::command]
+pub fn set_palette_cycle(
+    state: State<AppState>,
+    renderer: State<RendererState>,
+    project_id: String,
+    cycle: PaletteCycle,
+) -> Result<ProjectMetadata> {
+    cycle.validate()?;
+
+    let metadata = update_metadata(&state, &project_id, |metadata| {
+        match metadata.palette_cycles.iter_mut().find(|c| c.name == cycle.name) {
+            Some(existing) => *existing = cycle,
+            None => metadata.palette_cycles.push(cycle),
+        }
+        Ok(())
+    })?;
+    renderer.update_palette_cycles(&project_id, &metadata.palette_cycles);
+    Ok(metadata)
+}
+
+#[tauri::command]
+pub fn delete_palette_cycle(
+    state: State<AppState>,
+    renderer: State<RendererState>,
+    project_id: String,
+    name: String,
+) -> Result<ProjectMetadata> {
+    let metadata = update_metadata(&state, &project_id, |metadata| {
+        let count = metadata.palette_cycles.len();
+        metadata.palette_cycles.retain(|c| c.name != name);
+        if metadata.palette_cycles.len() == count {
+            return Err(AipixError::NotFound("Palette cycle"));
+        }
+        Ok(())
+    })?;
+    renderer.update_palette_cycles(&project_id, &metadata.palette_cycles);
+    Ok(metadata)
+}
+
+/// Add a frame tag, or replace the one with the same name
+///
+/// Frames aren't checked against the canvas, which may be resized; exports
+/// of a tag past the last frame fail.
+#[tauri::command]
+pub fn set_frame_tag(state: State<AppState>, project_id: String, tag: FrameTag) -> Result<ProjectMetadata> {
+    tag.validate()?;
+
+    update_metadata(&state, &project_id, |metadata| {
+        match metadata.tags.iter_mut().find(|t| t.name == tag.name) {
+            Some(existing) => *existing = tag,
+            None => metadata.tags.push(tag),
+        }
+        Ok(())
+    })
+}
+
+#[tauri::command]
+pub fn delete_frame_tag(state: State<AppState>, project_id: String, name: String) -> Result<ProjectMetadata> {
+    update_metadata(&state, &project_id, |metadata| {
+        let count = metadata.tags.len();
+        metadata.tags.retain(|t| t.name != name);
+        if metadata.tags.len() == count {
+            return Err(AipixError::NotFound("Tag"));
+        }
+        Ok(())
+    })
+}
+
+/// Export a nine-slice as a PNG plus a JSON file of its insets
+///
+/// Exports the named slice, or the whole canvas when `slice` is omitted.
+/// The JSON lists the insets both as named edges and in the orders Unity
+/// (left, bottom, right, top) and CSS border-image (top, right, bottom,
+/// left) expect. Returns the path of the JSON file.
+#[tauri::command]
+pub fn export_nine_slice(
+    state: State<AppState>,
+    project_id: String,
+    path: String,
+    slice: Option<String>,
+) -> Result<String> {
+    let metadata = {
+        let db_guard = state.db.lock().unwrap();
+        let db = db_guard.as_ref().ok_or(AipixError::DatabaseNotInitialized)?;
+        db.get_project_metadata(&project_id)?
+    };
+    let canvas = state.document(&project_id)?.lock().unwrap().history.buffer.clone();
+
+    let not_nine_slice = || AipixError::InvalidState("No nine-slice insets are set".to_string());
+    let (image, insets) = match &slice {
+        Some(name) => {
+            let slice = metadata
+                .slices
+                .iter()
+                .find(|s| &s.name == name)
+                .ok_or(AipixError::NotFound("Slice"))?;
+            slice.validate(canvas.width, canvas.height)?;
+            let insets = slice.nine_slice.ok_or_else(not_nine_slice)?;
+            (canvas.copy_region(slice.x, slice.y, slice.width, slice.height), insets)
+        }
+        None => (canvas, metadata.nine_slice.ok_or_else(not_nine_slice)?),
+    };
+    // The canvas may have been resized since the insets were set
+    insets.validate(image.width, image.height)?;
+
+    let mut image_path = PathBuf::from(&path);
+    if image_path.extension().is_none() {
+        image_path.set_extension("png");
+    }
+    let json_path = image_path.with_extension("json");
+    fileio::save_image(&image_path, &fileio::buffer_to_image(&image)?)?;
+
+    let (center_x, center_y, center_width, center_height) = insets.center(image.width, image.height);
+    let NineSlice { left, top, right, bottom } = insets;
+    let description = json!({
+        "image": image_path.file_name().map(|name| name.to_string_lossy().into_owned()),
+        "width": image.width,
+        "height": image.height,
+        "insets": insets,
+        "center": { "x": center_x, "y": center_y, "width": center_width, "height": center_height },
+        "unity_border": [left, bottom, right, top],
+        "css_border_image_slice": [top, right, bottom, left],
+    });
+    std::fs::write(&json_path, serde_json::to_vec_pretty(&description)?)?;
+
+    Ok(json_path.to_string_lossy().into_owned())
+}
@@ -1,5 +1,29 @@
 // Tauri commands module
 
 pub mod rendering;
+pub mod ai;
+pub mod events;
+pub mod jobs;
+pub mod limits;
+pub mod diagnostics;
+pub mod logging;
+pub mod recovery;
+pub mod plugins;
+pub mod mcp;
+pub mod http_api;
+pub mod files;
+pub mod tilemap;
+pub mod metadata;
+pub mod export;
+pub mod export_queue;
+pub mod documents;
+pub mod avatars;
+pub mod thumbnails;
+pub mod transform;
+pub mod clipboard;
+pub mod settings;
+pub mod brushes;
+pub mod analysis;
+pub mod activity;
 
 pub use rendering::RendererState;
@@ -0,0 +1,212 @@
+// Plugin commands
+//
+// Discovery and settings are synchronous; running a plugin over a canvas
+// goes through the background job queue like the other whole-canvas
+// operations, except tools, which are awaited on the blocking pool. The
+// document is only locked to copy the input and to write the result back,
+// never while the plugin runs.
+
+use super::events::{self, Changes};
+use super::jobs::spawn_canvas_job;
+use crate::engine;
+use crate::error::{AipixError, Result};
+use crate::journal::Operation;
+use crate::plugins::{self, runtime, Capability, LoadedPlugin, Permission, PluginInfo, PluginLoadError};
+use crate::AppState;
+use serde::Serialize;
+use serde_json::Value;
+use std::path::PathBuf;
+use tauri::{AppHandle, Manager, State};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PluginListing {
+    pub plugins: Vec<PluginInfo>,
+    pub errors: Vec<PluginLoadError>, // Directories that failed to load
+}
+
+fn with_plugins<T>(
+    state: &AppState,
+    f: impl FnOnce(&mut plugins::PluginManager) -> Result<T>,
+) -> Result<T> {
+    let mut manager = state.plugins.lock().unwrap();
+    let manager = manager
+        .as_mut()
+        .ok_or_else(|| AipixError::InvalidState("Plugins are not available".to_string()))?;
+    f(manager)
+}
+
+fn load_plugin(state: &AppState, plugin_id: &str, capability: Capability) -> Result<LoadedPlugin> {
+    with_plugins(state, |manager| manager.load(plugin_id, capability))
+}
+
+/// Rescan the plugin directory and list what was found
+#[tauri::command]
+pub fn list_plugins(state: State<'_, AppState>) -> Result<PluginListing> {
+    with_plugins(&state, |manager| {
+        let errors = manager.discover();
+        Ok(PluginListing { plugins: manager.list(), errors })
+    })
+}
+
+#[tauri::command]
+pub fn set_plugin_enabled(state: State<'_, AppState>, plugin_id: String, enabled: bool) -> Result<()> {
+    with_plugins(&state, |manager| manager.set_enabled(&plugin_id, enabled))
+}
+
+/// Grant exactly `permissions` (a subset of what the manifest requests)
+#[tauri::command]
+pub fn set_plugin_permissions(
+    state: State<'_, AppState>,
+    plugin_id: String,
+    permissions: Vec<Permission>,
+) -> Result<()> {
+    with_plugins(&state, |manager| manager.set_permissions(&plugin_id, permissions))
+}
+
+/// Copy what a plugin may see out of the document
+fn snapshot(state: &AppState, project_id: &str) -> Result<(engine::PixelBuffer, runtime::HostContext)> {
+    let document = state.document(project_id)?;
+    let document = document.lock().unwrap();
+    let context = runtime::HostContext {
+        palette: document.history.palette.colors.clone(),
+        selection: document.selection.clone(),
+    };
+    Ok((document.history.buffer.clone(), context))
+}
+
+/// Write a plugin's output back as one undoable step
+fn commit(
+    app: &AppHandle,
+    state: &AppState,
+    project_id: &str,
+    expected_size: Option<(u32, u32)>,
+    result: engine::PixelBuffer,
+) -> Result<()> {
+    let document = state.document(project_id)?;
+    let mut document = document.lock().unwrap();
+    let history = &mut document.history;
+
+    if let Some(size) = expected_size {
+        if (history.buffer.width, history.buffer.height) != size {
+            return Err(AipixError::Conflict(
+                "Canvas was resized while the plugin ran".to_string(),
+            ));
+        }
+    }
+
+    let resized = (history.buffer.width, history.buffer.height) != (result.width, result.height);
+    let size = (result.width, result.height);
+    history.push_state();
+    history.buffer = result;
+    if resized {
+        if let Some(selection) = document.selection.as_mut() {
+            *selection = engine::Selection::new(size.0, size.1);
+        }
+    }
+
+    state.record(project_id, &document, Operation::PushState);
+    state.record_with(project_id, &document, || Operation::replace(&document.history.buffer));
+
+    let changes = if resized { Changes::ALL } else { Changes::EDIT };
+    events::emit_changes(app, project_id, &document, changes);
+    Ok(())
+}
+
+/// Run a filter plugin over the canvas; finishes with `null`
+#[tauri::command]
+pub async fn run_filter_plugin(
+    app: AppHandle,
+    project_id: String,
+    plugin_id: String,
+    params: Option<Value>,
+) -> Result<String> {
+    Ok(spawn_canvas_job(app, project_id.clone(), "plugin_filter", move |app, state, job| {
+        let plugin = load_plugin(state, &plugin_id, Capability::Filter)?;
+        let (buffer, context) = snapshot(state, &project_id)?;
+        let size = (buffer.width, buffer.height);
+
+        let params = params.unwrap_or(Value::Null);
+        let result = state
+            .profiler
+            .time("plugin_filter", || runtime::run_filter(&plugin, &buffer, &params, context))?;
+
+        // Plugins can't be interrupted, but a cancelled result is discarded
+        if job.is_cancelled() {
+            return Err(AipixError::Cancelled);
+        }
+        commit(app, state, &project_id, Some(size), result)?;
+        Ok(Value::Null)
+    }))
+}
+
+/// Apply a tool plugin at (x, y)
+///
+/// Runs on the blocking pool like the jobs, but is awaited rather than
+/// queued: a tool click should land before the next one.
+#[tauri::command]
+pub async fn apply_tool_plugin(
+    app: AppHandle,
+    project_id: String,
+    plugin_id: String,
+    x: u32,
+    y: u32,
+    color: String,
+) -> Result<()> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let state = app.state::<AppState>();
+        let rgba = state.tool_color(&project_id, &color)?;
+        let plugin = load_plugin(&state, &plugin_id, Capability::Tool)?;
+        let (buffer, context) = snapshot(&state, &project_id)?;
+        let size = (buffer.width, buffer.height);
+
+        let result = runtime::run_tool(&plugin, &buffer, x, y, rgba, context)?;
+        commit(&app, &state, &project_id, Some(size), result)
+    })
+    .await
+    .map_err(|e| AipixError::Internal(format!("Tool plugin failed: {}", e)))?
+}
+
+/// Replace the canvas with a file decoded by an importer plugin; finishes with `[width, height]`
+#[tauri::command]
+pub async fn import_with_plugin(
+    app: AppHandle,
+    project_id: String,
+    plugin_id: String,
+    path: PathBuf,
+) -> Result<String> {
+    Ok(spawn_canvas_job(app, project_id.clone(), "plugin_import", move |app, state, job| {
+        let plugin = load_plugin(state, &plugin_id, Capability::Importer)?;
+        let bytes = std::fs::read(&path)?;
+
+        let image = runtime::run_import(&plugin, &bytes)?;
+        state.validate_canvas_size(image.width, image.height)?;
+        let size = (image.width, image.height);
+
+        if job.is_cancelled() {
+            return Err(AipixError::Cancelled);
+        }
+        commit(app, state, &project_id, None, image)?;
+        Ok(serde_json::to_value(size)?)
+    }))
+}
+
+/// Write the canvas to `path` through an exporter plugin; finishes with `null`
+#[tauri::command]
+pub async fn export_with_plugin(
+    app: AppHandle,
+    project_id: String,
+    plugin_id: String,
+    path: PathBuf,
+) -> Result<String> {
+    Ok(spawn_canvas_job(app, project_id.clone(), "plugin_export", move |_, state, job| {
+        let plugin = load_plugin(state, &plugin_id, Capability::Exporter)?;
+        let (buffer, _) = snapshot(state, &project_id)?;
+
+        let bytes = runtime::run_export(&plugin, &buffer)?;
+        if job.is_cancelled() {
+            return Err(AipixError::Cancelled);
+        }
+        std::fs::write(&path, bytes)?;
+        Ok(Value::Null)
+    }))
+}
@@ -0,0 +1,96 @@
+// Crash recovery commands
+//
+// On startup the frontend lists projects left with recovery data (the app
+// exited without discarding it), offers to restore them, and discards the
+// data once a project is saved or closed cleanly.
+
+use super::events::{self, Changes};
+use crate::error::{AipixError, Result};
+use crate::AppState;
+use serde::Serialize;
+use std::sync::{Arc, Mutex};
+use tauri::{AppHandle, State};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RecoveryReport {
+    pub project_id: String,
+    pub width: u32,
+    pub height: u32,
+    pub replayed: usize,
+    pub skipped: usize,
+}
+
+fn journal_unavailable() -> AipixError {
+    AipixError::InvalidState("Crash recovery is not available".to_string())
+}
+
+/// Projects with unsaved work from a previous session
+#[tauri::command]
+pub fn list_recoverable_documents(state: State<'_, AppState>) -> Result<Vec<String>> {
+    let journal = state.journal.lock().unwrap();
+    journal.as_ref().ok_or_else(journal_unavailable)?.recoverable()
+}
+
+/// Restore a project from its last autosave plus journal, replacing any open canvas
+#[tauri::command]
+pub fn recover_document(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    project_id: String,
+) -> Result<RecoveryReport> {
+    let recovered = {
+        let journal = state.journal.lock().unwrap();
+        journal.as_ref().ok_or_else(journal_unavailable)?.recover(&project_id)?
+    };
+
+    let document = recovered.document;
+    state.validate_canvas_size(document.history.buffer.width, document.history.buffer.height)?;
+
+    let report = RecoveryReport {
+        project_id: project_id.clone(),
+        width: document.history.buffer.width,
+        height: document.history.buffer.height,
+        replayed: recovered.replayed,
+        skipped: recovered.skipped,
+    };
+    tracing::info!(
+        %project_id,
+        replayed = report.replayed,
+        skipped = report.skipped,
+        "recovered document"
+    );
+
+    let handle = Arc::new(Mutex::new(document));
+    state
+        .documents
+        .write()
+        .unwrap()
+        .insert(project_id.clone(), handle.clone());
+
+    // Fold the replayed journal into a fresh checkpoint and keep journaling
+    let document = handle.lock().unwrap();
+    state.checkpoint(&project_id, &document);
+
+    events::emit_changes(&app, &project_id, &document, Changes::ALL);
+    Ok(report)
+}
+
+/// Autosave a project now (e.g. before a risky operation)
+#[tauri::command]
+pub fn autosave_document(state: State<'_, AppState>, project_id: String) -> Result<()> {
+    let document = state.document(&project_id)?;
+    let document = document.lock().unwrap();
+
+    let mut journal = state.journal.lock().unwrap();
+    journal
+        .as_mut()
+        .ok_or_else(journal_unavailable)?
+        .checkpoint(&project_id, &document.history.buffer)
+}
+
+/// Drop a project's recovery data after it was saved or closed cleanly
+#[tauri::command]
+pub fn discard_recovery(state: State<'_, AppState>, project_id: String) -> Result<()> {
+    let mut journal = state.journal.lock().unwrap();
+    journal.as_mut().ok_or_else(journal_unavailable)?.discard(&project_id)
+}
@@ -3,21 +3,98 @@
 // These commands bridge the frontend to our native Skia renderer,
 // replacing the WebGL/Canvas2D approach.
 
-use crate::engine::renderer::{PixelRenderer, Rect};
-use anyhow::Result;
+use super::clipboard::PastePreview;
+use crate::engine::renderer::overlay::{self, GridDensity};
+use crate::engine::renderer::{Filter, PaintBlend, PixelRenderer, Rect, ResizeAnchor, StrokePressure};
+use crate::engine::transform::Floating;
+use crate::engine::{cycling, BrushDynamics, Guide, PaletteCycle, Stabilizer};
+use crate::error::{AipixError, Result};
+use crate::AppState;
+use serde::Deserialize;
 use skia_safe::Color;
+use std::collections::HashMap;
 use std::sync::Mutex;
+use std::time::Instant;
 use tauri::State;
 
 /// Global renderer state
 pub struct RendererState {
     pub renderer: Mutex<Option<PixelRenderer>>,
+    /// Project whose guides are overlaid on rendered viewports, and its guides
+    pub guides: Mutex<Option<(String, Vec<Guide>)>>,
+    /// Project with a selection transform being previewed, and its floating pixels
+    pub floating: Mutex<Option<(String, Floating)>>,
+    /// Project with a paste being placed, and the pixels shown ghosted where they would land
+    pub paste_preview: Mutex<Option<(String, PastePreview)>>,
+    /// Project whose palette cycles are previewed, its cycles and when the preview started
+    pub cycling: Mutex<Option<(String, Vec<PaletteCycle>, Instant)>>,
+    /// User whose grid settings are used for the grid overlay, and their grid density
+    pub grid: Mutex<Option<(String, GridDensity)>>,
 }
 
 impl RendererState {
     pub fn new() -> Self {
         Self {
             renderer: Mutex::new(None),
+            guides: Mutex::new(None),
+            floating: Mutex::new(None),
+            paste_preview: Mutex::new(None),
+            cycling: Mutex::new(None),
+            grid: Mutex::new(None),
+        }
+    }
+
+    /// Refresh the overlay after `project_id`'s guides changed, if it is shown
+    pub fn update_guides(&self, project_id: &str, guides: &[Guide]) {
+        if let Some((shown, shown_guides)) = self.guides.lock().unwrap().as_mut() {
+            if shown == project_id {
+                *shown_guides = guides.to_vec();
+            }
+        }
+    }
+
+    /// Refresh the preview after `project_id`'s palette cycles changed, if it is shown
+    pub fn update_palette_cycles(&self, project_id: &str, cycles: &[PaletteCycle]) {
+        if let Some((shown, shown_cycles, _)) = self.cycling.lock().unwrap().as_mut() {
+            if shown == project_id {
+                *shown_cycles = cycles.to_vec();
+            }
+        }
+    }
+
+    /// Refresh the grid overlay after `user_id`'s grid density changed, if it is shown
+    pub fn update_grid_density(&self, user_id: &str, density: GridDensity) {
+        if let Some((shown, shown_density)) = self.grid.lock().unwrap().as_mut() {
+            if shown == user_id {
+                *shown_density = density;
+            }
+        }
+    }
+
+    /// How the previewed project's cycling colors are shown right now
+    ///
+    /// Locks the document, so call it before locking the renderer.
+    fn cycled_colors(&self, app_state: &AppState) -> Option<HashMap<[u8; 4], [u8; 4]>> {
+        let (project_id, cycles, started) = self.cycling.lock().unwrap().clone()?;
+        let document = app_state.document(&project_id).ok()?;
+        let palette = &document.lock().unwrap().history.palette;
+        Some(cycling::cycled_colors(palette, &cycles, started.elapsed().as_secs_f64()))
+    }
+
+    /// Stop placing a paste in `project_id`, returning the preview if there was one
+    pub fn clear_paste_preview(&self, project_id: &str) -> Option<PastePreview> {
+        let mut preview = self.paste_preview.lock().unwrap();
+        match preview.as_ref() {
+            Some((shown, _)) if shown == project_id => preview.take().map(|(_, preview)| preview),
+            _ => None,
+        }
+    }
+
+    /// Stop previewing `project_id`'s selection transform, if it is shown
+    pub fn clear_floating(&self, project_id: &str) {
+        let mut floating = self.floating.lock().unwrap();
+        if floating.as_ref().is_some_and(|(shown, _)| shown == project_id) {
+            *floating = None;
         }
     }
 }
@@ -25,11 +102,20 @@ impl RendererState {
 /// Parse hex color string to Skia Color
 fn parse_hex_color(hex: &str) -> Result<Color> {
     let hex = hex.trim_start_matches('#');
-    let r = u8::from_str_radix(&hex[0..2], 16)?;
-    let g = u8::from_str_radix(&hex[2..4], 16)?;
-    let b = u8::from_str_radix(&hex[4..6], 16)?;
+    if hex.len() != 6 && hex.len() != 8 {
+        return Err(AipixError::InvalidInput(format!("Invalid color: #{}", hex)));
+    }
+
+    let channel = |range: std::ops::Range<usize>| {
+        hex.get(range)
+            .and_then(|digits| u8::from_str_radix(digits, 16).ok())
+            .ok_or_else(|| AipixError::InvalidInput(format!("Invalid color: #{}", hex)))
+    };
+    let r = channel(0..2)?;
+    let g = channel(2..4)?;
+    let b = channel(4..6)?;
     let a = if hex.len() == 8 {
-        u8::from_str_radix(&hex[6..8], 16)?
+        channel(6..8)?
     } else {
         255
     };
@@ -41,18 +127,41 @@ fn parse_hex_color(hex: &str) -> Result<Color> {
 #[tauri::command]
 pub async fn init_renderer(
     state: State<'_, RendererState>,
+    app_state: State<'_, AppState>,
     width: i32,
     height: i32,
-) -> Result<(), String> {
-    let renderer = PixelRenderer::new(width, height)
-        .map_err(|e| format!("Failed to create renderer: {}", e))?;
+) -> Result<()> {
+    app_state
+        .canvas_limits
+        .lock()
+        .unwrap()
+        .validate_signed(width, height)?;
+    tracing::debug!(width, height, "initializing renderer");
+
+    let renderer = PixelRenderer::new(width, height)?;
 
     *state.renderer.lock().unwrap() = Some(renderer);
 
     Ok(())
 }
 
+/// The optional parts of a draw_stroke; every field may be left out
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct StrokeOptions {
+    pub pressures: Option<Vec<f32>>, // One per point, 0-1
+    pub dynamics: Option<BrushDynamics>,
+    pub stabilizer: Option<Stabilizer>,
+    pub blend: PaintBlend,
+}
+
 /// Draw a stroke (brush/pencil tool)
+///
+/// `color` is straight (not premultiplied) alpha, scaled by `opacity`, and
+/// the options' `blend` sets how the stroke combines with the canvas (normal
+/// by default). With `pressures` the brush size and opacity follow the
+/// stylus through `dynamics`, which defaults to pressure-scaled size. A
+/// `stabilizer` smooths the points before anything is drawn.
 #[tauri::command]
 pub async fn draw_stroke(
     state: State<'_, RendererState>,
@@ -60,82 +169,197 @@ pub async fn draw_stroke(
     brush_size: f32,
     color: String,
     opacity: f32,
-) -> Result<(), String> {
+    options: Option<StrokeOptions>,
+) -> Result<()> {
     let mut renderer_lock = state.renderer.lock().unwrap();
     let renderer = renderer_lock
         .as_mut()
-        .ok_or("Renderer not initialized")?;
-
-    let color = parse_hex_color(&color)
-        .map_err(|e| format!("Invalid color: {}", e))?;
+        .ok_or(AipixError::RendererNotInitialized)?;
+
+    let color = parse_hex_color(&color)?;
+    let StrokeOptions { pressures, dynamics, stabilizer, blend } = options.unwrap_or_default();
+    let points = match stabilizer {
+        Some(stabilizer) => {
+            stabilizer.validate()?;
+            stabilizer.apply(&points)
+        }
+        None => points,
+    };
 
-    renderer
-        .draw_stroke(&points, brush_size, color, opacity)
-        .map_err(|e| format!("Failed to draw stroke: {}", e))?;
+    match pressures {
+        Some(pressures) => {
+            let dynamics = dynamics.unwrap_or_default();
+            dynamics.validate()?;
+            let pressure = StrokePressure { pressures: &pressures, dynamics: &dynamics };
+            renderer.draw_pressure_stroke(&points, pressure, brush_size, color, opacity, blend)?;
+        }
+        None => renderer.draw_stroke(&points, brush_size, color, opacity, blend)?,
+    }
 
     Ok(())
 }
 
-/// Fill a rectangle
+/// Fill `rect` with a straight-alpha `color`, combined with the canvas by `blend`
 #[tauri::command]
 pub async fn fill_rect(
     state: State<'_, RendererState>,
-    x: i32,
-    y: i32,
-    width: i32,
-    height: i32,
+    rect: Rect,
     color: String,
     opacity: f32,
-) -> Result<(), String> {
+    blend: Option<PaintBlend>,
+) -> Result<()> {
     let mut renderer_lock = state.renderer.lock().unwrap();
     let renderer = renderer_lock
         .as_mut()
-        .ok_or("Renderer not initialized")?;
+        .ok_or(AipixError::RendererNotInitialized)?;
 
-    let rect = Rect::new(x, y, width, height);
-    let color = parse_hex_color(&color)
-        .map_err(|e| format!("Invalid color: {}", e))?;
+    let color = parse_hex_color(&color)?;
 
     renderer
-        .fill_rect(rect, color, opacity)
-        .map_err(|e| format!("Failed to fill rect: {}", e))?;
+        .fill_rect(rect, color, opacity, blend.unwrap_or_default())?;
 
     Ok(())
 }
 
+/// Apply a blur, drop shadow or color adjustment to the canvas (or only within `rect`)
+#[tauri::command]
+pub async fn apply_filter(
+    state: State<'_, RendererState>,
+    filter: Filter,
+    rect: Option<Rect>,
+) -> Result<()> {
+    let mut renderer_lock = state.renderer.lock().unwrap();
+    let renderer = renderer_lock
+        .as_mut()
+        .ok_or(AipixError::RendererNotInitialized)?;
+
+    renderer.apply_filter(&filter, rect)
+}
+
 /// Render viewport (with culling for performance)
 ///
 /// This is THE key optimization - only renders the visible region!
 #[tauri::command]
 pub async fn render_viewport(
     state: State<'_, RendererState>,
+    app_state: State<'_, AppState>,
     viewport_x: i32,
     viewport_y: i32,
     viewport_width: i32,
     viewport_height: i32,
     zoom: f32,
-) -> Result<Vec<u8>, String> {
+) -> Result<Vec<u8>> {
+    let cycled = state.cycled_colors(&app_state);
     let renderer_lock = state.renderer.lock().unwrap();
     let renderer = renderer_lock
         .as_ref()
-        .ok_or("Renderer not initialized")?;
+        .ok_or(AipixError::RendererNotInitialized)?;
+
+    let mut pixels = app_state.profiler.time("render_viewport", || {
+        renderer.render_viewport(viewport_x, viewport_y, viewport_width, viewport_height, zoom)
+    })?;
 
-    let pixels = renderer
-        .render_viewport(viewport_x, viewport_y, viewport_width, viewport_height, zoom)
-        .map_err(|e| format!("Failed to render viewport: {}", e))?;
+    let viewport = Rect::new(viewport_x, viewport_y, viewport_width, viewport_height);
+    let canvas_size = renderer.dimensions();
+    if let Some((_, floating)) = state.floating.lock().unwrap().as_ref() {
+        overlay::draw_floating(&mut pixels, viewport, canvas_size, floating);
+    }
+    if let Some((_, preview)) = state.paste_preview.lock().unwrap().as_ref() {
+        overlay::draw_ghost(&mut pixels, viewport, canvas_size, &preview.pixels, (preview.x, preview.y));
+    }
+    if let Some(cycled) = &cycled {
+        overlay::draw_palette_cycles(&mut pixels, viewport, canvas_size, cycled);
+    }
+    if let Some((_, density)) = state.grid.lock().unwrap().as_ref() {
+        overlay::draw_grid(&mut pixels, viewport, canvas_size, density.spacing());
+    }
+    if let Some((_, guides)) = state.guides.lock().unwrap().as_ref() {
+        overlay::draw_guides(&mut pixels, viewport, guides);
+    }
 
     Ok(pixels)
 }
 
+/// Overlay a project's guides on rendered viewports, or hide them with `None`
+#[tauri::command]
+pub async fn show_guides(
+    state: State<'_, RendererState>,
+    app_state: State<'_, AppState>,
+    project_id: Option<String>,
+) -> Result<()> {
+    let overlay = match project_id {
+        Some(project_id) => {
+            let db_guard = app_state.db.lock().unwrap();
+            let db = db_guard.as_ref().ok_or(AipixError::DatabaseNotInitialized)?;
+            let guides = db.get_project_metadata(&project_id)?.guides;
+            Some((project_id, guides))
+        }
+        None => None,
+    };
+
+    *state.guides.lock().unwrap() = overlay;
+    Ok(())
+}
+
+/// Overlay a grid spaced by a user's grid density setting, or hide it with `None`
+///
+/// Users who never saved settings get the medium grid.
+#[tauri::command]
+pub async fn show_grid(
+    state: State<'_, RendererState>,
+    app_state: State<'_, AppState>,
+    user_id: Option<String>,
+) -> Result<()> {
+    let overlay = match user_id {
+        Some(user_id) => {
+            let db_guard = app_state.db.lock().unwrap();
+            let db = db_guard.as_ref().ok_or(AipixError::DatabaseNotInitialized)?;
+            let density = match db.get_user_settings(&user_id)? {
+                Some(settings) => GridDensity::from_name(&settings.grid_density)?,
+                None => GridDensity::Medium,
+            };
+            Some((user_id, density))
+        }
+        None => None,
+    };
+
+    *state.grid.lock().unwrap() = overlay;
+    Ok(())
+}
+
+/// Animate a project's palette cycles on rendered viewports, or stop with `None`
+///
+/// Cycles run from when the preview starts; the frontend re-renders to
+/// advance them. The canvas pixels are never changed.
+#[tauri::command]
+pub async fn show_palette_cycling(
+    state: State<'_, RendererState>,
+    app_state: State<'_, AppState>,
+    project_id: Option<String>,
+) -> Result<()> {
+    let preview = match project_id {
+        Some(project_id) => {
+            let db_guard = app_state.db.lock().unwrap();
+            let db = db_guard.as_ref().ok_or(AipixError::DatabaseNotInitialized)?;
+            let cycles = db.get_project_metadata(&project_id)?.palette_cycles;
+            Some((project_id, cycles, Instant::now()))
+        }
+        None => None,
+    };
+
+    *state.cycling.lock().unwrap() = preview;
+    Ok(())
+}
+
 /// Get full canvas image data
 #[tauri::command]
 pub async fn get_canvas_image(
     state: State<'_, RendererState>,
-) -> Result<Vec<u8>, String> {
+) -> Result<Vec<u8>> {
     let renderer_lock = state.renderer.lock().unwrap();
     let renderer = renderer_lock
         .as_ref()
-        .ok_or("Renderer not initialized")?;
+        .ok_or(AipixError::RendererNotInitialized)?;
 
     Ok(renderer.get_image_data())
 }
@@ -145,35 +369,48 @@ pub async fn get_canvas_image(
 pub async fn clear_canvas(
     state: State<'_, RendererState>,
     color: String,
-) -> Result<(), String> {
+) -> Result<()> {
     let mut renderer_lock = state.renderer.lock().unwrap();
     let renderer = renderer_lock
         .as_mut()
-        .ok_or("Renderer not initialized")?;
+        .ok_or(AipixError::RendererNotInitialized)?;
 
-    let color = parse_hex_color(&color)
-        .map_err(|e| format!("Invalid color: {}", e))?;
+    let color = parse_hex_color(&color)?;
 
     renderer.clear(color);
 
     Ok(())
 }
 
-/// Resize the canvas
+/// Resize the canvas, keeping its pixels at `anchor` (top-left by default)
+///
+/// Newly exposed areas are filled with `background`, white by default.
 #[tauri::command]
 pub async fn resize_canvas(
     state: State<'_, RendererState>,
+    app_state: State<'_, AppState>,
     width: i32,
     height: i32,
-) -> Result<(), String> {
+    anchor: Option<ResizeAnchor>,
+    background: Option<String>,
+) -> Result<()> {
+    app_state
+        .canvas_limits
+        .lock()
+        .unwrap()
+        .validate_signed(width, height)?;
+    let background = match background {
+        Some(color) => parse_hex_color(&color)?,
+        None => Color::WHITE,
+    };
+
     let mut renderer_lock = state.renderer.lock().unwrap();
     let renderer = renderer_lock
         .as_mut()
-        .ok_or("Renderer not initialized")?;
+        .ok_or(AipixError::RendererNotInitialized)?;
 
     renderer
-        .resize(width, height)
-        .map_err(|e| format!("Failed to resize: {}", e))?;
+        .resize(width, height, anchor.unwrap_or_default(), background)?;
 
     Ok(())
 }
@@ -182,11 +419,11 @@ pub async fn resize_canvas(
 #[tauri::command]
 pub async fn get_dirty_bounds(
     state: State<'_, RendererState>,
-) -> Result<Option<Rect>, String> {
+) -> Result<Option<Rect>> {
     let renderer_lock = state.renderer.lock().unwrap();
     let renderer = renderer_lock
         .as_ref()
-        .ok_or("Renderer not initialized")?;
+        .ok_or(AipixError::RendererNotInitialized)?;
 
     Ok(renderer.get_dirty_bounds())
 }
@@ -195,11 +432,11 @@ pub async fn get_dirty_bounds(
 #[tauri::command]
 pub async fn clear_dirty_region(
     state: State<'_, RendererState>,
-) -> Result<(), String> {
+) -> Result<()> {
     let mut renderer_lock = state.renderer.lock().unwrap();
     let renderer = renderer_lock
         .as_mut()
-        .ok_or("Renderer not initialized")?;
+        .ok_or(AipixError::RendererNotInitialized)?;
 
     renderer.clear_dirty_region();
 
@@ -0,0 +1,44 @@
+// User settings commands
+//
+// Settings are stored per user in user_settings. Saving them refreshes
+// whatever already uses them, such as the renderer's grid overlay.
+
+use super::RendererState;
+use crate::database::UserSettings;
+use crate::engine::renderer::overlay::GridDensity;
+use crate::engine::tools::hex_to_rgba;
+use crate::error::{AipixError, Result};
+use crate::AppState;
+use tauri::State;
+
+#[tauri::command]
+pub fn get_user_settings(state: State<AppState>, user_id: String) -> Result<Option<UserSettings>> {
+    let db_guard = state.db.lock().unwrap();
+    let db = db_guard.as_ref().ok_or(AipixError::DatabaseNotInitialized)?;
+
+    db.get_user_settings(&user_id)
+}
+
+/// Save a user's settings and apply them where they are in use
+///
+/// New-project defaults are checked here, so projects can always be created from them.
+#[tauri::command]
+pub fn update_user_settings(
+    state: State<AppState>,
+    renderer: State<RendererState>,
+    settings: UserSettings,
+) -> Result<()> {
+    let density = GridDensity::from_name(&settings.grid_density)?;
+    state.validate_canvas_size(settings.default_width, settings.default_height)?;
+    for color in std::iter::once(&settings.default_background_color).chain(&settings.default_palette) {
+        hex_to_rgba(color)?;
+    }
+    {
+        let db_guard = state.db.lock().unwrap();
+        let db = db_guard.as_ref().ok_or(AipixError::DatabaseNotInitialized)?;
+        db.save_user_settings(&settings)?;
+    }
+
+    renderer.update_grid_density(&settings.user_id, density);
+    Ok(())
+}
@@ -0,0 +1,175 @@
+// Layer and frame preview thumbnails
+//
+// Layer and timeline panels ask for their previews after each edit, so encoded
+// thumbnails are cached. A pixel change (see events::emit_changes) only marks
+// a project's thumbnails dirty; a dirty thumbnail whose frame pixels hash the
+// same as before is reused, so only the edited frames are encoded again.
+// The project list's animated previews are built from saved canvases instead,
+// so projects don't have to be opened for them.
+
+use crate::engine::{PixelBuffer, SheetLayout};
+use crate::fileio::compression;
+use crate::error::{AipixError, Result};
+use crate::{fileio, AppState};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use tauri::State;
+
+/// Largest thumbnail side that can be requested
+pub const MAX_THUMBNAIL_SIZE: u32 = 512;
+
+/// Longest side of the project list's animated previews
+const ANIMATION_PREVIEW_SIZE: u32 = 64;
+
+/// Frames sampled for an animated preview, spread over the whole animation
+const ANIMATION_PREVIEW_FRAMES: u32 = 8;
+
+const ANIMATION_PREVIEW_DELAY_MS: u32 = 150;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct ThumbnailKey {
+    project_id: String,
+    rect: (u32, u32, u32, u32), // Canvas region as (x, y, width, height)
+    max_size: u32,
+}
+
+#[derive(Debug, Clone)]
+struct CachedThumbnail {
+    hash: u64, // Of the region's pixels when encoded
+    dirty: bool,
+    png: Vec<u8>,
+}
+
+#[derive(Debug, Default)]
+pub struct ThumbnailCache {
+    entries: HashMap<ThumbnailKey, CachedThumbnail>,
+}
+
+impl ThumbnailCache {
+    /// Mark a project's thumbnails for checking against the canvas on next use
+    pub fn invalidate(&mut self, project_id: &str) {
+        for (key, entry) in self.entries.iter_mut() {
+            if key.project_id == project_id {
+                entry.dirty = true;
+            }
+        }
+    }
+
+    /// Drop a project's thumbnails, e.g. when it is closed
+    pub fn remove(&mut self, project_id: &str) {
+        self.entries.retain(|key, _| key.project_id != project_id);
+    }
+}
+
+/// A frame of an open document's sprite sheet as a PNG at most `max_size` pixels a side
+///
+/// Frames are read from the canvas as cells of `frame_width` x `frame_height`,
+/// as for sprite sheet export.
+#[tauri::command]
+pub fn get_frame_thumbnail(
+    state: State<AppState>,
+    project_id: String,
+    frame_index: u32,
+    frame_width: u32,
+    frame_height: u32,
+    max_size: u32,
+) -> Result<Vec<u8>> {
+    check_thumbnail_size(max_size)?;
+
+    let document = state.document(&project_id)?;
+    let document = document.lock().unwrap();
+    let buffer = &document.history.buffer;
+    let layout = SheetLayout::new(buffer.width, buffer.height, frame_width, frame_height)?;
+    let rect = layout.frame_rect(frame_index).ok_or(AipixError::NotFound("Frame"))?;
+    cached_thumbnail(&state, ThumbnailKey { project_id, rect, max_size }, buffer)
+}
+
+/// An open document's layer as a PNG at most `max_size` pixels a side
+///
+/// A document is a single layer, so this is the whole canvas.
+#[tauri::command]
+pub fn get_layer_thumbnail(state: State<AppState>, project_id: String, max_size: u32) -> Result<Vec<u8>> {
+    check_thumbnail_size(max_size)?;
+
+    let document = state.document(&project_id)?;
+    let document = document.lock().unwrap();
+    let buffer = &document.history.buffer;
+    let rect = (0, 0, buffer.width, buffer.height);
+    cached_thumbnail(&state, ThumbnailKey { project_id, rect, max_size }, buffer)
+}
+
+/// Refuse a thumbnail size outside 1..=MAX_THUMBNAIL_SIZE
+fn check_thumbnail_size(max_size: u32) -> Result<()> {
+    if !(1..=MAX_THUMBNAIL_SIZE).contains(&max_size) {
+        return Err(AipixError::InvalidInput(format!(
+            "Thumbnail size must be between 1 and {}",
+            MAX_THUMBNAIL_SIZE
+        )));
+    }
+    Ok(())
+}
+
+/// The thumbnail of `key`'s region of `buffer`, encoded again only if its pixels changed
+fn cached_thumbnail(state: &AppState, key: ThumbnailKey, buffer: &PixelBuffer) -> Result<Vec<u8>> {
+    let mut cache = state.thumbnails.lock().unwrap();
+    if let Some(entry) = cache.entries.get(&key).filter(|entry| !entry.dirty) {
+        return Ok(entry.png.clone());
+    }
+
+    let (x, y, width, height) = key.rect;
+    let region = buffer.copy_region(x, y, width, height);
+    let mut hasher = DefaultHasher::new();
+    region.to_rgba().hash(&mut hasher);
+    let hash = hasher.finish();
+
+    match cache.entries.get_mut(&key) {
+        Some(entry) if entry.hash == hash => entry.dirty = false,
+        _ => {
+            let png = fileio::encode_thumbnail(&region, key.max_size)?;
+            cache.entries.insert(key.clone(), CachedThumbnail { hash, dirty: false, png });
+        }
+    }
+    Ok(cache.entries[&key].png.clone())
+}
+
+/// A small looping GIF of a project's animation, made from its saved canvas
+///
+/// Frames are cells of `frame_width` x `frame_height`, or of the frame size
+/// of the project's linked export when omitted; without either the preview
+/// is a still of the whole canvas. Long animations are sampled down to a few
+/// evenly spaced frames. Unsaved edits aren't shown.
+#[tauri::command]
+pub fn get_project_animation_preview(
+    state: State<AppState>,
+    project_id: String,
+    frame_width: Option<u32>,
+    frame_height: Option<u32>,
+) -> Result<Vec<u8>> {
+    let (canvas, linked_frame_size) = {
+        let db_guard = state.db.lock().unwrap();
+        let db = db_guard.as_ref().ok_or(AipixError::DatabaseNotInitialized)?;
+        let (data, codec) = db.get_project_pixels(&project_id)?.ok_or(AipixError::NotFound("Saved canvas"))?;
+        let linked = match db.get_project_metadata(&project_id)?.linked_export {
+            Some(profile_id) => db.get_export_profile(&profile_id)?,
+            None => None,
+        };
+        let linked_frame_size =
+            linked.and_then(|profile| profile.settings.frame_width.zip(profile.settings.frame_height));
+        (compression::decode_pixels(&data, codec)?, linked_frame_size)
+    };
+
+    let frames: Vec<PixelBuffer> = match frame_width.zip(frame_height).or(linked_frame_size) {
+        Some((frame_width, frame_height)) => {
+            let layout = SheetLayout::new(canvas.width, canvas.height, frame_width, frame_height)?;
+            let count = layout.frame_count();
+            let shown = count.min(ANIMATION_PREVIEW_FRAMES);
+            (0..shown)
+                .filter_map(|i| layout.frame_rect(i * count / shown))
+                .map(|(x, y, w, h)| canvas.copy_region(x, y, w, h))
+                .collect()
+        }
+        None => vec![canvas],
+    };
+    Ok(fileio::encode_gif_thumbnail(&frames, ANIMATION_PREVIEW_SIZE, ANIMATION_PREVIEW_DELAY_MS)?)
+}
@@ -0,0 +1,419 @@
+// Tileset and tilemap commands
+//
+// Tilemaps live beside canvas documents and are edited the same way: cell
+// painting takes `save_history` so a drag is one undo step, while tile
+// edits always snapshot. Tile pixels cross IPC as raw RGBA, like
+// get_canvas_data. Tilemaps are not journaled for crash recovery.
+
+use super::events;
+use crate::engine::{PixelBuffer, Tilemap, TilemapDocument, Tileset};
+use crate::error::{AipixError, Result};
+use crate::fileio;
+use crate::{AppState, TilemapHandle};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tauri::{AppHandle, State};
+
+/// Tiles per row in exported tileset images
+const ATLAS_COLUMNS: u32 = 16;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TilemapInfo {
+    pub tilemap_id: String,
+    pub tile_width: u32,
+    pub tile_height: u32,
+    pub tile_count: usize,
+    pub map: Tilemap,
+}
+
+/// How create_tilemap_from_canvas cuts the canvas up
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct SliceOptions {
+    pub skip_empty: bool,  // Fully transparent tiles become empty cells
+    pub deduplicate: bool, // Identical tiles share one entry
+}
+
+/// A block of cells for stamp_tiles, `width` per row
+#[derive(Debug, Clone, Deserialize)]
+pub struct TileStamp {
+    pub width: u32,
+    pub tiles: Vec<Option<u32>>,
+}
+
+fn info(tilemap_id: &str, tilemap: &TilemapDocument) -> TilemapInfo {
+    TilemapInfo {
+        tilemap_id: tilemap_id.to_string(),
+        tile_width: tilemap.tileset.tile_width,
+        tile_height: tilemap.tileset.tile_height,
+        tile_count: tilemap.tileset.len(),
+        map: tilemap.map.clone(),
+    }
+}
+
+/// The rendered map must fit the canvas limits like any other image
+fn validate_map_size(state: &AppState, width: u32, height: u32, tileset: &Tileset) -> Result<()> {
+    let too_large = || AipixError::InvalidInput("Tilemap is too large".to_string());
+    let pixel_width = width.checked_mul(tileset.tile_width).ok_or_else(too_large)?;
+    let pixel_height = height.checked_mul(tileset.tile_height).ok_or_else(too_large)?;
+    state.validate_canvas_size(pixel_width, pixel_height)
+}
+
+fn insert(
+    app: &AppHandle,
+    state: &AppState,
+    tilemap_id: String,
+    tilemap: TilemapDocument,
+) -> Result<TilemapInfo> {
+    let handle: TilemapHandle = Arc::new(Mutex::new(tilemap));
+    let mut tilemaps = state.tilemaps.write().unwrap();
+    if tilemaps.contains_key(&tilemap_id) {
+        return Err(AipixError::Conflict(format!("Tilemap {} is already open", tilemap_id)));
+    }
+    tilemaps.insert(tilemap_id.clone(), handle.clone());
+    drop(tilemaps);
+
+    let tilemap = handle.lock().unwrap();
+    events::emit_tilemap_changed(app, &tilemap_id, &tilemap);
+    Ok(info(&tilemap_id, &tilemap))
+}
+
+fn tile_from_rgba(tileset: &Tileset, data: Vec<u8>) -> Result<PixelBuffer> {
+    PixelBuffer::from_rgba(tileset.tile_width, tileset.tile_height, &data).ok_or_else(|| {
+        AipixError::InvalidInput(format!(
+            "Tile data must be {}x{} RGBA ({} bytes)",
+            tileset.tile_width,
+            tileset.tile_height,
+            tileset.tile_width * tileset.tile_height * 4
+        ))
+    })
+}
+
+/// Create an empty tilemap of `width` x `height` cells
+#[tauri::command]
+pub fn create_tilemap(
+    app: AppHandle,
+    state: State<AppState>,
+    tilemap_id: String,
+    width: u32,
+    height: u32,
+    tile_width: u32,
+    tile_height: u32,
+) -> Result<TilemapInfo> {
+    let tileset = Tileset::new(tile_width, tile_height)?;
+    validate_map_size(&state, width, height, &tileset)?;
+    let tilemap = TilemapDocument::new(tileset, Tilemap::new(width, height));
+    insert(&app, &state, tilemap_id, tilemap)
+}
+
+/// Cut a canvas into tiles and create the tilemap that rebuilds it
+#[tauri::command]
+pub fn create_tilemap_from_canvas(
+    app: AppHandle,
+    state: State<AppState>,
+    tilemap_id: String,
+    project_id: String,
+    tile_width: u32,
+    tile_height: u32,
+    options: SliceOptions,
+) -> Result<TilemapInfo> {
+    let buffer = state.document(&project_id)?.lock().unwrap().history.buffer.clone();
+    let mut tileset = Tileset::new(tile_width, tile_height)?;
+    let map = tileset.slice(&buffer, options.skip_empty, options.deduplicate);
+    insert(&app, &state, tilemap_id, TilemapDocument::new(tileset, map))
+}
+
+#[tauri::command]
+pub fn get_tilemap(state: State<AppState>, tilemap_id: String) -> Result<TilemapInfo> {
+    let tilemap = state.tilemap(&tilemap_id)?;
+    let tilemap = tilemap.lock().unwrap();
+    Ok(info(&tilemap_id, &tilemap))
+}
+
+#[tauri::command]
+pub fn close_tilemap(state: State<AppState>, tilemap_id: String) -> Result<()> {
+    state
+        .tilemaps
+        .write()
+        .unwrap()
+        .remove(&tilemap_id)
+        .map(|_| ())
+        .ok_or(AipixError::NotFound("Tilemap"))
+}
+
+/// A tile's pixels as raw RGBA
+#[tauri::command]
+pub fn get_tile(state: State<AppState>, tilemap_id: String, index: u32) -> Result<Vec<u8>> {
+    let tilemap = state.tilemap(&tilemap_id)?;
+    let tilemap = tilemap.lock().unwrap();
+    let tile = tilemap.tileset.get(index).ok_or(AipixError::NotFound("Tile"))?;
+    Ok(tile.to_rgba())
+}
+
+/// Add a tile from raw RGBA (transparent if `data` is omitted); returns its index
+#[tauri::command]
+pub fn add_tile(
+    app: AppHandle,
+    state: State<AppState>,
+    tilemap_id: String,
+    data: Option<Vec<u8>>,
+) -> Result<u32> {
+    let tilemap = state.tilemap(&tilemap_id)?;
+    let mut tilemap = tilemap.lock().unwrap();
+
+    let tileset = &tilemap.tileset;
+    let tile = match data {
+        Some(data) => tile_from_rgba(tileset, data)?,
+        None => PixelBuffer::new(tileset.tile_width, tileset.tile_height),
+    };
+    tilemap.push_state();
+    let index = tilemap.tileset.add(tile)?;
+
+    events::emit_tilemap_changed(&app, &tilemap_id, &tilemap);
+    Ok(index)
+}
+
+/// Add the tile-sized area of a canvas with its top-left at (x, y); returns its index
+#[tauri::command]
+pub fn add_tile_from_canvas(
+    app: AppHandle,
+    state: State<AppState>,
+    tilemap_id: String,
+    project_id: String,
+    x: u32,
+    y: u32,
+) -> Result<u32> {
+    let tilemap = state.tilemap(&tilemap_id)?;
+    let mut tilemap = tilemap.lock().unwrap();
+
+    let (tile_width, tile_height) = (tilemap.tileset.tile_width, tilemap.tileset.tile_height);
+    let tile = {
+        let document = state.document(&project_id)?;
+        let document = document.lock().unwrap();
+        let buffer = &document.history.buffer;
+        let fits = |start: u32, size: u32, limit: u32| start.checked_add(size).is_some_and(|end| end <= limit);
+        if !fits(x, tile_width, buffer.width) || !fits(y, tile_height, buffer.height) {
+            return Err(AipixError::OutOfBounds);
+        }
+        buffer.copy_region(x, y, tile_width, tile_height)
+    };
+    tilemap.push_state();
+    let index = tilemap.tileset.add(tile)?;
+
+    events::emit_tilemap_changed(&app, &tilemap_id, &tilemap);
+    Ok(index)
+}
+
+#[tauri::command]
+pub fn update_tile(
+    app: AppHandle,
+    state: State<AppState>,
+    tilemap_id: String,
+    index: u32,
+    data: Vec<u8>,
+) -> Result<()> {
+    let tilemap = state.tilemap(&tilemap_id)?;
+    let mut tilemap = tilemap.lock().unwrap();
+
+    let tile = tile_from_rgba(&tilemap.tileset, data)?;
+    tilemap.check_tile(Some(index))?;
+    tilemap.push_state();
+    tilemap.tileset.replace(index, tile)?;
+
+    events::emit_tilemap_changed(&app, &tilemap_id, &tilemap);
+    Ok(())
+}
+
+/// Delete a tile; cells using it are cleared and later indices shift down by one
+#[tauri::command]
+pub fn delete_tile(
+    app: AppHandle,
+    state: State<AppState>,
+    tilemap_id: String,
+    index: u32,
+) -> Result<()> {
+    let tilemap = state.tilemap(&tilemap_id)?;
+    let mut tilemap = tilemap.lock().unwrap();
+
+    tilemap.check_tile(Some(index))?;
+    tilemap.push_state();
+    tilemap.remove_tile(index)?;
+
+    events::emit_tilemap_changed(&app, &tilemap_id, &tilemap);
+    Ok(())
+}
+
+/// Apply a cell edit, snapshotting first when it starts an undo step
+fn edit_cells(
+    app: &AppHandle,
+    state: &AppState,
+    tilemap_id: &str,
+    tiles: &[Option<u32>],
+    save_history: bool,
+    edit: impl FnOnce(&mut Tilemap) -> Result<()>,
+) -> Result<()> {
+    let tilemap = state.tilemap(tilemap_id)?;
+    let mut tilemap = tilemap.lock().unwrap();
+
+    for &tile in tiles {
+        tilemap.check_tile(tile)?;
+    }
+    // Edit a copy so an out-of-bounds cell doesn't leave an empty undo step behind
+    let mut map = tilemap.map.clone();
+    edit(&mut map)?;
+    if save_history {
+        tilemap.push_state();
+    }
+    tilemap.map = map;
+
+    events::emit_tilemap_changed(app, tilemap_id, &tilemap);
+    Ok(())
+}
+
+/// Set one cell (`tile: null` erases it)
+#[tauri::command]
+pub fn place_tile(
+    app: AppHandle,
+    state: State<AppState>,
+    tilemap_id: String,
+    x: u32,
+    y: u32,
+    tile: Option<u32>,
+    save_history: bool,
+) -> Result<()> {
+    edit_cells(&app, &state, &tilemap_id, &[tile], save_history, |map| map.set(x, y, tile))
+}
+
+/// Place a block of cells with its top-left at (x, y)
+#[tauri::command]
+pub fn stamp_tiles(
+    app: AppHandle,
+    state: State<AppState>,
+    tilemap_id: String,
+    x: i32,
+    y: i32,
+    stamp: TileStamp,
+    save_history: bool,
+) -> Result<()> {
+    let TileStamp { width, tiles } = stamp;
+    edit_cells(&app, &state, &tilemap_id, &tiles, save_history, |map| map.stamp(x, y, width, &tiles))
+}
+
+/// Flood fill connected cells holding the same tile as (x, y)
+#[tauri::command]
+pub fn fill_tiles(
+    app: AppHandle,
+    state: State<AppState>,
+    tilemap_id: String,
+    x: u32,
+    y: u32,
+    tile: Option<u32>,
+) -> Result<()> {
+    edit_cells(&app, &state, &tilemap_id, &[tile], true, |map| map.fill(x, y, tile))
+}
+
+#[tauri::command]
+pub fn undo_tilemap(app: AppHandle, state: State<AppState>, tilemap_id: String) -> Result<()> {
+    let tilemap = state.tilemap(&tilemap_id)?;
+    let mut tilemap = tilemap.lock().unwrap();
+    tilemap.undo()?;
+    events::emit_tilemap_changed(&app, &tilemap_id, &tilemap);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn redo_tilemap(app: AppHandle, state: State<AppState>, tilemap_id: String) -> Result<()> {
+    let tilemap = state.tilemap(&tilemap_id)?;
+    let mut tilemap = tilemap.lock().unwrap();
+    tilemap.redo()?;
+    events::emit_tilemap_changed(&app, &tilemap_id, &tilemap);
+    Ok(())
+}
+
+fn save_png(path: &Path, buffer: &PixelBuffer) -> Result<()> {
+    fileio::save_image(path, &fileio::buffer_to_image(buffer)?)?;
+    Ok(())
+}
+
+/// Render the map to a PNG
+#[tauri::command]
+pub fn export_tilemap_image(state: State<AppState>, tilemap_id: String, path: String) -> Result<()> {
+    let rendered = {
+        let tilemap = state.tilemap(&tilemap_id)?;
+        let tilemap = tilemap.lock().unwrap();
+        tilemap.map.render(&tilemap.tileset)
+    };
+    save_png(Path::new(&path), &rendered)
+}
+
+/// Export for the Tiled editor: a JSON map (.tmj) plus its tileset image beside it
+///
+/// Returns the path of the tileset image.
+#[tauri::command]
+pub fn export_tilemap_tiled(state: State<AppState>, tilemap_id: String, path: String) -> Result<String> {
+    let (tileset, map) = {
+        let tilemap = state.tilemap(&tilemap_id)?;
+        let tilemap = tilemap.lock().unwrap();
+        (tilemap.tileset.clone(), tilemap.map.clone())
+    };
+
+    let mut map_path = PathBuf::from(&path);
+    if map_path.extension().is_none() {
+        map_path.set_extension("tmj");
+    }
+    let stem = map_path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| tilemap_id.clone());
+    let image_name = format!("{}-tileset.png", stem);
+    let image_path = map_path.with_file_name(&image_name);
+
+    let columns = (tileset.len() as u32).clamp(1, ATLAS_COLUMNS);
+    let atlas = tileset.atlas(columns);
+    save_png(&image_path, &atlas)?;
+
+    // Tiled global ids start at 1; 0 is an empty cell
+    let data: Vec<u32> = map.cells.iter().map(|cell| cell.map_or(0, |i| i + 1)).collect();
+    let document = json!({
+        "type": "map",
+        "version": "1.10",
+        "orientation": "orthogonal",
+        "renderorder": "right-down",
+        "infinite": false,
+        "width": map.width,
+        "height": map.height,
+        "tilewidth": tileset.tile_width,
+        "tileheight": tileset.tile_height,
+        "nextlayerid": 2,
+        "nextobjectid": 1,
+        "layers": [{
+            "id": 1,
+            "name": "Tiles",
+            "type": "tilelayer",
+            "x": 0,
+            "y": 0,
+            "width": map.width,
+            "height": map.height,
+            "opacity": 1,
+            "visible": true,
+            "data": data,
+        }],
+        "tilesets": [{
+            "firstgid": 1,
+            "name": stem,
+            "image": image_name,
+            "imagewidth": atlas.width,
+            "imageheight": atlas.height,
+            "tilewidth": tileset.tile_width,
+            "tileheight": tileset.tile_height,
+            "tilecount": tileset.len(),
+            "columns": columns,
+            "margin": 0,
+            "spacing": 0,
+        }],
+    });
+    std::fs::write(&map_path, serde_json::to_vec_pretty(&document)?)?;
+
+    Ok(image_path.to_string_lossy().into_owned())
+}
@@ -0,0 +1,71 @@
+// Selection transform commands
+//
+// Dragging or resizing a selection calls preview_selection_transform on every
+// move: the transformed pixels are only overlaid on rendered viewports, so
+// the canvas, its history and the journal are untouched. Releasing calls
+// commit_selection_transform, which writes the final transform as a single
+// undo step; cancel_selection_transform drops the preview instead.
+
+use super::events::{self, Changes};
+use super::RendererState;
+use crate::engine::{self, transform, SelectionTransform};
+use crate::error::{AipixError, Result};
+use crate::journal::Operation;
+use crate::AppState;
+use tauri::{AppHandle, State};
+
+/// Show the selection of an open document moved and resized to `transform`, without changing it
+#[tauri::command]
+pub fn preview_selection_transform(
+    state: State<AppState>,
+    renderer: State<RendererState>,
+    project_id: String,
+    transform: SelectionTransform,
+) -> Result<()> {
+    let document = state.document(&project_id)?;
+    let document = document.lock().unwrap();
+    let selection = document.selection.as_ref().ok_or(AipixError::NotFound("Selection"))?;
+
+    let floating = transform::lift(&document.history.buffer, selection, &transform)?
+        .ok_or_else(|| AipixError::InvalidState("No selection to transform".to_string()))?;
+    *renderer.floating.lock().unwrap() = Some((project_id, floating));
+    Ok(())
+}
+
+/// Apply `transform` to the selection's pixels as one undo step, ending any preview
+///
+/// The selection moves with the pixels.
+#[tauri::command]
+pub fn commit_selection_transform(
+    app: AppHandle,
+    state: State<AppState>,
+    renderer: State<RendererState>,
+    project_id: String,
+    transform: SelectionTransform,
+) -> Result<engine::Selection> {
+    let document = state.document(&project_id)?;
+    let mut document = document.lock().unwrap();
+    let engine::Document { history, selection, .. } = &mut *document;
+    let selection = selection.as_mut().ok_or(AipixError::NotFound("Selection"))?;
+
+    let floating = transform::lift(&history.buffer, selection, &transform)?
+        .ok_or_else(|| AipixError::InvalidState("No selection to transform".to_string()))?;
+    renderer.clear_floating(&project_id);
+
+    history.push_state();
+    let changed = transform::commit(&mut history.buffer, selection, &floating);
+    let selection = selection.clone();
+
+    state.record(&project_id, &document, Operation::PushState);
+    state.record_with(&project_id, &document, || Operation::patch(&document.history.buffer, changed));
+
+    events::emit_changes(&app, &project_id, &document, Changes::ALL);
+    Ok(selection)
+}
+
+/// Stop previewing a selection transform, leaving the selection as it was
+#[tauri::command]
+pub fn cancel_selection_transform(renderer: State<RendererState>, project_id: String) -> Result<()> {
+    renderer.clear_floating(&project_id);
+    Ok(())
+}
@@ -1,6 +1,9 @@
 // Data models for the application
 use serde::{Deserialize, Serialize};
 use chrono::{DateTime, Utc};
+use crate::fileio::compression::PixelCodec;
+use crate::engine::{guides, FrameTag, Guide, Hitbox, NineSlice, Palette, PaletteCycle, Pivot, Slice};
+use std::collections::BTreeMap;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct User {
@@ -28,6 +31,56 @@ pub struct Project {
     pub updated_at: DateTime<Utc>,
     pub last_modified: DateTime<Utc>,
     pub synced_at: Option<DateTime<Utc>>,
+    /// Server-assigned revision, bumped on every accepted push
+    #[serde(default)]
+    pub revision: i64,
+    /// Hidden from the active project list, but kept and synced as usual
+    #[serde(default)]
+    pub archived: bool,
+}
+
+/// Non-pixel project data, stored as JSON in project_data.metadata
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProjectMetadata {
+    pub nine_slice: Option<NineSlice>, // Whole canvas as a nine-slice
+    pub slices: Vec<Slice>,
+    pub frames: BTreeMap<u32, FrameMetadata>, // By frame index; frames without data are absent
+    pub guides: Vec<Guide>,
+    pub guide_snap_threshold: u32, // 0 turns guide snapping off
+    pub linked_export: Option<String>, // Export profile re-run whenever the project is saved
+    pub palette: Palette, // Saved with the canvas
+    pub palette_cycles: Vec<PaletteCycle>,
+    pub tags: Vec<FrameTag>, // Named frame ranges, e.g. one per animation
+}
+
+impl Default for ProjectMetadata {
+    fn default() -> Self {
+        Self {
+            nine_slice: None,
+            slices: Vec::new(),
+            frames: BTreeMap::new(),
+            guides: Vec::new(),
+            guide_snap_threshold: guides::DEFAULT_SNAP_THRESHOLD,
+            linked_export: None,
+            palette: Palette::new(),
+            palette_cycles: Vec::new(),
+            tags: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FrameMetadata {
+    pub pivot: Option<Pivot>,
+    pub hitboxes: Vec<Hitbox>,
+}
+
+impl FrameMetadata {
+    pub fn is_empty(&self) -> bool {
+        self.pivot.is_none() && self.hitboxes.is_empty()
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -39,6 +92,9 @@ pub struct Folder {
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
     pub synced_at: Option<DateTime<Utc>>,
+    /// Server-assigned revision, bumped on every accepted push
+    #[serde(default)]
+    pub revision: i64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -68,6 +124,174 @@ pub struct UserSettings {
     pub grid_density: String,
     pub default_view: String,
     pub show_thumbnails: bool,
+    // New-project defaults, pre-filled in the new-project dialog
+    #[serde(default = "default_canvas_size")]
+    pub default_width: u32,
+    #[serde(default = "default_canvas_size")]
+    pub default_height: u32,
+    #[serde(default = "default_background_color")]
+    pub default_background_color: String,
+    #[serde(default = "default_color_mode")]
+    pub default_color_mode: String,
+    #[serde(default)]
+    pub default_palette: Vec<String>, // Hex colors
+    #[serde(default)]
+    pub pixel_codec: PixelCodec, // How the user's project canvases are saved from now on
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl UserSettings {
+    /// Settings for a user who never saved any, matching the user_settings column defaults
+    pub fn new(user_id: &str) -> Self {
+        let now = Utc::now();
+        Self {
+            user_id: user_id.to_string(),
+            grid_density: "medium".to_string(),
+            default_view: "grid".to_string(),
+            show_thumbnails: true,
+            default_width: default_canvas_size(),
+            default_height: default_canvas_size(),
+            default_background_color: default_background_color(),
+            default_color_mode: default_color_mode(),
+            default_palette: Vec::new(),
+            pixel_codec: PixelCodec::default(),
+            created_at: now,
+            updated_at: now,
+        }
+    }
+}
+
+fn default_canvas_size() -> u32 {
+    32
+}
+
+fn default_background_color() -> String {
+    "#00000000".to_string()
+}
+
+fn default_color_mode() -> String {
+    "rgba".to_string()
+}
+
+/// A comment pinned to a canvas position; replies point at their thread root via `parent_id`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Comment {
+    pub id: String,
+    pub project_id: String,
+    pub user_id: String,
+    pub parent_id: Option<String>,
+    pub body: String,
+    pub x: i32,
+    pub y: i32,
+    pub frame_index: Option<u32>,
+    pub layer_id: Option<String>,
+    pub resolved: bool,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub synced_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub revision: i64,
+}
+
+/// Last heartbeat seen from a user who has a shared project open
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PresenceEntry {
+    pub project_id: String,
+    pub user_id: String,
+    pub username: String,
+    pub last_seen: DateTime<Utc>,
+}
+
+/// Exclusive edit lock on a shared project, valid until `expires_at`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EditLock {
+    pub project_id: String,
+    pub user_id: String,
+    pub acquired_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// A recorded AI generation request, kept so earlier results can be reproduced
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PromptHistoryEntry {
+    pub id: String,
+    pub project_id: Option<String>,
+    pub kind: String,     // Which generation command produced it, e.g. "inpaint"
+    pub prompt: String,
+    pub provider: Option<String>,
+    pub parameters: String, // JSON of the remaining command arguments
+    pub thumbnail: Option<Vec<u8>>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Active editing time on a project during one local day
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DailyActivity {
+    pub day: String, // YYYY-MM-DD
+    pub seconds: u64,
+}
+
+/// What an export profile writes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportFormat {
+    Png,         // The canvas, or one file per frame when a frame size is set
+    SpriteSheet, // The canvas plus a JSON description of its frames
+}
+
+/// Export settings, stored as JSON in export_profiles.settings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportSettings {
+    pub format: ExportFormat,
+    #[serde(default = "default_export_scale")]
+    pub scale: u32, // Nearest-neighbor, e.g. 4 for store screenshots
+    #[serde(default)]
+    pub trim: bool, // Crop each image to its non-transparent pixels
+    #[serde(default)]
+    pub palette: bool, // Snap colors to the nearest project palette entry
+    #[serde(default)]
+    pub background: Option<String>, // Hex color transparency is flattened onto
+    pub frame_width: Option<u32>,
+    pub frame_height: Option<u32>,
+    pub destination: String, // Directory
+    pub filename_pattern: String, // With tokens like {name}_{frame}
+}
+
+fn default_export_scale() -> u32 {
+    1
+}
+
+/// A named set of export settings, for exports that are repeated often
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportProfile {
+    pub id: String,
+    pub name: String,
+    pub settings: ExportSettings,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncItem {
+    pub id: i64,
+    pub table_name: String,
+    pub record_id: String,
+    pub operation: String,
+    pub data: String,
+    pub created_at: DateTime<Utc>,
+    pub synced: bool,
+    pub skipped: bool,
+    pub attempts: u32,
+    pub last_error: Option<String>,
+    /// Revision of the record the local change was based on
+    pub base_revision: i64,
+}
+
+/// Outcome of applying a record pulled from the server
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum SyncResolution {
+    Applied,  // Remote revision was newer and has been stored locally
+    Stale,    // Local copy is already at or past the remote revision
+    Conflict, // Remote moved on while local changes are still pending
+}
@@ -1,6 +1,6 @@
 // SQLite database schema creation and migrations
 use rusqlite::Connection;
-use anyhow::Result;
+use crate::error::Result;
 
 pub fn initialize_database(conn: &Connection) -> Result<()> {
     // Enable SQLite optimizations FIRST (before creating tables)
@@ -33,6 +33,7 @@ pub fn initialize_database(conn: &Connection) -> Result<()> {
             created_at TEXT NOT NULL,
             updated_at TEXT NOT NULL,
             synced_at TEXT,
+            revision INTEGER NOT NULL DEFAULT 0,
             FOREIGN KEY (user_id) REFERENCES users(id)
         )",
         (),
@@ -55,6 +56,8 @@ pub fn initialize_database(conn: &Connection) -> Result<()> {
             updated_at TEXT NOT NULL,
             last_modified TEXT NOT NULL,
             synced_at TEXT,
+            revision INTEGER NOT NULL DEFAULT 0,
+            archived BOOLEAN NOT NULL DEFAULT 0,
             FOREIGN KEY (user_id) REFERENCES users(id),
             FOREIGN KEY (folder_id) REFERENCES folders(id)
         )",
@@ -66,6 +69,7 @@ pub fn initialize_database(conn: &Connection) -> Result<()> {
         "CREATE TABLE IF NOT EXISTS project_data (
             project_id TEXT PRIMARY KEY,
             pixel_data BLOB NOT NULL,
+            pixel_codec TEXT NOT NULL DEFAULT 'png',
             layers BLOB,
             metadata TEXT,
             FOREIGN KEY (project_id) REFERENCES projects(id)
@@ -73,6 +77,80 @@ pub fn initialize_database(conn: &Connection) -> Result<()> {
         (),
     )?;
 
+    // Create comments table (pinned review feedback on canvases)
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS comments (
+            id TEXT PRIMARY KEY,
+            project_id TEXT NOT NULL,
+            user_id TEXT NOT NULL,
+            parent_id TEXT,
+            body TEXT NOT NULL,
+            x INTEGER NOT NULL,
+            y INTEGER NOT NULL,
+            frame_index INTEGER,
+            layer_id TEXT,
+            resolved BOOLEAN NOT NULL DEFAULT 0,
+            created_at TEXT NOT NULL,
+            updated_at TEXT NOT NULL,
+            synced_at TEXT,
+            revision INTEGER NOT NULL DEFAULT 0,
+            FOREIGN KEY (project_id) REFERENCES projects(id),
+            FOREIGN KEY (user_id) REFERENCES users(id),
+            FOREIGN KEY (parent_id) REFERENCES comments(id)
+        )",
+        (),
+    )?;
+
+    // Create project_presence table (heartbeats of users with a shared project open)
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS project_presence (
+            project_id TEXT NOT NULL,
+            user_id TEXT NOT NULL,
+            username TEXT NOT NULL,
+            last_seen TEXT NOT NULL,
+            PRIMARY KEY (project_id, user_id)
+        )",
+        (),
+    )?;
+
+    // Create edit_locks table (optional exclusive editing of shared projects)
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS edit_locks (
+            project_id TEXT PRIMARY KEY,
+            user_id TEXT NOT NULL,
+            acquired_at TEXT NOT NULL,
+            expires_at TEXT NOT NULL
+        )",
+        (),
+    )?;
+
+    // Create prompt_history table (AI generation requests, local only)
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS prompt_history (
+            id TEXT PRIMARY KEY,
+            project_id TEXT,
+            kind TEXT NOT NULL,
+            prompt TEXT NOT NULL,
+            provider TEXT,
+            parameters TEXT NOT NULL,
+            thumbnail BLOB,
+            created_at TEXT NOT NULL
+        )",
+        (),
+    )?;
+
+    // Create export_profiles table (saved export settings, local only)
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS export_profiles (
+            id TEXT PRIMARY KEY,
+            name TEXT NOT NULL,
+            settings TEXT NOT NULL,
+            created_at TEXT NOT NULL,
+            updated_at TEXT NOT NULL
+        )",
+        (),
+    )?;
+
     // Create team_members table
     conn.execute(
         "CREATE TABLE IF NOT EXISTS team_members (
@@ -109,6 +187,12 @@ pub fn initialize_database(conn: &Connection) -> Result<()> {
             grid_density TEXT NOT NULL DEFAULT 'medium',
             default_view TEXT NOT NULL DEFAULT 'grid',
             show_thumbnails BOOLEAN NOT NULL DEFAULT 1,
+            default_width INTEGER NOT NULL DEFAULT 32,
+            default_height INTEGER NOT NULL DEFAULT 32,
+            default_background_color TEXT NOT NULL DEFAULT '#00000000',
+            default_color_mode TEXT NOT NULL DEFAULT 'rgba',
+            default_palette TEXT NOT NULL DEFAULT '[]',
+            pixel_codec TEXT NOT NULL DEFAULT 'png',
             created_at TEXT NOT NULL,
             updated_at TEXT NOT NULL,
             FOREIGN KEY (user_id) REFERENCES users(id)
@@ -116,6 +200,17 @@ pub fn initialize_database(conn: &Connection) -> Result<()> {
         (),
     )?;
 
+    // Create project_activity table (active editing time per local day, local only)
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS project_activity (
+            project_id TEXT NOT NULL,
+            day TEXT NOT NULL,
+            seconds INTEGER NOT NULL DEFAULT 0,
+            PRIMARY KEY (project_id, day)
+        )",
+        (),
+    )?;
+
     // Create sync_queue table (tracks items that need to be synced to Supabase)
     conn.execute(
         "CREATE TABLE IF NOT EXISTS sync_queue (
@@ -125,7 +220,11 @@ pub fn initialize_database(conn: &Connection) -> Result<()> {
             operation TEXT NOT NULL,
             data TEXT NOT NULL,
             created_at TEXT NOT NULL,
-            synced BOOLEAN NOT NULL DEFAULT 0
+            synced BOOLEAN NOT NULL DEFAULT 0,
+            skipped BOOLEAN NOT NULL DEFAULT 0,
+            attempts INTEGER NOT NULL DEFAULT 0,
+            last_error TEXT,
+            base_revision INTEGER NOT NULL DEFAULT 0
         )",
         (),
     )?;
@@ -151,6 +250,16 @@ pub fn initialize_database(conn: &Connection) -> Result<()> {
         (),
     )?;
 
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_comments_project_id ON comments(project_id)",
+        (),
+    )?;
+
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_prompt_history_created_at ON prompt_history(created_at DESC)",
+        (),
+    )?;
+
     // Additional performance indexes
     conn.execute(
         "CREATE INDEX IF NOT EXISTS idx_projects_last_modified ON projects(last_modified DESC)",
@@ -183,6 +292,8 @@ pub fn run_migrations(conn: &Connection) -> Result<()> {
     let has_color_mode = table_info.iter().any(|(_, name, _)| name == "color_mode");
     let has_background_color = table_info.iter().any(|(_, name, _)| name == "background_color");
     let has_pixel_aspect_ratio = table_info.iter().any(|(_, name, _)| name == "pixel_aspect_ratio");
+    let has_project_revision = table_info.iter().any(|(_, name, _)| name == "revision");
+    let has_archived = table_info.iter().any(|(_, name, _)| name == "archived");
 
     // Add missing columns if needed
     if !has_color_mode {
@@ -206,5 +317,104 @@ pub fn run_migrations(conn: &Connection) -> Result<()> {
         )?;
     }
 
+    if !has_project_revision {
+        conn.execute(
+            "ALTER TABLE projects ADD COLUMN revision INTEGER NOT NULL DEFAULT 0",
+            (),
+        )?;
+    }
+
+    if !has_archived {
+        conn.execute(
+            "ALTER TABLE projects ADD COLUMN archived BOOLEAN NOT NULL DEFAULT 0",
+            (),
+        )?;
+    }
+
+    // Check if folders table needs the revision column
+    let folder_info: Vec<(i32, String, String)> = conn
+        .prepare("PRAGMA table_info(folders)")?
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+
+    if !folder_info.iter().any(|(_, name, _)| name == "revision") {
+        conn.execute(
+            "ALTER TABLE folders ADD COLUMN revision INTEGER NOT NULL DEFAULT 0",
+            (),
+        )?;
+    }
+
+    // Check if project_data records how its pixels are encoded
+    let project_data_info: Vec<(i32, String, String)> = conn
+        .prepare("PRAGMA table_info(project_data)")?
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+
+    if !project_data_info.iter().any(|(_, name, _)| name == "pixel_codec") {
+        conn.execute(
+            "ALTER TABLE project_data ADD COLUMN pixel_codec TEXT NOT NULL DEFAULT 'png'",
+            (),
+        )?;
+    }
+
+    // Check if user_settings needs the new-project defaults and the canvas codec
+    let user_settings_info: Vec<(i32, String, String)> = conn
+        .prepare("PRAGMA table_info(user_settings)")?
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+
+    let new_project_defaults = [
+        ("default_width", "INTEGER NOT NULL DEFAULT 32"),
+        ("default_height", "INTEGER NOT NULL DEFAULT 32"),
+        ("default_background_color", "TEXT NOT NULL DEFAULT '#00000000'"),
+        ("default_color_mode", "TEXT NOT NULL DEFAULT 'rgba'"),
+        ("default_palette", "TEXT NOT NULL DEFAULT '[]'"),
+        ("pixel_codec", "TEXT NOT NULL DEFAULT 'png'"),
+    ];
+    for (column, definition) in new_project_defaults {
+        if !user_settings_info.iter().any(|(_, name, _)| name == column) {
+            conn.execute(&format!("ALTER TABLE user_settings ADD COLUMN {} {}", column, definition), ())?;
+        }
+    }
+
+    // Check if sync_queue needs the repair/inspection columns
+    let sync_queue_info: Vec<(i32, String, String)> = conn
+        .prepare("PRAGMA table_info(sync_queue)")?
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+
+    let has_skipped = sync_queue_info.iter().any(|(_, name, _)| name == "skipped");
+    let has_attempts = sync_queue_info.iter().any(|(_, name, _)| name == "attempts");
+    let has_last_error = sync_queue_info.iter().any(|(_, name, _)| name == "last_error");
+    let has_base_revision = sync_queue_info.iter().any(|(_, name, _)| name == "base_revision");
+
+    if !has_skipped {
+        conn.execute(
+            "ALTER TABLE sync_queue ADD COLUMN skipped BOOLEAN NOT NULL DEFAULT 0",
+            (),
+        )?;
+    }
+
+    if !has_attempts {
+        conn.execute(
+            "ALTER TABLE sync_queue ADD COLUMN attempts INTEGER NOT NULL DEFAULT 0",
+            (),
+        )?;
+    }
+
+    if !has_last_error {
+        conn.execute(
+            "ALTER TABLE sync_queue ADD COLUMN last_error TEXT",
+            (),
+        )?;
+    }
+
+    if !has_base_revision {
+        conn.execute(
+            "ALTER TABLE sync_queue ADD COLUMN base_revision INTEGER NOT NULL DEFAULT 0",
+            (),
+        )?;
+    }
+
     Ok(())
 }
@@ -1,13 +1,23 @@
 // SQLite database connection and operations
 use rusqlite::{Connection, params, OptionalExtension};
-use anyhow::{Result, Context};
+use crate::error::{AipixError, Result};
+use crate::fileio::compression::PixelCodec;
 use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
-use chrono::Utc;
+use chrono::{DateTime, Duration, Utc};
 
 use super::models::*;
 use super::schema::initialize_database;
 
+/// Users without a heartbeat for this long are no longer considered present
+pub const PRESENCE_TIMEOUT_SECS: i64 = 60;
+
+/// Edit locks expire on their own so a crashed client can't hold a project forever
+pub const DEFAULT_EDIT_LOCK_SECS: i64 = 300;
+
+/// Longest edit lock a client may ask for; longer requests are shortened to it
+pub const MAX_EDIT_LOCK_SECS: i64 = 24 * 60 * 60;
+
 pub struct Database {
     conn: Arc<Mutex<Connection>>,
 }
@@ -20,14 +30,14 @@ impl Database {
             std::fs::create_dir_all(parent)?;
         }
 
-        let conn = Connection::open(&db_path)
-            .context("Failed to open SQLite database")?;
+        let conn = Connection::open(&db_path)?;
 
         // Enable foreign keys
         conn.execute("PRAGMA foreign_keys = ON", ())?;
 
         // Initialize schema
         initialize_database(&conn)?;
+        tracing::info!(path = %db_path.display(), "database opened");
 
         Ok(Self {
             conn: Arc::new(Mutex::new(conn)),
@@ -88,6 +98,19 @@ impl Database {
         Ok(())
     }
 
+    /// Every profile picture in use, for cleaning up unreferenced avatar files
+    pub fn get_profile_pictures(&self) -> Result<Vec<String>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT profile_picture FROM users WHERE profile_picture IS NOT NULL"
+        )?;
+
+        let pictures = stmt.query_map([], |row| row.get(0))?
+            .collect::<std::result::Result<Vec<String>, _>>()?;
+
+        Ok(pictures)
+    }
+
     // ===== Project Operations =====
 
     pub fn create_project(&self, project: &Project) -> Result<()> {
@@ -95,8 +118,8 @@ impl Database {
 
         // Insert project
         conn.execute(
-            "INSERT INTO projects (id, user_id, folder_id, name, width, height, color_mode, background_color, pixel_aspect_ratio, thumbnail, created_at, updated_at, last_modified, synced_at)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+            "INSERT INTO projects (id, user_id, folder_id, name, width, height, color_mode, background_color, pixel_aspect_ratio, thumbnail, created_at, updated_at, last_modified, synced_at, revision, archived)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16)",
             params![
                 project.id,
                 project.user_id,
@@ -112,61 +135,63 @@ impl Database {
                 project.updated_at.to_rfc3339(),
                 project.last_modified.to_rfc3339(),
                 project.synced_at.as_ref().map(|t| t.to_rfc3339()),
+                project.revision,
+                project.archived,
             ],
         )?;
 
+        // The queued change is based on what's stored, not on the caller's copy
+        let revision = record_revision(&conn, "projects", &project.id)?.unwrap_or(0);
+
         // Add to sync queue - reuse same connection to avoid deadlock
         conn.execute(
-            "INSERT INTO sync_queue (table_name, record_id, operation, data, created_at, synced)
-             VALUES (?1, ?2, ?3, ?4, ?5, 0)",
+            "INSERT INTO sync_queue (table_name, record_id, operation, data, created_at, synced, base_revision)
+             VALUES (?1, ?2, ?3, ?4, ?5, 0, ?6)",
             params![
                 "projects",
                 &project.id,
                 "INSERT",
                 &serde_json::to_string(project)?,
                 Utc::now().to_rfc3339(),
+                revision,
             ],
         )?;
 
         Ok(())
     }
 
-    pub fn get_projects_by_user(&self, user_id: &str) -> Result<Vec<Project>> {
+    /// A user's projects, most recently modified first; only archived or
+    /// only active ones when `archived` is given
+    pub fn get_projects_by_user(&self, user_id: &str, archived: Option<bool>) -> Result<Vec<Project>> {
         let conn = self.conn.lock().unwrap();
         let mut stmt = conn.prepare(
-            "SELECT id, user_id, folder_id, name, width, height, color_mode, background_color, pixel_aspect_ratio, thumbnail, created_at, updated_at, last_modified, synced_at
-             FROM projects WHERE user_id = ?1 ORDER BY last_modified DESC"
+            "SELECT id, user_id, folder_id, name, width, height, color_mode, background_color, pixel_aspect_ratio, thumbnail, created_at, updated_at, last_modified, synced_at, revision, archived
+             FROM projects WHERE user_id = ?1 AND (?2 IS NULL OR archived = ?2) ORDER BY last_modified DESC"
         )?;
 
-        let projects = stmt.query_map(params![user_id], |row| {
-            Ok(Project {
-                id: row.get(0)?,
-                user_id: row.get(1)?,
-                folder_id: row.get(2)?,
-                name: row.get(3)?,
-                width: row.get(4)?,
-                height: row.get(5)?,
-                color_mode: row.get(6)?,
-                background_color: row.get(7)?,
-                pixel_aspect_ratio: row.get(8)?,
-                thumbnail: row.get(9)?,
-                created_at: row.get::<_, String>(10)?.parse().unwrap(),
-                updated_at: row.get::<_, String>(11)?.parse().unwrap(),
-                last_modified: row.get::<_, String>(12)?.parse().unwrap(),
-                synced_at: row.get::<_, Option<String>>(13)?
-                    .and_then(|s| s.parse().ok()),
-            })
-        })?
-        .collect::<Result<Vec<_>, _>>()?;
+        let projects = stmt.query_map(params![user_id, archived], row_to_project)?
+            .collect::<Result<Vec<_>, _>>()?;
 
         Ok(projects)
     }
 
+    pub fn get_project(&self, project_id: &str) -> Result<Option<Project>> {
+        let conn = self.conn.lock().unwrap();
+        let project = conn.query_row(
+            "SELECT id, user_id, folder_id, name, width, height, color_mode, background_color, pixel_aspect_ratio, thumbnail, created_at, updated_at, last_modified, synced_at, revision, archived
+             FROM projects WHERE id = ?1",
+            params![project_id],
+            row_to_project,
+        ).optional()?;
+
+        Ok(project)
+    }
+
     pub fn update_project(&self, project: &Project) -> Result<()> {
         let conn = self.conn.lock().unwrap();
         conn.execute(
-            "UPDATE projects SET name = ?1, width = ?2, height = ?3, color_mode = ?4, background_color = ?5, pixel_aspect_ratio = ?6, thumbnail = ?7, updated_at = ?8, last_modified = ?9, folder_id = ?10
-             WHERE id = ?11",
+            "UPDATE projects SET name = ?1, width = ?2, height = ?3, color_mode = ?4, background_color = ?5, pixel_aspect_ratio = ?6, thumbnail = ?7, updated_at = ?8, last_modified = ?9, folder_id = ?10, archived = ?11
+             WHERE id = ?12",
             params![
                 project.name,
                 project.width,
@@ -178,20 +203,25 @@ impl Database {
                 project.updated_at.to_rfc3339(),
                 project.last_modified.to_rfc3339(),
                 project.folder_id,
+                project.archived,
                 project.id,
             ],
         )?;
 
+        // The frontend doesn't send revisions, so the stored one is the base
+        let revision = record_revision(&conn, "projects", &project.id)?.unwrap_or(0);
+
         // Add to sync queue - reuse same connection to avoid deadlock
         conn.execute(
-            "INSERT INTO sync_queue (table_name, record_id, operation, data, created_at, synced)
-             VALUES (?1, ?2, ?3, ?4, ?5, 0)",
+            "INSERT INTO sync_queue (table_name, record_id, operation, data, created_at, synced, base_revision)
+             VALUES (?1, ?2, ?3, ?4, ?5, 0, ?6)",
             params![
                 "projects",
                 &project.id,
                 "UPDATE",
                 &serde_json::to_string(project)?,
                 Utc::now().to_rfc3339(),
+                revision,
             ],
         )?;
 
@@ -201,22 +231,33 @@ impl Database {
     pub fn delete_project(&self, project_id: &str) -> Result<()> {
         let conn = self.conn.lock().unwrap();
 
+        let revision = record_revision(&conn, "projects", project_id)?.unwrap_or(0);
+
+        let comments = conn
+            .prepare("SELECT id, revision FROM comments WHERE project_id = ?1")?
+            .query_map(params![project_id], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+
         // Delete project data first
         conn.execute("DELETE FROM project_data WHERE project_id = ?1", params![project_id])?;
+        conn.execute("DELETE FROM comments WHERE project_id = ?1", params![project_id])?;
+        queue_comment_deletes(&conn, &comments)?;
+        conn.execute("DELETE FROM project_activity WHERE project_id = ?1", params![project_id])?;
 
         // Delete project
         conn.execute("DELETE FROM projects WHERE id = ?1", params![project_id])?;
 
         // Add to sync queue - reuse same connection to avoid deadlock
         conn.execute(
-            "INSERT INTO sync_queue (table_name, record_id, operation, data, created_at, synced)
-             VALUES (?1, ?2, ?3, ?4, ?5, 0)",
+            "INSERT INTO sync_queue (table_name, record_id, operation, data, created_at, synced, base_revision)
+             VALUES (?1, ?2, ?3, ?4, ?5, 0, ?6)",
             params![
                 "projects",
                 project_id,
                 "DELETE",
                 "{}",
                 Utc::now().to_rfc3339(),
+                revision,
             ],
         )?;
 
@@ -228,8 +269,8 @@ impl Database {
     pub fn create_folder(&self, folder: &Folder) -> Result<()> {
         let conn = self.conn.lock().unwrap();
         conn.execute(
-            "INSERT INTO folders (id, user_id, name, color, created_at, updated_at, synced_at)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            "INSERT INTO folders (id, user_id, name, color, created_at, updated_at, synced_at, revision)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
             params![
                 folder.id,
                 folder.user_id,
@@ -238,19 +279,23 @@ impl Database {
                 folder.created_at.to_rfc3339(),
                 folder.updated_at.to_rfc3339(),
                 folder.synced_at.as_ref().map(|t| t.to_rfc3339()),
+                folder.revision,
             ],
         )?;
 
+        let revision = record_revision(&conn, "folders", &folder.id)?.unwrap_or(0);
+
         // Add to sync queue - reuse same connection to avoid deadlock
         conn.execute(
-            "INSERT INTO sync_queue (table_name, record_id, operation, data, created_at, synced)
-             VALUES (?1, ?2, ?3, ?4, ?5, 0)",
+            "INSERT INTO sync_queue (table_name, record_id, operation, data, created_at, synced, base_revision)
+             VALUES (?1, ?2, ?3, ?4, ?5, 0, ?6)",
             params![
                 "folders",
                 &folder.id,
                 "INSERT",
                 &serde_json::to_string(folder)?,
                 Utc::now().to_rfc3339(),
+                revision,
             ],
         )?;
 
@@ -260,7 +305,7 @@ impl Database {
     pub fn get_folders_by_user(&self, user_id: &str) -> Result<Vec<Folder>> {
         let conn = self.conn.lock().unwrap();
         let mut stmt = conn.prepare(
-            "SELECT id, user_id, name, color, created_at, updated_at, synced_at
+            "SELECT id, user_id, name, color, created_at, updated_at, synced_at, revision
              FROM folders WHERE user_id = ?1 ORDER BY name"
         )?;
 
@@ -274,6 +319,7 @@ impl Database {
                 updated_at: row.get::<_, String>(5)?.parse().unwrap(),
                 synced_at: row.get::<_, Option<String>>(6)?
                     .and_then(|s| s.parse().ok()),
+                revision: row.get(7)?,
             })
         })?
         .collect::<Result<Vec<_>, _>>()?;
@@ -293,16 +339,19 @@ impl Database {
             ],
         )?;
 
+        let revision = record_revision(&conn, "folders", &folder.id)?.unwrap_or(0);
+
         // Add to sync queue - reuse same connection to avoid deadlock
         conn.execute(
-            "INSERT INTO sync_queue (table_name, record_id, operation, data, created_at, synced)
-             VALUES (?1, ?2, ?3, ?4, ?5, 0)",
+            "INSERT INTO sync_queue (table_name, record_id, operation, data, created_at, synced, base_revision)
+             VALUES (?1, ?2, ?3, ?4, ?5, 0, ?6)",
             params![
                 "folders",
                 &folder.id,
                 "UPDATE",
                 &serde_json::to_string(folder)?,
                 Utc::now().to_rfc3339(),
+                revision,
             ],
         )?;
 
@@ -312,6 +361,8 @@ impl Database {
     pub fn delete_folder(&self, folder_id: &str) -> Result<()> {
         let conn = self.conn.lock().unwrap();
 
+        let revision = record_revision(&conn, "folders", folder_id)?.unwrap_or(0);
+
         // Remove folder reference from projects
         conn.execute("UPDATE projects SET folder_id = NULL WHERE folder_id = ?1", params![folder_id])?;
 
@@ -320,17 +371,589 @@ impl Database {
 
         // Add to sync queue - reuse same connection to avoid deadlock
         conn.execute(
-            "INSERT INTO sync_queue (table_name, record_id, operation, data, created_at, synced)
-             VALUES (?1, ?2, ?3, ?4, ?5, 0)",
+            "INSERT INTO sync_queue (table_name, record_id, operation, data, created_at, synced, base_revision)
+             VALUES (?1, ?2, ?3, ?4, ?5, 0, ?6)",
             params![
                 "folders",
                 folder_id,
                 "DELETE",
                 "{}",
                 Utc::now().to_rfc3339(),
+                revision,
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    // ===== Comment Operations =====
+
+    pub fn create_comment(&self, comment: &Comment) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO comments (id, project_id, user_id, parent_id, body, x, y, frame_index, layer_id, resolved, created_at, updated_at, synced_at, revision)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+            params![
+                comment.id,
+                comment.project_id,
+                comment.user_id,
+                comment.parent_id,
+                comment.body,
+                comment.x,
+                comment.y,
+                comment.frame_index,
+                comment.layer_id,
+                comment.resolved,
+                comment.created_at.to_rfc3339(),
+                comment.updated_at.to_rfc3339(),
+                comment.synced_at.as_ref().map(|t| t.to_rfc3339()),
+                comment.revision,
+            ],
+        )?;
+
+        // The queued change is based on what's stored, not on the caller's copy
+        let revision = record_revision(&conn, "comments", &comment.id)?.unwrap_or(0);
+
+        // Add to sync queue - reuse same connection to avoid deadlock
+        conn.execute(
+            "INSERT INTO sync_queue (table_name, record_id, operation, data, created_at, synced, base_revision)
+             VALUES (?1, ?2, ?3, ?4, ?5, 0, ?6)",
+            params![
+                "comments",
+                &comment.id,
+                "INSERT",
+                &serde_json::to_string(comment)?,
+                Utc::now().to_rfc3339(),
+                revision,
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    pub fn get_project_comments(&self, project_id: &str) -> Result<Vec<Comment>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT id, project_id, user_id, parent_id, body, x, y, frame_index, layer_id, resolved, created_at, updated_at, synced_at, revision
+             FROM comments WHERE project_id = ?1 ORDER BY created_at"
+        )?;
+
+        let comments = stmt.query_map(params![project_id], |row| {
+            Ok(Comment {
+                id: row.get(0)?,
+                project_id: row.get(1)?,
+                user_id: row.get(2)?,
+                parent_id: row.get(3)?,
+                body: row.get(4)?,
+                x: row.get(5)?,
+                y: row.get(6)?,
+                frame_index: row.get(7)?,
+                layer_id: row.get(8)?,
+                resolved: row.get(9)?,
+                created_at: row.get::<_, String>(10)?.parse().unwrap(),
+                updated_at: row.get::<_, String>(11)?.parse().unwrap(),
+                synced_at: row.get::<_, Option<String>>(12)?
+                    .and_then(|s| s.parse().ok()),
+                revision: row.get(13)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(comments)
+    }
+
+    pub fn update_comment(&self, comment: &Comment) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE comments SET body = ?1, x = ?2, y = ?3, frame_index = ?4, layer_id = ?5, resolved = ?6, updated_at = ?7
+             WHERE id = ?8",
+            params![
+                comment.body,
+                comment.x,
+                comment.y,
+                comment.frame_index,
+                comment.layer_id,
+                comment.resolved,
+                comment.updated_at.to_rfc3339(),
+                comment.id,
+            ],
+        )?;
+
+        // The frontend doesn't send revisions, so the stored one is the base
+        let revision = record_revision(&conn, "comments", &comment.id)?.unwrap_or(0);
+
+        // Add to sync queue - reuse same connection to avoid deadlock
+        conn.execute(
+            "INSERT INTO sync_queue (table_name, record_id, operation, data, created_at, synced, base_revision)
+             VALUES (?1, ?2, ?3, ?4, ?5, 0, ?6)",
+            params![
+                "comments",
+                &comment.id,
+                "UPDATE",
+                &serde_json::to_string(comment)?,
+                Utc::now().to_rfc3339(),
+                revision,
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    /// Delete a comment with every reply under it, queueing a sync delete for each
+    pub fn delete_comment(&self, comment_id: &str) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        let subtree = "WITH RECURSIVE subtree(id) AS (
+                SELECT ?1 UNION SELECT comments.id FROM comments JOIN subtree ON comments.parent_id = subtree.id
+            )";
+        let comments = conn
+            .prepare(&format!("{} SELECT id, revision FROM comments WHERE id IN subtree", subtree))?
+            .query_map(params![comment_id], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+
+        // One statement, so replies and what they reply to go together
+        conn.execute(&format!("{} DELETE FROM comments WHERE id IN subtree", subtree), params![comment_id])?;
+        queue_comment_deletes(&conn, &comments)?;
+
+        Ok(())
+    }
+
+    // ===== Presence & Edit Lock Operations =====
+
+    /// Record a heartbeat (local or pulled from Supabase) for a user viewing a project
+    ///
+    /// An older heartbeat than the one stored is ignored. Timestamps are
+    /// compared as times, since pulled ones may carry another offset.
+    pub fn record_presence(&self, entry: &PresenceEntry) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        let stored: Option<String> = conn.query_row(
+            "SELECT last_seen FROM project_presence WHERE project_id = ?1 AND user_id = ?2",
+            params![entry.project_id, entry.user_id],
+            |row| row.get(0),
+        ).optional()?;
+        let stored = stored.and_then(|last_seen| last_seen.parse::<DateTime<Utc>>().ok());
+        let last_seen = stored.map_or(entry.last_seen, |stored| stored.max(entry.last_seen));
+
+        conn.execute(
+            "INSERT INTO project_presence (project_id, user_id, username, last_seen)
+             VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT(project_id, user_id) DO UPDATE SET
+                username = excluded.username,
+                last_seen = excluded.last_seen",
+            params![
+                entry.project_id,
+                entry.user_id,
+                entry.username,
+                last_seen.to_rfc3339(),
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Users who sent a heartbeat for the project within the presence timeout
+    ///
+    /// Expired heartbeats, of any project, are dropped on the way.
+    pub fn get_project_presence(&self, project_id: &str) -> Result<Vec<PresenceEntry>> {
+        let conn = self.conn.lock().unwrap();
+
+        let mut stmt = conn.prepare(
+            "SELECT project_id, user_id, username, last_seen
+             FROM project_presence ORDER BY username"
+        )?;
+
+        let entries = stmt.query_map([], |row| {
+            Ok(PresenceEntry {
+                project_id: row.get(0)?,
+                user_id: row.get(1)?,
+                username: row.get(2)?,
+                last_seen: row.get::<_, String>(3)?.parse().unwrap(),
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+        let cutoff = Utc::now() - Duration::seconds(PRESENCE_TIMEOUT_SECS);
+        let (present, expired): (Vec<_>, Vec<_>) = entries.into_iter().partition(|entry| entry.last_seen >= cutoff);
+        for entry in expired {
+            conn.execute(
+                "DELETE FROM project_presence WHERE project_id = ?1 AND user_id = ?2",
+                params![entry.project_id, entry.user_id],
+            )?;
+        }
+
+        Ok(present.into_iter().filter(|entry| entry.project_id == project_id).collect())
+    }
+
+    pub fn get_edit_lock(&self, project_id: &str) -> Result<Option<EditLock>> {
+        let conn = self.conn.lock().unwrap();
+        active_edit_lock(&conn, project_id, Utc::now())
+    }
+
+    /// Take (or renew) the exclusive edit lock; fails if another user holds an unexpired lock
+    ///
+    /// The lock lasts `ttl_secs`, kept between 1 and MAX_EDIT_LOCK_SECS.
+    pub fn acquire_edit_lock(&self, project_id: &str, user_id: &str, ttl_secs: i64) -> Result<EditLock> {
+        let conn = self.conn.lock().unwrap();
+        let now = Utc::now();
+
+        if let Some(existing) = active_edit_lock(&conn, project_id, now)? {
+            if existing.user_id != user_id {
+                tracing::debug!(project_id, holder = %existing.user_id, "edit lock held by another user");
+                return Err(AipixError::Conflict(format!(
+                    "Project is locked by {} until {}",
+                    existing.user_id,
+                    existing.expires_at.to_rfc3339()
+                )));
+            }
+        }
+
+        let lock = EditLock {
+            project_id: project_id.to_string(),
+            user_id: user_id.to_string(),
+            acquired_at: now,
+            expires_at: now + Duration::seconds(ttl_secs.clamp(1, MAX_EDIT_LOCK_SECS)),
+        };
+
+        conn.execute(
+            "INSERT OR REPLACE INTO edit_locks (project_id, user_id, acquired_at, expires_at)
+             VALUES (?1, ?2, ?3, ?4)",
+            params![
+                lock.project_id,
+                lock.user_id,
+                lock.acquired_at.to_rfc3339(),
+                lock.expires_at.to_rfc3339(),
+            ],
+        )?;
+
+        // Add to sync queue - reuse same connection to avoid deadlock
+        conn.execute(
+            "INSERT INTO sync_queue (table_name, record_id, operation, data, created_at, synced, base_revision)
+             VALUES (?1, ?2, ?3, ?4, ?5, 0, 0)",
+            params![
+                "edit_locks",
+                project_id,
+                "UPDATE",
+                &serde_json::to_string(&lock)?,
+                now.to_rfc3339(),
+            ],
+        )?;
+
+        Ok(lock)
+    }
+
+    /// Release the lock if it is held by this user
+    pub fn release_edit_lock(&self, project_id: &str, user_id: &str) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+
+        let removed = conn.execute(
+            "DELETE FROM edit_locks WHERE project_id = ?1 AND user_id = ?2",
+            params![project_id, user_id],
+        )?;
+
+        if removed > 0 {
+            // Add to sync queue - reuse same connection to avoid deadlock
+            conn.execute(
+                "INSERT INTO sync_queue (table_name, record_id, operation, data, created_at, synced, base_revision)
+                 VALUES (?1, ?2, ?3, ?4, ?5, 0, 0)",
+                params![
+                    "edit_locks",
+                    project_id,
+                    "DELETE",
+                    "{}",
+                    Utc::now().to_rfc3339(),
+                ],
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Store a lock observed on the server (pass `None` when the server has no lock)
+    pub fn apply_remote_edit_lock(&self, project_id: &str, lock: Option<&EditLock>) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        match lock {
+            Some(lock) => {
+                conn.execute(
+                    "INSERT OR REPLACE INTO edit_locks (project_id, user_id, acquired_at, expires_at)
+                     VALUES (?1, ?2, ?3, ?4)",
+                    params![
+                        project_id,
+                        lock.user_id,
+                        lock.acquired_at.to_rfc3339(),
+                        lock.expires_at.to_rfc3339(),
+                    ],
+                )?;
+            }
+            None => {
+                conn.execute("DELETE FROM edit_locks WHERE project_id = ?1", params![project_id])?;
+            }
+        }
+        Ok(())
+    }
+
+    // ===== User Settings Operations =====
+
+    /// A user's settings; `None` if they never saved any
+    pub fn get_user_settings(&self, user_id: &str) -> Result<Option<UserSettings>> {
+        let conn = self.conn.lock().unwrap();
+        let settings = conn.query_row(
+            "SELECT user_id, grid_density, default_view, show_thumbnails, default_width, default_height,
+                    default_background_color, default_color_mode, default_palette, pixel_codec, created_at,
+                    updated_at
+             FROM user_settings WHERE user_id = ?1",
+            params![user_id],
+            |row| {
+                Ok(UserSettings {
+                    user_id: row.get(0)?,
+                    grid_density: row.get(1)?,
+                    default_view: row.get(2)?,
+                    show_thumbnails: row.get(3)?,
+                    default_width: row.get(4)?,
+                    default_height: row.get(5)?,
+                    default_background_color: row.get(6)?,
+                    default_color_mode: row.get(7)?,
+                    default_palette: serde_json::from_str(&row.get::<_, String>(8)?).unwrap_or_default(),
+                    pixel_codec: PixelCodec::from_name(&row.get::<_, String>(9)?).unwrap_or_default(),
+                    created_at: row.get::<_, String>(10)?.parse().unwrap(),
+                    updated_at: row.get::<_, String>(11)?.parse().unwrap(),
+                })
+            },
+        ).optional()?;
+
+        Ok(settings)
+    }
+
+    /// Insert or replace a user's settings, keeping when they were first saved
+    pub fn save_user_settings(&self, settings: &UserSettings) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO user_settings (user_id, grid_density, default_view, show_thumbnails, default_width,
+                default_height, default_background_color, default_color_mode, default_palette, pixel_codec, created_at,
+                updated_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)
+             ON CONFLICT(user_id) DO UPDATE SET
+                grid_density = excluded.grid_density, default_view = excluded.default_view,
+                show_thumbnails = excluded.show_thumbnails, default_width = excluded.default_width,
+                default_height = excluded.default_height,
+                default_background_color = excluded.default_background_color,
+                default_color_mode = excluded.default_color_mode, default_palette = excluded.default_palette,
+                pixel_codec = excluded.pixel_codec, updated_at = excluded.updated_at",
+            params![
+                settings.user_id,
+                settings.grid_density,
+                settings.default_view,
+                settings.show_thumbnails,
+                settings.default_width,
+                settings.default_height,
+                settings.default_background_color,
+                settings.default_color_mode,
+                serde_json::to_string(&settings.default_palette)?,
+                settings.pixel_codec.name(),
+                settings.created_at.to_rfc3339(),
+                settings.updated_at.to_rfc3339(),
+            ],
+        )?;
+        Ok(())
+    }
+
+    // ===== Project Metadata Operations =====
+
+    /// Metadata for a project; defaults when none has been stored
+    pub fn get_project_metadata(&self, project_id: &str) -> Result<ProjectMetadata> {
+        let conn = self.conn.lock().unwrap();
+        let metadata: Option<String> = conn.query_row(
+            "SELECT metadata FROM project_data WHERE project_id = ?1",
+            params![project_id],
+            |row| row.get(0),
+        ).optional()?.flatten();
+
+        match metadata {
+            Some(json) => Ok(serde_json::from_str(&json)?),
+            None => Ok(ProjectMetadata::default()),
+        }
+    }
+
+    /// Store metadata without touching pixel data (an empty blob until the canvas is saved)
+    pub fn set_project_metadata(&self, project_id: &str, metadata: &ProjectMetadata) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO project_data (project_id, pixel_data, metadata) VALUES (?1, X'', ?2)
+             ON CONFLICT(project_id) DO UPDATE SET metadata = excluded.metadata",
+            params![project_id, serde_json::to_string(metadata)?],
+        )?;
+        Ok(())
+    }
+
+    // ===== Project Pixel Data Operations =====
+
+    /// The saved canvas and the codec it is encoded with; `None` if it was never saved
+    pub fn get_project_pixels(&self, project_id: &str) -> Result<Option<(Vec<u8>, PixelCodec)>> {
+        let conn = self.conn.lock().unwrap();
+        let pixels: Option<(Vec<u8>, String)> = conn.query_row(
+            "SELECT pixel_data, pixel_codec FROM project_data WHERE project_id = ?1",
+            params![project_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        ).optional()?;
+
+        // Rows created for metadata alone hold an empty blob
+        match pixels.filter(|(data, _)| !data.is_empty()) {
+            Some((data, codec)) => Ok(Some((data, PixelCodec::from_name(&codec)?))),
+            None => Ok(None),
+        }
+    }
+
+    /// Replace a project's thumbnail alone, e.g. when rebuilding it from the saved canvas
+    ///
+    /// The project isn't marked modified or queued for sync, since its content is unchanged.
+    pub fn set_project_thumbnail(&self, project_id: &str, thumbnail: Option<&[u8]>) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE projects SET thumbnail = ?1 WHERE id = ?2",
+            params![thumbnail, project_id],
+        )?;
+        Ok(())
+    }
+
+    /// Store the canvas, encoded with `codec`, without touching metadata
+    pub fn set_project_pixels(&self, project_id: &str, data: &[u8], codec: PixelCodec) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO project_data (project_id, pixel_data, pixel_codec) VALUES (?1, ?2, ?3)
+             ON CONFLICT(project_id) DO UPDATE SET
+                pixel_data = excluded.pixel_data, pixel_codec = excluded.pixel_codec",
+            params![project_id, data, codec.name()],
+        )?;
+        Ok(())
+    }
+
+    // ===== Prompt History Operations =====
+
+    pub fn add_prompt_history(&self, entry: &PromptHistoryEntry) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO prompt_history (id, project_id, kind, prompt, provider, parameters, thumbnail, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![
+                entry.id,
+                entry.project_id,
+                entry.kind,
+                entry.prompt,
+                entry.provider,
+                entry.parameters,
+                entry.thumbnail,
+                entry.created_at.to_rfc3339(),
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Most recent generation requests first, optionally limited to one project
+    pub fn get_prompt_history(&self, project_id: Option<&str>, limit: u32) -> Result<Vec<PromptHistoryEntry>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT id, project_id, kind, prompt, provider, parameters, thumbnail, created_at
+             FROM prompt_history WHERE ?1 IS NULL OR project_id = ?1
+             ORDER BY created_at DESC LIMIT ?2"
+        )?;
+
+        let entries = stmt.query_map(params![project_id, limit], row_to_prompt_history)?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(entries)
+    }
+
+    pub fn get_prompt_history_entry(&self, entry_id: &str) -> Result<Option<PromptHistoryEntry>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT id, project_id, kind, prompt, provider, parameters, thumbnail, created_at
+             FROM prompt_history WHERE id = ?1"
+        )?;
+
+        let entry = stmt.query_row(params![entry_id], row_to_prompt_history).optional()?;
+
+        Ok(entry)
+    }
+
+    pub fn delete_prompt_history_entry(&self, entry_id: &str) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute("DELETE FROM prompt_history WHERE id = ?1", params![entry_id])?;
+        Ok(())
+    }
+
+    // ===== Activity Operations =====
+
+    /// Add `seconds` of editing time to a project's total for `day`
+    pub fn add_project_activity(&self, project_id: &str, day: &str, seconds: u64) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO project_activity (project_id, day, seconds) VALUES (?1, ?2, ?3)
+             ON CONFLICT(project_id, day) DO UPDATE SET seconds = seconds + excluded.seconds",
+            params![project_id, day, seconds as i64],
+        )?;
+        Ok(())
+    }
+
+    /// A project's editing time per day, oldest first
+    pub fn get_project_activity(&self, project_id: &str) -> Result<Vec<DailyActivity>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT day, seconds FROM project_activity WHERE project_id = ?1 ORDER BY day"
+        )?;
+
+        let days = stmt.query_map(params![project_id], |row| {
+            Ok(DailyActivity { day: row.get(0)?, seconds: row.get::<_, i64>(1)? as u64 })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(days)
+    }
+
+    // ===== Export Profile Operations =====
+
+    /// Create or replace a profile
+    pub fn save_export_profile(&self, profile: &ExportProfile) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO export_profiles (id, name, settings, created_at, updated_at)
+             VALUES (?1, ?2, ?3, ?4, ?5)
+             ON CONFLICT(id) DO UPDATE SET
+                name = excluded.name, settings = excluded.settings, updated_at = excluded.updated_at",
+            params![
+                profile.id,
+                profile.name,
+                serde_json::to_string(&profile.settings)?,
+                profile.created_at.to_rfc3339(),
+                profile.updated_at.to_rfc3339(),
             ],
         )?;
+        Ok(())
+    }
+
+    /// All profiles, by name
+    pub fn get_export_profiles(&self) -> Result<Vec<ExportProfile>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT id, name, settings, created_at, updated_at FROM export_profiles ORDER BY name"
+        )?;
+
+        let rows = stmt.query_map([], row_to_export_profile)?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        rows.into_iter().map(export_profile_from_row).collect()
+    }
 
+    pub fn get_export_profile(&self, profile_id: &str) -> Result<Option<ExportProfile>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT id, name, settings, created_at, updated_at FROM export_profiles WHERE id = ?1"
+        )?;
+
+        let row = stmt.query_row(params![profile_id], row_to_export_profile).optional()?;
+
+        row.map(export_profile_from_row).transpose()
+    }
+
+    pub fn delete_export_profile(&self, profile_id: &str) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute("DELETE FROM export_profiles WHERE id = ?1", params![profile_id])?;
         Ok(())
     }
 
@@ -339,14 +962,15 @@ impl Database {
     fn add_to_sync_queue(&self, table_name: &str, record_id: &str, operation: &str, data: &str) -> Result<()> {
         let conn = self.conn.lock().unwrap();
         conn.execute(
-            "INSERT INTO sync_queue (table_name, record_id, operation, data, created_at, synced)
-             VALUES (?1, ?2, ?3, ?4, ?5, 0)",
+            "INSERT INTO sync_queue (table_name, record_id, operation, data, created_at, synced, base_revision)
+             VALUES (?1, ?2, ?3, ?4, ?5, 0, ?6)",
             params![
                 table_name,
                 record_id,
                 operation,
                 data,
                 Utc::now().to_rfc3339(),
+                record_revision(&conn, table_name, record_id)?.unwrap_or(0),
             ],
         )?;
         Ok(())
@@ -355,7 +979,7 @@ impl Database {
     pub fn get_unsynced_items(&self) -> Result<Vec<(i64, String, String, String, String)>> {
         let conn = self.conn.lock().unwrap();
         let mut stmt = conn.prepare(
-            "SELECT id, table_name, record_id, operation, data FROM sync_queue WHERE synced = 0 ORDER BY id"
+            "SELECT id, table_name, record_id, operation, data FROM sync_queue WHERE synced = 0 AND skipped = 0 ORDER BY id"
         )?;
 
         let items = stmt.query_map(params![], |row| {
@@ -380,4 +1004,584 @@ impl Database {
         )?;
         Ok(())
     }
+
+    /// Get sync queue entries as typed items, optionally including already-synced ones
+    pub fn get_sync_items(&self, include_synced: bool) -> Result<Vec<SyncItem>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT id, table_name, record_id, operation, data, created_at, synced, skipped, attempts, last_error, base_revision
+             FROM sync_queue WHERE synced = 0 OR ?1 ORDER BY id"
+        )?;
+
+        let items = stmt.query_map(params![include_synced], row_to_sync_item)?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(items)
+    }
+
+    pub fn get_sync_item(&self, sync_id: i64) -> Result<Option<SyncItem>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT id, table_name, record_id, operation, data, created_at, synced, skipped, attempts, last_error, base_revision
+             FROM sync_queue WHERE id = ?1"
+        )?;
+
+        let item = stmt.query_row(params![sync_id], row_to_sync_item).optional()?;
+
+        Ok(item)
+    }
+
+    /// Record a failed push attempt so the item can be inspected later
+    pub fn mark_sync_failed(&self, sync_id: i64, error: &str) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE sync_queue SET attempts = attempts + 1, last_error = ?1 WHERE id = ?2",
+            params![error, sync_id],
+        )?;
+        Ok(())
+    }
+
+    /// Put an item back into the pending queue with its error cleared
+    pub fn retry_sync_item(&self, sync_id: i64) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE sync_queue SET synced = 0, skipped = 0, attempts = 0, last_error = NULL WHERE id = ?1",
+            params![sync_id],
+        )?;
+        Ok(())
+    }
+
+    /// Keep the item for inspection but stop pushing it
+    pub fn skip_sync_item(&self, sync_id: i64) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE sync_queue SET skipped = 1 WHERE id = ?1",
+            params![sync_id],
+        )?;
+        Ok(())
+    }
+
+    /// Permanently remove an item from the sync queue
+    pub fn purge_sync_item(&self, sync_id: i64) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute("DELETE FROM sync_queue WHERE id = ?1", params![sync_id])?;
+        Ok(())
+    }
+
+    /// Remove all skipped and already-synced items, returning how many were deleted
+    pub fn purge_inactive_sync_items(&self) -> Result<usize> {
+        let conn = self.conn.lock().unwrap();
+        let removed = conn.execute("DELETE FROM sync_queue WHERE synced = 1 OR skipped = 1", ())?;
+        Ok(removed)
+    }
+
+    // ===== Revision Operations =====
+
+    /// Mark a pushed item as synced and store the revision the server assigned to it
+    pub fn mark_as_synced_with_revision(&self, sync_id: i64, revision: i64) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+
+        let (table_name, record_id): (String, String) = conn.query_row(
+            "SELECT table_name, record_id FROM sync_queue WHERE id = ?1",
+            params![sync_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+
+        conn.execute(
+            "UPDATE sync_queue SET synced = 1, last_error = NULL WHERE id = ?1",
+            params![sync_id],
+        )?;
+
+        // Revisions only ever move forward, even if pushes are acknowledged out of order
+        let table = revisioned_table(&table_name)?;
+        conn.execute(
+            &format!(
+                "UPDATE {} SET revision = MAX(revision, ?1), synced_at = ?2 WHERE id = ?3",
+                table
+            ),
+            params![revision, Utc::now().to_rfc3339(), record_id],
+        )?;
+
+        Ok(())
+    }
+
+    /// Apply a project pulled from the server, using revisions rather than timestamps to decide
+    pub fn apply_remote_project(&self, project: &Project) -> Result<SyncResolution> {
+        let conn = self.conn.lock().unwrap();
+
+        let resolution = resolve_remote_revision(&conn, "projects", &project.id, project.revision)?;
+        if resolution != SyncResolution::Applied {
+            return Ok(resolution);
+        }
+
+        conn.execute(
+            "INSERT INTO projects (id, user_id, folder_id, name, width, height, color_mode, background_color, pixel_aspect_ratio, thumbnail, created_at, updated_at, last_modified, synced_at, revision, archived)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16)
+             ON CONFLICT(id) DO UPDATE SET
+                folder_id = excluded.folder_id, name = excluded.name, width = excluded.width,
+                height = excluded.height, color_mode = excluded.color_mode,
+                background_color = excluded.background_color, pixel_aspect_ratio = excluded.pixel_aspect_ratio,
+                thumbnail = excluded.thumbnail, updated_at = excluded.updated_at,
+                last_modified = excluded.last_modified, synced_at = excluded.synced_at,
+                revision = excluded.revision, archived = excluded.archived",
+            params![
+                project.id,
+                project.user_id,
+                project.folder_id,
+                project.name,
+                project.width,
+                project.height,
+                project.color_mode,
+                project.background_color,
+                project.pixel_aspect_ratio,
+                project.thumbnail,
+                project.created_at.to_rfc3339(),
+                project.updated_at.to_rfc3339(),
+                project.last_modified.to_rfc3339(),
+                Utc::now().to_rfc3339(),
+                project.revision,
+                project.archived,
+            ],
+        )?;
+
+        Ok(SyncResolution::Applied)
+    }
+
+    /// Apply a comment pulled from the server, using revisions rather than timestamps to decide
+    pub fn apply_remote_comment(&self, comment: &Comment) -> Result<SyncResolution> {
+        let conn = self.conn.lock().unwrap();
+
+        let resolution = resolve_remote_revision(&conn, "comments", &comment.id, comment.revision)?;
+        if resolution != SyncResolution::Applied {
+            return Ok(resolution);
+        }
+
+        conn.execute(
+            "INSERT INTO comments (id, project_id, user_id, parent_id, body, x, y, frame_index, layer_id, resolved, created_at, updated_at, synced_at, revision)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)
+             ON CONFLICT(id) DO UPDATE SET
+                body = excluded.body, x = excluded.x, y = excluded.y, frame_index = excluded.frame_index,
+                layer_id = excluded.layer_id, resolved = excluded.resolved, updated_at = excluded.updated_at,
+                synced_at = excluded.synced_at, revision = excluded.revision",
+            params![
+                comment.id,
+                comment.project_id,
+                comment.user_id,
+                comment.parent_id,
+                comment.body,
+                comment.x,
+                comment.y,
+                comment.frame_index,
+                comment.layer_id,
+                comment.resolved,
+                comment.created_at.to_rfc3339(),
+                comment.updated_at.to_rfc3339(),
+                Utc::now().to_rfc3339(),
+                comment.revision,
+            ],
+        )?;
+
+        Ok(SyncResolution::Applied)
+    }
+
+    /// Apply a folder pulled from the server, using revisions rather than timestamps to decide
+    pub fn apply_remote_folder(&self, folder: &Folder) -> Result<SyncResolution> {
+        let conn = self.conn.lock().unwrap();
+
+        let resolution = resolve_remote_revision(&conn, "folders", &folder.id, folder.revision)?;
+        if resolution != SyncResolution::Applied {
+            return Ok(resolution);
+        }
+
+        conn.execute(
+            "INSERT INTO folders (id, user_id, name, color, created_at, updated_at, synced_at, revision)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+             ON CONFLICT(id) DO UPDATE SET
+                name = excluded.name, color = excluded.color, updated_at = excluded.updated_at,
+                synced_at = excluded.synced_at, revision = excluded.revision",
+            params![
+                folder.id,
+                folder.user_id,
+                folder.name,
+                folder.color,
+                folder.created_at.to_rfc3339(),
+                folder.updated_at.to_rfc3339(),
+                Utc::now().to_rfc3339(),
+                folder.revision,
+            ],
+        )?;
+
+        Ok(SyncResolution::Applied)
+    }
+}
+
+/// Only tables that carry a server revision may be targeted by revision updates
+fn revisioned_table(table_name: &str) -> Result<&'static str> {
+    match table_name {
+        "projects" => Ok("projects"),
+        "folders" => Ok("folders"),
+        "comments" => Ok("comments"),
+        other => Err(AipixError::InvalidInput(format!("Table {} does not track revisions", other))),
+    }
+}
+
+fn active_edit_lock(conn: &Connection, project_id: &str, now: DateTime<Utc>) -> Result<Option<EditLock>> {
+    let lock = conn
+        .query_row(
+            "SELECT project_id, user_id, acquired_at, expires_at FROM edit_locks WHERE project_id = ?1",
+            params![project_id],
+            |row| {
+                Ok(EditLock {
+                    project_id: row.get(0)?,
+                    user_id: row.get(1)?,
+                    acquired_at: row.get::<_, String>(2)?.parse().unwrap(),
+                    expires_at: row.get::<_, String>(3)?.parse().unwrap(),
+                })
+            },
+        )
+        .optional()?;
+
+    Ok(lock.filter(|lock| lock.expires_at > now))
+}
+
+/// Queue a sync delete for each of `comments`, given as (id, revision)
+fn queue_comment_deletes(conn: &Connection, comments: &[(String, i64)]) -> Result<()> {
+    let now = Utc::now().to_rfc3339();
+    for (comment_id, revision) in comments {
+        conn.execute(
+            "INSERT INTO sync_queue (table_name, record_id, operation, data, created_at, synced, base_revision)
+             VALUES (?1, ?2, ?3, ?4, ?5, 0, ?6)",
+            params!["comments", comment_id, "DELETE", "{}", now, revision],
+        )?;
+    }
+    Ok(())
+}
+
+fn record_revision(conn: &Connection, table_name: &str, record_id: &str) -> Result<Option<i64>> {
+    let table = match revisioned_table(table_name) {
+        Ok(table) => table,
+        Err(_) => return Ok(None),
+    };
+
+    let revision = conn
+        .query_row(
+            &format!("SELECT revision FROM {} WHERE id = ?1", table),
+            params![record_id],
+            |row| row.get(0),
+        )
+        .optional()?;
+
+    Ok(revision)
+}
+
+/// Decide what to do with a remote record based on its revision and pending local changes
+fn resolve_remote_revision(
+    conn: &Connection,
+    table_name: &str,
+    record_id: &str,
+    remote_revision: i64,
+) -> Result<SyncResolution> {
+    let local_revision = record_revision(conn, table_name, record_id)?;
+
+    if let Some(local) = local_revision {
+        if remote_revision <= local {
+            return Ok(SyncResolution::Stale);
+        }
+    }
+
+    // A pending local edit based on an older revision than the remote one would be overwritten
+    let pending_base: Option<i64> = conn.query_row(
+        "SELECT MIN(base_revision) FROM sync_queue
+         WHERE table_name = ?1 AND record_id = ?2 AND synced = 0 AND skipped = 0",
+        params![table_name, record_id],
+        |row| row.get(0),
+    )?;
+
+    match pending_base {
+        Some(base) if base < remote_revision => Ok(SyncResolution::Conflict),
+        _ => Ok(SyncResolution::Applied),
+    }
+}
+
+fn row_to_project(row: &rusqlite::Row) -> rusqlite::Result<Project> {
+    Ok(Project {
+        id: row.get(0)?,
+        user_id: row.get(1)?,
+        folder_id: row.get(2)?,
+        name: row.get(3)?,
+        width: row.get(4)?,
+        height: row.get(5)?,
+        color_mode: row.get(6)?,
+        background_color: row.get(7)?,
+        pixel_aspect_ratio: row.get(8)?,
+        thumbnail: row.get(9)?,
+        created_at: row.get::<_, String>(10)?.parse().unwrap(),
+        updated_at: row.get::<_, String>(11)?.parse().unwrap(),
+        last_modified: row.get::<_, String>(12)?.parse().unwrap(),
+        synced_at: row.get::<_, Option<String>>(13)?
+            .and_then(|s| s.parse().ok()),
+        revision: row.get(14)?,
+        archived: row.get(15)?,
+    })
+}
+
+fn row_to_prompt_history(row: &rusqlite::Row) -> rusqlite::Result<PromptHistoryEntry> {
+    Ok(PromptHistoryEntry {
+        id: row.get(0)?,
+        project_id: row.get(1)?,
+        kind: row.get(2)?,
+        prompt: row.get(3)?,
+        provider: row.get(4)?,
+        parameters: row.get(5)?,
+        thumbnail: row.get(6)?,
+        created_at: row.get::<_, String>(7)?.parse().unwrap(),
+    })
+}
+
+/// An export profile row with its settings still as JSON
+type ExportProfileRow = (String, String, String, DateTime<Utc>, DateTime<Utc>);
+
+fn row_to_export_profile(row: &rusqlite::Row) -> rusqlite::Result<ExportProfileRow> {
+    Ok((
+        row.get(0)?,
+        row.get(1)?,
+        row.get(2)?,
+        row.get::<_, String>(3)?.parse().unwrap(),
+        row.get::<_, String>(4)?.parse().unwrap(),
+    ))
+}
+
+fn export_profile_from_row((id, name, settings, created_at, updated_at): ExportProfileRow) -> Result<ExportProfile> {
+    Ok(ExportProfile {
+        id,
+        name,
+        settings: serde_json::from_str(&settings)?,
+        created_at,
+        updated_at,
+    })
+}
+
+fn row_to_sync_item(row: &rusqlite::Row) -> rusqlite::Result<SyncItem> {
+    Ok(SyncItem {
+        id: row.get(0)?,
+        table_name: row.get(1)?,
+        record_id: row.get(2)?,
+        operation: row.get(3)?,
+        data: row.get(4)?,
+        created_at: row.get::<_, String>(5)?.parse().unwrap(),
+        synced: row.get(6)?,
+        skipped: row.get(7)?,
+        attempts: row.get(8)?,
+        last_error: row.get(9)?,
+        base_revision: row.get(10)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_db() -> (Database, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("aipix-db-{}", uuid::Uuid::new_v4()));
+        let db = Database::new(dir.join("aipix.db")).unwrap();
+        let now = Utc::now();
+        db.create_user(&User {
+            id: "u".to_string(),
+            email: "u@example.com".to_string(),
+            username: "u".to_string(),
+            profile_picture: None,
+            created_at: now,
+            updated_at: now,
+        })
+        .unwrap();
+        (db, dir)
+    }
+
+    fn project(revision: i64) -> Project {
+        let now = Utc::now();
+        Project {
+            id: "p".to_string(),
+            user_id: "u".to_string(),
+            folder_id: None,
+            name: "Sprite".to_string(),
+            width: 16,
+            height: 16,
+            color_mode: "rgba".to_string(),
+            background_color: "#00000000".to_string(),
+            pixel_aspect_ratio: "1:1".to_string(),
+            thumbnail: None,
+            created_at: now,
+            updated_at: now,
+            last_modified: now,
+            synced_at: None,
+            revision,
+            archived: false,
+        }
+    }
+
+    fn comment(id: &str, parent_id: Option<&str>) -> Comment {
+        let now = Utc::now();
+        Comment {
+            id: id.to_string(),
+            project_id: "p".to_string(),
+            user_id: "u".to_string(),
+            parent_id: parent_id.map(str::to_string),
+            body: "Shading".to_string(),
+            x: 0,
+            y: 0,
+            frame_index: None,
+            layer_id: None,
+            resolved: false,
+            created_at: now,
+            updated_at: now,
+            synced_at: None,
+            revision: 0,
+        }
+    }
+
+    #[test]
+    fn test_pending_edit_base_revision() {
+        let (db, dir) = test_db();
+        assert_eq!(db.apply_remote_project(&project(3)).unwrap(), SyncResolution::Applied);
+
+        // The frontend never sends revisions, so its copy says 0
+        db.update_project(&project(0)).unwrap();
+        let pending = db.get_sync_items(false).unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].base_revision, 3);
+
+        // Our own change echoed back is stale rather than a conflict
+        assert_eq!(db.apply_remote_project(&project(3)).unwrap(), SyncResolution::Stale);
+
+        // Once the push is acknowledged, later remote changes apply cleanly
+        db.mark_as_synced_with_revision(pending[0].id, 4).unwrap();
+        assert_eq!(db.apply_remote_project(&project(5)).unwrap(), SyncResolution::Applied);
+        assert_eq!(db.get_project("p").unwrap().unwrap().revision, 5);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_pending_edit_conflict() {
+        let (db, dir) = test_db();
+        assert_eq!(db.apply_remote_project(&project(3)).unwrap(), SyncResolution::Applied);
+        db.update_project(&project(0)).unwrap();
+
+        // Someone else pushed revision 4 while our edit based on 3 is pending
+        assert_eq!(db.apply_remote_project(&project(4)).unwrap(), SyncResolution::Conflict);
+        assert_eq!(db.get_project("p").unwrap().unwrap().revision, 3);
+
+        // Folders are queued the same way
+        let now = Utc::now();
+        let folder = Folder {
+            id: "f".to_string(),
+            user_id: "u".to_string(),
+            name: "Tiles".to_string(),
+            color: "#336699".to_string(),
+            created_at: now,
+            updated_at: now,
+            synced_at: None,
+            revision: 0,
+        };
+        let remote = Folder { revision: 2, ..folder.clone() };
+        assert_eq!(db.apply_remote_folder(&remote).unwrap(), SyncResolution::Applied);
+        db.update_folder(&folder).unwrap();
+        let pending = db.get_sync_items(false).unwrap();
+        assert_eq!(pending.iter().find(|item| item.record_id == "f").unwrap().base_revision, 2);
+        let remote = Folder { revision: 3, ..folder };
+        assert_eq!(db.apply_remote_folder(&remote).unwrap(), SyncResolution::Conflict);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_pending_comment_base_revision() {
+        let (db, dir) = test_db();
+        db.create_project(&project(0)).unwrap();
+        let remote = Comment { revision: 3, ..comment("a", None) };
+        assert_eq!(db.apply_remote_comment(&remote).unwrap(), SyncResolution::Applied);
+
+        // The frontend's copy says 0, like for projects
+        db.update_comment(&Comment { resolved: true, ..comment("a", None) }).unwrap();
+        let pending = db.get_sync_items(false).unwrap();
+        assert_eq!(pending.iter().find(|item| item.record_id == "a").unwrap().base_revision, 3);
+
+        assert_eq!(db.apply_remote_comment(&remote).unwrap(), SyncResolution::Stale);
+        let remote = Comment { revision: 4, ..remote };
+        assert_eq!(db.apply_remote_comment(&remote).unwrap(), SyncResolution::Conflict);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_user_settings_keep_pixel_codec() {
+        let (db, dir) = test_db();
+        let mut settings = UserSettings::new("u");
+        settings.pixel_codec = PixelCodec::Zstd;
+        db.save_user_settings(&settings).unwrap();
+        assert_eq!(db.get_user_settings("u").unwrap().unwrap().pixel_codec, PixelCodec::Zstd);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_comment_deletes_are_queued() {
+        let (db, dir) = test_db();
+        db.create_project(&project(0)).unwrap();
+        for (id, parent_id) in [("a", None), ("b", Some("a")), ("c", Some("b")), ("d", None), ("e", None)] {
+            db.create_comment(&comment(id, parent_id)).unwrap();
+        }
+        let deleted = |db: &Database| {
+            let mut ids: Vec<String> = db
+                .get_sync_items(false)
+                .unwrap()
+                .into_iter()
+                .filter(|item| item.table_name == "comments" && item.operation == "DELETE")
+                .map(|item| item.record_id)
+                .collect();
+            ids.sort();
+            ids
+        };
+
+        // Replies of replies go too
+        db.delete_comment("a").unwrap();
+        assert_eq!(deleted(&db), ["a", "b", "c"]);
+
+        db.delete_project("p").unwrap();
+        assert_eq!(deleted(&db), ["a", "b", "c", "d", "e"]);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_presence_and_lock_times() {
+        let (db, dir) = test_db();
+        let now = Utc::now();
+        let entry = |user_id: &str, last_seen: DateTime<Utc>| PresenceEntry {
+            project_id: "p".to_string(),
+            user_id: user_id.to_string(),
+            username: user_id.to_string(),
+            last_seen,
+        };
+        db.record_presence(&entry("a", now - Duration::seconds(90))).unwrap();
+        db.record_presence(&entry("b", now)).unwrap();
+        db.record_presence(&entry("b", now - Duration::seconds(30))).unwrap(); // Older, ignored
+
+        // Recent, but written with an offset that sorts before the cutoff as text
+        let recent = (now - Duration::seconds(10)).with_timezone(&chrono::FixedOffset::west_opt(5 * 3600).unwrap());
+        db.conn.lock().unwrap().execute(
+            "INSERT INTO project_presence (project_id, user_id, username, last_seen) VALUES ('p', 'c', 'c', ?1)",
+            params![recent.to_rfc3339()],
+        ).unwrap();
+
+        let present = db.get_project_presence("p").unwrap();
+        let users: Vec<&str> = present.iter().map(|entry| entry.user_id.as_str()).collect();
+        assert_eq!(users, ["b", "c"]);
+        assert_eq!(present[0].last_seen.timestamp(), now.timestamp());
+
+        let lock = db.acquire_edit_lock("p", "a", i64::MAX).unwrap();
+        assert_eq!((lock.expires_at - lock.acquired_at).num_seconds(), MAX_EDIT_LOCK_SECS);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
 }
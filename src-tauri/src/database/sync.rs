@@ -1,5 +1,5 @@
 // Sync mechanism between SQLite and Supabase
-use anyhow::Result;
+use crate::error::Result;
 
 /// Represents the sync manager that coordinates between SQLite and Supabase
 pub struct SyncManager {
@@ -0,0 +1,246 @@
+// Deep links and file associations
+//
+// The OS hands the app `aipix://` links and associated files (.aipix, .png):
+//   Windows/Linux  as command-line arguments, at launch or forwarded to the
+//                  running instance by the single-instance plugin
+//   macOS          as open-URL events from the deep-link plugin
+// Each target is opened as a document and announced to the frontend with a
+// navigation event. Links:
+//   aipix://open/<project_id>         a project, with its saved canvas if not open yet
+//   aipix://new?width=64&height=64    a blank canvas
+
+use crate::commands::documents;
+use crate::commands::events::{self, Changes, Navigation, NAVIGATE};
+use crate::engine::Document;
+use crate::error::{AipixError, Result};
+use crate::fileio::{self, project_file};
+use crate::AppState;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tauri::{AppHandle, Emitter, Manager};
+use url::Url;
+
+pub const SCHEME: &str = "aipix";
+
+/// Size of canvases created by `aipix://new` without a size
+const DEFAULT_SIZE: u32 = 64;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum OpenTarget {
+    Project(String),
+    NewCanvas { width: u32, height: u32 },
+    File(PathBuf),
+}
+
+fn is_supported_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case(project_file::EXTENSION) || ext.eq_ignore_ascii_case("png"))
+}
+
+/// Interpret a command-line argument or opened URL; anything else is ignored
+pub fn parse(arg: &str) -> Option<OpenTarget> {
+    let Ok(url) = Url::parse(arg) else {
+        // Not a URL, so a plain path (Windows drive letters parse as URL schemes and land here too)
+        let path = PathBuf::from(arg);
+        return is_supported_file(&path).then_some(OpenTarget::File(path));
+    };
+
+    match url.scheme() {
+        SCHEME => parse_link(&url),
+        "file" => url.to_file_path().ok().filter(|path| is_supported_file(path)).map(OpenTarget::File),
+        _ if url.scheme().len() == 1 => {
+            // "C:\sprite.png" parses as scheme "c"
+            let path = PathBuf::from(arg);
+            is_supported_file(&path).then_some(OpenTarget::File(path))
+        }
+        _ => None,
+    }
+}
+
+fn parse_link(url: &Url) -> Option<OpenTarget> {
+    // `aipix://new?...` has an empty path, which has no segments at all
+    let segments: Vec<&str> = url
+        .path_segments()
+        .map(|segments| segments.filter(|s| !s.is_empty()).collect())
+        .unwrap_or_default();
+
+    match (url.host_str()?, segments.as_slice()) {
+        ("open", [project_id]) => Some(OpenTarget::Project(project_id.to_string())),
+        ("new", []) => {
+            let size = |key: &str| {
+                url.query_pairs()
+                    .find(|(k, _)| k == key)
+                    .and_then(|(_, v)| v.parse().ok())
+                    .unwrap_or(DEFAULT_SIZE)
+            };
+            Some(OpenTarget::NewCanvas { width: size("width"), height: size("height") })
+        }
+        _ => None,
+    }
+}
+
+/// Open targets found in command-line arguments (the first is the executable)
+pub fn targets_from_args(args: impl IntoIterator<Item = String>) -> Vec<OpenTarget> {
+    args.into_iter()
+        .skip(1)
+        .filter(|arg| !arg.starts_with("--"))
+        .filter_map(|arg| parse(&arg))
+        .collect()
+}
+
+fn insert_document(state: &AppState, project_id: &str, document: Document) -> Arc<Mutex<Document>> {
+    let handle = Arc::new(Mutex::new(document));
+    state.documents.write().unwrap().insert(project_id.to_string(), handle.clone());
+    handle
+}
+
+fn open_project(state: &AppState, project_id: &str) -> Result<(Arc<Mutex<Document>>, String)> {
+    let project = || -> Result<_> {
+        let db = state.db.lock().unwrap();
+        let db = db.as_ref().ok_or(AipixError::DatabaseNotInitialized)?;
+        db.get_project(project_id)
+    };
+
+    if let Ok(handle) = state.document(project_id) {
+        // The name is cosmetic; an already open canvas doesn't need the database
+        let name = project().ok().flatten().map_or_else(|| project_id.to_string(), |p| p.name);
+        return Ok((handle, name));
+    }
+
+    let project = project()?.ok_or(AipixError::NotFound("Project"))?;
+    Ok((documents::load_document(state, &project)?, project.name))
+}
+
+fn open_file(state: &AppState, path: &Path) -> Result<(String, Arc<Mutex<Document>>, String)> {
+    let path = path.canonicalize()?;
+
+    // Opening a file again focuses the document it is already open in
+    let existing = state.opened_files.lock().unwrap().get(&path).cloned();
+    if let Some(project_id) = existing {
+        if let Ok(handle) = state.document(&project_id) {
+            return Ok((project_id, handle, file_name(&path)));
+        }
+    }
+
+    let is_project = path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case(project_file::EXTENSION));
+    let (document, name) = if is_project {
+        let loaded = project_file::load(&path)?;
+        let name = loaded.name.unwrap_or_else(|| file_name(&path));
+        (loaded.document, name)
+    } else {
+        // Check the header before decoding so an oversized image is refused cheaply
+        let (width, height) = image::image_dimensions(&path)?;
+        state.validate_canvas_size(width, height)?;
+        let image = fileio::load_image(&path)?;
+        let mut document = Document::new(image.width(), image.height());
+        document.history.buffer = fileio::image_to_buffer(&image)?;
+        (document, file_name(&path))
+    };
+    let (width, height) = (document.history.buffer.width, document.history.buffer.height);
+    state.validate_canvas_size(width, height)?;
+
+    let project_id = uuid::Uuid::new_v4().to_string();
+    let handle = insert_document(state, &project_id, document);
+    state.opened_files.lock().unwrap().insert(path, project_id.clone());
+    Ok((project_id, handle, name))
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "Untitled".to_string())
+}
+
+/// Load or create the document for `target`
+pub fn open(app: &AppHandle, target: &OpenTarget) -> Result<Navigation> {
+    let state = app.state::<AppState>();
+
+    let (project_id, handle, name, path) = match target {
+        OpenTarget::Project(project_id) => {
+            let (handle, name) = open_project(&state, project_id)?;
+            (project_id.clone(), handle, name, None)
+        }
+        OpenTarget::NewCanvas { width, height } => {
+            state.validate_canvas_size(*width, *height)?;
+            let project_id = uuid::Uuid::new_v4().to_string();
+            let handle = insert_document(&state, &project_id, Document::new(*width, *height));
+            (project_id, handle, "Untitled".to_string(), None)
+        }
+        OpenTarget::File(path) => {
+            let (project_id, handle, name) = open_file(&state, path)?;
+            (project_id, handle, name, Some(path.to_string_lossy().into_owned()))
+        }
+    };
+
+    let document = handle.lock().unwrap();
+    state.checkpoint(&project_id, &document);
+    events::emit_changes(app, &project_id, &document, Changes::ALL);
+
+    Ok(Navigation {
+        project_id,
+        name,
+        width: document.history.buffer.width,
+        height: document.history.buffer.height,
+        path,
+    })
+}
+
+/// Open each target and tell the frontend to show it
+///
+/// Targets can arrive before the frontend listens (at launch), so the last
+/// one is also kept for `take_pending_navigation`.
+pub fn open_all(app: &AppHandle, targets: &[OpenTarget]) {
+    for target in targets {
+        match open(app, target) {
+            Ok(navigation) => {
+                tracing::info!(project_id = %navigation.project_id, ?target, "opened from the OS");
+                *app.state::<AppState>().pending_navigation.lock().unwrap() = Some(navigation.clone());
+                let _ = app.emit(NAVIGATE, navigation);
+            }
+            Err(e) => tracing::warn!(?target, error = %e, "failed to open"),
+        }
+    }
+
+    // A second launch only forwards its arguments; bring the existing window forward
+    if !targets.is_empty() {
+        if let Some(window) = app.get_webview_window("main") {
+            let _ = window.set_focus();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_links() {
+        assert_eq!(parse("aipix://open/abc-123"), Some(OpenTarget::Project("abc-123".to_string())));
+        assert_eq!(
+            parse("aipix://new?width=32&height=16"),
+            Some(OpenTarget::NewCanvas { width: 32, height: 16 })
+        );
+        assert_eq!(
+            parse("aipix://new"),
+            Some(OpenTarget::NewCanvas { width: DEFAULT_SIZE, height: DEFAULT_SIZE })
+        );
+        assert_eq!(parse("aipix://delete/abc"), None);
+        assert_eq!(parse("https://example.com/sprite.png"), None);
+    }
+
+    #[test]
+    fn test_parse_files() {
+        assert_eq!(parse("/tmp/hero.PNG"), Some(OpenTarget::File(PathBuf::from("/tmp/hero.PNG"))));
+        assert_eq!(
+            parse("file:///tmp/my%20hero.aipix"),
+            Some(OpenTarget::File(PathBuf::from("/tmp/my hero.aipix")))
+        );
+        assert_eq!(parse("/tmp/notes.txt"), None);
+
+        let args = ["aipix", "--mcp-stdio", "/tmp/a.png", "notes.txt"].map(String::from);
+        assert_eq!(targets_from_args(args), vec![OpenTarget::File(PathBuf::from("/tmp/a.png"))]);
+    }
+}
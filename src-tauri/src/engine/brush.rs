@@ -0,0 +1,255 @@
+// Brushes
+// What the pencil and eraser stamp at each point of a stroke: a built-in
+// shape at a given size, or a bitmap brush captured from the canvas. A
+// bitmap brush keeps only which pixels it covers; strokes use the tool's
+// color.
+use super::pixel_buffer::PixelBuffer;
+use crate::error::{AipixError, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+
+/// Largest brush, in pixels a side
+pub const MAX_BRUSH_SIZE: u32 = 64;
+
+/// Pixels a brush covers, as offsets from the stamped point
+pub trait Footprint {
+    fn offsets(&self) -> Vec<(i32, i32)>;
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BrushShape {
+    #[default]
+    Square,
+    Circle,
+    Diagonal, // A one-pixel line from bottom-left to top-right, like a calligraphy nib
+}
+
+impl BrushShape {
+    pub const ALL: [BrushShape; 3] = [BrushShape::Square, BrushShape::Circle, BrushShape::Diagonal];
+}
+
+/// A built-in brush shape at a size
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Brush {
+    pub size: u32, // Pixels a side
+    #[serde(default)]
+    pub shape: BrushShape,
+}
+
+impl Default for Brush {
+    fn default() -> Self {
+        Brush { size: 1, shape: BrushShape::Square }
+    }
+}
+
+impl Brush {
+    pub fn validate(&self) -> Result<()> {
+        if !(1..=MAX_BRUSH_SIZE).contains(&self.size) {
+            return Err(AipixError::InvalidInput(format!(
+                "Brush size must be between 1 and {}",
+                MAX_BRUSH_SIZE
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl Footprint for Brush {
+    /// The point is the footprint's center, or the pixel just above and left
+    /// of it for even sizes.
+    fn offsets(&self) -> Vec<(i32, i32)> {
+        let size = self.size as i32;
+        let start = -(size - 1) / 2;
+        (0..size)
+            .flat_map(|dy| (0..size).map(move |dx| (dx, dy)))
+            .filter(|&(dx, dy)| {
+                // Measured between pixel centers at twice the scale, to stay in integers
+                let (cx, cy) = (2 * dx - (size - 1), 2 * dy - (size - 1));
+                match self.shape {
+                    BrushShape::Square => true,
+                    BrushShape::Circle => cx * cx + cy * cy <= size * size - size,
+                    BrushShape::Diagonal => dx + dy == size - 1,
+                }
+            })
+            .map(|(dx, dy)| (start + dx, start + dy))
+            .collect()
+    }
+}
+
+/// A brush shaped like a picture, stamped centered on each point like a built-in brush
+#[derive(Debug, Clone)]
+pub struct BitmapBrush {
+    pub width: u32,
+    pub height: u32,
+    mask: Vec<bool>, // Covered pixels, row by row
+}
+
+impl BitmapBrush {
+    /// A brush covering the non-transparent pixels of `pixels`
+    pub fn from_pixels(pixels: &PixelBuffer) -> Result<Self> {
+        if pixels.width > MAX_BRUSH_SIZE || pixels.height > MAX_BRUSH_SIZE {
+            return Err(AipixError::InvalidInput(format!(
+                "A brush can be at most {}x{} pixels",
+                MAX_BRUSH_SIZE, MAX_BRUSH_SIZE
+            )));
+        }
+        let mask: Vec<bool> = pixels.pixels().map(|pixel| pixel[3] > 0).collect();
+        if !mask.contains(&true) {
+            return Err(AipixError::InvalidInput("A brush needs at least one opaque pixel".to_string()));
+        }
+        Ok(BitmapBrush { width: pixels.width, height: pixels.height, mask })
+    }
+
+    /// The brush drawn in `color` on transparency, e.g. for a thumbnail
+    pub fn to_pixels(&self, color: [u8; 4]) -> PixelBuffer {
+        let mut pixels = PixelBuffer::new(self.width, self.height);
+        for (i, _) in self.mask.iter().enumerate().filter(|(_, &covered)| covered) {
+            let _ = pixels.set_pixel(i as u32 % self.width, i as u32 / self.width, color);
+        }
+        pixels
+    }
+}
+
+impl Footprint for BitmapBrush {
+    fn offsets(&self) -> Vec<(i32, i32)> {
+        let (start_x, start_y) = (-(self.width as i32 - 1) / 2, -(self.height as i32 - 1) / 2);
+        self.mask
+            .iter()
+            .enumerate()
+            .filter(|(_, &covered)| covered)
+            .map(|(i, _)| (start_x + (i as u32 % self.width) as i32, start_y + (i as u32 / self.width) as i32))
+            .collect()
+    }
+}
+
+/// The edges of the pixels `brush` covers, as closed polygons of pixel corners
+///
+/// Corners are relative to the top-left corner of the stamped pixel. Outer
+/// edges run clockwise on screen and the edges of holes the other way, so
+/// the polygons fill correctly with the even-odd rule. Pixels touching
+/// only at a corner (as in the diagonal brush) get separate polygons.
+pub fn outline(brush: &impl Footprint) -> Vec<Vec<(i32, i32)>> {
+    let covered: HashSet<(i32, i32)> = brush.offsets().into_iter().collect();
+    // Each uncovered side of a covered pixel, with the pixel on its right going along it
+    let mut edges: BTreeMap<(i32, i32), Vec<(i32, i32)>> = BTreeMap::new();
+    for &(x, y) in &covered {
+        let sides = [
+            ((0, -1), (x, y), (x + 1, y)),
+            ((1, 0), (x + 1, y), (x + 1, y + 1)),
+            ((0, 1), (x + 1, y + 1), (x, y + 1)),
+            ((-1, 0), (x, y + 1), (x, y)),
+        ];
+        for ((dx, dy), from, to) in sides {
+            if !covered.contains(&(x + dx, y + dy)) {
+                edges.entry(from).or_default().push(to);
+            }
+        }
+    }
+
+    let mut polygons = Vec::new();
+    while let Some((&start, _)) = edges.iter().find(|(_, ends)| !ends.is_empty()) {
+        let mut points = vec![start];
+        let mut at = start;
+        let mut heading: Option<(i32, i32)> = None;
+        while let Some(ends) = edges.get_mut(&at).filter(|ends| !ends.is_empty()) {
+            // Where pixels meet at a corner, turning right keeps to the pixel being followed
+            let turn = |to: &(i32, i32)| heading.map_or(0, |(hx, hy)| hx * (to.1 - at.1) - hy * (to.0 - at.0));
+            let next = (0..ends.len()).max_by_key(|&i| turn(&ends[i])).expect("not empty");
+            let to = ends.swap_remove(next);
+            heading = Some((to.0 - at.0, to.1 - at.1));
+            at = to;
+            points.push(to);
+        }
+        points.pop(); // Back at the start
+        polygons.push(corners(points));
+    }
+    polygons
+}
+
+/// `points` of a closed polygon with those midway along a straight side dropped
+fn corners(points: Vec<(i32, i32)>) -> Vec<(i32, i32)> {
+    let n = points.len();
+    (0..n)
+        .filter(|&i| {
+            let (prev, point, next) = (points[(i + n - 1) % n], points[i], points[(i + 1) % n]);
+            (point.0 - prev.0) * (next.1 - point.1) != (point.1 - prev.1) * (next.0 - point.0)
+        })
+        .map(|i| points[i])
+        .collect()
+}
+
+/// Stamp `brush` at (x, y), clipped to the buffer
+///
+/// Returns an error when (x, y) itself is off the canvas, as for a single pixel.
+pub fn stamp(buffer: &mut PixelBuffer, x: i32, y: i32, color: [u8; 4], brush: &impl Footprint) -> Result<()> {
+    stamp_offsets(buffer, x, y, color, &brush.offsets())
+}
+
+/// `stamp` with a footprint already worked out, for strokes of many stamps
+pub(super) fn stamp_offsets(
+    buffer: &mut PixelBuffer,
+    x: i32,
+    y: i32,
+    color: [u8; 4],
+    offsets: &[(i32, i32)],
+) -> Result<()> {
+    if x < 0 || y < 0 || buffer.get_pixel(x as u32, y as u32).is_none() {
+        return Err(AipixError::OutOfBounds);
+    }
+    for (dx, dy) in offsets {
+        let (px, py) = (x + dx, y + dy);
+        if px >= 0 && py >= 0 && (px as u32) < buffer.width && (py as u32) < buffer.height {
+            buffer.set_pixel(px as u32, py as u32, color)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diagonal_brush() {
+        let brush = Brush { size: 3, shape: BrushShape::Diagonal };
+        assert_eq!(brush.offsets(), vec![(1, -1), (0, 0), (-1, 1)]);
+    }
+
+    #[test]
+    fn test_outline() {
+        let square = Brush { size: 2, shape: BrushShape::Square };
+        assert_eq!(outline(&square), vec![vec![(0, 0), (2, 0), (2, 2), (0, 2)]]);
+
+        // A plus sign: one twelve-cornered polygon around the center pixel
+        let circle = Brush { size: 3, shape: BrushShape::Circle };
+        let polygons = outline(&circle);
+        assert_eq!(polygons.len(), 1);
+        assert_eq!(polygons[0].len(), 12);
+        assert!(polygons[0].contains(&(0, -1)) && polygons[0].contains(&(2, 1)));
+
+        // Diagonal pixels only touch at corners
+        let diagonal = Brush { size: 3, shape: BrushShape::Diagonal };
+        assert_eq!(outline(&diagonal).len(), 3);
+        assert!(outline(&diagonal).iter().all(|polygon| polygon.len() == 4));
+    }
+
+    #[test]
+    fn test_bitmap_brush() {
+        // An L: three pixels down the left, one to the right at the bottom
+        let mut pixels = PixelBuffer::new(2, 3);
+        for (x, y) in [(0, 0), (0, 1), (0, 2), (1, 2)] {
+            pixels.set_pixel(x, y, [10, 20, 30, 255]).unwrap();
+        }
+        let brush = BitmapBrush::from_pixels(&pixels).unwrap();
+        assert_eq!(brush.offsets(), vec![(0, -1), (0, 0), (0, 1), (1, 1)]);
+        assert_eq!(brush.to_pixels([10, 20, 30, 255]), pixels);
+
+        let mut buffer = PixelBuffer::new(4, 4);
+        stamp(&mut buffer, 0, 0, [255, 0, 0, 255], &brush).unwrap();
+        assert_eq!(buffer.pixels().filter(|p| p[3] > 0).count(), 3);
+
+        assert!(BitmapBrush::from_pixels(&PixelBuffer::new(2, 2)).is_err());
+        assert!(BitmapBrush::from_pixels(&PixelBuffer::new(MAX_BRUSH_SIZE + 1, 1)).is_err());
+    }
+}
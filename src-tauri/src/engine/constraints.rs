@@ -0,0 +1,169 @@
+// Hardware color constraints
+// Retro consoles limit how many colors a picture may use, overall and within
+// each attribute cell. A document can be checked against one of these
+// profiles, or have it enforced so edits that break the rules are refused.
+// Fully transparent pixels are the backdrop and never count as a color.
+use super::pixel_buffer::PixelBuffer;
+use crate::error::{AipixError, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HardwareProfile {
+    /// Game Boy: four shades
+    GameBoy,
+    /// NES background: a backdrop plus four 3-color palettes, one per 16x16 area
+    Nes,
+    /// C64 hi-res bitmap: 16 colors, two per 8x8 cell
+    C64,
+}
+
+/// Colors allowed per `width` x `height` cell, counted from the canvas top-left
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CellRule {
+    pub width: u32,
+    pub height: u32,
+    pub max_colors: usize,
+}
+
+impl HardwareProfile {
+    /// Colors allowed across the whole canvas
+    pub fn max_colors(self) -> usize {
+        match self {
+            HardwareProfile::GameBoy => 4,
+            HardwareProfile::Nes => 13,
+            HardwareProfile::C64 => 16,
+        }
+    }
+
+    pub fn cell_rule(self) -> Option<CellRule> {
+        match self {
+            HardwareProfile::GameBoy => None,
+            HardwareProfile::Nes => Some(CellRule { width: 16, height: 16, max_colors: 4 }),
+            HardwareProfile::C64 => Some(CellRule { width: 8, height: 8, max_colors: 2 }),
+        }
+    }
+}
+
+/// A document's hardware profile and whether it is enforced or only reported
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ColorConstraints {
+    pub profile: HardwareProfile,
+    #[serde(default)]
+    pub enforce: bool, // Refuse edits that add violations
+}
+
+/// A region using more colors than its profile allows
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct ConstraintViolation {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32, // The whole canvas for the overall limit, else one cell
+    pub height: u32,
+    pub colors: usize,
+    pub limit: usize,
+}
+
+fn count_colors(buffer: &PixelBuffer, x: u32, y: u32, width: u32, height: u32) -> usize {
+    let mut colors = HashSet::new();
+    for py in y..y + height {
+        for px in x..x + width {
+            if let Some(color) = buffer.get_pixel(px, py).filter(|color| color[3] > 0) {
+                colors.insert(color);
+            }
+        }
+    }
+    colors.len()
+}
+
+/// Every violation of `profile` on `buffer`, the overall limit first and then cells row by row
+pub fn check(buffer: &PixelBuffer, profile: HardwareProfile) -> Vec<ConstraintViolation> {
+    let mut violations = Vec::new();
+    let colors = count_colors(buffer, 0, 0, buffer.width, buffer.height);
+    if colors > profile.max_colors() {
+        violations.push(ConstraintViolation {
+            x: 0,
+            y: 0,
+            width: buffer.width,
+            height: buffer.height,
+            colors,
+            limit: profile.max_colors(),
+        });
+    }
+
+    if let Some(rule) = profile.cell_rule() {
+        for y in (0..buffer.height).step_by(rule.height as usize) {
+            for x in (0..buffer.width).step_by(rule.width as usize) {
+                let (width, height) = (rule.width.min(buffer.width - x), rule.height.min(buffer.height - y));
+                let colors = count_colors(buffer, x, y, width, height);
+                if colors > rule.max_colors {
+                    violations.push(ConstraintViolation { x, y, width, height, colors, limit: rule.max_colors });
+                }
+            }
+        }
+    }
+    violations
+}
+
+/// Refuse a change from `before` to `after` that breaks `profile` where it held, or breaks it further
+pub fn check_edit(before: &PixelBuffer, after: &PixelBuffer, profile: HardwareProfile) -> Result<()> {
+    let existing = check(before, profile);
+    let region = |v: &ConstraintViolation| (v.x, v.y, v.width, v.height);
+    let added = check(after, profile).into_iter().find(|violation| {
+        !existing.iter().any(|old| region(old) == region(violation) && old.colors >= violation.colors)
+    });
+
+    match added {
+        Some(violation) => Err(AipixError::InvalidInput(format!(
+            "The edit uses {} colors at {},{} ({}x{}) where the {:?} profile allows {}",
+            violation.colors, violation.x, violation.y, violation.width, violation.height, profile, violation.limit
+        ))),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shade(level: u8) -> [u8; 4] {
+        [level, level, level, 255]
+    }
+
+    #[test]
+    fn test_overall_limit() {
+        let mut buffer = PixelBuffer::new(8, 1);
+        for x in 0..4 {
+            buffer.set_pixel(x, 0, shade(x as u8 * 60)).unwrap();
+        }
+        assert!(check(&buffer, HardwareProfile::GameBoy).is_empty());
+
+        let before = buffer.clone();
+        buffer.set_pixel(7, 0, shade(250)).unwrap();
+        let violations = check(&buffer, HardwareProfile::GameBoy);
+        assert_eq!(violations, vec![ConstraintViolation { x: 0, y: 0, width: 8, height: 1, colors: 5, limit: 4 }]);
+        assert!(check_edit(&before, &buffer, HardwareProfile::GameBoy).is_err());
+
+        // Already broken, but not made worse
+        let mut recolored = buffer.clone();
+        recolored.set_pixel(7, 0, shade(0)).unwrap();
+        recolored.set_pixel(6, 0, shade(250)).unwrap();
+        assert!(check_edit(&buffer, &recolored, HardwareProfile::GameBoy).is_ok());
+    }
+
+    #[test]
+    fn test_cell_limit() {
+        // Three colors in the first 8x8 cell, two in the second (which is cut short)
+        let mut buffer = PixelBuffer::new(12, 8);
+        buffer.set_pixel(0, 0, shade(10)).unwrap();
+        buffer.set_pixel(1, 0, shade(20)).unwrap();
+        buffer.set_pixel(7, 7, shade(30)).unwrap();
+        buffer.set_pixel(8, 0, shade(10)).unwrap();
+        buffer.set_pixel(11, 7, shade(40)).unwrap();
+
+        let violations = check(&buffer, HardwareProfile::C64);
+        assert_eq!(violations, vec![ConstraintViolation { x: 0, y: 0, width: 8, height: 8, colors: 3, limit: 2 }]);
+        assert!(check(&buffer, HardwareProfile::Nes).is_empty());
+    }
+}
@@ -0,0 +1,116 @@
+// Palette cycling
+// Animates a run of palette entries by rotating their colors over time, the
+// classic way to make water flow or fire flicker without redrawing a pixel.
+// Cycles only change how the canvas is shown; its pixels keep their colors.
+use super::palette::Palette;
+use crate::error::{AipixError, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Fastest cycle in steps per second
+pub const MAX_CYCLE_RATE: f32 = 60.0;
+
+/// Palette entries `start..=end` shifting one place every 1/`rate` seconds
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PaletteCycle {
+    pub name: String,
+    pub start: usize,
+    pub end: usize, // Inclusive
+    pub rate: f32,  // Steps per second
+    #[serde(default)]
+    pub reverse: bool, // Colors move towards lower indices
+}
+
+impl PaletteCycle {
+    pub fn validate(&self) -> Result<()> {
+        if self.name.trim().is_empty() {
+            return Err(AipixError::InvalidInput("Palette cycle name cannot be empty".to_string()));
+        }
+        if self.start >= self.end {
+            return Err(AipixError::InvalidInput("A palette cycle needs at least two entries".to_string()));
+        }
+        if !(self.rate > 0.0 && self.rate <= MAX_CYCLE_RATE) {
+            return Err(AipixError::InvalidInput(format!(
+                "Palette cycle rate must be above 0 and at most {} steps per second",
+                MAX_CYCLE_RATE
+            )));
+        }
+        Ok(())
+    }
+
+    /// Colors of the cycled entries `steps` steps in; `None` if the range runs past the palette
+    pub fn colors_at(&self, palette: &Palette, steps: u64) -> Option<Vec<[u8; 4]>> {
+        let colors = palette.colors.get(self.start..=self.end)?;
+        let len = colors.len() as u64;
+        let shift = (steps % len) as usize;
+        let mut shifted = colors.to_vec();
+        if self.reverse {
+            shifted.rotate_left(shift);
+        } else {
+            shifted.rotate_right(shift);
+        }
+        Some(shifted)
+    }
+
+    /// Whole steps taken `elapsed` seconds after the cycle started
+    pub fn steps(&self, elapsed: f64) -> u64 {
+        (elapsed.max(0.0) * self.rate as f64) as u64
+    }
+}
+
+/// The color each cycled palette color is shown as, `elapsed` seconds in
+///
+/// Colors that stay put are left out. Cycles running past the end of the
+/// palette (e.g. after entries were removed) are skipped; a color in more
+/// than one cycle follows the first.
+pub fn cycled_colors(palette: &Palette, cycles: &[PaletteCycle], elapsed: f64) -> HashMap<[u8; 4], [u8; 4]> {
+    let mut shown = HashMap::new();
+    for cycle in cycles {
+        let Some(colors) = cycle.colors_at(palette, cycle.steps(elapsed)) else { continue };
+        for (original, color) in palette.colors[cycle.start..=cycle.end].iter().zip(colors) {
+            if *original != color {
+                shown.entry(*original).or_insert(color);
+            }
+        }
+    }
+    shown
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: [u8; 4] = [255, 0, 0, 255];
+    const GREEN: [u8; 4] = [0, 255, 0, 255];
+    const BLUE: [u8; 4] = [0, 0, 255, 255];
+    const WHITE: [u8; 4] = [255, 255, 255, 255];
+
+    fn cycle(start: usize, end: usize, reverse: bool) -> PaletteCycle {
+        PaletteCycle { name: "water".to_string(), start, end, rate: 4.0, reverse }
+    }
+
+    #[test]
+    fn test_colors_shift_along_range() {
+        let palette = Palette::from_colors(vec![WHITE, RED, GREEN, BLUE]);
+        assert_eq!(cycle(1, 3, false).colors_at(&palette, 1), Some(vec![BLUE, RED, GREEN]));
+        assert_eq!(cycle(1, 3, true).colors_at(&palette, 1), Some(vec![GREEN, BLUE, RED]));
+        assert_eq!(cycle(1, 3, false).colors_at(&palette, 3), Some(vec![RED, GREEN, BLUE]));
+        assert_eq!(cycle(2, 4, false).colors_at(&palette, 1), None);
+
+        // 4 steps per second: half a second in is two steps
+        let shown = cycled_colors(&palette, &[cycle(1, 3, false)], 0.5);
+        assert_eq!(shown.get(&RED), Some(&GREEN));
+        assert_eq!(shown.get(&BLUE), Some(&RED));
+        assert!(!shown.contains_key(&WHITE));
+        assert!(cycled_colors(&palette, &[cycle(1, 3, false)], 0.2).is_empty());
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(cycle(1, 3, false).validate().is_ok());
+        assert!(cycle(2, 2, false).validate().is_err());
+        assert!(PaletteCycle { rate: 0.0, ..cycle(1, 3, false) }.validate().is_err());
+        assert!(PaletteCycle { rate: f32::NAN, ..cycle(1, 3, false) }.validate().is_err());
+        assert!(PaletteCycle { name: " ".to_string(), ..cycle(1, 3, false) }.validate().is_err());
+    }
+}
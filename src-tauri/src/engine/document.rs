@@ -0,0 +1,161 @@
+// Open document state
+// Everything the editor keeps in memory for one project, locked as a unit
+use super::constraints::{self, ColorConstraints};
+use super::history::CanvasHistory;
+use super::lasso::LassoPath;
+use super::pixel_buffer::PixelBuffer;
+use super::renderer::Rect;
+use super::tools::{self, BlendMode, Selection, SelectionBounds, SnapGrid};
+use crate::error::Result;
+
+/// What a tool run through `Document::paint` changed
+#[derive(Debug, Clone, Copy)]
+pub enum Painted {
+    /// Nothing was restored, so the tool's own operation describes the edit
+    Freely,
+    /// Bounds of the pixels changed once blended and with transparency or unselected pixels restored, if any
+    Locked(Option<SelectionBounds>),
+}
+
+#[derive(Clone)]
+pub struct Document {
+    pub history: CanvasHistory,
+    pub selection: Option<Selection>,
+    pub grid: Option<SnapGrid>, // Snapping is on while a grid is set
+    pub preserve_transparency: bool, // Tools may only recolor existing pixels
+    pub constraints: Option<ColorConstraints>, // Hardware color limits checked while drawing
+    pub lasso: Option<LassoPath>, // Lasso selection being drawn, until it is closed
+}
+
+impl Document {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            history: CanvasHistory::new(width, height),
+            selection: None,
+            grid: None,
+            preserve_transparency: false,
+            constraints: None,
+            lasso: None,
+        }
+    }
+
+    /// Run a tool on the canvas, enforcing `preserve_transparency` and any enforced constraints
+    ///
+    /// An edit breaking the constraints is undone and refused.
+    pub fn paint<T>(&mut self, tool: impl FnOnce(&mut PixelBuffer) -> Result<T>) -> Result<(T, Painted)> {
+        self.paint_masked(false, None, tool)
+    }
+
+    /// Run a drawing tool on the canvas: as `paint`, also keeping it inside the selection if there is one
+    ///
+    /// For tools that paint where the pointer goes (pencil, shapes, fills);
+    /// ones that place or transform content use `paint`.
+    pub fn draw<T>(&mut self, tool: impl FnOnce(&mut PixelBuffer) -> Result<T>) -> Result<(T, Painted)> {
+        self.paint_masked(true, None, tool)
+    }
+
+    /// `draw` with the tool's color composited onto the canvas by `blend` instead of replacing it
+    ///
+    /// With a mode the tool also runs once on a probe copy of the canvas,
+    /// which tells the pixels it painted from ones it left alone.
+    pub fn draw_blended<T>(
+        &mut self,
+        blend: Option<BlendMode>,
+        mut tool: impl FnMut(&mut PixelBuffer) -> Result<T>,
+    ) -> Result<(T, Painted)> {
+        let Some(mode) = blend else {
+            return self.paint_masked(true, None, tool);
+        };
+        let mut probe = tools::blend_probe(&self.history.buffer);
+        tool(&mut probe)?;
+        self.paint_masked(true, Some((mode, probe)), tool)
+    }
+
+    fn paint_masked<T>(
+        &mut self,
+        masked: bool,
+        blend: Option<(BlendMode, PixelBuffer)>,
+        tool: impl FnOnce(&mut PixelBuffer) -> Result<T>,
+    ) -> Result<(T, Painted)> {
+        let enforced = self.constraints.filter(|c| c.enforce).map(|c| c.profile);
+        let mask = self.selection.as_ref().filter(|selection| masked && !selection.is_empty());
+        let _span = tracing::trace_span!(
+            "paint",
+            masked = mask.is_some(),
+            blend = ?blend.as_ref().map(|(mode, _)| mode),
+            preserve_transparency = self.preserve_transparency,
+        )
+        .entered();
+        let buffer = &mut self.history.buffer;
+        if !self.preserve_transparency && mask.is_none() && blend.is_none() && enforced.is_none() {
+            return Ok((tool(buffer)?, Painted::Freely));
+        }
+
+        let before = buffer.clone();
+        let result = tool(buffer);
+        let mut painted = Painted::Freely;
+        if let Some((mode, probe)) = blend {
+            painted = Painted::Locked(tools::blend_changes(&before, buffer, &probe, mode));
+        }
+        if self.preserve_transparency {
+            painted = Painted::Locked(tools::preserve_transparency(&before, buffer));
+        }
+        if let Some(mask) = mask {
+            painted = Painted::Locked(tools::clip_to_selection(&before, buffer, mask));
+        }
+        if let Some(profile) = enforced {
+            if let Err(e) = constraints::check_edit(&before, buffer, profile) {
+                tracing::debug!(error = %e, "edit refused by color constraints");
+                *buffer = before;
+                return Err(e);
+            }
+        }
+        Ok((result?, painted))
+    }
+
+    /// Undo the last step; returns the region to redraw as `CanvasHistory::undo` does
+    ///
+    /// The selection isn't part of the history, so when the step changed the
+    /// canvas size (an upscale, say) it starts over empty at the restored size.
+    pub fn undo(&mut self) -> Result<Option<Rect>> {
+        let changed = self.history.undo()?;
+        self.fit_selection();
+        Ok(changed)
+    }
+
+    /// Redo the last undone step, with the selection handled as for `undo`
+    pub fn redo(&mut self) -> Result<Option<Rect>> {
+        let changed = self.history.redo()?;
+        self.fit_selection();
+        Ok(changed)
+    }
+
+    fn fit_selection(&mut self) {
+        let (width, height) = (self.history.buffer.width, self.history.buffer.height);
+        if let Some(selection) = self.selection.as_mut().filter(|s| (s.width, s.height) != (width, height)) {
+            tracing::debug!(width, height, "selection cleared for the restored canvas size");
+            *selection = Selection::new(width, height);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_undo_resize_fits_selection() {
+        let mut document = Document::new(2, 2);
+        document.selection = Some(Selection::new(2, 2));
+        document.history.push_state();
+        document.history.buffer = PixelBuffer::new(4, 4);
+        document.selection = Some(Selection::new(4, 4));
+
+        document.undo().unwrap();
+        let selection = document.selection.as_ref().unwrap();
+        assert_eq!((selection.width, selection.height, selection.mask.len()), (2, 2, 4));
+
+        document.redo().unwrap();
+        assert_eq!(document.selection.as_ref().unwrap().mask.len(), 16);
+    }
+}
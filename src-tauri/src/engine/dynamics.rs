@@ -0,0 +1,116 @@
+// Brush dynamics
+// Maps stylus pressure to brush size and opacity through adjustable curves
+use crate::error::{AipixError, Result};
+use serde::{Deserialize, Serialize};
+
+/// Piecewise-linear response curve from pressure (0-1) to a scale factor (0-1)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PressureCurve {
+    pub points: Vec<(f32, f32)>, // (pressure, output), sorted by pressure
+}
+
+impl PressureCurve {
+    pub fn linear() -> Self {
+        Self { points: vec![(0.0, 0.0), (1.0, 1.0)] }
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        let in_range = |v: f32| (0.0..=1.0).contains(&v);
+        if self.points.len() < 2
+            || !self.points.iter().all(|&(x, y)| in_range(x) && in_range(y))
+            || !self.points.windows(2).all(|pair| pair[0].0 < pair[1].0)
+        {
+            return Err(AipixError::InvalidInput(
+                "Pressure curve needs at least two points in 0-1, sorted by pressure".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Output for `pressure`; outside the curve's points the nearest end is used
+    pub fn apply(&self, pressure: f32) -> f32 {
+        let pressure = pressure.clamp(0.0, 1.0);
+        let (Some(&first), Some(&last)) = (self.points.first(), self.points.last()) else {
+            return pressure;
+        };
+        if pressure <= first.0 {
+            return first.1;
+        }
+        for pair in self.points.windows(2) {
+            let ((x0, y0), (x1, y1)) = (pair[0], pair[1]);
+            if pressure <= x1 {
+                return y0 + (y1 - y0) * (pressure - x0) / (x1 - x0);
+            }
+        }
+        last.1
+    }
+}
+
+/// Which brush properties follow pressure; `None` leaves a property constant
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BrushDynamics {
+    pub size: Option<PressureCurve>,
+    pub opacity: Option<PressureCurve>,
+}
+
+impl Default for BrushDynamics {
+    fn default() -> Self {
+        Self { size: Some(PressureCurve::linear()), opacity: None }
+    }
+}
+
+impl BrushDynamics {
+    pub fn validate(&self) -> Result<()> {
+        for curve in [&self.size, &self.opacity].into_iter().flatten() {
+            curve.validate()?;
+        }
+        Ok(())
+    }
+
+    /// Size at `pressure`, never thinner than one pixel
+    pub fn size(&self, base: f32, pressure: f32) -> f32 {
+        match &self.size {
+            Some(curve) => (base * curve.apply(pressure)).max(1.0),
+            None => base,
+        }
+    }
+
+    pub fn opacity(&self, base: f32, pressure: f32) -> f32 {
+        match &self.opacity {
+            Some(curve) => base * curve.apply(pressure),
+            None => base,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pressure_curve() {
+        let soft = PressureCurve { points: vec![(0.0, 0.2), (0.5, 0.8), (1.0, 1.0)] };
+        assert!(soft.validate().is_ok());
+        assert_eq!(soft.apply(0.0), 0.2);
+        assert!((soft.apply(0.25) - 0.5).abs() < 1e-6);
+        assert!((soft.apply(0.75) - 0.9).abs() < 1e-6);
+        assert_eq!(soft.apply(2.0), 1.0);
+
+        let unsorted = PressureCurve { points: vec![(0.5, 0.0), (0.2, 1.0)] };
+        assert!(unsorted.validate().is_err());
+        assert!(PressureCurve { points: vec![(0.0, 0.0)] }.validate().is_err());
+    }
+
+    #[test]
+    fn test_brush_dynamics() {
+        let dynamics = BrushDynamics::default();
+        assert_eq!(dynamics.size(8.0, 0.5), 4.0);
+        assert_eq!(dynamics.size(8.0, 0.0), 1.0);
+        assert_eq!(dynamics.opacity(0.8, 0.1), 0.8);
+
+        let faded = BrushDynamics { size: None, opacity: Some(PressureCurve::linear()) };
+        assert_eq!(faded.size(8.0, 0.5), 8.0);
+        assert_eq!(faded.opacity(0.8, 0.5), 0.4);
+    }
+}
@@ -0,0 +1,346 @@
+// Pixel filters
+// Kernel-based filters applied to the pixels under a brush. Each dab reads
+// the neighbourhood before writing, so the result doesn't depend on the order
+// pixels are visited in. Colors are weighted by alpha, so blurring next to
+// transparency fades an edge out instead of darkening it.
+//
+// Whole-image filters (`PixelFilter`) go through `apply_masked`, the one place
+// that decides which pixels a `FilterScope` lets them touch.
+use super::brush::Footprint;
+use super::pixel_buffer::PixelBuffer;
+use super::progress::{Progress, Untracked};
+use super::tools::{
+    hsl_to_rgb, line_points, mix_coverage, position_hash, rgb_to_hsl, DitherPattern, Selection, SelectionBounds,
+    FULLY_SELECTED,
+};
+use crate::error::{AipixError, Result};
+
+/// 3x3 Gaussian blur weights
+pub const GAUSSIAN_3X3: [[u32; 3]; 3] = [[1, 2, 1], [2, 4, 2], [1, 2, 1]];
+
+/// `kernel` applied around (x, y), repeating the edge pixels past the border
+pub fn convolve_at(buffer: &PixelBuffer, x: u32, y: u32, kernel: &[[u32; 3]; 3]) -> [u8; 4] {
+    let (mut weights, mut alpha, mut rgb) = (0u64, 0u64, [0u64; 3]);
+    for (ky, row) in kernel.iter().enumerate() {
+        for (kx, &weight) in row.iter().enumerate() {
+            let sx = (x as i64 + kx as i64 - 1).clamp(0, buffer.width as i64 - 1) as u32;
+            let sy = (y as i64 + ky as i64 - 1).clamp(0, buffer.height as i64 - 1) as u32;
+            let Some(color) = buffer.get_pixel(sx, sy) else { continue };
+            let weight = weight as u64;
+            weights += weight;
+            alpha += weight * color[3] as u64;
+            for channel in 0..3 {
+                rgb[channel] += weight * color[3] as u64 * color[channel] as u64;
+            }
+        }
+    }
+    if alpha == 0 {
+        return [0, 0, 0, 0];
+    }
+    let channel = |total: u64| ((total + alpha / 2) / alpha) as u8;
+    [channel(rgb[0]), channel(rgb[1]), channel(rgb[2]), ((alpha + weights / 2) / weights) as u8]
+}
+
+fn mix(from: [u8; 4], to: [u8; 4], amount: f32) -> [u8; 4] {
+    std::array::from_fn(|i| (from[i] as f32 + (to[i] as f32 - from[i] as f32) * amount).round() as u8)
+}
+
+/// Blur the pixels under a footprint of `offsets` centered on (x, y), moving each `strength` (0-1) of the way
+///
+/// Clipped to the buffer; as for the pencil, a center off the canvas is an error.
+pub fn blur_dab(buffer: &mut PixelBuffer, x: i32, y: i32, offsets: &[(i32, i32)], strength: f32) -> Result<()> {
+    if x < 0 || y < 0 || buffer.get_pixel(x as u32, y as u32).is_none() {
+        return Err(AipixError::OutOfBounds);
+    }
+    let blurred: Vec<(u32, u32, [u8; 4])> = offsets
+        .iter()
+        .map(|(dx, dy)| (x + dx, y + dy))
+        .filter(|&(px, py)| px >= 0 && py >= 0 && (px as u32) < buffer.width && (py as u32) < buffer.height)
+        .filter_map(|(px, py)| {
+            let (px, py) = (px as u32, py as u32);
+            let color = buffer.get_pixel(px, py)?;
+            Some((px, py, mix(color, convolve_at(buffer, px, py, &GAUSSIAN_3X3), strength)))
+        })
+        .collect();
+    buffer.set_pixels(&blurred)?;
+    Ok(())
+}
+
+/// Blur brush - `brush` dabbed along a line from (x0, y0) to (x1, y1)
+///
+/// Each dab blurs what the previous ones left, so slow strokes soften more.
+pub fn blur_line(
+    buffer: &mut PixelBuffer,
+    (x0, y0): (i32, i32),
+    (x1, y1): (i32, i32),
+    brush: &impl Footprint,
+    strength: f32,
+) -> Result<()> {
+    if !(0.0..=1.0).contains(&strength) {
+        return Err(AipixError::InvalidInput("Blur strength must be between 0 and 1".to_string()));
+    }
+    let offsets = brush.offsets();
+    for (x, y) in line_points(x0, y0, x1, y1) {
+        blur_dab(buffer, x, y, &offsets, strength)?;
+    }
+    Ok(())
+}
+
+/// Which pixels a `PixelFilter` touches
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FilterScope {
+    /// The canvas inside the selection, or all of it without one
+    #[default]
+    Selection,
+    /// The whole canvas, a document's one layer, ignoring the selection
+    Layer,
+}
+
+impl FilterScope {
+    /// The selection a filter in this scope is clipped to, if any
+    pub fn mask(self, selection: Option<&Selection>) -> Option<&Selection> {
+        match self {
+            FilterScope::Selection => selection.filter(|selection| !selection.is_empty()),
+            FilterScope::Layer => None,
+        }
+    }
+}
+
+/// Filters that recolor every pixel they are applied to
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum PixelFilter {
+    /// Rotate hue by `hue` degrees (-180 to 180) and add `saturation` and `lightness` (-1 to 1)
+    Hsl { hue: f32, saturation: f32, lightness: f32 },
+    /// Stretch `black`..`white` to the full range, bending midtones by `gamma` (above 1 brightens)
+    Levels { black: u8, white: u8, gamma: f32 },
+    /// Reduce each channel to `levels` steps, ordering the in-between values with `pattern`
+    Dither { pattern: DitherPattern, levels: u8 },
+    /// Paint `color` on transparent pixels next to an opaque one
+    Outline { color: [u8; 4] },
+    /// Move each channel by up to `amount`; the same `seed` gives the same noise
+    Noise { amount: u8, seed: u64 },
+}
+
+impl PixelFilter {
+    pub fn validate(&self) -> Result<()> {
+        let invalid = |message: &str| Err(AipixError::InvalidInput(message.to_string()));
+        match *self {
+            PixelFilter::Hsl { hue, .. } if !(-180.0..=180.0).contains(&hue) => {
+                invalid("Hue shift must be between -180 and 180 degrees")
+            }
+            PixelFilter::Hsl { saturation, lightness, .. }
+                if !(-1.0..=1.0).contains(&saturation) || !(-1.0..=1.0).contains(&lightness) =>
+            {
+                invalid("Saturation and lightness must be between -1 and 1")
+            }
+            PixelFilter::Levels { black, white, .. } if black >= white => invalid("Black level must be below white"),
+            PixelFilter::Levels { gamma, .. } if !(0.1..=10.0).contains(&gamma) => {
+                invalid("Gamma must be between 0.1 and 10")
+            }
+            PixelFilter::Dither { levels, .. } if levels < 2 => invalid("Dither needs at least 2 levels"),
+            _ => Ok(()),
+        }
+    }
+
+    /// The new color of (x, y), reading neighbours from `source`
+    fn pixel(&self, source: &PixelBuffer, x: u32, y: u32, color: [u8; 4]) -> [u8; 4] {
+        let [r, g, b, a] = color;
+        match *self {
+            _ if a == 0 && !matches!(self, PixelFilter::Outline { .. }) => color,
+            PixelFilter::Hsl { hue, saturation, lightness } => {
+                let (h, s, l) = rgb_to_hsl([r, g, b]);
+                let [r, g, b] = hsl_to_rgb(h + hue, s + saturation, l + lightness);
+                [r, g, b, a]
+            }
+            PixelFilter::Levels { black, white, gamma } => {
+                let level = |c: u8| {
+                    let t = (c as f32 - black as f32) / (white as f32 - black as f32);
+                    (t.clamp(0.0, 1.0).powf(1.0 / gamma) * 255.0).round() as u8
+                };
+                [level(r), level(g), level(b), a]
+            }
+            PixelFilter::Dither { pattern, levels } => {
+                let (steps, threshold) = ((levels - 1) as f32, pattern.threshold(x, y));
+                let step = |c: u8| {
+                    let value = c as f32 / 255.0 * steps;
+                    let level = value.floor() + if value.fract() > threshold { 1.0 } else { 0.0 };
+                    (level / steps * 255.0).round() as u8
+                };
+                [step(r), step(g), step(b), a]
+            }
+            PixelFilter::Outline { color: outline } => {
+                let opaque = |dx: i64, dy: i64| {
+                    let (nx, ny) = (x as i64 + dx, y as i64 + dy);
+                    nx >= 0 && ny >= 0 && source.get_pixel(nx as u32, ny as u32).is_some_and(|c| c[3] > 0)
+                };
+                if a == 0 && (opaque(-1, 0) || opaque(1, 0) || opaque(0, -1) || opaque(0, 1)) {
+                    outline
+                } else {
+                    color
+                }
+            }
+            PixelFilter::Noise { amount, seed } => {
+                // One byte of the position's hash per channel
+                let bytes = position_hash(seed, x, y).to_le_bytes();
+                let span = 2 * amount as i32 + 1;
+                let jitter = |c: u8, byte: u8| (c as i32 + byte as i32 % span - amount as i32).clamp(0, 255) as u8;
+                [jitter(r, bytes[0]), jitter(g, bytes[1]), jitter(b, bytes[2]), a]
+            }
+        }
+    }
+}
+
+/// Run `filter` over `buffer`, only where `mask` is selected when given
+///
+/// Every pixel reads the unfiltered buffer, so outlines don't grow into
+/// themselves. Returns the bounds of the pixels that changed.
+fn apply_masked(
+    buffer: &mut PixelBuffer,
+    mask: Option<&Selection>,
+    filter: &PixelFilter,
+    progress: &dyn Progress,
+) -> Result<Option<SelectionBounds>> {
+    let region = match mask {
+        Some(mask) => mask.bounds,
+        None if buffer.width == 0 || buffer.height == 0 => None,
+        None => Some(SelectionBounds { min_x: 0, max_x: buffer.width - 1, min_y: 0, max_y: buffer.height - 1 }),
+    };
+    let Some(region) = region else { return Ok(None) };
+
+    let source = buffer.clone();
+    let (mut pixels, mut changed) = (Vec::new(), None::<SelectionBounds>);
+    let rows = region.min_y..=region.max_y.min(buffer.height.saturating_sub(1));
+    let total = rows.clone().count() as u64;
+    for y in rows {
+        progress.update((y - region.min_y) as u64, total)?;
+        for x in region.min_x..=region.max_x.min(buffer.width.saturating_sub(1)) {
+            let coverage = mask.map_or(FULLY_SELECTED, |mask| mask.coverage(x, y));
+            let Some(color) = source.get_pixel(x, y).filter(|_| coverage > 0) else { continue };
+            let filtered = mix_coverage(color, filter.pixel(&source, x, y, color), coverage);
+            if filtered != color {
+                pixels.push((x, y, filtered));
+                let point = SelectionBounds::point(x, y);
+                changed = Some(changed.map_or(point, |changed| changed.union(point)));
+            }
+        }
+    }
+    progress.update(total, total)?;
+    buffer.set_pixels(&pixels)?;
+    Ok(changed)
+}
+
+/// Apply `filter` to a single-canvas image, returning the bounds of what changed
+pub fn apply_filter(
+    buffer: &mut PixelBuffer,
+    selection: Option<&Selection>,
+    scope: FilterScope,
+    filter: &PixelFilter,
+) -> Result<Option<SelectionBounds>> {
+    apply_filter_with_progress(buffer, selection, scope, filter, &Untracked)
+}
+
+/// Like `apply_filter`, reporting progress (and checking for cancellation) per row
+///
+/// The buffer is only written once every row is done, so a cancelled filter leaves it as it was.
+pub fn apply_filter_with_progress(
+    buffer: &mut PixelBuffer,
+    selection: Option<&Selection>,
+    scope: FilterScope,
+    filter: &PixelFilter,
+    progress: &dyn Progress,
+) -> Result<Option<SelectionBounds>> {
+    filter.validate()?;
+    apply_masked(buffer, scope.mask(selection), filter, progress)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::Brush;
+
+    const RED: [u8; 4] = [255, 0, 0, 255];
+
+    #[test]
+    fn test_blur_fades_edges() {
+        let mut buffer = PixelBuffer::new(3, 3);
+        buffer.set_pixel(1, 1, RED).unwrap();
+
+        // A quarter of the Gaussian weight is the center; color stays red
+        assert_eq!(convolve_at(&buffer, 1, 1, &GAUSSIAN_3X3), [255, 0, 0, 64]);
+        assert_eq!(convolve_at(&buffer, 0, 0, &GAUSSIAN_3X3), [255, 0, 0, 16]);
+
+        blur_line(&mut buffer, (1, 1), (1, 1), &Brush::default(), 0.5).unwrap();
+        assert_eq!(buffer.get_pixel(1, 1), Some([255, 0, 0, 160]));
+        assert_eq!(buffer.get_pixel(0, 0), Some([0, 0, 0, 0])); // Outside the brush
+        assert!(blur_line(&mut buffer, (3, 0), (3, 0), &Brush::default(), 0.5).is_err());
+    }
+
+    #[test]
+    fn test_blur_keeps_flat_areas() {
+        let mut buffer = PixelBuffer::new(4, 4);
+        buffer.fill_rect(0, 0, 4, 4, RED);
+        let brush = Brush { size: 3, ..Brush::default() };
+        blur_line(&mut buffer, (0, 0), (3, 3), &brush, 1.0).unwrap();
+        assert!(buffer.pixels().all(|pixel| pixel == RED));
+    }
+
+    #[test]
+    fn test_filter_scopes() {
+        let mut buffer = PixelBuffer::new(4, 4);
+        buffer.fill_rect(0, 0, 4, 4, [100, 100, 100, 255]);
+        let mut selection = Selection::new(4, 4);
+        selection.select_pixel(1, 1, true);
+        selection.update_bounds();
+        let levels = PixelFilter::Levels { black: 0, white: 200, gamma: 1.0 };
+
+        apply_filter(&mut buffer, Some(&selection), FilterScope::Selection, &levels).unwrap();
+        assert_eq!(buffer.get_pixel(1, 1), Some([128, 128, 128, 255]));
+        assert_eq!(buffer.get_pixel(0, 0), Some([100, 100, 100, 255]));
+
+        apply_filter(&mut buffer, Some(&selection), FilterScope::Layer, &levels).unwrap();
+        assert_eq!(buffer.get_pixel(0, 0), Some([128, 128, 128, 255]));
+    }
+
+    #[test]
+    fn test_outline_and_noise() {
+        let mut buffer = PixelBuffer::new(3, 3);
+        buffer.set_pixel(1, 1, RED).unwrap();
+        let outline = PixelFilter::Outline { color: [0, 0, 0, 255] };
+        let changed = apply_filter(&mut buffer, None, FilterScope::Layer, &outline).unwrap().unwrap();
+        assert_eq!((changed.min_x, changed.max_x, changed.min_y, changed.max_y), (0, 2, 0, 2));
+        assert_eq!(buffer.get_pixel(1, 0), Some([0, 0, 0, 255]));
+        assert_eq!(buffer.get_pixel(0, 0), Some([0, 0, 0, 0])); // Diagonal only
+        assert_eq!(buffer.get_pixel(1, 1), Some(RED));
+
+        let noise = PixelFilter::Noise { amount: 8, seed: 7 };
+        let mut noisy = buffer.clone();
+        apply_filter(&mut noisy, None, FilterScope::Layer, &noise).unwrap();
+        let mut again = buffer.clone();
+        apply_filter(&mut again, None, FilterScope::Layer, &noise).unwrap();
+        assert_eq!(noisy, again);
+        assert_eq!(noisy.get_pixel(0, 0), Some([0, 0, 0, 0])); // Transparent pixels stay put
+        assert!(PixelFilter::Dither { pattern: DitherPattern::Bayer2, levels: 1 }.validate().is_err());
+    }
+
+    #[test]
+    fn test_cancelled_filter_leaves_buffer() {
+        struct CancelAfter(u64);
+        impl Progress for CancelAfter {
+            fn update(&self, done: u64, _total: u64) -> Result<()> {
+                if done >= self.0 { Err(AipixError::Cancelled) } else { Ok(()) }
+            }
+        }
+
+        let mut buffer = PixelBuffer::new(4, 4);
+        buffer.fill_rect(0, 0, 4, 4, [100, 100, 100, 255]);
+        let levels = PixelFilter::Levels { black: 0, white: 200, gamma: 1.0 };
+        let result = apply_filter_with_progress(&mut buffer, None, FilterScope::Layer, &levels, &CancelAfter(2));
+        assert!(matches!(result, Err(AipixError::Cancelled)));
+        assert_eq!(buffer.get_pixel(0, 0), Some([100, 100, 100, 255])); // First rows were filtered but not written
+
+        apply_filter_with_progress(&mut buffer, None, FilterScope::Layer, &levels, &CancelAfter(5)).unwrap();
+        assert_eq!(buffer.get_pixel(3, 3), Some([128, 128, 128, 255]));
+    }
+}
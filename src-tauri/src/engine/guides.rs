@@ -0,0 +1,67 @@
+// Guides
+// Horizontal and vertical reference lines placed by the user; shape tools
+// and selections snap to them when they come within a few pixels
+use serde::{Deserialize, Serialize};
+
+/// Snap distance in pixels for projects that haven't set one
+pub const DEFAULT_SNAP_THRESHOLD: u32 = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GuideOrientation {
+    Horizontal, // A row: snaps y
+    Vertical,   // A column: snaps x
+}
+
+/// A guide along the pixel boundary at `position`, which may lie off-canvas
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Guide {
+    pub id: String,
+    pub orientation: GuideOrientation,
+    pub position: i32,
+}
+
+impl Guide {
+    pub fn new(orientation: GuideOrientation, position: i32) -> Self {
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            orientation,
+            position,
+        }
+    }
+}
+
+/// Position of the nearest guide of `orientation` within `threshold` pixels of `value`
+pub fn nearest_guide(guides: &[Guide], orientation: GuideOrientation, value: i32, threshold: u32) -> Option<i32> {
+    guides
+        .iter()
+        .filter(|guide| guide.orientation == orientation)
+        .map(|guide| guide.position)
+        .filter(|position| position.abs_diff(value) <= threshold)
+        .min_by_key(|position| position.abs_diff(value))
+}
+
+/// Move (x, y) onto the nearest guide on each axis within `threshold` pixels
+pub fn snap_to_guides(guides: &[Guide], x: i32, y: i32, threshold: u32) -> (i32, i32) {
+    (
+        nearest_guide(guides, GuideOrientation::Vertical, x, threshold).unwrap_or(x),
+        nearest_guide(guides, GuideOrientation::Horizontal, y, threshold).unwrap_or(y),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snap_to_guides() {
+        let guides = vec![
+            Guide::new(GuideOrientation::Vertical, 10),
+            Guide::new(GuideOrientation::Vertical, 14),
+            Guide::new(GuideOrientation::Horizontal, 32),
+        ];
+        assert_eq!(snap_to_guides(&guides, 13, 29, 3), (14, 32));
+        assert_eq!(snap_to_guides(&guides, 11, 28, 3), (10, 28));
+        assert_eq!(snap_to_guides(&guides, 20, 32, 3), (20, 32));
+        assert_eq!(snap_to_guides(&guides, 11, 31, 0), (11, 31));
+    }
+}
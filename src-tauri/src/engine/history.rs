@@ -1,68 +1,98 @@
 // Canvas history system for undo/redo functionality
+use super::palette::Palette;
 use super::pixel_buffer::PixelBuffer;
+use super::renderer::Rect;
+use crate::error::{AipixError, Result};
 
 const MAX_HISTORY_SIZE: usize = 50; // Maximum number of undo states
 
+/// One undo step: everything `CanvasHistory` restores
+#[derive(Clone)]
+struct Snapshot {
+    buffer: PixelBuffer, // Kept whole so resizes can be undone
+    palette: Palette,
+    stroke_end: Option<(u32, u32)>,
+}
+
 #[derive(Clone)]
 pub struct CanvasHistory {
     pub buffer: PixelBuffer,
-    undo_stack: Vec<Vec<u8>>, // Stack of previous states (RGBA data)
-    redo_stack: Vec<Vec<u8>>, // Stack of undone states
+    pub palette: Palette, // Undone with the pixels, since palette merges remap them
+    pub stroke_end: Option<(u32, u32)>, // Last pencil/eraser point, which a connected point or line continues from
+    undo_stack: Vec<Snapshot>, // Stack of previous states
+    redo_stack: Vec<Snapshot>, // Stack of undone states
 }
 
 impl CanvasHistory {
     pub fn new(width: u32, height: u32) -> Self {
         Self {
             buffer: PixelBuffer::new(width, height),
+            palette: Palette::new(),
+            stroke_end: None,
             undo_stack: Vec::new(),
             redo_stack: Vec::new(),
         }
     }
 
+    fn snapshot(&self) -> Snapshot {
+        Snapshot { buffer: self.buffer.clone(), palette: self.palette.clone(), stroke_end: self.stroke_end }
+    }
+
+    /// Make `snapshot` the current state, returning the state it replaces
+    fn restore(&mut self, snapshot: Snapshot) -> Snapshot {
+        Snapshot {
+            buffer: std::mem::replace(&mut self.buffer, snapshot.buffer),
+            palette: std::mem::replace(&mut self.palette, snapshot.palette),
+            stroke_end: std::mem::replace(&mut self.stroke_end, snapshot.stroke_end),
+        }
+    }
+
     /// Save current state to undo stack before making changes
     pub fn push_state(&mut self) {
         // Save current buffer data to undo stack
-        let snapshot = self.buffer.data.clone();
+        let snapshot = self.snapshot();
         self.undo_stack.push(snapshot);
 
         // Limit history size to prevent memory issues
         if self.undo_stack.len() > MAX_HISTORY_SIZE {
             self.undo_stack.remove(0);
+            tracing::trace!(limit = MAX_HISTORY_SIZE, "oldest undo state dropped");
         }
 
         // Clear redo stack when new action is performed
         self.redo_stack.clear();
+        tracing::trace!(undo = self.undo_stack.len(), "history state pushed");
     }
 
     /// Undo last action
-    pub fn undo(&mut self) -> Result<(), String> {
+    ///
+    /// Returns the region whose pixels changed, if any, so only it needs redrawing.
+    pub fn undo(&mut self) -> Result<Option<Rect>> {
         if let Some(previous_state) = self.undo_stack.pop() {
-            // Save current state to redo stack
-            let current_state = self.buffer.data.clone();
+            // Save current state to redo stack, restoring the previous one
+            let current_state = self.restore(previous_state);
+            let changed = changed_region(&current_state.buffer, &self.buffer);
             self.redo_stack.push(current_state);
+            tracing::trace!(?changed, undo = self.undo_stack.len(), "undo");
 
-            // Restore previous state
-            self.buffer.data = previous_state;
-
-            Ok(())
+            Ok(changed)
         } else {
-            Err("Nothing to undo".to_string())
+            Err(AipixError::InvalidState("Nothing to undo".to_string()))
         }
     }
 
-    /// Redo last undone action
-    pub fn redo(&mut self) -> Result<(), String> {
+    /// Redo last undone action, returning the changed region as for undo
+    pub fn redo(&mut self) -> Result<Option<Rect>> {
         if let Some(next_state) = self.redo_stack.pop() {
-            // Save current state to undo stack
-            let current_state = self.buffer.data.clone();
+            // Save current state to undo stack, restoring the next one
+            let current_state = self.restore(next_state);
+            let changed = changed_region(&current_state.buffer, &self.buffer);
             self.undo_stack.push(current_state);
+            tracing::trace!(?changed, redo = self.redo_stack.len(), "redo");
 
-            // Restore next state
-            self.buffer.data = next_state;
-
-            Ok(())
+            Ok(changed)
         } else {
-            Err("Nothing to redo".to_string())
+            Err(AipixError::InvalidState("Nothing to redo".to_string()))
         }
     }
 
@@ -86,6 +116,18 @@ impl CanvasHistory {
         self.redo_stack.len()
     }
 
+    /// Bytes held by the undo and redo snapshots
+    ///
+    /// Each snapshot only counts the strips it doesn't share with the next
+    /// state towards the current one, as unchanged strips are stored once.
+    pub fn snapshot_bytes(&self) -> (usize, usize) {
+        let total = |stack: &[Snapshot]| {
+            let next = stack.iter().skip(1).map(|snapshot| &snapshot.buffer).chain([&self.buffer]);
+            stack.iter().zip(next).map(|(snapshot, next)| snapshot.buffer.unshared_bytes(next)).sum()
+        };
+        (total(&self.undo_stack), total(&self.redo_stack))
+    }
+
     /// Clear all history
     pub fn clear_history(&mut self) {
         self.undo_stack.clear();
@@ -93,6 +135,12 @@ impl CanvasHistory {
     }
 }
 
+/// Bounding rect of the pixels that differ between two canvases; all of `after` if the size changed
+fn changed_region(before: &PixelBuffer, after: &PixelBuffer) -> Option<Rect> {
+    let (min_x, min_y, max_x, max_y) = after.changed_bounds(before)?;
+    Some(Rect::new(min_x as i32, min_y as i32, (max_x - min_x + 1) as i32, (max_y - min_y + 1) as i32))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -124,6 +172,81 @@ mod tests {
         assert_eq!(history.buffer.get_pixel(6, 6).unwrap(), [0, 255, 0, 255]);
     }
 
+    #[test]
+    fn test_undo_returns_changed_region() {
+        let mut history = CanvasHistory::new(10, 10);
+
+        history.push_state();
+        history.buffer.set_pixel(2, 7, [255, 0, 0, 255]).unwrap();
+        history.buffer.set_pixel(5, 3, [255, 0, 0, 255]).unwrap();
+        let region = history.undo().unwrap().unwrap();
+        assert_eq!((region.x, region.y, region.width, region.height), (2, 3, 4, 5));
+        let region = history.redo().unwrap().unwrap();
+        assert_eq!((region.x, region.y, region.width, region.height), (2, 3, 4, 5));
+
+        // A palette-only step changes no pixels
+        history.push_state();
+        history.palette.colors.push([1, 2, 3, 255]);
+        assert!(history.undo().unwrap().is_none());
+
+        // A resize redraws everything
+        history.push_state();
+        history.buffer = PixelBuffer::new(4, 4);
+        let region = history.undo().unwrap().unwrap();
+        assert_eq!((region.width, region.height), (10, 10));
+    }
+
+    #[test]
+    fn test_undo_restores_stroke_end() {
+        let mut history = CanvasHistory::new(4, 4);
+        history.push_state();
+        history.stroke_end = Some((1, 1));
+        history.push_state();
+        history.stroke_end = Some((3, 2));
+
+        history.undo().unwrap();
+        assert_eq!(history.stroke_end, Some((1, 1)));
+        history.undo().unwrap();
+        assert_eq!(history.stroke_end, None);
+        history.redo().unwrap();
+        assert_eq!(history.stroke_end, Some((1, 1)));
+    }
+
+    #[test]
+    fn test_undo_restores_palette() {
+        let mut history = CanvasHistory::new(2, 2);
+        history.palette.colors.push([1, 2, 3, 255]);
+
+        history.push_state();
+        history.palette.colors.clear();
+        history.buffer.set_pixel(0, 0, [1, 2, 3, 255]).unwrap();
+
+        history.undo().unwrap();
+        assert_eq!(history.palette.colors, vec![[1, 2, 3, 255]]);
+        assert_eq!(history.buffer.get_pixel(0, 0), Some([0, 0, 0, 0]));
+
+        history.redo().unwrap();
+        assert!(history.palette.is_empty());
+    }
+
+    #[test]
+    fn test_snapshots_share_unchanged_strips() {
+        let mut history = CanvasHistory::new(8, 192);
+        history.buffer.clear([255, 0, 0, 255]);
+        let strip_bytes = history.buffer.byte_len() / 3; // Three strips of 64 rows
+
+        history.push_state();
+        history.buffer.set_pixel(0, 0, [0, 0, 255, 255]).unwrap();
+        assert_eq!(history.snapshot_bytes(), (strip_bytes, 0));
+
+        history.push_state();
+        history.buffer.set_pixel(0, 150, [0, 0, 255, 255]).unwrap();
+        assert_eq!(history.snapshot_bytes(), (2 * strip_bytes, 0));
+
+        history.undo().unwrap();
+        assert_eq!(history.snapshot_bytes(), (strip_bytes, strip_bytes));
+    }
+
     #[test]
     fn test_history_limit() {
         let mut history = CanvasHistory::new(10, 10);
@@ -0,0 +1,105 @@
+// Hitboxes
+// Named collision shapes attached to animation frames (hitboxes, hurtboxes,
+// ground contacts...), in pixels from the frame's top-left
+use crate::error::{AipixError, Result};
+use serde::{Deserialize, Serialize};
+
+/// Polygons are capped so a stray import can't bloat the project metadata
+pub const MAX_POLYGON_POINTS: usize = 64;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum HitboxShape {
+    Rect { x: i32, y: i32, width: u32, height: u32 },
+    Polygon { points: Vec<(i32, i32)> },
+}
+
+impl HitboxShape {
+    /// Bounding box as (x, y, width, height)
+    pub fn bounds(&self) -> (i32, i32, u32, u32) {
+        match self {
+            HitboxShape::Rect { x, y, width, height } => (*x, *y, *width, *height),
+            HitboxShape::Polygon { points } => {
+                let min_x = points.iter().map(|p| p.0).min().unwrap_or(0);
+                let min_y = points.iter().map(|p| p.1).min().unwrap_or(0);
+                let max_x = points.iter().map(|p| p.0).max().unwrap_or(0);
+                let max_y = points.iter().map(|p| p.1).max().unwrap_or(0);
+                (min_x, min_y, max_x.abs_diff(min_x), max_y.abs_diff(min_y))
+            }
+        }
+    }
+
+    /// The shape over an image scaled up by `factor`
+    pub fn scaled(&self, factor: u32) -> Self {
+        let f = factor as i32;
+        match self {
+            HitboxShape::Rect { x, y, width, height } => HitboxShape::Rect {
+                x: x * f,
+                y: y * f,
+                width: width * factor,
+                height: height * factor,
+            },
+            HitboxShape::Polygon { points } => HitboxShape::Polygon {
+                points: points.iter().map(|&(x, y)| (x * f, y * f)).collect(),
+            },
+        }
+    }
+}
+
+/// A named shape on one frame; names are unique per frame
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Hitbox {
+    pub name: String,
+    pub shape: HitboxShape,
+}
+
+impl Hitbox {
+    /// Shapes may extend past the frame, but must enclose some area
+    pub fn validate(&self) -> Result<()> {
+        if self.name.trim().is_empty() {
+            return Err(AipixError::InvalidInput("Hitbox name cannot be empty".to_string()));
+        }
+        match &self.shape {
+            HitboxShape::Rect { width, height, .. } if *width == 0 || *height == 0 => {
+                Err(AipixError::InvalidInput("Hitbox rectangle must not be empty".to_string()))
+            }
+            HitboxShape::Polygon { points } if points.len() < 3 || points.len() > MAX_POLYGON_POINTS => {
+                Err(AipixError::InvalidInput(format!(
+                    "Hitbox polygon must have between 3 and {} points",
+                    MAX_POLYGON_POINTS
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hitbox_validation() {
+        let rect = Hitbox { name: "hurt".to_string(), shape: HitboxShape::Rect { x: -2, y: 4, width: 8, height: 12 } };
+        assert!(rect.validate().is_ok());
+        assert_eq!(rect.shape.bounds(), (-2, 4, 8, 12));
+
+        let triangle = Hitbox {
+            name: "hit".to_string(),
+            shape: HitboxShape::Polygon { points: vec![(0, 0), (6, 2), (1, 5)] },
+        };
+        assert!(triangle.validate().is_ok());
+        assert_eq!(triangle.shape.bounds(), (0, 0, 6, 5));
+
+        let line = Hitbox { name: "hit".to_string(), shape: HitboxShape::Polygon { points: vec![(0, 0), (6, 2)] } };
+        assert!(line.validate().is_err());
+        let unnamed = Hitbox { name: " ".to_string(), ..rect };
+        assert!(unnamed.validate().is_err());
+    }
+
+    #[test]
+    fn test_shape_serialization() {
+        let shape: HitboxShape = serde_json::from_str(r#"{"type":"rect","x":1,"y":2,"width":3,"height":4}"#).unwrap();
+        assert_eq!(shape, HitboxShape::Rect { x: 1, y: 2, width: 3, height: 4 });
+    }
+}
@@ -0,0 +1,90 @@
+// In-between frames
+// Rough tweening between two sprite sheet keyframes: the new frame either
+// cross-fades one keyframe into the other, or slides the first keyframe's
+// drawing part of the way to where the second one's sits.
+use super::pixel_buffer::PixelBuffer;
+use serde::Deserialize;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InbetweenMode {
+    /// Interpolate every pixel's color and opacity
+    #[default]
+    Blend,
+    /// Move the first keyframe's drawing towards the second's position
+    Translate,
+}
+
+/// The frame at `t` (0 to 1) of the way from `from` to `to`, both of the same size
+///
+/// Translation goes by the top-left of each keyframe's visible pixels, so it
+/// suits a drawing that moves without changing much.
+pub fn inbetween(from: &PixelBuffer, to: &PixelBuffer, t: f32, mode: InbetweenMode) -> PixelBuffer {
+    let t = t.clamp(0.0, 1.0);
+    match mode {
+        InbetweenMode::Blend => blend(from, to, t),
+        InbetweenMode::Translate => {
+            let (dx, dy) = match (from.content_bounds(), to.content_bounds()) {
+                (Some((x0, y0, ..)), Some((x1, y1, ..))) => (x1 as f32 - x0 as f32, y1 as f32 - y0 as f32),
+                _ => (0.0, 0.0),
+            };
+            let mut frame = PixelBuffer::new(from.width, from.height);
+            frame.blit(from, (dx * t).round() as i32, (dy * t).round() as i32);
+            frame
+        }
+    }
+}
+
+fn blend(from: &PixelBuffer, to: &PixelBuffer, t: f32) -> PixelBuffer {
+    let mut frame = PixelBuffer::new(from.width, from.height);
+    for y in 0..frame.height {
+        for x in 0..frame.width {
+            let (a, b) = (from.get_pixel(x, y).unwrap_or_default(), to.get_pixel(x, y).unwrap_or_default());
+            let (weight_a, weight_b) = (a[3] as f32 * (1.0 - t), b[3] as f32 * t);
+            let alpha = weight_a + weight_b;
+            if alpha == 0.0 {
+                continue;
+            }
+
+            // Weighted by opacity, so a transparent pixel's RGB doesn't tint the mix
+            let channel = |i: usize| ((a[i] as f32 * weight_a + b[i] as f32 * weight_b) / alpha).round() as u8;
+            let _ = frame.set_pixel(x, y, [channel(0), channel(1), channel(2), alpha.round() as u8]);
+        }
+    }
+    frame
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: [u8; 4] = [255, 0, 0, 255];
+    const BLUE: [u8; 4] = [0, 0, 255, 255];
+
+    #[test]
+    fn test_blend() {
+        let mut from = PixelBuffer::new(2, 1);
+        from.set_pixel(0, 0, RED).unwrap();
+        from.set_pixel(1, 0, RED).unwrap();
+        let mut to = PixelBuffer::new(2, 1);
+        to.set_pixel(0, 0, BLUE).unwrap();
+
+        let frame = inbetween(&from, &to, 0.5, InbetweenMode::Blend);
+        assert_eq!(frame.get_pixel(0, 0).unwrap(), [128, 0, 128, 255]);
+        assert_eq!(frame.get_pixel(1, 0).unwrap(), [255, 0, 0, 128]);
+        assert_eq!(inbetween(&from, &to, 1.0, InbetweenMode::Blend), to);
+    }
+
+    #[test]
+    fn test_translate() {
+        let mut from = PixelBuffer::new(8, 8);
+        from.set_pixel(0, 0, RED).unwrap();
+        from.set_pixel(1, 0, RED).unwrap();
+        let mut to = PixelBuffer::new(8, 8);
+        to.set_pixel(4, 2, BLUE).unwrap();
+
+        let frame = inbetween(&from, &to, 0.5, InbetweenMode::Translate);
+        assert_eq!(frame.content_bounds(), Some((2, 1, 3, 1)));
+        assert_eq!(frame.get_pixel(2, 1).unwrap(), RED);
+    }
+}
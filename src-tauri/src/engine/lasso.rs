@@ -0,0 +1,90 @@
+// Incremental lasso selections
+//
+// A freehand lasso is built up one point at a time while the user drags.
+// Each new point only traces the segment from the previous one, so the
+// outline shown during the drag costs as much as the pointer moved; the
+// polygon is filled once, when the lasso is closed.
+use super::tools::{line_points, select_lasso_add_point, Selection, SelectionBounds, SelectionMode};
+use crate::error::{AipixError, Result};
+use serde::Serialize;
+
+/// Most points one lasso may have
+pub const MAX_LASSO_POINTS: usize = 65536;
+
+/// A lasso being drawn, as a polygon that is closed back to its first point
+#[derive(Debug, Clone)]
+pub struct LassoPath {
+    points: Vec<(i32, i32)>,
+    bounds: (i32, i32, i32, i32), // min_x, min_y, max_x, max_y of the points
+}
+
+/// Outline pixels added by one lasso point
+#[derive(Debug, Clone, Serialize)]
+pub struct LassoPreview {
+    pub points: Vec<(i32, i32)>, // From the previous point to the new one; may lie off the canvas
+    pub point_count: usize,
+    pub bounds: Option<SelectionBounds>, // The whole path clipped to the canvas
+}
+
+impl LassoPath {
+    pub fn new(x: i32, y: i32) -> Self {
+        LassoPath { points: vec![(x, y)], bounds: (x, y, x, y) }
+    }
+
+    pub fn points(&self) -> &[(i32, i32)] {
+        &self.points
+    }
+
+    /// Extend the path to `x`, `y`, returning the outline pixels it adds
+    ///
+    /// Repeating the last point adds nothing.
+    pub fn add_point(&mut self, x: i32, y: i32) -> Result<Vec<(i32, i32)>> {
+        let (last_x, last_y) = *self.points.last().expect("a lasso path always has its first point");
+        if (last_x, last_y) == (x, y) {
+            return Ok(Vec::new());
+        }
+        if self.points.len() >= MAX_LASSO_POINTS {
+            return Err(AipixError::InvalidInput(format!("A lasso can have at most {} points", MAX_LASSO_POINTS)));
+        }
+
+        self.points.push((x, y));
+        let (min_x, min_y, max_x, max_y) = self.bounds;
+        self.bounds = (min_x.min(x), min_y.min(y), max_x.max(x), max_y.max(y));
+        Ok(line_points(last_x, last_y, x, y).into_iter().skip(1).collect())
+    }
+
+    /// The path's bounds within `selection`'s canvas, if it overlaps it
+    pub fn bounds(&self, selection: &Selection) -> Option<SelectionBounds> {
+        let (min_x, min_y, max_x, max_y) = self.bounds;
+        selection.clip_bounds(min_x as i64, min_y as i64, max_x as i64, max_y as i64)
+    }
+
+    /// Fill the closed path into `selection`; paths of fewer than three points select nothing
+    pub fn close(&self, selection: &mut Selection, mode: SelectionMode) {
+        select_lasso_add_point(selection, &self.points, mode);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_incremental_lasso() {
+        let mut lasso = LassoPath::new(2, 2);
+        assert_eq!(lasso.add_point(5, 2).unwrap(), vec![(3, 2), (4, 2), (5, 2)]);
+        assert!(lasso.add_point(5, 2).unwrap().is_empty());
+        assert_eq!(lasso.add_point(5, 5).unwrap().len(), 3);
+        lasso.add_point(2, 5).unwrap();
+        assert_eq!(lasso.points().len(), 4);
+
+        let mut incremental = Selection::new(8, 8);
+        lasso.close(&mut incremental, SelectionMode::Replace);
+        let mut whole = Selection::new(8, 8);
+        select_lasso_add_point(&mut whole, lasso.points(), SelectionMode::Replace);
+        assert_eq!(incremental.mask, whole.mask);
+
+        let bounds = lasso.bounds(&incremental).unwrap();
+        assert_eq!((bounds.min_x, bounds.min_y, bounds.max_x, bounds.max_y), (2, 2, 5, 5));
+    }
+}
@@ -0,0 +1,114 @@
+// Canvas size limits
+// Checked before any canvas-sized allocation so a bad request fails with a
+// clear error instead of overflowing `width * height * 4` or exhausting memory
+use crate::error::{AipixError, Result};
+use serde::{Deserialize, Serialize};
+
+/// Default longest side, in pixels
+pub const DEFAULT_MAX_SIDE: u32 = 16384;
+/// Default pixel budget (256 MB of RGBA)
+pub const DEFAULT_MAX_PIXELS: u64 = 8192 * 8192;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct CanvasLimits {
+    pub max_width: u32,
+    pub max_height: u32,
+    pub max_pixels: u64, // width * height
+}
+
+impl Default for CanvasLimits {
+    fn default() -> Self {
+        Self {
+            max_width: DEFAULT_MAX_SIDE,
+            max_height: DEFAULT_MAX_SIDE,
+            max_pixels: DEFAULT_MAX_PIXELS,
+        }
+    }
+}
+
+impl CanvasLimits {
+    /// Check that a `width` x `height` canvas may be allocated
+    pub fn validate(&self, width: u32, height: u32) -> Result<()> {
+        if width == 0 || height == 0 {
+            return Err(AipixError::InvalidInput(format!(
+                "Canvas size {}x{} is empty; width and height must be at least 1",
+                width, height
+            )));
+        }
+        if width > self.max_width {
+            return Err(AipixError::InvalidInput(format!(
+                "Canvas width {} exceeds the maximum of {}",
+                width, self.max_width
+            )));
+        }
+        if height > self.max_height {
+            return Err(AipixError::InvalidInput(format!(
+                "Canvas height {} exceeds the maximum of {}",
+                height, self.max_height
+            )));
+        }
+
+        let pixels = width as u64 * height as u64;
+        if pixels > self.max_pixels {
+            return Err(AipixError::InvalidInput(format!(
+                "Canvas size {}x{} ({} pixels) exceeds the maximum of {} pixels",
+                width, height, pixels, self.max_pixels
+            )));
+        }
+        Ok(())
+    }
+
+    /// Like `validate`, for signed sizes coming from the renderer API
+    pub fn validate_signed(&self, width: i32, height: i32) -> Result<()> {
+        if width < 0 || height < 0 {
+            return Err(AipixError::InvalidInput(format!(
+                "Canvas size {}x{} is negative",
+                width, height
+            )));
+        }
+        self.validate(width as u32, height as u32)
+    }
+
+    /// Reject limits that could themselves overflow an RGBA allocation
+    pub fn check_sane(&self) -> Result<()> {
+        if self.max_width == 0 || self.max_height == 0 || self.max_pixels == 0 {
+            return Err(AipixError::InvalidInput(
+                "Canvas limits must be at least 1".to_string(),
+            ));
+        }
+
+        let max_bytes = self.max_pixels.checked_mul(4);
+        if !matches!(max_bytes, Some(bytes) if bytes <= isize::MAX as u64) {
+            return Err(AipixError::InvalidInput(format!(
+                "A limit of {} pixels can't be allocated on this platform",
+                self.max_pixels
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        let limits = CanvasLimits::default();
+        assert!(limits.validate(64, 64).is_ok());
+        assert!(limits.validate(8192, 8192).is_ok());
+        assert!(limits.validate(0, 64).is_err());
+        assert!(limits.validate(DEFAULT_MAX_SIDE + 1, 1).is_err());
+        assert!(limits.validate(16384, 16384).is_err()); // Over the pixel budget
+        assert!(limits.validate_signed(-1, 64).is_err());
+        assert!(limits.validate(u32::MAX, u32::MAX).is_err());
+    }
+
+    #[test]
+    fn test_check_sane() {
+        assert!(CanvasLimits::default().check_sane().is_ok());
+
+        let limits = CanvasLimits { max_pixels: u64::MAX, ..CanvasLimits::default() };
+        assert!(limits.check_sane().is_err());
+    }
+}
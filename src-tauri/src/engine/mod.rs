@@ -5,12 +5,51 @@ pub mod pixel_buffer;
 pub mod layer;
 pub mod animation;
 pub mod tools;
+pub mod filters;
+pub mod brush;
 pub mod history;
+pub mod document;
+pub mod palette;
+pub mod cycling;
+pub mod constraints;
+pub mod lasso;
+pub mod upscale;
+pub mod limits;
+pub mod tilemap;
+pub mod slice;
+pub mod sheet;
+pub mod hitbox;
+pub mod dynamics;
+pub mod stabilizer;
+pub mod guides;
+pub mod rotsprite;
+pub mod inbetween;
+pub mod transform;
+pub mod progress;
 pub mod renderer;  // Native Skia renderer (replaces WebGL)
 
 pub use pixel_buffer::PixelBuffer;
 pub use layer::Layer;
 pub use animation::Frame;
 pub use history::CanvasHistory;
-pub use tools::{Selection, SelectionMode, SelectionBounds};
+pub use document::{Document, Painted};
+pub use palette::{Palette, PaletteSort};
+pub use cycling::PaletteCycle;
+pub use constraints::{ColorConstraints, HardwareProfile};
+pub use lasso::{LassoPath, LassoPreview};
+pub use upscale::UpscaleAlgorithm;
+pub use limits::CanvasLimits;
+pub use tilemap::{Tilemap, TilemapDocument, Tileset};
+pub use slice::{NineSlice, Pivot, Slice};
+pub use sheet::{FrameSelection, FrameTag, SheetLayout};
+pub use hitbox::{Hitbox, HitboxShape};
+pub use dynamics::{BrushDynamics, PressureCurve};
+pub use stabilizer::Stabilizer;
+pub use guides::{Guide, GuideOrientation};
+pub use transform::SelectionTransform;
+pub use inbetween::InbetweenMode;
+pub use filters::{FilterScope, PixelFilter};
+pub use progress::{Progress, Untracked};
+pub use brush::{BitmapBrush, Brush, BrushShape};
+pub use tools::{AreaSample, BlendMode, Dither, DitherPattern, Gradient, GradientKind, Jumble, LineProfile, Selection, SelectionMode, SelectionBounds, Shade, ShadeDirection, SnapGrid, Snapping};
 pub use renderer::{PixelRenderer, DirtyRegion, Rect};
@@ -0,0 +1,302 @@
+// Project color palette
+use super::pixel_buffer::PixelBuffer;
+use super::tools::{color_distance, hex_to_rgba, rgb_to_hsl};
+use crate::error::{AipixError, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Prefix of a tool color given as a palette index, e.g. `palette:3`
+pub const INDEX_PREFIX: &str = "palette:";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PaletteSort {
+    /// Grays first (dark to light), then around the color wheel from red
+    Hue,
+    /// Dark to light
+    Luminance,
+    /// Most used on the canvas first
+    Frequency,
+}
+
+/// Rec. 709 relative luminance of an RGB color
+fn luminance(color: [u8; 4]) -> f32 {
+    0.2126 * color[0] as f32 + 0.7152 * color[1] as f32 + 0.0722 * color[2] as f32
+}
+
+/// Colors a picture uses, compared by RGB as when replacing colors; transparent pixels don't count
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColorUsage {
+    pub colors: usize,
+    pub off_palette: Vec<([u8; 3], usize)>, // Colors not in the palette with their pixel counts, rarest first
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Palette {
+    pub colors: Vec<[u8; 4]>,
+}
+
+impl Palette {
+    pub fn new() -> Self {
+        Self { colors: Vec::new() }
+    }
+
+    pub fn from_colors(colors: Vec<[u8; 4]>) -> Self {
+        Self { colors }
+    }
+
+    pub fn len(&self) -> usize {
+        self.colors.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.colors.is_empty()
+    }
+
+    pub fn get(&self, index: usize) -> Option<[u8; 4]> {
+        self.colors.get(index).copied()
+    }
+
+    pub fn contains(&self, color: [u8; 4]) -> bool {
+        self.colors.contains(&color)
+    }
+
+    /// Index of the closest palette entry (RGB distance); transparent pixels are never remapped
+    pub fn nearest_index(&self, color: [u8; 4]) -> Option<usize> {
+        self.colors
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, entry)| {
+                let dr = entry[0] as i32 - color[0] as i32;
+                let dg = entry[1] as i32 - color[1] as i32;
+                let db = entry[2] as i32 - color[2] as i32;
+                dr * dr + dg * dg + db * db
+            })
+            .map(|(index, _)| index)
+    }
+
+    /// Snap a color to the closest palette entry, keeping fully transparent pixels as-is
+    pub fn quantize(&self, color: [u8; 4]) -> [u8; 4] {
+        if color[3] == 0 {
+            return color;
+        }
+        self.nearest_index(color)
+            .map(|index| self.colors[index])
+            .unwrap_or(color)
+    }
+
+    /// Insert colors at `index` (clamped to the end), or append when no index is given
+    pub fn insert_colors(&mut self, index: Option<usize>, colors: &[[u8; 4]]) {
+        let at = index.unwrap_or(self.colors.len()).min(self.colors.len());
+        self.colors.splice(at..at, colors.iter().copied());
+    }
+
+    pub fn remove(&mut self, index: usize) -> Option<[u8; 4]> {
+        if index < self.colors.len() {
+            Some(self.colors.remove(index))
+        } else {
+            None
+        }
+    }
+
+    /// Reorder the entries; `canvas` supplies pixel counts for frequency order
+    ///
+    /// The sort is stable, so equal entries keep their relative order.
+    pub fn sort(&mut self, order: PaletteSort, canvas: &PixelBuffer) {
+        match order {
+            PaletteSort::Hue => self.colors.sort_by(|a, b| {
+                let (hue_a, saturation_a, lightness_a) = rgb_to_hsl([a[0], a[1], a[2]]);
+                let (hue_b, saturation_b, lightness_b) = rgb_to_hsl([b[0], b[1], b[2]]);
+                (saturation_a > 0.0, hue_a, lightness_a)
+                    .partial_cmp(&(saturation_b > 0.0, hue_b, lightness_b))
+                    .unwrap()
+            }),
+            PaletteSort::Luminance => self.colors.sort_by(|a, b| luminance(*a).total_cmp(&luminance(*b))),
+            PaletteSort::Frequency => {
+                // Counted by RGB, matching how colors are replaced on the canvas
+                let mut counts: HashMap<[u8; 3], usize> = HashMap::new();
+                for pixel in canvas.pixels().filter(|pixel| pixel[3] > 0) {
+                    *counts.entry([pixel[0], pixel[1], pixel[2]]).or_default() += 1;
+                }
+                let count = |color: &[u8; 4]| counts.get(&[color[0], color[1], color[2]]).copied().unwrap_or(0);
+                self.colors.sort_by_key(|color| std::cmp::Reverse(count(color)));
+            }
+        }
+    }
+
+    /// The colors `buffer` uses, and those missing from the palette
+    ///
+    /// Rarest first, since colors that slipped in by accident tend to cover
+    /// few pixels; ties are in RGB order.
+    pub fn usage(&self, buffer: &PixelBuffer) -> ColorUsage {
+        let mut counts: HashMap<[u8; 3], usize> = HashMap::new();
+        for pixel in buffer.pixels().filter(|pixel| pixel[3] > 0) {
+            *counts.entry([pixel[0], pixel[1], pixel[2]]).or_default() += 1;
+        }
+
+        let colors = counts.len();
+        let mut off_palette: Vec<([u8; 3], usize)> = counts
+            .into_iter()
+            .filter(|(rgb, _)| !self.colors.iter().any(|entry| entry[..3] == rgb[..]))
+            .collect();
+        off_palette.sort_by_key(|&(rgb, pixels)| (pixels, rgb));
+        ColorUsage { colors, off_palette }
+    }
+
+    /// Remove entries within `tolerance` (see the magic wand) of an earlier entry
+    ///
+    /// Returns each removed color with the entry it duplicated. A tolerance
+    /// of 0 removes exact duplicates only.
+    pub fn dedupe(&mut self, tolerance: u8) -> Vec<([u8; 4], [u8; 4])> {
+        let mut kept: Vec<[u8; 4]> = Vec::with_capacity(self.colors.len());
+        let mut removed = Vec::new();
+        for color in self.colors.drain(..) {
+            let duplicate = kept.iter().find(|entry| {
+                let near = tolerance > 0 && entry[3] == color[3] && color_distance(**entry, color) <= tolerance;
+                **entry == color || near
+            });
+            match duplicate {
+                Some(entry) => removed.push((color, *entry)),
+                None => kept.push(color),
+            }
+        }
+        self.colors = kept;
+        removed
+    }
+
+    /// Index of the entry exactly matching `color`, alpha included
+    pub fn index_of(&self, color: [u8; 4]) -> Option<usize> {
+        self.colors.iter().position(|&entry| entry == color)
+    }
+
+    /// A tool color: hex, or `palette:<index>` for that entry as it is now
+    ///
+    /// An index gives the entry exactly, alpha included, where a hex color
+    /// picked from the canvas would drop alpha.
+    pub fn resolve(&self, color: &str) -> Result<[u8; 4]> {
+        let Some(index) = color.strip_prefix(INDEX_PREFIX) else {
+            return hex_to_rgba(color);
+        };
+        let index: usize = index
+            .parse()
+            .map_err(|_| AipixError::InvalidInput(format!("Invalid palette index: {}", index)))?;
+        self.get(index).ok_or_else(|| {
+            AipixError::InvalidInput(format!("Palette index out of range (palette has {} colors)", self.len()))
+        })
+    }
+
+    /// Merge the entry at `remove` into the one at `keep`, removing it
+    ///
+    /// Returns the removed color and the kept one, for remapping pixels.
+    pub fn merge(&mut self, keep: usize, remove: usize) -> Result<([u8; 4], [u8; 4])> {
+        let (Some(kept), Some(_)) = (self.get(keep), self.get(remove)) else {
+            return Err(AipixError::InvalidInput(format!(
+                "Palette index out of range (palette has {} colors)",
+                self.len()
+            )));
+        };
+        if keep == remove {
+            return Err(AipixError::InvalidInput("Cannot merge a palette entry with itself".to_string()));
+        }
+        Ok((self.colors.remove(remove), kept))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quantize() {
+        let palette = Palette::from_colors(vec![[0, 0, 0, 255], [255, 255, 255, 255]]);
+
+        assert_eq!(palette.quantize([30, 20, 10, 255]), [0, 0, 0, 255]);
+        assert_eq!(palette.quantize([200, 220, 210, 255]), [255, 255, 255, 255]);
+        assert_eq!(palette.quantize([200, 220, 210, 0]), [200, 220, 210, 0]);
+        assert_eq!(Palette::new().quantize([1, 2, 3, 255]), [1, 2, 3, 255]);
+    }
+
+    #[test]
+    fn test_resolve() {
+        let palette = Palette::from_colors(vec![[0, 0, 0, 255], [10, 20, 30, 128]]);
+
+        assert_eq!(palette.resolve("palette:1").unwrap(), [10, 20, 30, 128]);
+        assert_eq!(palette.resolve("#ff0000").unwrap(), [255, 0, 0, 255]);
+        assert!(palette.resolve("palette:2").is_err());
+        assert!(palette.resolve("palette:x").is_err());
+        assert_eq!(palette.index_of([10, 20, 30, 128]), Some(1));
+        assert_eq!(palette.index_of([10, 20, 30, 255]), None);
+    }
+
+    #[test]
+    fn test_usage() {
+        let palette = Palette::from_colors(vec![[0, 0, 0, 255], [255, 255, 255, 255]]);
+        let mut buffer = PixelBuffer::new(4, 1);
+        buffer.set_pixel(0, 0, [0, 0, 0, 255]).unwrap();
+        buffer.set_pixel(1, 0, [250, 0, 0, 255]).unwrap();
+        buffer.set_pixel(2, 0, [250, 0, 0, 255]).unwrap();
+        buffer.set_pixel(3, 0, [1, 1, 1, 255]).unwrap();
+
+        let usage = palette.usage(&buffer);
+        assert_eq!(usage.colors, 3);
+        assert_eq!(usage.off_palette, vec![([1, 1, 1], 1), ([250, 0, 0], 2)]);
+        assert!(palette.usage(&PixelBuffer::new(2, 2)).off_palette.is_empty());
+    }
+
+    #[test]
+    fn test_insert_colors() {
+        let mut palette = Palette::from_colors(vec![[0, 0, 0, 255], [255, 255, 255, 255]]);
+
+        palette.insert_colors(Some(1), &[[255, 0, 0, 255]]);
+        assert_eq!(palette.get(1), Some([255, 0, 0, 255]));
+
+        palette.insert_colors(None, &[[0, 255, 0, 255]]);
+        assert_eq!(palette.get(3), Some([0, 255, 0, 255]));
+
+        // Out-of-range index appends
+        palette.insert_colors(Some(99), &[[0, 0, 255, 255]]);
+        assert_eq!(palette.len(), 5);
+    }
+
+    #[test]
+    fn test_sort() {
+        const BLACK: [u8; 4] = [0, 0, 0, 255];
+        const WHITE: [u8; 4] = [255, 255, 255, 255];
+        const RED: [u8; 4] = [255, 0, 0, 255];
+        const GREEN: [u8; 4] = [0, 255, 0, 255];
+        const BLUE: [u8; 4] = [0, 0, 255, 255];
+        let mut palette = Palette::from_colors(vec![BLUE, WHITE, GREEN, BLACK, RED]);
+        let mut canvas = PixelBuffer::new(4, 1);
+
+        palette.sort(PaletteSort::Hue, &canvas);
+        assert_eq!(palette.colors, vec![BLACK, WHITE, RED, GREEN, BLUE]);
+
+        palette.sort(PaletteSort::Luminance, &canvas);
+        assert_eq!(palette.colors, vec![BLACK, BLUE, RED, GREEN, WHITE]);
+
+        canvas.fill_rect(0, 0, 3, 1, GREEN);
+        canvas.set_pixel(3, 0, WHITE).unwrap();
+        palette.sort(PaletteSort::Frequency, &canvas);
+        assert_eq!(palette.colors, vec![GREEN, WHITE, BLACK, BLUE, RED]);
+    }
+
+    #[test]
+    fn test_dedupe_and_merge() {
+        let mut palette = Palette::from_colors(vec![
+            [10, 10, 10, 255],
+            [12, 10, 10, 255],
+            [10, 10, 10, 255],
+            [200, 0, 0, 255],
+        ]);
+
+        assert_eq!(palette.dedupe(0), vec![([10, 10, 10, 255], [10, 10, 10, 255])]);
+        assert_eq!(palette.len(), 3);
+        assert_eq!(palette.dedupe(2), vec![([12, 10, 10, 255], [10, 10, 10, 255])]);
+        assert_eq!(palette.colors, vec![[10, 10, 10, 255], [200, 0, 0, 255]]);
+
+        assert_eq!(palette.merge(1, 0).unwrap(), ([10, 10, 10, 255], [200, 0, 0, 255]));
+        assert_eq!(palette.colors, vec![[200, 0, 0, 255]]);
+        assert!(palette.merge(0, 0).is_err());
+        assert!(palette.merge(0, 1).is_err());
+    }
+}
@@ -1,50 +1,485 @@
 // Pixel buffer implementation
-// Represents a 2D grid of pixels with RGBA values
+// Represents a 2D grid of pixels with RGBA values, stored as horizontal strips
+// of rows. A strip is only allocated once something is drawn in it, and
+// clones (undo snapshots) share strips until one of them writes there.
+use crate::error::{AipixError, Result};
+use std::sync::Arc;
+
+/// Rows in each storage strip
+const STRIP_ROWS: u32 = 64;
+
+const TRANSPARENT: [u8; 4] = [0, 0, 0, 0];
 
 #[derive(Debug, Clone)]
 pub struct PixelBuffer {
     pub width: u32,
     pub height: u32,
-    pub data: Vec<u8>, // RGBA format: 4 bytes per pixel
+    strips: Vec<Option<Arc<Vec<u8>>>>, // RGBA format: 4 bytes per pixel; None until drawn in
 }
 
 impl PixelBuffer {
     pub fn new(width: u32, height: u32) -> Self {
-        let size = (width * height * 4) as usize;
         Self {
             width,
             height,
-            data: vec![0; size],
+            strips: vec![None; height.div_ceil(STRIP_ROWS) as usize],
+        }
+    }
+
+    /// Buffer holding row-major RGBA bytes, or None if they aren't `width * height` pixels
+    pub fn from_rgba(width: u32, height: u32, data: &[u8]) -> Option<Self> {
+        if data.len() as u64 != width as u64 * height as u64 * 4 {
+            return None;
+        }
+        let mut buffer = Self::new(width, height);
+        let strip_bytes = buffer.stride() * STRIP_ROWS as usize;
+        if strip_bytes > 0 {
+            for (strip, bytes) in buffer.strips.iter_mut().zip(data.chunks(strip_bytes)) {
+                if bytes.iter().any(|&byte| byte != 0) {
+                    *strip = Some(Arc::new(bytes.to_vec()));
+                }
+            }
+        }
+        Some(buffer)
+    }
+
+    /// Row-major RGBA bytes of the whole buffer
+    pub fn to_rgba(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(self.stride() * self.height as usize);
+        for (index, strip) in self.strips.iter().enumerate() {
+            match strip {
+                Some(bytes) => data.extend_from_slice(bytes),
+                None => data.resize(data.len() + self.strip_len(index), 0),
+            }
+        }
+        data
+    }
+
+    /// RGBA bytes of each pixel in row-major order
+    pub fn pixels(&self) -> impl Iterator<Item = &[u8]> + '_ {
+        self.strips.iter().enumerate().flat_map(move |(index, strip)| {
+            let blank = if strip.is_none() { self.strip_len(index) / 4 } else { 0 };
+            strip
+                .iter()
+                .flat_map(|bytes| bytes.chunks_exact(4))
+                .chain(std::iter::repeat_n(&TRANSPARENT[..], blank))
+        })
+    }
+
+    /// Mutable RGBA bytes of each pixel in row-major order
+    ///
+    /// Allocates and unshares every strip, so prefer `set_pixel` for sparse edits.
+    pub fn pixels_mut(&mut self) -> impl Iterator<Item = &mut [u8]> + '_ {
+        for index in 0..self.strips.len() {
+            self.strip_mut(index);
         }
+        self.strips.iter_mut().flatten().flat_map(|strip| Arc::make_mut(strip).chunks_exact_mut(4))
+    }
+
+    /// Bytes of pixel storage allocated
+    pub fn byte_len(&self) -> usize {
+        self.strips.iter().flatten().map(|strip| strip.len()).sum()
+    }
+
+    /// Bytes of pixel storage allocated that aren't shared with `other`
+    pub fn unshared_bytes(&self, other: &PixelBuffer) -> usize {
+        let shared = |index: usize, strip: &Arc<Vec<u8>>| {
+            matches!(other.strips.get(index), Some(Some(theirs)) if Arc::ptr_eq(strip, theirs))
+        };
+        self.strips
+            .iter()
+            .enumerate()
+            .filter_map(|(index, strip)| strip.as_ref().filter(|strip| !shared(index, strip)))
+            .map(|strip| strip.len())
+            .sum()
     }
 
     pub fn get_pixel(&self, x: u32, y: u32) -> Option<[u8; 4]> {
         if x >= self.width || y >= self.height {
             return None;
         }
-        let index = ((y * self.width + x) * 4) as usize;
-        Some([
-            self.data[index],
-            self.data[index + 1],
-            self.data[index + 2],
-            self.data[index + 3],
-        ])
+        let Some(row) = self.row(y) else {
+            return Some(TRANSPARENT);
+        };
+        let index = x as usize * 4;
+        Some([row[index], row[index + 1], row[index + 2], row[index + 3]])
     }
 
-    pub fn set_pixel(&mut self, x: u32, y: u32, color: [u8; 4]) -> Result<(), String> {
-        if x >= self.width || y >= self.height {
-            return Err("Pixel coordinates out of bounds".to_string());
+    /// Set a pixel; writing the color it already has leaves its strip shared
+    pub fn set_pixel(&mut self, x: u32, y: u32, color: [u8; 4]) -> Result<()> {
+        if self.get_pixel(x, y).ok_or(AipixError::OutOfBounds)? != color {
+            let index = x as usize * 4;
+            self.row_mut(y)[index..index + 4].copy_from_slice(&color);
         }
-        let index = ((y * self.width + x) * 4) as usize;
-        self.data[index..index + 4].copy_from_slice(&color);
         Ok(())
     }
 
+    /// Write many arbitrary pixels; all of them or, if any lies off the canvas, none
+    ///
+    /// Later entries for the same pixel win. Returns the bounding box of the
+    /// written pixels as (min_x, min_y, max_x, max_y).
+    pub fn set_pixels(&mut self, pixels: &[(u32, u32, [u8; 4])]) -> Result<Option<(u32, u32, u32, u32)>> {
+        let mut bounds: Option<(u32, u32, u32, u32)> = None;
+        for &(x, y, _) in pixels {
+            if x >= self.width || y >= self.height {
+                return Err(AipixError::OutOfBounds);
+            }
+            bounds = Some(match bounds {
+                Some((x0, y0, x1, y1)) => (x0.min(x), y0.min(y), x1.max(x), y1.max(y)),
+                None => (x, y, x, y),
+            });
+        }
+
+        for &(x, y, color) in pixels {
+            self.set_pixel(x, y, color)?;
+        }
+        Ok(bounds)
+    }
+
     pub fn clear(&mut self, color: [u8; 4]) {
+        for index in 0..self.strips.len() {
+            self.strips[index] = (color != TRANSPARENT).then(|| {
+                let mut bytes = vec![0; self.strip_len(index)];
+                fill_pixels(&mut bytes, color);
+                Arc::new(bytes)
+            });
+        }
+    }
+
+    /// Fill a rectangle with a color, clipped to the buffer
+    pub fn fill_rect(&mut self, x: u32, y: u32, width: u32, height: u32, color: [u8; 4]) {
+        let x_end = x.saturating_add(width).min(self.width);
+        let y_end = y.saturating_add(height).min(self.height);
+        if x >= x_end || y >= y_end {
+            return;
+        }
+
+        for row_y in y..y_end {
+            if color == TRANSPARENT && self.row(row_y).is_none() {
+                continue;
+            }
+            fill_pixels(&mut self.row_mut(row_y)[x as usize * 4..x_end as usize * 4], color);
+        }
+    }
+
+    /// Copy a rectangle into a new buffer, clipped to this buffer
+    ///
+    /// A rectangle starting past the right or bottom edge gives an empty buffer.
+    pub fn copy_region(&self, x: u32, y: u32, width: u32, height: u32) -> PixelBuffer {
+        let (x, y) = (x.min(self.width), y.min(self.height));
+        let x_end = x.saturating_add(width).min(self.width);
+        let y_end = y.saturating_add(height).min(self.height);
+        let mut region = PixelBuffer::new(x_end.saturating_sub(x), y_end.saturating_sub(y));
+        if region.width == 0 {
+            return region;
+        }
+
+        for row_y in 0..region.height {
+            if let Some(src) = self.row(y + row_y) {
+                region.row_mut(row_y).copy_from_slice(&src[x as usize * 4..x_end as usize * 4]);
+            }
+        }
+
+        region
+    }
+
+    /// Bounding box of the non-transparent pixels as (min_x, min_y, max_x, max_y)
+    pub fn content_bounds(&self) -> Option<(u32, u32, u32, u32)> {
+        let mut bounds: Option<(u32, u32, u32, u32)> = None;
         for y in 0..self.height {
-            for x in 0..self.width {
-                let _ = self.set_pixel(x, y, color);
+            let Some(row) = self.row(y) else { continue };
+            for (x, pixel) in row.chunks_exact(4).enumerate() {
+                if pixel[3] > 0 {
+                    let x = x as u32;
+                    bounds = Some(match bounds {
+                        Some((x0, y0, x1, y1)) => (x0.min(x), y0.min(y), x1.max(x), y1.max(y)),
+                        None => (x, y, x, y),
+                    });
+                }
             }
         }
+        bounds
+    }
+
+    /// Bounding box of the pixels that differ from `other` as (min_x, min_y, max_x, max_y)
+    ///
+    /// Strips still shared with `other` are skipped without comparing them.
+    /// Buffers of different sizes differ everywhere.
+    pub fn changed_bounds(&self, other: &PixelBuffer) -> Option<(u32, u32, u32, u32)> {
+        if (self.width, self.height) != (other.width, other.height) {
+            return (self.width > 0 && self.height > 0).then(|| (0, 0, self.width - 1, self.height - 1));
+        }
+
+        let mut bounds: Option<(u32, u32, u32, u32)> = None;
+        for (index, strips) in self.strips.iter().zip(&other.strips).enumerate() {
+            match strips {
+                (None, None) => continue,
+                (Some(mine), Some(theirs)) if Arc::ptr_eq(mine, theirs) => continue,
+                _ => {}
+            }
+
+            let first_row = index as u32 * STRIP_ROWS;
+            for y in first_row..(first_row + STRIP_ROWS).min(self.height) {
+                let (mine, theirs) = (self.row(y), other.row(y));
+                if mine == theirs {
+                    continue;
+                }
+                let pixel = |row: Option<&[u8]>, x: u32| {
+                    row.map_or(TRANSPARENT, |row| {
+                        let index = x as usize * 4;
+                        [row[index], row[index + 1], row[index + 2], row[index + 3]]
+                    })
+                };
+                let differs = |x: &u32| pixel(mine, *x) != pixel(theirs, *x);
+                let Some(first) = (0..self.width).find(differs) else { continue };
+                let last = (0..self.width).rev().find(differs).unwrap_or(first);
+                bounds = Some(match bounds {
+                    Some((x0, y0, x1, _)) => (x0.min(first), y0, x1.max(last), y),
+                    None => (first, y, last, y),
+                });
+            }
+        }
+        bounds
+    }
+
+    /// Copy `src` onto this buffer with its top-left at (x, y), replacing pixels
+    ///
+    /// Offsets may be negative or reach past the edge; only the overlap is copied.
+    pub fn blit(&mut self, src: &PixelBuffer, x: i32, y: i32) {
+        let dest_x0 = x.max(0) as i64;
+        let dest_y0 = y.max(0) as i64;
+        let dest_x1 = (x as i64 + src.width as i64).min(self.width as i64);
+        let dest_y1 = (y as i64 + src.height as i64).min(self.height as i64);
+        if dest_x0 >= dest_x1 || dest_y0 >= dest_y1 {
+            return;
+        }
+
+        let src_x0 = (dest_x0 - x as i64) as usize;
+        let span = (dest_x1 - dest_x0) as usize;
+        let dest_start = dest_x0 as usize * 4;
+
+        for dest_y in dest_y0..dest_y1 {
+            let src_row = src.row((dest_y - y as i64) as u32);
+            if src_row.is_none() && self.row(dest_y as u32).is_none() {
+                continue;
+            }
+            let dest = &mut self.row_mut(dest_y as u32)[dest_start..dest_start + span * 4];
+            match src_row {
+                Some(src_row) => dest.copy_from_slice(&src_row[src_x0 * 4..(src_x0 + span) * 4]),
+                None => dest.fill(0),
+            }
+        }
+    }
+
+    /// Picture of how this buffer differs from `other`, with the number of changed pixels
+    ///
+    /// The picture covers both buffers. Changed pixels are drawn in
+    /// `highlight` and the rest in this buffer's colors, faded to a quarter
+    /// of their opacity. Pixels outside either buffer count as changed if the
+    /// other buffer shows something there, and fully transparent pixels are
+    /// equal whatever their RGB.
+    pub fn diff(&self, other: &PixelBuffer, highlight: [u8; 4]) -> (PixelBuffer, usize) {
+        let mut picture = PixelBuffer::new(self.width.max(other.width), self.height.max(other.height));
+        let visible = |color: Option<[u8; 4]>| color.filter(|color| color[3] > 0);
+        let mut changed = 0;
+
+        for y in 0..picture.height {
+            for x in 0..picture.width {
+                let (mine, theirs) = (visible(self.get_pixel(x, y)), visible(other.get_pixel(x, y)));
+                let pixel = if mine == theirs {
+                    let Some([r, g, b, a]) = mine else { continue };
+                    [r, g, b, a / 4]
+                } else {
+                    changed += 1;
+                    highlight
+                };
+                let _ = picture.set_pixel(x, y, pixel);
+            }
+        }
+        (picture, changed)
+    }
+
+    /// Bytes in one row of pixels
+    fn stride(&self) -> usize {
+        self.width as usize * 4
+    }
+
+    /// Bytes in strip `index`; the last strip may hold fewer rows
+    fn strip_len(&self, index: usize) -> usize {
+        let rows = (self.height - index as u32 * STRIP_ROWS).min(STRIP_ROWS);
+        rows as usize * self.stride()
+    }
+
+    /// Strip `index`, allocated and unshared so it can be written
+    fn strip_mut(&mut self, index: usize) -> &mut Vec<u8> {
+        let len = self.strip_len(index);
+        Arc::make_mut(self.strips[index].get_or_insert_with(|| Arc::new(vec![0; len])))
+    }
+
+    /// RGBA bytes of row `y`, or None if it's off the buffer or its strip is
+    /// still unallocated and so transparent
+    fn row(&self, y: u32) -> Option<&[u8]> {
+        let strip = self.strips.get((y / STRIP_ROWS) as usize)?.as_ref()?;
+        let start = (y % STRIP_ROWS) as usize * self.stride();
+        strip.get(start..start + self.stride())
+    }
+
+    /// Mutable RGBA bytes of row `y`, which must lie on the buffer
+    fn row_mut(&mut self, y: u32) -> &mut [u8] {
+        let stride = self.stride();
+        let start = (y % STRIP_ROWS) as usize * stride;
+        &mut self.strip_mut((y / STRIP_ROWS) as usize)[start..start + stride]
+    }
+}
+
+impl PartialEq for PixelBuffer {
+    fn eq(&self, other: &Self) -> bool {
+        (self.width, self.height) == (other.width, other.height) && self.changed_bounds(other).is_none()
+    }
+}
+
+impl Eq for PixelBuffer {}
+
+/// Fill a run of RGBA pixels with one color
+fn fill_pixels(pixels: &mut [u8], color: [u8; 4]) {
+    if color.iter().all(|&c| c == color[0]) {
+        pixels.fill(color[0]);
+    } else {
+        for pixel in pixels.chunks_exact_mut(4) {
+            pixel.copy_from_slice(&color);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: [u8; 4] = [255, 0, 0, 255];
+
+    #[test]
+    fn test_fill_rect_clips() {
+        let mut buffer = PixelBuffer::new(4, 4);
+        buffer.fill_rect(2, 2, 10, 10, RED);
+
+        assert_eq!(buffer.get_pixel(3, 3).unwrap(), RED);
+        assert_eq!(buffer.get_pixel(2, 2).unwrap(), RED);
+        assert_eq!(buffer.get_pixel(1, 3).unwrap(), [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_set_pixels_all_or_nothing() {
+        let mut buffer = PixelBuffer::new(4, 4);
+        let bounds = buffer.set_pixels(&[(3, 1, RED), (1, 2, RED), (1, 2, [0, 0, 255, 255])]).unwrap();
+        assert_eq!(bounds, Some((1, 1, 3, 2)));
+        assert_eq!(buffer.get_pixel(3, 1).unwrap(), RED);
+        assert_eq!(buffer.get_pixel(1, 2).unwrap(), [0, 0, 255, 255]);
+
+        assert!(buffer.set_pixels(&[(0, 0, RED), (4, 0, RED)]).is_err());
+        assert_eq!(buffer.get_pixel(0, 0).unwrap(), [0, 0, 0, 0]);
+        assert_eq!(buffer.set_pixels(&[]).unwrap(), None);
+    }
+
+    #[test]
+    fn test_copy_region_and_blit() {
+        let mut buffer = PixelBuffer::new(4, 4);
+        buffer.set_pixel(1, 1, RED).unwrap();
+
+        let region = buffer.copy_region(1, 1, 2, 2);
+        assert_eq!((region.width, region.height), (2, 2));
+        assert_eq!(region.get_pixel(0, 0).unwrap(), RED);
+
+        // Partially off the top-left edge: only the bottom-right pixel lands
+        let mut target = PixelBuffer::new(4, 4);
+        target.blit(&region, -1, -1);
+        assert_eq!(target.get_pixel(0, 0).unwrap(), [0, 0, 0, 0]);
+
+        target.blit(&region, 3, 3);
+        assert_eq!(target.get_pixel(3, 3).unwrap(), RED);
+    }
+
+    #[test]
+    fn test_copy_region_out_of_bounds() {
+        let buffer = PixelBuffer::new(4, 4);
+        for (x, y) in [(4, 0), (9, 1), (0, 4), (2, 7), (u32::MAX, u32::MAX)] {
+            let region = buffer.copy_region(x, y, 2, 2);
+            assert_eq!(region.width * region.height, 0, "origin ({}, {})", x, y);
+        }
+        let region = buffer.copy_region(3, 3, 5, 5);
+        assert_eq!((region.width, region.height), (1, 1));
+    }
+
+    #[test]
+    fn test_diff() {
+        let highlight = [255, 0, 255, 255];
+        let mut saved = PixelBuffer::new(2, 2);
+        saved.set_pixel(0, 0, RED).unwrap();
+        saved.set_pixel(1, 1, [9, 9, 9, 0]).unwrap();
+
+        // One pixel repainted, and a column added by growing the canvas
+        let mut current = PixelBuffer::new(3, 2);
+        current.set_pixel(0, 0, RED).unwrap();
+        current.set_pixel(0, 1, RED).unwrap();
+        current.set_pixel(2, 0, RED).unwrap();
+
+        let (picture, changed) = current.diff(&saved, highlight);
+        assert_eq!(changed, 2);
+        assert_eq!((picture.width, picture.height), (3, 2));
+        assert_eq!(picture.get_pixel(0, 0).unwrap(), [255, 0, 0, 63]);
+        assert_eq!(picture.get_pixel(0, 1).unwrap(), highlight);
+        assert_eq!(picture.get_pixel(2, 0).unwrap(), highlight);
+        assert_eq!(picture.get_pixel(1, 1).unwrap(), [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_strips_allocate_lazily() {
+        let mut buffer = PixelBuffer::new(8192, 8192);
+        assert_eq!(buffer.byte_len(), 0);
+        assert_eq!(buffer.get_pixel(8000, 8000).unwrap(), [0, 0, 0, 0]);
+
+        buffer.fill_rect(0, 100, 8192, 1, [0, 0, 0, 0]);
+        assert_eq!(buffer.byte_len(), 0);
+
+        buffer.set_pixel(8000, 8000, RED).unwrap();
+        assert_eq!(buffer.byte_len(), 8192 * 4 * STRIP_ROWS as usize);
+        assert_eq!(buffer.content_bounds(), Some((8000, 8000, 8000, 8000)));
+
+        buffer.clear([0, 0, 0, 0]);
+        assert_eq!(buffer.byte_len(), 0);
+    }
+
+    #[test]
+    fn test_rgba_roundtrip() {
+        // Tall enough for a partial last strip, with paint only in the first
+        let (width, height) = (3, STRIP_ROWS + 5);
+        let mut data = vec![0; (width * height * 4) as usize];
+        data[4..8].copy_from_slice(&RED);
+
+        let buffer = PixelBuffer::from_rgba(width, height, &data).unwrap();
+        assert_eq!(buffer.get_pixel(1, 0).unwrap(), RED);
+        assert_eq!(buffer.byte_len(), (width * STRIP_ROWS * 4) as usize);
+        assert_eq!(buffer.to_rgba(), data);
+        assert_eq!(buffer.pixels().count(), (width * height) as usize);
+        assert_eq!(buffer.pixels().position(|pixel| pixel == RED), Some(1));
+
+        assert!(PixelBuffer::from_rgba(width, height, &data[4..]).is_none());
+    }
+
+    #[test]
+    fn test_clones_share_strips() {
+        let mut buffer = PixelBuffer::new(4, STRIP_ROWS * 3);
+        buffer.clear(RED);
+        let snapshot = buffer.clone();
+        assert_eq!(buffer.unshared_bytes(&snapshot), 0);
+
+        // Rewriting a pixel's own color keeps the strip shared
+        buffer.set_pixel(1, 1, RED).unwrap();
+        assert_eq!(buffer.unshared_bytes(&snapshot), 0);
+
+        buffer.set_pixel(2, STRIP_ROWS + 1, [0, 0, 255, 255]).unwrap();
+        assert_eq!(buffer.unshared_bytes(&snapshot), (4 * STRIP_ROWS * 4) as usize);
+        assert_eq!(buffer.changed_bounds(&snapshot), Some((2, STRIP_ROWS + 1, 2, STRIP_ROWS + 1)));
+        assert_ne!(buffer, snapshot);
+        assert_eq!(snapshot.get_pixel(2, STRIP_ROWS + 1).unwrap(), RED);
     }
 }
@@ -0,0 +1,18 @@
+// Progress reporting
+// Long engine operations report how far along they are through a Progress,
+// which can also stop them early by returning an error (e.g. Cancelled)
+use crate::error::Result;
+
+pub trait Progress {
+    /// `done` of `total` units are finished; an error aborts the operation
+    fn update(&self, done: u64, total: u64) -> Result<()>;
+}
+
+/// For callers that don't track progress
+pub struct Untracked;
+
+impl Progress for Untracked {
+    fn update(&self, _done: u64, _total: u64) -> Result<()> {
+        Ok(())
+    }
+}
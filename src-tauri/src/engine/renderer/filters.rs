@@ -0,0 +1,100 @@
+// Skia image filters
+//
+// Blur, drop shadow and color adjustments run as Skia image filters on the
+// renderer's surface rather than as per-pixel loops over the buffer.
+
+use crate::error::{AipixError, Result};
+use serde::{Deserialize, Serialize};
+use skia_safe::{color_filters, image_filters, Color, ImageFilter, TileMode};
+
+/// Largest blur radius in pixels
+pub const MAX_BLUR_RADIUS: f32 = 100.0;
+
+/// Rec. 709 luma weights, used for saturation
+const LUMA: [f32; 3] = [0.2126, 0.7152, 0.0722];
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Filter {
+    /// Gaussian blur; `radius` is the standard deviation in pixels
+    Blur { radius: f32 },
+    /// The content's silhouette in `color`, offset and blurred, behind the content
+    DropShadow {
+        offset_x: f32,
+        offset_y: f32,
+        blur: f32,
+        color: [u8; 4], // RGBA
+    },
+    /// Brightness, contrast and saturation, each from -1 to 1 (0 leaves the image unchanged)
+    Adjust {
+        brightness: f32,
+        contrast: f32,
+        saturation: f32,
+    },
+    /// A row-major 4x5 RGBA color matrix; the last column is an offset in 0..1 units
+    ColorMatrix { matrix: [f32; 20] },
+}
+
+impl Filter {
+    pub fn validate(&self) -> Result<()> {
+        let blur_ok = |radius: f32| (0.0..=MAX_BLUR_RADIUS).contains(&radius);
+        let adjust_ok = |value: f32| (-1.0..=1.0).contains(&value);
+        let valid = match self {
+            Filter::Blur { radius } => blur_ok(*radius),
+            Filter::DropShadow { offset_x, offset_y, blur, .. } => {
+                offset_x.is_finite() && offset_y.is_finite() && blur_ok(*blur)
+            }
+            Filter::Adjust { brightness, contrast, saturation } => {
+                adjust_ok(*brightness) && adjust_ok(*contrast) && adjust_ok(*saturation)
+            }
+            Filter::ColorMatrix { matrix } => matrix.iter().all(|value| value.is_finite()),
+        };
+        if valid {
+            Ok(())
+        } else {
+            Err(AipixError::InvalidInput(format!("Invalid filter parameters: {:?}", self)))
+        }
+    }
+
+    /// The Skia image filter, sampling transparent pixels beyond the canvas
+    /// except for blur, which clamps so canvas edges don't fade out
+    pub fn to_image_filter(&self) -> Result<ImageFilter> {
+        let filter = match *self {
+            Filter::Blur { radius } => image_filters::blur((radius, radius), TileMode::Clamp, None, None),
+            Filter::DropShadow { offset_x, offset_y, blur, color: [r, g, b, a] } => image_filters::drop_shadow(
+                (offset_x, offset_y),
+                (blur, blur),
+                Color::from_argb(a, r, g, b),
+                None,
+                None,
+                None,
+            ),
+            Filter::Adjust { brightness, contrast, saturation } => {
+                let matrix = adjustment_matrix(brightness, contrast, saturation);
+                image_filters::color_filter(color_filters::matrix_row_major(&matrix, None), None, None)
+            }
+            Filter::ColorMatrix { ref matrix } => {
+                image_filters::color_filter(color_filters::matrix_row_major(matrix, None), None, None)
+            }
+        };
+        filter.ok_or_else(|| AipixError::RendererError("Failed to create image filter".to_string()))
+    }
+}
+
+/// Color matrix applying saturation, then contrast, then brightness
+pub fn adjustment_matrix(brightness: f32, contrast: f32, saturation: f32) -> [f32; 20] {
+    let saturation = 1.0 + saturation;
+    let scale = 1.0 + contrast;
+    let offset = 0.5 * (1.0 - scale) + brightness;
+
+    let mut matrix = [0.0; 20];
+    for row in 0..3 {
+        for column in 0..3 {
+            let identity = if row == column { 1.0 } else { 0.0 };
+            matrix[row * 5 + column] = scale * ((1.0 - saturation) * LUMA[column] + saturation * identity);
+        }
+        matrix[row * 5 + 4] = offset;
+    }
+    matrix[18] = 1.0; // Alpha unchanged
+    matrix
+}
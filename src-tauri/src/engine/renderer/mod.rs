@@ -5,6 +5,9 @@
 
 pub mod dirty_region;
 pub mod pixel_renderer;
+pub mod overlay;
+pub mod filters;
 
 pub use dirty_region::{DirtyRegion, Rect};
-pub use pixel_renderer::PixelRenderer;
+pub use filters::Filter;
+pub use pixel_renderer::{PaintBlend, PixelRenderer, ResizeAnchor, StrokePressure};
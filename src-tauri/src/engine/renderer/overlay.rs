@@ -0,0 +1,224 @@
+// Editor Overlays
+//
+// Drawn over rendered viewports only, never into the canvas pixels, so
+// they don't end up in exports or the undo history.
+
+use super::dirty_region::Rect;
+use crate::engine::guides::{Guide, GuideOrientation};
+use crate::engine::pixel_buffer::PixelBuffer;
+use crate::engine::transform::Floating;
+use crate::error::{AipixError, Result};
+use std::collections::HashMap;
+use std::ops::Range;
+
+/// Guide line color (RGBA)
+pub const GUIDE_COLOR: [u8; 4] = [0, 200, 255, 255];
+
+/// Grid line color (RGB); lines are blended halfway into the pixels below
+pub const GRID_COLOR: [u8; 3] = [128, 128, 128];
+
+/// How closely the canvas grid is drawn, as stored in user_settings.grid_density
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GridDensity {
+    Low,
+    Medium,
+    High,
+}
+
+impl GridDensity {
+    pub fn from_name(name: &str) -> Result<Self> {
+        match name {
+            "low" => Ok(GridDensity::Low),
+            "medium" => Ok(GridDensity::Medium),
+            "high" => Ok(GridDensity::High),
+            _ => Err(AipixError::InvalidInput(format!("Unknown grid density \"{}\"", name))),
+        }
+    }
+
+    /// Canvas pixels between grid lines
+    pub fn spacing(self) -> i32 {
+        match self {
+            GridDensity::Low => 32,
+            GridDensity::Medium => 16,
+            GridDensity::High => 8,
+        }
+    }
+}
+
+/// Draw guides as one-pixel lines over an RGBA viewport rendered at 1:1
+///
+/// A guide at position `p` is drawn on the pixel row/column just after
+/// that boundary.
+pub fn draw_guides(pixels: &mut [u8], viewport: Rect, guides: &[Guide]) {
+    let Rect { x: viewport_x, y: viewport_y, .. } = viewport;
+    let width = viewport.width.max(0) as usize;
+    let height = viewport.height.max(0) as usize;
+    if pixels.len() < width * height * 4 {
+        return;
+    }
+
+    let mut set = |x: usize, y: usize| {
+        let i = (y * width + x) * 4;
+        pixels[i..i + 4].copy_from_slice(&GUIDE_COLOR);
+    };
+    for guide in guides {
+        match guide.orientation {
+            GuideOrientation::Vertical => {
+                let Ok(x) = usize::try_from(guide.position as i64 - viewport_x as i64) else { continue };
+                if x < width {
+                    (0..height).for_each(|y| set(x, y));
+                }
+            }
+            GuideOrientation::Horizontal => {
+                let Ok(y) = usize::try_from(guide.position as i64 - viewport_y as i64) else { continue };
+                if y < height {
+                    (0..width).for_each(|x| set(x, y));
+                }
+            }
+        }
+    }
+}
+
+/// Draw a grid of lines every `spacing` canvas pixels over an RGBA viewport rendered at 1:1
+///
+/// Like guides, a line is drawn on the pixel row/column just after each
+/// boundary. Only the canvas is covered.
+pub fn draw_grid(
+    pixels: &mut [u8],
+    viewport: Rect,
+    (canvas_width, canvas_height): (i32, i32),
+    spacing: i32,
+) {
+    let Rect { x: viewport_x, y: viewport_y, .. } = viewport;
+    let width = viewport.width.max(0) as usize;
+    let height = viewport.height.max(0) as usize;
+    if pixels.len() < width * height * 4 || spacing <= 0 {
+        return;
+    }
+
+    let columns = canvas_span(viewport_x, width, canvas_width);
+    let rows = canvas_span(viewport_y, height, canvas_height);
+    for row in rows.clone() {
+        let on_row = (viewport_y + row) % spacing == 0;
+        for column in columns.clone() {
+            if on_row || (viewport_x + column) % spacing == 0 {
+                let i = (row as usize * width + column as usize) * 4;
+                for (channel, line) in pixels[i..i + 3].iter_mut().zip(GRID_COLOR) {
+                    *channel = ((*channel as u16 + line as u16) / 2) as u8;
+                }
+            }
+        }
+    }
+}
+
+/// Viewport columns (or rows) from `offset` that lie over a canvas `canvas` pixels wide
+fn canvas_span(offset: i32, size: usize, canvas: i32) -> Range<i32> {
+    let size = size as i32;
+    (-offset).clamp(0, size)..(canvas - offset).clamp(0, size)
+}
+
+/// Draw a floating selection over an RGBA viewport rendered at 1:1
+///
+/// The pixels it was lifted from are shown cleared and its transformed
+/// pixels replace whatever is under them, as committing it would.
+pub fn draw_floating(
+    pixels: &mut [u8],
+    viewport: Rect,
+    (canvas_width, canvas_height): (i32, i32),
+    floating: &Floating,
+) {
+    let Rect { x: viewport_x, y: viewport_y, .. } = viewport;
+    let width = viewport.width.max(0) as usize;
+    let height = viewport.height.max(0) as usize;
+    if pixels.len() < width * height * 4 {
+        return;
+    }
+
+    let columns = canvas_span(viewport_x, width, canvas_width);
+    let rows = canvas_span(viewport_y, height, canvas_height);
+    for row in rows {
+        for column in columns.clone() {
+            let (x, y) = (viewport_x as i64 + column as i64, viewport_y as i64 + row as i64);
+            let color = match floating.pixel_at(x, y) {
+                Some(color) => color,
+                None if floating.lifted_from(x, y) => [0, 0, 0, 0],
+                None => continue,
+            };
+            let i = (row as usize * width + column as usize) * 4;
+            pixels[i..i + 4].copy_from_slice(&color);
+        }
+    }
+}
+
+/// Opacity of a ghosted paste preview, out of 255
+pub const GHOST_OPACITY: u8 = 128;
+
+/// Draw `ghost` with its top-left at canvas (`x`, `y`), half transparent, over an RGBA viewport rendered at 1:1
+///
+/// Only the part over the canvas is drawn, since that is all a paste writes.
+pub fn draw_ghost(
+    pixels: &mut [u8],
+    viewport: Rect,
+    (canvas_width, canvas_height): (i32, i32),
+    ghost: &PixelBuffer,
+    (x, y): (i32, i32),
+) {
+    let Rect { x: viewport_x, y: viewport_y, .. } = viewport;
+    let width = viewport.width.max(0) as usize;
+    let height = viewport.height.max(0) as usize;
+    if pixels.len() < width * height * 4 {
+        return;
+    }
+
+    let columns = canvas_span(viewport_x, width, canvas_width);
+    let rows = canvas_span(viewport_y, height, canvas_height);
+    for row in rows {
+        let ghost_y = viewport_y as i64 + row as i64 - y as i64;
+        for column in columns.clone() {
+            let ghost_x = viewport_x as i64 + column as i64 - x as i64;
+            if ghost_x < 0 || ghost_y < 0 {
+                continue;
+            }
+            let Some(color) = ghost.get_pixel(ghost_x as u32, ghost_y as u32).filter(|color| color[3] > 0) else {
+                continue;
+            };
+
+            let alpha = color[3] as u32 * GHOST_OPACITY as u32 / 255;
+            let i = (row as usize * width + column as usize) * 4;
+            for channel in 0..3 {
+                let below = pixels[i + channel] as u32;
+                pixels[i + channel] = ((color[channel] as u32 * alpha + below * (255 - alpha)) / 255) as u8;
+            }
+            pixels[i + 3] = pixels[i + 3].max(alpha as u8);
+        }
+    }
+}
+
+/// Show canvas colors as `shown` maps them, over an RGBA viewport rendered at 1:1
+///
+/// Used for palette cycling (see engine::cycling); the area off the canvas
+/// is left alone.
+pub fn draw_palette_cycles(
+    pixels: &mut [u8],
+    viewport: Rect,
+    (canvas_width, canvas_height): (i32, i32),
+    shown: &HashMap<[u8; 4], [u8; 4]>,
+) {
+    let Rect { x: viewport_x, y: viewport_y, .. } = viewport;
+    let width = viewport.width.max(0) as usize;
+    let height = viewport.height.max(0) as usize;
+    if pixels.len() < width * height * 4 || shown.is_empty() {
+        return;
+    }
+
+    let columns = canvas_span(viewport_x, width, canvas_width);
+    for row in canvas_span(viewport_y, height, canvas_height) {
+        let start = (row as usize * width + columns.start as usize) * 4;
+        let end = (row as usize * width + columns.end as usize) * 4;
+        for pixel in pixels[start..end].chunks_exact_mut(4) {
+            if let Some(color) = shown.get(&[pixel[0], pixel[1], pixel[2], pixel[3]]) {
+                pixel.copy_from_slice(color);
+            }
+        }
+    }
+}
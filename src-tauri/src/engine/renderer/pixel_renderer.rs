@@ -4,8 +4,96 @@
 // raw pixel buffers and create Skia surfaces on-demand for rendering.
 
 use super::dirty_region::{DirtyRegion, Rect};
-use anyhow::{Context, Result};
-use skia_safe::{Color, ImageInfo, Paint, Path, ColorType, AlphaType, surfaces};
+use super::filters::Filter;
+use crate::engine::dynamics::BrushDynamics;
+use crate::error::{AipixError, Result};
+use serde::{Deserialize, Serialize};
+use skia_safe::{canvas::SaveLayerRec, Color, ImageInfo, Paint, Path, ColorType, AlphaType, BlendMode, surfaces};
+
+/// How drawn pixels combine with the canvas under them
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PaintBlend {
+    /// Composite over the canvas
+    #[default]
+    Normal,
+    /// Overwrite the canvas, alpha included
+    Replace,
+    Multiply,
+    Screen,
+    Add,
+    /// Remove the canvas where drawn, by the paint's alpha
+    Erase,
+}
+
+impl PaintBlend {
+    fn blend_mode(self) -> BlendMode {
+        match self {
+            PaintBlend::Normal => BlendMode::SrcOver,
+            PaintBlend::Replace => BlendMode::Src,
+            PaintBlend::Multiply => BlendMode::Multiply,
+            PaintBlend::Screen => BlendMode::Screen,
+            PaintBlend::Add => BlendMode::Plus,
+            PaintBlend::Erase => BlendMode::DstOut,
+        }
+    }
+}
+
+/// Set `paint` to draw the straight-alpha `color` at `opacity`
+///
+/// Skia premultiplies paint colors itself when drawing onto the canvas. The
+/// color's own alpha is scaled by `opacity`, not replaced by it.
+fn set_paint_color(paint: &mut Paint, color: Color, opacity: f32) {
+    paint.set_color(color);
+    paint.set_alpha_f(color.a() as f32 / 255.0 * opacity.clamp(0.0, 1.0));
+}
+
+/// A straight-alpha RGBA pixel as stored on the premultiplied canvas
+fn premultiply([r, g, b, a]: [u8; 4]) -> [u8; 4] {
+    let scale = |c: u8| ((c as u16 * a as u16 + 127) / 255) as u8;
+    [scale(r), scale(g), scale(b), a]
+}
+
+/// Where existing pixels stay when the canvas is resized
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ResizeAnchor {
+    #[default]
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+impl ResizeAnchor {
+    /// Position of the old canvas's top-left on the resized one (negative when cropped away)
+    pub fn offset(self, old_width: i32, old_height: i32, width: i32, height: i32) -> (i32, i32) {
+        let (dx, dy) = (width - old_width, height - old_height);
+        let x = match self {
+            ResizeAnchor::TopLeft | ResizeAnchor::Left | ResizeAnchor::BottomLeft => 0,
+            ResizeAnchor::Top | ResizeAnchor::Center | ResizeAnchor::Bottom => dx / 2,
+            ResizeAnchor::TopRight | ResizeAnchor::Right | ResizeAnchor::BottomRight => dx,
+        };
+        let y = match self {
+            ResizeAnchor::TopLeft | ResizeAnchor::Top | ResizeAnchor::TopRight => 0,
+            ResizeAnchor::Left | ResizeAnchor::Center | ResizeAnchor::Right => dy / 2,
+            ResizeAnchor::BottomLeft | ResizeAnchor::Bottom | ResizeAnchor::BottomRight => dy,
+        };
+        (x, y)
+    }
+}
+
+/// Stylus pressure along a stroke, and how it maps to size and opacity
+#[derive(Debug, Clone, Copy)]
+pub struct StrokePressure<'a> {
+    pub pressures: &'a [f32], // One per point, 0-1
+    pub dynamics: &'a BrushDynamics,
+}
 
 /// Thread-safe pixel buffer renderer
 pub struct PixelRenderer {
@@ -27,7 +115,15 @@ unsafe impl Sync for PixelRenderer {}
 impl PixelRenderer {
     /// Create a new pixel renderer
     pub fn new(width: i32, height: i32) -> Result<Self> {
-        let pixel_count = (width * height * 4) as usize; // RGBA = 4 bytes per pixel
+        // RGBA = 4 bytes per pixel; size limits are enforced by the caller,
+        // this only guards against negative or overflowing dimensions
+        let pixel_count = usize::try_from(width)
+            .ok()
+            .zip(usize::try_from(height).ok())
+            .and_then(|(w, h)| w.checked_mul(h)?.checked_mul(4))
+            .ok_or_else(|| {
+                AipixError::InvalidInput(format!("Invalid canvas size {}x{}", width, height))
+            })?;
         let pixels = vec![255u8; pixel_count]; // White background
 
         Ok(Self {
@@ -38,13 +134,14 @@ impl PixelRenderer {
         })
     }
 
-    /// Draw a stroke (brush/pencil)
+    /// Draw a stroke (brush/pencil) of the straight-alpha `color`
     pub fn draw_stroke(
         &mut self,
         points: &[(f32, f32)],
         brush_size: f32,
         color: Color,
         opacity: f32,
+        blend: PaintBlend,
     ) -> Result<()> {
         if points.is_empty() {
             return Ok(());
@@ -66,14 +163,14 @@ impl PixelRenderer {
             self.pixels.as_mut_slice(),
             Some(row_bytes),
             None
-        ).context("Failed to create surface")?;
+        ).ok_or_else(|| AipixError::RendererError("Failed to create surface".to_string()))?;
 
         let canvas = surface.canvas();
 
         // Setup paint
         let mut paint = Paint::default();
-        paint.set_color(color);
-        paint.set_alpha_f(opacity);
+        set_paint_color(&mut paint, color, opacity);
+        paint.set_blend_mode(blend.blend_mode());
         paint.set_stroke_width(brush_size);
         paint.set_stroke_cap(skia_safe::PaintCap::Round);
         paint.set_stroke_join(skia_safe::PaintJoin::Round);
@@ -106,8 +203,89 @@ impl PixelRenderer {
         Ok(())
     }
 
-    /// Fill a rectangle
-    pub fn fill_rect(&mut self, rect: Rect, color: Color, opacity: f32) -> Result<()> {
+    /// Draw a stroke whose size and opacity follow `pressure`
+    ///
+    /// Each segment uses the average pressure of its ends. Segments are drawn
+    /// into a layer that replaces rather than blends, so the round joints
+    /// between translucent segments don't darken; the layer is then
+    /// combined with the canvas by `blend`.
+    pub fn draw_pressure_stroke(
+        &mut self,
+        points: &[(f32, f32)],
+        pressure: StrokePressure,
+        brush_size: f32,
+        color: Color,
+        opacity: f32,
+        blend: PaintBlend,
+    ) -> Result<()> {
+        let StrokePressure { pressures, dynamics } = pressure;
+        if points.len() != pressures.len() {
+            return Err(AipixError::InvalidInput(format!(
+                "Got {} pressure values for {} points",
+                pressures.len(),
+                points.len()
+            )));
+        }
+        if points.is_empty() {
+            return Ok(());
+        }
+
+        let image_info = ImageInfo::new(
+            (self.width, self.height),
+            ColorType::RGBA8888,
+            AlphaType::Premul,
+            None,
+        );
+
+        let row_bytes = (self.width * 4) as usize;
+
+        let mut surface = surfaces::wrap_pixels(
+            &image_info,
+            self.pixels.as_mut_slice(),
+            Some(row_bytes),
+            None
+        ).ok_or_else(|| AipixError::RendererError("Failed to create surface".to_string()))?;
+
+        let canvas = surface.canvas();
+        let mut layer_paint = Paint::default();
+        layer_paint.set_blend_mode(blend.blend_mode());
+        canvas.save_layer(&SaveLayerRec::default().paint(&layer_paint));
+
+        let mut paint = Paint::default();
+        paint.set_blend_mode(BlendMode::Src);
+        paint.set_stroke_cap(skia_safe::PaintCap::Round);
+        paint.set_anti_alias(false); // Pixel-perfect
+        paint.set_style(skia_safe::PaintStyle::Stroke);
+
+        // A single point is a zero-length segment, drawn as a dot by the round cap
+        let segments: Vec<(usize, usize)> = if points.len() == 1 {
+            vec![(0, 0)]
+        } else {
+            (1..points.len()).map(|i| (i - 1, i)).collect()
+        };
+        for (start, end) in segments {
+            let pressure = (pressures[start] + pressures[end]) / 2.0;
+            let size = dynamics.size(brush_size, pressure);
+            paint.set_stroke_width(size);
+            set_paint_color(&mut paint, color, dynamics.opacity(opacity, pressure));
+            canvas.draw_line(points[start], points[end], &paint);
+
+            self.dirty_region.add_line(
+                points[start].0 as i32,
+                points[start].1 as i32,
+                points[end].0 as i32,
+                points[end].1 as i32,
+                size.ceil() as i32,
+            );
+        }
+
+        canvas.restore();
+
+        Ok(())
+    }
+
+    /// Fill a rectangle with the straight-alpha `color`
+    pub fn fill_rect(&mut self, rect: Rect, color: Color, opacity: f32, blend: PaintBlend) -> Result<()> {
         let image_info = ImageInfo::new(
             (self.width, self.height),
             ColorType::RGBA8888,
@@ -123,13 +301,13 @@ impl PixelRenderer {
             self.pixels.as_mut_slice(),
             Some(row_bytes),
             None
-        ).context("Failed to create surface")?;
+        ).ok_or_else(|| AipixError::RendererError("Failed to create surface".to_string()))?;
 
         let canvas = surface.canvas();
 
         let mut paint = Paint::default();
-        paint.set_color(color);
-        paint.set_alpha_f(opacity);
+        set_paint_color(&mut paint, color, opacity);
+        paint.set_blend_mode(blend.blend_mode());
         paint.set_anti_alias(false);
 
         canvas.draw_rect(
@@ -146,6 +324,69 @@ impl PixelRenderer {
         Ok(())
     }
 
+    /// Run a Skia image filter over the canvas, or only over `region`
+    ///
+    /// Filters still read pixels outside `region` (a blur near its edge
+    /// samples its surroundings) but only write inside it.
+    pub fn apply_filter(&mut self, filter: &Filter, region: Option<Rect>) -> Result<()> {
+        filter.validate()?;
+        let image_filter = filter.to_image_filter()?;
+
+        let bounds = match region {
+            Some(rect) => {
+                let left = rect.x.max(0);
+                let top = rect.y.max(0);
+                let right = (rect.x + rect.width).min(self.width);
+                let bottom = (rect.y + rect.height).min(self.height);
+                Rect::new(left, top, (right - left).max(0), (bottom - top).max(0))
+            }
+            None => Rect::new(0, 0, self.width, self.height),
+        };
+        if bounds.is_empty() {
+            return Ok(());
+        }
+
+        let image_info = ImageInfo::new(
+            (self.width, self.height),
+            ColorType::RGBA8888,
+            AlphaType::Premul,
+            None,
+        );
+
+        let row_bytes = (self.width * 4) as usize;
+
+        let mut surface = surfaces::wrap_pixels(
+            &image_info,
+            self.pixels.as_mut_slice(),
+            Some(row_bytes),
+            None
+        ).ok_or_else(|| AipixError::RendererError("Failed to create surface".to_string()))?;
+
+        // Filter a copy back onto the cleared surface
+        let source = surface.image_snapshot();
+        let canvas = surface.canvas();
+        canvas.save();
+        canvas.clip_rect(
+            skia_safe::Rect::from_xywh(
+                bounds.x as f32,
+                bounds.y as f32,
+                bounds.width as f32,
+                bounds.height as f32,
+            ),
+            None,
+            false,
+        );
+        canvas.clear(Color::TRANSPARENT);
+
+        let mut paint = Paint::default();
+        paint.set_image_filter(image_filter);
+        canvas.draw_image(&source, (0, 0), Some(&paint));
+        canvas.restore();
+
+        self.dirty_region.add_rect(bounds);
+        Ok(())
+    }
+
     /// Render viewport with culling
     pub fn render_viewport(
         &self,
@@ -187,18 +428,21 @@ impl PixelRenderer {
         self.pixels.clone()
     }
 
-    /// Clear canvas
-    pub fn clear(&mut self, color: Color) {
-        let r = color.r();
-        let g = color.g();
-        let b = color.b();
-        let a = color.a();
+    /// Canvas width and height
+    pub fn dimensions(&self) -> (i32, i32) {
+        (self.width, self.height)
+    }
+
+    /// Bytes held by the pixel buffer
+    pub fn memory_bytes(&self) -> usize {
+        self.pixels.len()
+    }
 
+    /// Clear canvas to the straight-alpha `color`
+    pub fn clear(&mut self, color: Color) {
+        let fill = premultiply([color.r(), color.g(), color.b(), color.a()]);
         for chunk in self.pixels.chunks_exact_mut(4) {
-            chunk[0] = r;
-            chunk[1] = g;
-            chunk[2] = b;
-            chunk[3] = a;
+            chunk.copy_from_slice(&fill);
         }
 
         self.dirty_region.add_rect(Rect::new(0, 0, self.width, self.height));
@@ -214,11 +458,59 @@ impl PixelRenderer {
         self.dirty_region.clear();
     }
 
-    /// Resize
-    pub fn resize(&mut self, width: i32, height: i32) -> Result<()> {
+    /// Replace the canvas with straight-alpha RGBA pixels (e.g. a document's buffer)
+    pub fn load_pixels(&mut self, width: i32, height: i32, rgba: &[u8]) -> Result<()> {
+        let expected = usize::try_from(width)
+            .ok()
+            .zip(usize::try_from(height).ok())
+            .and_then(|(w, h)| w.checked_mul(h)?.checked_mul(4));
+        if expected != Some(rgba.len()) {
+            return Err(AipixError::InvalidInput(format!(
+                "{} bytes do not make a {}x{} RGBA image",
+                rgba.len(),
+                width,
+                height
+            )));
+        }
+
+        // The surface is premultiplied
+        self.pixels = rgba
+            .chunks_exact(4)
+            .flat_map(|px| premultiply([px[0], px[1], px[2], px[3]]))
+            .collect();
+        self.width = width;
+        self.height = height;
+        self.dirty_region.clear();
+        self.dirty_region.add_rect(Rect::new(0, 0, width, height));
+        Ok(())
+    }
+
+    /// Resize the canvas, keeping its pixels placed by `anchor`
+    ///
+    /// Newly exposed areas are filled with `background`; pixels pushed past
+    /// the new edges are cropped.
+    pub fn resize(&mut self, width: i32, height: i32, anchor: ResizeAnchor, background: Color) -> Result<()> {
+        let mut resized = Self::new(width, height)?;
+        let fill = premultiply([background.r(), background.g(), background.b(), background.a()]);
+        for chunk in resized.pixels.chunks_exact_mut(4) {
+            chunk.copy_from_slice(&fill);
+        }
+
+        // Copy the rows of the old canvas that still fit
+        let (offset_x, offset_y) = anchor.offset(self.width, self.height, width, height);
+        let (from_x, to_x) = ((-offset_x).max(0), (width - offset_x).min(self.width));
+        if from_x < to_x {
+            let row_len = (to_x - from_x) as usize * 4;
+            for y in (-offset_y).max(0)..(height - offset_y).min(self.height) {
+                let src = ((y * self.width + from_x) as usize) * 4;
+                let dst = (((y + offset_y) * width + from_x + offset_x) as usize) * 4;
+                resized.pixels[dst..dst + row_len].copy_from_slice(&self.pixels[src..src + row_len]);
+            }
+        }
+
+        self.pixels = resized.pixels;
         self.width = width;
         self.height = height;
-        self.pixels = vec![255u8; (width * height * 4) as usize];
         self.dirty_region.clear();
         self.dirty_region.add_rect(Rect::new(0, 0, width, height));
         Ok(())
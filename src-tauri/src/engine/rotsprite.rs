@@ -0,0 +1,153 @@
+// RotSprite rotation
+// Rotates pixel art by arbitrary angles without nearest-neighbour shredding:
+// the content is enlarged 8x with Scale2x (which keeps edges hard and follows
+// diagonals), rotated by nearest-neighbour sampling at that resolution and
+// sampled back down. Every output pixel takes a color from the source.
+use super::pixel_buffer::PixelBuffer;
+use super::progress::Progress;
+use super::upscale::scale2x;
+use crate::error::{AipixError, Result};
+
+/// Oversampling factor: three Scale2x passes
+const OVERSAMPLE: u32 = 8;
+
+/// Largest content (non-transparent bounding box) that can be rotated; the
+/// 8x intermediate of this is 64 MiB
+pub const MAX_CONTENT_PIXELS: u64 = 512 * 512;
+
+/// Rotate the content of `buffer` by `degrees` clockwise about (`center_x`, `center_y`)
+///
+/// The result has the same size as `buffer`; content rotated past its edges
+/// is clipped. Right angles are rotated exactly. Progress is counted in rows.
+pub fn rotsprite(
+    buffer: &PixelBuffer,
+    degrees: f32,
+    center_x: f32,
+    center_y: f32,
+    progress: &dyn Progress,
+) -> Result<PixelBuffer> {
+    let mut output = PixelBuffer::new(buffer.width, buffer.height);
+    let Some((min_x, min_y, max_x, max_y)) = buffer.content_bounds() else {
+        return Ok(output);
+    };
+    let (width, height) = (max_x - min_x + 1, max_y - min_y + 1);
+    if width as u64 * height as u64 > MAX_CONTENT_PIXELS {
+        return Err(AipixError::InvalidInput(format!(
+            "Content of {}x{} is too large to rotate (at most {} pixels)",
+            width, height, MAX_CONTENT_PIXELS
+        )));
+    }
+
+    let degrees = degrees.rem_euclid(360.0);
+    let quarter_turns = (degrees / 90.0).round();
+    let right_angle = (degrees - quarter_turns * 90.0).abs() < 1e-4;
+    let (sin, cos) = if right_angle {
+        [(0.0, 1.0), (1.0, 0.0), (0.0, -1.0), (-1.0, 0.0)][quarter_turns as usize % 4]
+    } else {
+        (degrees as f64).to_radians().sin_cos()
+    };
+
+    // Pad with a transparent border so Scale2x sees the content's real edges
+    let mut source = PixelBuffer::new(width + 2, height + 2);
+    source.blit(&buffer.copy_region(min_x, min_y, width, height), 1, 1);
+    let (origin_x, origin_y) = (min_x as f64 - 1.0, min_y as f64 - 1.0);
+    let scale = if right_angle {
+        1
+    } else {
+        source = scale2x(&scale2x(&scale2x(&source)));
+        OVERSAMPLE
+    };
+
+    let (cx, cy) = (center_x as f64, center_y as f64);
+    let rotate = |x: f64, y: f64| {
+        let (dx, dy) = (x - cx, y - cy);
+        (cx + dx * cos - dy * sin, cy + dx * sin + dy * cos)
+    };
+
+    // Only visit output pixels the rotated content box can reach
+    let corners = [
+        rotate(min_x as f64, min_y as f64),
+        rotate(max_x as f64 + 1.0, min_y as f64),
+        rotate(min_x as f64, max_y as f64 + 1.0),
+        rotate(max_x as f64 + 1.0, max_y as f64 + 1.0),
+    ];
+    let span = |values: [f64; 4], limit: u32| {
+        let low = values.iter().copied().fold(f64::INFINITY, f64::min).floor().max(0.0);
+        let high = values.iter().copied().fold(f64::NEG_INFINITY, f64::max).ceil().min(limit as f64);
+        low as u32..high.max(low) as u32
+    };
+    let xs = span(corners.map(|c| c.0), buffer.width);
+    let ys = span(corners.map(|c| c.1), buffer.height);
+
+    let rows = (ys.end - ys.start) as u64;
+    for y in ys.clone() {
+        progress.update((y - ys.start) as u64, rows)?;
+        for x in xs.clone() {
+            // Inverse rotation of the output pixel's center
+            let (dx, dy) = (x as f64 + 0.5 - cx, y as f64 + 0.5 - cy);
+            let source_x = (cx + dx * cos + dy * sin - origin_x) * scale as f64;
+            let source_y = (cy - dx * sin + dy * cos - origin_y) * scale as f64;
+            if source_x < 0.0 || source_y < 0.0 {
+                continue;
+            }
+            if let Some(color) = source.get_pixel(source_x as u32, source_y as u32) {
+                if color[3] > 0 {
+                    let _ = output.set_pixel(x, y, color);
+                }
+            }
+        }
+    }
+
+    progress.update(rows, rows)?;
+
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::progress::Untracked;
+
+    const RED: [u8; 4] = [255, 0, 0, 255];
+    const BLUE: [u8; 4] = [0, 0, 255, 255];
+
+    #[test]
+    fn test_right_angles_are_exact() {
+        let mut buffer = PixelBuffer::new(4, 4);
+        buffer.set_pixel(0, 0, RED).unwrap();
+        buffer.set_pixel(1, 0, BLUE).unwrap();
+
+        let quarter = rotsprite(&buffer, 90.0, 2.0, 2.0, &Untracked).unwrap();
+        assert_eq!(quarter.get_pixel(3, 0), Some(RED));
+        assert_eq!(quarter.get_pixel(3, 1), Some(BLUE));
+
+        assert_eq!(rotsprite(&buffer, 360.0, 2.0, 2.0, &Untracked).unwrap(), buffer);
+        assert_eq!(rotsprite(&buffer, -270.0, 2.0, 2.0, &Untracked).unwrap(), quarter);
+    }
+
+    #[test]
+    fn test_arbitrary_angle_keeps_palette() {
+        let mut buffer = PixelBuffer::new(24, 24);
+        buffer.fill_rect(6, 6, 12, 12, RED);
+        buffer.fill_rect(6, 6, 12, 2, BLUE);
+
+        let rotated = rotsprite(&buffer, 30.0, 12.0, 12.0, &Untracked).unwrap();
+        let colors: Vec<[u8; 4]> = rotated.pixels().map(|c| [c[0], c[1], c[2], c[3]]).collect();
+        assert!(colors.iter().all(|c| [RED, BLUE, [0, 0, 0, 0]].contains(c)));
+
+        // Area is roughly preserved and the center stays put
+        let filled = colors.iter().filter(|c| c[3] > 0).count() as i64;
+        assert!((filled - 144).abs() < 16, "filled {}", filled);
+        assert_eq!(rotated.get_pixel(12, 12), Some(RED));
+    }
+
+    #[test]
+    fn test_empty_and_oversized_content() {
+        let empty = PixelBuffer::new(8, 8);
+        assert_eq!(rotsprite(&empty, 45.0, 4.0, 4.0, &Untracked).unwrap(), empty);
+
+        let mut large = PixelBuffer::new(600, 600);
+        large.fill_rect(0, 0, 600, 600, RED);
+        assert!(rotsprite(&large, 45.0, 300.0, 300.0, &Untracked).is_err());
+    }
+}
@@ -0,0 +1,159 @@
+// Sprite sheet layout
+// Animation frames drawn side by side on one canvas, read as a grid of
+// equal cells left to right, top to bottom. Tags name runs of frames, e.g.
+// one animation of a character sheet holding several.
+use super::pixel_buffer::PixelBuffer;
+use crate::error::{AipixError, Result};
+use serde::{Deserialize, Serialize};
+use std::ops::RangeInclusive;
+
+/// A named run of frames
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FrameTag {
+    pub name: String,
+    pub from: u32,
+    pub to: u32, // Inclusive
+}
+
+impl FrameTag {
+    pub fn validate(&self) -> Result<()> {
+        if self.name.trim().is_empty() {
+            return Err(AipixError::InvalidInput("Tag name cannot be empty".to_string()));
+        }
+        if self.from > self.to {
+            return Err(AipixError::InvalidInput("A tag's first frame can't come after its last".to_string()));
+        }
+        Ok(())
+    }
+}
+
+/// Frames to export instead of all of them
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FrameSelection {
+    Range { first: u32, last: u32 }, // Inclusive
+    Tag(String),
+}
+
+impl FrameSelection {
+    /// The selected frame indices, which must all exist in `layout`
+    pub fn resolve(&self, tags: &[FrameTag], layout: &SheetLayout) -> Result<RangeInclusive<u32>> {
+        let (first, last) = match self {
+            FrameSelection::Range { first, last } => (*first, *last),
+            FrameSelection::Tag(name) => {
+                let tag = tags.iter().find(|tag| &tag.name == name).ok_or(AipixError::NotFound("Tag"))?;
+                (tag.from, tag.to)
+            }
+        };
+        if first > last || last >= layout.frame_count() {
+            return Err(AipixError::InvalidInput(format!(
+                "Frames {} to {} aren't a range of the sheet's {} frames",
+                first,
+                last,
+                layout.frame_count()
+            )));
+        }
+        Ok(first..=last)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SheetLayout {
+    pub frame_width: u32,
+    pub frame_height: u32,
+    pub columns: u32,
+    pub rows: u32,
+}
+
+impl SheetLayout {
+    /// Cells that fit whole in a canvas of `width` x `height`; partial cells at the edges are ignored
+    pub fn new(width: u32, height: u32, frame_width: u32, frame_height: u32) -> Result<Self> {
+        if frame_width == 0 || frame_height == 0 || frame_width > width || frame_height > height {
+            return Err(AipixError::InvalidInput(format!(
+                "Frame size must be between 1x1 and the canvas size ({}x{})",
+                width, height
+            )));
+        }
+        Ok(Self {
+            frame_width,
+            frame_height,
+            columns: width / frame_width,
+            rows: height / frame_height,
+        })
+    }
+
+    pub fn frame_count(&self) -> u32 {
+        self.columns * self.rows
+    }
+
+    /// Canvas rectangle of frame `index` as (x, y, width, height)
+    pub fn frame_rect(&self, index: u32) -> Option<(u32, u32, u32, u32)> {
+        (index < self.frame_count()).then(|| {
+            (
+                (index % self.columns) * self.frame_width,
+                (index / self.columns) * self.frame_height,
+                self.frame_width,
+                self.frame_height,
+            )
+        })
+    }
+
+    /// The frames in `frames` of `canvas` laid out on a sheet of their own
+    ///
+    /// The new sheet keeps this one's column count, or fewer for fewer frames.
+    pub fn pack(&self, canvas: &PixelBuffer, frames: RangeInclusive<u32>) -> (PixelBuffer, SheetLayout) {
+        let count = frames.clone().count() as u32;
+        let columns = count.clamp(1, self.columns);
+        let layout = SheetLayout {
+            frame_width: self.frame_width,
+            frame_height: self.frame_height,
+            columns,
+            rows: count.div_ceil(columns).max(1),
+        };
+
+        let mut sheet = PixelBuffer::new(layout.columns * layout.frame_width, layout.rows * layout.frame_height);
+        for (position, index) in frames.enumerate() {
+            let (Some((x, y, w, h)), Some((to_x, to_y, _, _))) =
+                (self.frame_rect(index), layout.frame_rect(position as u32))
+            else {
+                continue;
+            };
+            sheet.blit(&canvas.copy_region(x, y, w, h), to_x as i32, to_y as i32);
+        }
+        (sheet, layout)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_rects() {
+        let layout = SheetLayout::new(100, 40, 32, 16).unwrap();
+        assert_eq!((layout.columns, layout.rows, layout.frame_count()), (3, 2, 6));
+        assert_eq!(layout.frame_rect(4), Some((32, 16, 32, 16)));
+        assert_eq!(layout.frame_rect(6), None);
+        assert!(SheetLayout::new(100, 40, 0, 16).is_err());
+        assert!(SheetLayout::new(100, 40, 32, 41).is_err());
+    }
+
+    #[test]
+    fn test_select_and_pack_frames() {
+        let layout = SheetLayout::new(8, 4, 2, 2).unwrap(); // 4 columns, 2 rows
+        let tags = vec![FrameTag { name: "walk".to_string(), from: 3, to: 5 }];
+        assert_eq!(FrameSelection::Tag("walk".to_string()).resolve(&tags, &layout).unwrap(), 3..=5);
+        assert!(FrameSelection::Tag("run".to_string()).resolve(&tags, &layout).is_err());
+        assert!(FrameSelection::Range { first: 6, last: 8 }.resolve(&tags, &layout).is_err());
+        assert!(FrameSelection::Range { first: 2, last: 1 }.resolve(&tags, &layout).is_err());
+
+        let mut canvas = PixelBuffer::new(8, 4);
+        canvas.set_pixel(6, 0, [255, 0, 0, 255]).unwrap(); // Frame 3
+        canvas.set_pixel(1, 3, [0, 0, 255, 255]).unwrap(); // Frame 4
+
+        let (sheet, packed) = layout.pack(&canvas, 3..=5);
+        assert_eq!((packed.columns, packed.rows, sheet.width, sheet.height), (3, 1, 6, 2));
+        assert_eq!(sheet.get_pixel(0, 0), Some([255, 0, 0, 255]));
+        assert_eq!(sheet.get_pixel(3, 1), Some([0, 0, 255, 255]));
+    }
+}
@@ -0,0 +1,105 @@
+// Slices, nine-slice insets and pivots
+// Named rectangles of a canvas, optionally stretchable as nine-slices
+// (corners fixed, edges and center scaled) for UI frameworks and engines,
+// and the anchor points game engines align sprites by
+use crate::error::{AipixError, Result};
+use serde::{Deserialize, Serialize};
+
+/// Distances in pixels from each edge to the stretchable center
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct NineSlice {
+    pub left: u32,
+    pub top: u32,
+    pub right: u32,
+    pub bottom: u32,
+}
+
+impl NineSlice {
+    /// Insets must leave a center of at least one pixel in an area of `width` x `height`
+    pub fn validate(&self, width: u32, height: u32) -> Result<()> {
+        let horizontal = self.left as u64 + self.right as u64;
+        let vertical = self.top as u64 + self.bottom as u64;
+        if horizontal >= width as u64 || vertical >= height as u64 {
+            return Err(AipixError::InvalidInput(format!(
+                "Nine-slice insets leave no center in a {}x{} area",
+                width, height
+            )));
+        }
+        Ok(())
+    }
+
+    /// The stretchable center of an area of `width` x `height`, as (x, y, width, height)
+    pub fn center(&self, width: u32, height: u32) -> (u32, u32, u32, u32) {
+        (
+            self.left,
+            self.top,
+            width.saturating_sub(self.left + self.right),
+            height.saturating_sub(self.top + self.bottom),
+        )
+    }
+}
+
+/// Anchor point in pixels from the top-left of a frame or slice
+///
+/// May lie outside it (e.g. below a character's feet).
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Pivot {
+    pub x: i32,
+    pub y: i32,
+}
+
+/// A named region of the canvas
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Slice {
+    pub name: String,
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    #[serde(default)]
+    pub nine_slice: Option<NineSlice>,
+    #[serde(default)]
+    pub pivot: Option<Pivot>,
+}
+
+impl Slice {
+    /// Check the slice lies inside a canvas of `width` x `height`, as do its insets
+    pub fn validate(&self, width: u32, height: u32) -> Result<()> {
+        if self.name.trim().is_empty() {
+            return Err(AipixError::InvalidInput("Slice name cannot be empty".to_string()));
+        }
+        let inside = |start: u32, size: u32, limit: u32| {
+            size > 0 && start.checked_add(size).is_some_and(|end| end <= limit)
+        };
+        if !inside(self.x, self.width, width) || !inside(self.y, self.height, height) {
+            return Err(AipixError::OutOfBounds);
+        }
+        match &self.nine_slice {
+            Some(nine_slice) => nine_slice.validate(self.width, self.height),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nine_slice_validation() {
+        let insets = NineSlice { left: 4, top: 3, right: 4, bottom: 3 };
+        assert!(insets.validate(9, 7).is_ok());
+        assert!(insets.validate(8, 7).is_err());
+        assert_eq!(insets.center(16, 16), (4, 3, 8, 10));
+    }
+
+    #[test]
+    fn test_slice_validation() {
+        let mut slice = Slice { name: "button".to_string(), x: 8, y: 8, width: 16, height: 8, nine_slice: None, pivot: None };
+        assert!(slice.validate(32, 16).is_ok());
+        assert!(slice.validate(20, 16).is_err());
+
+        slice.nine_slice = Some(NineSlice { left: 2, top: 4, right: 2, bottom: 4 });
+        assert!(slice.validate(32, 16).is_err());
+    }
+}
@@ -0,0 +1,100 @@
+// Stroke stabilization
+// Smooths freehand input before it is rasterized. Both modes keep one output
+// point per input point, so per-point data such as pressure stays aligned,
+// and end exactly where the pen lifted.
+use crate::error::{AipixError, Result};
+use serde::{Deserialize, Serialize};
+
+pub const MAX_WINDOW: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Stabilizer {
+    /// Average of the last `window` points; reduces jitter, rounds corners
+    MovingAverage { window: usize },
+    /// The brush trails the pointer on a string of `length` pixels and only
+    /// moves once it is pulled taut, ignoring small wobbles entirely
+    PullString { length: f32 },
+}
+
+impl Stabilizer {
+    pub fn validate(&self) -> Result<()> {
+        match *self {
+            Stabilizer::MovingAverage { window } if window == 0 || window > MAX_WINDOW => {
+                Err(AipixError::InvalidInput(format!(
+                    "Stabilizer window must be between 1 and {}",
+                    MAX_WINDOW
+                )))
+            }
+            Stabilizer::PullString { length } if !(length >= 0.0 && length.is_finite()) => {
+                Err(AipixError::InvalidInput("Stabilizer string length must be positive".to_string()))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn apply(&self, points: &[(f32, f32)]) -> Vec<(f32, f32)> {
+        let mut smoothed = match *self {
+            Stabilizer::MovingAverage { window } => moving_average(points, window.max(1)),
+            Stabilizer::PullString { length } => pull_string(points, length),
+        };
+        if let (Some(last), Some(&end)) = (smoothed.last_mut(), points.last()) {
+            *last = end;
+        }
+        smoothed
+    }
+}
+
+fn moving_average(points: &[(f32, f32)], window: usize) -> Vec<(f32, f32)> {
+    (0..points.len())
+        .map(|i| {
+            let recent = &points[(i + 1).saturating_sub(window)..=i];
+            let n = recent.len() as f32;
+            let (x, y) = recent.iter().fold((0.0, 0.0), |(sx, sy), &(x, y)| (sx + x, sy + y));
+            (x / n, y / n)
+        })
+        .collect()
+}
+
+fn pull_string(points: &[(f32, f32)], length: f32) -> Vec<(f32, f32)> {
+    let Some(&start) = points.first() else {
+        return Vec::new();
+    };
+    let mut brush = start;
+    points
+        .iter()
+        .map(|&(x, y)| {
+            let (dx, dy) = (x - brush.0, y - brush.1);
+            let distance = dx.hypot(dy);
+            if distance > length {
+                let pull = (distance - length) / distance;
+                brush = (brush.0 + dx * pull, brush.1 + dy * pull);
+            }
+            brush
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_moving_average() {
+        let points = [(0.0, 0.0), (2.0, 2.0), (4.0, 0.0), (6.0, 2.0)];
+        let smoothed = Stabilizer::MovingAverage { window: 2 }.apply(&points);
+        assert_eq!(smoothed, vec![(0.0, 0.0), (1.0, 1.0), (3.0, 1.0), (6.0, 2.0)]);
+        assert!(Stabilizer::MovingAverage { window: 0 }.validate().is_err());
+    }
+
+    #[test]
+    fn test_pull_string() {
+        let points = [(0.0, 0.0), (1.0, 1.0), (10.0, 0.0), (12.0, 0.0)];
+        let smoothed = Stabilizer::PullString { length: 4.0 }.apply(&points);
+        assert_eq!(smoothed.len(), points.len());
+        assert_eq!(smoothed[1], (0.0, 0.0)); // Wobble inside the string is ignored
+        assert_eq!(smoothed[2], (6.0, 0.0));
+        assert_eq!(smoothed[3], (12.0, 0.0)); // Ends at the pen
+        assert!(Stabilizer::PullString { length: -1.0 }.validate().is_err());
+    }
+}
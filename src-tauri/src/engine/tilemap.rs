@@ -0,0 +1,370 @@
+// Tilesets and tilemaps
+// A tileset holds fixed-size tiles; a tilemap is a grid of indices into it,
+// so drawing places tiles instead of pixels
+use super::pixel_buffer::PixelBuffer;
+use crate::error::{AipixError, Result};
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+
+const MAX_HISTORY_SIZE: usize = 50; // Tilemap undo states kept, as for canvases
+
+/// Largest tile side accepted
+pub const MAX_TILE_SIZE: u32 = 256;
+
+#[derive(Debug, Clone)]
+pub struct Tileset {
+    pub tile_width: u32,
+    pub tile_height: u32,
+    pub tiles: Vec<PixelBuffer>,
+}
+
+impl Tileset {
+    pub fn new(tile_width: u32, tile_height: u32) -> Result<Self> {
+        if tile_width == 0 || tile_height == 0 || tile_width > MAX_TILE_SIZE || tile_height > MAX_TILE_SIZE {
+            return Err(AipixError::InvalidInput(format!(
+                "Tile size must be between 1 and {}",
+                MAX_TILE_SIZE
+            )));
+        }
+        Ok(Self { tile_width, tile_height, tiles: Vec::new() })
+    }
+
+    pub fn len(&self) -> usize {
+        self.tiles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tiles.is_empty()
+    }
+
+    pub fn get(&self, index: u32) -> Option<&PixelBuffer> {
+        self.tiles.get(index as usize)
+    }
+
+    fn check_size(&self, tile: &PixelBuffer) -> Result<()> {
+        if (tile.width, tile.height) != (self.tile_width, self.tile_height) {
+            return Err(AipixError::InvalidInput(format!(
+                "Tiles in this set are {}x{}",
+                self.tile_width, self.tile_height
+            )));
+        }
+        Ok(())
+    }
+
+    /// Append a tile and return its index
+    pub fn add(&mut self, tile: PixelBuffer) -> Result<u32> {
+        self.check_size(&tile)?;
+        self.tiles.push(tile);
+        Ok(self.tiles.len() as u32 - 1)
+    }
+
+    pub fn replace(&mut self, index: u32, tile: PixelBuffer) -> Result<()> {
+        self.check_size(&tile)?;
+        let slot = self.tiles.get_mut(index as usize).ok_or(AipixError::NotFound("Tile"))?;
+        *slot = tile;
+        Ok(())
+    }
+
+    /// Cut `buffer` into tiles, row by row; partial tiles at the edges are dropped
+    ///
+    /// Returns the tilemap that rebuilds the buffer from the new tiles. Fully
+    /// transparent tiles become empty cells when `skip_empty` is set, and
+    /// identical tiles share one entry when `deduplicate` is set.
+    pub fn slice(&mut self, buffer: &PixelBuffer, skip_empty: bool, deduplicate: bool) -> Tilemap {
+        let columns = buffer.width / self.tile_width;
+        let rows = buffer.height / self.tile_height;
+        let mut map = Tilemap::new(columns, rows);
+        let mut seen: HashMap<Vec<u8>, u32> = HashMap::new();
+
+        for row in 0..rows {
+            for column in 0..columns {
+                let tile = buffer.copy_region(
+                    column * self.tile_width,
+                    row * self.tile_height,
+                    self.tile_width,
+                    self.tile_height,
+                );
+                if skip_empty && tile.pixels().all(|pixel| pixel[3] == 0) {
+                    continue;
+                }
+
+                let index = match deduplicate.then(|| seen.get(&tile.to_rgba()).copied()).flatten() {
+                    Some(index) => index,
+                    None => {
+                        if deduplicate {
+                            seen.insert(tile.to_rgba(), self.tiles.len() as u32);
+                        }
+                        self.tiles.push(tile);
+                        self.tiles.len() as u32 - 1
+                    }
+                };
+                map.cells[(row * columns + column) as usize] = Some(index);
+            }
+        }
+        map
+    }
+
+    /// All tiles packed into one image, `columns` per row (the Tiled tileset image)
+    pub fn atlas(&self, columns: u32) -> PixelBuffer {
+        let columns = columns.max(1);
+        let rows = (self.tiles.len() as u32).div_ceil(columns).max(1);
+        let mut atlas = PixelBuffer::new(columns * self.tile_width, rows * self.tile_height);
+        for (i, tile) in self.tiles.iter().enumerate() {
+            let i = i as u32;
+            atlas.blit(
+                tile,
+                ((i % columns) * self.tile_width) as i32,
+                ((i / columns) * self.tile_height) as i32,
+            );
+        }
+        atlas
+    }
+}
+
+/// A grid of tile indices; `None` is an empty cell
+#[derive(Debug, Clone, Serialize)]
+pub struct Tilemap {
+    pub width: u32,  // In tiles
+    pub height: u32, // In tiles
+    pub cells: Vec<Option<u32>>,
+}
+
+impl Tilemap {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self { width, height, cells: vec![None; (width * height) as usize] }
+    }
+
+    fn offset(&self, x: u32, y: u32) -> Option<usize> {
+        (x < self.width && y < self.height).then(|| (y * self.width + x) as usize)
+    }
+
+    pub fn get(&self, x: u32, y: u32) -> Option<Option<u32>> {
+        self.offset(x, y).map(|i| self.cells[i])
+    }
+
+    pub fn set(&mut self, x: u32, y: u32, tile: Option<u32>) -> Result<()> {
+        let i = self.offset(x, y).ok_or(AipixError::OutOfBounds)?;
+        self.cells[i] = tile;
+        Ok(())
+    }
+
+    /// Place a `width`-wide block of cells with its top-left at (x, y), clipped to the map
+    ///
+    /// `None` entries in the block are written too, so stamps can erase.
+    pub fn stamp(&mut self, x: i32, y: i32, width: u32, tiles: &[Option<u32>]) -> Result<()> {
+        if width == 0 || !tiles.len().is_multiple_of(width as usize) {
+            return Err(AipixError::InvalidInput(
+                "Stamp must be a whole number of rows".to_string(),
+            ));
+        }
+
+        for (i, &tile) in tiles.iter().enumerate() {
+            let cell_x = x as i64 + (i % width as usize) as i64;
+            let cell_y = y as i64 + (i / width as usize) as i64;
+            if cell_x < 0 || cell_y < 0 {
+                continue;
+            }
+            if let Some(offset) = self.offset(cell_x as u32, cell_y as u32) {
+                self.cells[offset] = tile;
+            }
+        }
+        Ok(())
+    }
+
+    /// Replace the 4-connected region of cells equal to the one at (x, y)
+    pub fn fill(&mut self, x: u32, y: u32, tile: Option<u32>) -> Result<()> {
+        let start = self.offset(x, y).ok_or(AipixError::OutOfBounds)?;
+        let target = self.cells[start];
+        if target == tile {
+            return Ok(());
+        }
+
+        let mut queue = VecDeque::from([(x, y)]);
+        self.cells[start] = tile;
+        while let Some((x, y)) = queue.pop_front() {
+            let neighbors = [
+                (x.wrapping_sub(1), y),
+                (x + 1, y),
+                (x, y.wrapping_sub(1)),
+                (x, y + 1),
+            ];
+            for (nx, ny) in neighbors {
+                if let Some(i) = self.offset(nx, ny) {
+                    if self.cells[i] == target {
+                        self.cells[i] = tile;
+                        queue.push_back((nx, ny));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Fix up indices after tile `index` was removed from the tileset
+    fn remove_tile(&mut self, index: u32) {
+        for cell in self.cells.iter_mut() {
+            *cell = match *cell {
+                Some(i) if i == index => None,
+                Some(i) if i > index => Some(i - 1),
+                other => other,
+            };
+        }
+    }
+
+    /// Draw the map with `tileset`; cells pointing past the tileset stay transparent
+    pub fn render(&self, tileset: &Tileset) -> PixelBuffer {
+        let mut output = PixelBuffer::new(self.width * tileset.tile_width, self.height * tileset.tile_height);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let Some(tile) = self.cells[(y * self.width + x) as usize].and_then(|i| tileset.get(i)) else {
+                    continue;
+                };
+                output.blit(tile, (x * tileset.tile_width) as i32, (y * tileset.tile_height) as i32);
+            }
+        }
+        output
+    }
+}
+
+/// An open tilemap with its tileset, undoable as a unit
+#[derive(Clone)]
+pub struct TilemapDocument {
+    pub tileset: Tileset,
+    pub map: Tilemap,
+    undo_stack: Vec<(Tileset, Tilemap)>,
+    redo_stack: Vec<(Tileset, Tilemap)>,
+}
+
+impl TilemapDocument {
+    pub fn new(tileset: Tileset, map: Tilemap) -> Self {
+        Self { tileset, map, undo_stack: Vec::new(), redo_stack: Vec::new() }
+    }
+
+    /// Save the current state before a change
+    pub fn push_state(&mut self) {
+        self.undo_stack.push((self.tileset.clone(), self.map.clone()));
+        if self.undo_stack.len() > MAX_HISTORY_SIZE {
+            self.undo_stack.remove(0);
+        }
+        self.redo_stack.clear();
+    }
+
+    pub fn undo(&mut self) -> Result<()> {
+        let previous = self
+            .undo_stack
+            .pop()
+            .ok_or_else(|| AipixError::InvalidState("Nothing to undo".to_string()))?;
+        let current = self.swap(previous);
+        self.redo_stack.push(current);
+        Ok(())
+    }
+
+    pub fn redo(&mut self) -> Result<()> {
+        let next = self
+            .redo_stack
+            .pop()
+            .ok_or_else(|| AipixError::InvalidState("Nothing to redo".to_string()))?;
+        let current = self.swap(next);
+        self.undo_stack.push(current);
+        Ok(())
+    }
+
+    fn swap(&mut self, (tileset, map): (Tileset, Tilemap)) -> (Tileset, Tilemap) {
+        (std::mem::replace(&mut self.tileset, tileset), std::mem::replace(&mut self.map, map))
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo_stack.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo_stack.is_empty()
+    }
+
+    /// Remove a tile; cells using it become empty and later indices shift down
+    pub fn remove_tile(&mut self, index: u32) -> Result<PixelBuffer> {
+        if index as usize >= self.tileset.tiles.len() {
+            return Err(AipixError::NotFound("Tile"));
+        }
+        self.map.remove_tile(index);
+        Ok(self.tileset.tiles.remove(index as usize))
+    }
+
+    /// Check that a tile index exists before placing it
+    pub fn check_tile(&self, tile: Option<u32>) -> Result<()> {
+        match tile {
+            Some(index) if index as usize >= self.tileset.len() => Err(AipixError::NotFound("Tile")),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: [u8; 4] = [255, 0, 0, 255];
+    const BLUE: [u8; 4] = [0, 0, 255, 255];
+
+    fn tile(color: [u8; 4]) -> PixelBuffer {
+        let mut tile = PixelBuffer::new(2, 2);
+        tile.clear(color);
+        tile
+    }
+
+    #[test]
+    fn test_slice_and_render() {
+        // 3x2 tiles: red, transparent, red / blue, blue, red
+        let mut buffer = PixelBuffer::new(6, 4);
+        for (i, color) in [Some(RED), None, Some(RED), Some(BLUE), Some(BLUE), Some(RED)].iter().enumerate() {
+            if let Some(color) = color {
+                buffer.fill_rect((i as u32 % 3) * 2, (i as u32 / 3) * 2, 2, 2, *color);
+            }
+        }
+
+        let mut tileset = Tileset::new(2, 2).unwrap();
+        let map = tileset.slice(&buffer, true, true);
+
+        assert_eq!(tileset.len(), 2);
+        assert_eq!(map.cells, vec![Some(0), None, Some(0), Some(1), Some(1), Some(0)]);
+        assert_eq!(map.render(&tileset), buffer);
+    }
+
+    #[test]
+    fn test_fill_and_stamp() {
+        let mut map = Tilemap::new(4, 3);
+        map.set(1, 0, Some(2)).unwrap();
+        map.set(1, 1, Some(2)).unwrap();
+        map.set(0, 1, Some(2)).unwrap();
+
+        // The wall of 2s keeps the fill out of the top-left corner
+        map.fill(3, 2, Some(7)).unwrap();
+        assert_eq!(map.get(0, 0), Some(None));
+        assert_eq!(map.get(2, 0), Some(Some(7)));
+
+        map.stamp(3, 1, 2, &[Some(1), None, Some(1), None]).unwrap();
+        assert_eq!(map.get(3, 1), Some(Some(1)));
+        assert_eq!(map.get(3, 2), Some(Some(1)));
+        assert!(map.stamp(0, 0, 3, &[Some(1); 4]).is_err());
+    }
+
+    #[test]
+    fn test_remove_tile_and_undo() {
+        let mut tileset = Tileset::new(2, 2).unwrap();
+        tileset.add(tile(RED)).unwrap();
+        tileset.add(tile(BLUE)).unwrap();
+        let mut map = Tilemap::new(2, 1);
+        map.cells = vec![Some(0), Some(1)];
+        let mut document = TilemapDocument::new(tileset, map);
+
+        document.push_state();
+        document.remove_tile(0).unwrap();
+        assert_eq!(document.map.cells, vec![None, Some(0)]);
+        assert_eq!(document.tileset.len(), 1);
+
+        document.undo().unwrap();
+        assert_eq!(document.map.cells, vec![Some(0), Some(1)]);
+        assert_eq!(document.tileset.len(), 2);
+        assert!(document.can_redo());
+    }
+}
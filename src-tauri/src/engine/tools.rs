@@ -1,35 +1,190 @@
 // Drawing tools implementation
+use super::brush::{stamp, stamp_offsets, Brush, BrushShape, Footprint};
+use super::dynamics::BrushDynamics;
+use super::guides::{nearest_guide, Guide, GuideOrientation};
+use super::palette::Palette;
 use super::pixel_buffer::PixelBuffer;
-use std::collections::VecDeque;
+use crate::error::{AipixError, Result};
+use std::collections::{HashMap, HashSet, VecDeque};
 
-/// Convert hex color string to RGBA
-pub fn hex_to_rgba(hex: &str) -> Result<[u8; 4], String> {
+/// Convert hex color string (#rrggbb, or #rrggbbaa with alpha) to RGBA
+pub fn hex_to_rgba(hex: &str) -> Result<[u8; 4]> {
     let hex = hex.trim_start_matches('#');
 
-    if hex.len() != 6 {
-        return Err("Invalid hex color format".to_string());
+    if hex.len() != 6 && hex.len() != 8 {
+        return Err(invalid_hex());
     }
 
-    let r = u8::from_str_radix(&hex[0..2], 16).map_err(|_| "Invalid hex color")?;
-    let g = u8::from_str_radix(&hex[2..4], 16).map_err(|_| "Invalid hex color")?;
-    let b = u8::from_str_radix(&hex[4..6], 16).map_err(|_| "Invalid hex color")?;
+    let channel = |i: usize| {
+        hex.get(i..i + 2)
+            .and_then(|digits| u8::from_str_radix(digits, 16).ok())
+            .ok_or_else(invalid_hex)
+    };
+    let a = if hex.len() == 8 { channel(6)? } else { 255 };
+
+    Ok([channel(0)?, channel(2)?, channel(4)?, a])
+}
 
-    Ok([r, g, b, 255])
+fn invalid_hex() -> AipixError {
+    AipixError::InvalidInput("Invalid hex color format".to_string())
 }
 
-/// Convert RGBA to hex color string
+/// Convert RGBA to hex color string: #rrggbb, or #rrggbbaa when not opaque
 pub fn rgba_to_hex(rgba: [u8; 4]) -> String {
-    format!("#{:02x}{:02x}{:02x}", rgba[0], rgba[1], rgba[2])
+    match rgba[3] {
+        255 => format!("#{:02x}{:02x}{:02x}", rgba[0], rgba[1], rgba[2]),
+        a => format!("#{:02x}{:02x}{:02x}{:02x}", rgba[0], rgba[1], rgba[2], a),
+    }
+}
+
+/// Convert RGB to HSL (hue in degrees 0-360, saturation and lightness 0-1)
+pub fn rgb_to_hsl(rgb: [u8; 3]) -> (f32, f32, f32) {
+    let r = rgb[0] as f32 / 255.0;
+    let g = rgb[1] as f32 / 255.0;
+    let b = rgb[2] as f32 / 255.0;
+
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let lightness = (max + min) / 2.0;
+    let delta = max - min;
+
+    if delta == 0.0 {
+        return (0.0, 0.0, lightness);
+    }
+
+    let saturation = delta / (1.0 - (2.0 * lightness - 1.0).abs());
+    let hue = if max == r {
+        60.0 * ((g - b) / delta).rem_euclid(6.0)
+    } else if max == g {
+        60.0 * ((b - r) / delta + 2.0)
+    } else {
+        60.0 * ((r - g) / delta + 4.0)
+    };
+
+    (hue, saturation, lightness)
 }
 
-/// Pencil tool - draws a single pixel
-pub fn pencil(buffer: &mut PixelBuffer, x: u32, y: u32, color: [u8; 4]) -> Result<(), String> {
-    buffer.set_pixel(x, y, color)
+/// Convert HSL (hue in degrees, saturation and lightness 0-1) to RGB
+pub fn hsl_to_rgb(hue: f32, saturation: f32, lightness: f32) -> [u8; 3] {
+    let hue = hue.rem_euclid(360.0);
+    let saturation = saturation.clamp(0.0, 1.0);
+    let lightness = lightness.clamp(0.0, 1.0);
+
+    let chroma = (1.0 - (2.0 * lightness - 1.0).abs()) * saturation;
+    let x = chroma * (1.0 - ((hue / 60.0).rem_euclid(2.0) - 1.0).abs());
+    let m = lightness - chroma / 2.0;
+
+    let (r, g, b) = match (hue / 60.0) as u32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+
+    [
+        ((r + m) * 255.0).round() as u8,
+        ((g + m) * 255.0).round() as u8,
+        ((b + m) * 255.0).round() as u8,
+    ]
 }
 
-/// Eraser tool - sets pixel to transparent
-pub fn eraser(buffer: &mut PixelBuffer, x: u32, y: u32) -> Result<(), String> {
-    buffer.set_pixel(x, y, [0, 0, 0, 0])
+/// Pencil tool - stamps `brush` (a single pixel by default) at (x, y)
+pub fn pencil(buffer: &mut PixelBuffer, x: u32, y: u32, color: [u8; 4], brush: &impl Footprint) -> Result<()> {
+    stamp(buffer, x as i32, y as i32, color, brush)
+}
+
+/// Pencil tool continuing a freehand stroke from its previous point
+///
+/// Input events arrive further apart than one pixel when the pointer moves
+/// fast; the gap back to `from` is filled with a Bresenham line. A `from`
+/// outside the canvas (e.g. after a resize) starts a new stroke. Returns the
+/// point the stroke was connected from, if any.
+pub fn pencil_stroke(
+    buffer: &mut PixelBuffer,
+    from: Option<(u32, u32)>,
+    x: u32,
+    y: u32,
+    color: [u8; 4],
+    brush: &impl Footprint,
+) -> Result<Option<(u32, u32)>> {
+    let from = from.filter(|&(from_x, from_y)| buffer.get_pixel(from_x, from_y).is_some());
+    match from {
+        Some((from_x, from_y)) => {
+            if buffer.get_pixel(x, y).is_none() {
+                return Err(AipixError::OutOfBounds);
+            }
+            brush_line(buffer, from_x as i32, from_y as i32, x as i32, y as i32, color, brush)?
+        }
+        None => pencil(buffer, x, y, color, brush)?,
+    }
+    Ok(from)
+}
+
+/// Pencil tool along a polyline - `brush` stamped on Bresenham lines joining `points` in turn
+///
+/// Lets a fast freehand stroke be drawn in one call instead of one per
+/// point. Stamps are clipped to the buffer, so the stroke may leave the
+/// canvas and come back; a point shared by two segments is stamped once.
+pub fn polyline(buffer: &mut PixelBuffer, points: &[(i32, i32)], color: [u8; 4], brush: &impl Footprint) -> Result<()> {
+    let offsets = brush.offsets();
+    if let Some(&(x, y)) = points.first() {
+        stamp_clipped(buffer, x, y, color, &offsets)?;
+    }
+    for segment in points.windows(2) {
+        let ((x0, y0), (x1, y1)) = (segment[0], segment[1]);
+        for (x, y) in line_points(x0, y0, x1, y1).into_iter().skip(1) {
+            stamp_clipped(buffer, x, y, color, &offsets)?;
+        }
+    }
+    Ok(())
+}
+
+/// `polyline` following stylus pressure, one value (0-1) per point
+///
+/// Each stamp takes the brush size and color alpha `dynamics` give for the
+/// pressure at that spot, interpolated between the points of its segment.
+pub fn pressure_polyline(
+    buffer: &mut PixelBuffer,
+    points: &[(i32, i32)],
+    pressures: &[f32],
+    color: [u8; 4],
+    brush: &Brush,
+    dynamics: &BrushDynamics,
+) -> Result<()> {
+    if pressures.len() != points.len() {
+        return Err(AipixError::InvalidInput(format!(
+            "A stroke of {} points needs as many pressures, not {}",
+            points.len(),
+            pressures.len()
+        )));
+    }
+    dynamics.validate()?;
+
+    let mut stamp_at = |x: i32, y: i32, pressure: f32| {
+        let size = dynamics.size(brush.size as f32, pressure).round() as u32;
+        let alpha = dynamics.opacity(color[3] as f32, pressure).round().clamp(0.0, 255.0) as u8;
+        let offsets = Brush { size, shape: brush.shape }.offsets();
+        stamp_clipped(buffer, x, y, [color[0], color[1], color[2], alpha], &offsets)
+    };
+    if let (Some(&(x, y)), Some(&pressure)) = (points.first(), pressures.first()) {
+        stamp_at(x, y, pressure)?;
+    }
+    for (segment, pressure) in points.windows(2).zip(pressures.windows(2)) {
+        let ((x0, y0), (x1, y1)) = (segment[0], segment[1]);
+        let line = line_points(x0, y0, x1, y1);
+        let steps = (line.len() - 1).max(1) as f32;
+        for (i, (x, y)) in line.into_iter().enumerate().skip(1) {
+            stamp_at(x, y, pressure[0] + (pressure[1] - pressure[0]) * i as f32 / steps)?;
+        }
+    }
+    Ok(())
+}
+
+/// Eraser tool - makes the pixels under `brush` transparent
+pub fn eraser(buffer: &mut PixelBuffer, x: u32, y: u32, brush: &impl Footprint) -> Result<()> {
+    stamp(buffer, x as i32, y as i32, [0, 0, 0, 0], brush)
 }
 
 /// Eyedropper tool - gets color at position
@@ -37,7 +192,220 @@ pub fn eyedropper(buffer: &PixelBuffer, x: u32, y: u32) -> Option<[u8; 4]> {
     buffer.get_pixel(x, y)
 }
 
-/// Line tool - draws a line using Bresenham's algorithm
+/// Largest eyedropper sample radius (a 65x65 area)
+pub const MAX_SAMPLE_RADIUS: u32 = 32;
+
+/// How the eyedropper reduces a sampled area to one color
+#[derive(Debug, Clone, Copy, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AreaSample {
+    #[default]
+    Average,  // Mean color, weighted by alpha
+    Dominant, // Most common color (the earliest in row order on a tie)
+}
+
+/// Eyedropper over the square of pixels within `radius` of (x, y), clipped to the buffer
+///
+/// Smooths out dithering and noise, e.g. in imported photos. A radius of 0
+/// samples just the one pixel.
+pub fn eyedropper_area(buffer: &PixelBuffer, x: u32, y: u32, radius: u32, mode: AreaSample) -> Option<[u8; 4]> {
+    buffer.get_pixel(x, y)?;
+    let (x0, y0) = (x.saturating_sub(radius), y.saturating_sub(radius));
+    let side = |start: u32, center: u32| (center - start).saturating_add(radius).saturating_add(1);
+    let area = buffer.copy_region(x0, y0, side(x0, x), side(y0, y));
+    let pixels = area.pixels().map(|p| [p[0], p[1], p[2], p[3]]);
+
+    match mode {
+        AreaSample::Average => {
+            let mut sum = [0u64; 4]; // Color premultiplied by alpha, then alpha
+            for [r, g, b, a] in pixels {
+                for (channel, value) in sum.iter_mut().zip([r, g, b]) {
+                    *channel += value as u64 * a as u64;
+                }
+                sum[3] += a as u64;
+            }
+            if sum[3] == 0 {
+                return Some([0, 0, 0, 0]);
+            }
+            let count = (area.width * area.height) as u64;
+            let unpremultiply = |channel: u64| ((channel + sum[3] / 2) / sum[3]) as u8;
+            Some([
+                unpremultiply(sum[0]),
+                unpremultiply(sum[1]),
+                unpremultiply(sum[2]),
+                ((sum[3] + count / 2) / count) as u8,
+            ])
+        }
+        AreaSample::Dominant => {
+            let mut counts: HashMap<[u8; 4], (usize, usize)> = HashMap::new(); // Count, first index
+            for (index, color) in pixels.enumerate() {
+                counts.entry(color).or_insert((0, index)).0 += 1;
+            }
+            counts
+                .into_iter()
+                .max_by_key(|&(_, (count, first))| (count, std::cmp::Reverse(first)))
+                .map(|(color, _)| color)
+        }
+    }
+}
+
+/// Drawing grid that shape and line endpoints snap to
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct SnapGrid {
+    pub cell_width: u32,
+    pub cell_height: u32,
+    #[serde(default)]
+    pub offset_x: i32,
+    #[serde(default)]
+    pub offset_y: i32,
+}
+
+impl SnapGrid {
+    pub fn validate(&self) -> Result<()> {
+        if self.cell_width == 0 || self.cell_height == 0 {
+            return Err(AipixError::InvalidInput("Grid cells must be at least 1x1".to_string()));
+        }
+        Ok(())
+    }
+
+    /// Nearest grid intersection to (x, y)
+    pub fn snap(&self, x: i32, y: i32) -> (i32, i32) {
+        let snap_axis = |value: i32, cell: u32, offset: i32| {
+            let cell = cell.max(1) as f64;
+            let cells = ((value as f64 - offset as f64) / cell).round();
+            (cells * cell + offset as f64).clamp(i32::MIN as f64, i32::MAX as f64) as i32
+        };
+        (
+            snap_axis(x, self.cell_width, self.offset_x),
+            snap_axis(y, self.cell_height, self.offset_y),
+        )
+    }
+
+}
+
+/// Everything shape tools and selections snap to
+///
+/// A guide within `guide_threshold` pixels wins over the grid on its axis.
+#[derive(Debug, Clone, Default)]
+pub struct Snapping {
+    pub grid: Option<SnapGrid>,
+    pub guides: Vec<Guide>,
+    pub guide_threshold: u32,
+}
+
+impl Snapping {
+    pub fn point(&self, x: i32, y: i32) -> (i32, i32) {
+        let (grid_x, grid_y) = self.grid.map_or((x, y), |grid| grid.snap(x, y));
+        let guide = |orientation, value| nearest_guide(&self.guides, orientation, value, self.guide_threshold);
+        (
+            guide(GuideOrientation::Vertical, x).unwrap_or(grid_x),
+            guide(GuideOrientation::Horizontal, y).unwrap_or(grid_y),
+        )
+    }
+
+    /// Snap the edges of an inclusive rectangle, clamped to the canvas origin
+    ///
+    /// The far edges snap by the boundary after their last pixel, so a
+    /// rectangle dragged across grid cells covers them whole.
+    pub fn rect(&self, x0: u32, y0: u32, x1: u32, y1: u32) -> (u32, u32, u32, u32) {
+        let to_i32 = |v: u32| v.min(i32::MAX as u32 - 1) as i32;
+        let (left, top) = self.point(to_i32(x0.min(x1)), to_i32(y0.min(y1)));
+        let (right, bottom) = self.point(to_i32(x0.max(x1)) + 1, to_i32(y0.max(y1)) + 1);
+        let end = |start: i32, stop: i32| if stop > start { stop - 1 } else { start };
+        let clamp = |v: i32| v.max(0) as u32;
+        (clamp(left), clamp(top), clamp(end(left, right)), clamp(end(top, bottom)))
+    }
+}
+
+/// Widest outline the shape tools draw, in pixels
+pub const MAX_THICKNESS: u32 = 16;
+
+pub fn validate_thickness(thickness: u32) -> Result<()> {
+    if !(1..=MAX_THICKNESS).contains(&thickness) {
+        return Err(AipixError::InvalidInput(format!(
+            "Thickness must be between 1 and {}",
+            MAX_THICKNESS
+        )));
+    }
+    Ok(())
+}
+
+/// Stamp `offsets` at (x, y) in `color`, clipped to the buffer even when (x, y) is off it
+fn stamp_clipped(buffer: &mut PixelBuffer, x: i32, y: i32, color: [u8; 4], offsets: &[(i32, i32)]) -> Result<()> {
+    for (dx, dy) in offsets {
+        let (px, py) = (x + dx, y + dy);
+        if px >= 0 && py >= 0 && (px as u32) < buffer.width && (py as u32) < buffer.height {
+            buffer.set_pixel(px as u32, py as u32, color)?;
+        }
+    }
+    Ok(())
+}
+
+/// A round brush `thickness` pixels across stamped at each of `points`, clipped to the buffer
+///
+/// How the shape tools widen their one-pixel outlines. Checks the
+/// thickness, so callers can leave one-pixel outlines to their own code.
+fn stroke_points(
+    buffer: &mut PixelBuffer,
+    points: impl IntoIterator<Item = (i32, i32)>,
+    color: [u8; 4],
+    thickness: u32,
+) -> Result<()> {
+    validate_thickness(thickness)?;
+    let offsets = Brush { size: thickness, shape: BrushShape::Circle }.offsets();
+    for (x, y) in points {
+        stamp_clipped(buffer, x, y, color, &offsets)?;
+    }
+    Ok(())
+}
+
+/// How the width of a thick line runs from end to end
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LineProfile {
+    #[default]
+    Uniform,
+    Tapered, // Narrowing to one pixel at both ends, over twice the width
+}
+
+/// Line tool with a width profile - `thickness` pixels wide, or at its widest when tapered
+///
+/// The line is stepped from its leftmost (then topmost) end whichever way
+/// it was drawn, so a line and its reverse cover the same pixels and a
+/// taper is the same at both ends. Widths are whole round stamps; nothing
+/// is antialiased.
+pub fn profiled_line(
+    buffer: &mut PixelBuffer,
+    start: (i32, i32),
+    end: (i32, i32),
+    color: [u8; 4],
+    thickness: u32,
+    profile: LineProfile,
+) -> Result<()> {
+    validate_thickness(thickness)?;
+    let ((x0, y0), (x1, y1)) = (start.min(end), start.max(end));
+    let points = line_points(x0, y0, x1, y1);
+
+    let footprints: Vec<Vec<(i32, i32)>> =
+        (1..=thickness).map(|size| Brush { size, shape: BrushShape::Circle }.offsets()).collect();
+    let last = points.len() - 1;
+    let taper = (2 * thickness) as f32;
+    for (i, &(x, y)) in points.iter().enumerate() {
+        let width = match profile {
+            LineProfile::Uniform => thickness,
+            LineProfile::Tapered => {
+                // Odd widths while narrowing, as even ones sit off-center
+                let along = (i.min(last - i) as f32 / taper).min(1.0);
+                let half = ((thickness - 1) as f32 / 2.0 * along).round() as u32;
+                if along < 1.0 { (1 + 2 * half).min(thickness) } else { thickness }
+            }
+        };
+        stamp_clipped(buffer, x, y, color, &footprints[width as usize - 1])?;
+    }
+    Ok(())
+}
+
+/// Line tool - draws a line `thickness` pixels wide using Bresenham's algorithm
 pub fn line(
     buffer: &mut PixelBuffer,
     x0: i32,
@@ -45,7 +413,22 @@ pub fn line(
     x1: i32,
     y1: i32,
     color: [u8; 4],
-) -> Result<(), String> {
+    thickness: u32,
+) -> Result<()> {
+    if thickness != 1 {
+        return profiled_line(buffer, (x0, y0), (x1, y1), color, thickness, LineProfile::Uniform);
+    }
+    for (x, y) in line_points(x0, y0, x1, y1) {
+        if x >= 0 && y >= 0 {
+            buffer.set_pixel(x as u32, y as u32, color)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Points of a Bresenham line from (x0, y0) to (x1, y1), both included
+pub(super) fn line_points(x0: i32, y0: i32, x1: i32, y1: i32) -> Vec<(i32, i32)> {
     let dx = (x1 - x0).abs();
     let dy = -(y1 - y0).abs();
     let sx = if x0 < x1 { 1 } else { -1 };
@@ -54,11 +437,10 @@ pub fn line(
 
     let mut x = x0;
     let mut y = y0;
+    let mut points = Vec::new();
 
     loop {
-        if x >= 0 && y >= 0 {
-            buffer.set_pixel(x as u32, y as u32, color)?;
-        }
+        points.push((x, y));
 
         if x == x1 && y == y1 {
             break;
@@ -75,60 +457,98 @@ pub fn line(
         }
     }
 
+    points
+}
+
+/// Line of `brush` stamps, one at each point of a one-pixel line
+pub fn brush_line(
+    buffer: &mut PixelBuffer,
+    x0: i32,
+    y0: i32,
+    x1: i32,
+    y1: i32,
+    color: [u8; 4],
+    brush: &impl Footprint,
+) -> Result<()> {
+    let offsets = brush.offsets();
+    if offsets == [(0, 0)] {
+        return line(buffer, x0, y0, x1, y1, color, 1);
+    }
+
+    for (x, y) in line_points(x0, y0, x1, y1) {
+        if x >= 0 && y >= 0 {
+            stamp_offsets(buffer, x, y, color, &offsets)?;
+        }
+    }
     Ok(())
 }
 
-/// Rectangle tool - draws a filled or outlined rectangle
+/// Rectangle tool - draws a filled rectangle or one outlined `thickness` pixels wide
 pub fn rectangle(
     buffer: &mut PixelBuffer,
-    x0: u32,
-    y0: u32,
-    x1: u32,
-    y1: u32,
+    (x0, y0): (u32, u32),
+    (x1, y1): (u32, u32),
     color: [u8; 4],
     filled: bool,
-) -> Result<(), String> {
+    thickness: u32,
+) -> Result<()> {
     let min_x = x0.min(x1);
     let max_x = x0.max(x1);
     let min_y = y0.min(y1);
     let max_y = y0.max(y1);
 
+    if max_x >= buffer.width || max_y >= buffer.height {
+        return Err(AipixError::OutOfBounds);
+    }
+
+    let width = max_x - min_x + 1;
+    let height = max_y - min_y + 1;
+
     if filled {
         // Fill the rectangle
-        for y in min_y..=max_y {
-            for x in min_x..=max_x {
-                buffer.set_pixel(x, y, color)?;
-            }
-        }
+        buffer.fill_rect(min_x, min_y, width, height, color);
+    } else if thickness != 1 {
+        let (min_x, min_y, max_x, max_y) = (min_x as i32, min_y as i32, max_x as i32, max_y as i32);
+        let corners = [(min_x, min_y), (max_x, min_y), (max_x, max_y), (min_x, max_y), (min_x, min_y)];
+        let edges = corners.windows(2).flat_map(|edge| line_points(edge[0].0, edge[0].1, edge[1].0, edge[1].1));
+        stroke_points(buffer, edges, color, thickness)?;
     } else {
-        // Draw outline
-        for x in min_x..=max_x {
-            buffer.set_pixel(x, min_y, color)?;
-            buffer.set_pixel(x, max_y, color)?;
-        }
-        for y in min_y..=max_y {
-            buffer.set_pixel(min_x, y, color)?;
-            buffer.set_pixel(max_x, y, color)?;
-        }
+        // Draw outline: top and bottom edges, then the sides
+        buffer.fill_rect(min_x, min_y, width, 1, color);
+        buffer.fill_rect(min_x, max_y, width, 1, color);
+        buffer.fill_rect(min_x, min_y, 1, height, color);
+        buffer.fill_rect(max_x, min_y, 1, height, color);
     }
 
     Ok(())
 }
 
+/// Whether `color` is close enough to `target` for a fill with `tolerance`
+///
+/// RGB is compared as for the magic wand and alpha on its own, so a
+/// transparent pixel never matches an opaque one of the same RGB.
+fn fill_matches(color: [u8; 4], target: [u8; 4], tolerance: u8) -> bool {
+    color_distance(color, target) <= tolerance && color[3].abs_diff(target[3]) <= tolerance
+}
+
 /// Fill/Bucket tool - flood fill using BFS
+///
+/// Connected pixels within `tolerance` of the clicked color are painted;
+/// 0 only fills the exact color.
 pub fn fill(
     buffer: &mut PixelBuffer,
     x: u32,
     y: u32,
     new_color: [u8; 4],
-) -> Result<(), String> {
+    tolerance: u8,
+) -> Result<()> {
     let target_color = match buffer.get_pixel(x, y) {
         Some(c) => c,
-        None => return Err("Invalid starting position".to_string()),
+        None => return Err(AipixError::OutOfBounds),
     };
 
     // If the target color is the same as new color, nothing to do
-    if target_color == new_color {
+    if target_color == new_color && tolerance == 0 {
         return Ok(());
     }
 
@@ -137,21 +557,25 @@ pub fn fill(
 
     let width = buffer.width;
     let height = buffer.height;
+    // The new color may itself be within tolerance, so filled pixels are tracked
+    let mut visited = vec![false; (width * height) as usize];
 
     while let Some((px, py)) = queue.pop_front() {
         // Check bounds
         if px >= width || py >= height {
             continue;
         }
+        let index = (py * width + px) as usize;
+        if visited[index] {
+            continue;
+        }
 
         // Check if pixel matches target color
-        if let Some(current_color) = buffer.get_pixel(px, py) {
-            if current_color != target_color {
-                continue;
-            }
-        } else {
-            continue;
+        match buffer.get_pixel(px, py) {
+            Some(current_color) if fill_matches(current_color, target_color, tolerance) => {}
+            _ => continue,
         }
+        visited[index] = true;
 
         // Fill this pixel
         buffer.set_pixel(px, py, new_color)?;
@@ -174,16 +598,50 @@ pub fn fill(
     Ok(())
 }
 
-/// Circle tool - draws a filled or outlined circle using Bresenham's algorithm
+/// Global fill - paint every pixel close to the color at (x, y), connected or not
+///
+/// Pixels match when their RGB and alpha each differ from the clicked color
+/// by at most `tolerance` (averaged over RGB, as for the magic wand). Only
+/// selected pixels are painted when there is a `selection`. Returns the
+/// bounds of the pixels changed, if any.
+pub fn fill_global(
+    buffer: &mut PixelBuffer,
+    x: u32,
+    y: u32,
+    new_color: [u8; 4],
+    tolerance: u8,
+    selection: Option<&Selection>,
+) -> Result<Option<SelectionBounds>> {
+    let target_color = buffer.get_pixel(x, y).ok_or(AipixError::OutOfBounds)?;
+
+    let mut changed: Option<SelectionBounds> = None;
+    for py in 0..buffer.height {
+        for px in 0..buffer.width {
+            let Some(color) = buffer.get_pixel(px, py) else { continue };
+            if color == new_color
+                || !fill_matches(color, target_color, tolerance)
+                || selection.is_some_and(|s| !s.is_selected(px, py))
+            {
+                continue;
+            }
+            buffer.set_pixel(px, py, new_color)?;
+            let point = SelectionBounds::point(px, py);
+            changed = Some(changed.map_or(point, |bounds| bounds.union(point)));
+        }
+    }
+    Ok(changed)
+}
+
+/// Circle tool - draws a filled circle, or one outlined `thickness` pixels
+/// wide, using Bresenham's algorithm
 pub fn circle(
     buffer: &mut PixelBuffer,
-    center_x: i32,
-    center_y: i32,
-    end_x: i32,
-    end_y: i32,
+    (center_x, center_y): (i32, i32),
+    (end_x, end_y): (i32, i32),
     color: [u8; 4],
     filled: bool,
-) -> Result<(), String> {
+    thickness: u32,
+) -> Result<()> {
     // Calculate radius from center to end point
     let dx = end_x - center_x;
     let dy = end_y - center_y;
@@ -211,10 +669,11 @@ pub fn circle(
         let mut x = radius;
         let mut y = 0;
         let mut decision_over_2 = 1 - x;
+        let mut outline = Vec::new();
 
         while y <= x {
-            // Draw 8-way symmetry points
-            let points = [
+            // 8-way symmetry points
+            outline.extend([
                 (center_x + x, center_y + y),
                 (center_x - x, center_y + y),
                 (center_x + x, center_y - y),
@@ -223,13 +682,7 @@ pub fn circle(
                 (center_x - y, center_y + x),
                 (center_x + y, center_y - x),
                 (center_x - y, center_y - x),
-            ];
-
-            for (px, py) in points.iter() {
-                if *px >= 0 && *py >= 0 {
-                    buffer.set_pixel(*px as u32, *py as u32, color)?;
-                }
-            }
+            ]);
 
             y += 1;
             if decision_over_2 <= 0 {
@@ -239,11 +692,411 @@ pub fn circle(
                 decision_over_2 += 2 * (y - x) + 1;
             }
         }
+
+        if thickness != 1 {
+            return stroke_points(buffer, outline, color, thickness);
+        }
+        for (px, py) in outline {
+            if px >= 0 && py >= 0 {
+                buffer.set_pixel(px as u32, py as u32, color)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Inclusive box (min_x, min_y, max_x, max_y) of a shape dragged from (x0, y0) to (x1, y1)
+///
+/// With `from_center` the drag starts at the shape's center and the box
+/// grows the same distance either side of it; `constrain_aspect` makes it
+/// square, as long as the longer side of the drag, keeping its direction.
+pub fn shape_box(
+    (x0, y0): (i32, i32),
+    (x1, y1): (i32, i32),
+    from_center: bool,
+    constrain_aspect: bool,
+) -> (i32, i32, i32, i32) {
+    let (mut dx, mut dy) = (x1.saturating_sub(x0), y1.saturating_sub(y0));
+    if constrain_aspect {
+        let side = dx.saturating_abs().max(dy.saturating_abs());
+        let toward = |d: i32| if d < 0 { -side } else { side };
+        (dx, dy) = (toward(dx), toward(dy));
+    }
+
+    let (x1, y1) = (x0.saturating_add(dx), y0.saturating_add(dy));
+    if from_center {
+        let (dx, dy) = (dx.saturating_abs(), dy.saturating_abs());
+        (x0.saturating_sub(dx), y0.saturating_sub(dy), x0.saturating_add(dx), y0.saturating_add(dy))
+    } else {
+        (x0.min(x1), y0.min(y1), x0.max(x1), y0.max(y1))
+    }
+}
+
+/// Ellipse tool - the ellipse filling the inclusive box from (min_x, min_y)
+/// to (max_x, max_y), clipped to the buffer
+///
+/// Pixels are in when their centers are, so the shape is symmetric in both
+/// axes and a square box gives a circle. The outline is the inside pixels
+/// with a side facing out, widened to `thickness` pixels around them.
+pub fn ellipse(
+    buffer: &mut PixelBuffer,
+    (min_x, min_y): (i32, i32),
+    (max_x, max_y): (i32, i32),
+    color: [u8; 4],
+    filled: bool,
+    thickness: u32,
+) -> Result<()> {
+    let (min_x, max_x) = (min_x.min(max_x), min_x.max(max_x));
+    let (min_y, max_y) = (min_y.min(max_y), min_y.max(max_y));
+    let (radius_x, radius_y) = ((max_x - min_x + 1) as f64 / 2.0, (max_y - min_y + 1) as f64 / 2.0);
+    let (center_x, center_y) = (min_x as f64 + radius_x, min_y as f64 + radius_y);
+    let inside = |x: i32, y: i32| {
+        let (nx, ny) = ((x as f64 + 0.5 - center_x) / radius_x, (y as f64 + 0.5 - center_y) / radius_y);
+        // A hair of slack keeps pixels whose centers lie exactly on the edge
+        nx * nx + ny * ny <= 1.0 + 1e-9
+    };
+
+    let edge = |x: i32, y: i32| !(inside(x - 1, y) && inside(x + 1, y) && inside(x, y - 1) && inside(x, y + 1));
+
+    if !filled && thickness != 1 {
+        // Outline pixels just off the canvas still reach onto it
+        let reach = thickness.min(MAX_THICKNESS) as i32;
+        let (clip_x, clip_y) = (buffer.width as i32 - 1 + reach, buffer.height as i32 - 1 + reach);
+        let outline: Vec<(i32, i32)> = (min_y.max(-reach)..=max_y.min(clip_y))
+            .flat_map(|y| (min_x.max(-reach)..=max_x.min(clip_x)).map(move |x| (x, y)))
+            .filter(|&(x, y)| inside(x, y) && edge(x, y))
+            .collect();
+        return stroke_points(buffer, outline, color, thickness);
+    }
+
+    let (clip_x, clip_y) = (buffer.width as i32 - 1, buffer.height as i32 - 1);
+    for y in min_y.max(0)..=max_y.min(clip_y) {
+        for x in min_x.max(0)..=max_x.min(clip_x) {
+            if inside(x, y) && (filled || edge(x, y)) {
+                buffer.set_pixel(x as u32, y as u32, color)?;
+            }
+        }
     }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GradientKind {
+    #[default]
+    Linear, // Along the line from start to end
+    Radial, // Outward from start, reaching the end color at end's distance
+}
+
+/// Ordered dither used to mix the two colors of a gradient
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DitherPattern {
+    None, // A hard edge halfway
+    Bayer2,
+    #[default]
+    Bayer4,
+    Bayer8,
+}
 
+impl DitherPattern {
+    /// Threshold in 0-1 above which (x, y) takes the end color
+    pub(super) fn threshold(self, x: u32, y: u32) -> f32 {
+        let bits = match self {
+            DitherPattern::None => return 0.5,
+            DitherPattern::Bayer2 => 1,
+            DitherPattern::Bayer4 => 2,
+            DitherPattern::Bayer8 => 3,
+        };
+        // Each larger matrix repeats the 2x2 one, finer bits weighing more
+        const BAYER2: [[u32; 2]; 2] = [[0, 2], [3, 1]];
+        let index = (0..bits).fold(0, |index, bit| {
+            index + BAYER2[((y >> bit) & 1) as usize][((x >> bit) & 1) as usize] * 4u32.pow(bits - 1 - bit)
+        });
+        (index as f32 + 0.5) / 4u32.pow(bits) as f32
+    }
+}
+
+/// Dither brush colors: `secondary` where the pattern is below `density`, `primary` elsewhere
+///
+/// The pattern is anchored to the canvas, so overlapping stamps and
+/// separate strokes line up. Bayer 2x2 at a density of 0.5 is a checkerboard.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Dither {
+    pub primary: [u8; 4],
+    pub secondary: [u8; 4],
+    pub pattern: DitherPattern,
+    pub density: f32, // Share of pixels in the secondary color, 0-1
+}
+
+impl Dither {
+    pub fn validate(&self) -> Result<()> {
+        if !(0.0..=1.0).contains(&self.density) {
+            return Err(AipixError::InvalidInput("Dither density must be between 0 and 1".to_string()));
+        }
+        Ok(())
+    }
+
+    pub fn color_at(&self, x: u32, y: u32) -> [u8; 4] {
+        if self.density > self.pattern.threshold(x, y) {
+            self.secondary
+        } else {
+            self.primary
+        }
+    }
+}
+
+/// Dither brush - `brush` stamped along a line from (x0, y0) to (x1, y1), each pixel colored by `dither`
+///
+/// Clipped to the buffer; as for the pencil, a point off the canvas is an error.
+pub fn dither_line(
+    buffer: &mut PixelBuffer,
+    (x0, y0): (i32, i32),
+    (x1, y1): (i32, i32),
+    dither: &Dither,
+    brush: &impl Footprint,
+) -> Result<()> {
+    let offsets = brush.offsets();
+    for (x, y) in line_points(x0, y0, x1, y1) {
+        if x < 0 || y < 0 || buffer.get_pixel(x as u32, y as u32).is_none() {
+            return Err(AipixError::OutOfBounds);
+        }
+        for (dx, dy) in &offsets {
+            let (px, py) = (x + dx, y + dy);
+            if px >= 0 && py >= 0 && (px as u32) < buffer.width && (py as u32) < buffer.height {
+                buffer.set_pixel(px as u32, py as u32, dither.color_at(px as u32, py as u32))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ShadeDirection {
+    Lighten,
+    Darken,
+}
+
+/// Shading brush settings: how far each pass moves a pixel's color
+///
+/// Lightening turns hues toward yellow and darkening toward blue by up to
+/// `hue_shift` degrees, the usual pixel-art hue shifting. Greys keep no hue.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Shade {
+    pub direction: ShadeDirection,
+    pub strength: f32, // Lightness change, 0-1
+    pub hue_shift: f32, // Degrees, 0-180
+}
+
+impl Shade {
+    pub fn validate(&self) -> Result<()> {
+        if !(0.0..=1.0).contains(&self.strength) {
+            return Err(AipixError::InvalidInput("Shading strength must be between 0 and 1".to_string()));
+        }
+        if !(0.0..=180.0).contains(&self.hue_shift) {
+            return Err(AipixError::InvalidInput("Hue shift must be between 0 and 180 degrees".to_string()));
+        }
+        Ok(())
+    }
+
+    /// `color` shaded once; transparent pixels and alpha are left alone
+    pub fn apply(&self, color: [u8; 4]) -> [u8; 4] {
+        if color[3] == 0 {
+            return color;
+        }
+        let (hue, saturation, lightness) = rgb_to_hsl([color[0], color[1], color[2]]);
+        let (target_hue, lightness) = match self.direction {
+            ShadeDirection::Lighten => (60.0, lightness + self.strength),
+            ShadeDirection::Darken => (240.0, lightness - self.strength),
+        };
+        let turn = ((target_hue - hue + 540.0) % 360.0) - 180.0;
+        let hue = if saturation > 0.0 { hue + turn.clamp(-self.hue_shift, self.hue_shift) } else { hue };
+
+        let [r, g, b] = hsl_to_rgb(hue, saturation, lightness);
+        [r, g, b, color[3]]
+    }
+}
+
+/// Shading brush - shade every pixel under `brush` along a line from (x0, y0) to (x1, y1) once
+///
+/// With `continued` the stroke carries on from (x0, y0), whose footprint
+/// the previous segment already shaded, so it is left alone. Clipped to the
+/// buffer; as for the pencil, a point off the canvas is an error.
+pub fn shade_line(
+    buffer: &mut PixelBuffer,
+    (x0, y0): (i32, i32),
+    (x1, y1): (i32, i32),
+    shade: &Shade,
+    brush: &impl Footprint,
+    continued: bool,
+) -> Result<()> {
+    let offsets = brush.offsets();
+    let mut shaded = HashSet::new();
+    if continued {
+        shaded.extend(offsets.iter().map(|(dx, dy)| (x0 + dx, y0 + dy)));
+    }
+
+    for (x, y) in line_points(x0, y0, x1, y1) {
+        if x < 0 || y < 0 || buffer.get_pixel(x as u32, y as u32).is_none() {
+            return Err(AipixError::OutOfBounds);
+        }
+        for (dx, dy) in &offsets {
+            let (px, py) = (x + dx, y + dy);
+            if px < 0 || py < 0 || !shaded.insert((px, py)) {
+                continue;
+            }
+            if let Some(color) = buffer.get_pixel(px as u32, py as u32) {
+                buffer.set_pixel(px as u32, py as u32, shade.apply(color))?;
+            }
+        }
+    }
     Ok(())
 }
 
+/// Largest distance the jumble brush moves a pixel
+pub const MAX_JUMBLE_RADIUS: u32 = 8;
+
+/// Jumble brush settings: how far pixels move and how many of them do
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Jumble {
+    pub radius: u32,    // Farthest a pixel is swapped, 1-MAX_JUMBLE_RADIUS
+    pub intensity: f32, // Chance each pixel is swapped, 0-1
+    pub seed: u64,      // The same seed jumbles the same way
+}
+
+impl Jumble {
+    pub fn validate(&self) -> Result<()> {
+        if !(1..=MAX_JUMBLE_RADIUS).contains(&self.radius) {
+            return Err(AipixError::InvalidInput(format!(
+                "Jumble radius must be between 1 and {}",
+                MAX_JUMBLE_RADIUS
+            )));
+        }
+        if !(0.0..=1.0).contains(&self.intensity) {
+            return Err(AipixError::InvalidInput("Jumble intensity must be between 0 and 1".to_string()));
+        }
+        Ok(())
+    }
+}
+
+/// SplitMix64 of `seed` and a position, for noise that replays the same
+pub(super) fn position_hash(seed: u64, x: u32, y: u32) -> u64 {
+    let mut z = seed ^ ((x as u64) << 32 | y as u64);
+    z = z.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+/// Jumble brush - swap pixels under `brush` along a line from (x0, y0) to (x1, y1)
+/// with others up to `radius` away, scattering them into textured noise
+///
+/// Pixels only trade places within the stroke's footprint, so no color
+/// comes in from outside it. Clipped to the buffer; as for the pencil, a
+/// point off the canvas is an error.
+pub fn jumble_line(
+    buffer: &mut PixelBuffer,
+    (x0, y0): (i32, i32),
+    (x1, y1): (i32, i32),
+    jumble: &Jumble,
+    brush: &impl Footprint,
+) -> Result<()> {
+    let offsets = brush.offsets();
+    let mut footprint = Vec::new();
+    let mut covered = HashSet::new();
+    for (x, y) in line_points(x0, y0, x1, y1) {
+        if x < 0 || y < 0 || buffer.get_pixel(x as u32, y as u32).is_none() {
+            return Err(AipixError::OutOfBounds);
+        }
+        for (dx, dy) in &offsets {
+            let (px, py) = (x + dx, y + dy);
+            if px >= 0 && py >= 0 && buffer.get_pixel(px as u32, py as u32).is_some() && covered.insert((px, py)) {
+                footprint.push((px as u32, py as u32));
+            }
+        }
+    }
+
+    let (radius, span) = (jumble.radius as i64, 2 * jumble.radius as u64 + 1);
+    for (x, y) in footprint {
+        // The low half of the hash decides whether the pixel moves, the high half where to
+        let hash = position_hash(jumble.seed, x, y);
+        if (hash as u32) as f64 / (u32::MAX as f64 + 1.0) >= jumble.intensity as f64 {
+            continue;
+        }
+        let dx = ((hash >> 32) & 0xFFFF) % span;
+        let dy = (hash >> 48) % span;
+        let (tx, ty) = (x as i64 + dx as i64 - radius, y as i64 + dy as i64 - radius);
+        if (tx, ty) == (x as i64, y as i64) || !covered.contains(&(tx as i32, ty as i32)) {
+            continue;
+        }
+        let (tx, ty) = (tx as u32, ty as u32);
+        let (Some(here), Some(there)) = (buffer.get_pixel(x, y), buffer.get_pixel(tx, ty)) else {
+            continue;
+        };
+        buffer.set_pixel(x, y, there)?;
+        buffer.set_pixel(tx, ty, here)?;
+    }
+    Ok(())
+}
+
+/// Gradient tool settings: the colors at either end and how they're mixed
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Gradient {
+    pub from: [u8; 4], // At the start point
+    pub to: [u8; 4],   // At the end point, and past it
+    pub kind: GradientKind,
+    pub dither: DitherPattern,
+}
+
+/// Gradient tool - `gradient`'s two colors from (x0, y0) to (x1, y1), mixed by an ordered dither
+///
+/// Only pixels in `selection` are painted when it has any selected;
+/// otherwise the whole canvas is. Returns the bounds of the painted area.
+pub fn gradient(
+    buffer: &mut PixelBuffer,
+    (x0, y0): (i32, i32),
+    (x1, y1): (i32, i32),
+    gradient: &Gradient,
+    selection: Option<&Selection>,
+) -> Result<Option<SelectionBounds>> {
+    let Gradient { from, to, kind, dither } = *gradient;
+    let (dx, dy) = ((x1 - x0) as f32, (y1 - y0) as f32);
+    let length_squared = dx * dx + dy * dy;
+    if length_squared == 0.0 {
+        return Err(AipixError::InvalidInput("A gradient's start and end must differ".to_string()));
+    }
+
+    let selection = selection.filter(|selection| selection.bounds.is_some());
+    let bounds = match selection {
+        Some(selection) => selection.bounds,
+        None if buffer.width > 0 && buffer.height > 0 => Some(SelectionBounds {
+            min_x: 0,
+            max_x: buffer.width - 1,
+            min_y: 0,
+            max_y: buffer.height - 1,
+        }),
+        None => None,
+    };
+    let Some(area) = bounds else { return Ok(None) };
+
+    for y in area.min_y..=area.max_y {
+        for x in area.min_x..=area.max_x {
+            let coverage = selection.map_or(FULLY_SELECTED, |selection| selection.coverage(x, y));
+            let Some(old) = buffer.get_pixel(x, y).filter(|_| coverage > 0) else { continue };
+            let (px, py) = ((x as i32 - x0) as f32, (y as i32 - y0) as f32);
+            let t = match kind {
+                GradientKind::Linear => (px * dx + py * dy) / length_squared,
+                GradientKind::Radial => ((px * px + py * py) / length_squared).sqrt(),
+            };
+            let color = if t.clamp(0.0, 1.0) > dither.threshold(x, y) { to } else { from };
+            buffer.set_pixel(x, y, mix_coverage(old, color, coverage))?;
+        }
+    }
+    Ok(bounds)
+}
+
 /// Color Replace tool - replaces all instances of a target color with a new color
 pub fn replace_all_color(
     buffer: &mut PixelBuffer,
@@ -270,6 +1123,32 @@ pub fn replace_all_color(
     }
 }
 
+/// Antialias cleanup - snaps semi-transparent pixels to fully opaque or fully transparent
+///
+/// Meant for imported images whose edges were smoothed against a background.
+/// Pixels at least `threshold` opaque become solid in the nearest `palette`
+/// color (or their own color when the palette is empty); the rest are
+/// cleared. Returns the bounds of the pixels changed, if any.
+pub fn cleanup_antialiasing(buffer: &mut PixelBuffer, threshold: u8, palette: &Palette) -> Option<SelectionBounds> {
+    let mut changed: Option<SelectionBounds> = None;
+    for y in 0..buffer.height {
+        for x in 0..buffer.width {
+            let color = match buffer.get_pixel(x, y) {
+                Some(color) if color[3] > 0 && color[3] < 255 => color,
+                _ => continue,
+            };
+            let snapped = match color[3] >= threshold.max(1) {
+                true => palette.quantize([color[0], color[1], color[2], 255]),
+                false => [0, 0, 0, 0],
+            };
+            let _ = buffer.set_pixel(x, y, snapped);
+            let point = SelectionBounds::point(x, y);
+            changed = Some(changed.map_or(point, |bounds| bounds.union(point)));
+        }
+    }
+    changed
+}
+
 /// Selection types
 #[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
 pub enum SelectionMode {
@@ -279,13 +1158,22 @@ pub enum SelectionMode {
     Intersect, // Keep only overlap
 }
 
-/// Selection data structure - stores which pixels are selected
+/// Coverage of a fully selected pixel
+pub const FULLY_SELECTED: u8 = 255;
+
+/// Widest edge Selection::feather softens
+pub const MAX_FEATHER_RADIUS: u32 = 32;
+
+/// Selection data structure - stores how much of each pixel is selected
+///
+/// Pixels with partial coverage (feathered edges) take a proportional share
+/// of what tools do to them.
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct Selection {
     pub width: u32,
     pub height: u32,
-    pub mask: Vec<bool>, // true = selected, false = not selected
-    pub bounds: Option<SelectionBounds>,
+    pub mask: Vec<u8>, // Coverage: 0 = not selected, FULLY_SELECTED = selected, partly selected between
+    pub bounds: Option<SelectionBounds>, // Kept tight around the mask; update after writing to it directly
 }
 
 #[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize)]
@@ -296,12 +1184,39 @@ pub struct SelectionBounds {
     pub max_y: u32,
 }
 
+impl SelectionBounds {
+    pub fn point(x: u32, y: u32) -> Self {
+        SelectionBounds { min_x: x, max_x: x, min_y: y, max_y: y }
+    }
+
+    /// Bounds covering both
+    pub fn union(self, other: SelectionBounds) -> SelectionBounds {
+        SelectionBounds {
+            min_x: self.min_x.min(other.min_x),
+            max_x: self.max_x.max(other.max_x),
+            min_y: self.min_y.min(other.min_y),
+            max_y: self.max_y.max(other.max_y),
+        }
+    }
+
+    /// The overlap of both, if any
+    pub fn intersection(self, other: SelectionBounds) -> Option<SelectionBounds> {
+        let bounds = SelectionBounds {
+            min_x: self.min_x.max(other.min_x),
+            max_x: self.max_x.min(other.max_x),
+            min_y: self.min_y.max(other.min_y),
+            max_y: self.max_y.min(other.max_y),
+        };
+        (bounds.min_x <= bounds.max_x && bounds.min_y <= bounds.max_y).then_some(bounds)
+    }
+}
+
 impl Selection {
     pub fn new(width: u32, height: u32) -> Self {
         Selection {
             width,
             height,
-            mask: vec![false; (width * height) as usize],
+            mask: vec![0; (width * height) as usize],
             bounds: None,
         }
     }
@@ -311,61 +1226,84 @@ impl Selection {
     }
 
     pub fn clear(&mut self) {
-        self.mask.fill(false);
+        self.mask.fill(0);
         self.bounds = None;
     }
 
     pub fn select_pixel(&mut self, x: u32, y: u32, selected: bool) {
+        self.set_coverage(x, y, if selected { FULLY_SELECTED } else { 0 });
+    }
+
+    pub fn set_coverage(&mut self, x: u32, y: u32, coverage: u8) {
         if x < self.width && y < self.height {
             let index = (y * self.width + x) as usize;
-            self.mask[index] = selected;
+            self.mask[index] = coverage;
         }
     }
 
+    /// Whether any of the pixel is selected
     pub fn is_selected(&self, x: u32, y: u32) -> bool {
+        self.coverage(x, y) > 0
+    }
+
+    /// How much of the pixel is selected, 0 outside the canvas
+    pub fn coverage(&self, x: u32, y: u32) -> u8 {
         if x < self.width && y < self.height {
             let index = (y * self.width + x) as usize;
             self.mask[index]
         } else {
-            false
+            0
         }
     }
 
     /// Update selection bounds after modifying mask
     pub fn update_bounds(&mut self) {
-        let mut min_x = self.width;
-        let mut max_x = 0;
-        let mut min_y = self.height;
-        let mut max_y = 0;
-        let mut has_selection = false;
-
-        for y in 0..self.height {
-            for x in 0..self.width {
-                if self.is_selected(x, y) {
-                    has_selection = true;
-                    min_x = min_x.min(x);
-                    max_x = max_x.max(x);
-                    min_y = min_y.min(y);
-                    max_y = max_y.max(y);
-                }
+        let canvas = self.clip_bounds(0, 0, self.width as i64 - 1, self.height as i64 - 1);
+        self.update_bounds_within(canvas);
+    }
+
+    /// Update selection bounds scanning only `region`, which must hold every selected pixel
+    ///
+    /// Tools pass the area their edit could have left selected, so a small
+    /// selection on a huge canvas doesn't cost a scan of the whole mask.
+    pub fn update_bounds_within(&mut self, region: Option<SelectionBounds>) {
+        let mut bounds: Option<SelectionBounds> = None;
+        if let Some(region) = region {
+            for y in region.min_y..=region.max_y {
+                let row = (y * self.width) as usize;
+                let selected = &self.mask[row + region.min_x as usize..=row + region.max_x as usize];
+                let (Some(first), Some(last)) =
+                    (selected.iter().position(|&s| s > 0), selected.iter().rposition(|&s| s > 0))
+                else {
+                    continue;
+                };
+                let span = SelectionBounds {
+                    min_x: region.min_x + first as u32,
+                    max_x: region.min_x + last as u32,
+                    min_y: y,
+                    max_y: y,
+                };
+                bounds = Some(bounds.map_or(span, |bounds| bounds.union(span)));
             }
         }
+        self.bounds = bounds;
+    }
 
-        if has_selection {
-            self.bounds = Some(SelectionBounds {
-                min_x,
-                max_x,
-                min_y,
-                max_y,
-            });
-        } else {
-            self.bounds = None;
-        }
+    /// A box given by inclusive corners, clipped to the canvas
+    pub(super) fn clip_bounds(&self, min_x: i64, min_y: i64, max_x: i64, max_y: i64) -> Option<SelectionBounds> {
+        let (min_x, min_y) = (min_x.max(0), min_y.max(0));
+        let (max_x, max_y) = (max_x.min(self.width as i64 - 1), max_y.min(self.height as i64 - 1));
+        (min_x <= max_x && min_y <= max_y).then_some(SelectionBounds {
+            min_x: min_x as u32,
+            max_x: max_x as u32,
+            min_y: min_y as u32,
+            max_y: max_y as u32,
+        })
     }
 
     /// Select all pixels
     pub fn select_all(&mut self) {
-        self.mask.fill(true);
+        self.mask.fill(FULLY_SELECTED);
         self.bounds = Some(SelectionBounds {
             min_x: 0,
             max_x: self.width - 1,
@@ -377,10 +1315,44 @@ impl Selection {
     /// Invert selection
     pub fn invert(&mut self) {
         for pixel in self.mask.iter_mut() {
-            *pixel = !*pixel;
+            *pixel = FULLY_SELECTED - *pixel;
         }
         self.update_bounds();
     }
+
+    /// Soften the selection's edge over `radius` pixels, leaving it partly selected
+    ///
+    /// Coverage is box blurred across and then down, so the edge fades out
+    /// `radius` pixels either side of where it was.
+    pub fn feather(&mut self, radius: u32) {
+        let Some(bounds) = self.bounds.filter(|_| radius > 0) else { return };
+        let r = radius as i64;
+        let (min_x, min_y) = (bounds.min_x as i64 - r, bounds.min_y as i64 - r);
+        let Some(area) = self.clip_bounds(min_x, min_y, bounds.max_x as i64 + r, bounds.max_y as i64 + r) else {
+            return;
+        };
+
+        // Only pixels on the canvas are averaged, so a selection reaching its edge stays solid there
+        let (width, height) = (self.width as i64, self.height as i64);
+        let average = |coverages: &mut dyn Iterator<Item = u8>| {
+            let (sum, count) = coverages.fold((0, 0), |(sum, count), coverage| (sum + coverage as u32, count + 1));
+            ((sum + count / 2) / count.max(1)) as u8
+        };
+        let mut across = self.mask.clone();
+        for y in area.min_y as i64..=area.max_y as i64 {
+            for x in area.min_x as i64..=area.max_x as i64 {
+                let row = (x - r..=x + r).filter(|nx| (0..width).contains(nx));
+                across[(y * width + x) as usize] = average(&mut row.map(|nx| self.mask[(y * width + nx) as usize]));
+            }
+        }
+        for y in area.min_y as i64..=area.max_y as i64 {
+            for x in area.min_x as i64..=area.max_x as i64 {
+                let column = (y - r..=y + r).filter(|ny| (0..height).contains(ny));
+                self.mask[(y * width + x) as usize] = average(&mut column.map(|ny| across[(ny * width + x) as usize]));
+            }
+        }
+        self.update_bounds_within(Some(area));
+    }
 }
 
 /// Rectangular selection tool
@@ -398,21 +1370,21 @@ pub fn select_rectangle(
     let max_y = y0.max(y1);
 
     // Create temporary mask for this operation
-    let mut temp_mask = vec![false; (selection.width * selection.height) as usize];
+    let mut temp_mask = vec![0; (selection.width * selection.height) as usize];
 
     // Mark pixels in rectangle
     for y in min_y..=max_y {
         for x in min_x..=max_x {
             if x < selection.width && y < selection.height {
                 let index = (y * selection.width + x) as usize;
-                temp_mask[index] = true;
+                temp_mask[index] = FULLY_SELECTED;
             }
         }
     }
 
     // Apply selection mode
-    apply_selection_mode(selection, &temp_mask, mode);
-    selection.update_bounds();
+    let shape = selection.clip_bounds(min_x as i64, min_y as i64, max_x as i64, max_y as i64);
+    apply_selection_mode(selection, &temp_mask, shape, mode);
 }
 
 /// Elliptical selection tool
@@ -433,11 +1405,17 @@ pub fn select_ellipse(
     }
 
     // Create temporary mask for this operation
-    let mut temp_mask = vec![false; (selection.width * selection.height) as usize];
-
-    // Use ellipse equation: (x/a)^2 + (y/b)^2 <= 1
-    for y in 0..selection.height as i32 {
-        for x in 0..selection.width as i32 {
+    let mut temp_mask = vec![0; (selection.width * selection.height) as usize];
+    let shape = selection.clip_bounds(
+        center_x as i64 - dx as i64,
+        center_y as i64 - dy as i64,
+        center_x as i64 + dx as i64,
+        center_y as i64 + dy as i64,
+    );
+
+    // Use ellipse equation: (x/a)^2 + (y/b)^2 <= 1, within the ellipse's bounding box
+    for y in shape.iter().flat_map(|b| b.min_y as i32..=b.max_y as i32) {
+        for x in shape.iter().flat_map(|b| b.min_x as i32..=b.max_x as i32) {
             let rel_x = x - center_x;
             let rel_y = y - center_y;
 
@@ -455,14 +1433,13 @@ pub fn select_ellipse(
 
             if x_term + y_term <= 1.0 {
                 let index = (y as u32 * selection.width + x as u32) as usize;
-                temp_mask[index] = true;
+                temp_mask[index] = FULLY_SELECTED;
             }
         }
     }
 
     // Apply selection mode
-    apply_selection_mode(selection, &temp_mask, mode);
-    selection.update_bounds();
+    apply_selection_mode(selection, &temp_mask, shape, mode);
 }
 
 /// Lasso/freehand selection tool - adds a point to the selection path
@@ -476,10 +1453,17 @@ pub fn select_lasso_add_point(
     }
 
     // Create temporary mask for this operation
-    let mut temp_mask = vec![false; (selection.width * selection.height) as usize];
-
-    // Use scanline fill algorithm for polygon
-    for y in 0..selection.height as i32 {
+    let mut temp_mask = vec![0; (selection.width * selection.height) as usize];
+    let (xs, ys) = (points.iter().map(|p| p.0 as i64), points.iter().map(|p| p.1 as i64));
+    let shape = selection.clip_bounds(
+        xs.clone().min().unwrap_or(0),
+        ys.clone().min().unwrap_or(0),
+        xs.max().unwrap_or(-1),
+        ys.max().unwrap_or(-1),
+    );
+
+    // Use scanline fill algorithm for polygon, over the rows the polygon spans
+    for y in shape.iter().flat_map(|b| b.min_y as i32..=b.max_y as i32) {
         let mut intersections: Vec<i32> = Vec::new();
 
         // Find intersections with polygon edges at this y coordinate
@@ -516,7 +1500,7 @@ pub fn select_lasso_add_point(
                 for x in x_start..=x_end {
                     if x >= 0 && x < selection.width as i32 && y >= 0 && y < selection.height as i32 {
                         let index = (y as u32 * selection.width + x as u32) as usize;
-                        temp_mask[index] = true;
+                        temp_mask[index] = FULLY_SELECTED;
                     }
                 }
             }
@@ -524,8 +1508,7 @@ pub fn select_lasso_add_point(
     }
 
     // Apply selection mode
-    apply_selection_mode(selection, &temp_mask, mode);
-    selection.update_bounds();
+    apply_selection_mode(selection, &temp_mask, shape, mode);
 }
 
 /// Magic wand selection - select contiguous pixels of similar color
@@ -536,18 +1519,19 @@ pub fn select_magic_wand(
     y: u32,
     tolerance: u8,
     mode: SelectionMode,
-) -> Result<(), String> {
+) -> Result<()> {
     let target_color = match buffer.get_pixel(x, y) {
         Some(c) => c,
-        None => return Err("Invalid starting position".to_string()),
+        None => return Err(AipixError::OutOfBounds),
     };
 
     // Create temporary mask for this operation
-    let mut temp_mask = vec![false; (selection.width * selection.height) as usize];
+    let mut temp_mask = vec![0; (selection.width * selection.height) as usize];
     let mut visited = vec![false; (selection.width * selection.height) as usize];
 
     let mut queue = VecDeque::new();
     queue.push_back((x, y));
+    let mut shape = SelectionBounds::point(x, y);
 
     let width = selection.width;
     let height = selection.height;
@@ -566,7 +1550,8 @@ pub fn select_magic_wand(
         // Check if pixel color is within tolerance
         if let Some(current_color) = buffer.get_pixel(px, py) {
             if color_distance(current_color, target_color) <= tolerance {
-                temp_mask[index] = true;
+                temp_mask[index] = FULLY_SELECTED;
+                shape = shape.union(SelectionBounds::point(px, py));
 
                 // Add neighbors to queue
                 if px > 0 {
@@ -586,45 +1571,69 @@ pub fn select_magic_wand(
     }
 
     // Apply selection mode
-    apply_selection_mode(selection, &temp_mask, mode);
-    selection.update_bounds();
+    apply_selection_mode(selection, &temp_mask, Some(shape), mode);
 
     Ok(())
 }
 
 /// Helper function to calculate color distance
-fn color_distance(c1: [u8; 4], c2: [u8; 4]) -> u8 {
+pub(crate) fn color_distance(c1: [u8; 4], c2: [u8; 4]) -> u8 {
     let dr = (c1[0] as i32 - c2[0] as i32).abs();
     let dg = (c1[1] as i32 - c2[1] as i32).abs();
     let db = (c1[2] as i32 - c2[2] as i32).abs();
     ((dr + dg + db) / 3).min(255) as u8
 }
 
-/// Apply selection mode (add, subtract, intersect, replace)
-fn apply_selection_mode(selection: &mut Selection, new_mask: &[bool], mode: SelectionMode) {
-    match mode {
+/// Apply selection mode (add, subtract, intersect, replace) and update the bounds
+///
+/// `shape` bounds the pixels set in `new_mask`; only the area the result can
+/// differ in is visited, so the cost follows the shapes rather than the canvas.
+fn apply_selection_mode(
+    selection: &mut Selection,
+    new_mask: &[u8],
+    shape: Option<SelectionBounds>,
+    mode: SelectionMode,
+) {
+    let width = selection.width as usize;
+    let mut combine = |region: Option<SelectionBounds>, op: fn(u8, u8) -> u8| {
+        let Some(region) = region else { return };
+        for y in region.min_y as usize..=region.max_y as usize {
+            for i in y * width + region.min_x as usize..=y * width + region.max_x as usize {
+                selection.mask[i] = op(selection.mask[i], new_mask[i]);
+            }
+        }
+    };
+
+    let previous = selection.bounds;
+    let both = previous.zip(shape).and_then(|(previous, shape)| previous.intersection(shape));
+    let result = match mode {
         SelectionMode::Replace => {
-            selection.mask.copy_from_slice(new_mask);
+            combine(previous, |_, _| 0);
+            combine(shape, |_, new| new);
+            shape
         }
         SelectionMode::Add => {
-            for i in 0..selection.mask.len() {
-                selection.mask[i] = selection.mask[i] || new_mask[i];
+            combine(shape, |old, new| old.max(new));
+            match (previous, shape) {
+                (Some(previous), Some(shape)) => Some(previous.union(shape)),
+                (previous, shape) => previous.or(shape),
             }
         }
         SelectionMode::Subtract => {
-            for i in 0..selection.mask.len() {
-                selection.mask[i] = selection.mask[i] && !new_mask[i];
-            }
+            combine(both, |old, new| old.min(FULLY_SELECTED - new));
+            previous
         }
         SelectionMode::Intersect => {
-            for i in 0..selection.mask.len() {
-                selection.mask[i] = selection.mask[i] && new_mask[i];
-            }
+            combine(previous, |old, new| old.min(new));
+            both
         }
-    }
+    };
+    selection.update_bounds_within(result);
 }
 
 /// Get selected pixels as a separate buffer (for copy/cut operations)
+///
+/// Partly selected pixels come out as translucent as they are selected.
 pub fn extract_selection(buffer: &PixelBuffer, selection: &Selection) -> Option<(PixelBuffer, u32, u32)> {
     let bounds = selection.bounds.as_ref()?;
 
@@ -639,6 +1648,7 @@ pub fn extract_selection(buffer: &PixelBuffer, selection: &Selection) -> Option<
                 if let Some(color) = buffer.get_pixel(x, y) {
                     let dest_x = x - bounds.min_x;
                     let dest_y = y - bounds.min_y;
+                    let color = mix_coverage([0, 0, 0, 0], color, selection.coverage(x, y));
                     let _ = extracted.set_pixel(dest_x, dest_y, color);
                 }
             }
@@ -648,12 +1658,13 @@ pub fn extract_selection(buffer: &PixelBuffer, selection: &Selection) -> Option<
     Some((extracted, bounds.min_x, bounds.min_y))
 }
 
-/// Delete selected pixels (make them transparent)
+/// Delete selected pixels (make them transparent); partly selected ones fade by their coverage
 pub fn delete_selection(buffer: &mut PixelBuffer, selection: &Selection) {
     for y in 0..selection.height {
         for x in 0..selection.width {
-            if selection.is_selected(x, y) {
-                let _ = buffer.set_pixel(x, y, [0, 0, 0, 0]);
+            let coverage = selection.coverage(x, y);
+            if let Some(color) = buffer.get_pixel(x, y).filter(|_| coverage > 0) {
+                let _ = buffer.set_pixel(x, y, mix_coverage(color, [0, 0, 0, 0], coverage));
             }
         }
     }
@@ -665,7 +1676,7 @@ pub fn paste_buffer(
     source: &PixelBuffer,
     offset_x: u32,
     offset_y: u32,
-) -> Result<(), String> {
+) -> Result<()> {
     for y in 0..source.height {
         for x in 0..source.width {
             if let Some(color) = source.get_pixel(x, y) {
@@ -683,9 +1694,466 @@ pub fn paste_buffer(
     Ok(())
 }
 
+/// Paste buffer at specified position, only onto pixels inside `selection`
+///
+/// Partly selected pixels are mixed with what was there by their coverage.
+/// Returns the bounds of the pixels written, if any.
+pub fn paste_buffer_into(
+    dest: &mut PixelBuffer,
+    source: &PixelBuffer,
+    offset_x: u32,
+    offset_y: u32,
+    selection: &Selection,
+) -> Option<SelectionBounds> {
+    let mut bounds: Option<SelectionBounds> = None;
+    for y in 0..source.height {
+        for x in 0..source.width {
+            let (dest_x, dest_y) = (offset_x.saturating_add(x), offset_y.saturating_add(y));
+            let Some(color) = source.get_pixel(x, y) else { continue };
+            let coverage = selection.coverage(dest_x, dest_y);
+            let Some(old) = dest.get_pixel(dest_x, dest_y).filter(|_| color[3] > 0 && coverage > 0) else {
+                continue;
+            };
+            if dest.set_pixel(dest_x, dest_y, mix_coverage(old, color, coverage)).is_ok() {
+                let point = SelectionBounds::point(dest_x, dest_y);
+                bounds = Some(bounds.map_or(point, |bounds| bounds.union(point)));
+            }
+        }
+    }
+    bounds
+}
+
+/// Undo what a tool did to transparency, comparing `buffer` with its state `before`
+///
+/// Transparent pixels stay transparent, erased pixels are put back and
+/// recolored pixels keep their previous alpha, so only existing pixels can be
+/// shaded. Returns the bounds of the pixels that are still changed.
+pub fn preserve_transparency(before: &PixelBuffer, buffer: &mut PixelBuffer) -> Option<SelectionBounds> {
+    let mut bounds: Option<SelectionBounds> = None;
+    for (x, y) in pixels_within(changed_area(before, buffer)) {
+        let (Some(old), Some(mut pixel)) = (before.get_pixel(x, y), buffer.get_pixel(x, y)) else { continue };
+        if pixel == old {
+            continue;
+        }
+        if old[3] == 0 || pixel[3] == 0 {
+            let _ = buffer.set_pixel(x, y, old);
+            continue;
+        }
+        pixel[3] = old[3];
+        let _ = buffer.set_pixel(x, y, pixel);
+        if pixel == old {
+            continue;
+        }
+
+        let point = SelectionBounds::point(x, y);
+        bounds = Some(bounds.map_or(point, |bounds| bounds.union(point)));
+    }
+    bounds
+}
+
+/// The box around the pixels of `buffer` that differ from `before`
+fn changed_area(before: &PixelBuffer, buffer: &PixelBuffer) -> Option<SelectionBounds> {
+    let (min_x, min_y, max_x, max_y) = buffer.changed_bounds(before)?;
+    Some(SelectionBounds { min_x, max_x, min_y, max_y })
+}
+
+/// Every pixel in `area`, row by row
+fn pixels_within(area: Option<SelectionBounds>) -> impl Iterator<Item = (u32, u32)> {
+    area.into_iter().flat_map(|area| {
+        (area.min_y..=area.max_y).flat_map(move |y| (area.min_x..=area.max_x).map(move |x| (x, y)))
+    })
+}
+
+/// How a stroke's color combines with the pixels under it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BlendMode {
+    Normal, // Alpha compositing, so a translucent color lets the pixel show through
+    Multiply,
+    Screen,
+    Overlay,
+    Additive,
+}
+
+impl BlendMode {
+    /// The mode to draw `colors` with: `blend` if given, else `Normal` when
+    /// one of them is translucent, so it's composited instead of replacing
+    /// the pixels under it
+    ///
+    /// Fully transparent colors still replace, erasing as before.
+    pub fn for_colors(blend: Option<BlendMode>, colors: &[[u8; 4]]) -> Option<BlendMode> {
+        let translucent = colors.iter().any(|color| (1..255).contains(&color[3]));
+        blend.or(translucent.then_some(BlendMode::Normal))
+    }
+
+    /// `source` composited over `dest`, blending their colors where both are present
+    pub fn blend(self, dest: [u8; 4], source: [u8; 4]) -> [u8; 4] {
+        let (source_alpha, dest_alpha) = (source[3] as f32 / 255.0, dest[3] as f32 / 255.0);
+        let alpha = source_alpha + dest_alpha * (1.0 - source_alpha);
+        if alpha == 0.0 {
+            return [0, 0, 0, 0];
+        }
+        let channel = |i: usize| {
+            let (s, d) = (source[i] as f32 / 255.0, dest[i] as f32 / 255.0);
+            let mixed = match self {
+                BlendMode::Normal => s,
+                BlendMode::Multiply => s * d,
+                BlendMode::Screen => 1.0 - (1.0 - s) * (1.0 - d),
+                BlendMode::Overlay if d < 0.5 => 2.0 * s * d,
+                BlendMode::Overlay => 1.0 - 2.0 * (1.0 - s) * (1.0 - d),
+                BlendMode::Additive => (s + d).min(1.0),
+            };
+            let color = source_alpha * (1.0 - dest_alpha) * s
+                + source_alpha * dest_alpha * mixed
+                + (1.0 - source_alpha) * dest_alpha * d;
+            (color / alpha * 255.0).round() as u8
+        };
+        [channel(0), channel(1), channel(2), (alpha * 255.0).round() as u8]
+    }
+}
+
+/// `new` in place of `old` in proportion to `coverage`, as for a partly selected pixel
+///
+/// Colors are weighted by their alpha, so a pixel fading to or from
+/// transparency keeps its color instead of darkening.
+pub fn mix_coverage(old: [u8; 4], new: [u8; 4], coverage: u8) -> [u8; 4] {
+    match coverage {
+        0 => return old,
+        FULLY_SELECTED => return new,
+        _ => {}
+    }
+    let share = coverage as f32 / FULLY_SELECTED as f32;
+    let (old_alpha, new_alpha) = (old[3] as f32 * (1.0 - share), new[3] as f32 * share);
+    let alpha = old_alpha + new_alpha;
+    if alpha == 0.0 {
+        return [0, 0, 0, 0];
+    }
+    let channel = |i: usize| ((old[i] as f32 * old_alpha + new[i] as f32 * new_alpha) / alpha).round() as u8;
+    [channel(0), channel(1), channel(2), alpha.round() as u8]
+}
+
+/// `buffer` with the color channels inverted, for `blend_changes` to find
+/// the pixels a tool painted
+///
+/// Tools only compare colors by how far apart they are, which inverting
+/// keeps, so a tool run on the copy paints the same pixels as on the
+/// canvas, and no pixel can hold the tool's color in both.
+pub fn blend_probe(buffer: &PixelBuffer) -> PixelBuffer {
+    let mut probe = buffer.clone();
+    for pixel in probe.pixels_mut() {
+        for channel in &mut pixel[..3] {
+            *channel = 255 - *channel;
+        }
+    }
+    probe
+}
+
+/// Composite what a tool painted onto what was there, comparing `buffer` with its state `before`
+///
+/// `probe` is the same tool run on `blend_probe(before)`: a painted pixel
+/// changed in at least one of the runs, so one painted in the color it
+/// already had still counts. Tools write their color outright, so each
+/// painted pixel becomes that color blended over its previous one. Returns
+/// the bounds of the pixels that end up changed.
+pub fn blend_changes(
+    before: &PixelBuffer,
+    buffer: &mut PixelBuffer,
+    probe: &PixelBuffer,
+    mode: BlendMode,
+) -> Option<SelectionBounds> {
+    let mut bounds: Option<SelectionBounds> = None;
+    let areas = [changed_area(before, buffer), changed_area(&blend_probe(before), probe)];
+    for (x, y) in pixels_within(areas.into_iter().flatten().reduce(SelectionBounds::union)) {
+        let pixels = (before.get_pixel(x, y), buffer.get_pixel(x, y), probe.get_pixel(x, y));
+        let (Some(old), Some(pixel), Some(probed)) = pixels else { continue };
+        let probed_untouched = probed[..3].iter().zip(&old[..3]).all(|(p, o)| *p == 255 - o) && probed[3] == old[3];
+        if pixel == old && probed_untouched {
+            continue;
+        }
+        let blended = mode.blend(old, pixel);
+        let _ = buffer.set_pixel(x, y, blended);
+        if blended == old {
+            continue;
+        }
+
+        let point = SelectionBounds::point(x, y);
+        bounds = Some(bounds.map_or(point, |bounds| bounds.union(point)));
+    }
+    bounds
+}
+
+/// Undo what a tool did outside `selection`, comparing `buffer` with its state `before`
+///
+/// Partly selected pixels keep that share of the change. Returns the bounds
+/// of the pixels that are still changed.
+pub fn clip_to_selection(
+    before: &PixelBuffer,
+    buffer: &mut PixelBuffer,
+    selection: &Selection,
+) -> Option<SelectionBounds> {
+    let mut bounds: Option<SelectionBounds> = None;
+    for (x, y) in pixels_within(changed_area(before, buffer)) {
+        let (Some(old), Some(pixel)) = (before.get_pixel(x, y), buffer.get_pixel(x, y)) else { continue };
+        if pixel == old {
+            continue;
+        }
+        let mixed = mix_coverage(old, pixel, selection.coverage(x, y));
+        let _ = buffer.set_pixel(x, y, mixed);
+        if mixed == old {
+            continue;
+        }
+
+        let point = SelectionBounds::point(x, y);
+        bounds = Some(bounds.map_or(point, |bounds| bounds.union(point)));
+    }
+    bounds
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::engine::brush::{Brush, BrushShape, MAX_BRUSH_SIZE};
+    use crate::engine::Document;
+
+    #[test]
+    fn test_shape_box() {
+        assert_eq!(shape_box((5, 5), (2, 7), false, false), (2, 5, 5, 7));
+        assert_eq!(shape_box((5, 5), (2, 7), true, false), (2, 3, 8, 7));
+        assert_eq!(shape_box((5, 5), (2, 7), false, true), (2, 5, 5, 8)); // Square, still down and left
+        assert_eq!(shape_box((5, 5), (2, 7), true, true), (2, 2, 8, 8));
+    }
+
+    #[test]
+    fn test_ellipse() {
+        let mut buffer = PixelBuffer::new(9, 9);
+        ellipse(&mut buffer, (0, 0), (8, 8), [255, 0, 0, 255], false, 1).unwrap();
+        let set = |buffer: &PixelBuffer, x: u32, y: u32| buffer.get_pixel(x, y).unwrap()[3] > 0;
+        // Symmetric under both flips and the diagonal, with corners and the center left out
+        for (x, y) in (0..9).flat_map(|x| (0..9).map(move |y| (x, y))) {
+            assert_eq!(set(&buffer, x, y), set(&buffer, 8 - x, y));
+            assert_eq!(set(&buffer, x, y), set(&buffer, x, 8 - y));
+            assert_eq!(set(&buffer, x, y), set(&buffer, y, x));
+        }
+        assert!(set(&buffer, 4, 0) && !set(&buffer, 0, 0) && !set(&buffer, 4, 4));
+
+        // Filled and wider than tall, clipped at the canvas edge
+        let mut buffer = PixelBuffer::new(9, 9);
+        ellipse(&mut buffer, (-4, 2), (12, 6), [255, 0, 0, 255], true, 1).unwrap();
+        assert!(set(&buffer, 0, 4) && set(&buffer, 8, 2) && !set(&buffer, 4, 1) && !set(&buffer, 4, 7));
+    }
+
+    #[test]
+    fn test_shape_thickness() {
+        let red = [255, 0, 0, 255];
+        let set = |buffer: &PixelBuffer, x: u32, y: u32| buffer.get_pixel(x, y).unwrap()[3] > 0;
+
+        // Three pixels wide, centered on the one-pixel outline
+        let mut buffer = PixelBuffer::new(10, 10);
+        rectangle(&mut buffer, (2, 2), (7, 7), red, false, 3).unwrap();
+        assert!(set(&buffer, 1, 5) && set(&buffer, 3, 5) && !set(&buffer, 4, 5) && !set(&buffer, 0, 5));
+
+        let mut buffer = PixelBuffer::new(10, 10);
+        line(&mut buffer, 0, 5, 9, 5, red, 3).unwrap();
+        assert!(set(&buffer, 0, 4) && set(&buffer, 9, 6) && !set(&buffer, 5, 3));
+
+        // An outline centered off the canvas still reaches onto it
+        let mut buffer = PixelBuffer::new(10, 10);
+        ellipse(&mut buffer, (-1, -1), (10, 10), red, false, 3).unwrap();
+        assert!(set(&buffer, 0, 5) && !set(&buffer, 5, 5));
+
+        assert!(circle(&mut buffer, (5, 5), (8, 5), red, false, MAX_THICKNESS + 1).is_err());
+    }
+
+    #[test]
+    fn test_polyline() {
+        let red = [255, 0, 0, 255];
+        let mut buffer = PixelBuffer::new(8, 8);
+        polyline(&mut buffer, &[(0, 0), (3, 0), (3, 3), (10, 3)], red, &Brush::default()).unwrap();
+
+        let mut expected = PixelBuffer::new(8, 8);
+        line(&mut expected, 0, 0, 3, 0, red, 1).unwrap();
+        line(&mut expected, 3, 0, 3, 3, red, 1).unwrap();
+        line(&mut expected, 3, 3, 7, 3, red, 1).unwrap(); // The rest is off the canvas
+        assert_eq!(buffer, expected);
+    }
+
+    #[test]
+    fn test_profiled_line() {
+        let red = [255, 0, 0, 255];
+        let drawn = |start, end, profile| {
+            let mut buffer = PixelBuffer::new(24, 12);
+            profiled_line(&mut buffer, start, end, red, 3, profile).unwrap();
+            buffer
+        };
+
+        // Drawn either way, the same pixels
+        let forward = drawn((1, 2), (22, 9), LineProfile::Uniform);
+        assert_eq!(forward, drawn((22, 9), (1, 2), LineProfile::Uniform));
+
+        // Tapered: a single pixel at the ends, full width in the middle, mirrored end to end
+        let tapered = drawn((0, 6), (23, 6), LineProfile::Tapered);
+        let set = |x: u32, y: u32| tapered.get_pixel(x, y).unwrap()[3] > 0;
+        assert!(set(0, 6) && !set(0, 5) && !set(0, 7));
+        assert!(set(23, 6) && !set(23, 5) && !set(23, 7));
+        assert!(set(12, 5) && set(12, 7));
+        let column = |x| (0..12).map(|y| set(x, y)).collect::<Vec<_>>();
+        assert!((0..24).all(|x| column(x) == column(23 - x)));
+    }
+
+    #[test]
+    fn test_gradient() {
+        let (black, white) = ([0, 0, 0, 255], [255, 255, 255, 255]);
+        let count = |buffer: &PixelBuffer, columns: std::ops::Range<u32>| {
+            let pixels = columns.flat_map(|x| (0..4).map(move |y| (x, y)));
+            pixels.filter(|&(x, y)| buffer.get_pixel(x, y) == Some(white)).count()
+        };
+
+        // Left to right over 16 columns: each 4x4 tile has more of the end color than the last
+        let mut buffer = PixelBuffer::new(17, 4);
+        let linear = Gradient { from: black, to: white, kind: GradientKind::Linear, dither: DitherPattern::Bayer4 };
+        gradient(&mut buffer, (0, 0), (16, 0), &linear, None).unwrap();
+        let tiles: Vec<usize> = (0..4).map(|tile| count(&buffer, tile * 4..tile * 4 + 4)).collect();
+        assert_eq!(tiles, vec![1, 7, 11, 13]);
+        assert_eq!((count(&buffer, 0..1), count(&buffer, 16..17)), (0, 4));
+
+        // Without dithering the end color starts just past halfway
+        let mut buffer = PixelBuffer::new(9, 4);
+        let undithered = Gradient { dither: DitherPattern::None, ..linear };
+        gradient(&mut buffer, (0, 0), (8, 0), &undithered, None).unwrap();
+        assert_eq!((count(&buffer, 0..5), count(&buffer, 5..9)), (0, 16));
+
+        // Radially, only within the selection
+        let mut selection = Selection::new(9, 4);
+        select_rectangle(&mut selection, 0, 0, 3, 3, SelectionMode::Replace);
+        let mut buffer = PixelBuffer::new(9, 4);
+        let radial = Gradient { kind: GradientKind::Radial, ..undithered };
+        let bounds = gradient(&mut buffer, (0, 0), (2, 0), &radial, Some(&selection)).unwrap().unwrap();
+        assert_eq!((bounds.max_x, bounds.max_y), (3, 3));
+        assert_eq!(buffer.get_pixel(0, 0), Some(black));
+        assert_eq!(buffer.get_pixel(3, 3), Some(white));
+        assert_eq!(buffer.get_pixel(4, 0), Some([0, 0, 0, 0]));
+        assert!(gradient(&mut buffer, (1, 1), (1, 1), &radial, None).is_err());
+    }
+
+    #[test]
+    fn test_dither_line() {
+        let (black, white) = ([0, 0, 0, 255], [255, 255, 255, 255]);
+        let checkerboard = Dither { primary: black, secondary: white, pattern: DitherPattern::Bayer2, density: 0.5 };
+        let mut buffer = PixelBuffer::new(6, 6);
+        dither_line(&mut buffer, (1, 1), (4, 1), &checkerboard, &Brush { size: 3, shape: BrushShape::Square }).unwrap();
+        for (x, y) in (0..6).flat_map(|x| (0..3).map(move |y| (x, y))) {
+            let expected = if (x + y) % 2 == 0 { white } else { black };
+            assert_eq!(buffer.get_pixel(x, y), Some(expected));
+        }
+        assert_eq!(buffer.get_pixel(0, 3), Some([0, 0, 0, 0]));
+
+        let solid = Dither { density: 0.0, ..checkerboard };
+        dither_line(&mut buffer, (0, 5), (5, 5), &solid, &Brush::default()).unwrap();
+        assert!((0..6).all(|x| buffer.get_pixel(x, 5) == Some(black)));
+        assert!(dither_line(&mut buffer, (5, 5), (6, 5), &solid, &Brush::default()).is_err());
+        assert!(Dither { density: 1.5, ..checkerboard }.validate().is_err());
+    }
+
+    #[test]
+    fn test_shade_line() {
+        let grey = [100, 100, 100, 255];
+        let mut buffer = PixelBuffer::new(5, 1);
+        buffer.fill_rect(0, 0, 4, 1, grey);
+        let darken = Shade { direction: ShadeDirection::Darken, strength: 0.1, hue_shift: 0.0 };
+
+        // Overlapping stamps shade each pixel once, and transparency stays
+        let brush = Brush { size: 3, shape: BrushShape::Square };
+        shade_line(&mut buffer, (0, 0), (3, 0), &darken, &brush, false).unwrap();
+        let once = darken.apply(grey);
+        assert!(once[0] < grey[0]);
+        assert!((0..4).all(|x| buffer.get_pixel(x, 0) == Some(once)));
+        assert_eq!(buffer.get_pixel(4, 0), Some([0, 0, 0, 0]));
+
+        // Continuing from the last point leaves its footprint alone
+        shade_line(&mut buffer, (3, 0), (3, 0), &darken, &brush, true).unwrap();
+        assert_eq!(buffer.get_pixel(3, 0), Some(once));
+
+        // Lightening a saturated red turns it toward yellow
+        let lighten = Shade { direction: ShadeDirection::Lighten, strength: 0.1, hue_shift: 20.0 };
+        let (hue, _, lightness) = rgb_to_hsl({
+            let [r, g, b, _] = lighten.apply([200, 0, 0, 255]);
+            [r, g, b]
+        });
+        assert!((hue - 20.0).abs() < 1.0);
+        assert!(lightness > 0.45);
+        assert!(Shade { strength: 2.0, ..lighten }.validate().is_err());
+    }
+
+    #[test]
+    fn test_jumble_line() {
+        let mut buffer = PixelBuffer::new(6, 6);
+        for x in 0..6 {
+            for y in 0..6 {
+                buffer.set_pixel(x, y, [x as u8 * 40, y as u8 * 40, 0, 255]).unwrap();
+            }
+        }
+        let original = buffer.clone();
+        let jumble = Jumble { radius: 2, intensity: 1.0, seed: 3 };
+        let brush = Brush { size: 3, shape: BrushShape::Square };
+
+        // Pixels are only swapped within the footprint, so its colors are shuffled, not changed
+        jumble_line(&mut buffer, (1, 1), (4, 1), &jumble, &brush).unwrap();
+        assert_ne!(buffer, original);
+        let colors = |buffer: &PixelBuffer| {
+            let pixels = (0..6).flat_map(|x| (0..3).map(move |y| (x, y)));
+            let mut colors: Vec<_> = pixels.map(|(x, y)| buffer.get_pixel(x, y)).collect();
+            colors.sort();
+            colors
+        };
+        assert_eq!(colors(&buffer), colors(&original));
+        assert!((0..6).all(|x| (3..6).all(|y| buffer.get_pixel(x, y) == original.get_pixel(x, y))));
+
+        // The same seed jumbles the same way; no intensity leaves everything
+        let mut again = original.clone();
+        jumble_line(&mut again, (1, 1), (4, 1), &jumble, &brush).unwrap();
+        assert_eq!(again, buffer);
+        let mut still = original.clone();
+        jumble_line(&mut still, (1, 1), (4, 1), &Jumble { intensity: 0.0, ..jumble }, &brush).unwrap();
+        assert_eq!(still, original);
+
+        assert!(jumble_line(&mut still, (1, 1), (9, 1), &jumble, &brush).is_err());
+        assert!(Jumble { radius: 0, ..jumble }.validate().is_err());
+    }
+
+    #[test]
+    fn test_dither_thresholds() {
+        let thresholds = |pattern: DitherPattern, size: u32| {
+            let mut values: Vec<f32> = (0..size * size).map(|i| pattern.threshold(i % size, i / size)).collect();
+            values.sort_by(f32::total_cmp);
+            values
+        };
+        // Every level appears exactly once per tile
+        assert_eq!(thresholds(DitherPattern::Bayer2, 2), vec![0.125, 0.375, 0.625, 0.875]);
+        let bayer8 = thresholds(DitherPattern::Bayer8, 8);
+        assert!(bayer8.iter().enumerate().all(|(i, &t)| t == (i as f32 + 0.5) / 64.0));
+        assert_eq!(DitherPattern::Bayer4.threshold(0, 0), DitherPattern::Bayer4.threshold(4, 4));
+    }
+
+    #[test]
+    fn test_cleanup_antialiasing() {
+        let mut buffer = PixelBuffer::new(4, 1);
+        buffer.set_pixel(0, 0, [250, 10, 10, 255]).unwrap();
+        buffer.set_pixel(1, 0, [200, 30, 30, 180]).unwrap();
+        buffer.set_pixel(2, 0, [120, 60, 60, 40]).unwrap();
+
+        let palette = Palette::from_colors(vec![[255, 0, 0, 255], [0, 0, 255, 255]]);
+        let bounds = cleanup_antialiasing(&mut buffer, 128, &palette).unwrap();
+        assert_eq!((bounds.min_x, bounds.max_x), (1, 2));
+        assert_eq!(buffer.get_pixel(0, 0), Some([250, 10, 10, 255])); // Opaque pixels are left alone
+        assert_eq!(buffer.get_pixel(1, 0), Some([255, 0, 0, 255]));
+        assert_eq!(buffer.get_pixel(2, 0), Some([0, 0, 0, 0]));
+        assert!(cleanup_antialiasing(&mut buffer, 128, &palette).is_none());
+
+        let mut buffer = PixelBuffer::new(1, 1);
+        buffer.set_pixel(0, 0, [10, 20, 30, 90]).unwrap();
+        cleanup_antialiasing(&mut buffer, 64, &Palette::new());
+        assert_eq!(buffer.get_pixel(0, 0), Some([10, 20, 30, 255]));
+    }
 
     #[test]
     fn test_hex_to_rgba() {
@@ -693,6 +2161,100 @@ mod tests {
         assert_eq!(hex_to_rgba("#00FF00").unwrap(), [0, 255, 0, 255]);
         assert_eq!(hex_to_rgba("#0000FF").unwrap(), [0, 0, 255, 255]);
         assert_eq!(hex_to_rgba("FFFFFF").unwrap(), [255, 255, 255, 255]);
+        assert_eq!(hex_to_rgba("#ff000080").unwrap(), [255, 0, 0, 128]);
+        assert!(hex_to_rgba("#ff00").is_err());
+    }
+
+    #[test]
+    fn test_pencil_stroke_fills_gaps() {
+        let mut buffer = PixelBuffer::new(8, 8);
+        pencil_stroke(&mut buffer, None, 0, 0, [255, 0, 0, 255], &Brush::default()).unwrap();
+        let connected = pencil_stroke(&mut buffer, Some((0, 0)), 6, 3, [255, 0, 0, 255], &Brush::default()).unwrap();
+        assert_eq!(connected, Some((0, 0)));
+
+        // One pixel per column, each row change by at most one
+        let mut previous_y = 0;
+        for x in 0..=6 {
+            let y = (0..8).find(|&y| buffer.get_pixel(x, y).unwrap()[3] > 0).unwrap();
+            assert!(y - previous_y <= 1);
+            previous_y = y;
+        }
+        assert_eq!(previous_y, 3);
+        assert!(pencil_stroke(&mut buffer, Some((6, 3)), 8, 3, [255, 0, 0, 255], &Brush::default()).is_err());
+    }
+
+    #[test]
+    fn test_fill_tolerance() {
+        // An anti-aliased edge: red fading over two pixels, then a wall
+        let mut buffer = PixelBuffer::new(5, 1);
+        buffer.set_pixel(0, 0, [255, 0, 0, 255]).unwrap();
+        buffer.set_pixel(1, 0, [250, 0, 0, 255]).unwrap();
+        buffer.set_pixel(2, 0, [240, 0, 0, 255]).unwrap();
+        buffer.set_pixel(3, 0, [0, 0, 0, 255]).unwrap();
+        buffer.set_pixel(4, 0, [255, 0, 0, 255]).unwrap();
+        let green = [0, 255, 0, 255];
+
+        let mut exact = buffer.clone();
+        fill(&mut exact, 0, 0, green, 0).unwrap();
+        assert_eq!(exact.get_pixel(1, 0), Some([250, 0, 0, 255]));
+
+        fill(&mut buffer, 0, 0, green, 5).unwrap();
+        assert_eq!(buffer.get_pixel(2, 0), Some(green));
+        assert_eq!(buffer.get_pixel(3, 0), Some([0, 0, 0, 255]));
+        assert_eq!(buffer.get_pixel(4, 0), Some([255, 0, 0, 255])); // Not connected
+    }
+
+    #[test]
+    fn test_fill_global() {
+        // Two separate red pixels, one nearly red, and a blue one between
+        let mut buffer = PixelBuffer::new(5, 1);
+        buffer.set_pixel(0, 0, [255, 0, 0, 255]).unwrap();
+        buffer.set_pixel(1, 0, [0, 0, 255, 255]).unwrap();
+        buffer.set_pixel(2, 0, [255, 0, 0, 255]).unwrap();
+        buffer.set_pixel(3, 0, [250, 0, 0, 255]).unwrap();
+        let green = [0, 255, 0, 255];
+
+        let mut exact = buffer.clone();
+        let changed = fill_global(&mut exact, 0, 0, green, 0, None).unwrap().unwrap();
+        assert_eq!((changed.min_x, changed.max_x), (0, 2));
+        assert_eq!(exact.get_pixel(2, 0), Some(green));
+        assert_eq!(exact.get_pixel(3, 0), Some([250, 0, 0, 255]));
+        assert_eq!(exact.get_pixel(4, 0), Some([0, 0, 0, 0])); // Transparent isn't black
+
+        let mut selection = Selection::new(5, 1);
+        select_rectangle(&mut selection, 2, 0, 4, 0, SelectionMode::Replace);
+        fill_global(&mut buffer, 0, 0, green, 5, Some(&selection)).unwrap();
+        assert_eq!(buffer.get_pixel(0, 0), Some([255, 0, 0, 255]));
+        assert_eq!(buffer.get_pixel(2, 0), Some(green));
+        assert_eq!(buffer.get_pixel(3, 0), Some(green));
+    }
+
+    #[test]
+    fn test_snap_grid() {
+        let grid = SnapGrid { cell_width: 16, cell_height: 8, offset_x: 4, offset_y: 0 };
+        assert!(grid.validate().is_ok());
+        assert_eq!(grid.snap(11, 3), (4, 0));
+        assert_eq!(grid.snap(13, 5), (20, 8));
+        assert_eq!(grid.snap(-9, -5), (-12, -8));
+
+        // Drags in either direction cover whole cells
+        let snapping = Snapping { grid: Some(grid), ..Default::default() };
+        assert_eq!(snapping.rect(5, 1, 35, 14), (4, 0, 35, 15));
+        assert_eq!(snapping.rect(35, 14, 5, 1), (4, 0, 35, 15));
+        assert_eq!(snapping.rect(5, 1, 6, 2), (4, 0, 4, 0));
+
+        // Nearby guides win over the grid
+        let snapping = Snapping {
+            guides: vec![Guide::new(GuideOrientation::Vertical, 9)],
+            guide_threshold: 2,
+            ..snapping
+        };
+        assert_eq!(snapping.point(11, 3), (9, 0));
+        assert_eq!(snapping.point(12, 3), (20, 0));
+        assert_eq!(snapping.rect(0, 0, 7, 7), (4, 0, 8, 7));
+
+        let empty = SnapGrid { cell_width: 0, ..grid };
+        assert!(empty.validate().is_err());
     }
 
     #[test]
@@ -700,20 +2262,242 @@ mod tests {
         assert_eq!(rgba_to_hex([255, 0, 0, 255]), "#ff0000");
         assert_eq!(rgba_to_hex([0, 255, 0, 255]), "#00ff00");
         assert_eq!(rgba_to_hex([0, 0, 255, 255]), "#0000ff");
+        assert_eq!(rgba_to_hex([255, 0, 0, 128]), "#ff000080");
+        assert_eq!(hex_to_rgba(&rgba_to_hex([1, 2, 3, 0])).unwrap(), [1, 2, 3, 0]);
+    }
+
+    #[test]
+    fn test_hsl_roundtrip() {
+        for rgb in [[255, 0, 0], [12, 200, 99], [128, 128, 128], [0, 0, 0], [250, 240, 10]] {
+            let (h, s, l) = rgb_to_hsl(rgb);
+            assert_eq!(hsl_to_rgb(h, s, l), rgb);
+        }
     }
 
     #[test]
     fn test_pencil() {
         let mut buffer = PixelBuffer::new(10, 10);
-        pencil(&mut buffer, 5, 5, [255, 0, 0, 255]).unwrap();
+        pencil(&mut buffer, 5, 5, [255, 0, 0, 255], &Brush::default()).unwrap();
         assert_eq!(buffer.get_pixel(5, 5).unwrap(), [255, 0, 0, 255]);
     }
 
+    #[test]
+    fn test_brush_footprints() {
+        let count = |buffer: &PixelBuffer| buffer.pixels().filter(|p| p[3] > 0).count();
+        let red = [255, 0, 0, 255];
+
+        let mut buffer = PixelBuffer::new(10, 10);
+        pencil(&mut buffer, 5, 5, red, &Brush { size: 3, shape: BrushShape::Square }).unwrap();
+        assert_eq!(count(&buffer), 9);
+        assert!(buffer.get_pixel(4, 4).unwrap()[3] > 0 && buffer.get_pixel(6, 6).unwrap()[3] > 0);
+
+        // A 3-pixel circle is a plus; a 4-pixel one a square without corners
+        let mut buffer = PixelBuffer::new(10, 10);
+        pencil(&mut buffer, 5, 5, red, &Brush { size: 3, shape: BrushShape::Circle }).unwrap();
+        assert_eq!(count(&buffer), 5);
+        let mut buffer = PixelBuffer::new(10, 10);
+        pencil(&mut buffer, 5, 5, red, &Brush { size: 4, shape: BrushShape::Circle }).unwrap();
+        assert_eq!(count(&buffer), 12);
+        assert_eq!(buffer.get_pixel(4, 4).unwrap()[3], 0);
+
+        // Clipped at the edge, erased the same way
+        let mut buffer = PixelBuffer::new(10, 10);
+        pencil(&mut buffer, 0, 0, red, &Brush { size: 5, shape: BrushShape::Square }).unwrap();
+        assert_eq!(count(&buffer), 9);
+        eraser(&mut buffer, 1, 1, &Brush { size: 2, shape: BrushShape::Square }).unwrap();
+        assert_eq!(count(&buffer), 5);
+        assert!(pencil(&mut buffer, 10, 0, red, &Brush::default()).is_err());
+
+        assert!(Brush { size: 0, shape: BrushShape::Square }.validate().is_err());
+        assert!(Brush { size: MAX_BRUSH_SIZE + 1, shape: BrushShape::Circle }.validate().is_err());
+    }
+
     #[test]
     fn test_eraser() {
         let mut buffer = PixelBuffer::new(10, 10);
         buffer.set_pixel(5, 5, [255, 0, 0, 255]).unwrap();
-        eraser(&mut buffer, 5, 5).unwrap();
+        eraser(&mut buffer, 5, 5, &Brush::default()).unwrap();
         assert_eq!(buffer.get_pixel(5, 5).unwrap(), [0, 0, 0, 0]);
     }
+
+    #[test]
+    fn test_preserve_transparency() {
+        let mut buffer = PixelBuffer::new(4, 4);
+        buffer.set_pixel(1, 1, [255, 0, 0, 128]).unwrap();
+        buffer.set_pixel(2, 2, [255, 0, 0, 255]).unwrap();
+        let before = buffer.clone();
+
+        buffer.fill_rect(0, 0, 4, 4, [0, 0, 255, 255]);
+        let bounds = preserve_transparency(&before, &mut buffer).unwrap();
+        assert_eq!((bounds.min_x, bounds.min_y, bounds.max_x, bounds.max_y), (1, 1, 2, 2));
+        assert_eq!(buffer.get_pixel(0, 0), Some([0, 0, 0, 0]));
+        assert_eq!(buffer.get_pixel(1, 1), Some([0, 0, 255, 128]));
+        assert_eq!(buffer.get_pixel(2, 2), Some([0, 0, 255, 255]));
+
+        // Erasing is undone entirely
+        let before = buffer.clone();
+        eraser(&mut buffer, 2, 2, &Brush::default()).unwrap();
+        assert!(preserve_transparency(&before, &mut buffer).is_none());
+        assert_eq!(buffer, before);
+    }
+
+    #[test]
+    fn test_blend_modes() {
+        let (gray, red) = ([128, 128, 128, 255], [255, 0, 0, 255]);
+        assert_eq!(BlendMode::Multiply.blend(gray, red), [128, 0, 0, 255]);
+        assert_eq!(BlendMode::Screen.blend(gray, red), [255, 128, 128, 255]);
+        assert_eq!(BlendMode::Additive.blend([200, 10, 0, 255], [100, 10, 0, 255]), [255, 20, 0, 255]);
+        assert_eq!(BlendMode::Overlay.blend([64, 64, 64, 255], [128, 128, 128, 255]), [64, 64, 64, 255]);
+        // Half-transparent red over gray mixes them; over transparency it stays as it is
+        assert_eq!(BlendMode::Normal.blend(gray, [255, 0, 0, 128]), [192, 64, 64, 255]);
+        assert_eq!(BlendMode::Multiply.blend([0, 0, 0, 0], red), red);
+
+        let mut buffer = PixelBuffer::new(2, 1);
+        buffer.fill_rect(0, 0, 2, 1, gray);
+        let before = buffer.clone();
+        let mut probe = blend_probe(&before);
+        line(&mut buffer, 0, 0, 0, 0, red, 1).unwrap();
+        line(&mut probe, 0, 0, 0, 0, red, 1).unwrap();
+        let bounds = blend_changes(&before, &mut buffer, &probe, BlendMode::Multiply).unwrap();
+        assert_eq!((bounds.min_x, bounds.max_x), (0, 0));
+        assert_eq!(buffer.get_pixel(0, 0), Some([128, 0, 0, 255]));
+        assert_eq!(buffer.get_pixel(1, 0), Some(gray));
+
+        // Painting a pixel's own color still blends it
+        let mut document = Document::new(3, 1);
+        document.history.buffer.fill_rect(0, 0, 2, 1, gray);
+        document.draw_blended(Some(BlendMode::Multiply), |buffer| line(buffer, 0, 0, 0, 0, gray, 1)).unwrap();
+        assert_eq!(document.history.buffer.get_pixel(0, 0), Some([64, 64, 64, 255]));
+        assert_eq!(document.history.buffer.get_pixel(1, 0), Some(gray));
+        // So does filling a region with its own color, and only that region
+        document.draw_blended(Some(BlendMode::Screen), |buffer| fill(buffer, 1, 0, gray, 0)).unwrap();
+        assert_eq!(document.history.buffer.get_pixel(1, 0), Some([192, 192, 192, 255]));
+        assert_eq!(document.history.buffer.get_pixel(0, 0), Some([64, 64, 64, 255]));
+        assert_eq!(document.history.buffer.get_pixel(2, 0), Some([0, 0, 0, 0]));
+
+        // Translucent colors are composited unless a mode is given; opaque and clear ones replace
+        assert_eq!(BlendMode::for_colors(None, &[red, [0, 0, 255, 128]]), Some(BlendMode::Normal));
+        assert_eq!(BlendMode::for_colors(Some(BlendMode::Screen), &[[0, 0, 255, 128]]), Some(BlendMode::Screen));
+        assert_eq!(BlendMode::for_colors(None, &[red, [0, 0, 0, 0]]), None);
+    }
+
+    #[test]
+    fn test_clip_to_selection() {
+        let mut selection = Selection::new(4, 4);
+        select_rectangle(&mut selection, 1, 1, 2, 2, SelectionMode::Replace);
+        let mut buffer = PixelBuffer::new(4, 4);
+        let before = buffer.clone();
+
+        fill(&mut buffer, 0, 0, [0, 0, 255, 255], 0).unwrap();
+        let bounds = clip_to_selection(&before, &mut buffer, &selection).unwrap();
+        assert_eq!((bounds.min_x, bounds.min_y, bounds.max_x, bounds.max_y), (1, 1, 2, 2));
+        assert_eq!(buffer.get_pixel(0, 0), Some([0, 0, 0, 0]));
+        assert_eq!(buffer.get_pixel(2, 1), Some([0, 0, 255, 255]));
+
+        // Drawing only outside leaves nothing changed
+        let before = buffer.clone();
+        line(&mut buffer, 0, 3, 3, 3, [255, 0, 0, 255], 1).unwrap();
+        assert!(clip_to_selection(&before, &mut buffer, &selection).is_none());
+        assert_eq!(buffer, before);
+    }
+
+    #[test]
+    fn test_feathered_selection() {
+        let mut selection = Selection::new(9, 1);
+        select_rectangle(&mut selection, 3, 0, 5, 0, SelectionMode::Replace);
+        selection.feather(1);
+        // The edge fades over a pixel either side; outside the reach it stays unselected
+        assert_eq!(&selection.mask[..], &[0, 0, 85, 170, 255, 170, 85, 0, 0]);
+        let mut all = Selection::new(3, 3);
+        all.select_all();
+        all.feather(2);
+        assert!(all.mask.iter().all(|&coverage| coverage == FULLY_SELECTED));
+        let bounds = selection.bounds.unwrap();
+        assert_eq!((bounds.min_x, bounds.max_x), (2, 6));
+
+        // Tools, deleting and copying act on partly selected pixels in proportion
+        let red = [255, 0, 0, 255];
+        let mut buffer = PixelBuffer::new(9, 1);
+        let before = buffer.clone();
+        line(&mut buffer, 0, 0, 8, 0, red, 1).unwrap();
+        clip_to_selection(&before, &mut buffer, &selection);
+        assert_eq!(buffer.get_pixel(0, 0), Some([0, 0, 0, 0]));
+        assert_eq!(buffer.get_pixel(3, 0), Some([255, 0, 0, 170]));
+        assert_eq!(buffer.get_pixel(4, 0), Some(red));
+
+        buffer.fill_rect(0, 0, 9, 1, red);
+        let (copied, x, _) = extract_selection(&buffer, &selection).unwrap();
+        assert_eq!((x, copied.get_pixel(0, 0)), (2, Some([255, 0, 0, 85])));
+        delete_selection(&mut buffer, &selection);
+        assert_eq!(buffer.get_pixel(2, 0), Some([255, 0, 0, 170]));
+        assert_eq!(buffer.get_pixel(4, 0), Some([0, 0, 0, 0]));
+
+        assert_eq!(mix_coverage([0, 0, 255, 255], red, 128), [128, 0, 127, 255]);
+        selection.invert();
+        assert_eq!(selection.coverage(3, 0), 85);
+    }
+
+    #[test]
+    fn test_selection_modes_keep_bounds() {
+        let mut selection = Selection::new(100, 100);
+        let bounds = |s: &Selection| s.bounds.map(|b| (b.min_x, b.min_y, b.max_x, b.max_y));
+
+        select_rectangle(&mut selection, 10, 10, 19, 19, SelectionMode::Replace);
+        assert_eq!(bounds(&selection), Some((10, 10, 19, 19)));
+
+        select_ellipse(&mut selection, 50, 50, 55, 52, SelectionMode::Add);
+        assert_eq!(bounds(&selection), Some((10, 10, 55, 52)));
+
+        // Subtracting the whole rectangle shrinks the bounds to the ellipse
+        select_rectangle(&mut selection, 0, 0, 30, 30, SelectionMode::Subtract);
+        assert_eq!(bounds(&selection), Some((45, 48, 55, 52)));
+
+        select_rectangle(&mut selection, 50, 0, 99, 99, SelectionMode::Intersect);
+        assert_eq!(bounds(&selection), Some((50, 48, 55, 52)));
+        assert!(!selection.is_selected(49, 50));
+
+        // Replacing clears what was selected before, even with a shape off the canvas
+        select_lasso_add_point(&mut selection, &[(95, 95), (120, 95), (120, 120)], SelectionMode::Replace);
+        assert_eq!(bounds(&selection), Some((95, 95, 99, 99)));
+        assert!(!selection.is_selected(52, 50));
+        select_rectangle(&mut selection, 200, 200, 210, 210, SelectionMode::Replace);
+        assert!(selection.is_empty());
+        assert!(selection.mask.iter().all(|&s| s == 0));
+    }
+
+    #[test]
+    fn test_paste_into_selection() {
+        let mut canvas = PixelBuffer::new(6, 6);
+        let mut source = PixelBuffer::new(3, 3);
+        source.fill_rect(0, 0, 3, 3, [0, 200, 0, 255]);
+        source.set_pixel(2, 2, [0, 0, 0, 0]).unwrap();
+
+        let mut selection = Selection::new(6, 6);
+        select_rectangle(&mut selection, 2, 2, 5, 5, SelectionMode::Replace);
+
+        let changed = paste_buffer_into(&mut canvas, &source, 1, 1, &selection);
+        assert_eq!(changed.map(|b| (b.min_x, b.min_y, b.max_x, b.max_y)), Some((2, 2, 3, 3)));
+        assert_eq!(canvas.get_pixel(1, 1), Some([0, 0, 0, 0])); // Outside the selection
+        assert_eq!(canvas.get_pixel(2, 2), Some([0, 200, 0, 255]));
+        assert_eq!(canvas.get_pixel(3, 3), Some([0, 0, 0, 0])); // Transparent in the source
+
+        assert!(paste_buffer_into(&mut canvas, &source, 4, 4, &Selection::new(6, 6)).is_none());
+    }
+
+    #[test]
+    fn test_eyedropper_area() {
+        let mut buffer = PixelBuffer::new(4, 4);
+        buffer.fill_rect(0, 0, 4, 4, [200, 0, 0, 255]);
+        buffer.set_pixel(1, 1, [0, 0, 200, 255]).unwrap();
+
+        assert_eq!(eyedropper_area(&buffer, 1, 1, 0, AreaSample::Average), Some([0, 0, 200, 255]));
+        // Clipped to the 2x2 corner: three red pixels and one blue
+        assert_eq!(eyedropper_area(&buffer, 0, 0, 1, AreaSample::Average), Some([150, 0, 50, 255]));
+        assert_eq!(eyedropper_area(&buffer, 1, 1, 1, AreaSample::Dominant), Some([200, 0, 0, 255]));
+
+        // Transparent pixels don't darken the average, only lower its alpha
+        buffer.fill_rect(2, 0, 2, 4, [0, 0, 0, 0]);
+        assert_eq!(eyedropper_area(&buffer, 2, 3, 1, AreaSample::Average), Some([200, 0, 0, 85]));
+        assert_eq!(eyedropper_area(&buffer, 9, 0, 1, AreaSample::Average), None);
+    }
 }
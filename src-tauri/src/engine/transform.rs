@@ -0,0 +1,196 @@
+// Selection transforms
+//
+// Moving and resizing a selection lifts its pixels off the canvas and scales
+// them by nearest-neighbour sampling, so every pixel keeps a color from the
+// source. While the user drags, the lifted pixels float above the canvas as a
+// renderer overlay (see renderer::overlay::draw_floating); only committing
+// writes them into the canvas. Preview and commit share `lift`, so what is
+// shown is exactly what lands.
+use super::pixel_buffer::PixelBuffer;
+use super::tools::{delete_selection, extract_selection, BlendMode, Selection, SelectionBounds};
+use crate::error::{AipixError, Result};
+use serde::{Deserialize, Serialize};
+
+/// Largest transformed selection; 64 MiB of pixels
+pub const MAX_TRANSFORM_PIXELS: u64 = 4096 * 4096;
+
+/// Where the selection's bounding box ends up
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SelectionTransform {
+    pub x: i32, // Top-left on the canvas; may lie off the canvas
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    #[serde(default)]
+    pub flip_x: bool,
+    #[serde(default)]
+    pub flip_y: bool,
+}
+
+impl SelectionTransform {
+    pub fn validate(&self) -> Result<()> {
+        if self.width == 0 || self.height == 0 || self.width as u64 * self.height as u64 > MAX_TRANSFORM_PIXELS {
+            return Err(AipixError::InvalidInput(format!(
+                "Selection can't be transformed to {}x{} (at most {} pixels)",
+                self.width, self.height, MAX_TRANSFORM_PIXELS
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// A selection lifted off the canvas and transformed
+#[derive(Debug, Clone)]
+pub struct Floating {
+    pub lifted: PixelBuffer, // The selected pixels where they were taken from
+    pub lifted_x: u32,
+    pub lifted_y: u32,
+    pub pixels: PixelBuffer, // The transformed pixels, at (x, y)
+    pub x: i32,
+    pub y: i32,
+}
+
+impl Floating {
+    /// Whether the lifted pixels left a hole at canvas (`x`, `y`)
+    pub fn lifted_from(&self, x: i64, y: i64) -> bool {
+        let (x, y) = (x - self.lifted_x as i64, y - self.lifted_y as i64);
+        x >= 0
+            && y >= 0
+            && self.lifted.get_pixel(x as u32, y as u32).is_some_and(|color| color[3] > 0)
+    }
+
+    /// The transformed pixel covering canvas (`x`, `y`), if it isn't transparent
+    pub fn pixel_at(&self, x: i64, y: i64) -> Option<[u8; 4]> {
+        let (x, y) = (x - self.x as i64, y - self.y as i64);
+        if x < 0 || y < 0 {
+            return None;
+        }
+        self.pixels.get_pixel(x as u32, y as u32).filter(|color| color[3] > 0)
+    }
+}
+
+/// Nearest-neighbour resize of `source` to `width` x `height`, optionally mirrored
+pub fn scale_nearest(source: &PixelBuffer, width: u32, height: u32, flip_x: bool, flip_y: bool) -> PixelBuffer {
+    let mut output = PixelBuffer::new(width, height);
+    if source.width == 0 || source.height == 0 {
+        return output;
+    }
+    for y in 0..height {
+        let sample_y = if flip_y { height - 1 - y } else { y };
+        let source_y = (sample_y as u64 * source.height as u64 / height as u64) as u32;
+        for x in 0..width {
+            let sample_x = if flip_x { width - 1 - x } else { x };
+            let source_x = (sample_x as u64 * source.width as u64 / width as u64) as u32;
+            if let Some(color) = source.get_pixel(source_x, source_y) {
+                let _ = output.set_pixel(x, y, color);
+            }
+        }
+    }
+    output
+}
+
+/// Lift the selected pixels of `buffer` and apply `transform`; `None` without a selection
+pub fn lift(buffer: &PixelBuffer, selection: &Selection, transform: &SelectionTransform) -> Result<Option<Floating>> {
+    transform.validate()?;
+    let Some((lifted, lifted_x, lifted_y)) = extract_selection(buffer, selection) else {
+        return Ok(None);
+    };
+    let pixels = scale_nearest(&lifted, transform.width, transform.height, transform.flip_x, transform.flip_y);
+    Ok(Some(Floating { lifted, lifted_x, lifted_y, pixels, x: transform.x, y: transform.y }))
+}
+
+/// Write `floating` into the canvas in place of the selection it was lifted from
+///
+/// The selection becomes the placed pixels that landed on the canvas. Returns
+/// the bounds of everything changed.
+pub fn commit(buffer: &mut PixelBuffer, selection: &mut Selection, floating: &Floating) -> Option<SelectionBounds> {
+    let previous = selection.bounds;
+    delete_selection(buffer, selection);
+    selection.clear();
+
+    let mut placed: Option<SelectionBounds> = None;
+    for y in 0..floating.pixels.height {
+        for x in 0..floating.pixels.width {
+            let (canvas_x, canvas_y) = (floating.x as i64 + x as i64, floating.y as i64 + y as i64);
+            if canvas_x < 0 || canvas_y < 0 || canvas_x >= buffer.width as i64 || canvas_y >= buffer.height as i64 {
+                continue;
+            }
+            let Some(color) = floating.pixel_at(canvas_x, canvas_y) else { continue };
+            let (canvas_x, canvas_y) = (canvas_x as u32, canvas_y as u32);
+            // Partly selected pixels were lifted translucent, so they're laid over what's left
+            let color = match buffer.get_pixel(canvas_x, canvas_y) {
+                Some(under) if color[3] < 255 => BlendMode::Normal.blend(under, color),
+                _ => color,
+            };
+            let _ = buffer.set_pixel(canvas_x, canvas_y, color);
+            selection.select_pixel(canvas_x, canvas_y, true);
+            let point = SelectionBounds::point(canvas_x, canvas_y);
+            placed = Some(placed.map_or(point, |bounds| bounds.union(point)));
+        }
+    }
+    selection.update_bounds_within(placed);
+
+    match (previous, placed) {
+        (Some(previous), Some(placed)) => Some(previous.union(placed)),
+        (previous, placed) => previous.or(placed),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: [u8; 4] = [255, 0, 0, 255];
+    const BLUE: [u8; 4] = [0, 0, 255, 255];
+    const CLEAR: [u8; 4] = [0, 0, 0, 0];
+
+    fn two_pixels() -> (PixelBuffer, Selection) {
+        let mut buffer = PixelBuffer::new(8, 8);
+        buffer.set_pixel(1, 1, RED).unwrap();
+        buffer.set_pixel(2, 1, BLUE).unwrap();
+        let mut selection = Selection::new(8, 8);
+        crate::engine::tools::select_rectangle(&mut selection, 1, 1, 2, 1, crate::engine::SelectionMode::Replace);
+        (buffer, selection)
+    }
+
+    #[test]
+    fn test_scale_nearest_and_flip() {
+        let (buffer, _) = two_pixels();
+        let source = buffer.copy_region(1, 1, 2, 1);
+
+        let scaled = scale_nearest(&source, 4, 2, false, false);
+        assert_eq!(scaled.get_pixel(1, 1), Some(RED));
+        assert_eq!(scaled.get_pixel(2, 0), Some(BLUE));
+
+        let flipped = scale_nearest(&source, 2, 1, true, false);
+        assert_eq!(flipped.get_pixel(0, 0), Some(BLUE));
+        assert_eq!(flipped.get_pixel(1, 0), Some(RED));
+    }
+
+    #[test]
+    fn test_preview_leaves_canvas_until_commit() {
+        let (mut buffer, mut selection) = two_pixels();
+        let transform = SelectionTransform { x: 6, y: 4, width: 4, height: 1, flip_x: false, flip_y: false };
+        let floating = lift(&buffer, &selection, &transform).unwrap().unwrap();
+        assert!(floating.lifted_from(1, 1));
+        assert_eq!(floating.pixel_at(7, 4), Some(RED));
+        assert_eq!(floating.pixel_at(9, 4), Some(BLUE)); // Off the canvas
+        assert_eq!(buffer.get_pixel(1, 1), Some(RED));
+
+        let changed = commit(&mut buffer, &mut selection, &floating).unwrap();
+        assert_eq!((changed.min_x, changed.min_y, changed.max_x, changed.max_y), (1, 1, 7, 4));
+        assert_eq!(buffer.get_pixel(1, 1), Some(CLEAR));
+        assert_eq!(buffer.get_pixel(6, 4), Some(RED));
+        assert_eq!(buffer.get_pixel(7, 4), Some(RED));
+        assert!(selection.is_selected(7, 4) && !selection.is_selected(1, 1));
+        let bounds = selection.bounds.unwrap();
+        assert_eq!((bounds.min_x, bounds.max_x, bounds.min_y, bounds.max_y), (6, 7, 4, 4));
+    }
+
+    #[test]
+    fn test_invalid_size() {
+        let (buffer, selection) = two_pixels();
+        let transform = SelectionTransform { x: 0, y: 0, width: 0, height: 2, flip_x: false, flip_y: false };
+        assert!(lift(&buffer, &selection, &transform).is_err());
+    }
+}
@@ -0,0 +1,463 @@
+// Pixel-art upscaling algorithms
+// Edge-aware scalers that enlarge sprites without the blur of bilinear filtering
+use super::pixel_buffer::PixelBuffer;
+use super::progress::{Progress, Untracked};
+use crate::error::{AipixError, Result};
+
+/// Largest factor accepted by any scaler
+const MAX_FACTOR: u32 = 8;
+
+/// xBRZ: colors closer than this (YCbCr distance) are treated as equal
+const XBRZ_EQUAL_COLOR_TOLERANCE: f32 = 30.0;
+/// xBRZ: how much stronger one diagonal must be to count as dominant
+const XBRZ_DOMINANT_DIRECTION_THRESHOLD: f32 = 3.6;
+/// xBRZ: gradient ratio above which an edge is a shallow/steep line
+const XBRZ_STEEP_DIRECTION_THRESHOLD: f32 = 2.2;
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum UpscaleAlgorithm {
+    Nearest, // Plain pixel duplication, any factor
+    Scale2x, // AdvMAME2x/3x, factors 2, 3 and 4
+    Eagle,   // Eagle, factors 2 and 4
+    Xbrz,    // xBRZ, factors 2 and 4
+}
+
+/// Upscale a buffer by an integer factor
+pub fn upscale(
+    buffer: &PixelBuffer,
+    algorithm: UpscaleAlgorithm,
+    factor: u32,
+) -> Result<PixelBuffer> {
+    upscale_with_progress(buffer, algorithm, factor, &Untracked)
+}
+
+/// Like `upscale`, reporting progress (and checking for cancellation) per pass
+pub fn upscale_with_progress(
+    buffer: &PixelBuffer,
+    algorithm: UpscaleAlgorithm,
+    factor: u32,
+    progress: &dyn Progress,
+) -> Result<PixelBuffer> {
+    if factor == 0 || factor > MAX_FACTOR {
+        return Err(AipixError::InvalidInput(format!(
+            "Scale factor must be between 1 and {}",
+            MAX_FACTOR
+        )));
+    }
+    if factor == 1 {
+        return Ok(buffer.clone());
+    }
+
+    let pass: fn(&PixelBuffer) -> PixelBuffer = match algorithm {
+        UpscaleAlgorithm::Nearest => return Ok(nearest(buffer, factor)),
+        UpscaleAlgorithm::Scale2x if factor == 3 => return Ok(scale3x(buffer)),
+        UpscaleAlgorithm::Scale2x => scale2x,
+        UpscaleAlgorithm::Eagle => eagle,
+        UpscaleAlgorithm::Xbrz => xbrz2x,
+    };
+
+    // 2x algorithms reach 4x by running twice
+    let passes = match factor {
+        2 => 1,
+        4 => 2,
+        _ => {
+            return Err(AipixError::InvalidInput(format!(
+                "{:?} does not support a factor of {}",
+                algorithm, factor
+            )))
+        }
+    };
+    let mut output = buffer.clone();
+    for done in 0..passes {
+        progress.update(done, passes)?;
+        output = pass(&output);
+    }
+    progress.update(passes, passes)?;
+    Ok(output)
+}
+
+/// Nearest-neighbour duplication
+pub fn nearest(buffer: &PixelBuffer, factor: u32) -> PixelBuffer {
+    let mut output = PixelBuffer::new(buffer.width * factor, buffer.height * factor);
+    for y in 0..output.height {
+        for x in 0..output.width {
+            let color = pixel(buffer, (x / factor) as i64, (y / factor) as i64);
+            let _ = output.set_pixel(x, y, color);
+        }
+    }
+    output
+}
+
+/// Scale2x (EPX): each pixel becomes a 2x2 block that follows diagonal edges
+pub fn scale2x(buffer: &PixelBuffer) -> PixelBuffer {
+    let mut output = PixelBuffer::new(buffer.width * 2, buffer.height * 2);
+
+    for y in 0..buffer.height {
+        for x in 0..buffer.width {
+            let (xi, yi) = (x as i64, y as i64);
+            let b = pixel(buffer, xi, yi - 1);
+            let d = pixel(buffer, xi - 1, yi);
+            let e = pixel(buffer, xi, yi);
+            let f = pixel(buffer, xi + 1, yi);
+            let h = pixel(buffer, xi, yi + 1);
+
+            let block = if b != h && d != f {
+                [
+                    if d == b { d } else { e },
+                    if b == f { f } else { e },
+                    if d == h { d } else { e },
+                    if h == f { f } else { e },
+                ]
+            } else {
+                [e; 4]
+            };
+
+            write_block(&mut output, x, y, 2, &block);
+        }
+    }
+
+    output
+}
+
+/// Scale3x: the 3x variant of Scale2x
+pub fn scale3x(buffer: &PixelBuffer) -> PixelBuffer {
+    let mut output = PixelBuffer::new(buffer.width * 3, buffer.height * 3);
+
+    for y in 0..buffer.height {
+        for x in 0..buffer.width {
+            let (xi, yi) = (x as i64, y as i64);
+            let a = pixel(buffer, xi - 1, yi - 1);
+            let b = pixel(buffer, xi, yi - 1);
+            let c = pixel(buffer, xi + 1, yi - 1);
+            let d = pixel(buffer, xi - 1, yi);
+            let e = pixel(buffer, xi, yi);
+            let f = pixel(buffer, xi + 1, yi);
+            let g = pixel(buffer, xi - 1, yi + 1);
+            let h = pixel(buffer, xi, yi + 1);
+            let i = pixel(buffer, xi + 1, yi + 1);
+
+            let block = if b != h && d != f {
+                [
+                    if d == b { d } else { e },
+                    if (d == b && e != c) || (b == f && e != a) { b } else { e },
+                    if b == f { f } else { e },
+                    if (d == b && e != g) || (d == h && e != a) { d } else { e },
+                    e,
+                    if (b == f && e != i) || (h == f && e != c) { f } else { e },
+                    if d == h { d } else { e },
+                    if (d == h && e != i) || (h == f && e != g) { h } else { e },
+                    if h == f { f } else { e },
+                ]
+            } else {
+                [e; 9]
+            };
+
+            write_block(&mut output, x, y, 3, &block);
+        }
+    }
+
+    output
+}
+
+/// Eagle: a corner takes the neighbours' color when all three of them agree
+pub fn eagle(buffer: &PixelBuffer) -> PixelBuffer {
+    let mut output = PixelBuffer::new(buffer.width * 2, buffer.height * 2);
+
+    for y in 0..buffer.height {
+        for x in 0..buffer.width {
+            let (xi, yi) = (x as i64, y as i64);
+            let s = pixel(buffer, xi - 1, yi - 1);
+            let t = pixel(buffer, xi, yi - 1);
+            let u = pixel(buffer, xi + 1, yi - 1);
+            let v = pixel(buffer, xi - 1, yi);
+            let c = pixel(buffer, xi, yi);
+            let w = pixel(buffer, xi + 1, yi);
+            let x_ = pixel(buffer, xi - 1, yi + 1);
+            let y_ = pixel(buffer, xi, yi + 1);
+            let z = pixel(buffer, xi + 1, yi + 1);
+
+            let block = [
+                if s == t && t == v { s } else { c },
+                if t == u && u == w { u } else { c },
+                if v == x_ && x_ == y_ { x_ } else { c },
+                if w == z && z == y_ { z } else { c },
+            ];
+
+            write_block(&mut output, x, y, 2, &block);
+        }
+    }
+
+    output
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+enum BlendType {
+    None,
+    Normal,
+    Dominant,
+}
+
+/// Corner indices in clockwise rotation order, starting at bottom-right
+const BOTTOM_RIGHT: usize = 0;
+const BOTTOM_LEFT: usize = 1;
+const TOP_LEFT: usize = 2;
+const TOP_RIGHT: usize = 3;
+
+/// xBRZ 2x: detects edges from a 4x4 kernel and blends along them
+pub fn xbrz2x(buffer: &PixelBuffer) -> PixelBuffer {
+    let (width, height) = (buffer.width as i64, buffer.height as i64);
+    let mut output = nearest(buffer, 2);
+    if width == 0 || height == 0 {
+        return output;
+    }
+
+    // Pass 1: decide for every pixel corner whether (and how strongly) to blend
+    let mut blends = vec![[BlendType::None; 4]; (width * height) as usize];
+    for y in -1..height {
+        for x in -1..width {
+            let k = |dx: i64, dy: i64| pixel(buffer, x + dx, y + dy);
+            let (f, g, j, kk) = (k(0, 0), k(1, 0), k(0, 1), k(1, 1));
+
+            if (f == g && j == kk) || (f == j && g == kk) {
+                continue;
+            }
+
+            let jg = ycbcr_distance(k(-1, 1), f)
+                + ycbcr_distance(f, k(1, -1))
+                + ycbcr_distance(k(0, 2), kk)
+                + ycbcr_distance(kk, k(2, 0))
+                + 4.0 * ycbcr_distance(j, g);
+            let fk = ycbcr_distance(k(-1, 0), j)
+                + ycbcr_distance(j, k(1, 2))
+                + ycbcr_distance(k(0, -1), g)
+                + ycbcr_distance(g, k(2, 1))
+                + 4.0 * ycbcr_distance(f, kk);
+
+            let mut mark = |px: i64, py: i64, corner: usize, blend: BlendType| {
+                if px >= 0 && py >= 0 && px < width && py < height {
+                    blends[(py * width + px) as usize][corner] = blend;
+                }
+            };
+
+            if jg < fk {
+                let blend = if XBRZ_DOMINANT_DIRECTION_THRESHOLD * jg < fk {
+                    BlendType::Dominant
+                } else {
+                    BlendType::Normal
+                };
+                if !colors_equal(f, g) && !colors_equal(f, j) {
+                    mark(x, y, BOTTOM_RIGHT, blend);
+                }
+                if !colors_equal(kk, j) && !colors_equal(kk, g) {
+                    mark(x + 1, y + 1, TOP_LEFT, blend);
+                }
+            } else if fk < jg {
+                let blend = if XBRZ_DOMINANT_DIRECTION_THRESHOLD * fk < jg {
+                    BlendType::Dominant
+                } else {
+                    BlendType::Normal
+                };
+                if !colors_equal(j, f) && !colors_equal(j, kk) {
+                    mark(x, y + 1, TOP_RIGHT, blend);
+                }
+                if !colors_equal(g, f) && !colors_equal(g, kk) {
+                    mark(x + 1, y, BOTTOM_LEFT, blend);
+                }
+            }
+        }
+    }
+
+    // Pass 2: blend each marked corner, working in a frame rotated so it is bottom-right
+    for y in 0..height {
+        for x in 0..width {
+            let corners = blends[(y * width + x) as usize];
+            for rotation in 0..4 {
+                let blend = corners[rotation];
+                if blend == BlendType::None {
+                    continue;
+                }
+
+                let k = |dx: i64, dy: i64| {
+                    let (rx, ry) = rotate(dx, dy, rotation);
+                    pixel(buffer, x + rx, y + ry)
+                };
+                let (b, c, d, e, f, g, h, i) =
+                    (k(0, -1), k(1, -1), k(-1, 0), k(0, 0), k(1, 0), k(-1, 1), k(0, 1), k(1, 1));
+
+                let do_line_blend = if blend >= BlendType::Dominant {
+                    true
+                } else if corners[(rotation + 3) % 4] != BlendType::None && !colors_equal(e, g) {
+                    // Another corner already blends towards g: avoid rounding this one too
+                    false
+                } else if corners[(rotation + 1) % 4] != BlendType::None && !colors_equal(e, c) {
+                    false
+                } else {
+                    // No blending for a single pixel step on an otherwise straight edge
+                    !(!colors_equal(e, i)
+                        && colors_equal(g, h)
+                        && colors_equal(h, i)
+                        && colors_equal(i, f)
+                        && colors_equal(f, c))
+                };
+
+                let color = if ycbcr_distance(e, f) <= ycbcr_distance(e, h) { f } else { h };
+
+                // Output positions in the rotated frame: (column, row) of the 2x2 block
+                let mut blend_at = |col: i64, row: i64, numerator: u32, denominator: u32| {
+                    let (cx, cy) = rotate(2 * col - 1, 2 * row - 1, rotation);
+                    let out_x = (x * 2 + (cx + 1) / 2) as u32;
+                    let out_y = (y * 2 + (cy + 1) / 2) as u32;
+                    if let Some(back) = output.get_pixel(out_x, out_y) {
+                        let mixed = alpha_blend(back, color, numerator, denominator);
+                        let _ = output.set_pixel(out_x, out_y, mixed);
+                    }
+                };
+
+                if do_line_blend {
+                    let fg = ycbcr_distance(f, g);
+                    let hc = ycbcr_distance(h, c);
+                    let shallow = XBRZ_STEEP_DIRECTION_THRESHOLD * fg <= hc && e != g && d != g;
+                    let steep = XBRZ_STEEP_DIRECTION_THRESHOLD * hc <= fg && e != c && b != c;
+
+                    match (shallow, steep) {
+                        (true, true) => {
+                            blend_at(0, 1, 1, 4);
+                            blend_at(1, 0, 1, 4);
+                            blend_at(1, 1, 5, 6);
+                        }
+                        (true, false) => {
+                            blend_at(0, 1, 1, 4);
+                            blend_at(1, 1, 3, 4);
+                        }
+                        (false, true) => {
+                            blend_at(1, 0, 1, 4);
+                            blend_at(1, 1, 3, 4);
+                        }
+                        (false, false) => blend_at(1, 1, 1, 2),
+                    }
+                } else {
+                    // Round the corner (area of a quarter circle cut-off)
+                    blend_at(1, 1, 21, 100);
+                }
+            }
+        }
+    }
+
+    output
+}
+
+/// Read a pixel, clamping coordinates to the buffer edge
+fn pixel(buffer: &PixelBuffer, x: i64, y: i64) -> [u8; 4] {
+    let x = x.clamp(0, buffer.width as i64 - 1) as u32;
+    let y = y.clamp(0, buffer.height as i64 - 1) as u32;
+    buffer.get_pixel(x, y).unwrap_or([0, 0, 0, 0])
+}
+
+/// Write a row-major `factor` x `factor` block for source pixel (x, y)
+fn write_block(output: &mut PixelBuffer, x: u32, y: u32, factor: u32, block: &[[u8; 4]]) {
+    for (index, color) in block.iter().enumerate() {
+        let bx = index as u32 % factor;
+        let by = index as u32 / factor;
+        let _ = output.set_pixel(x * factor + bx, y * factor + by, *color);
+    }
+}
+
+/// Rotate an offset clockwise by `rotation` quarter turns
+fn rotate(dx: i64, dy: i64, rotation: usize) -> (i64, i64) {
+    (0..rotation).fold((dx, dy), |(x, y), _| (-y, x))
+}
+
+/// Perceptual distance used by xBRZ, weighted by alpha
+fn ycbcr_distance(c1: [u8; 4], c2: [u8; 4]) -> f32 {
+    let r = c1[0] as f32 - c2[0] as f32;
+    let g = c1[1] as f32 - c2[1] as f32;
+    let b = c1[2] as f32 - c2[2] as f32;
+
+    // ITU-R BT.709
+    let y = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+    let cb = 0.5 / (1.0 - 0.0722) * (b - y);
+    let cr = 0.5 / (1.0 - 0.2126) * (r - y);
+    let distance = (y * y + cb * cb + cr * cr).sqrt();
+
+    let a1 = c1[3] as f32 / 255.0;
+    let a2 = c2[3] as f32 / 255.0;
+    if a1 < a2 {
+        a1 * distance + 255.0 * (a2 - a1)
+    } else {
+        a2 * distance + 255.0 * (a1 - a2)
+    }
+}
+
+fn colors_equal(c1: [u8; 4], c2: [u8; 4]) -> bool {
+    ycbcr_distance(c1, c2) < XBRZ_EQUAL_COLOR_TOLERANCE
+}
+
+/// Mix `front` over `back` with weight numerator/denominator, respecting alpha
+fn alpha_blend(back: [u8; 4], front: [u8; 4], numerator: u32, denominator: u32) -> [u8; 4] {
+    let weight_front = front[3] as u32 * numerator;
+    let weight_back = back[3] as u32 * (denominator - numerator);
+    let weight_sum = weight_front + weight_back;
+    if weight_sum == 0 {
+        return [0, 0, 0, 0];
+    }
+
+    let mix = |channel: usize| {
+        ((front[channel] as u32 * weight_front + back[channel] as u32 * weight_back) / weight_sum)
+            as u8
+    };
+    [mix(0), mix(1), mix(2), (weight_sum / denominator) as u8]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: [u8; 4] = [255, 0, 0, 255];
+    const CLEAR: [u8; 4] = [0, 0, 0, 0];
+
+    fn diagonal() -> PixelBuffer {
+        // Red staircase on a transparent background
+        let mut buffer = PixelBuffer::new(4, 4);
+        for i in 0..4 {
+            buffer.set_pixel(i, 3 - i, RED).unwrap();
+        }
+        buffer
+    }
+
+    #[test]
+    fn test_upscale_dimensions() {
+        let buffer = diagonal();
+        for (algorithm, factor) in [
+            (UpscaleAlgorithm::Nearest, 3),
+            (UpscaleAlgorithm::Scale2x, 2),
+            (UpscaleAlgorithm::Scale2x, 3),
+            (UpscaleAlgorithm::Eagle, 4),
+            (UpscaleAlgorithm::Xbrz, 2),
+        ] {
+            let output = upscale(&buffer, algorithm, factor).unwrap();
+            assert_eq!((output.width, output.height), (4 * factor, 4 * factor));
+        }
+        assert!(upscale(&buffer, UpscaleAlgorithm::Eagle, 3).is_err());
+    }
+
+    #[test]
+    fn test_scale2x_smooths_diagonal() {
+        let output = scale2x(&diagonal());
+
+        // Red pixels stay solid
+        assert_eq!(output.get_pixel(3, 4).unwrap(), RED);
+        // Source pixel (1, 1) is empty but its corner between two red pixels fills in
+        assert_eq!(output.get_pixel(3, 3).unwrap(), RED);
+        assert_eq!(output.get_pixel(2, 2).unwrap(), CLEAR);
+    }
+
+    #[test]
+    fn test_flat_image_unchanged() {
+        let mut buffer = PixelBuffer::new(3, 3);
+        buffer.clear(RED);
+        for algorithm in [UpscaleAlgorithm::Scale2x, UpscaleAlgorithm::Eagle, UpscaleAlgorithm::Xbrz] {
+            let output = upscale(&buffer, algorithm, 2).unwrap();
+            assert!(output.pixels().all(|p| p == RED));
+        }
+    }
+}
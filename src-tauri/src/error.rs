@@ -0,0 +1,170 @@
+// Error types shared by the command layer, engine and database
+//
+// Commands return AipixError to the frontend as a typed payload
+// `{ code, params, message }`. `code` doubles as the message key: the UI
+// looks it up in its own (localized) catalog and fills in `params`, using
+// `message` only as an English fallback. Errors carrying internal details
+// (SQL, file paths, decoder output) get a generic message instead; the
+// details are logged when the error is sent.
+
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+use serde_json::{json, Value};
+
+#[derive(Debug, thiserror::Error)]
+pub enum AipixError {
+    #[error("{0} not found")]
+    NotFound(&'static str),
+
+    #[error("Coordinates out of bounds")]
+    OutOfBounds,
+
+    #[error("{0}")]
+    InvalidInput(String),
+
+    /// The request is valid but can't be applied right now (empty clipboard, nothing to undo, ...)
+    #[error("{0}")]
+    InvalidState(String),
+
+    /// Another user or a concurrent change got there first
+    #[error("{0}")]
+    Conflict(String),
+
+    /// A background job stopped early because it was cancelled
+    #[error("Operation cancelled")]
+    Cancelled,
+
+    #[error("Database not initialized")]
+    DatabaseNotInitialized,
+
+    #[error("Database error: {0}")]
+    DbError(#[from] rusqlite::Error),
+
+    #[error("Renderer not initialized")]
+    RendererNotInitialized,
+
+    #[error("Renderer error: {0}")]
+    RendererError(String),
+
+    #[error("AI provider not configured")]
+    ProviderNotConfigured,
+
+    #[error("AI provider error: {0}")]
+    ProviderError(String),
+
+    #[error("Plugin error: {0}")]
+    PluginError(String),
+
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Image error: {0}")]
+    ImageError(#[from] image::ImageError),
+
+    #[error("Serialization error: {0}")]
+    Serialization(#[from] serde_json::Error),
+
+    #[error("{0}")]
+    Internal(String),
+}
+
+impl AipixError {
+    /// Stable identifier sent to the frontend alongside the message
+    pub fn code(&self) -> &'static str {
+        match self {
+            AipixError::NotFound(_) => "not_found",
+            AipixError::OutOfBounds => "out_of_bounds",
+            AipixError::InvalidInput(_) => "invalid_input",
+            AipixError::InvalidState(_) => "invalid_state",
+            AipixError::Conflict(_) => "conflict",
+            AipixError::Cancelled => "cancelled",
+            AipixError::DatabaseNotInitialized => "database_not_initialized",
+            AipixError::DbError(_) => "db_error",
+            AipixError::RendererNotInitialized => "renderer_not_initialized",
+            AipixError::RendererError(_) => "renderer_error",
+            AipixError::ProviderNotConfigured => "provider_not_configured",
+            AipixError::ProviderError(_) => "provider_error",
+            AipixError::PluginError(_) => "plugin_error",
+            AipixError::Io(_) => "io_error",
+            AipixError::ImageError(_) => "image_error",
+            AipixError::Serialization(_) => "serialization_error",
+            AipixError::Internal(_) => "internal",
+        }
+    }
+
+    /// Values for the placeholders of `code`'s message
+    pub fn params(&self) -> Value {
+        match self {
+            AipixError::NotFound(item) => json!({ "item": item }),
+            AipixError::InvalidInput(detail)
+            | AipixError::InvalidState(detail)
+            | AipixError::Conflict(detail)
+            | AipixError::ProviderError(detail)
+            | AipixError::PluginError(detail) => json!({ "detail": detail }),
+            AipixError::Io(e) => json!({ "kind": format!("{:?}", e.kind()) }),
+            _ => json!({}),
+        }
+    }
+
+    /// Whether the error text holds internal details that stay in the logs
+    pub fn is_internal(&self) -> bool {
+        matches!(
+            self,
+            AipixError::DbError(_)
+                | AipixError::RendererError(_)
+                | AipixError::Io(_)
+                | AipixError::ImageError(_)
+                | AipixError::Serialization(_)
+                | AipixError::Internal(_)
+        )
+    }
+
+    /// English message for users, free of internal details
+    pub fn user_message(&self) -> String {
+        match self {
+            AipixError::DbError(_) => "The database could not complete the request".to_string(),
+            AipixError::RendererError(_) => "The canvas could not be rendered".to_string(),
+            AipixError::Io(_) => "A file could not be read or written".to_string(),
+            AipixError::ImageError(_) => "The image could not be read or written".to_string(),
+            AipixError::Serialization(_) => "The data could not be read or written".to_string(),
+            AipixError::Internal(_) => "Something went wrong".to_string(),
+            _ => self.to_string(),
+        }
+    }
+}
+
+impl Serialize for AipixError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        if self.is_internal() {
+            tracing::error!(code = self.code(), error = %self, "request failed");
+        }
+        let mut payload = serializer.serialize_struct("AipixError", 3)?;
+        payload.serialize_field("code", self.code())?;
+        payload.serialize_field("params", &self.params())?;
+        payload.serialize_field("message", &self.user_message())?;
+        payload.end()
+    }
+}
+
+pub type Result<T, E = AipixError> = std::result::Result<T, E>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_serialized_payload() {
+        let value = serde_json::to_value(AipixError::NotFound("Canvas")).unwrap();
+        assert_eq!(value["code"], "not_found");
+        assert_eq!(value["params"]["item"], "Canvas");
+        assert_eq!(value["message"], "Canvas not found");
+    }
+
+    #[test]
+    fn test_internal_details_stay_out_of_payload() {
+        let error = AipixError::Io(std::io::Error::new(std::io::ErrorKind::PermissionDenied, "/home/me/secret.png"));
+        let value = serde_json::to_value(error).unwrap();
+        assert_eq!(value["code"], "io_error");
+        assert_eq!(value["params"]["kind"], "PermissionDenied");
+        assert!(!value["message"].as_str().unwrap().contains("secret"));
+    }
+}
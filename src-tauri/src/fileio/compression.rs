@@ -0,0 +1,123 @@
+// Stored canvas encodings
+//
+// Project canvases are saved to project_data.pixel_data in one of several
+// codecs. PNG is the most compact but slow to encode for large canvases;
+// the others store raw RGBA (after an 8-byte little-endian width/height
+// header), optionally compressed. The codec is stored with each canvas, so
+// changing the setting never makes saved projects unreadable.
+use super::{decode_png, encode_png};
+use crate::engine::PixelBuffer;
+use crate::error::{AipixError, Result};
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Write};
+
+/// zstd level; low levels compress pixel art well at close to memcpy speed
+const ZSTD_LEVEL: i32 = 3;
+
+const HEADER_LEN: usize = 8;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PixelCodec {
+    /// PNG, as every project saved before codecs existed
+    #[default]
+    Png,
+    /// Raw RGBA
+    None,
+    Zstd,
+    Deflate,
+}
+
+impl PixelCodec {
+    /// Name stored alongside the pixels
+    pub fn name(self) -> &'static str {
+        match self {
+            PixelCodec::Png => "png",
+            PixelCodec::None => "none",
+            PixelCodec::Zstd => "zstd",
+            PixelCodec::Deflate => "deflate",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Result<Self> {
+        match name {
+            "png" => Ok(PixelCodec::Png),
+            "none" => Ok(PixelCodec::None),
+            "zstd" => Ok(PixelCodec::Zstd),
+            "deflate" => Ok(PixelCodec::Deflate),
+            _ => Err(AipixError::InvalidState(format!("Unknown canvas codec \"{}\"", name))),
+        }
+    }
+}
+
+/// Encode a canvas for storage with `codec`
+pub fn encode_pixels(buffer: &PixelBuffer, codec: PixelCodec) -> Result<Vec<u8>> {
+    if codec == PixelCodec::Png {
+        return Ok(encode_png(buffer)?);
+    }
+
+    let data = buffer.to_rgba();
+    let mut raw = Vec::with_capacity(HEADER_LEN + data.len());
+    raw.extend_from_slice(&buffer.width.to_le_bytes());
+    raw.extend_from_slice(&buffer.height.to_le_bytes());
+    raw.extend_from_slice(&data);
+    match codec {
+        PixelCodec::Zstd => Ok(zstd::encode_all(raw.as_slice(), ZSTD_LEVEL)?),
+        PixelCodec::Deflate => {
+            let mut encoder = DeflateEncoder::new(Vec::new(), Compression::fast());
+            encoder.write_all(&raw)?;
+            Ok(encoder.finish()?)
+        }
+        _ => Ok(raw),
+    }
+}
+
+/// Decode a canvas stored with `codec`
+pub fn decode_pixels(bytes: &[u8], codec: PixelCodec) -> Result<PixelBuffer> {
+    let raw = match codec {
+        PixelCodec::Png => return Ok(decode_png(bytes)?),
+        PixelCodec::None => bytes.to_vec(),
+        PixelCodec::Zstd => zstd::decode_all(bytes)?,
+        PixelCodec::Deflate => {
+            let mut raw = Vec::new();
+            DeflateDecoder::new(bytes).read_to_end(&mut raw)?;
+            raw
+        }
+    };
+
+    let corrupt = || AipixError::InvalidState("Stored canvas is corrupt".to_string());
+    let header = raw.get(..HEADER_LEN).ok_or_else(corrupt)?;
+    let width = u32::from_le_bytes([header[0], header[1], header[2], header[3]]);
+    let height = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+    PixelBuffer::from_rgba(width, height, &raw[HEADER_LEN..]).ok_or_else(corrupt)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_codec_roundtrips() {
+        let mut buffer = PixelBuffer::new(5, 3);
+        buffer.fill_rect(1, 1, 3, 2, [200, 10, 30, 255]);
+        buffer.set_pixel(4, 0, [1, 2, 3, 4]).unwrap();
+
+        for codec in [PixelCodec::Png, PixelCodec::None, PixelCodec::Zstd, PixelCodec::Deflate] {
+            let decoded = decode_pixels(&encode_pixels(&buffer, codec).unwrap(), codec).unwrap();
+            assert_eq!((decoded.width, decoded.height), (5, 3), "{:?}", codec);
+            assert_eq!(decoded, buffer, "{:?}", codec);
+            assert_eq!(PixelCodec::from_name(codec.name()).unwrap(), codec);
+        }
+    }
+
+    #[test]
+    fn test_corrupt_data() {
+        let raw = encode_pixels(&PixelBuffer::new(2, 2), PixelCodec::None).unwrap();
+        assert!(decode_pixels(&raw[..raw.len() - 1], PixelCodec::None).is_err());
+        assert!(decode_pixels(&raw[..4], PixelCodec::None).is_err());
+        assert!(PixelCodec::from_name("lz4").is_err());
+    }
+}
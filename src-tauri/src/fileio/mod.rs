@@ -1,5 +1,15 @@
 // File I/O operations for loading and saving images
-use image::{ImageError, RgbaImage};
+pub mod compression;
+pub mod project_file;
+pub mod viewer;
+
+use crate::engine::PixelBuffer;
+use crate::error::AipixError;
+use image::error::{ParameterError, ParameterErrorKind};
+use image::imageops::{self, FilterType};
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::{Delay, Frame, ImageError, ImageFormat, RgbaImage};
+use std::io::Cursor;
 use std::path::Path;
 
 pub fn load_image(path: &Path) -> Result<RgbaImage, ImageError> {
@@ -11,6 +21,117 @@ pub fn save_image(path: &Path, img: &RgbaImage) -> Result<(), ImageError> {
     img.save(path)
 }
 
+/// Convert a pixel buffer into an image for encoding
+pub fn buffer_to_image(buffer: &PixelBuffer) -> Result<RgbaImage, ImageError> {
+    RgbaImage::from_raw(buffer.width, buffer.height, buffer.to_rgba()).ok_or_else(|| {
+        ImageError::Parameter(ParameterError::from_kind(ParameterErrorKind::DimensionMismatch))
+    })
+}
+
+/// Convert a decoded image into a pixel buffer
+pub fn image_to_buffer(img: &RgbaImage) -> Result<PixelBuffer, ImageError> {
+    PixelBuffer::from_rgba(img.width(), img.height(), img.as_raw()).ok_or_else(|| {
+        ImageError::Parameter(ParameterError::from_kind(ParameterErrorKind::DimensionMismatch))
+    })
+}
+
+/// Encode a pixel buffer as PNG bytes (for IPC and remote providers)
+pub fn encode_png(buffer: &PixelBuffer) -> Result<Vec<u8>, ImageError> {
+    let img = buffer_to_image(buffer)?;
+    let mut bytes = Vec::new();
+    img.write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png)?;
+    Ok(bytes)
+}
+
+/// Shrink a pixel buffer to at most `max_size` on either side (nearest-neighbor, never upscaled)
+fn shrink(buffer: &PixelBuffer, max_size: u32) -> Result<RgbaImage, ImageError> {
+    let img = buffer_to_image(buffer)?;
+    let scale = (max_size as f32 / img.width().max(img.height()).max(1) as f32).min(1.0);
+    let width = ((img.width() as f32 * scale).round() as u32).max(1);
+    let height = ((img.height() as f32 * scale).round() as u32).max(1);
+    Ok(imageops::resize(&img, width, height, FilterType::Nearest))
+}
+
+/// Encode a PNG preview no larger than `max_size` on either side (nearest-neighbor, never upscaled)
+pub fn encode_thumbnail(buffer: &PixelBuffer, max_size: u32) -> Result<Vec<u8>, ImageError> {
+    let thumbnail = shrink(buffer, max_size)?;
+    let mut bytes = Vec::new();
+    thumbnail.write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png)?;
+    Ok(bytes)
+}
+
+/// Encode frames as a looping GIF preview, each shrunk as by encode_thumbnail and shown for `delay_ms`
+///
+/// GIF has no partial transparency, so pixels are either shown or not.
+pub fn encode_gif_thumbnail(frames: &[PixelBuffer], max_size: u32, delay_ms: u32) -> Result<Vec<u8>, ImageError> {
+    let mut bytes = Vec::new();
+    {
+        let mut encoder = GifEncoder::new_with_speed(&mut bytes, 10);
+        encoder.set_repeat(Repeat::Infinite)?;
+        for frame in frames {
+            let delay = Delay::from_numer_denom_ms(delay_ms, 1);
+            encoder.encode_frame(Frame::from_parts(shrink(frame, max_size)?, 0, 0, delay))?;
+        }
+    }
+    Ok(bytes)
+}
+
+/// Center-crop an image to a square and shrink it to at most `size` pixels a side
+///
+/// Meant for photos and other avatars, so it filters smoothly; small images
+/// are never upscaled.
+pub fn square_avatar(img: &RgbaImage, size: u32) -> RgbaImage {
+    let side = img.width().min(img.height());
+    let x = (img.width() - side) / 2;
+    let y = (img.height() - side) / 2;
+    let square = imageops::crop_imm(img, x, y, side, side).to_image();
+
+    if side > size {
+        imageops::resize(&square, size, size, FilterType::Lanczos3)
+    } else {
+        square
+    }
+}
+
+/// Fill `{token}` placeholders of a file name pattern
+///
+/// Values are made safe for file names (path separators and other reserved
+/// characters become `_`); unknown tokens are an error so typos don't end
+/// up in file names.
+pub fn expand_filename_pattern(pattern: &str, tokens: &[(&str, String)]) -> crate::error::Result<String> {
+    let mut name = String::new();
+    let mut rest = pattern;
+    while let Some(start) = rest.find('{') {
+        name.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| AipixError::InvalidInput(format!("Unclosed token in \"{}\"", pattern)))?;
+        let token = &rest[start + 1..start + end];
+        let (_, value) = tokens
+            .iter()
+            .find(|(name, _)| *name == token)
+            .ok_or_else(|| AipixError::InvalidInput(format!("Unknown file name token {{{}}}", token)))?;
+        name.extend(value.chars().map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        }));
+        rest = &rest[start + end + 1..];
+    }
+    name.push_str(rest);
+
+    let name = name.trim();
+    if name.is_empty() || name == "." || name == ".." || name.contains(['/', '\\']) {
+        return Err(AipixError::InvalidInput(format!("\"{}\" is not a valid file name", name)));
+    }
+    Ok(name.to_string())
+}
+
+/// Decode PNG (or any supported format) bytes into a pixel buffer
+pub fn decode_png(bytes: &[u8]) -> Result<PixelBuffer, ImageError> {
+    image_to_buffer(&image::load_from_memory(bytes)?.to_rgba8())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -20,4 +141,50 @@ mod tests {
         // Basic test placeholder
         // TODO: Add comprehensive tests
     }
+
+    #[test]
+    fn test_png_roundtrip() {
+        let mut buffer = PixelBuffer::new(3, 2);
+        buffer.set_pixel(1, 1, [10, 20, 30, 128]).unwrap();
+
+        let bytes = encode_png(&buffer).unwrap();
+        let decoded = decode_png(&bytes).unwrap();
+
+        assert_eq!(decoded.width, 3);
+        assert_eq!(decoded.height, 2);
+        assert_eq!(decoded, buffer);
+    }
+
+    #[test]
+    fn test_gif_thumbnail() {
+        let mut frames = vec![PixelBuffer::new(8, 4), PixelBuffer::new(8, 4)];
+        frames[1].set_pixel(0, 0, [255, 0, 0, 255]).unwrap();
+
+        let bytes = encode_gif_thumbnail(&frames, 4, 100).unwrap();
+        assert!(bytes.starts_with(b"GIF89a"));
+        let decoded = decode_png(&bytes).unwrap(); // First frame only
+        assert_eq!((decoded.width, decoded.height), (4, 2));
+    }
+
+    #[test]
+    fn test_expand_filename_pattern() {
+        let tokens = [("name", "hero/walk".to_string()), ("frame", "3".to_string())];
+        assert_eq!(expand_filename_pattern("{name}_{frame}.png", &tokens).unwrap(), "hero_walk_3.png");
+        assert_eq!(expand_filename_pattern("sheet", &tokens).unwrap(), "sheet");
+        assert!(expand_filename_pattern("{nmae}", &tokens).is_err());
+        assert!(expand_filename_pattern("{name", &tokens).is_err());
+        assert!(expand_filename_pattern("../{frame}", &tokens).is_err());
+    }
+
+    #[test]
+    fn test_square_avatar() {
+        let mut img = RgbaImage::new(30, 10);
+        img.put_pixel(15, 5, image::Rgba([255, 0, 0, 255]));
+
+        let cropped = square_avatar(&img, 64);
+        assert_eq!(cropped.dimensions(), (10, 10));
+        assert_eq!(cropped.get_pixel(5, 5).0, [255, 0, 0, 255]);
+
+        assert_eq!(square_avatar(&RgbaImage::new(300, 400), 64).dimensions(), (64, 64));
+    }
 }
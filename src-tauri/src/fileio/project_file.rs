@@ -0,0 +1,107 @@
+// .aipix project files
+//
+// A JSON document holding one canvas (as a base64 PNG) and its palette.
+// `version` is bumped when fields are added so older builds can refuse
+// files they would silently truncate.
+use super::{decode_png, encode_png};
+use crate::engine::{Document, Palette};
+use crate::error::{AipixError, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+pub const EXTENSION: &str = "aipix";
+const FORMAT: &str = "aipix";
+const VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+struct ProjectFile {
+    format: String,
+    version: u32,
+    name: Option<String>,
+    width: u32,
+    height: u32,
+    palette: Vec<[u8; 4]>,
+    image: String, // Base64 PNG
+}
+
+/// A document read from disk, with the name stored alongside it
+pub struct LoadedProject {
+    pub name: Option<String>,
+    pub document: Document,
+}
+
+pub fn save(path: &Path, document: &Document, name: Option<&str>) -> Result<()> {
+    let buffer = &document.history.buffer;
+    let file = ProjectFile {
+        format: FORMAT.to_string(),
+        version: VERSION,
+        name: name.map(str::to_string),
+        width: buffer.width,
+        height: buffer.height,
+        palette: document.history.palette.colors.clone(),
+        image: STANDARD.encode(encode_png(buffer)?),
+    };
+
+    // Write beside the target and rename so a crash never leaves half a file
+    let tmp = path.with_extension("aipix.tmp");
+    std::fs::write(&tmp, serde_json::to_vec(&file)?)?;
+    std::fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+pub fn load(path: &Path) -> Result<LoadedProject> {
+    let file: ProjectFile = serde_json::from_slice(&std::fs::read(path)?)
+        .map_err(|e| AipixError::InvalidInput(format!("Not an AIPIX project file: {}", e)))?;
+
+    if file.format != FORMAT {
+        return Err(AipixError::InvalidInput("Not an AIPIX project file".to_string()));
+    }
+    if file.version > VERSION {
+        return Err(AipixError::InvalidInput(format!(
+            "Project file version {} needs a newer AIPIX",
+            file.version
+        )));
+    }
+
+    let bytes = STANDARD
+        .decode(&file.image)
+        .map_err(|_| AipixError::InvalidInput("Project image is not valid base64".to_string()))?;
+    let buffer = decode_png(&bytes)?;
+    if (buffer.width, buffer.height) != (file.width, file.height) {
+        return Err(AipixError::InvalidInput(
+            "Project image does not match its recorded size".to_string(),
+        ));
+    }
+
+    let mut document = Document::new(buffer.width, buffer.height);
+    document.history.buffer = buffer;
+    document.history.palette = Palette { colors: file.palette };
+    Ok(LoadedProject { name: file.name, document })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_save_load_roundtrip() {
+        let dir = std::env::temp_dir().join(format!("aipix-project-file-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("sprite.aipix");
+
+        let mut document = Document::new(4, 3);
+        document.history.buffer.set_pixel(2, 1, [1, 2, 3, 200]).unwrap();
+        document.history.palette.colors.push([1, 2, 3, 200]);
+        save(&path, &document, Some("Sprite")).unwrap();
+
+        let loaded = load(&path).unwrap();
+        assert_eq!(loaded.name.as_deref(), Some("Sprite"));
+        assert_eq!(loaded.document.history.buffer, document.history.buffer);
+        assert_eq!(loaded.document.history.palette.colors, vec![[1, 2, 3, 200]]);
+
+        std::fs::write(&path, b"{\"format\":\"other\"}").unwrap();
+        assert!(load(&path).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,99 @@
+// Standalone animation viewer
+//
+// A single HTML file with every frame embedded as a base64 PNG and a small
+// player script, so a preview can be sent to anyone with a browser. Frames
+// are drawn with nearest-neighbour scaling at `zoom` times their size.
+use super::encode_png;
+use crate::engine::PixelBuffer;
+use crate::error::Result;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde_json::json;
+
+const PLAYER: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>{{title}}</title>
+<style>
+  body { margin: 0; min-height: 100vh; display: flex; flex-direction: column; align-items: center;
+         justify-content: center; gap: 12px; background: #202124; color: #e8eaed; font: 14px sans-serif; }
+  img { image-rendering: pixelated; background: repeating-conic-gradient(#555 0 25%, #444 0 50%) 0 0 / 16px 16px; }
+  button { background: #3c4043; color: inherit; border: 0; border-radius: 4px; padding: 6px 12px; cursor: pointer; }
+</style>
+</head>
+<body>
+<h1>{{title}}</h1>
+<img id="frame" alt="">
+<div>
+  <button id="previous">&#9664;</button>
+  <button id="play">Pause</button>
+  <button id="next">&#9654;</button>
+  <span id="counter"></span>
+</div>
+<script>
+const animation = {{animation}};
+const image = document.getElementById("frame");
+const counter = document.getElementById("counter");
+const play = document.getElementById("play");
+image.width = animation.width * animation.zoom;
+image.height = animation.height * animation.zoom;
+let index = 0, timer = null;
+
+function show(next) {
+  index = (next + animation.frames.length) % animation.frames.length;
+  image.src = "data:image/png;base64," + animation.frames[index];
+  counter.textContent = (index + 1) + " / " + animation.frames.length;
+}
+function tick() {
+  timer = setTimeout(() => { show(index + 1); tick(); }, animation.durations[index]);
+}
+function toggle() {
+  if (timer) { clearTimeout(timer); timer = null; } else { tick(); }
+  play.textContent = timer ? "Pause" : "Play";
+}
+document.getElementById("previous").onclick = () => show(index - 1);
+document.getElementById("next").onclick = () => show(index + 1);
+play.onclick = toggle;
+show(0);
+if (animation.frames.length > 1) tick(); else play.disabled = true;
+</script>
+</body>
+</html>
+"#;
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// The viewer page for `frames`, all of the same size, each shown for its entry of `durations_ms`
+pub fn viewer_html(title: &str, frames: &[PixelBuffer], durations_ms: &[u32], zoom: u32) -> Result<String> {
+    let (width, height) = frames.first().map_or((0, 0), |frame| (frame.width, frame.height));
+    let encoded = frames.iter().map(|frame| Ok(STANDARD.encode(encode_png(frame)?))).collect::<Result<Vec<_>>>()?;
+    let animation = json!({
+        "width": width,
+        "height": height,
+        "zoom": zoom.max(1),
+        "frames": encoded,
+        "durations": durations_ms,
+    });
+
+    // `</` can't appear in the JSON, so it can't close the script element early
+    let animation = animation.to_string().replace("</", "<\\/");
+    Ok(PLAYER.replace("{{title}}", &escape_html(title)).replace("{{animation}}", &animation))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_viewer_html() {
+        let frames = vec![PixelBuffer::new(2, 2), PixelBuffer::new(2, 2)];
+        let html = viewer_html("Hero <run>", &frames, &[100, 150], 8).unwrap();
+
+        assert!(html.contains("<title>Hero &lt;run&gt;</title>"));
+        assert_eq!(html.matches("iVBORw0KGgo").count(), 2); // Base64 PNG signatures
+        assert!(html.contains(r#""durations":[100,150]"#));
+        assert!(!html.contains("{{"));
+    }
+}
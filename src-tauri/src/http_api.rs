@@ -0,0 +1,342 @@
+// Local HTTP API
+//
+// Optional server on 127.0.0.1 so external tools (game editors, build
+// scripts) can fetch the current sprite or trigger exports while the editor
+// is running:
+//   GET  /api/projects                  open projects and their sizes
+//   GET  /api/projects/{id}/canvas.png  canvas as PNG, `?scale=1..8`
+//   GET  /api/projects/{id}/palette     palette as hex colors
+//   POST /api/projects/{id}/export      `{ "path", "scale" }`, writes a PNG
+//   GET  /api/events                    WebSocket stream of document changes
+//
+// Every request must carry the token generated when the server starts, as
+// `Authorization: Bearer <token>` or `?token=<token>` (browsers can't set
+// headers on WebSocket upgrades). Binding to localhost alone doesn't stop
+// web pages in a browser from reaching the port.
+
+use crate::commands::events::{DocumentChanged, DOCUMENT_CHANGED};
+use crate::engine::{self, UpscaleAlgorithm};
+use crate::error::{AipixError, Result};
+use crate::fileio;
+use crate::AppState;
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{Path, Query, Request, State};
+use axum::http::{header, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+use std::net::{Ipv4Addr, SocketAddr, TcpListener};
+use tauri::{AppHandle, Manager};
+use tokio::sync::{broadcast, oneshot};
+
+/// Change events buffered per WebSocket client before it starts missing some
+const EVENT_BUFFER: usize = 64;
+
+impl IntoResponse for AipixError {
+    fn into_response(self) -> Response {
+        let status = match self {
+            AipixError::NotFound(_) => StatusCode::NOT_FOUND,
+            AipixError::OutOfBounds | AipixError::InvalidInput(_) => StatusCode::BAD_REQUEST,
+            AipixError::InvalidState(_) | AipixError::Conflict(_) => StatusCode::CONFLICT,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        (status, Json(self)).into_response()
+    }
+}
+
+#[derive(Clone)]
+struct ApiState {
+    app: AppHandle,
+    token: String,
+    events: broadcast::Sender<DocumentChanged>,
+}
+
+impl ApiState {
+    /// Run `f` against the app state off the async runtime (documents use blocking locks)
+    async fn blocking<T: Send + 'static>(
+        &self,
+        f: impl FnOnce(&AppState) -> Result<T> + Send + 'static,
+    ) -> Result<T> {
+        let app = self.app.clone();
+        tauri::async_runtime::spawn_blocking(move || f(&app.state::<AppState>()))
+            .await
+            .map_err(|e| AipixError::Internal(format!("API handler failed: {}", e)))?
+    }
+}
+
+/// A running API server; `stop` shuts it down
+pub struct ServerHandle {
+    pub addr: SocketAddr,
+    pub token: String,
+    events: broadcast::Sender<DocumentChanged>,
+    shutdown: oneshot::Sender<()>,
+}
+
+impl ServerHandle {
+    /// Forward a document change to connected WebSocket clients
+    pub fn publish(&self, event: DocumentChanged) {
+        // Err only means nobody is listening
+        let _ = self.events.send(event);
+    }
+
+    pub fn stop(self) {
+        let _ = self.shutdown.send(());
+    }
+}
+
+/// Start the API on localhost; port 0 picks a free port
+pub fn start(app: AppHandle, port: u16) -> Result<ServerHandle> {
+    // Bind synchronously so address-in-use errors reach the caller
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))?;
+    listener.set_nonblocking(true)?;
+    let addr = listener.local_addr()?;
+
+    let token = uuid::Uuid::new_v4().simple().to_string();
+    let (events, _) = broadcast::channel(EVENT_BUFFER);
+    let (shutdown, stopped) = oneshot::channel::<()>();
+    let router = router(ApiState { app, token: token.clone(), events: events.clone() });
+
+    tauri::async_runtime::spawn(async move {
+        let listener = match tokio::net::TcpListener::from_std(listener) {
+            Ok(listener) => listener,
+            Err(e) => {
+                tracing::error!(error = %e, "http api failed to start");
+                return;
+            }
+        };
+        tracing::info!(%addr, "http api listening");
+
+        let server = axum::serve(listener, router).with_graceful_shutdown(async {
+            let _ = stopped.await;
+        });
+        if let Err(e) = server.await {
+            tracing::error!(error = %e, "http api failed");
+        }
+        tracing::info!(%addr, "http api stopped");
+    });
+
+    Ok(ServerHandle { addr, token, events, shutdown })
+}
+
+fn router(state: ApiState) -> Router {
+    Router::new()
+        .route("/api/projects", get(list_projects))
+        .route("/api/projects/{id}/canvas.png", get(canvas_png))
+        .route("/api/projects/{id}/palette", get(palette))
+        .route("/api/projects/{id}/export", post(export))
+        .route("/api/events", get(events))
+        .layer(middleware::from_fn_with_state(state.clone(), authorize))
+        .with_state(state)
+}
+
+/// Token from the Authorization header, or else the `token` query parameter
+///
+/// Also checks MCP WebSocket handshakes, which carry the MCP server's token.
+pub(crate) fn request_token<B>(request: &axum::http::Request<B>) -> Option<&str> {
+    let header = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+
+    header.or_else(|| {
+        request
+            .uri()
+            .query()?
+            .split('&')
+            .find_map(|pair| pair.strip_prefix("token="))
+    })
+}
+
+async fn authorize(State(api): State<ApiState>, request: Request, next: Next) -> Response {
+    if request_token(&request) != Some(api.token.as_str()) {
+        return (StatusCode::UNAUTHORIZED, "Missing or invalid API token").into_response();
+    }
+    next.run(request).await
+}
+
+#[derive(Serialize)]
+struct ProjectInfo {
+    id: String,
+    width: u32,
+    height: u32,
+}
+
+async fn list_projects(State(api): State<ApiState>) -> Result<Json<Vec<ProjectInfo>>> {
+    let projects = api
+        .blocking(|state| {
+            let handles: Vec<_> = state
+                .documents
+                .read()
+                .unwrap()
+                .iter()
+                .map(|(id, handle)| (id.clone(), handle.clone()))
+                .collect();
+
+            let mut projects: Vec<ProjectInfo> = handles
+                .into_iter()
+                .map(|(id, handle)| {
+                    let document = handle.lock().unwrap();
+                    ProjectInfo {
+                        id,
+                        width: document.history.buffer.width,
+                        height: document.history.buffer.height,
+                    }
+                })
+                .collect();
+            projects.sort_by(|a, b| a.id.cmp(&b.id));
+            Ok(projects)
+        })
+        .await?;
+    Ok(Json(projects))
+}
+
+#[derive(Deserialize)]
+struct ScaleQuery {
+    scale: Option<u32>,
+}
+
+/// The canvas scaled up by an integer factor (nearest-neighbor, as pixel art expects)
+fn scaled_canvas(state: &AppState, project_id: &str, scale: Option<u32>) -> Result<engine::PixelBuffer> {
+    let buffer = state.document(project_id)?.lock().unwrap().history.buffer.clone();
+    engine::upscale::upscale(&buffer, UpscaleAlgorithm::Nearest, scale.unwrap_or(1))
+}
+
+async fn canvas_png(
+    State(api): State<ApiState>,
+    Path(project_id): Path<String>,
+    Query(query): Query<ScaleQuery>,
+) -> Result<Response> {
+    let png = api
+        .blocking(move |state| {
+            let buffer = scaled_canvas(state, &project_id, query.scale)?;
+            Ok(fileio::encode_png(&buffer)?)
+        })
+        .await?;
+
+    Ok((
+        [(header::CONTENT_TYPE, "image/png"), (header::CACHE_CONTROL, "no-store")],
+        png,
+    )
+        .into_response())
+}
+
+async fn palette(State(api): State<ApiState>, Path(project_id): Path<String>) -> Result<Json<Vec<String>>> {
+    let colors = api
+        .blocking(move |state| {
+            let document = state.document(&project_id)?;
+            let document = document.lock().unwrap();
+            Ok(document
+                .history
+                .palette
+                .colors
+                .iter()
+                .map(|c| engine::tools::rgba_to_hex(*c))
+                .collect())
+        })
+        .await?;
+    Ok(Json(colors))
+}
+
+#[derive(Deserialize)]
+struct ExportRequest {
+    path: String,
+    scale: Option<u32>,
+}
+
+#[derive(Serialize)]
+struct ExportResult {
+    path: String,
+    width: u32,
+    height: u32,
+}
+
+async fn export(
+    State(api): State<ApiState>,
+    Path(project_id): Path<String>,
+    Json(request): Json<ExportRequest>,
+) -> Result<Json<ExportResult>> {
+    let path = std::path::PathBuf::from(&request.path);
+    if !path.is_absolute() {
+        return Err(AipixError::InvalidInput("Export path must be absolute".to_string()));
+    }
+
+    let result = api
+        .blocking(move |state| {
+            let buffer = scaled_canvas(state, &project_id, request.scale)?;
+            fileio::save_image(&path, &fileio::buffer_to_image(&buffer)?)?;
+            tracing::info!(project_id = %project_id, path = %path.display(), "exported through http api");
+            Ok(ExportResult { path: request.path, width: buffer.width, height: buffer.height })
+        })
+        .await?;
+    Ok(Json(result))
+}
+
+#[derive(Serialize)]
+struct EventMessage<'a> {
+    event: &'a str,
+    #[serde(flatten)]
+    payload: &'a DocumentChanged,
+}
+
+async fn events(ws: WebSocketUpgrade, State(api): State<ApiState>) -> Response {
+    let receiver = api.events.subscribe();
+    ws.on_upgrade(move |socket| stream_events(socket, receiver))
+}
+
+async fn stream_events(mut socket: WebSocket, mut receiver: broadcast::Receiver<DocumentChanged>) {
+    loop {
+        tokio::select! {
+            event = receiver.recv() => match event {
+                Ok(event) => {
+                    let message = EventMessage { event: DOCUMENT_CHANGED, payload: &event };
+                    let Ok(text) = serde_json::to_string(&message) else { continue };
+                    if socket.send(Message::Text(text.into())).await.is_err() {
+                        break;
+                    }
+                }
+                // A slow client only needs to know something changed; refetching catches it up
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+            message = socket.recv() => match message {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => {} // Clients have nothing to say on this channel
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+
+    fn request(uri: &str, authorization: Option<&str>) -> Request {
+        let mut builder = axum::http::Request::builder().uri(uri);
+        if let Some(value) = authorization {
+            builder = builder.header(header::AUTHORIZATION, value);
+        }
+        builder.body(Body::empty()).unwrap()
+    }
+
+    #[test]
+    fn test_request_token() {
+        assert_eq!(request_token(&request("/api/projects", Some("Bearer abc"))), Some("abc"));
+        assert_eq!(request_token(&request("/api/events?x=1&token=abc", None)), Some("abc"));
+        // The header wins over the query
+        assert_eq!(request_token(&request("/api/events?token=abc", Some("Bearer def"))), Some("def"));
+        assert_eq!(request_token(&request("/api/projects", Some("Basic abc"))), None);
+        assert_eq!(request_token(&request("/api/projects?tokens=abc", None)), None);
+    }
+
+    #[test]
+    fn test_error_status() {
+        let status = |error: AipixError| error.into_response().status();
+        assert_eq!(status(AipixError::NotFound("Canvas")), StatusCode::NOT_FOUND);
+        assert_eq!(status(AipixError::InvalidInput("bad".to_string())), StatusCode::BAD_REQUEST);
+        assert_eq!(status(AipixError::Internal("oops".to_string())), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+}
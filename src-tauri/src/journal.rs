@@ -0,0 +1,713 @@
+// Crash recovery journal
+//
+// Every open project gets a directory under `<app data>/recovery/<project_id>`
+// holding a checkpoint (the last autosave: the canvas as PNG plus the sequence
+// number it includes) and an append-only `journal.jsonl` of the operations
+// applied since. Each entry is written as soon as the operation succeeds, so
+// after a crash replaying the journal over the checkpoint loses at most the
+// operation that was in flight.
+//
+// Operations are recorded as their inputs where replay is deterministic
+// (strokes, fills, undo/redo) and as the resulting pixels otherwise (anything
+// that depends on the selection, clipboard or an AI provider).
+
+use crate::engine::{
+    self, Brush, BrushDynamics, Dither, Document, Jumble, LineProfile, PixelBuffer, SelectionBounds, Shade,
+    SheetLayout, UpscaleAlgorithm,
+};
+use crate::error::{AipixError, Result};
+use crate::fileio;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+const CHECKPOINT_FILE: &str = "checkpoint.json";
+const JOURNAL_FILE: &str = "journal.jsonl";
+
+/// Entries after which the journal is folded into a new checkpoint
+pub const AUTOSAVE_INTERVAL: u64 = 500;
+
+/// A replayable document mutation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Operation {
+    PushState,
+    Undo,
+    Redo,
+    Pencil { x: u32, y: u32, color: [u8; 4] },
+    Eraser { x: u32, y: u32 },
+    Line {
+        x0: i32,
+        y0: i32,
+        x1: i32,
+        y1: i32,
+        color: [u8; 4],
+        #[serde(default = "one_pixel")]
+        thickness: u32,
+        #[serde(default)]
+        profile: LineProfile,
+    },
+    BrushStroke { x0: u32, y0: u32, x1: u32, y1: u32, color: [u8; 4], brush: Brush }, // Pencil or eraser wider than a pixel
+    // A batch of freehand points; with `pressures` (one per point, 0-1) drawn through `dynamics`
+    PencilStroke {
+        points: Vec<(i32, i32)>,
+        color: [u8; 4],
+        brush: Brush,
+        #[serde(default)]
+        pressures: Option<Vec<f32>>,
+        #[serde(default)]
+        dynamics: Option<BrushDynamics>,
+    },
+    DitherStroke { x0: u32, y0: u32, x1: u32, y1: u32, dither: Dither, brush: Brush },
+    ShadeStroke { x0: u32, y0: u32, x1: u32, y1: u32, shade: Shade, brush: Brush, continued: bool },
+    BlurStroke { x0: u32, y0: u32, x1: u32, y1: u32, brush: Brush, strength: f32 },
+    JumbleStroke { x0: u32, y0: u32, x1: u32, y1: u32, jumble: Jumble, brush: Brush },
+    Rectangle {
+        x0: u32,
+        y0: u32,
+        x1: u32,
+        y1: u32,
+        color: [u8; 4],
+        filled: bool,
+        #[serde(default = "one_pixel")]
+        thickness: u32,
+    },
+    Circle {
+        center_x: i32,
+        center_y: i32,
+        end_x: i32,
+        end_y: i32,
+        color: [u8; 4],
+        filled: bool,
+        #[serde(default = "one_pixel")]
+        thickness: u32,
+    },
+    Ellipse {
+        // Inclusive bounding box
+        x0: i32,
+        y0: i32,
+        x1: i32,
+        y1: i32,
+        color: [u8; 4],
+        filled: bool,
+        #[serde(default = "one_pixel")]
+        thickness: u32,
+    },
+    Fill { x: u32, y: u32, color: [u8; 4], #[serde(default)] tolerance: u8 },
+    ReplaceColor { target: [u8; 4], replacement: [u8; 4] },
+    SetPixels { pixels: Vec<(u32, u32, [u8; 4])> },
+    Upscale { algorithm: UpscaleAlgorithm, factor: u32 },
+    Paste { x: u32, y: u32, image: String },  // Base64 PNG of the clipboard
+    Patch { x: u32, y: u32, image: String },  // Base64 PNG of the resulting pixels
+    Replace { image: String },                // Base64 PNG of a new, possibly resized canvas
+}
+
+/// Outline thickness of shapes journaled before it could be set
+fn one_pixel() -> u32 {
+    1
+}
+
+impl Operation {
+    /// Record the pixels of `buffer` inside `bounds` (the whole canvas if `None`)
+    pub fn patch(buffer: &PixelBuffer, bounds: Option<SelectionBounds>) -> Result<Self> {
+        let (x, y, width, height) = match bounds {
+            Some(b) => (b.min_x, b.min_y, b.max_x - b.min_x + 1, b.max_y - b.min_y + 1),
+            None => (0, 0, buffer.width, buffer.height),
+        };
+        let region = buffer.copy_region(x, y, width, height);
+        Ok(Operation::Patch { x, y, image: encode(&region)? })
+    }
+
+    /// The one-pixel line joining two points of a pencil or eraser stroke
+    pub fn pencil_line((x0, y0): (u32, u32), (x1, y1): (u32, u32), color: [u8; 4]) -> Self {
+        let (x0, y0, x1, y1) = (x0 as i32, y0 as i32, x1 as i32, y1 as i32);
+        Operation::Line { x0, y0, x1, y1, color, thickness: 1, profile: LineProfile::Uniform }
+    }
+
+    pub fn paste(source: &PixelBuffer, x: u32, y: u32) -> Result<Self> {
+        Ok(Operation::Paste { x, y, image: encode(source)? })
+    }
+
+    pub fn replace(buffer: &PixelBuffer) -> Result<Self> {
+        Ok(Operation::Replace { image: encode(buffer)? })
+    }
+
+    /// Whether the operation only draws on the canvas in place (what a batch may hold)
+    fn is_drawing(&self) -> bool {
+        matches!(
+            self,
+            Operation::Pencil { .. }
+                | Operation::Eraser { .. }
+                | Operation::Line { .. }
+                | Operation::BrushStroke { .. }
+                | Operation::PencilStroke { .. }
+                | Operation::DitherStroke { .. }
+                | Operation::ShadeStroke { .. }
+                | Operation::BlurStroke { .. }
+                | Operation::JumbleStroke { .. }
+                | Operation::Rectangle { .. }
+                | Operation::Circle { .. }
+                | Operation::Ellipse { .. }
+                | Operation::Fill { .. }
+                | Operation::ReplaceColor { .. }
+                | Operation::SetPixels { .. }
+        )
+    }
+
+    /// A drawing operation inside `frame` (x, y, width, height), moved to the frame's own coordinates
+    fn frame_local(&self, (x, y, width, height): (u32, u32, u32, u32)) -> Result<Self> {
+        let points = match *self {
+            Operation::Pencil { x, y, .. } | Operation::Eraser { x, y } | Operation::Fill { x, y, .. } => {
+                vec![(x as i64, y as i64)]
+            }
+            Operation::Line { x0, y0, x1, y1, .. }
+            | Operation::Ellipse { x0, y0, x1, y1, .. }
+            | Operation::Circle { center_x: x0, center_y: y0, end_x: x1, end_y: y1, .. } => {
+                vec![(x0 as i64, y0 as i64), (x1 as i64, y1 as i64)]
+            }
+            Operation::Rectangle { x0, y0, x1, y1, .. }
+            | Operation::BrushStroke { x0, y0, x1, y1, .. }
+            | Operation::DitherStroke { x0, y0, x1, y1, .. }
+            | Operation::ShadeStroke { x0, y0, x1, y1, .. }
+            | Operation::BlurStroke { x0, y0, x1, y1, .. }
+            | Operation::JumbleStroke { x0, y0, x1, y1, .. } => {
+                vec![(x0 as i64, y0 as i64), (x1 as i64, y1 as i64)]
+            }
+            Operation::SetPixels { ref pixels } => pixels.iter().map(|&(x, y, _)| (x as i64, y as i64)).collect(),
+            Operation::PencilStroke { ref points, .. } => points.iter().map(|&(x, y)| (x as i64, y as i64)).collect(),
+            _ => Vec::new(),
+        };
+        let inside = |&(px, py): &(i64, i64)| {
+            (x as i64..(x + width) as i64).contains(&px) && (y as i64..(y + height) as i64).contains(&py)
+        };
+        if !points.iter().all(inside) {
+            return Err(AipixError::InvalidInput(
+                "Operations repeated across frames must lie within the source frame".to_string(),
+            ));
+        }
+
+        let (dx, dy) = (x as i32, y as i32);
+        Ok(match self.clone() {
+            Operation::Pencil { x: px, y: py, color } => Operation::Pencil { x: px - x, y: py - y, color },
+            Operation::Eraser { x: px, y: py } => Operation::Eraser { x: px - x, y: py - y },
+            Operation::Fill { x: px, y: py, color, tolerance } => {
+                Operation::Fill { x: px - x, y: py - y, color, tolerance }
+            }
+            Operation::Line { x0, y0, x1, y1, color, thickness, profile } => {
+                Operation::Line { x0: x0 - dx, y0: y0 - dy, x1: x1 - dx, y1: y1 - dy, color, thickness, profile }
+            }
+            Operation::Rectangle { x0, y0, x1, y1, color, filled, thickness } => {
+                Operation::Rectangle { x0: x0 - x, y0: y0 - y, x1: x1 - x, y1: y1 - y, color, filled, thickness }
+            }
+            Operation::Ellipse { x0, y0, x1, y1, color, filled, thickness } => {
+                Operation::Ellipse { x0: x0 - dx, y0: y0 - dy, x1: x1 - dx, y1: y1 - dy, color, filled, thickness }
+            }
+            Operation::BrushStroke { x0, y0, x1, y1, color, brush } => {
+                Operation::BrushStroke { x0: x0 - x, y0: y0 - y, x1: x1 - x, y1: y1 - y, color, brush }
+            }
+            Operation::DitherStroke { x0, y0, x1, y1, dither, brush } => {
+                Operation::DitherStroke { x0: x0 - x, y0: y0 - y, x1: x1 - x, y1: y1 - y, dither, brush }
+            }
+            Operation::ShadeStroke { x0, y0, x1, y1, shade, brush, continued } => {
+                Operation::ShadeStroke { x0: x0 - x, y0: y0 - y, x1: x1 - x, y1: y1 - y, shade, brush, continued }
+            }
+            Operation::BlurStroke { x0, y0, x1, y1, brush, strength } => {
+                Operation::BlurStroke { x0: x0 - x, y0: y0 - y, x1: x1 - x, y1: y1 - y, brush, strength }
+            }
+            Operation::JumbleStroke { x0, y0, x1, y1, jumble, brush } => {
+                Operation::JumbleStroke { x0: x0 - x, y0: y0 - y, x1: x1 - x, y1: y1 - y, jumble, brush }
+            }
+            Operation::Circle { center_x, center_y, end_x, end_y, color, filled, thickness } => Operation::Circle {
+                center_x: center_x - dx,
+                center_y: center_y - dy,
+                end_x: end_x - dx,
+                end_y: end_y - dy,
+                color,
+                filled,
+                thickness,
+            },
+            Operation::PencilStroke { points, color, brush, pressures, dynamics } => Operation::PencilStroke {
+                points: points.into_iter().map(|(px, py)| (px - dx, py - dy)).collect(),
+                color,
+                brush,
+                pressures,
+                dynamics,
+            },
+            Operation::SetPixels { pixels } => Operation::SetPixels {
+                pixels: pixels.into_iter().map(|(px, py, color)| (px - x, py - y, color)).collect(),
+            },
+            op => op,
+        })
+    }
+}
+
+/// Sprite sheet frames a batch of drawing operations is repeated on
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct FrameSpan {
+    pub frame_width: u32,
+    pub frame_height: u32,
+    pub source: u32, // Frame the operations were drawn on
+    pub first: u32,
+    pub last: u32, // Inclusive
+}
+
+fn encode(buffer: &PixelBuffer) -> Result<String> {
+    Ok(STANDARD.encode(fileio::encode_png(buffer)?))
+}
+
+fn decode(image: &str) -> Result<PixelBuffer> {
+    let bytes = STANDARD
+        .decode(image)
+        .map_err(|_| AipixError::InvalidInput("Journal image is not valid base64".to_string()))?;
+    Ok(fileio::decode_png(&bytes)?)
+}
+
+/// Apply one operation the same way the originating command did
+pub fn apply(document: &mut Document, op: &Operation) -> Result<()> {
+    let history = &mut document.history;
+    match op {
+        Operation::PushState => history.push_state(),
+        Operation::Undo => {
+            document.undo()?;
+        }
+        Operation::Redo => {
+            document.redo()?;
+        }
+        Operation::Pencil { x, y, color } => {
+            engine::tools::pencil(&mut history.buffer, *x, *y, *color, &Brush::default())?
+        }
+        Operation::Eraser { x, y } => engine::tools::eraser(&mut history.buffer, *x, *y, &Brush::default())?,
+        Operation::Line { x0, y0, x1, y1, color, thickness, profile: LineProfile::Uniform } => {
+            engine::tools::line(&mut history.buffer, *x0, *y0, *x1, *y1, *color, *thickness)?
+        }
+        Operation::Line { x0, y0, x1, y1, color, thickness, profile } => {
+            engine::tools::profiled_line(&mut history.buffer, (*x0, *y0), (*x1, *y1), *color, *thickness, *profile)?
+        }
+        Operation::BrushStroke { x0, y0, x1, y1, color, brush } => {
+            let (x0, y0, x1, y1) = (*x0 as i32, *y0 as i32, *x1 as i32, *y1 as i32);
+            engine::tools::brush_line(&mut history.buffer, x0, y0, x1, y1, *color, brush)?
+        }
+        Operation::PencilStroke { points, color, brush, pressures: None, .. } => {
+            engine::tools::polyline(&mut history.buffer, points, *color, brush)?
+        }
+        Operation::PencilStroke { points, color, brush, pressures: Some(pressures), dynamics } => {
+            let dynamics = dynamics.clone().unwrap_or_default();
+            engine::tools::pressure_polyline(&mut history.buffer, points, pressures, *color, brush, &dynamics)?
+        }
+        Operation::DitherStroke { x0, y0, x1, y1, dither, brush } => {
+            let (start, end) = ((*x0 as i32, *y0 as i32), (*x1 as i32, *y1 as i32));
+            engine::tools::dither_line(&mut history.buffer, start, end, dither, brush)?
+        }
+        Operation::ShadeStroke { x0, y0, x1, y1, shade, brush, continued } => {
+            let (start, end) = ((*x0 as i32, *y0 as i32), (*x1 as i32, *y1 as i32));
+            engine::tools::shade_line(&mut history.buffer, start, end, shade, brush, *continued)?
+        }
+        Operation::BlurStroke { x0, y0, x1, y1, brush, strength } => {
+            let (start, end) = ((*x0 as i32, *y0 as i32), (*x1 as i32, *y1 as i32));
+            engine::filters::blur_line(&mut history.buffer, start, end, brush, *strength)?
+        }
+        Operation::JumbleStroke { x0, y0, x1, y1, jumble, brush } => {
+            let (start, end) = ((*x0 as i32, *y0 as i32), (*x1 as i32, *y1 as i32));
+            engine::tools::jumble_line(&mut history.buffer, start, end, jumble, brush)?
+        }
+        Operation::Rectangle { x0, y0, x1, y1, color, filled, thickness } => {
+            engine::tools::rectangle(&mut history.buffer, (*x0, *y0), (*x1, *y1), *color, *filled, *thickness)?
+        }
+        Operation::Circle { center_x, center_y, end_x, end_y, color, filled, thickness } => {
+            let (center, end) = ((*center_x, *center_y), (*end_x, *end_y));
+            engine::tools::circle(&mut history.buffer, center, end, *color, *filled, *thickness)?
+        }
+        Operation::Ellipse { x0, y0, x1, y1, color, filled, thickness } => {
+            let (start, end) = ((*x0, *y0), (*x1, *y1));
+            engine::tools::ellipse(&mut history.buffer, start, end, *color, *filled, *thickness)?
+        }
+        Operation::Fill { x, y, color, tolerance } => {
+            engine::tools::fill(&mut history.buffer, *x, *y, *color, *tolerance)?
+        }
+        Operation::ReplaceColor { target, replacement } => {
+            engine::tools::replace_all_color(&mut history.buffer, *target, *replacement)
+        }
+        Operation::SetPixels { pixels } => {
+            history.buffer.set_pixels(pixels)?;
+        }
+        Operation::Upscale { algorithm, factor } => {
+            history.buffer = engine::upscale::upscale(&history.buffer, *algorithm, *factor)?;
+            if let Some(selection) = document.selection.as_mut() {
+                *selection = engine::Selection::new(history.buffer.width, history.buffer.height);
+            }
+        }
+        Operation::Paste { x, y, image } => {
+            engine::tools::paste_buffer(&mut history.buffer, &decode(image)?, *x, *y)?
+        }
+        Operation::Patch { x, y, image } => history.buffer.blit(&decode(image)?, *x as i32, *y as i32),
+        Operation::Replace { image } => {
+            history.buffer = decode(image)?;
+            if let Some(selection) = document.selection.as_mut() {
+                *selection = engine::Selection::new(history.buffer.width, history.buffer.height);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Apply drawing operations to `document` as one undoable step
+///
+/// Works on a copy, so a failing operation leaves `document` untouched. With
+/// `frames` the operations, given on the source frame, are repeated on every
+/// frame of the span instead, each drawn on its own so strokes and fills stay
+/// inside their frame. Returns what to journal: the operations themselves
+/// where they replay the same, otherwise a patch of the pixels they changed.
+pub fn apply_batch(document: &mut Document, ops: Vec<Operation>, frames: Option<FrameSpan>) -> Result<Vec<Operation>> {
+    if !ops.iter().all(Operation::is_drawing) {
+        return Err(AipixError::InvalidInput("Only drawing operations can be batched".to_string()));
+    }
+
+    let mut edited = document.clone();
+    edited.history.push_state();
+    let mut changed = None;
+    match frames {
+        Some(span) => changed = Some(draw_on_frames(&mut edited.history.buffer, &ops, span)?),
+        None => {
+            for op in &ops {
+                apply(&mut edited, op)?;
+            }
+        }
+    }
+    let replayable = frames.is_none() && !edited.preserve_transparency;
+    if edited.preserve_transparency {
+        changed = engine::tools::preserve_transparency(&document.history.buffer, &mut edited.history.buffer);
+    }
+
+    let mut journaled = vec![Operation::PushState];
+    if replayable {
+        journaled.extend(ops);
+    } else if let Some(bounds) = changed {
+        journaled.push(Operation::patch(&edited.history.buffer, Some(bounds))?);
+    }
+    *document = edited;
+    Ok(journaled)
+}
+
+/// Draw `ops` on each frame of `span`; returns the bounds of the frames drawn on
+fn draw_on_frames(buffer: &mut PixelBuffer, ops: &[Operation], span: FrameSpan) -> Result<SelectionBounds> {
+    let layout = SheetLayout::new(buffer.width, buffer.height, span.frame_width, span.frame_height)?;
+    let frame_rect = |index: u32| layout.frame_rect(index).ok_or(AipixError::NotFound("Frame"));
+    if span.first > span.last {
+        return Err(AipixError::InvalidInput("The frame span is empty".to_string()));
+    }
+    frame_rect(span.last)?;
+
+    let source = frame_rect(span.source)?;
+    let local = ops.iter().map(|op| op.frame_local(source)).collect::<Result<Vec<_>>>()?;
+
+    let mut bounds: Option<SelectionBounds> = None;
+    for index in span.first..=span.last {
+        let (x, y, width, height) = frame_rect(index)?;
+        let mut frame = Document::new(width, height);
+        frame.history.buffer = buffer.copy_region(x, y, width, height);
+        for op in &local {
+            apply(&mut frame, op)?;
+        }
+        buffer.blit(&frame.history.buffer, x as i32, y as i32);
+
+        let frame = SelectionBounds { min_x: x, max_x: x + width - 1, min_y: y, max_y: y + height - 1 };
+        bounds = Some(bounds.map_or(frame, |bounds| bounds.union(frame)));
+    }
+    Ok(bounds.expect("the span holds at least one frame"))
+}
+
+#[derive(Serialize, Deserialize)]
+struct Checkpoint {
+    seq: u64, // Last journal entry folded into `image`
+    image: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    seq: u64,
+    #[serde(flatten)]
+    op: Operation,
+}
+
+/// Outcome of replaying a project's recovery data
+pub struct Recovered {
+    pub document: Document,
+    pub replayed: usize,
+    pub skipped: usize, // Entries that failed to apply (e.g. an undo past the checkpoint)
+}
+
+struct ProjectJournal {
+    file: File,
+    next_seq: u64,
+    since_checkpoint: u64,
+}
+
+/// Recovery files for every open project
+pub struct Journal {
+    dir: PathBuf,
+    projects: HashMap<String, ProjectJournal>,
+}
+
+impl Journal {
+    pub fn open(dir: PathBuf) -> Result<Self> {
+        std::fs::create_dir_all(&dir)?;
+        Ok(Self { dir, projects: HashMap::new() })
+    }
+
+    fn project_dir(&self, project_id: &str) -> Result<PathBuf> {
+        let valid = !project_id.is_empty()
+            && project_id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !valid {
+            return Err(AipixError::InvalidInput(format!(
+                "Invalid project id for recovery: {}",
+                project_id
+            )));
+        }
+        Ok(self.dir.join(project_id))
+    }
+
+    /// Append an operation; returns true when an autosave is due
+    ///
+    /// Journaling for a project starts at its first checkpoint, so this is a
+    /// no-op for projects that haven't been checkpointed this session.
+    pub fn append(&mut self, project_id: &str, op: Operation) -> Result<bool> {
+        let Some(journal) = self.projects.get_mut(project_id) else {
+            return Ok(false);
+        };
+
+        // A PushState is always followed by the edit it precedes, and the
+        // canvas already includes that edit, so never checkpoint between them
+        let splits_edit = matches!(op, Operation::PushState);
+
+        let entry = Entry { seq: journal.next_seq, op };
+        let mut line = serde_json::to_vec(&entry)?;
+        line.push(b'\n');
+        journal.file.write_all(&line)?;
+
+        journal.next_seq += 1;
+        journal.since_checkpoint += 1;
+        Ok(journal.since_checkpoint >= AUTOSAVE_INTERVAL && !splits_edit)
+    }
+
+    /// Autosave `buffer` and start a fresh journal for the project
+    pub fn checkpoint(&mut self, project_id: &str, buffer: &PixelBuffer) -> Result<()> {
+        let dir = self.project_dir(project_id)?;
+        std::fs::create_dir_all(&dir)?;
+
+        let next_seq = self.projects.get(project_id).map_or(1, |journal| journal.next_seq);
+        let checkpoint = Checkpoint { seq: next_seq - 1, image: encode(buffer)? };
+
+        // Write-then-rename so a crash leaves either the old or the new checkpoint
+        let temp = dir.join(format!("{}.tmp", CHECKPOINT_FILE));
+        std::fs::write(&temp, serde_json::to_vec(&checkpoint)?)?;
+        std::fs::rename(&temp, dir.join(CHECKPOINT_FILE))?;
+
+        // Entries up to `seq` are now redundant; replay skips them if truncation is lost
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(dir.join(JOURNAL_FILE))?;
+        file.set_len(0)?;
+
+        self.projects.insert(
+            project_id.to_string(),
+            ProjectJournal { file, next_seq, since_checkpoint: 0 },
+        );
+        Ok(())
+    }
+
+    /// Delete a project's recovery data (after a clean save or close)
+    pub fn discard(&mut self, project_id: &str) -> Result<()> {
+        let dir = self.project_dir(project_id)?;
+        self.projects.remove(project_id);
+        if dir.exists() {
+            std::fs::remove_dir_all(dir)?;
+        }
+        Ok(())
+    }
+
+    /// Projects with recovery data that isn't being written by this session
+    pub fn recoverable(&self) -> Result<Vec<String>> {
+        let mut projects = Vec::new();
+        for entry in std::fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            let project_id = entry.file_name().to_string_lossy().to_string();
+            if entry.path().join(CHECKPOINT_FILE).exists() && !self.projects.contains_key(&project_id) {
+                projects.push(project_id);
+            }
+        }
+        projects.sort();
+        Ok(projects)
+    }
+
+    /// Rebuild a document from its checkpoint and journal
+    pub fn recover(&self, project_id: &str) -> Result<Recovered> {
+        let dir = self.project_dir(project_id)?;
+        let checkpoint: Checkpoint = match std::fs::read(dir.join(CHECKPOINT_FILE)) {
+            Ok(bytes) => serde_json::from_slice(&bytes)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Err(AipixError::NotFound("Recovery data"))
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        let base = decode(&checkpoint.image)?;
+        let mut document = Document::new(base.width, base.height);
+        document.history.buffer = base;
+
+        let (mut replayed, mut skipped) = (0, 0);
+        for op in read_entries(&dir.join(JOURNAL_FILE), checkpoint.seq)? {
+            match apply(&mut document, &op) {
+                Ok(()) => replayed += 1,
+                Err(e) => {
+                    tracing::warn!(project_id, error = %e, "skipping journal entry");
+                    skipped += 1;
+                }
+            }
+        }
+
+        Ok(Recovered { document, replayed, skipped })
+    }
+}
+
+/// Operations after `after_seq`, stopping at the first torn or unreadable line
+fn read_entries(path: &Path, after_seq: u64) -> Result<Vec<Operation>> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut ops = Vec::new();
+    for line in BufReader::new(file).lines() {
+        let Ok(entry) = serde_json::from_str::<Entry>(&line?) else {
+            break; // The write in flight when the app died
+        };
+        if entry.seq > after_seq {
+            ops.push(entry.op);
+        }
+    }
+    Ok(ops)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: [u8; 4] = [255, 0, 0, 255];
+
+    #[test]
+    fn test_recover_replays_journal() {
+        let dir = std::env::temp_dir().join(format!("aipix-journal-{}", uuid::Uuid::new_v4()));
+        let mut journal = Journal::open(dir.clone()).unwrap();
+
+        let mut document = Document::new(8, 8);
+        journal.checkpoint("p1", &document.history.buffer).unwrap();
+
+        let ops = [
+            Operation::PushState,
+            Operation::Pencil { x: 1, y: 1, color: RED },
+            Operation::PushState,
+            Operation::Fill { x: 5, y: 5, color: RED, tolerance: 0 },
+            Operation::Undo,
+        ];
+        for op in ops {
+            apply(&mut document, &op).unwrap();
+            journal.append("p1", op).unwrap();
+        }
+
+        // A torn final line is ignored
+        let mut file = OpenOptions::new().append(true).open(dir.join("p1").join(JOURNAL_FILE)).unwrap();
+        file.write_all(b"{\"seq\":6,\"op\":\"pen").unwrap();
+
+        let reader = Journal::open(dir.clone()).unwrap();
+        assert_eq!(reader.recoverable().unwrap(), vec!["p1"]);
+
+        let recovered = reader.recover("p1").unwrap();
+        assert_eq!(recovered.replayed, 5);
+        assert_eq!(recovered.document.history.buffer, document.history.buffer);
+        assert!(recovered.document.history.can_undo());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_apply_batch_across_frames() {
+        let mut document = Document::new(12, 4);
+        let span = FrameSpan { frame_width: 4, frame_height: 4, source: 1, first: 1, last: 2 };
+        let ops = vec![
+            Operation::Pencil { x: 4, y: 0, color: RED },
+            Operation::pencil_line((5, 3), (7, 3), RED),
+            Operation::Fill { x: 6, y: 1, color: [0, 0, 255, 255], tolerance: 0 },
+        ];
+
+        let journaled = apply_batch(&mut document, ops.clone(), Some(span)).unwrap();
+        assert!(matches!(journaled[..], [Operation::PushState, Operation::Patch { x: 4, y: 0, .. }]));
+        for x in [4, 8] {
+            assert_eq!(document.history.buffer.get_pixel(x, 0), Some(RED));
+            assert_eq!(document.history.buffer.get_pixel(x + 3, 3), Some(RED));
+            assert_eq!(document.history.buffer.get_pixel(x + 2, 1), Some([0, 0, 255, 255]));
+        }
+        // The fill stays inside each frame
+        assert_eq!(document.history.buffer.get_pixel(0, 1), Some([0, 0, 0, 0]));
+        assert_eq!(document.history.undo_count(), 1);
+
+        // Operations outside the source frame are rejected without a change
+        let outside = vec![Operation::Pencil { x: 0, y: 0, color: RED }];
+        assert!(apply_batch(&mut document, outside, Some(span)).is_err());
+        assert!(apply_batch(&mut document, vec![Operation::Undo], None).is_err());
+        assert_eq!(document.history.undo_count(), 1);
+
+        // Without frames the operations are journaled as they are
+        let journaled = apply_batch(&mut document, ops, None).unwrap();
+        assert_eq!(journaled.len(), 4);
+    }
+
+    #[test]
+    fn test_pencil_stroke_pressures() {
+        let stroke = |pressures: Vec<f32>| Operation::PencilStroke {
+            points: vec![(1, 4), (8, 4)],
+            color: RED,
+            brush: Brush { size: 3, ..Brush::default() },
+            pressures: Some(pressures),
+            dynamics: None,
+        };
+
+        // Light at the start, full size at the end
+        let mut document = Document::new(10, 10);
+        apply_batch(&mut document, vec![stroke(vec![0.0, 1.0])], None).unwrap();
+        let buffer = &document.history.buffer;
+        assert_eq!((buffer.get_pixel(1, 3), buffer.get_pixel(1, 4)), (Some([0, 0, 0, 0]), Some(RED)));
+        assert_eq!(buffer.get_pixel(8, 3), Some(RED));
+
+        // One pressure per point
+        let mut document = Document::new(10, 10);
+        assert!(apply_batch(&mut document, vec![stroke(vec![1.0])], None).is_err());
+        assert_eq!(document.history.undo_count(), 0);
+    }
+
+    #[test]
+    fn test_checkpoint_folds_journal() {
+        let dir = std::env::temp_dir().join(format!("aipix-journal-{}", uuid::Uuid::new_v4()));
+        let mut journal = Journal::open(dir.clone()).unwrap();
+
+        let mut document = Document::new(4, 4);
+        journal.checkpoint("p1", &document.history.buffer).unwrap();
+
+        let op = Operation::Pencil { x: 0, y: 0, color: RED };
+        apply(&mut document, &op).unwrap();
+        journal.append("p1", op).unwrap();
+        journal.checkpoint("p1", &document.history.buffer).unwrap();
+
+        let recovered = journal.recover("p1").unwrap();
+        assert_eq!(recovered.replayed, 0);
+        assert_eq!(recovered.document.history.buffer.get_pixel(0, 0), Some(RED));
+
+        journal.discard("p1").unwrap();
+        assert!(journal.recoverable().unwrap().is_empty());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
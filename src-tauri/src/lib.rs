@@ -1,16 +1,211 @@
 // Library entry point for AIPIX backend
+pub mod error;
 pub mod database;
 pub mod engine;
 pub mod fileio;
+pub mod ai;
+#[cfg(feature = "app")]
 pub mod commands;  // Tauri commands
+pub mod profiling;
+pub mod logging;
+pub mod journal;
+pub mod plugins;
+#[cfg(feature = "app")]
+pub mod mcp;
+#[cfg(feature = "app")]
+pub mod http_api;
+#[cfg(feature = "app")]
+pub mod deep_link;
 
-use std::sync::Mutex;
-use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+// The app state is only needed by the Tauri app (see the "app" feature)
+#[cfg(feature = "app")]
+use {
+    error::{AipixError, Result},
+    std::collections::HashMap,
+    std::path::PathBuf,
+    std::sync::atomic::AtomicBool,
+    std::sync::RwLock,
+    std::time::{Duration, Instant},
+};
+
+/// An open document, locked independently of every other project
+pub type DocumentHandle = Arc<Mutex<engine::Document>>;
+
+/// An open tilemap, locked like a document
+pub type TilemapHandle = Arc<Mutex<engine::TilemapDocument>>;
 
 // Global database state
+#[cfg(feature = "app")]
 pub struct AppState {
     pub db: Mutex<Option<database::Database>>,
-    pub canvases: Mutex<HashMap<String, engine::CanvasHistory>>,
-    pub selections: Mutex<HashMap<String, engine::Selection>>,
-    pub clipboard: Mutex<Option<(engine::PixelBuffer, u32, u32)>>, // buffer, offset_x, offset_y
+    pub documents: RwLock<HashMap<String, DocumentHandle>>,
+    pub tilemaps: RwLock<HashMap<String, TilemapHandle>>,
+    pub clipboard: Mutex<commands::clipboard::Clipboard>,
+    pub brushes: Mutex<commands::brushes::BrushLibrary>, // Custom brushes captured this session
+    pub ai_provider: Mutex<Option<ai::AiProviderConfig>>,
+    pub canvas_limits: Mutex<engine::CanvasLimits>,
+    pub profiler: profiling::Profiler,
+    pub journal: Mutex<Option<journal::Journal>>, // None until the app data dir is known
+    pub plugins: Mutex<Option<plugins::PluginManager>>, // Likewise
+    pub mcp_server: Mutex<Option<mcp::ServerHandle>>, // Running WebSocket MCP server
+    pub mcp_export_dir: Mutex<Option<PathBuf>>, // The only directory MCP agents may export to; None forbids exports
+    pub http_api: Mutex<Option<http_api::ServerHandle>>, // Running local HTTP API
+    pub opened_files: Mutex<HashMap<PathBuf, String>>, // Canonical path -> project id, for files opened from the OS
+    pub pending_navigation: Mutex<Option<commands::events::Navigation>>, // Opened at launch, before the UI listened
+    pub jobs: Mutex<HashMap<String, Arc<AtomicBool>>>, // Waiting and running background jobs' cancel flags, by job id
+    pub document_access: Mutex<HashMap<String, Instant>>, // When each open document was last looked up
+    pub idle_unload: Mutex<Option<Duration>>, // Idle documents are saved and unloaded after this; None keeps them
+    pub thumbnails: Mutex<commands::thumbnails::ThumbnailCache>,
+    pub activity: Mutex<commands::activity::ActivityTracker>, // Editing time not yet saved
+    pub export_queue: Mutex<commands::export_queue::ExportQueue>, // Batch exports, waiting, running and finished
+}
+
+#[cfg(feature = "app")]
+impl Default for AppState {
+    fn default() -> Self {
+        Self {
+            db: Mutex::new(None),
+            documents: RwLock::new(HashMap::new()),
+            tilemaps: RwLock::new(HashMap::new()),
+            clipboard: Mutex::new(commands::clipboard::Clipboard::default()),
+            brushes: Mutex::new(commands::brushes::BrushLibrary::default()),
+            ai_provider: Mutex::new(None),
+            canvas_limits: Mutex::new(engine::CanvasLimits::default()),
+            profiler: profiling::Profiler::new(),
+            journal: Mutex::new(None),
+            plugins: Mutex::new(None),
+            mcp_server: Mutex::new(None),
+            mcp_export_dir: Mutex::new(None),
+            http_api: Mutex::new(None),
+            opened_files: Mutex::new(HashMap::new()),
+            pending_navigation: Mutex::new(None),
+            jobs: Mutex::new(HashMap::new()),
+            document_access: Mutex::new(HashMap::new()),
+            idle_unload: Mutex::new(None),
+            thumbnails: Mutex::new(commands::thumbnails::ThumbnailCache::default()),
+            activity: Mutex::new(commands::activity::ActivityTracker::default()),
+            export_queue: Mutex::new(commands::export_queue::ExportQueue::default()),
+        }
+    }
+}
+
+#[cfg(feature = "app")]
+impl AppState {
+    /// Look up an open document; the map itself is only locked for the lookup
+    ///
+    /// Counts as a use of the document, postponing its idle unload.
+    pub fn document(&self, project_id: &str) -> Result<DocumentHandle> {
+        let handle = self
+            .documents
+            .read()
+            .unwrap()
+            .get(project_id)
+            .cloned()
+            .ok_or(AipixError::NotFound("Canvas"))?;
+        self.document_access.lock().unwrap().insert(project_id.to_string(), Instant::now());
+        Ok(handle)
+    }
+
+    /// Look up an open tilemap
+    pub fn tilemap(&self, tilemap_id: &str) -> Result<TilemapHandle> {
+        self.tilemaps
+            .read()
+            .unwrap()
+            .get(tilemap_id)
+            .cloned()
+            .ok_or(AipixError::NotFound("Tilemap"))
+    }
+
+    /// A tool color for a project: hex, or `palette:<index>` for its palette entry
+    ///
+    /// Only a palette index locks the document, so call this before locking it.
+    pub fn tool_color(&self, project_id: &str, color: &str) -> Result<[u8; 4]> {
+        if !color.starts_with(engine::palette::INDEX_PREFIX) {
+            return engine::tools::hex_to_rgba(color);
+        }
+        self.document(project_id)?.lock().unwrap().history.palette.resolve(color)
+    }
+
+    /// What shape tools and selections snap to in a project: its drawing grid and guides
+    ///
+    /// Guides are read from the project metadata; without a database there are none.
+    pub fn snapping(&self, project_id: &str) -> Result<engine::Snapping> {
+        let grid = self.document(project_id)?.lock().unwrap().grid;
+
+        let metadata = match self.db.lock().unwrap().as_ref() {
+            Some(db) => db.get_project_metadata(project_id)?,
+            None => Default::default(),
+        };
+        Ok(engine::Snapping {
+            grid,
+            guides: metadata.guides,
+            guide_threshold: metadata.guide_snap_threshold,
+        })
+    }
+
+    /// Check a canvas size against the configured limits
+    pub fn validate_canvas_size(&self, width: u32, height: u32) -> Result<()> {
+        self.canvas_limits.lock().unwrap().validate(width, height)
+    }
+
+    /// Journal an operation just applied to `document`
+    pub fn record(&self, project_id: &str, document: &engine::Document, op: journal::Operation) {
+        self.record_with(project_id, document, || Ok(op));
+    }
+
+    /// Like `record`, for operations that are expensive to build (only built when journaling)
+    ///
+    /// Journaling is best-effort: a failure is logged but never fails the edit.
+    pub fn record_with(
+        &self,
+        project_id: &str,
+        document: &engine::Document,
+        op: impl FnOnce() -> Result<journal::Operation>,
+    ) {
+        let mut journal = self.journal.lock().unwrap();
+        let Some(journal) = journal.as_mut() else {
+            return;
+        };
+
+        let result = op().and_then(|op| journal.append(project_id, op)).and_then(|autosave| {
+            if autosave {
+                journal.checkpoint(project_id, &document.history.buffer)?;
+            }
+            Ok(())
+        });
+        if let Err(e) = result {
+            tracing::warn!(project_id, error = %e, "failed to journal operation");
+        }
+    }
+
+    /// Journal a tool run through `Document::paint`
+    ///
+    /// While transparency is preserved the tool's operation wouldn't replay
+    /// the same, so the changed pixels are recorded instead.
+    pub fn record_painted(
+        &self,
+        project_id: &str,
+        document: &engine::Document,
+        painted: engine::Painted,
+        op: impl FnOnce() -> Result<journal::Operation>,
+    ) {
+        match painted {
+            engine::Painted::Freely => self.record_with(project_id, document, op),
+            engine::Painted::Locked(Some(bounds)) => self.record_with(project_id, document, || {
+                journal::Operation::patch(&document.history.buffer, Some(bounds))
+            }),
+            engine::Painted::Locked(None) => {}
+        }
+    }
+
+    /// Autosave `document` and restart its journal
+    pub fn checkpoint(&self, project_id: &str, document: &engine::Document) {
+        if let Some(journal) = self.journal.lock().unwrap().as_mut() {
+            if let Err(e) = journal.checkpoint(project_id, &document.history.buffer) {
+                tracing::warn!(project_id, error = %e, "failed to autosave document");
+            }
+        }
+    }
 }
@@ -0,0 +1,155 @@
+// Logging setup
+//
+// Everything is logged through `tracing`. Events go to a daily rolling file
+// under `<app data>/logs` (and to stderr in debug builds); the level can be
+// changed at runtime and the end of the current file read back, so support
+// can ask for logs without users hunting for the directory.
+
+use crate::error::{AipixError, Result};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{fmt, reload, EnvFilter, Registry};
+
+/// Prefix of the rolling log files (`aipix.log.YYYY-MM-DD`)
+const LOG_FILE_PREFIX: &str = "aipix.log";
+
+/// Level used until `set_level` is called (`RUST_LOG` takes precedence)
+const DEFAULT_LEVEL: &str = "info";
+
+/// Most bytes read from the end of the log file by `tail`
+const MAX_TAIL_BYTES: u64 = 512 * 1024;
+
+struct Logging {
+    dir: PathBuf,
+    filter: reload::Handle<EnvFilter, Registry>,
+    level: Mutex<String>,
+    _guard: WorkerGuard, // Flushes the background writer on drop
+}
+
+// The subscriber is process-global, so its handles are too
+static LOGGING: OnceLock<Logging> = OnceLock::new();
+
+/// Install the global subscriber writing to `log_dir`; later calls are no-ops
+pub fn init(log_dir: &Path) -> Result<()> {
+    if LOGGING.get().is_some() {
+        return Ok(());
+    }
+    std::fs::create_dir_all(log_dir)?;
+
+    let level = std::env::var("RUST_LOG").unwrap_or_else(|_| DEFAULT_LEVEL.to_string());
+    let (filter, filter_handle) = reload::Layer::new(parse_filter(&level)?);
+
+    let appender = tracing_appender::rolling::daily(log_dir, LOG_FILE_PREFIX);
+    let (writer, guard) = tracing_appender::non_blocking(appender);
+
+    let stderr = cfg!(debug_assertions).then(|| fmt::layer().with_writer(std::io::stderr));
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(fmt::layer().with_writer(writer).with_ansi(false))
+        .with(stderr)
+        .try_init()
+        .map_err(|e| AipixError::Internal(format!("Failed to install logger: {}", e)))?;
+
+    let _ = LOGGING.set(Logging {
+        dir: log_dir.to_path_buf(),
+        filter: filter_handle,
+        level: Mutex::new(level),
+        _guard: guard,
+    });
+
+    tracing::info!(dir = %log_dir.display(), "logging initialized");
+    Ok(())
+}
+
+fn logging() -> Result<&'static Logging> {
+    LOGGING
+        .get()
+        .ok_or_else(|| AipixError::InvalidState("Logging is not initialized".to_string()))
+}
+
+fn parse_filter(level: &str) -> Result<EnvFilter> {
+    EnvFilter::try_new(level)
+        .map_err(|e| AipixError::InvalidInput(format!("Invalid log level '{}': {}", level, e)))
+}
+
+/// Change the active filter, e.g. `debug` or `info,aipix_lib::database=trace`
+pub fn set_level(level: &str) -> Result<()> {
+    let logging = logging()?;
+    logging
+        .filter
+        .reload(parse_filter(level)?)
+        .map_err(|e| AipixError::Internal(format!("Failed to change log level: {}", e)))?;
+
+    *logging.level.lock().unwrap() = level.to_string();
+    tracing::info!(level, "log level changed");
+    Ok(())
+}
+
+/// The filter currently in effect
+pub fn level() -> Result<String> {
+    Ok(logging()?.level.lock().unwrap().clone())
+}
+
+/// Last `lines` lines of the newest log file
+pub fn tail(lines: usize) -> Result<Vec<String>> {
+    match newest_log_file(&logging()?.dir)? {
+        Some(path) => read_tail(&path, lines),
+        None => Ok(Vec::new()),
+    }
+}
+
+fn newest_log_file(dir: &Path) -> Result<Option<PathBuf>> {
+    let mut newest: Option<(std::time::SystemTime, PathBuf)> = None;
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        if !entry.file_name().to_string_lossy().starts_with(LOG_FILE_PREFIX) {
+            continue;
+        }
+        let modified = entry.metadata()?.modified()?;
+        if newest.as_ref().is_none_or(|(time, _)| modified > *time) {
+            newest = Some((modified, entry.path()));
+        }
+    }
+    Ok(newest.map(|(_, path)| path))
+}
+
+fn read_tail(path: &Path, lines: usize) -> Result<Vec<String>> {
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len();
+    let start = len.saturating_sub(MAX_TAIL_BYTES);
+    file.seek(SeekFrom::Start(start))?;
+
+    let mut bytes = Vec::new();
+    file.read_to_end(&mut bytes)?;
+    let text = String::from_utf8_lossy(&bytes);
+
+    let mut all: Vec<&str> = text.lines().collect();
+    if start > 0 && !all.is_empty() {
+        all.remove(0); // Started mid-line
+    }
+
+    let skip = all.len().saturating_sub(lines);
+    Ok(all[skip..].iter().map(|line| line.to_string()).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_tail() {
+        let path = std::env::temp_dir().join(format!("aipix-log-{}", uuid::Uuid::new_v4()));
+        std::fs::write(&path, "one\ntwo\nthree\n").unwrap();
+
+        assert_eq!(read_tail(&path, 2).unwrap(), vec!["two", "three"]);
+        assert_eq!(read_tail(&path, 10).unwrap().len(), 3);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,10 +1,12 @@
 // Prevents additional console window on Windows in release builds
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-use aipix_lib::{database, engine, commands, AppState};
-use std::collections::HashMap;
-use std::sync::Mutex;
-use tauri::{Manager, State};
+use aipix_lib::error::{AipixError, Result};
+use aipix_lib::commands::events::{self, Changes};
+use aipix_lib::journal::{self, FrameSpan, Operation};
+use aipix_lib::{database, engine, commands, fileio, AppState};
+use std::sync::{Arc, Mutex};
+use tauri::{AppHandle, Manager, State};
 
 // Tauri commands
 #[tauri::command]
@@ -13,14 +15,13 @@ fn greet(name: &str) -> String {
 }
 
 #[tauri::command]
-fn init_database(app_handle: tauri::AppHandle, state: State<AppState>) -> Result<String, String> {
+fn init_database(app_handle: tauri::AppHandle, state: State<AppState>) -> Result<String> {
     let app_data_dir = app_handle.path().app_data_dir()
-        .map_err(|e| e.to_string())?;
+        .map_err(|e| AipixError::Internal(e.to_string()))?;
 
     let db_path = app_data_dir.join("aipix.db");
 
-    let db = database::Database::new(db_path)
-        .map_err(|e| format!("Failed to initialize database: {}", e))?;
+    let db = database::Database::new(db_path)?;
 
     *state.db.lock().unwrap() = Some(db);
 
@@ -31,168 +32,474 @@ fn init_database(app_handle: tauri::AppHandle, state: State<AppState>) -> Result
 fn create_project(
     state: State<AppState>,
     project: database::Project,
-) -> Result<(), String> {
+) -> Result<()> {
     let db_guard = state.db.lock().unwrap();
-    let db = db_guard.as_ref().ok_or("Database not initialized")?;
+    let db = db_guard.as_ref().ok_or(AipixError::DatabaseNotInitialized)?;
 
     db.create_project(&project)
-        .map_err(|e| format!("Failed to create project: {}", e))
 }
 
+/// A user's projects; with `archived`, only archived (true) or only active (false) ones
 #[tauri::command]
 fn get_user_projects(
     state: State<AppState>,
     user_id: String,
-) -> Result<Vec<database::Project>, String> {
+    archived: Option<bool>,
+) -> Result<Vec<database::Project>> {
     let db_guard = state.db.lock().unwrap();
-    let db = db_guard.as_ref().ok_or("Database not initialized")?;
+    let db = db_guard.as_ref().ok_or(AipixError::DatabaseNotInitialized)?;
 
-    db.get_projects_by_user(&user_id)
-        .map_err(|e| format!("Failed to get projects: {}", e))
+    db.get_projects_by_user(&user_id, archived)
 }
 
 #[tauri::command]
 fn update_project(
     state: State<AppState>,
     project: database::Project,
-) -> Result<(), String> {
+) -> Result<()> {
     let db_guard = state.db.lock().unwrap();
-    let db = db_guard.as_ref().ok_or("Database not initialized")?;
+    let db = db_guard.as_ref().ok_or(AipixError::DatabaseNotInitialized)?;
 
     db.update_project(&project)
-        .map_err(|e| format!("Failed to update project: {}", e))
 }
 
 #[tauri::command]
 fn delete_project(
     state: State<AppState>,
     project_id: String,
-) -> Result<(), String> {
+) -> Result<()> {
     let db_guard = state.db.lock().unwrap();
-    let db = db_guard.as_ref().ok_or("Database not initialized")?;
+    let db = db_guard.as_ref().ok_or(AipixError::DatabaseNotInitialized)?;
 
     db.delete_project(&project_id)
-        .map_err(|e| format!("Failed to delete project: {}", e))
+}
+
+/// Archive projects, hiding them from the active list; unlike deleting,
+/// they're kept and synced. Returns the projects as updated.
+#[tauri::command]
+fn archive_projects(state: State<AppState>, project_ids: Vec<String>) -> Result<Vec<database::Project>> {
+    set_projects_archived(&state, &project_ids, true)
+}
+
+/// Return archived projects to the active list; returns them as updated
+#[tauri::command]
+fn unarchive_projects(state: State<AppState>, project_ids: Vec<String>) -> Result<Vec<database::Project>> {
+    set_projects_archived(&state, &project_ids, false)
+}
+
+/// All projects are looked up first, so nothing changes when one is missing
+fn set_projects_archived(state: &AppState, project_ids: &[String], archived: bool) -> Result<Vec<database::Project>> {
+    let db_guard = state.db.lock().unwrap();
+    let db = db_guard.as_ref().ok_or(AipixError::DatabaseNotInitialized)?;
+
+    let projects = project_ids
+        .iter()
+        .map(|project_id| db.get_project(project_id)?.ok_or(AipixError::NotFound("Project")))
+        .collect::<Result<Vec<_>>>()?;
+    let now = chrono::Utc::now();
+    projects
+        .into_iter()
+        .map(|project| {
+            if project.archived == archived {
+                return Ok(project);
+            }
+            let project = database::Project { archived, updated_at: now, ..project };
+            db.update_project(&project)?;
+            Ok(project)
+        })
+        .collect()
 }
 
 #[tauri::command]
 fn create_folder(
     state: State<AppState>,
     folder: database::Folder,
-) -> Result<(), String> {
+) -> Result<()> {
     let db_guard = state.db.lock().unwrap();
-    let db = db_guard.as_ref().ok_or("Database not initialized")?;
+    let db = db_guard.as_ref().ok_or(AipixError::DatabaseNotInitialized)?;
 
     db.create_folder(&folder)
-        .map_err(|e| format!("Failed to create folder: {}", e))
 }
 
 #[tauri::command]
 fn get_user_folders(
     state: State<AppState>,
     user_id: String,
-) -> Result<Vec<database::Folder>, String> {
+) -> Result<Vec<database::Folder>> {
     let db_guard = state.db.lock().unwrap();
-    let db = db_guard.as_ref().ok_or("Database not initialized")?;
+    let db = db_guard.as_ref().ok_or(AipixError::DatabaseNotInitialized)?;
 
     db.get_folders_by_user(&user_id)
-        .map_err(|e| format!("Failed to get folders: {}", e))
 }
 
 #[tauri::command]
 fn update_folder(
     state: State<AppState>,
     folder: database::Folder,
-) -> Result<(), String> {
+) -> Result<()> {
     let db_guard = state.db.lock().unwrap();
-    let db = db_guard.as_ref().ok_or("Database not initialized")?;
+    let db = db_guard.as_ref().ok_or(AipixError::DatabaseNotInitialized)?;
 
     db.update_folder(&folder)
-        .map_err(|e| format!("Failed to update folder: {}", e))
 }
 
 #[tauri::command]
 fn delete_folder(
     state: State<AppState>,
     folder_id: String,
-) -> Result<(), String> {
+) -> Result<()> {
     let db_guard = state.db.lock().unwrap();
-    let db = db_guard.as_ref().ok_or("Database not initialized")?;
+    let db = db_guard.as_ref().ok_or(AipixError::DatabaseNotInitialized)?;
 
     db.delete_folder(&folder_id)
-        .map_err(|e| format!("Failed to delete folder: {}", e))
 }
 
 #[tauri::command]
 fn create_user(
     state: State<AppState>,
     user: database::User,
-) -> Result<(), String> {
+) -> Result<()> {
     let db_guard = state.db.lock().unwrap();
-    let db = db_guard.as_ref().ok_or("Database not initialized")?;
+    let db = db_guard.as_ref().ok_or(AipixError::DatabaseNotInitialized)?;
 
     db.create_user(&user)
-        .map_err(|e| format!("Failed to create user: {}", e))
 }
 
 #[tauri::command]
 fn get_user(
     state: State<AppState>,
     user_id: String,
-) -> Result<Option<database::User>, String> {
+) -> Result<Option<database::User>> {
     let db_guard = state.db.lock().unwrap();
-    let db = db_guard.as_ref().ok_or("Database not initialized")?;
+    let db = db_guard.as_ref().ok_or(AipixError::DatabaseNotInitialized)?;
 
     db.get_user(&user_id)
-        .map_err(|e| format!("Failed to get user: {}", e))
 }
 
 #[tauri::command]
 fn update_user(
     state: State<AppState>,
     user: database::User,
-) -> Result<(), String> {
+) -> Result<()> {
     let db_guard = state.db.lock().unwrap();
-    let db = db_guard.as_ref().ok_or("Database not initialized")?;
+    let db = db_guard.as_ref().ok_or(AipixError::DatabaseNotInitialized)?;
 
     db.update_user(&user)
-        .map_err(|e| format!("Failed to update user: {}", e))
+}
+
+#[tauri::command]
+fn create_comment(
+    state: State<AppState>,
+    comment: database::Comment,
+) -> Result<()> {
+    let db_guard = state.db.lock().unwrap();
+    let db = db_guard.as_ref().ok_or(AipixError::DatabaseNotInitialized)?;
+
+    db.create_comment(&comment)
+}
+
+#[tauri::command]
+fn get_project_comments(
+    state: State<AppState>,
+    project_id: String,
+) -> Result<Vec<database::Comment>> {
+    let db_guard = state.db.lock().unwrap();
+    let db = db_guard.as_ref().ok_or(AipixError::DatabaseNotInitialized)?;
+
+    db.get_project_comments(&project_id)
+}
+
+#[tauri::command]
+fn update_comment(
+    state: State<AppState>,
+    comment: database::Comment,
+) -> Result<()> {
+    let db_guard = state.db.lock().unwrap();
+    let db = db_guard.as_ref().ok_or(AipixError::DatabaseNotInitialized)?;
+
+    db.update_comment(&comment)
+}
+
+#[tauri::command]
+fn delete_comment(
+    state: State<AppState>,
+    comment_id: String,
+) -> Result<()> {
+    let db_guard = state.db.lock().unwrap();
+    let db = db_guard.as_ref().ok_or(AipixError::DatabaseNotInitialized)?;
+
+    db.delete_comment(&comment_id)
+}
+
+#[tauri::command]
+fn apply_remote_comment(
+    state: State<AppState>,
+    comment: database::Comment,
+) -> Result<database::SyncResolution> {
+    let db_guard = state.db.lock().unwrap();
+    let db = db_guard.as_ref().ok_or(AipixError::DatabaseNotInitialized)?;
+
+    db.apply_remote_comment(&comment)
+}
+
+#[tauri::command]
+fn heartbeat_presence(
+    state: State<AppState>,
+    project_id: String,
+    user_id: String,
+    username: String,
+) -> Result<database::PresenceEntry> {
+    let db_guard = state.db.lock().unwrap();
+    let db = db_guard.as_ref().ok_or(AipixError::DatabaseNotInitialized)?;
+
+    let entry = database::PresenceEntry {
+        project_id,
+        user_id,
+        username,
+        last_seen: chrono::Utc::now(),
+    };
+
+    db.record_presence(&entry)?;
+
+    // Returned so the frontend can broadcast it through Supabase
+    Ok(entry)
+}
+
+#[tauri::command]
+fn apply_remote_presence(
+    state: State<AppState>,
+    entry: database::PresenceEntry,
+) -> Result<()> {
+    let db_guard = state.db.lock().unwrap();
+    let db = db_guard.as_ref().ok_or(AipixError::DatabaseNotInitialized)?;
+
+    db.record_presence(&entry)
+}
+
+#[tauri::command]
+fn get_project_presence(
+    state: State<AppState>,
+    project_id: String,
+) -> Result<Vec<database::PresenceEntry>> {
+    let db_guard = state.db.lock().unwrap();
+    let db = db_guard.as_ref().ok_or(AipixError::DatabaseNotInitialized)?;
+
+    db.get_project_presence(&project_id)
+}
+
+#[tauri::command]
+fn acquire_edit_lock(
+    state: State<AppState>,
+    project_id: String,
+    user_id: String,
+    ttl_seconds: Option<i64>,
+) -> Result<database::EditLock> {
+    let db_guard = state.db.lock().unwrap();
+    let db = db_guard.as_ref().ok_or(AipixError::DatabaseNotInitialized)?;
+
+    let ttl = ttl_seconds.unwrap_or(database::sqlite::DEFAULT_EDIT_LOCK_SECS);
+    db.acquire_edit_lock(&project_id, &user_id, ttl)
+}
+
+#[tauri::command]
+fn release_edit_lock(
+    state: State<AppState>,
+    project_id: String,
+    user_id: String,
+) -> Result<()> {
+    let db_guard = state.db.lock().unwrap();
+    let db = db_guard.as_ref().ok_or(AipixError::DatabaseNotInitialized)?;
+
+    db.release_edit_lock(&project_id, &user_id)
+}
+
+#[tauri::command]
+fn get_edit_lock(
+    state: State<AppState>,
+    project_id: String,
+) -> Result<Option<database::EditLock>> {
+    let db_guard = state.db.lock().unwrap();
+    let db = db_guard.as_ref().ok_or(AipixError::DatabaseNotInitialized)?;
+
+    db.get_edit_lock(&project_id)
+}
+
+#[tauri::command]
+fn apply_remote_edit_lock(
+    state: State<AppState>,
+    project_id: String,
+    lock: Option<database::EditLock>,
+) -> Result<()> {
+    let db_guard = state.db.lock().unwrap();
+    let db = db_guard.as_ref().ok_or(AipixError::DatabaseNotInitialized)?;
+
+    db.apply_remote_edit_lock(&project_id, lock.as_ref())
 }
 
 #[tauri::command]
 fn get_unsynced_items(
     state: State<AppState>,
-) -> Result<Vec<(i64, String, String, String, String)>, String> {
+) -> Result<Vec<(i64, String, String, String, String)>> {
     let db_guard = state.db.lock().unwrap();
-    let db = db_guard.as_ref().ok_or("Database not initialized")?;
+    let db = db_guard.as_ref().ok_or(AipixError::DatabaseNotInitialized)?;
 
     db.get_unsynced_items()
-        .map_err(|e| format!("Failed to get unsynced items: {}", e))
 }
 
 #[tauri::command]
 fn mark_as_synced(
     state: State<AppState>,
     sync_id: i64,
-) -> Result<(), String> {
+) -> Result<()> {
     let db_guard = state.db.lock().unwrap();
-    let db = db_guard.as_ref().ok_or("Database not initialized")?;
+    let db = db_guard.as_ref().ok_or(AipixError::DatabaseNotInitialized)?;
 
     db.mark_as_synced(sync_id)
-        .map_err(|e| format!("Failed to mark as synced: {}", e))
+}
+
+#[tauri::command]
+fn mark_as_synced_with_revision(
+    state: State<AppState>,
+    sync_id: i64,
+    revision: i64,
+) -> Result<()> {
+    let db_guard = state.db.lock().unwrap();
+    let db = db_guard.as_ref().ok_or(AipixError::DatabaseNotInitialized)?;
+
+    db.mark_as_synced_with_revision(sync_id, revision)
+}
+
+#[tauri::command]
+fn apply_remote_project(
+    state: State<AppState>,
+    project: database::Project,
+) -> Result<database::SyncResolution> {
+    let db_guard = state.db.lock().unwrap();
+    let db = db_guard.as_ref().ok_or(AipixError::DatabaseNotInitialized)?;
+
+    db.apply_remote_project(&project)
+}
+
+#[tauri::command]
+fn apply_remote_folder(
+    state: State<AppState>,
+    folder: database::Folder,
+) -> Result<database::SyncResolution> {
+    let db_guard = state.db.lock().unwrap();
+    let db = db_guard.as_ref().ok_or(AipixError::DatabaseNotInitialized)?;
+
+    db.apply_remote_folder(&folder)
+}
+
+#[tauri::command]
+fn get_sync_items(
+    state: State<AppState>,
+    include_synced: bool,
+) -> Result<Vec<database::SyncItem>> {
+    let db_guard = state.db.lock().unwrap();
+    let db = db_guard.as_ref().ok_or(AipixError::DatabaseNotInitialized)?;
+
+    db.get_sync_items(include_synced)
+}
+
+#[tauri::command]
+fn get_sync_item_error(
+    state: State<AppState>,
+    sync_id: i64,
+) -> Result<Option<String>> {
+    let db_guard = state.db.lock().unwrap();
+    let db = db_guard.as_ref().ok_or(AipixError::DatabaseNotInitialized)?;
+
+    let item = db.get_sync_item(sync_id)?
+        .ok_or(AipixError::NotFound("Sync item"))?;
+
+    Ok(item.last_error)
+}
+
+#[tauri::command]
+fn mark_sync_failed(
+    state: State<AppState>,
+    sync_id: i64,
+    error: String,
+) -> Result<()> {
+    let db_guard = state.db.lock().unwrap();
+    let db = db_guard.as_ref().ok_or(AipixError::DatabaseNotInitialized)?;
+
+    db.mark_sync_failed(sync_id, &error)
+}
+
+#[tauri::command]
+fn retry_sync_item(
+    state: State<AppState>,
+    sync_id: i64,
+) -> Result<()> {
+    let db_guard = state.db.lock().unwrap();
+    let db = db_guard.as_ref().ok_or(AipixError::DatabaseNotInitialized)?;
+
+    db.retry_sync_item(sync_id)
+}
+
+#[tauri::command]
+fn skip_sync_item(
+    state: State<AppState>,
+    sync_id: i64,
+) -> Result<()> {
+    let db_guard = state.db.lock().unwrap();
+    let db = db_guard.as_ref().ok_or(AipixError::DatabaseNotInitialized)?;
+
+    db.skip_sync_item(sync_id)
+}
+
+#[tauri::command]
+fn purge_sync_item(
+    state: State<AppState>,
+    sync_id: i64,
+) -> Result<()> {
+    let db_guard = state.db.lock().unwrap();
+    let db = db_guard.as_ref().ok_or(AipixError::DatabaseNotInitialized)?;
+
+    db.purge_sync_item(sync_id)
+}
+
+#[tauri::command]
+fn purge_inactive_sync_items(
+    state: State<AppState>,
+) -> Result<usize> {
+    let db_guard = state.db.lock().unwrap();
+    let db = db_guard.as_ref().ok_or(AipixError::DatabaseNotInitialized)?;
+
+    db.purge_inactive_sync_items()
 }
 
 // Canvas drawing tool commands
 #[tauri::command]
 fn create_canvas(
+    app: AppHandle,
     state: State<AppState>,
     project_id: String,
     width: u32,
     height: u32,
-) -> Result<(), String> {
-    let mut canvases = state.canvases.lock().unwrap();
-    let history = engine::CanvasHistory::new(width, height);
-    canvases.insert(project_id, history);
+) -> Result<()> {
+    state.validate_canvas_size(width, height)?;
+    tracing::info!(%project_id, width, height, "creating canvas");
+
+    let document = state
+        .documents
+        .write()
+        .unwrap()
+        .entry(project_id.clone())
+        .or_insert_with(|| Arc::new(Mutex::new(engine::Document::new(width, height))))
+        .clone();
+
+    // Reopening keeps the palette and grid; the selection is re-created by the caller
+    let mut document = document.lock().unwrap();
+    let palette = std::mem::take(&mut document.history.palette);
+    document.history = engine::CanvasHistory::new(width, height);
+    document.history.palette = palette;
+    document.selection = None;
+    state.checkpoint(&project_id, &document);
+
+    events::emit_changes(&app, &project_id, &document, Changes::ALL);
     Ok(())
 }
 
@@ -200,48 +507,414 @@ fn create_canvas(
 fn get_canvas_data(
     state: State<AppState>,
     project_id: String,
-) -> Result<Vec<u8>, String> {
-    let canvases = state.canvases.lock().unwrap();
-    let history = canvases
-        .get(&project_id)
-        .ok_or("Canvas not found")?;
-    Ok(history.buffer.data.clone())
+) -> Result<Vec<u8>> {
+    let document = state.document(&project_id)?;
+    let document = document.lock().unwrap();
+    let history = &document.history;
+    Ok(history.buffer.to_rgba())
+}
+
+/// RGBA bytes of part of the canvas, or a PNG of it with `png`
+///
+/// The rectangle must lie within the canvas.
+#[tauri::command]
+fn get_canvas_region(
+    state: State<AppState>,
+    project_id: String,
+    rect: engine::Rect,
+    png: Option<bool>,
+) -> Result<Vec<u8>> {
+    let document = state.document(&project_id)?;
+    let document = document.lock().unwrap();
+    let buffer = &document.history.buffer;
+
+    let inside = |start: i32, size: i32, limit: u32| {
+        start >= 0 && size > 0 && (start as i64 + size as i64) <= limit as i64
+    };
+    if !inside(rect.x, rect.width, buffer.width) || !inside(rect.y, rect.height, buffer.height) {
+        return Err(AipixError::OutOfBounds);
+    }
+
+    let region = buffer.copy_region(rect.x as u32, rect.y as u32, rect.width as u32, rect.height as u32);
+    if png.unwrap_or(false) {
+        Ok(fileio::encode_png(&region)?)
+    } else {
+        Ok(region.to_rgba())
+    }
 }
 
+/// Draw one pencil point; with `connect` it continues the stroke from the
+/// previous pencil or eraser point without leaving gaps
+///
+/// The point is a `size` pixel (1-64, default 1) square, circle or diagonal,
+/// or the custom brush `brush_id` (see commands::brushes).
+///
+/// As for every drawing tool, `color` is hex (`#rrggbb`, or `#rrggbbaa`
+/// with alpha) or `palette:<index>`, the palette entry as it is when the
+/// point is drawn. With a `blend` mode the color is composited onto the
+/// canvas instead of replacing it; a translucent color is composited
+/// normally when no mode is given.
 #[tauri::command]
 fn draw_pencil(
+    app: AppHandle,
     state: State<AppState>,
     project_id: String,
     x: u32,
     y: u32,
     color: String,
-) -> Result<(), String> {
-    let mut canvases = state.canvases.lock().unwrap();
-    let history = canvases
-        .get_mut(&project_id)
-        .ok_or("Canvas not found")?;
+    connect: Option<bool>,
+    size: Option<u32>,
+    shape: Option<engine::BrushShape>,
+    brush_id: Option<u64>,
+    blend: Option<engine::BlendMode>,
+) -> Result<()> {
+    let rgba = state.tool_color(&project_id, &color)?;
+    let blend = engine::BlendMode::for_colors(blend, &[rgba]);
+    if let Some(brush_id) = brush_id {
+        let stamp = commands::brushes::Stamp { brush_id, at: (x, y), connect: connect.unwrap_or(false) };
+        return commands::brushes::draw(&app, &state, &project_id, stamp, rgba, blend);
+    }
+    let brush = engine::Brush { size: size.unwrap_or(1), shape: shape.unwrap_or_default() };
+    brush.validate()?;
+    let document = state.document(&project_id)?;
+    let mut document = document.lock().unwrap();
+
+    let from = document.history.stroke_end.filter(|_| connect.unwrap_or(false));
+    let (from, painted) =
+        document.draw_blended(blend, |buffer| engine::tools::pencil_stroke(buffer, from, x, y, rgba, &brush))?;
+    document.history.stroke_end = Some((x, y));
+
+    let op = match from {
+        _ if brush.size > 1 => {
+            let (x0, y0) = from.unwrap_or((x, y));
+            Operation::BrushStroke { x0, y0, x1: x, y1: y, color: rgba, brush }
+        }
+        Some(from) => Operation::pencil_line(from, (x, y), rgba),
+        None => Operation::Pencil { x, y, color: rgba },
+    };
+    state.record_painted(&project_id, &document, painted, || Ok(op));
+
+    events::emit_changes(&app, &project_id, &document, Changes::PIXELS);
+    Ok(())
+}
 
-    let rgba = engine::tools::hex_to_rgba(&color)?;
-    engine::tools::pencil(&mut history.buffer, x, y, rgba)
+/// Draw a freehand pencil stroke through `points` in one call
+///
+/// Points are joined with Bresenham lines and may lie off the canvas.
+/// `connect`, `size`, `shape` and `blend` work as for draw_pencil; the last
+/// point is where a later connected point continues from.
+#[tauri::command]
+fn draw_pencil_stroke(
+    app: AppHandle,
+    state: State<AppState>,
+    project_id: String,
+    points: Vec<(i32, i32)>,
+    color: String,
+    connect: Option<bool>,
+    size: Option<u32>,
+    shape: Option<engine::BrushShape>,
+    blend: Option<engine::BlendMode>,
+) -> Result<()> {
+    let rgba = state.tool_color(&project_id, &color)?;
+    let blend = engine::BlendMode::for_colors(blend, &[rgba]);
+    let brush = engine::Brush { size: size.unwrap_or(1), shape: shape.unwrap_or_default() };
+    brush.validate()?;
+    let Some(&(last_x, last_y)) = points.last() else {
+        return Err(AipixError::InvalidInput("A stroke needs at least one point".to_string()));
+    };
+    let document = state.document(&project_id)?;
+    let mut document = document.lock().unwrap();
+
+    let from = document.history.stroke_end.filter(|_| connect.unwrap_or(false));
+    let points: Vec<(i32, i32)> = from.map(|(x, y)| (x as i32, y as i32)).into_iter().chain(points).collect();
+    let ((), painted) = document.draw_blended(blend, |buffer| engine::tools::polyline(buffer, &points, rgba, &brush))?;
+    document.history.stroke_end = u32::try_from(last_x).ok().zip(u32::try_from(last_y).ok());
+
+    state.record_painted(&project_id, &document, painted, || {
+        Ok(Operation::PencilStroke { points, color: rgba, brush, pressures: None, dynamics: None })
+    });
+
+    events::emit_changes(&app, &project_id, &document, Changes::PIXELS);
+    Ok(())
 }
 
+/// Erase one point; `connect`, `size`, `shape` and `brush_id` work as for draw_pencil
 #[tauri::command]
 fn draw_eraser(
+    app: AppHandle,
     state: State<AppState>,
     project_id: String,
     x: u32,
     y: u32,
-) -> Result<(), String> {
-    let mut canvases = state.canvases.lock().unwrap();
-    let history = canvases
-        .get_mut(&project_id)
-        .ok_or("Canvas not found")?;
+    connect: Option<bool>,
+    size: Option<u32>,
+    shape: Option<engine::BrushShape>,
+    brush_id: Option<u64>,
+) -> Result<()> {
+    if let Some(brush_id) = brush_id {
+        let stamp = commands::brushes::Stamp { brush_id, at: (x, y), connect: connect.unwrap_or(false) };
+        return commands::brushes::draw(&app, &state, &project_id, stamp, [0, 0, 0, 0], None);
+    }
+    let brush = engine::Brush { size: size.unwrap_or(1), shape: shape.unwrap_or_default() };
+    brush.validate()?;
+    let document = state.document(&project_id)?;
+    let mut document = document.lock().unwrap();
+
+    let from = document.history.stroke_end.filter(|_| connect.unwrap_or(false));
+    let (from, painted) =
+        document.draw(|buffer| engine::tools::pencil_stroke(buffer, from, x, y, [0, 0, 0, 0], &brush))?;
+    document.history.stroke_end = Some((x, y));
+
+    let op = match from {
+        _ if brush.size > 1 => {
+            let (x0, y0) = from.unwrap_or((x, y));
+            Operation::BrushStroke { x0, y0, x1: x, y1: y, color: [0, 0, 0, 0], brush }
+        }
+        Some(from) => Operation::pencil_line(from, (x, y), [0, 0, 0, 0]),
+        None => Operation::Eraser { x, y },
+    };
+    state.record_painted(&project_id, &document, painted, || Ok(op));
+
+    events::emit_changes(&app, &project_id, &document, Changes::PIXELS);
+    Ok(())
+}
 
-    engine::tools::eraser(&mut history.buffer, x, y)
+/// Paint one point of a dither brush stroke, a staple of pixel-art shading
+///
+/// Pixels take `secondary_color` where the `pattern` (default Bayer 2x2)
+/// is below `density` (default 0.5, a checkerboard) and `color` elsewhere.
+/// `connect`, `size` and `shape` work as for draw_pencil.
+#[tauri::command]
+fn draw_dither(
+    app: AppHandle,
+    state: State<AppState>,
+    project_id: String,
+    x: u32,
+    y: u32,
+    color: String,
+    secondary_color: String,
+    pattern: Option<engine::DitherPattern>,
+    density: Option<f32>,
+    connect: Option<bool>,
+    size: Option<u32>,
+    shape: Option<engine::BrushShape>,
+) -> Result<()> {
+    let brush = engine::Brush { size: size.unwrap_or(1), shape: shape.unwrap_or_default() };
+    brush.validate()?;
+    let dither = engine::Dither {
+        primary: state.tool_color(&project_id, &color)?,
+        secondary: state.tool_color(&project_id, &secondary_color)?,
+        pattern: pattern.unwrap_or(engine::DitherPattern::Bayer2),
+        density: density.unwrap_or(0.5),
+    };
+    dither.validate()?;
+    let document = state.document(&project_id)?;
+    let mut document = document.lock().unwrap();
+
+    let from = document.history.stroke_end.filter(|_| connect.unwrap_or(false));
+    let blend = engine::BlendMode::for_colors(None, &[dither.primary, dither.secondary]);
+    let ((x0, y0), painted) = document.draw_blended(blend, |buffer| {
+        // As for the pencil, a point left off the canvas (e.g. after a resize) starts a new stroke
+        let (x0, y0) = from.filter(|&(x0, y0)| buffer.get_pixel(x0, y0).is_some()).unwrap_or((x, y));
+        engine::tools::dither_line(buffer, (x0 as i32, y0 as i32), (x as i32, y as i32), &dither, &brush)?;
+        Ok((x0, y0))
+    })?;
+    document.history.stroke_end = Some((x, y));
+
+    state.record_painted(&project_id, &document, painted, || {
+        Ok(Operation::DitherStroke { x0, y0, x1: x, y1: y, dither, brush })
+    });
+
+    events::emit_changes(&app, &project_id, &document, Changes::PIXELS);
+    Ok(())
+}
+
+/// Paint one point of a shading stroke, lightening or darkening the pixels under the brush
+///
+/// Each pixel moves by `strength` in lightness (default 0.1) and turns by up
+/// to `hue_shift` degrees (default 0) toward yellow when lightening or blue
+/// when darkening. A stroke shades each pixel once. `connect`, `size` and
+/// `shape` work as for draw_pencil.
+#[tauri::command]
+fn draw_shade(
+    app: AppHandle,
+    state: State<AppState>,
+    project_id: String,
+    x: u32,
+    y: u32,
+    direction: engine::ShadeDirection,
+    strength: Option<f32>,
+    hue_shift: Option<f32>,
+    connect: Option<bool>,
+    size: Option<u32>,
+    shape: Option<engine::BrushShape>,
+) -> Result<()> {
+    let brush = engine::Brush { size: size.unwrap_or(1), shape: shape.unwrap_or_default() };
+    brush.validate()?;
+    let shade = engine::Shade { direction, strength: strength.unwrap_or(0.1), hue_shift: hue_shift.unwrap_or(0.0) };
+    shade.validate()?;
+    let document = state.document(&project_id)?;
+    let mut document = document.lock().unwrap();
+
+    let from = document.history.stroke_end.filter(|_| connect.unwrap_or(false));
+    let ((x0, y0, continued), painted) = document.draw(|buffer| {
+        // As for the pencil, a point left off the canvas (e.g. after a resize) starts a new stroke
+        let from = from.filter(|&(x0, y0)| buffer.get_pixel(x0, y0).is_some());
+        let (x0, y0) = from.unwrap_or((x, y));
+        let start = (x0 as i32, y0 as i32);
+        engine::tools::shade_line(buffer, start, (x as i32, y as i32), &shade, &brush, from.is_some())?;
+        Ok((x0, y0, from.is_some()))
+    })?;
+    document.history.stroke_end = Some((x, y));
+
+    state.record_painted(&project_id, &document, painted, || {
+        Ok(Operation::ShadeStroke { x0, y0, x1: x, y1: y, shade, brush, continued })
+    });
+
+    events::emit_changes(&app, &project_id, &document, Changes::PIXELS);
+    Ok(())
+}
+
+/// Paint one point of a blur stroke, softening the pixels under the brush
+///
+/// Each pixel moves `strength` (default 0.5) of the way to the average of
+/// its neighbours. `connect`, `size` and `shape` work as for draw_pencil.
+#[tauri::command]
+fn draw_blur(
+    app: AppHandle,
+    state: State<AppState>,
+    project_id: String,
+    x: u32,
+    y: u32,
+    strength: Option<f32>,
+    connect: Option<bool>,
+    size: Option<u32>,
+    shape: Option<engine::BrushShape>,
+) -> Result<()> {
+    let brush = engine::Brush { size: size.unwrap_or(1), shape: shape.unwrap_or_default() };
+    brush.validate()?;
+    let strength = strength.unwrap_or(0.5);
+    let document = state.document(&project_id)?;
+    let mut document = document.lock().unwrap();
+
+    let from = document.history.stroke_end.filter(|_| connect.unwrap_or(false));
+    let ((x0, y0), painted) = document.draw(|buffer| {
+        // As for the pencil, a point left off the canvas (e.g. after a resize) starts a new stroke
+        let (x0, y0) = from.filter(|&(x0, y0)| buffer.get_pixel(x0, y0).is_some()).unwrap_or((x, y));
+        engine::filters::blur_line(buffer, (x0 as i32, y0 as i32), (x as i32, y as i32), &brush, strength)?;
+        Ok((x0, y0))
+    })?;
+    document.history.stroke_end = Some((x, y));
+
+    state.record_painted(&project_id, &document, painted, || {
+        Ok(Operation::BlurStroke { x0, y0, x1: x, y1: y, brush, strength })
+    });
+
+    events::emit_changes(&app, &project_id, &document, Changes::PIXELS);
+    Ok(())
+}
+
+/// Paint one point of a jumble stroke, swapping pixels under the brush with
+/// others up to `radius` (1-8, default 1) away for textured noise
+///
+/// Each pixel is swapped with a chance of `intensity` (default 0.5). The
+/// same `seed` jumbles the same way; a random one is used when omitted.
+/// `connect`, `size` and `shape` work as for draw_pencil.
+#[tauri::command]
+fn draw_jumble(
+    app: AppHandle,
+    state: State<AppState>,
+    project_id: String,
+    x: u32,
+    y: u32,
+    radius: Option<u32>,
+    intensity: Option<f32>,
+    seed: Option<u64>,
+    connect: Option<bool>,
+    size: Option<u32>,
+    shape: Option<engine::BrushShape>,
+) -> Result<()> {
+    let brush = engine::Brush { size: size.unwrap_or(1), shape: shape.unwrap_or_default() };
+    brush.validate()?;
+    let jumble = engine::Jumble {
+        radius: radius.unwrap_or(1),
+        intensity: intensity.unwrap_or(0.5),
+        seed: seed.unwrap_or_else(|| uuid::Uuid::new_v4().as_u64_pair().0),
+    };
+    jumble.validate()?;
+    let document = state.document(&project_id)?;
+    let mut document = document.lock().unwrap();
+
+    let from = document.history.stroke_end.filter(|_| connect.unwrap_or(false));
+    let ((x0, y0), painted) = document.draw(|buffer| {
+        // As for the pencil, a point left off the canvas (e.g. after a resize) starts a new stroke
+        let (x0, y0) = from.filter(|&(x0, y0)| buffer.get_pixel(x0, y0).is_some()).unwrap_or((x, y));
+        engine::tools::jumble_line(buffer, (x0 as i32, y0 as i32), (x as i32, y as i32), &jumble, &brush)?;
+        Ok((x0, y0))
+    })?;
+    document.history.stroke_end = Some((x, y));
+
+    state.record_painted(&project_id, &document, painted, || {
+        Ok(Operation::JumbleStroke { x0, y0, x1: x, y1: y, jumble, brush })
+    });
+
+    events::emit_changes(&app, &project_id, &document, Changes::PIXELS);
+    Ok(())
+}
+
+/// Shift-click line continuation: a line from the last pencil or eraser point
+/// to (x, y), which becomes the new last point
+///
+/// The last point is undone with the canvas, so after an undo the line
+/// continues from the end of the stroke that is left. Without one (e.g. on a
+/// fresh canvas) only (x, y) is drawn.
+#[tauri::command]
+fn draw_line_from_last(
+    app: AppHandle,
+    state: State<AppState>,
+    project_id: String,
+    x: u32,
+    y: u32,
+    color: String,
+    save_history: bool,
+) -> Result<()> {
+    let rgba = state.tool_color(&project_id, &color)?;
+    let document = state.document(&project_id)?;
+    let mut document = document.lock().unwrap();
+
+    // Save state before drawing (for undo)
+    if save_history {
+        document.history.push_state();
+    }
+
+    let from = document.history.stroke_end;
+    let blend = engine::BlendMode::for_colors(None, &[rgba]);
+    let (from, painted) = document.draw_blended(blend, |buffer| {
+        engine::tools::pencil_stroke(buffer, from, x, y, rgba, &engine::Brush::default())
+    })?;
+    document.history.stroke_end = Some((x, y));
+
+    if save_history {
+        state.record(&project_id, &document, Operation::PushState);
+    }
+    let op = match from {
+        Some(from) => Operation::pencil_line(from, (x, y), rgba),
+        None => Operation::Pencil { x, y, color: rgba },
+    };
+    state.record_painted(&project_id, &document, painted, || Ok(op));
+
+    let changes = if save_history { Changes::EDIT } else { Changes::PIXELS };
+    events::emit_changes(&app, &project_id, &document, changes);
+    Ok(())
 }
 
+/// Draw a line `thickness` (1-16, default 1) pixels wide between two points
+///
+/// A `tapered` profile narrows to one pixel at both ends, for quick sketching.
+/// `blend` works as for draw_pencil.
 #[tauri::command]
 fn draw_line(
+    app: AppHandle,
     state: State<AppState>,
     project_id: String,
     x0: i32,
@@ -250,23 +923,59 @@ fn draw_line(
     y1: i32,
     color: String,
     save_history: bool,
-) -> Result<(), String> {
-    let mut canvases = state.canvases.lock().unwrap();
-    let history = canvases
-        .get_mut(&project_id)
-        .ok_or("Canvas not found")?;
+    thickness: Option<u32>,
+    profile: Option<engine::LineProfile>,
+    blend: Option<engine::BlendMode>,
+) -> Result<()> {
+    let thickness = shape_thickness(thickness)?;
+    let profile = profile.unwrap_or_default();
+    let snapping = state.snapping(&project_id)?;
+    let ((x0, y0), (x1, y1)) = (snapping.point(x0, y0), snapping.point(x1, y1));
+
+    let document = state.document(&project_id)?;
+    let mut document = document.lock().unwrap();
+    let history = &mut document.history;
 
     // Save state before drawing (for undo)
     if save_history {
         history.push_state();
     }
 
-    let rgba = engine::tools::hex_to_rgba(&color)?;
-    engine::tools::line(&mut history.buffer, x0, y0, x1, y1, rgba)
+    let rgba = document.history.palette.resolve(&color)?;
+    let blend = engine::BlendMode::for_colors(blend, &[rgba]);
+    let ((), painted) = document.draw_blended(blend, |buffer| match profile {
+        engine::LineProfile::Uniform => engine::tools::line(buffer, x0, y0, x1, y1, rgba, thickness),
+        profile => engine::tools::profiled_line(buffer, (x0, y0), (x1, y1), rgba, thickness, profile),
+    })?;
+
+    if save_history {
+        state.record(&project_id, &document, Operation::PushState);
+    }
+    state.record_painted(&project_id, &document, painted, || {
+        Ok(Operation::Line { x0, y0, x1, y1, color: rgba, thickness, profile })
+    });
+
+    let changes = if save_history { Changes::EDIT } else { Changes::PIXELS };
+    events::emit_changes(&app, &project_id, &document, changes);
+    Ok(())
 }
 
+/// Width of a shape outline, checked against the engine's limit
+fn shape_thickness(thickness: Option<u32>) -> Result<u32> {
+    let thickness = thickness.unwrap_or(1);
+    engine::tools::validate_thickness(thickness)?;
+    Ok(thickness)
+}
+
+/// Draw a rectangle between two corners
+///
+/// With `from_center`, (x0, y0) is the center and (x1, y1) a corner;
+/// `constrain_aspect` makes it a square as long as the drag's longer side.
+/// Outlines are `thickness` (1-16, default 1) pixels wide; `blend` works as
+/// for draw_pencil.
 #[tauri::command]
 fn draw_rectangle(
+    app: AppHandle,
     state: State<AppState>,
     project_id: String,
     x0: u32,
@@ -276,23 +985,61 @@ fn draw_rectangle(
     color: String,
     filled: bool,
     save_history: bool,
-) -> Result<(), String> {
-    let mut canvases = state.canvases.lock().unwrap();
-    let history = canvases
-        .get_mut(&project_id)
-        .ok_or("Canvas not found")?;
+    from_center: Option<bool>,
+    constrain_aspect: Option<bool>,
+    thickness: Option<u32>,
+    blend: Option<engine::BlendMode>,
+) -> Result<()> {
+    let thickness = shape_thickness(thickness)?;
+    let snapping = state.snapping(&project_id)?;
+    let (x0, y0, x1, y1) = match (from_center.unwrap_or(false), constrain_aspect.unwrap_or(false)) {
+        (false, false) => snapping.rect(x0, y0, x1, y1),
+        (from_center, constrain_aspect) => {
+            let to_i32 = |v: u32| v.min(i32::MAX as u32) as i32;
+            let (start, end) = (snapping.point(to_i32(x0), to_i32(y0)), snapping.point(to_i32(x1), to_i32(y1)));
+            let (min_x, min_y, max_x, max_y) = engine::tools::shape_box(start, end, from_center, constrain_aspect);
+            if min_x < 0 || min_y < 0 {
+                return Err(AipixError::OutOfBounds);
+            }
+            (min_x as u32, min_y as u32, max_x as u32, max_y as u32)
+        }
+    };
+
+    let document = state.document(&project_id)?;
+    let mut document = document.lock().unwrap();
+    let history = &mut document.history;
 
     // Save state before drawing (for undo)
     if save_history {
         history.push_state();
     }
 
-    let rgba = engine::tools::hex_to_rgba(&color)?;
-    engine::tools::rectangle(&mut history.buffer, x0, y0, x1, y1, rgba, filled)
+    let rgba = document.history.palette.resolve(&color)?;
+    let blend = engine::BlendMode::for_colors(blend, &[rgba]);
+    let ((), painted) = document.draw_blended(blend, |buffer| {
+        engine::tools::rectangle(buffer, (x0, y0), (x1, y1), rgba, filled, thickness)
+    })?;
+
+    if save_history {
+        state.record(&project_id, &document, Operation::PushState);
+    }
+    state.record_painted(&project_id, &document, painted, || {
+        Ok(Operation::Rectangle { x0, y0, x1, y1, color: rgba, filled, thickness })
+    });
+
+    let changes = if save_history { Changes::EDIT } else { Changes::PIXELS };
+    events::emit_changes(&app, &project_id, &document, changes);
+    Ok(())
 }
 
+/// Draw a circle around (center_x, center_y) reaching (end_x, end_y)
+///
+/// With `from_center` false the points are instead opposite corners of the
+/// circle's bounding square, as for draw_ellipse with `constrain_aspect`.
+/// `thickness` and `blend` work as for draw_rectangle.
 #[tauri::command]
 fn draw_circle(
+    app: AppHandle,
     state: State<AppState>,
     project_id: String,
     center_x: i32,
@@ -302,129 +1049,676 @@ fn draw_circle(
     color: String,
     filled: bool,
     save_history: bool,
-) -> Result<(), String> {
-    let mut canvases = state.canvases.lock().unwrap();
-    let history = canvases
-        .get_mut(&project_id)
-        .ok_or("Canvas not found")?;
+    from_center: Option<bool>,
+    thickness: Option<u32>,
+    blend: Option<engine::BlendMode>,
+) -> Result<()> {
+    let thickness = shape_thickness(thickness)?;
+    if !from_center.unwrap_or(true) {
+        let (start, end) = ((center_x, center_y), (end_x, end_y));
+        let options = EllipseOptions { from_center: false, constrain_aspect: true, thickness, blend };
+        return paint_ellipse(app, state, project_id, start, end, color, filled, save_history, options);
+    }
+
+    let snapping = state.snapping(&project_id)?;
+    let ((center_x, center_y), (end_x, end_y)) =
+        (snapping.point(center_x, center_y), snapping.point(end_x, end_y));
+
+    let document = state.document(&project_id)?;
+    let mut document = document.lock().unwrap();
+    let history = &mut document.history;
 
     // Save state before drawing (for undo)
     if save_history {
         history.push_state();
     }
 
-    let rgba = engine::tools::hex_to_rgba(&color)?;
-    engine::tools::circle(&mut history.buffer, center_x, center_y, end_x, end_y, rgba, filled)
+    let rgba = document.history.palette.resolve(&color)?;
+    let blend = engine::BlendMode::for_colors(blend, &[rgba]);
+    let ((), painted) = document.draw_blended(blend, |buffer| {
+        engine::tools::circle(buffer, (center_x, center_y), (end_x, end_y), rgba, filled, thickness)
+    })?;
+
+    if save_history {
+        state.record(&project_id, &document, Operation::PushState);
+    }
+    state.record_painted(&project_id, &document, painted, || {
+        Ok(Operation::Circle { center_x, center_y, end_x, end_y, color: rgba, filled, thickness })
+    });
+
+    let changes = if save_history { Changes::EDIT } else { Changes::PIXELS };
+    events::emit_changes(&app, &project_id, &document, changes);
+    Ok(())
 }
 
+/// Draw an ellipse inside the box between two corners, clipped to the canvas
+///
+/// `from_center`, `constrain_aspect`, `thickness` and `blend` work as for
+/// draw_rectangle; `constrain_aspect` draws a circle.
 #[tauri::command]
-fn draw_fill(
+fn draw_ellipse(
+    app: AppHandle,
     state: State<AppState>,
     project_id: String,
-    x: u32,
-    y: u32,
+    x0: i32,
+    y0: i32,
+    x1: i32,
+    y1: i32,
     color: String,
-) -> Result<(), String> {
-    let mut canvases = state.canvases.lock().unwrap();
-    let history = canvases
-        .get_mut(&project_id)
-        .ok_or("Canvas not found")?;
+    filled: bool,
+    save_history: bool,
+    from_center: Option<bool>,
+    constrain_aspect: Option<bool>,
+    thickness: Option<u32>,
+    blend: Option<engine::BlendMode>,
+) -> Result<()> {
+    let (start, end) = ((x0, y0), (x1, y1));
+    let options = EllipseOptions {
+        from_center: from_center.unwrap_or(false),
+        constrain_aspect: constrain_aspect.unwrap_or(false),
+        thickness: shape_thickness(thickness)?,
+        blend,
+    };
+    paint_ellipse(app, state, project_id, start, end, color, filled, save_history, options)
+}
 
-    // Save state before filling (for undo)
-    history.push_state();
+/// draw_ellipse's options with their defaults filled in
+struct EllipseOptions {
+    from_center: bool,
+    constrain_aspect: bool, // Draw a circle
+    thickness: u32,
+    blend: Option<engine::BlendMode>,
+}
+
+/// draw_ellipse with its options resolved, shared with draw_circle
+fn paint_ellipse(
+    app: AppHandle,
+    state: State<AppState>,
+    project_id: String,
+    start: (i32, i32),
+    end: (i32, i32),
+    color: String,
+    filled: bool,
+    save_history: bool,
+    options: EllipseOptions,
+) -> Result<()> {
+    let EllipseOptions { from_center, constrain_aspect, thickness, blend } = options;
+    let snapping = state.snapping(&project_id)?;
+    let (start, end) = (snapping.point(start.0, start.1), snapping.point(end.0, end.1));
+    let (x0, y0, x1, y1) = engine::tools::shape_box(start, end, from_center, constrain_aspect);
+
+    let document = state.document(&project_id)?;
+    let mut document = document.lock().unwrap();
+
+    // Save state before drawing (for undo)
+    if save_history {
+        document.history.push_state();
+    }
+
+    let rgba = document.history.palette.resolve(&color)?;
+    let blend = engine::BlendMode::for_colors(blend, &[rgba]);
+    let ((), painted) = document.draw_blended(blend, |buffer| {
+        engine::tools::ellipse(buffer, (x0, y0), (x1, y1), rgba, filled, thickness)
+    })?;
+
+    if save_history {
+        state.record(&project_id, &document, Operation::PushState);
+    }
+    state.record_painted(&project_id, &document, painted, || {
+        Ok(Operation::Ellipse { x0, y0, x1, y1, color: rgba, filled, thickness })
+    });
 
-    let rgba = engine::tools::hex_to_rgba(&color)?;
-    engine::tools::fill(&mut history.buffer, x, y, rgba)
+    let changes = if save_history { Changes::EDIT } else { Changes::PIXELS };
+    events::emit_changes(&app, &project_id, &document, changes);
+    Ok(())
+}
+
+/// Fill the selection, or the whole canvas without one, with a two-color
+/// dithered gradient from (x0, y0) to (x1, y1) as one undoable step
+///
+/// `kind` defaults to linear and `dither` to a 4x4 Bayer matrix.
+#[tauri::command]
+fn draw_gradient(
+    app: AppHandle,
+    state: State<AppState>,
+    project_id: String,
+    x0: i32,
+    y0: i32,
+    x1: i32,
+    y1: i32,
+    from_color: String,
+    to_color: String,
+    kind: Option<engine::GradientKind>,
+    dither: Option<engine::DitherPattern>,
+) -> Result<()> {
+    let snapping = state.snapping(&project_id)?;
+    let (start, end) = (snapping.point(x0, y0), snapping.point(x1, y1));
+    let (from, to) = (state.tool_color(&project_id, &from_color)?, state.tool_color(&project_id, &to_color)?);
+
+    let document = state.document(&project_id)?;
+    let mut document = document.lock().unwrap();
+    let selection = document.selection.clone();
+
+    let gradient = engine::Gradient { from, to, kind: kind.unwrap_or_default(), dither: dither.unwrap_or_default() };
+
+    document.history.push_state();
+    let (changed, painted) =
+        document.paint(|buffer| engine::tools::gradient(buffer, start, end, &gradient, selection.as_ref()))?;
+
+    // Dithered and clipped to the selection, so the result is recorded
+    state.record(&project_id, &document, Operation::PushState);
+    if let Some(changed) = changed {
+        state.record_painted(&project_id, &document, painted, || {
+            Operation::patch(&document.history.buffer, Some(changed))
+        });
+    }
+
+    events::emit_changes(&app, &project_id, &document, Changes::EDIT);
+    Ok(())
 }
 
+/// Fill a sprite sheet frame with an in-between of two keyframes
+///
+/// `position` is how far the new frame sits from `from_frame` towards
+/// `to_frame`, from 0 to 1 (halfway by default). The target frame's pixels
+/// are replaced; frames are cells of `frame_width` x `frame_height`.
+#[tauri::command]
+fn generate_inbetween(
+    app: AppHandle,
+    state: State<AppState>,
+    project_id: String,
+    frame_width: u32,
+    frame_height: u32,
+    from_frame: u32,
+    to_frame: u32,
+    target_frame: u32,
+    position: Option<f32>,
+    mode: Option<engine::InbetweenMode>,
+) -> Result<()> {
+    let position = position.unwrap_or(0.5);
+    if !(0.0..=1.0).contains(&position) {
+        return Err(AipixError::InvalidInput("Position must be between 0 and 1".to_string()));
+    }
+
+    let document = state.document(&project_id)?;
+    let mut document = document.lock().unwrap();
+    let buffer = &document.history.buffer;
+    let layout = engine::SheetLayout::new(buffer.width, buffer.height, frame_width, frame_height)?;
+    let frame = |index| {
+        let (x, y, w, h) = layout.frame_rect(index).ok_or(AipixError::NotFound("Frame"))?;
+        Ok::<_, AipixError>(buffer.copy_region(x, y, w, h))
+    };
+    let (from, to) = (frame(from_frame)?, frame(to_frame)?);
+    let inbetween = engine::inbetween::inbetween(&from, &to, position, mode.unwrap_or_default());
+    let (x, y, w, h) = layout.frame_rect(target_frame).ok_or(AipixError::NotFound("Frame"))?;
+
+    document.history.push_state();
+    let ((), painted) = document.paint(|buffer| {
+        buffer.blit(&inbetween, x as i32, y as i32);
+        Ok(())
+    })?;
+
+    let bounds = engine::SelectionBounds { min_x: x, max_x: x + w - 1, min_y: y, max_y: y + h - 1 };
+    state.record(&project_id, &document, Operation::PushState);
+    state.record_painted(&project_id, &document, painted, || {
+        Operation::patch(&document.history.buffer, Some(bounds))
+    });
+
+    events::emit_changes(&app, &project_id, &document, Changes::EDIT);
+    Ok(())
+}
+
+/// Apply drawing operations as one undoable step
+///
+/// With `frames` they are repeated on a range of sprite sheet frames, e.g. to
+/// paint the same fix on several frames of an animation at once.
+#[tauri::command]
+fn draw_operations(
+    app: AppHandle,
+    state: State<AppState>,
+    project_id: String,
+    operations: Vec<Operation>,
+    frames: Option<FrameSpan>,
+) -> Result<()> {
+    let document = state.document(&project_id)?;
+    let mut document = document.lock().unwrap();
+
+    for op in journal::apply_batch(&mut document, operations, frames)? {
+        state.record(&project_id, &document, op);
+    }
+
+    events::emit_changes(&app, &project_id, &document, Changes::EDIT);
+    Ok(())
+}
+
+/// Write many arbitrary pixels in one call, e.g. from a script, AI output or an importer
+///
+/// `pixels` are (x, y, hex color), with `#rrggbbaa` for alpha. Nothing is
+/// written if any color is invalid or any pixel lies off the canvas. Returns
+/// the region written, for redrawing.
+#[tauri::command]
+fn set_pixels(
+    app: AppHandle,
+    state: State<AppState>,
+    project_id: String,
+    pixels: Vec<(u32, u32, String)>,
+    save_history: bool,
+) -> Result<Option<engine::renderer::Rect>> {
+    let document = state.document(&project_id)?;
+    let mut document = document.lock().unwrap();
+
+    // Checked up front so a bad pixel doesn't leave an empty undo step
+    let (width, height) = (document.history.buffer.width, document.history.buffer.height);
+    let pixels = pixels
+        .into_iter()
+        .map(|(x, y, color)| {
+            if x >= width || y >= height {
+                return Err(AipixError::OutOfBounds);
+            }
+            Ok((x, y, engine::tools::hex_to_rgba(&color)?))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    if save_history {
+        document.history.push_state();
+    }
+    let (bounds, painted) = document.paint(|buffer| buffer.set_pixels(&pixels))?;
+
+    if save_history {
+        state.record(&project_id, &document, Operation::PushState);
+    }
+    state.record_painted(&project_id, &document, painted, || Ok(Operation::SetPixels { pixels }));
+
+    let changes = if save_history { Changes::EDIT } else { Changes::PIXELS };
+    events::emit_changes(&app, &project_id, &document, changes);
+    Ok(bounds.map(|(min_x, min_y, max_x, max_y)| {
+        engine::renderer::Rect::new(
+            min_x as i32,
+            min_y as i32,
+            (max_x - min_x + 1) as i32,
+            (max_y - min_y + 1) as i32,
+        )
+    }))
+}
+
+/// A color picked from the canvas
+#[derive(serde::Serialize)]
+struct PickedColor {
+    color: String, // #rrggbb, which color inputs accept; the alpha is separate
+    alpha: u8,
+    index: Option<usize>, // The palette entry exactly matching the pixel, for drawing with `palette:<index>`
+}
+
+/// Eyedropper; with a `radius` it reduces the surrounding square of pixels
+/// to one color as `area` says (the average by default)
 #[tauri::command]
 fn pick_color(
     state: State<AppState>,
     project_id: String,
     x: u32,
     y: u32,
-) -> Result<String, String> {
-    let canvases = state.canvases.lock().unwrap();
-    let history = canvases
-        .get(&project_id)
-        .ok_or("Canvas not found")?;
+    radius: Option<u32>,
+    area: Option<engine::AreaSample>,
+) -> Result<PickedColor> {
+    let radius = radius.unwrap_or(0);
+    if radius > engine::tools::MAX_SAMPLE_RADIUS {
+        return Err(AipixError::InvalidInput(format!(
+            "Sample radius must be at most {}",
+            engine::tools::MAX_SAMPLE_RADIUS
+        )));
+    }
+
+    let document = state.document(&project_id)?;
+    let document = document.lock().unwrap();
+    let history = &document.history;
+
+    let rgba = engine::tools::eyedropper_area(&history.buffer, x, y, radius, area.unwrap_or_default())
+        .ok_or(AipixError::OutOfBounds)?;
+
+    let index = history.palette.index_of(rgba);
+    let [r, g, b, alpha] = rgba;
+    Ok(PickedColor { color: engine::tools::rgba_to_hex([r, g, b, 255]), alpha, index })
+}
+
+// Grid commands
+
+#[tauri::command]
+fn get_drawing_grid(
+    state: State<AppState>,
+    project_id: String,
+) -> Result<Option<engine::SnapGrid>> {
+    let document = state.document(&project_id)?;
+    let grid = document.lock().unwrap().grid;
+    Ok(grid)
+}
+
+/// Set the grid shape tools and selections snap to; `None` turns grid snapping off
+#[tauri::command]
+fn set_drawing_grid(
+    state: State<AppState>,
+    project_id: String,
+    grid: Option<engine::SnapGrid>,
+) -> Result<()> {
+    if let Some(grid) = &grid {
+        grid.validate()?;
+    }
+
+    let document = state.document(&project_id)?;
+    document.lock().unwrap().grid = grid;
+    Ok(())
+}
+
+#[tauri::command]
+fn get_preserve_transparency(
+    state: State<AppState>,
+    project_id: String,
+) -> Result<bool> {
+    let document = state.document(&project_id)?;
+    let preserve = document.lock().unwrap().preserve_transparency;
+    Ok(preserve)
+}
+
+/// Lock transparent pixels: while on, drawing tools only recolor existing pixels
+#[tauri::command]
+fn set_preserve_transparency(
+    state: State<AppState>,
+    project_id: String,
+    preserve: bool,
+) -> Result<()> {
+    let document = state.document(&project_id)?;
+    document.lock().unwrap().preserve_transparency = preserve;
+    Ok(())
+}
+
+#[tauri::command]
+fn get_color_constraints(
+    state: State<AppState>,
+    project_id: String,
+) -> Result<Option<engine::ColorConstraints>> {
+    let document = state.document(&project_id)?;
+    let constraints = document.lock().unwrap().constraints;
+    Ok(constraints)
+}
+
+/// Check drawing against a console's color limits; `None` turns checking off
+///
+/// Enforced constraints refuse edits that add violations. Pixels already
+/// breaking them are left as they are.
+#[tauri::command]
+fn set_color_constraints(
+    state: State<AppState>,
+    project_id: String,
+    constraints: Option<engine::ColorConstraints>,
+) -> Result<()> {
+    let document = state.document(&project_id)?;
+    document.lock().unwrap().constraints = constraints;
+    Ok(())
+}
+
+/// Regions of the canvas using more colors than `profile` (the document's by default) allows
+#[tauri::command]
+fn check_color_constraints(
+    state: State<AppState>,
+    project_id: String,
+    profile: Option<engine::HardwareProfile>,
+) -> Result<Vec<engine::constraints::ConstraintViolation>> {
+    let document = state.document(&project_id)?;
+    let document = document.lock().unwrap();
+    let profile = profile
+        .or(document.constraints.map(|constraints| constraints.profile))
+        .ok_or_else(|| AipixError::InvalidInput("No hardware profile to check against".to_string()))?;
+
+    Ok(engine::constraints::check(&document.history.buffer, profile))
+}
+
+// Palette commands
 
-    let rgba = engine::tools::eyedropper(&history.buffer, x, y)
-        .ok_or("Invalid coordinates")?;
+#[tauri::command]
+fn get_palette(
+    state: State<AppState>,
+    project_id: String,
+) -> Result<Vec<String>> {
+    let document = state.document(&project_id)?;
+    let document = document.lock().unwrap();
+    let colors = document
+        .history
+        .palette
+        .colors
+        .iter()
+        .map(|c| engine::tools::rgba_to_hex(*c))
+        .collect();
+    Ok(colors)
+}
 
-    Ok(engine::tools::rgba_to_hex(rgba))
+#[tauri::command]
+fn set_palette(
+    state: State<AppState>,
+    project_id: String,
+    colors: Vec<String>,
+) -> Result<()> {
+    let colors = colors
+        .iter()
+        .map(|c| engine::tools::hex_to_rgba(c))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let document = state.document(&project_id)?;
+    document.lock().unwrap().history.palette = engine::Palette::from_colors(colors);
+    Ok(())
 }
 
 #[tauri::command]
-fn replace_color(
+fn add_palette_colors(
     state: State<AppState>,
     project_id: String,
-    target_color: String,
-    new_color: String,
-) -> Result<(), String> {
-    let mut canvases = state.canvases.lock().unwrap();
-    let history = canvases
-        .get_mut(&project_id)
-        .ok_or("Canvas not found")?;
+    colors: Vec<String>,
+    index: Option<usize>,
+) -> Result<()> {
+    let colors = colors
+        .iter()
+        .map(|c| engine::tools::hex_to_rgba(c))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let document = state.document(&project_id)?;
+    document.lock().unwrap().history.palette.insert_colors(index, &colors);
+    Ok(())
+}
 
-    let target_rgba = engine::tools::hex_to_rgba(&target_color)?;
-    let new_rgba = engine::tools::hex_to_rgba(&new_color)?;
+/// Remap canvas pixels of each removed palette color to the entry it was merged into
+fn remap_palette_pixels(
+    state: &AppState,
+    project_id: &str,
+    document: &mut engine::Document,
+    merges: &[([u8; 4], [u8; 4])],
+) -> Result<()> {
+    for &(removed, kept) in merges {
+        let ((), painted) = document.paint(|buffer| {
+            engine::tools::replace_all_color(buffer, removed, kept);
+            Ok(())
+        })?;
+        state.record_painted(project_id, document, painted, || {
+            Ok(Operation::ReplaceColor { target: removed, replacement: kept })
+        });
+    }
+    Ok(())
+}
 
-    engine::tools::replace_all_color(&mut history.buffer, target_rgba, new_rgba);
+/// Snap semi-transparent edge pixels, as left by antialiasing in imported
+/// images, to solid palette colors or transparency as one undoable step
+///
+/// Pixels at least `threshold` opaque (default 128) become solid, in the
+/// nearest palette color unless `use_palette` is false; the rest are cleared.
+#[tauri::command]
+fn cleanup_antialiasing(
+    app: AppHandle,
+    state: State<AppState>,
+    project_id: String,
+    threshold: Option<u8>,
+    use_palette: Option<bool>,
+) -> Result<()> {
+    let document = state.document(&project_id)?;
+    let mut document = document.lock().unwrap();
+    let palette = match use_palette.unwrap_or(true) {
+        true => document.history.palette.clone(),
+        false => engine::Palette::new(),
+    };
+
+    document.history.push_state();
+    let (changed, painted) = document.paint(|buffer| {
+        Ok(engine::tools::cleanup_antialiasing(buffer, threshold.unwrap_or(128), &palette))
+    })?;
+
+    state.record(&project_id, &document, Operation::PushState);
+    if let Some(changed) = changed {
+        state.record_painted(&project_id, &document, painted, || {
+            Operation::patch(&document.history.buffer, Some(changed))
+        });
+    }
 
+    events::emit_changes(&app, &project_id, &document, Changes::EDIT);
     Ok(())
 }
 
+/// Reorder the palette as one undoable step
+#[tauri::command]
+fn sort_palette(
+    app: AppHandle,
+    state: State<AppState>,
+    project_id: String,
+    order: engine::PaletteSort,
+) -> Result<Vec<String>> {
+    let document = state.document(&project_id)?;
+    let mut document = document.lock().unwrap();
+    let history = &mut document.history;
+
+    history.push_state();
+    history.palette.sort(order, &history.buffer);
+    state.record(&project_id, &document, Operation::PushState);
+
+    events::emit_changes(&app, &project_id, &document, Changes::HISTORY);
+    drop(document);
+    get_palette(state, project_id)
+}
+
+/// Remove duplicate palette entries as one undoable step
+///
+/// With a `tolerance` near-duplicates go too; `remap` also recolors their
+/// pixels on the canvas to the entry that was kept.
+#[tauri::command]
+fn dedupe_palette(
+    app: AppHandle,
+    state: State<AppState>,
+    project_id: String,
+    tolerance: Option<u8>,
+    remap: Option<bool>,
+) -> Result<Vec<String>> {
+    let document = state.document(&project_id)?;
+    let mut document = document.lock().unwrap();
+    let history = &mut document.history;
+
+    history.push_state();
+    let merges = history.palette.dedupe(tolerance.unwrap_or(0));
+    state.record(&project_id, &document, Operation::PushState);
+    if remap.unwrap_or(false) {
+        remap_palette_pixels(&state, &project_id, &mut document, &merges)?;
+    }
+
+    events::emit_changes(&app, &project_id, &document, Changes::EDIT);
+    drop(document);
+    get_palette(state, project_id)
+}
+
+/// Merge one palette entry into another as one undoable step, recoloring
+/// the removed entry's pixels on the canvas
+#[tauri::command]
+fn merge_palette_colors(
+    app: AppHandle,
+    state: State<AppState>,
+    project_id: String,
+    keep_index: usize,
+    remove_index: usize,
+) -> Result<Vec<String>> {
+    let document = state.document(&project_id)?;
+    let mut document = document.lock().unwrap();
+    let mut palette = document.history.palette.clone();
+    let merge = palette.merge(keep_index, remove_index)?;
+
+    let history = &mut document.history;
+    history.push_state();
+    history.palette = palette;
+    state.record(&project_id, &document, Operation::PushState);
+    remap_palette_pixels(&state, &project_id, &mut document, &[merge])?;
+
+    events::emit_changes(&app, &project_id, &document, Changes::EDIT);
+    drop(document);
+    get_palette(state, project_id)
+}
+
 // History commands
 #[tauri::command]
 fn save_history_state(
+    app: AppHandle,
     state: State<AppState>,
     project_id: String,
-) -> Result<(), String> {
-    let mut canvases = state.canvases.lock().unwrap();
-    let history = canvases
-        .get_mut(&project_id)
-        .ok_or("Canvas not found")?;
+) -> Result<()> {
+    let document = state.document(&project_id)?;
+    let mut document = document.lock().unwrap();
+    let history = &mut document.history;
 
     history.push_state();
+    state.record(&project_id, &document, Operation::PushState);
+
+    events::emit_changes(&app, &project_id, &document, Changes::HISTORY);
     Ok(())
 }
 
+/// Undo the last step; returns the region to redraw (`None` when no pixels changed)
 #[tauri::command]
 fn undo_canvas(
+    app: AppHandle,
     state: State<AppState>,
     project_id: String,
-) -> Result<(), String> {
-    let mut canvases = state.canvases.lock().unwrap();
-    let history = canvases
-        .get_mut(&project_id)
-        .ok_or("Canvas not found")?;
-
-    history.undo()
+) -> Result<Option<engine::renderer::Rect>> {
+    let document = state.document(&project_id)?;
+    let mut document = document.lock().unwrap();
+    let size = (document.history.buffer.width, document.history.buffer.height);
+
+    let changed = document.undo()?;
+    state.record(&project_id, &document, Operation::Undo);
+
+    // A resize undone or redone also started the selection over
+    let resized = size != (document.history.buffer.width, document.history.buffer.height);
+    events::emit_changes(&app, &project_id, &document, if resized { Changes::ALL } else { Changes::EDIT });
+    Ok(changed)
 }
 
+/// Redo the last undone step; returns the region to redraw as for undo_canvas
 #[tauri::command]
 fn redo_canvas(
+    app: AppHandle,
     state: State<AppState>,
     project_id: String,
-) -> Result<(), String> {
-    let mut canvases = state.canvases.lock().unwrap();
-    let history = canvases
-        .get_mut(&project_id)
-        .ok_or("Canvas not found")?;
-
-    history.redo()
+) -> Result<Option<engine::renderer::Rect>> {
+    let document = state.document(&project_id)?;
+    let mut document = document.lock().unwrap();
+    let size = (document.history.buffer.width, document.history.buffer.height);
+
+    let changed = document.redo()?;
+    state.record(&project_id, &document, Operation::Redo);
+
+    // A resize undone or redone also started the selection over
+    let resized = size != (document.history.buffer.width, document.history.buffer.height);
+    events::emit_changes(&app, &project_id, &document, if resized { Changes::ALL } else { Changes::EDIT });
+    Ok(changed)
 }
 
 #[tauri::command]
 fn can_undo(
     state: State<AppState>,
     project_id: String,
-) -> Result<bool, String> {
-    let canvases = state.canvases.lock().unwrap();
-    let history = canvases
-        .get(&project_id)
-        .ok_or("Canvas not found")?;
+) -> Result<bool> {
+    let document = state.document(&project_id)?;
+    let document = document.lock().unwrap();
+    let history = &document.history;
 
     Ok(history.can_undo())
 }
@@ -433,11 +1727,10 @@ fn can_undo(
 fn can_redo(
     state: State<AppState>,
     project_id: String,
-) -> Result<bool, String> {
-    let canvases = state.canvases.lock().unwrap();
-    let history = canvases
-        .get(&project_id)
-        .ok_or("Canvas not found")?;
+) -> Result<bool> {
+    let document = state.document(&project_id)?;
+    let document = document.lock().unwrap();
+    let history = &document.history;
 
     Ok(history.can_redo())
 }
@@ -446,18 +1739,23 @@ fn can_redo(
 
 #[tauri::command]
 fn create_selection(
+    app: AppHandle,
     state: State<AppState>,
     project_id: String,
     width: u32,
     height: u32,
-) -> Result<(), String> {
-    let mut selections = state.selections.lock().unwrap();
-    selections.insert(project_id, engine::Selection::new(width, height));
+) -> Result<()> {
+    let document = state.document(&project_id)?;
+    let mut document = document.lock().unwrap();
+    document.selection = Some(engine::Selection::new(width, height));
+
+    events::emit_changes(&app, &project_id, &document, Changes::SELECTION);
     Ok(())
 }
 
 #[tauri::command]
 fn select_rectangle(
+    app: AppHandle,
     state: State<AppState>,
     project_id: String,
     x0: u32,
@@ -465,18 +1763,26 @@ fn select_rectangle(
     x1: u32,
     y1: u32,
     mode: engine::SelectionMode,
-) -> Result<engine::Selection, String> {
-    let mut selections = state.selections.lock().unwrap();
-    let selection = selections
-        .get_mut(&project_id)
-        .ok_or("Selection not found")?;
+) -> Result<engine::Selection> {
+    let (x0, y0, x1, y1) = state.snapping(&project_id)?.rect(x0, y0, x1, y1);
+
+    let document = state.document(&project_id)?;
+    let mut document = document.lock().unwrap();
+    let selection = document
+        .selection
+        .as_mut()
+        .ok_or(AipixError::NotFound("Selection"))?;
 
     engine::tools::select_rectangle(selection, x0, y0, x1, y1, mode);
-    Ok(selection.clone())
+    let selection = selection.clone();
+
+    events::emit_changes(&app, &project_id, &document, Changes::SELECTION);
+    Ok(selection)
 }
 
 #[tauri::command]
 fn select_ellipse(
+    app: AppHandle,
     state: State<AppState>,
     project_id: String,
     center_x: i32,
@@ -484,107 +1790,200 @@ fn select_ellipse(
     end_x: i32,
     end_y: i32,
     mode: engine::SelectionMode,
-) -> Result<engine::Selection, String> {
-    let mut selections = state.selections.lock().unwrap();
-    let selection = selections
-        .get_mut(&project_id)
-        .ok_or("Selection not found")?;
+) -> Result<engine::Selection> {
+    let snapping = state.snapping(&project_id)?;
+    let ((center_x, center_y), (end_x, end_y)) =
+        (snapping.point(center_x, center_y), snapping.point(end_x, end_y));
+
+    let document = state.document(&project_id)?;
+    let mut document = document.lock().unwrap();
+    let selection = document
+        .selection
+        .as_mut()
+        .ok_or(AipixError::NotFound("Selection"))?;
 
     engine::tools::select_ellipse(selection, center_x, center_y, end_x, end_y, mode);
-    Ok(selection.clone())
+    let selection = selection.clone();
+
+    events::emit_changes(&app, &project_id, &document, Changes::SELECTION);
+    Ok(selection)
 }
 
 #[tauri::command]
 fn select_lasso(
+    app: AppHandle,
     state: State<AppState>,
     project_id: String,
     points: Vec<(i32, i32)>,
     mode: engine::SelectionMode,
-) -> Result<engine::Selection, String> {
-    let mut selections = state.selections.lock().unwrap();
-    let selection = selections
-        .get_mut(&project_id)
-        .ok_or("Selection not found")?;
+) -> Result<engine::Selection> {
+    let document = state.document(&project_id)?;
+    let mut document = document.lock().unwrap();
+    let selection = document
+        .selection
+        .as_mut()
+        .ok_or(AipixError::NotFound("Selection"))?;
 
     engine::tools::select_lasso_add_point(selection, &points, mode);
-    Ok(selection.clone())
+    let selection = selection.clone();
+
+    events::emit_changes(&app, &project_id, &document, Changes::SELECTION);
+    Ok(selection)
 }
 
+/// Start a lasso at `x`, `y`, dropping any lasso not yet closed
+///
+/// Unlike select_lasso, the path is sent a point at a time with
+/// add_lasso_point and only filled by close_lasso.
 #[tauri::command]
-fn select_magic_wand(
-    state: State<AppState>,
-    project_id: String,
-    x: u32,
-    y: u32,
-    tolerance: u8,
-    mode: engine::SelectionMode,
-) -> Result<engine::Selection, String> {
-    let mut canvases = state.canvases.lock().unwrap();
-    let mut selections = state.selections.lock().unwrap();
+fn begin_lasso(state: State<AppState>, project_id: String, x: i32, y: i32) -> Result<()> {
+    let document = state.document(&project_id)?;
+    let mut document = document.lock().unwrap();
+    if document.selection.is_none() {
+        return Err(AipixError::NotFound("Selection"));
+    }
 
-    let history = canvases
-        .get_mut(&project_id)
-        .ok_or("Canvas not found")?;
+    document.lasso = Some(engine::LassoPath::new(x, y));
+    Ok(())
+}
 
-    let selection = selections
-        .get_mut(&project_id)
-        .ok_or("Selection not found")?;
+/// Extend the lasso being drawn, returning the outline it adds for the UI to draw
+#[tauri::command]
+fn add_lasso_point(state: State<AppState>, project_id: String, x: i32, y: i32) -> Result<engine::LassoPreview> {
+    let document = state.document(&project_id)?;
+    let mut document = document.lock().unwrap();
+    let engine::Document { selection, lasso, .. } = &mut *document;
+    let selection = selection.as_ref().ok_or(AipixError::NotFound("Selection"))?;
+    let lasso = lasso.as_mut().ok_or_else(|| AipixError::InvalidState("No lasso has been begun".to_string()))?;
+
+    let points = lasso.add_point(x, y)?;
+    Ok(engine::LassoPreview { points, point_count: lasso.points().len(), bounds: lasso.bounds(selection) })
+}
 
-    engine::tools::select_magic_wand(&history.buffer, selection, x, y, tolerance, mode)?;
-    Ok(selection.clone())
+/// Close the lasso being drawn and fill it into the selection with `mode`
+#[tauri::command]
+fn close_lasso(
+    app: AppHandle,
+    state: State<AppState>,
+    project_id: String,
+    mode: engine::SelectionMode,
+) -> Result<engine::Selection> {
+    let document = state.document(&project_id)?;
+    let mut document = document.lock().unwrap();
+    let lasso = document.lasso.take().ok_or_else(|| AipixError::InvalidState("No lasso has been begun".to_string()))?;
+    let selection = document
+        .selection
+        .as_mut()
+        .ok_or(AipixError::NotFound("Selection"))?;
+
+    lasso.close(selection, mode);
+    let selection = selection.clone();
+
+    events::emit_changes(&app, &project_id, &document, Changes::SELECTION);
+    Ok(selection)
 }
 
 #[tauri::command]
 fn select_all(
+    app: AppHandle,
     state: State<AppState>,
     project_id: String,
-) -> Result<engine::Selection, String> {
-    let mut selections = state.selections.lock().unwrap();
-    let selection = selections
-        .get_mut(&project_id)
-        .ok_or("Selection not found")?;
+) -> Result<engine::Selection> {
+    let document = state.document(&project_id)?;
+    let mut document = document.lock().unwrap();
+    let selection = document
+        .selection
+        .as_mut()
+        .ok_or(AipixError::NotFound("Selection"))?;
 
     selection.select_all();
-    Ok(selection.clone())
+    let selection = selection.clone();
+
+    events::emit_changes(&app, &project_id, &document, Changes::SELECTION);
+    Ok(selection)
 }
 
 #[tauri::command]
 fn deselect(
+    app: AppHandle,
     state: State<AppState>,
     project_id: String,
-) -> Result<(), String> {
-    let mut selections = state.selections.lock().unwrap();
-    let selection = selections
-        .get_mut(&project_id)
-        .ok_or("Selection not found")?;
+) -> Result<()> {
+    let document = state.document(&project_id)?;
+    let mut document = document.lock().unwrap();
+    let selection = document
+        .selection
+        .as_mut()
+        .ok_or(AipixError::NotFound("Selection"))?;
 
     selection.clear();
+
+    events::emit_changes(&app, &project_id, &document, Changes::SELECTION);
     Ok(())
 }
 
 #[tauri::command]
 fn invert_selection(
+    app: AppHandle,
     state: State<AppState>,
     project_id: String,
-) -> Result<engine::Selection, String> {
-    let mut selections = state.selections.lock().unwrap();
-    let selection = selections
-        .get_mut(&project_id)
-        .ok_or("Selection not found")?;
+) -> Result<engine::Selection> {
+    let document = state.document(&project_id)?;
+    let mut document = document.lock().unwrap();
+    let selection = document
+        .selection
+        .as_mut()
+        .ok_or(AipixError::NotFound("Selection"))?;
 
     selection.invert();
-    Ok(selection.clone())
+    let selection = selection.clone();
+
+    events::emit_changes(&app, &project_id, &document, Changes::SELECTION);
+    Ok(selection)
+}
+
+/// Soften the selection's edge over `radius` pixels (1-32), leaving it partly selected
+///
+/// Tools, copies, deletes and pastes affect partly selected pixels in
+/// proportion to how much of them is selected.
+#[tauri::command]
+fn feather_selection(
+    app: AppHandle,
+    state: State<AppState>,
+    project_id: String,
+    radius: u32,
+) -> Result<engine::Selection> {
+    if !(1..=engine::tools::MAX_FEATHER_RADIUS).contains(&radius) {
+        return Err(AipixError::InvalidInput(format!(
+            "Feather radius must be between 1 and {}",
+            engine::tools::MAX_FEATHER_RADIUS
+        )));
+    }
+    let document = state.document(&project_id)?;
+    let mut document = document.lock().unwrap();
+    let selection = document
+        .selection
+        .as_mut()
+        .ok_or(AipixError::NotFound("Selection"))?;
+
+    selection.feather(radius);
+    let selection = selection.clone();
+
+    events::emit_changes(&app, &project_id, &document, Changes::SELECTION);
+    Ok(selection)
 }
 
 #[tauri::command]
 fn get_selection(
     state: State<AppState>,
     project_id: String,
-) -> Result<engine::Selection, String> {
-    let selections = state.selections.lock().unwrap();
-    let selection = selections
-        .get(&project_id)
-        .ok_or("Selection not found")?;
+) -> Result<engine::Selection> {
+    let document = state.document(&project_id)?;
+    let document = document.lock().unwrap();
+    let selection = document
+        .selection
+        .as_ref()
+        .ok_or(AipixError::NotFound("Selection"))?;
 
     Ok(selection.clone())
 }
@@ -593,111 +1992,158 @@ fn get_selection(
 fn copy_selection(
     state: State<AppState>,
     project_id: String,
-) -> Result<(), String> {
-    let mut canvases = state.canvases.lock().unwrap();
-    let selections = state.selections.lock().unwrap();
+) -> Result<()> {
+    let document = state.document(&project_id)?;
+    let mut document = document.lock().unwrap();
+    let engine::Document { history, selection, .. } = &mut *document;
 
-    let history = canvases
-        .get_mut(&project_id)
-        .ok_or("Canvas not found")?;
-
-    let selection = selections
-        .get(&project_id)
-        .ok_or("Selection not found")?;
+    let selection = selection
+        .as_ref()
+        .ok_or(AipixError::NotFound("Selection"))?;
 
     if let Some(extracted) = engine::tools::extract_selection(&history.buffer, selection) {
-        let mut clipboard = state.clipboard.lock().unwrap();
-        *clipboard = Some(extracted);
+        state.clipboard.lock().unwrap().push(extracted);
         Ok(())
     } else {
-        Err("No selection to copy".to_string())
+        Err(AipixError::InvalidState("No selection to copy".to_string()))
     }
 }
 
 #[tauri::command]
 fn cut_selection(
+    app: AppHandle,
     state: State<AppState>,
     project_id: String,
-) -> Result<(), String> {
-    let mut canvases = state.canvases.lock().unwrap();
-    let selections = state.selections.lock().unwrap();
-
-    let history = canvases
-        .get_mut(&project_id)
-        .ok_or("Canvas not found")?;
+) -> Result<()> {
+    let document = state.document(&project_id)?;
+    let mut document = document.lock().unwrap();
+    let engine::Document { history, selection, .. } = &mut *document;
 
-    let selection = selections
-        .get(&project_id)
-        .ok_or("Selection not found")?;
+    let selection = selection
+        .as_ref()
+        .ok_or(AipixError::NotFound("Selection"))?;
 
     // Save to clipboard
-    if let Some(extracted) = engine::tools::extract_selection(&history.buffer, selection) {
-        let mut clipboard = state.clipboard.lock().unwrap();
-        *clipboard = Some(extracted);
+    let extracted = engine::tools::extract_selection(&history.buffer, selection)
+        .ok_or_else(|| AipixError::InvalidState("No selection to cut".to_string()))?;
+    state.clipboard.lock().unwrap().push(extracted);
 
-        // Delete from canvas
-        history.push_state();
-        engine::tools::delete_selection(&mut history.buffer, selection);
-        Ok(())
-    } else {
-        Err("No selection to cut".to_string())
-    }
+    // Delete from canvas
+    history.push_state();
+    engine::tools::delete_selection(&mut history.buffer, selection);
+    let bounds = selection.bounds;
+
+    state.record(&project_id, &document, Operation::PushState);
+    state.record_with(&project_id, &document, || {
+        Operation::patch(&document.history.buffer, bounds)
+    });
+
+    events::emit_changes(&app, &project_id, &document, Changes::EDIT);
+    Ok(())
 }
 
 #[tauri::command]
 fn paste_selection(
+    app: AppHandle,
     state: State<AppState>,
     project_id: String,
     x: u32,
     y: u32,
-) -> Result<(), String> {
-    let mut canvases = state.canvases.lock().unwrap();
-    let clipboard = state.clipboard.lock().unwrap();
+) -> Result<()> {
+    commands::clipboard::paste(&app, &state, &project_id, None, Some((x, y)))
+}
 
-    let history = canvases
-        .get_mut(&project_id)
-        .ok_or("Canvas not found")?;
+/// Paste at the coordinates the clipboard was copied or cut from
+#[tauri::command]
+fn paste_in_place(
+    app: AppHandle,
+    state: State<AppState>,
+    project_id: String,
+) -> Result<()> {
+    commands::clipboard::paste(&app, &state, &project_id, None, None)
+}
 
-    if let Some((ref buffer, _, _)) = *clipboard {
-        history.push_state();
-        engine::tools::paste_buffer(&mut history.buffer, buffer, x, y)?;
-        Ok(())
-    } else {
-        Err("Clipboard is empty".to_string())
+/// Paste only into the active selection, clipping away everything outside it
+///
+/// Pastes at `x`, `y`, or where the clipboard was copied from when omitted.
+#[tauri::command]
+fn paste_into_selection(
+    app: AppHandle,
+    state: State<AppState>,
+    project_id: String,
+    x: Option<u32>,
+    y: Option<u32>,
+) -> Result<()> {
+    let document = state.document(&project_id)?;
+    let mut document = document.lock().unwrap();
+    let selection = document
+        .selection
+        .clone()
+        .ok_or(AipixError::NotFound("Selection"))?;
+
+    let clipboard = state.clipboard.lock().unwrap();
+    let entry = clipboard.get(None)?;
+    let (x, y) = (x.unwrap_or(entry.x), y.unwrap_or(entry.y));
+
+    document.history.push_state();
+    let (changed, painted) = document.paint(|canvas| {
+        Ok(engine::tools::paste_buffer_into(canvas, &entry.buffer, x, y, &selection))
+    })?;
+
+    // Clipped pixels can't be replayed from the clipboard, so record the result
+    state.record(&project_id, &document, Operation::PushState);
+    if let Some(changed) = changed {
+        state.record_painted(&project_id, &document, painted, || {
+            Operation::patch(&document.history.buffer, Some(changed))
+        });
     }
+
+    events::emit_changes(&app, &project_id, &document, Changes::EDIT);
+    Ok(())
 }
 
 #[tauri::command]
 fn delete_selected(
+    app: AppHandle,
     state: State<AppState>,
     project_id: String,
-) -> Result<(), String> {
-    let mut canvases = state.canvases.lock().unwrap();
-    let selections = state.selections.lock().unwrap();
-
-    let history = canvases
-        .get_mut(&project_id)
-        .ok_or("Canvas not found")?;
+) -> Result<()> {
+    let document = state.document(&project_id)?;
+    let mut document = document.lock().unwrap();
+    let engine::Document { history, selection, .. } = &mut *document;
 
-    let selection = selections
-        .get(&project_id)
-        .ok_or("Selection not found")?;
+    let selection = selection
+        .as_ref()
+        .ok_or(AipixError::NotFound("Selection"))?;
 
     history.push_state();
     engine::tools::delete_selection(&mut history.buffer, selection);
+    let bounds = selection.bounds;
+
+    state.record(&project_id, &document, Operation::PushState);
+    state.record_with(&project_id, &document, || {
+        Operation::patch(&document.history.buffer, bounds)
+    });
+
+    events::emit_changes(&app, &project_id, &document, Changes::EDIT);
     Ok(())
 }
 
 fn main() {
-    tauri::Builder::default()
+    let builder = tauri::Builder::default();
+
+    // Registered first: a second launch forwards its arguments (files, links) here and exits
+    #[cfg(not(any(target_os = "android", target_os = "ios")))]
+    let builder = builder.plugin(tauri_plugin_single_instance::init(|app, args, _cwd| {
+        let targets = aipix_lib::deep_link::targets_from_args(args);
+        aipix_lib::deep_link::open_all(app, &targets);
+    }));
+
+    builder
+        .plugin(tauri_plugin_deep_link::init())
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_shell::init())
-        .manage(AppState {
-            db: Mutex::new(None),
-            canvases: Mutex::new(HashMap::new()),
-            selections: Mutex::new(HashMap::new()),
-            clipboard: Mutex::new(None),
-        })
+        .manage(AppState::default())
         .manage(commands::RendererState::new())
         .invoke_handler(tauri::generate_handler![
             greet,
@@ -706,6 +2152,8 @@ fn main() {
             get_user_projects,
             update_project,
             delete_project,
+            archive_projects,
+            unarchive_projects,
             create_folder,
             get_user_folders,
             update_folder,
@@ -713,19 +2161,80 @@ fn main() {
             create_user,
             get_user,
             update_user,
+            commands::settings::get_user_settings,
+            commands::settings::update_user_settings,
+            commands::brushes::create_brush,
+            commands::brushes::list_brushes,
+            commands::brushes::delete_brush,
+            commands::brushes::get_brush_outline,
+            commands::analysis::analyze_frames,
+            commands::activity::get_time_spent,
+            create_comment,
+            get_project_comments,
+            update_comment,
+            delete_comment,
+            apply_remote_comment,
+            heartbeat_presence,
+            apply_remote_presence,
+            get_project_presence,
+            acquire_edit_lock,
+            release_edit_lock,
+            get_edit_lock,
+            apply_remote_edit_lock,
             get_unsynced_items,
             mark_as_synced,
+            mark_as_synced_with_revision,
+            apply_remote_project,
+            apply_remote_folder,
+            get_sync_items,
+            get_sync_item_error,
+            mark_sync_failed,
+            retry_sync_item,
+            skip_sync_item,
+            purge_sync_item,
+            purge_inactive_sync_items,
             create_canvas,
             get_canvas_data,
+            get_canvas_region,
             draw_pencil,
+            draw_pencil_stroke,
             draw_eraser,
+            draw_dither,
+            draw_shade,
+            draw_blur,
+            draw_jumble,
             draw_line,
+            draw_line_from_last,
             draw_rectangle,
             draw_circle,
-            draw_fill,
+            draw_ellipse,
+            draw_gradient,
+            generate_inbetween,
+            draw_operations,
+            set_pixels,
+            commands::jobs::draw_fill,
             pick_color,
-            replace_color,
+            get_drawing_grid,
+            set_drawing_grid,
+            get_preserve_transparency,
+            set_preserve_transparency,
+            get_color_constraints,
+            set_color_constraints,
+            check_color_constraints,
+            commands::jobs::replace_color,
+            get_palette,
+            set_palette,
+            add_palette_colors,
+            sort_palette,
+            cleanup_antialiasing,
+            dedupe_palette,
+            merge_palette_colors,
             save_history_state,
+            commands::jobs::upscale_layer,
+            commands::jobs::rotate_layer,
+            commands::jobs::rotate_selection,
+            commands::jobs::apply_pixel_filter,
+            commands::jobs::cancel_job,
             undo_canvas,
             redo_canvas,
             can_undo,
@@ -734,27 +2243,202 @@ fn main() {
             select_rectangle,
             select_ellipse,
             select_lasso,
-            select_magic_wand,
+            begin_lasso,
+            add_lasso_point,
+            close_lasso,
+            commands::jobs::select_magic_wand,
             select_all,
             deselect,
             invert_selection,
+            feather_selection,
             get_selection,
             copy_selection,
             cut_selection,
             paste_selection,
+            paste_in_place,
+            paste_into_selection,
+            commands::clipboard::get_clipboard_history,
+            commands::clipboard::paste_clipboard_entry,
+            commands::clipboard::begin_paste_preview,
+            commands::clipboard::move_paste_preview,
+            commands::clipboard::commit_paste,
+            commands::clipboard::cancel_paste_preview,
             delete_selected,
             // Native Skia rendering commands
             commands::rendering::init_renderer,
             commands::rendering::draw_stroke,
             commands::rendering::fill_rect,
+            commands::rendering::apply_filter,
             commands::rendering::render_viewport,
+            commands::rendering::show_guides,
+            commands::rendering::show_palette_cycling,
+            commands::rendering::show_grid,
             commands::rendering::get_canvas_image,
             commands::rendering::clear_canvas,
             commands::rendering::resize_canvas,
             commands::rendering::get_dirty_bounds,
             commands::rendering::clear_dirty_region,
+            // AI-assisted commands
+            commands::ai::set_ai_provider,
+            commands::limits::get_canvas_limits,
+            commands::limits::set_canvas_limits,
+            commands::diagnostics::get_performance_report,
+            commands::diagnostics::reset_performance_stats,
+            commands::logging::get_log_tail,
+            commands::logging::set_log_level,
+            commands::recovery::list_recoverable_documents,
+            commands::recovery::recover_document,
+            commands::recovery::autosave_document,
+            commands::recovery::discard_recovery,
+            commands::plugins::list_plugins,
+            commands::plugins::set_plugin_enabled,
+            commands::plugins::set_plugin_permissions,
+            commands::plugins::run_filter_plugin,
+            commands::plugins::apply_tool_plugin,
+            commands::plugins::import_with_plugin,
+            commands::plugins::export_with_plugin,
+            commands::mcp::start_mcp_server,
+            commands::mcp::stop_mcp_server,
+            commands::mcp::get_mcp_status,
+            commands::mcp::set_mcp_export_dir,
+            commands::http_api::start_http_api,
+            commands::http_api::stop_http_api,
+            commands::http_api::get_http_api_status,
+            commands::files::open_link,
+            commands::files::open_file,
+            commands::files::take_pending_navigation,
+            commands::files::save_project_file,
+            commands::tilemap::create_tilemap,
+            commands::tilemap::create_tilemap_from_canvas,
+            commands::tilemap::get_tilemap,
+            commands::tilemap::close_tilemap,
+            commands::tilemap::get_tile,
+            commands::tilemap::add_tile,
+            commands::tilemap::add_tile_from_canvas,
+            commands::tilemap::update_tile,
+            commands::tilemap::delete_tile,
+            commands::tilemap::place_tile,
+            commands::tilemap::stamp_tiles,
+            commands::tilemap::fill_tiles,
+            commands::tilemap::undo_tilemap,
+            commands::tilemap::redo_tilemap,
+            commands::tilemap::export_tilemap_image,
+            commands::tilemap::export_tilemap_tiled,
+            commands::metadata::get_project_metadata,
+            commands::metadata::set_nine_slice,
+            commands::metadata::set_slice,
+            commands::metadata::delete_slice,
+            commands::metadata::export_nine_slice,
+            commands::metadata::get_frame_pivot,
+            commands::metadata::set_frame_pivot,
+            commands::metadata::get_slice_pivot,
+            commands::metadata::set_slice_pivot,
+            commands::metadata::get_frame_hitboxes,
+            commands::metadata::set_hitbox,
+            commands::metadata::delete_hitbox,
+            commands::metadata::add_guide,
+            commands::metadata::move_guide,
+            commands::metadata::delete_guide,
+            commands::metadata::clear_guides,
+            commands::metadata::set_guide_snap_threshold,
+            commands::metadata::set_palette_cycle,
+            commands::metadata::delete_palette_cycle,
+            commands::metadata::set_frame_tag,
+            commands::metadata::delete_frame_tag,
+            commands::export::export_sprite_sheet,
+            commands::export::export_viewer,
+            commands::export::get_export_profiles,
+            commands::export::save_export_profile,
+            commands::export::delete_export_profile,
+            commands::export::export_with_profile,
+            commands::export::render_export_preview,
+            commands::export::get_linked_export,
+            commands::export::set_linked_export,
+            commands::export_queue::queue_exports,
+            commands::export_queue::get_export_queue,
+            commands::export_queue::cancel_export,
+            commands::export_queue::clear_finished_exports,
+            commands::export_queue::set_export_concurrency,
+            commands::documents::open_project,
+            commands::documents::create_project_with_defaults,
+            commands::documents::regenerate_thumbnails,
+            commands::documents::compare_versions,
+            commands::documents::save_project,
+            commands::documents::close_project,
+            commands::documents::close_document,
+            commands::documents::get_open_documents,
+            commands::documents::get_idle_unload_minutes,
+            commands::documents::set_idle_unload_minutes,
+            commands::documents::get_pixel_codec,
+            commands::documents::set_pixel_codec,
+            commands::avatars::import_profile_picture,
+            commands::avatars::remove_profile_picture,
+            commands::avatars::get_profile_picture,
+            commands::avatars::clean_up_avatars,
+            commands::thumbnails::get_frame_thumbnail,
+            commands::thumbnails::get_layer_thumbnail,
+            commands::thumbnails::get_project_animation_preview,
+            commands::transform::preview_selection_transform,
+            commands::transform::commit_selection_transform,
+            commands::transform::cancel_selection_transform,
+            commands::ai::preview_background_removal,
+            commands::ai::remove_background,
+            commands::ai::suggest_palette,
+            commands::ai::inpaint_selection,
+            commands::ai::get_prompt_history,
+            commands::ai::delete_prompt_history_entry,
+            commands::ai::rerun_prompt,
         ])
         .setup(|app| {
+            // Logging, recovery and plugins are best-effort: a read-only data dir shouldn't stop the app
+            match app.path().app_data_dir() {
+                Ok(dir) => {
+                    if let Err(e) = aipix_lib::logging::init(&dir.join("logs")) {
+                        eprintln!("Failed to initialize logging: {}", e);
+                    }
+                    match aipix_lib::journal::Journal::open(dir.join("recovery")) {
+                        Ok(journal) => *app.state::<AppState>().journal.lock().unwrap() = Some(journal),
+                        Err(e) => tracing::warn!(error = %e, "crash recovery disabled"),
+                    }
+                    match aipix_lib::plugins::PluginManager::open(dir.join("plugins")) {
+                        Ok(plugins) => *app.state::<AppState>().plugins.lock().unwrap() = Some(plugins),
+                        Err(e) => tracing::warn!(error = %e, "plugins disabled"),
+                    }
+                }
+                Err(e) => eprintln!("No app data directory for logs: {}", e),
+            }
+
+            commands::documents::spawn_idle_unloader(app.handle().clone());
+
+            if std::env::args().any(|arg| arg == aipix_lib::mcp::STDIO_FLAG) {
+                aipix_lib::mcp::transport::run_stdio(app.handle().clone());
+            }
+
+            // Windows and Linux pass opened files and links as arguments; macOS sends events
+            #[cfg(any(windows, target_os = "linux"))]
+            {
+                use tauri_plugin_deep_link::DeepLinkExt;
+                // Installers register the scheme; this covers dev builds and AppImages
+                if let Err(e) = app.deep_link().register_all() {
+                    tracing::warn!(error = %e, "failed to register aipix:// links");
+                }
+                let targets = aipix_lib::deep_link::targets_from_args(std::env::args());
+                aipix_lib::deep_link::open_all(app.handle(), &targets);
+            }
+            #[cfg(target_os = "macos")]
+            {
+                use tauri_plugin_deep_link::DeepLinkExt;
+                let handle = app.handle().clone();
+                app.deep_link().on_open_url(move |event| {
+                    let targets: Vec<_> = event
+                        .urls()
+                        .iter()
+                        .filter_map(|url| aipix_lib::deep_link::parse(url.as_str()))
+                        .collect();
+                    aipix_lib::deep_link::open_all(&handle, &targets);
+                });
+            }
+
             #[cfg(debug_assertions)]
             {
                 let window = app.get_webview_window("main").unwrap();
@@ -0,0 +1,30 @@
+// Model Context Protocol server
+//
+// Lets external AI agents drive the editor: open projects, draw, fill,
+// undo and export. Messages are JSON-RPC 2.0 as specified by MCP; the same
+// dispatcher serves two transports:
+//   stdio      launch the app with `--mcp-stdio`, one message per line
+//   WebSocket  start_mcp_server binds ws://127.0.0.1:<port>, one message per frame;
+//              clients must present the token it returns
+//
+// Tools act on the same open documents as the UI, so edits show up live.
+// export_png only writes inside the directory set with set_mcp_export_dir.
+
+pub mod protocol;
+pub mod tools;
+pub mod transport;
+
+pub use protocol::handle_message;
+pub use transport::ServerHandle;
+
+use crate::AppState;
+use tauri::AppHandle;
+
+/// Command-line flag that serves MCP over stdin/stdout
+pub const STDIO_FLAG: &str = "--mcp-stdio";
+
+/// What a tool call can reach
+pub struct McpContext<'a> {
+    pub state: &'a AppState,
+    pub app: Option<&'a AppHandle>, // Used to notify the UI; None in tests
+}
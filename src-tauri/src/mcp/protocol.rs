@@ -0,0 +1,99 @@
+// JSON-RPC 2.0 framing and the MCP lifecycle methods
+use super::{tools, McpContext};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+/// Protocol revision implemented here
+pub const PROTOCOL_VERSION: &str = "2024-11-05";
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+pub const INVALID_PARAMS: i64 = -32602;
+
+#[derive(Deserialize)]
+struct Request {
+    jsonrpc: String,
+    id: Option<Value>, // Absent for notifications
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+/// A JSON-RPC error returned instead of a result
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+}
+
+impl RpcError {
+    pub fn invalid_params(message: impl Into<String>) -> Self {
+        Self { code: INVALID_PARAMS, message: message.into() }
+    }
+}
+
+fn response(id: Value, result: Result<Value, RpcError>) -> Value {
+    match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(error) => json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "error": { "code": error.code, "message": error.message },
+        }),
+    }
+}
+
+/// Handle one incoming message; returns the serialized reply, if any
+///
+/// Batches are not supported (they were dropped from later MCP revisions).
+pub fn handle_message(ctx: &McpContext, message: &str) -> Option<String> {
+    let value: Value = match serde_json::from_str(message) {
+        Ok(value) => value,
+        Err(e) => {
+            let error = RpcError { code: PARSE_ERROR, message: e.to_string() };
+            return Some(response(Value::Null, Err(error)).to_string());
+        }
+    };
+
+    let request: Request = match serde_json::from_value(value) {
+        Ok(request) => request,
+        Err(e) => {
+            let error = RpcError { code: INVALID_REQUEST, message: e.to_string() };
+            return Some(response(Value::Null, Err(error)).to_string());
+        }
+    };
+    if request.jsonrpc != "2.0" {
+        let error = RpcError { code: INVALID_REQUEST, message: "jsonrpc must be \"2.0\"".to_string() };
+        return Some(response(request.id.unwrap_or(Value::Null), Err(error)).to_string());
+    }
+
+    let result = dispatch(ctx, &request.method, request.params);
+
+    // Notifications never get a reply, not even an error
+    let id = request.id?;
+    Some(response(id, result).to_string())
+}
+
+fn dispatch(ctx: &McpContext, method: &str, params: Value) -> Result<Value, RpcError> {
+    match method {
+        "initialize" => Ok(json!({
+            "protocolVersion": PROTOCOL_VERSION,
+            "capabilities": { "tools": { "listChanged": false } },
+            "serverInfo": { "name": "aipix", "version": env!("CARGO_PKG_VERSION") },
+        })),
+        "notifications/initialized" | "notifications/cancelled" | "ping" => Ok(json!({})),
+        "tools/list" => Ok(json!({ "tools": tools::definitions() })),
+        "tools/call" => {
+            let name = params
+                .get("name")
+                .and_then(Value::as_str)
+                .ok_or_else(|| RpcError::invalid_params("Missing tool name"))?;
+            let arguments = params.get("arguments").cloned().unwrap_or_else(|| json!({}));
+            tools::call(ctx, name, arguments)
+        }
+        _ => Err(RpcError {
+            code: METHOD_NOT_FOUND,
+            message: format!("Method not found: {}", method),
+        }),
+    }
+}
@@ -0,0 +1,608 @@
+// MCP tools
+//
+// Edits are expressed as journal operations and applied through
+// `journal::apply`, so an agent's changes are undoable, crash-recoverable and
+// identical to what the matching editor command does.
+
+use super::protocol::RpcError;
+use super::McpContext;
+use crate::commands::documents;
+use crate::commands::events::{self, Changes};
+use crate::engine::{self, Document, LineProfile};
+use crate::error::{AipixError, Result};
+use crate::fileio;
+use crate::journal::{self, Operation};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// Largest export scale accepted by `export_png`
+const MAX_EXPORT_SCALE: u32 = 8;
+
+fn schema(properties: Value, required: &[&str]) -> Value {
+    json!({ "type": "object", "properties": properties, "required": required })
+}
+
+fn tool(name: &str, description: &str, input_schema: Value) -> Value {
+    json!({ "name": name, "description": description, "inputSchema": input_schema })
+}
+
+/// Tool descriptions returned by `tools/list`
+pub fn definitions() -> Vec<Value> {
+    let project = json!({ "type": "string", "description": "Project id" });
+    let color = json!({ "type": "string", "description": "Hex color, #RRGGBB or #RRGGBBAA" });
+    let int = json!({ "type": "integer" });
+    let flag = json!({ "type": "boolean", "default": false });
+    let thickness = json!({
+        "type": "integer", "minimum": 1, "maximum": engine::tools::MAX_THICKNESS, "default": 1,
+        "description": "Outline width in pixels",
+    });
+
+    vec![
+        tool(
+            "list_projects",
+            "List the projects open in the editor with their canvas sizes.",
+            schema(json!({}), &[]),
+        ),
+        tool(
+            "open_project",
+            "Open a project's canvas; a stored one loads its saved pixels, a new one needs width and height.",
+            schema(
+                json!({ "project_id": project, "width": int, "height": int }),
+                &["project_id"],
+            ),
+        ),
+        tool(
+            "get_canvas",
+            "Return the canvas as a PNG image.",
+            schema(json!({ "project_id": project }), &["project_id"]),
+        ),
+        tool(
+            "get_palette",
+            "Return the project's palette as hex colors.",
+            schema(json!({ "project_id": project }), &["project_id"]),
+        ),
+        tool(
+            "draw_pixels",
+            "Set individual pixels to one color, as a single undoable step.",
+            schema(
+                json!({
+                    "project_id": project,
+                    "color": color,
+                    "pixels": {
+                        "type": "array",
+                        "items": { "type": "array", "items": int, "minItems": 2, "maxItems": 2 },
+                        "description": "[x, y] pairs",
+                    },
+                }),
+                &["project_id", "color", "pixels"],
+            ),
+        ),
+        tool(
+            "draw_line",
+            "Draw a line between two points.",
+            schema(
+                json!({
+                    "project_id": project, "x0": int, "y0": int, "x1": int, "y1": int, "color": color,
+                    "thickness": thickness,
+                    "profile": {
+                        "type": "string", "enum": ["uniform", "tapered"], "default": "uniform",
+                        "description": "tapered narrows the line to one pixel at both ends",
+                    },
+                }),
+                &["project_id", "x0", "y0", "x1", "y1", "color"],
+            ),
+        ),
+        tool(
+            "draw_rectangle",
+            "Draw a rectangle between two corners, outlined or filled.",
+            schema(
+                json!({
+                    "project_id": project, "x0": int, "y0": int, "x1": int, "y1": int,
+                    "color": color, "filled": flag, "thickness": thickness,
+                }),
+                &["project_id", "x0", "y0", "x1", "y1", "color"],
+            ),
+        ),
+        tool(
+            "draw_circle",
+            "Draw a circle from its center to a point on its edge, outlined or filled.",
+            schema(
+                json!({
+                    "project_id": project, "center_x": int, "center_y": int, "end_x": int,
+                    "end_y": int, "color": color, "filled": flag, "thickness": thickness,
+                }),
+                &["project_id", "center_x", "center_y", "end_x", "end_y", "color"],
+            ),
+        ),
+        tool(
+            "draw_ellipse",
+            "Draw an ellipse filling the box between two corners, outlined or filled.",
+            schema(
+                json!({
+                    "project_id": project, "x0": int, "y0": int, "x1": int, "y1": int,
+                    "color": color, "filled": flag, "thickness": thickness,
+                }),
+                &["project_id", "x0", "y0", "x1", "y1", "color"],
+            ),
+        ),
+        tool(
+            "fill",
+            "Flood fill the contiguous area of one color starting at a pixel.",
+            schema(
+                json!({
+                    "project_id": project, "x": int, "y": int, "color": color,
+                    "tolerance": {
+                        "type": "integer", "minimum": 0, "maximum": 255, "default": 0,
+                        "description": "How far a color may differ from the clicked one and still be filled",
+                    },
+                }),
+                &["project_id", "x", "y", "color"],
+            ),
+        ),
+        tool(
+            "undo",
+            "Undo the last step.",
+            schema(json!({ "project_id": project }), &["project_id"]),
+        ),
+        tool(
+            "redo",
+            "Redo the last undone step.",
+            schema(json!({ "project_id": project }), &["project_id"]),
+        ),
+        tool(
+            "export_png",
+            "Write the canvas to a PNG file, optionally scaled up by an integer factor.",
+            schema(
+                json!({
+                    "project_id": project,
+                    "path": { "type": "string", "description": "Path inside the export directory set in the editor" },
+                    "scale": { "type": "integer", "minimum": 1, "maximum": MAX_EXPORT_SCALE, "default": 1 },
+                }),
+                &["project_id", "path"],
+            ),
+        ),
+    ]
+}
+
+#[derive(Deserialize)]
+struct ProjectArgs {
+    project_id: String,
+}
+
+#[derive(Deserialize)]
+struct OpenArgs {
+    project_id: String,
+    width: Option<u32>,
+    height: Option<u32>,
+}
+
+#[derive(Deserialize)]
+struct PixelsArgs {
+    project_id: String,
+    color: String,
+    pixels: Vec<(u32, u32)>,
+}
+
+#[derive(Deserialize)]
+struct LineArgs {
+    project_id: String,
+    x0: i32,
+    y0: i32,
+    x1: i32,
+    y1: i32,
+    color: String,
+    thickness: Option<u32>,
+    #[serde(default)]
+    profile: LineProfile,
+}
+
+#[derive(Deserialize)]
+struct RectangleArgs {
+    project_id: String,
+    x0: u32,
+    y0: u32,
+    x1: u32,
+    y1: u32,
+    color: String,
+    #[serde(default)]
+    filled: bool,
+    thickness: Option<u32>,
+}
+
+#[derive(Deserialize)]
+struct CircleArgs {
+    project_id: String,
+    center_x: i32,
+    center_y: i32,
+    end_x: i32,
+    end_y: i32,
+    color: String,
+    #[serde(default)]
+    filled: bool,
+    thickness: Option<u32>,
+}
+
+#[derive(Deserialize)]
+struct EllipseArgs {
+    project_id: String,
+    x0: i32,
+    y0: i32,
+    x1: i32,
+    y1: i32,
+    color: String,
+    #[serde(default)]
+    filled: bool,
+    thickness: Option<u32>,
+}
+
+#[derive(Deserialize)]
+struct FillArgs {
+    project_id: String,
+    x: u32,
+    y: u32,
+    color: String,
+    #[serde(default)]
+    tolerance: u8,
+}
+
+#[derive(Deserialize)]
+struct ExportArgs {
+    project_id: String,
+    path: String,
+    scale: Option<u32>,
+}
+
+fn text(message: impl Into<String>) -> Value {
+    json!({ "content": [{ "type": "text", "text": message.into() }], "isError": false })
+}
+
+fn tool_error(message: impl Into<String>) -> Value {
+    json!({ "content": [{ "type": "text", "text": message.into() }], "isError": true })
+}
+
+fn parse<T: DeserializeOwned>(arguments: Value) -> Result<T> {
+    serde_json::from_value(arguments)
+        .map_err(|e| AipixError::InvalidInput(format!("Invalid arguments: {}", e)))
+}
+
+/// Run a tool; failures are reported in the result (`isError`) as MCP expects
+pub fn call(ctx: &McpContext, name: &str, arguments: Value) -> Result<Value, RpcError> {
+    let result = match name {
+        "list_projects" => list_projects(ctx),
+        "open_project" => parse(arguments).and_then(|args| open_project(ctx, args)),
+        "get_canvas" => parse(arguments).and_then(|args| get_canvas(ctx, args)),
+        "get_palette" => parse(arguments).and_then(|args| get_palette(ctx, args)),
+        "draw_pixels" => parse(arguments).and_then(|args| draw_pixels(ctx, args)),
+        "draw_line" => parse(arguments).and_then(|args: LineArgs| {
+            let color = engine::tools::hex_to_rgba(&args.color)?;
+            let LineArgs { x0, y0, x1, y1, thickness, profile, .. } = args;
+            let op = Operation::Line { x0, y0, x1, y1, color, thickness: thickness.unwrap_or(1), profile };
+            edit(ctx, &args.project_id, vec![op])
+        }),
+        "draw_rectangle" => parse(arguments).and_then(|args: RectangleArgs| {
+            let color = engine::tools::hex_to_rgba(&args.color)?;
+            let RectangleArgs { x0, y0, x1, y1, filled, thickness, .. } = args;
+            let op = Operation::Rectangle { x0, y0, x1, y1, color, filled, thickness: thickness.unwrap_or(1) };
+            edit(ctx, &args.project_id, vec![op])
+        }),
+        "draw_circle" => parse(arguments).and_then(|args: CircleArgs| {
+            let color = engine::tools::hex_to_rgba(&args.color)?;
+            let CircleArgs { center_x, center_y, end_x, end_y, filled, thickness, .. } = args;
+            let thickness = thickness.unwrap_or(1);
+            let op = Operation::Circle { center_x, center_y, end_x, end_y, color, filled, thickness };
+            edit(ctx, &args.project_id, vec![op])
+        }),
+        "draw_ellipse" => parse(arguments).and_then(|args: EllipseArgs| {
+            let color = engine::tools::hex_to_rgba(&args.color)?;
+            let EllipseArgs { x0, y0, x1, y1, filled, thickness, .. } = args;
+            let op = Operation::Ellipse { x0, y0, x1, y1, color, filled, thickness: thickness.unwrap_or(1) };
+            edit(ctx, &args.project_id, vec![op])
+        }),
+        "fill" => parse(arguments).and_then(|args: FillArgs| {
+            let color = engine::tools::hex_to_rgba(&args.color)?;
+            let op = Operation::Fill { x: args.x, y: args.y, color, tolerance: args.tolerance };
+            edit(ctx, &args.project_id, vec![op])
+        }),
+        "undo" => parse(arguments).and_then(|args: ProjectArgs| history(ctx, &args.project_id, Operation::Undo)),
+        "redo" => parse(arguments).and_then(|args: ProjectArgs| history(ctx, &args.project_id, Operation::Redo)),
+        "export_png" => parse(arguments).and_then(|args| export_png(ctx, args)),
+        _ => return Err(RpcError::invalid_params(format!("Unknown tool: {}", name))),
+    };
+
+    Ok(result.unwrap_or_else(|e| tool_error(e.to_string())))
+}
+
+fn document(ctx: &McpContext, project_id: &str) -> Result<Arc<Mutex<Document>>> {
+    ctx.state.document(project_id)
+}
+
+fn emit(ctx: &McpContext, project_id: &str, document: &Document, changes: Changes) {
+    if let Some(app) = ctx.app {
+        events::emit_changes(app, project_id, document, changes);
+    }
+}
+
+fn size_text(project_id: &str, document: &Document) -> String {
+    format!(
+        "{}: {}x{}",
+        project_id, document.history.buffer.width, document.history.buffer.height
+    )
+}
+
+/// Apply `ops` as one undoable step
+fn edit(ctx: &McpContext, project_id: &str, ops: Vec<Operation>) -> Result<Value> {
+    let handle = document(ctx, project_id)?;
+    let mut document = handle.lock().unwrap();
+
+    let count = ops.len();
+    for op in journal::apply_batch(&mut document, ops, None)? {
+        ctx.state.record(project_id, &document, op);
+    }
+
+    emit(ctx, project_id, &document, Changes::EDIT);
+    Ok(text(format!("Applied {} operation(s) to {}", count, project_id)))
+}
+
+fn history(ctx: &McpContext, project_id: &str, op: Operation) -> Result<Value> {
+    let handle = document(ctx, project_id)?;
+    let mut document = handle.lock().unwrap();
+
+    journal::apply(&mut document, &op)?;
+    ctx.state.record(project_id, &document, op);
+
+    emit(ctx, project_id, &document, Changes::EDIT);
+    Ok(text(format!(
+        "Undo steps: {}, redo steps: {}",
+        document.history.undo_count(),
+        document.history.redo_count()
+    )))
+}
+
+fn list_projects(ctx: &McpContext) -> Result<Value> {
+    let handles: Vec<_> = ctx
+        .state
+        .documents
+        .read()
+        .unwrap()
+        .iter()
+        .map(|(id, handle)| (id.clone(), handle.clone()))
+        .collect();
+
+    let mut lines: Vec<String> = handles
+        .iter()
+        .map(|(id, handle)| size_text(id, &handle.lock().unwrap()))
+        .collect();
+    lines.sort();
+
+    if lines.is_empty() {
+        return Ok(text("No projects are open"));
+    }
+    Ok(text(lines.join("\n")))
+}
+
+fn open_project(ctx: &McpContext, args: OpenArgs) -> Result<Value> {
+    if let Ok(handle) = document(ctx, &args.project_id) {
+        return Ok(text(size_text(&args.project_id, &handle.lock().unwrap())));
+    }
+
+    // A stored project opens with its saved canvas, never as a blank one
+    // that closing or unloading would later save over it
+    let stored = match ctx.state.db.lock().unwrap().as_ref() {
+        Some(db) => db.get_project(&args.project_id)?,
+        None => None,
+    };
+    if let Some(project) = stored {
+        let handle = documents::load_document(ctx.state, &project)?;
+        let document = handle.lock().unwrap();
+        ctx.state.checkpoint(&args.project_id, &document);
+        emit(ctx, &args.project_id, &document, Changes::ALL);
+        return Ok(text(size_text(&args.project_id, &document)));
+    }
+
+    let (Some(width), Some(height)) = (args.width, args.height) else {
+        return Err(AipixError::InvalidInput(format!(
+            "{} is not open or stored; pass width and height to create it",
+            args.project_id
+        )));
+    };
+    ctx.state.validate_canvas_size(width, height)?;
+
+    let handle = ctx
+        .state
+        .documents
+        .write()
+        .unwrap()
+        .entry(args.project_id.clone())
+        .or_insert_with(|| Arc::new(Mutex::new(Document::new(width, height))))
+        .clone();
+
+    let document = handle.lock().unwrap();
+    ctx.state.checkpoint(&args.project_id, &document);
+    emit(ctx, &args.project_id, &document, Changes::ALL);
+    Ok(text(size_text(&args.project_id, &document)))
+}
+
+fn get_canvas(ctx: &McpContext, args: ProjectArgs) -> Result<Value> {
+    let buffer = document(ctx, &args.project_id)?.lock().unwrap().history.buffer.clone();
+    let png = fileio::encode_png(&buffer)?;
+
+    Ok(json!({
+        "content": [
+            { "type": "image", "data": STANDARD.encode(png), "mimeType": "image/png" },
+            { "type": "text", "text": format!("{}x{}", buffer.width, buffer.height) },
+        ],
+        "isError": false,
+    }))
+}
+
+fn get_palette(ctx: &McpContext, args: ProjectArgs) -> Result<Value> {
+    let colors: Vec<String> = document(ctx, &args.project_id)?
+        .lock()
+        .unwrap()
+        .history
+        .palette
+        .colors
+        .iter()
+        .map(|c| engine::tools::rgba_to_hex(*c))
+        .collect();
+
+    if colors.is_empty() {
+        return Ok(text("The palette is empty"));
+    }
+    Ok(text(colors.join(" ")))
+}
+
+fn draw_pixels(ctx: &McpContext, args: PixelsArgs) -> Result<Value> {
+    let color = engine::tools::hex_to_rgba(&args.color)?;
+    let ops = args
+        .pixels
+        .iter()
+        .map(|&(x, y)| Operation::Pencil { x, y, color })
+        .collect();
+    edit(ctx, &args.project_id, ops)
+}
+
+fn export_png(ctx: &McpContext, args: ExportArgs) -> Result<Value> {
+    let scale = args.scale.unwrap_or(1);
+    if scale == 0 || scale > MAX_EXPORT_SCALE {
+        return Err(AipixError::InvalidInput(format!(
+            "Scale must be between 1 and {}",
+            MAX_EXPORT_SCALE
+        )));
+    }
+
+    let dir = ctx.state.mcp_export_dir.lock().unwrap().clone().ok_or_else(|| {
+        AipixError::InvalidState("No MCP export directory is set; choose one in the editor".to_string())
+    })?;
+    let path = export_path(&dir, &args.path)?;
+
+    let buffer = document(ctx, &args.project_id)?.lock().unwrap().history.buffer.clone();
+    let scaled = engine::upscale::nearest(&buffer, scale);
+    let image = fileio::buffer_to_image(&scaled)?;
+    fileio::save_image(&path, &image)?;
+
+    Ok(text(format!(
+        "Exported {}x{} PNG to {}",
+        scaled.width,
+        scaled.height,
+        path.display()
+    )))
+}
+
+/// Where export_png writes `requested`, which must lie inside `dir`
+///
+/// Relative paths start from `dir`. The parent directory has to exist and is
+/// resolved with `..` and symlinks followed, so neither can lead outside.
+fn export_path(dir: &Path, requested: &str) -> Result<PathBuf> {
+    let dir = dir.canonicalize()?;
+    let joined = dir.join(requested);
+    let (Some(parent), Some(name)) = (joined.parent(), joined.file_name()) else {
+        return Err(AipixError::InvalidInput("Export path must name a file".to_string()));
+    };
+
+    let path = parent.canonicalize()?.join(name);
+    if !path.starts_with(&dir) || path.is_symlink() {
+        return Err(AipixError::InvalidInput(format!(
+            "Export path must be inside {}",
+            dir.display()
+        )));
+    }
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mcp::handle_message;
+    use crate::AppState;
+
+    fn request(ctx: &McpContext, id: u32, method: &str, params: Value) -> Value {
+        let message = json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params });
+        serde_json::from_str(&handle_message(ctx, &message.to_string()).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_draw_through_tools() {
+        let state = AppState::default();
+        let ctx = McpContext { state: &state, app: None };
+
+        let init = request(&ctx, 1, "initialize", json!({}));
+        assert_eq!(init["result"]["serverInfo"]["name"], "aipix");
+
+        let call = |id, name: &str, arguments: Value| {
+            request(&ctx, id, "tools/call", json!({ "name": name, "arguments": arguments }))
+        };
+        let opened = call(2, "open_project", json!({ "project_id": "p", "width": 8, "height": 8 }));
+        assert_eq!(opened["result"]["isError"], false);
+
+        let drawn = call(3, "draw_pixels", json!({ "project_id": "p", "color": "#ff0000", "pixels": [[1, 1], [2, 2]] }));
+        assert_eq!(drawn["result"]["isError"], false);
+
+        let buffer = state.document("p").unwrap().lock().unwrap().history.buffer.clone();
+        assert_eq!(buffer.get_pixel(2, 2), Some([255, 0, 0, 255]));
+
+        // One failing pixel rejects the whole step
+        let failed = call(4, "draw_pixels", json!({ "project_id": "p", "color": "#00ff00", "pixels": [[0, 0], [99, 0]] }));
+        assert_eq!(failed["result"]["isError"], true);
+        let buffer = state.document("p").unwrap().lock().unwrap().history.buffer.clone();
+        assert_eq!(buffer.get_pixel(0, 0), Some([0, 0, 0, 0]));
+
+        let undone = call(5, "undo", json!({ "project_id": "p" }));
+        assert_eq!(undone["result"]["isError"], false);
+        let buffer = state.document("p").unwrap().lock().unwrap().history.buffer.clone();
+        assert_eq!(buffer.get_pixel(2, 2), Some([0, 0, 0, 0]));
+    }
+
+    #[test]
+    fn test_export_path() {
+        let dir = std::env::temp_dir().join(format!("aipix-mcp-export-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(dir.join("sprites")).unwrap();
+        let root = dir.canonicalize().unwrap();
+
+        assert_eq!(export_path(&dir, "hero.png").unwrap(), root.join("hero.png"));
+        assert_eq!(export_path(&dir, "sprites/hero.png").unwrap(), root.join("sprites/hero.png"));
+        assert_eq!(export_path(&dir, root.join("a.png").to_str().unwrap()).unwrap(), root.join("a.png"));
+        assert!(export_path(&dir, "../hero.png").is_err());
+        assert!(export_path(&dir, "sprites/../../hero.png").is_err());
+        assert!(export_path(&dir, "/tmp/hero.png").is_err());
+        assert!(export_path(&dir, "missing/hero.png").is_err());
+        assert!(export_path(&dir, "sprites/..").is_err());
+
+        // Exporting is refused until the user picks a directory
+        let state = AppState::default();
+        let ctx = McpContext { state: &state, app: None };
+        let call = |id, name: &str, arguments: Value| {
+            request(&ctx, id, "tools/call", json!({ "name": name, "arguments": arguments }))
+        };
+        call(1, "open_project", json!({ "project_id": "p", "width": 4, "height": 4 }));
+        let refused = call(2, "export_png", json!({ "project_id": "p", "path": "p.png" }));
+        assert_eq!(refused["result"]["isError"], true);
+
+        *state.mcp_export_dir.lock().unwrap() = Some(dir.clone());
+        let exported = call(3, "export_png", json!({ "project_id": "p", "path": "p.png" }));
+        assert_eq!(exported["result"]["isError"], false);
+        assert!(root.join("p.png").is_file());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_protocol_errors() {
+        let state = AppState::default();
+        let ctx = McpContext { state: &state, app: None };
+
+        let unknown = request(&ctx, 1, "resources/list", json!({}));
+        assert_eq!(unknown["error"]["code"], -32601);
+
+        let parse_error: Value = serde_json::from_str(&handle_message(&ctx, "{").unwrap()).unwrap();
+        assert_eq!(parse_error["error"]["code"], -32700);
+
+        // Notifications get no reply
+        let notification = json!({ "jsonrpc": "2.0", "method": "notifications/initialized" });
+        assert!(handle_message(&ctx, &notification.to_string()).is_none());
+    }
+}
@@ -0,0 +1,183 @@
+// MCP transports
+//
+// Both transports hand each message to `handle_message` on a blocking
+// thread, since tools lock documents and may encode whole canvases.
+//
+// The WebSocket server only accepts handshakes carrying the token generated
+// when it starts, the same way the HTTP API does (`Authorization: Bearer`
+// or `?token=`), and refuses any with an Origin header: browsers always
+// send one, MCP clients don't, and localhost alone is reachable from every
+// web page the user opens.
+
+use super::{handle_message, McpContext};
+use crate::error::Result;
+use crate::http_api::request_token;
+use crate::AppState;
+use futures_util::{SinkExt, StreamExt};
+use std::io::{BufRead, Write};
+use std::net::{Ipv4Addr, SocketAddr, TcpListener};
+use tauri::{AppHandle, Manager};
+use std::sync::Arc;
+use tokio::sync::oneshot;
+use tokio_tungstenite::tungstenite::handshake::server::{ErrorResponse, Request, Response};
+use tokio_tungstenite::tungstenite::http::{header, StatusCode};
+use tokio_tungstenite::tungstenite::Message;
+
+fn handle(app: &AppHandle, message: &str) -> Option<String> {
+    let state = app.state::<AppState>();
+    let ctx = McpContext { state: &state, app: Some(app) };
+    handle_message(&ctx, message)
+}
+
+/// Serve MCP over stdin/stdout until stdin closes
+///
+/// stdout carries protocol messages only; logs go to the log file.
+pub fn run_stdio(app: AppHandle) {
+    std::thread::spawn(move || {
+        let stdin = std::io::stdin();
+        let mut stdout = std::io::stdout();
+
+        for line in stdin.lock().lines() {
+            let Ok(line) = line else { break };
+            if line.trim().is_empty() {
+                continue;
+            }
+            let Some(reply) = handle(&app, &line) else { continue };
+            if writeln!(stdout, "{}", reply).and_then(|_| stdout.flush()).is_err() {
+                break;
+            }
+        }
+        tracing::info!("mcp stdio closed");
+    });
+}
+
+/// A running WebSocket server; dropping the handle does not stop it, `stop` does
+pub struct ServerHandle {
+    pub addr: SocketAddr,
+    pub token: String,
+    shutdown: oneshot::Sender<()>,
+}
+
+impl ServerHandle {
+    pub fn stop(self) {
+        let _ = self.shutdown.send(());
+    }
+}
+
+/// Start serving MCP over WebSocket on localhost; port 0 picks a free port
+pub fn start(app: AppHandle, port: u16) -> Result<ServerHandle> {
+    // Bind synchronously so address-in-use errors reach the caller
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))?;
+    listener.set_nonblocking(true)?;
+    let addr = listener.local_addr()?;
+    let token = uuid::Uuid::new_v4().simple().to_string();
+    let accepted_token: Arc<str> = Arc::from(token.as_str());
+    let (shutdown, mut stopped) = oneshot::channel();
+
+    tauri::async_runtime::spawn(async move {
+        let listener = match tokio::net::TcpListener::from_std(listener) {
+            Ok(listener) => listener,
+            Err(e) => {
+                tracing::error!(error = %e, "mcp server failed to start");
+                return;
+            }
+        };
+        tracing::info!(%addr, "mcp server listening");
+
+        loop {
+            tokio::select! {
+                _ = &mut stopped => break,
+                accepted = listener.accept() => match accepted {
+                    Ok((stream, peer)) => {
+                        let token = accepted_token.clone();
+                        tauri::async_runtime::spawn(serve_connection(app.clone(), stream, peer, token));
+                    }
+                    Err(e) => tracing::warn!(error = %e, "mcp accept failed"),
+                },
+            }
+        }
+        tracing::info!(%addr, "mcp server stopped");
+    });
+
+    Ok(ServerHandle { addr, token, shutdown })
+}
+
+/// Refuse handshakes from web pages and ones without the server's token
+fn check_handshake(request: &Request, token: &str) -> std::result::Result<(), (StatusCode, &'static str)> {
+    if request.headers().contains_key(header::ORIGIN) {
+        return Err((StatusCode::FORBIDDEN, "Browser origins may not connect"));
+    }
+    if request_token(request) != Some(token) {
+        return Err((StatusCode::UNAUTHORIZED, "Missing or invalid MCP token"));
+    }
+    Ok(())
+}
+
+async fn serve_connection(app: AppHandle, stream: tokio::net::TcpStream, peer: SocketAddr, token: Arc<str>) {
+    #[allow(clippy::result_large_err)] // tungstenite's callback signature, not ours
+    let authorize = |request: &Request, response: Response| {
+        check_handshake(request, &token).map(|()| response).map_err(|(status, reason)| {
+            tracing::warn!(%peer, %status, "mcp handshake refused");
+            let mut refused = ErrorResponse::new(Some(reason.to_string()));
+            *refused.status_mut() = status;
+            refused
+        })
+    };
+    let mut socket = match tokio_tungstenite::accept_hdr_async(stream, authorize).await {
+        Ok(socket) => socket,
+        Err(e) => {
+            tracing::warn!(%peer, error = %e, "mcp handshake failed");
+            return;
+        }
+    };
+    tracing::info!(%peer, "mcp client connected");
+
+    while let Some(message) = socket.next().await {
+        let text = match message {
+            Ok(Message::Text(text)) => text.to_string(),
+            Ok(Message::Close(_)) | Err(_) => break,
+            Ok(_) => continue, // Pings are answered by tungstenite; binary frames aren't MCP
+        };
+
+        let app = app.clone();
+        let reply = match tauri::async_runtime::spawn_blocking(move || handle(&app, &text)).await {
+            Ok(reply) => reply,
+            Err(e) => {
+                tracing::error!(%peer, error = %e, "mcp handler panicked");
+                break;
+            }
+        };
+        if let Some(reply) = reply {
+            if socket.send(Message::Text(reply.into())).await.is_err() {
+                break;
+            }
+        }
+    }
+    tracing::info!(%peer, "mcp client disconnected");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn handshake(uri: &str, headers: &[(header::HeaderName, &str)]) -> Request {
+        let mut request = Request::builder().uri(uri);
+        for (name, value) in headers {
+            request = request.header(name, *value);
+        }
+        request.body(()).unwrap()
+    }
+
+    #[test]
+    fn test_check_handshake() {
+        assert!(check_handshake(&handshake("/", &[(header::AUTHORIZATION, "Bearer abc")]), "abc").is_ok());
+        assert!(check_handshake(&handshake("/?token=abc", &[]), "abc").is_ok());
+
+        let refused = |request: Request| check_handshake(&request, "abc").unwrap_err().0;
+        assert_eq!(refused(handshake("/", &[])), StatusCode::UNAUTHORIZED);
+        assert_eq!(refused(handshake("/?token=abd", &[])), StatusCode::UNAUTHORIZED);
+        // A page that learned the token still can't connect from the browser
+        let from_page = handshake("/?token=abc", &[(header::ORIGIN, "https://example.com")]);
+        assert_eq!(refused(from_page), StatusCode::FORBIDDEN);
+    }
+}
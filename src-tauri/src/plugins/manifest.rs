@@ -0,0 +1,120 @@
+// Plugin manifest (`plugin.json`)
+use crate::error::{AipixError, Result};
+use serde::{Deserialize, Serialize};
+
+/// Host API version implemented by this build
+pub const API_VERSION: u32 = 1;
+
+/// Kinds of functionality a plugin can provide
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Capability {
+    Filter,   // Rewrites the whole canvas
+    Tool,     // Applies at a canvas position with the current color
+    Importer, // Turns file bytes into an image
+    Exporter, // Turns an image into file bytes
+}
+
+impl Capability {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Capability::Filter => "filter",
+            Capability::Tool => "tool",
+            Capability::Importer => "importer",
+            Capability::Exporter => "exporter",
+        }
+    }
+}
+
+/// Host functions a plugin may ask for; each must be granted by the user
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Permission {
+    Log,       // aipix.log
+    Palette,   // aipix.palette_len / aipix.palette_get
+    Selection, // aipix.is_selected
+}
+
+impl Permission {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Permission::Log => "log",
+            Permission::Palette => "palette",
+            Permission::Selection => "selection",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PluginManifest {
+    pub id: String,
+    pub name: String,
+    pub version: String,
+    #[serde(default)]
+    pub description: String,
+    pub api_version: u32,
+    pub entry: String, // .wasm file, relative to the plugin directory
+    pub capabilities: Vec<Capability>,
+    #[serde(default)]
+    pub permissions: Vec<Permission>,
+    #[serde(default)]
+    pub file_extensions: Vec<String>, // For importers/exporters, without the dot
+}
+
+impl PluginManifest {
+    pub fn validate(&self) -> Result<()> {
+        let valid_id = !self.id.is_empty()
+            && self
+                .id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_'));
+        if !valid_id {
+            return Err(AipixError::InvalidInput(format!("Invalid plugin id: {}", self.id)));
+        }
+        if self.api_version != API_VERSION {
+            return Err(AipixError::InvalidInput(format!(
+                "Plugin {} targets API version {}, this build supports {}",
+                self.id, self.api_version, API_VERSION
+            )));
+        }
+        // Keep the entry inside the plugin directory
+        let entry = std::path::Path::new(&self.entry);
+        if entry.is_absolute() || entry.components().any(|c| matches!(c, std::path::Component::ParentDir)) {
+            return Err(AipixError::InvalidInput(format!("Invalid plugin entry: {}", self.entry)));
+        }
+        if self.capabilities.is_empty() {
+            return Err(AipixError::InvalidInput(format!(
+                "Plugin {} declares no capabilities",
+                self.id
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        let mut manifest: PluginManifest = serde_json::from_value(serde_json::json!({
+            "id": "com.example.invert",
+            "name": "Invert",
+            "version": "1.0.0",
+            "api_version": 1,
+            "entry": "invert.wasm",
+            "capabilities": ["filter"],
+            "permissions": ["log"],
+        }))
+        .unwrap();
+        assert!(manifest.validate().is_ok());
+
+        manifest.entry = "../escape.wasm".to_string();
+        assert!(manifest.validate().is_err());
+
+        manifest.entry = "invert.wasm".to_string();
+        manifest.api_version = 2;
+        assert!(manifest.validate().is_err());
+    }
+}
@@ -0,0 +1,198 @@
+// WASM plugins
+//
+// Plugins live in `<app data>/plugins/<dir>/`, each with a `plugin.json`
+// manifest next to its `.wasm` module. They are discovered on every listing,
+// start disabled, and only get the host functions whose permissions the user
+// granted. See `runtime` for the host API a module implements.
+
+pub mod manifest;
+pub mod runtime;
+
+pub use manifest::{Capability, Permission, PluginManifest};
+
+use crate::error::{AipixError, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+const MANIFEST_FILE: &str = "plugin.json";
+const SETTINGS_FILE: &str = "plugins.json";
+
+/// What the user chose for a plugin (persisted across discovery)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PluginSettings {
+    pub enabled: bool,
+    pub granted: Vec<Permission>,
+}
+
+/// A discovered plugin as shown in the plugin manager
+#[derive(Debug, Clone, Serialize)]
+pub struct PluginInfo {
+    pub manifest: PluginManifest,
+    pub enabled: bool,
+    pub granted: Vec<Permission>,
+    pub path: PathBuf,
+}
+
+/// A plugin directory that couldn't be loaded
+#[derive(Debug, Clone, Serialize)]
+pub struct PluginLoadError {
+    pub path: PathBuf,
+    pub error: String,
+}
+
+/// Everything needed to run an enabled plugin
+#[derive(Debug, Clone)]
+pub struct LoadedPlugin {
+    pub manifest: PluginManifest,
+    pub wasm: PathBuf,
+    pub granted: Vec<Permission>,
+}
+
+pub struct PluginManager {
+    dir: PathBuf,
+    plugins: HashMap<String, (PluginManifest, PathBuf)>, // id -> manifest, plugin directory
+    settings: HashMap<String, PluginSettings>,
+}
+
+impl PluginManager {
+    pub fn open(dir: PathBuf) -> Result<Self> {
+        std::fs::create_dir_all(&dir)?;
+        let settings = match std::fs::read(dir.join(SETTINGS_FILE)) {
+            Ok(bytes) => serde_json::from_slice(&bytes)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut manager = Self { dir, plugins: HashMap::new(), settings };
+        manager.discover();
+        Ok(manager)
+    }
+
+    /// Rescan the plugin directory; returns the directories that failed to load
+    pub fn discover(&mut self) -> Vec<PluginLoadError> {
+        self.plugins.clear();
+        let mut errors = Vec::new();
+
+        let entries = match std::fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) => {
+                errors.push(PluginLoadError { path: self.dir.clone(), error: e.to_string() });
+                return errors;
+            }
+        };
+
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if !path.is_dir() {
+                continue;
+            }
+            match load_manifest(&path) {
+                Ok(manifest) if self.plugins.contains_key(&manifest.id) => {
+                    errors.push(PluginLoadError {
+                        path,
+                        error: format!("Duplicate plugin id {}", manifest.id),
+                    });
+                }
+                Ok(manifest) => {
+                    self.plugins.insert(manifest.id.clone(), (manifest, path));
+                }
+                Err(e) => {
+                    tracing::warn!(path = %path.display(), error = %e, "skipping plugin");
+                    errors.push(PluginLoadError { path, error: e.to_string() });
+                }
+            }
+        }
+        errors
+    }
+
+    pub fn list(&self) -> Vec<PluginInfo> {
+        let mut plugins: Vec<PluginInfo> = self
+            .plugins
+            .values()
+            .map(|(manifest, path)| {
+                let settings = self.settings.get(&manifest.id).cloned().unwrap_or_default();
+                PluginInfo {
+                    manifest: manifest.clone(),
+                    enabled: settings.enabled,
+                    granted: settings.granted,
+                    path: path.clone(),
+                }
+            })
+            .collect();
+        plugins.sort_by(|a, b| a.manifest.name.cmp(&b.manifest.name));
+        plugins
+    }
+
+    fn manifest(&self, plugin_id: &str) -> Result<&PluginManifest> {
+        self.plugins
+            .get(plugin_id)
+            .map(|(manifest, _)| manifest)
+            .ok_or(AipixError::NotFound("Plugin"))
+    }
+
+    pub fn set_enabled(&mut self, plugin_id: &str, enabled: bool) -> Result<()> {
+        self.manifest(plugin_id)?;
+        self.settings.entry(plugin_id.to_string()).or_default().enabled = enabled;
+        self.save_settings()
+    }
+
+    /// Replace the granted permissions; only permissions the manifest asks for can be granted
+    pub fn set_permissions(&mut self, plugin_id: &str, permissions: Vec<Permission>) -> Result<()> {
+        let manifest = self.manifest(plugin_id)?;
+        if let Some(extra) = permissions.iter().find(|p| !manifest.permissions.contains(p)) {
+            return Err(AipixError::InvalidInput(format!(
+                "Plugin {} does not request the {} permission",
+                plugin_id,
+                extra.name()
+            )));
+        }
+
+        self.settings.entry(plugin_id.to_string()).or_default().granted = permissions;
+        self.save_settings()
+    }
+
+    /// Look up an enabled plugin offering `capability`
+    pub fn load(&self, plugin_id: &str, capability: Capability) -> Result<LoadedPlugin> {
+        let (manifest, path) = self.plugins.get(plugin_id).ok_or(AipixError::NotFound("Plugin"))?;
+        let settings = self.settings.get(plugin_id).cloned().unwrap_or_default();
+
+        if !settings.enabled {
+            return Err(AipixError::InvalidState(format!("Plugin {} is disabled", manifest.name)));
+        }
+        if !manifest.capabilities.contains(&capability) {
+            return Err(AipixError::InvalidInput(format!(
+                "Plugin {} does not provide a {}",
+                manifest.name,
+                capability.name()
+            )));
+        }
+
+        Ok(LoadedPlugin {
+            manifest: manifest.clone(),
+            wasm: path.join(&manifest.entry),
+            granted: settings.granted,
+        })
+    }
+
+    fn save_settings(&self) -> Result<()> {
+        let temp = self.dir.join(format!("{}.tmp", SETTINGS_FILE));
+        std::fs::write(&temp, serde_json::to_vec_pretty(&self.settings)?)?;
+        std::fs::rename(temp, self.dir.join(SETTINGS_FILE))?;
+        Ok(())
+    }
+}
+
+fn load_manifest(dir: &Path) -> Result<PluginManifest> {
+    let bytes = std::fs::read(dir.join(MANIFEST_FILE))?;
+    let manifest: PluginManifest = serde_json::from_slice(&bytes)?;
+    manifest.validate()?;
+
+    if !dir.join(&manifest.entry).is_file() {
+        return Err(AipixError::InvalidInput(format!(
+            "Plugin entry {} not found",
+            manifest.entry
+        )));
+    }
+    Ok(manifest)
+}
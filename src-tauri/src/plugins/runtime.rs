@@ -0,0 +1,431 @@
+// Plugin runtime (host API version 1)
+//
+// Modules run in the wasmi interpreter with a fuel budget and a memory cap,
+// so a misbehaving plugin fails its call instead of hanging or exhausting
+// the app. All pointers are offsets into the module's exported `memory`.
+//
+// Every module exports:
+//   aipix_alloc(len) -> ptr                    buffer the host copies input into
+// and, per capability:
+//   aipix_filter(pixels, w, h, params, params_len) -> status   RGBA edited in place
+//   aipix_tool(pixels, w, h, x, y, rgba) -> status             RGBA edited in place
+//   aipix_import(data, len) -> ptr             ptr to [w: u32 LE][h: u32 LE][RGBA], 0 on failure
+//   aipix_export(pixels, w, h) -> ptr          ptr to [len: u32 LE][bytes], 0 on failure
+// A non-zero status is reported as a plugin error. `params` is UTF-8 JSON and
+// colors are packed as 0xRRGGBBAA.
+//
+// Host functions (module "aipix"), each behind a permission:
+//   log(level, ptr, len)        Log       0 = error, 1 = warn, 2 = info, 3+ = debug
+//   palette_len() -> i32        Palette
+//   palette_get(i) -> rgba      Palette   0 when out of range
+//   is_selected(x, y) -> i32    Selection 1 everywhere when nothing is selected
+
+use super::manifest::Permission;
+use super::LoadedPlugin;
+use crate::engine::{PixelBuffer, Selection};
+use crate::error::{AipixError, Result};
+use wasmi::{
+    Caller, Config, Engine, Extern, Linker, Memory, Module, Store, StoreLimits,
+    StoreLimitsBuilder, TypedFunc, WasmParams, WasmResults,
+};
+
+/// Instructions (roughly) a single plugin call may execute
+const FUEL_LIMIT: u64 = 20_000_000_000;
+
+/// Fuel for a tool call, which the user waits on with every click
+const TOOL_FUEL_LIMIT: u64 = 500_000_000;
+
+/// Largest linear memory a plugin may grow to
+const MEMORY_LIMIT: usize = 1 << 30;
+
+/// Longest message accepted by `aipix.log`
+const MAX_LOG_BYTES: usize = 4096;
+
+/// Document state exposed to host functions
+#[derive(Debug, Clone, Default)]
+pub struct HostContext {
+    pub palette: Vec<[u8; 4]>,
+    pub selection: Option<Selection>,
+}
+
+struct HostState {
+    plugin_id: String,
+    context: HostContext,
+    limits: StoreLimits,
+}
+
+/// Permission a host import needs, or None for an unknown import
+fn import_permission(module: &str, name: &str) -> Option<Permission> {
+    match (module, name) {
+        ("aipix", "log") => Some(Permission::Log),
+        ("aipix", "palette_len") | ("aipix", "palette_get") => Some(Permission::Palette),
+        ("aipix", "is_selected") => Some(Permission::Selection),
+        _ => None,
+    }
+}
+
+fn pack_color(color: [u8; 4]) -> i32 {
+    u32::from_be_bytes(color) as i32
+}
+
+struct Instance {
+    name: String,
+    store: Store<HostState>,
+    instance: wasmi::Instance,
+    memory: Memory,
+}
+
+impl Instance {
+    fn new(plugin: &LoadedPlugin, context: HostContext, fuel: u64) -> Result<Self> {
+        let name = plugin.manifest.name.clone();
+        let error = |e: wasmi::Error| AipixError::PluginError(format!("{}: {}", name, e));
+
+        let bytes = std::fs::read(&plugin.wasm)?;
+        let mut config = Config::default();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config);
+        let module = Module::new(&engine, &bytes).map_err(error)?;
+
+        // Refuse up front rather than failing at the first call of an ungranted import
+        for import in module.imports() {
+            let permission = import_permission(import.module(), import.name()).ok_or_else(|| {
+                AipixError::PluginError(format!(
+                    "{} imports unknown host function {}.{}",
+                    name,
+                    import.module(),
+                    import.name()
+                ))
+            })?;
+            if !plugin.granted.contains(&permission) {
+                return Err(AipixError::InvalidState(format!(
+                    "Plugin {} needs the {} permission",
+                    name,
+                    permission.name()
+                )));
+            }
+        }
+
+        let mut store = Store::new(
+            &engine,
+            HostState {
+                plugin_id: plugin.manifest.id.clone(),
+                context,
+                limits: StoreLimitsBuilder::new().memory_size(MEMORY_LIMIT).build(),
+            },
+        );
+        store.limiter(|state| &mut state.limits);
+        store
+            .set_fuel(fuel)
+            .map_err(|e| AipixError::Internal(e.to_string()))?;
+
+        let mut linker = Linker::new(&engine);
+        define_host_functions(&mut linker).map_err(|e| AipixError::Internal(e.to_string()))?;
+
+        let instance = linker
+            .instantiate(&mut store, &module)
+            .and_then(|pre| pre.start(&mut store))
+            .map_err(error)?;
+        let memory = instance.get_memory(&store, "memory").ok_or_else(|| {
+            AipixError::PluginError(format!("{} does not export its memory", name))
+        })?;
+
+        Ok(Self { name, store, instance, memory })
+    }
+
+    fn call<P: WasmParams, R: WasmResults>(&mut self, export: &str, params: P) -> Result<R> {
+        let func: TypedFunc<P, R> = self
+            .instance
+            .get_typed_func(&self.store, export)
+            .map_err(|_| {
+                AipixError::PluginError(format!("{} does not export {}", self.name, export))
+            })?;
+        func.call(&mut self.store, params)
+            .map_err(|e| AipixError::PluginError(format!("{}: {}", self.name, e)))
+    }
+
+    fn check_status(&self, export: &str, status: i32) -> Result<()> {
+        if status != 0 {
+            return Err(AipixError::PluginError(format!(
+                "{}: {} failed with status {}",
+                self.name, export, status
+            )));
+        }
+        Ok(())
+    }
+
+    /// Copy `bytes` into plugin memory, returning their offset
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<i32> {
+        let len = i32::try_from(bytes.len())
+            .map_err(|_| AipixError::InvalidInput("Input too large for a plugin".to_string()))?;
+        let ptr: i32 = self.call("aipix_alloc", len)?;
+        self.memory
+            .write(&mut self.store, ptr as u32 as usize, bytes)
+            .map_err(|e| AipixError::PluginError(format!("{}: {}", self.name, e)))?;
+        Ok(ptr)
+    }
+
+    /// Copy `len` bytes out of plugin memory; `ptr` and `len` come from the
+    /// plugin, so they are checked against its memory before anything is allocated
+    fn read_bytes(&self, ptr: i32, len: usize) -> Result<Vec<u8>> {
+        let start = ptr as u32 as usize;
+        start
+            .checked_add(len)
+            .and_then(|end| self.memory.data(&self.store).get(start..end))
+            .map(<[u8]>::to_vec)
+            .ok_or_else(|| AipixError::PluginError(format!("{} pointed outside its memory", self.name)))
+    }
+
+    fn read_u32(&self, ptr: i32) -> Result<u32> {
+        let bytes = self.read_bytes(ptr, 4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn write_image(&mut self, buffer: &PixelBuffer) -> Result<(i32, i32, i32)> {
+        let ptr = self.write_bytes(&buffer.to_rgba())?;
+        Ok((ptr, buffer.width as i32, buffer.height as i32))
+    }
+
+    /// Read back an image the plugin edited in place
+    fn read_image(&self, ptr: i32, width: u32, height: u32) -> Result<PixelBuffer> {
+        let data = self.read_bytes(ptr, width as usize * height as usize * 4)?;
+        PixelBuffer::from_rgba(width, height, &data).ok_or(AipixError::OutOfBounds)
+    }
+}
+
+fn caller_memory(caller: &Caller<'_, HostState>) -> std::result::Result<Memory, wasmi::Error> {
+    caller
+        .get_export("memory")
+        .and_then(Extern::into_memory)
+        .ok_or_else(|| wasmi::Error::new("plugin does not export its memory"))
+}
+
+fn define_host_functions(linker: &mut Linker<HostState>) -> std::result::Result<(), wasmi::Error> {
+    linker.func_wrap(
+        "aipix",
+        "log",
+        |caller: Caller<'_, HostState>, level: i32, ptr: i32, len: i32| {
+            let memory = caller_memory(&caller)?;
+            let mut bytes = vec![0; (len.max(0) as usize).min(MAX_LOG_BYTES)];
+            memory
+                .read(&caller, ptr as u32 as usize, &mut bytes)
+                .map_err(|e| wasmi::Error::new(e.to_string()))?;
+
+            let plugin = &caller.data().plugin_id;
+            let message = String::from_utf8_lossy(&bytes);
+            match level {
+                0 => tracing::error!(plugin, "{}", message),
+                1 => tracing::warn!(plugin, "{}", message),
+                2 => tracing::info!(plugin, "{}", message),
+                _ => tracing::debug!(plugin, "{}", message),
+            }
+            Ok(())
+        },
+    )?;
+
+    linker.func_wrap("aipix", "palette_len", |caller: Caller<'_, HostState>| {
+        caller.data().context.palette.len() as i32
+    })?;
+
+    linker.func_wrap("aipix", "palette_get", |caller: Caller<'_, HostState>, index: i32| {
+        usize::try_from(index)
+            .ok()
+            .and_then(|index| caller.data().context.palette.get(index).copied())
+            .map_or(0, pack_color)
+    })?;
+
+    linker.func_wrap(
+        "aipix",
+        "is_selected",
+        |caller: Caller<'_, HostState>, x: i32, y: i32| -> i32 {
+            match &caller.data().context.selection {
+                Some(selection) if x >= 0 && y >= 0 => selection.is_selected(x as u32, y as u32) as i32,
+                Some(_) => 0,
+                None => 1,
+            }
+        },
+    )?;
+    Ok(())
+}
+
+/// Run a filter over `buffer`, returning the filtered copy
+pub fn run_filter(
+    plugin: &LoadedPlugin,
+    buffer: &PixelBuffer,
+    params: &serde_json::Value,
+    context: HostContext,
+) -> Result<PixelBuffer> {
+    let mut instance = Instance::new(plugin, context, FUEL_LIMIT)?;
+    let (pixels, width, height) = instance.write_image(buffer)?;
+    let params = serde_json::to_vec(params)?;
+    let params_len = params.len() as i32;
+    let params_ptr = instance.write_bytes(&params)?;
+
+    let status: i32 = instance.call("aipix_filter", (pixels, width, height, params_ptr, params_len))?;
+    instance.check_status("aipix_filter", status)?;
+    instance.read_image(pixels, buffer.width, buffer.height)
+}
+
+/// Apply a tool at (x, y) with `color`, returning the edited copy
+pub fn run_tool(
+    plugin: &LoadedPlugin,
+    buffer: &PixelBuffer,
+    x: u32,
+    y: u32,
+    color: [u8; 4],
+    context: HostContext,
+) -> Result<PixelBuffer> {
+    let mut instance = Instance::new(plugin, context, TOOL_FUEL_LIMIT)?;
+    let (pixels, width, height) = instance.write_image(buffer)?;
+
+    let status: i32 = instance.call(
+        "aipix_tool",
+        (pixels, width, height, x as i32, y as i32, pack_color(color)),
+    )?;
+    instance.check_status("aipix_tool", status)?;
+    instance.read_image(pixels, buffer.width, buffer.height)
+}
+
+/// Decode file bytes with an importer plugin
+pub fn run_import(plugin: &LoadedPlugin, bytes: &[u8]) -> Result<PixelBuffer> {
+    let mut instance = Instance::new(plugin, HostContext::default(), FUEL_LIMIT)?;
+    let data = instance.write_bytes(bytes)?;
+    let len = bytes.len() as i32;
+
+    let result: i32 = instance.call("aipix_import", (data, len))?;
+    if result == 0 {
+        return Err(AipixError::PluginError(format!("{} could not import the file", instance.name)));
+    }
+
+    let width = instance.read_u32(result)?;
+    let height = instance.read_u32(result.wrapping_add(4))?;
+    // The image has to fit in plugin memory, which bounds the copy below
+    let bytes = width as u64 * height as u64 * 4;
+    if bytes == 0 || bytes > MEMORY_LIMIT as u64 {
+        return Err(AipixError::PluginError(format!(
+            "{} returned an invalid {}x{} image",
+            instance.name, width, height
+        )));
+    }
+    instance.read_image(result.wrapping_add(8), width, height)
+}
+
+/// Encode an image with an exporter plugin
+pub fn run_export(plugin: &LoadedPlugin, buffer: &PixelBuffer) -> Result<Vec<u8>> {
+    let mut instance = Instance::new(plugin, HostContext::default(), FUEL_LIMIT)?;
+    let (pixels, width, height) = instance.write_image(buffer)?;
+
+    let result: i32 = instance.call("aipix_export", (pixels, width, height))?;
+    if result == 0 {
+        return Err(AipixError::PluginError(format!("{} could not export the image", instance.name)));
+    }
+
+    let len = instance.read_u32(result)?;
+    instance.read_bytes(result.wrapping_add(4), len as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugins::{Capability, PluginManifest};
+
+    // Inverts every byte, then logs an empty message
+    const INVERT_WAT: &str = r#"
+        (module
+          (import "aipix" "log" (func $log (param i32 i32 i32)))
+          (memory (export "memory") 1)
+          (global $next (mut i32) (i32.const 1024))
+          (func (export "aipix_alloc") (param $len i32) (result i32)
+            (local $ptr i32)
+            (local.set $ptr (global.get $next))
+            (global.set $next (i32.add (global.get $next) (local.get $len)))
+            (local.get $ptr))
+          (func (export "aipix_filter")
+            (param $px i32) (param $w i32) (param $h i32) (param $p i32) (param $plen i32)
+            (result i32)
+            (local $i i32) (local $end i32)
+            (local.set $i (local.get $px))
+            (local.set $end
+              (i32.add (local.get $px) (i32.mul (i32.mul (local.get $w) (local.get $h)) (i32.const 4))))
+            (block $done
+              (loop $next
+                (br_if $done (i32.ge_u (local.get $i) (local.get $end)))
+                (i32.store8 (local.get $i) (i32.sub (i32.const 255) (i32.load8_u (local.get $i))))
+                (local.set $i (i32.add (local.get $i) (i32.const 1)))
+                (br $next)))
+            (call $log (i32.const 2) (i32.const 0) (i32.const 0))
+            (i32.const 0)))
+    "#;
+
+    // Exports a length far past the end of its memory
+    const OVERLONG_EXPORT_WAT: &str = r#"
+        (module
+          (memory (export "memory") 1)
+          (data (i32.const 16) "\ff\ff\ff\7f")
+          (func (export "aipix_alloc") (param $len i32) (result i32) (i32.const 1024))
+          (func (export "aipix_export") (param $px i32) (param $w i32) (param $h i32) (result i32)
+            (i32.const 16)))
+    "#;
+
+    fn invert_plugin(granted: Vec<Permission>) -> (LoadedPlugin, std::path::PathBuf) {
+        wat_plugin(INVERT_WAT, Capability::Filter, granted)
+    }
+
+    fn wat_plugin(wat: &str, capability: Capability, granted: Vec<Permission>) -> (LoadedPlugin, std::path::PathBuf) {
+        let path = std::env::temp_dir().join(format!("aipix-plugin-{}.wasm", uuid::Uuid::new_v4()));
+        std::fs::write(&path, wat::parse_str(wat).unwrap()).unwrap();
+
+        let plugin = LoadedPlugin {
+            manifest: PluginManifest {
+                id: "test.invert".to_string(),
+                name: "Invert".to_string(),
+                version: "1.0.0".to_string(),
+                description: String::new(),
+                api_version: 1,
+                entry: "invert.wasm".to_string(),
+                capabilities: vec![capability],
+                permissions: vec![Permission::Log],
+                file_extensions: Vec::new(),
+            },
+            wasm: path.clone(),
+            granted,
+        };
+        (plugin, path)
+    }
+
+    #[test]
+    fn test_run_filter() {
+        let (plugin, path) = invert_plugin(vec![Permission::Log]);
+        let mut buffer = PixelBuffer::new(2, 2);
+        buffer.set_pixel(0, 0, [255, 0, 0, 255]).unwrap();
+
+        let result =
+            run_filter(&plugin, &buffer, &serde_json::Value::Null, HostContext::default()).unwrap();
+        assert_eq!(result.get_pixel(0, 0), Some([0, 255, 255, 0]));
+        assert_eq!(result.get_pixel(1, 1), Some([255, 255, 255, 255]));
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_ungranted_permission_is_refused() {
+        let (plugin, path) = invert_plugin(Vec::new());
+        let buffer = PixelBuffer::new(2, 2);
+
+        let error = run_filter(&plugin, &buffer, &serde_json::Value::Null, HostContext::default())
+            .unwrap_err();
+        assert_eq!(error.code(), "invalid_state");
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_export_length_is_bounded_by_memory() {
+        let (plugin, path) = wat_plugin(OVERLONG_EXPORT_WAT, Capability::Exporter, Vec::new());
+        let buffer = PixelBuffer::new(2, 2);
+
+        let error = run_export(&plugin, &buffer).unwrap_err();
+        assert_eq!(error.code(), "plugin_error");
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
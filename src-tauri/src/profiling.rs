@@ -0,0 +1,173 @@
+// Lightweight operation profiling
+//
+// Commands wrap hot engine calls in `Profiler::time`; get_performance_report
+// turns the collected timings into histograms so slow setups can be
+// diagnosed from inside the app without attaching a profiler.
+
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Histogram bucket upper bounds, in milliseconds (a final bucket catches the rest)
+const BUCKET_BOUNDS_MS: [f64; 12] = [
+    0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 25.0, 50.0, 100.0, 250.0, 1000.0,
+];
+
+/// Samples kept per operation for percentiles
+const RECENT_SAMPLES: usize = 256;
+
+#[derive(Debug, Default)]
+struct Timings {
+    count: u64,
+    total: Duration,
+    min: Duration,
+    max: Duration,
+    buckets: [u64; BUCKET_BOUNDS_MS.len() + 1],
+    recent: VecDeque<Duration>,
+}
+
+impl Timings {
+    fn record(&mut self, elapsed: Duration) {
+        if self.count == 0 || elapsed < self.min {
+            self.min = elapsed;
+        }
+        self.max = self.max.max(elapsed);
+        self.count += 1;
+        self.total += elapsed;
+
+        let ms = as_ms(elapsed);
+        let bucket = BUCKET_BOUNDS_MS
+            .iter()
+            .position(|&bound| ms <= bound)
+            .unwrap_or(BUCKET_BOUNDS_MS.len());
+        self.buckets[bucket] += 1;
+
+        if self.recent.len() == RECENT_SAMPLES {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(elapsed);
+    }
+
+    fn stats(&self, operation: &str) -> OperationStats {
+        let mut recent: Vec<Duration> = self.recent.iter().copied().collect();
+        recent.sort();
+        let percentile = |p: f64| {
+            let index = ((recent.len() - 1) as f64 * p).round() as usize;
+            as_ms(recent[index])
+        };
+
+        OperationStats {
+            operation: operation.to_string(),
+            count: self.count,
+            total_ms: as_ms(self.total),
+            mean_ms: as_ms(self.total) / self.count as f64,
+            min_ms: as_ms(self.min),
+            max_ms: as_ms(self.max),
+            p50_ms: percentile(0.5),
+            p95_ms: percentile(0.95),
+            histogram: self
+                .buckets
+                .iter()
+                .enumerate()
+                .map(|(i, &count)| HistogramBucket {
+                    le_ms: BUCKET_BOUNDS_MS.get(i).copied(),
+                    count,
+                })
+                .collect(),
+        }
+    }
+}
+
+fn as_ms(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HistogramBucket {
+    pub le_ms: Option<f64>, // None = no upper bound
+    pub count: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct OperationStats {
+    pub operation: String,
+    pub count: u64,
+    pub total_ms: f64,
+    pub mean_ms: f64,
+    pub min_ms: f64,
+    pub max_ms: f64,
+    pub p50_ms: f64, // Percentiles cover the most recent samples only
+    pub p95_ms: f64,
+    pub histogram: Vec<HistogramBucket>,
+}
+
+/// Collects timings per named operation
+#[derive(Debug, Default)]
+pub struct Profiler {
+    timings: Mutex<HashMap<&'static str, Timings>>,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run `f` and record how long it took under `operation`
+    pub fn time<T>(&self, operation: &'static str, f: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let result = f();
+        self.record(operation, start.elapsed());
+        result
+    }
+
+    pub fn record(&self, operation: &'static str, elapsed: Duration) {
+        self.timings
+            .lock()
+            .unwrap()
+            .entry(operation)
+            .or_default()
+            .record(elapsed);
+    }
+
+    /// Stats for every operation recorded so far, sorted by name
+    pub fn stats(&self) -> Vec<OperationStats> {
+        let timings = self.timings.lock().unwrap();
+        let mut stats: Vec<OperationStats> = timings
+            .iter()
+            .map(|(operation, timings)| timings.stats(operation))
+            .collect();
+        stats.sort_by(|a, b| a.operation.cmp(&b.operation));
+        stats
+    }
+
+    pub fn reset(&self) {
+        self.timings.lock().unwrap().clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_histogram() {
+        let profiler = Profiler::new();
+        profiler.record("fill", Duration::from_micros(50));
+        profiler.record("fill", Duration::from_millis(3));
+        profiler.record("fill", Duration::from_secs(2));
+
+        let stats = profiler.stats();
+        assert_eq!(stats.len(), 1);
+
+        let fill = &stats[0];
+        assert_eq!(fill.count, 3);
+        assert_eq!(fill.min_ms, 0.05);
+        assert_eq!(fill.max_ms, 2000.0);
+        assert_eq!(fill.p50_ms, 3.0);
+        assert_eq!(fill.histogram[0].count, 1); // <= 0.1 ms
+        assert_eq!(fill.histogram[5].count, 1); // <= 5 ms
+        assert_eq!(fill.histogram.last().unwrap().count, 1); // Unbounded
+        assert_eq!(fill.histogram.last().unwrap().le_ms, None);
+    }
+}
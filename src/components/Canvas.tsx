@@ -4,6 +4,7 @@ import { ColorPicker } from "./ColorPicker";
 import { LayerPanel, Layer, BlendMode } from "./LayerPanel";
 import { NativeSkiaRenderer } from "../utils/nativeRenderer";
 import { SelectionOverlay } from "./SelectionOverlay";
+import { runCanvasJob } from "../lib/canvasJobs";
 
 interface SelectionBounds {
   min_x: number;
@@ -15,7 +16,7 @@ interface SelectionBounds {
 interface Selection {
   width: number;
   height: number;
-  mask: boolean[];
+  mask: number[]; // Coverage per pixel: 0 unselected, 255 fully selected
   bounds: SelectionBounds | null;
 }
 
@@ -739,12 +740,12 @@ export const Canvas = ({
 
     if (selectedTool === "eyedropper") {
       try {
-        const color: string = await invoke("pick_color", {
+        const picked: { color: string; alpha: number; index: number | null } = await invoke("pick_color", {
           projectId,
           x,
           y,
         });
-        setSelectedColor(color);
+        setSelectedColor(picked.color);
       } catch (error) {
         console.error("Failed to pick color:", error);
       }
@@ -753,7 +754,7 @@ export const Canvas = ({
 
     if (selectedTool === "fill") {
       try {
-        await invoke("draw_fill", {
+        await runCanvasJob("draw_fill", {
           projectId,
           x,
           y,
@@ -792,7 +793,7 @@ export const Canvas = ({
     if (selectedTool === "selectWand") {
       // Magic wand selection
       try {
-        const result: Selection = await invoke("select_magic_wand", {
+        const result = await runCanvasJob<Selection>("select_magic_wand", {
           projectId,
           x,
           y,
@@ -1,5 +1,6 @@
 import { useState, useRef, useEffect, useMemo, useCallback, memo, Suspense, lazy } from "react";
 import { invoke } from "@tauri-apps/api/core";
+import { errorMessage } from "../lib/errors";
 import { ProjectCard } from "./ProjectCard";
 import { FolderCard } from "./FolderCard";
 
@@ -87,7 +88,7 @@ export const Dashboard = memo(({ onOpenSettings, onOpenProject }: DashboardProps
     try {
       // Load in parallel for better performance
       const [projectsData, foldersData] = await Promise.all([
-        invoke("get_user_projects", { userId }) as Promise<any[]>,
+        invoke("get_user_projects", { userId, archived: false }) as Promise<any[]>,
         invoke("get_user_folders", { userId }) as Promise<any[]>,
       ]);
 
@@ -224,7 +225,7 @@ export const Dashboard = memo(({ onOpenSettings, onOpenProject }: DashboardProps
         },
       }).catch((error: any) => {
         console.error("Failed to save project:", error);
-        alert(`Failed to save project: ${error}`);
+        alert(`Failed to save project: ${errorMessage(error)}`);
         setProjects((prev) => prev.filter((p) => p.id !== projectId));
       });
     },
@@ -275,7 +276,7 @@ export const Dashboard = memo(({ onOpenSettings, onOpenProject }: DashboardProps
         },
       }).catch((error: any) => {
         console.error("Failed to save folder:", error);
-        alert(`Failed to save folder: ${error}`);
+        alert(`Failed to save folder: ${errorMessage(error)}`);
         setFolders((prev) => prev.filter((f) => f.id !== folderId));
       });
     },
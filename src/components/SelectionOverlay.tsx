@@ -10,7 +10,7 @@ interface SelectionBounds {
 interface Selection {
   width: number;
   height: number;
-  mask: boolean[];
+  mask: number[]; // Coverage per pixel: 0 unselected, 255 fully selected
   bounds: SelectionBounds | null;
 }
 
@@ -1,6 +1,7 @@
 import { useState, useEffect } from "react";
 import { invoke } from "@tauri-apps/api/core";
 import { supabase } from "../lib/supabase";
+import { errorMessage } from "../lib/errors";
 
 interface SettingsProps {
   onBack: () => void;
@@ -70,7 +71,7 @@ export const Settings = ({ onBack }: SettingsProps) => {
       alert("Profile updated successfully!");
     } catch (error: any) {
       console.error("Failed to update profile:", error);
-      alert(`Failed to update profile: ${error}`);
+      alert(`Failed to update profile: ${errorMessage(error)}`);
     } finally {
       setIsSaving(false);
     }
@@ -0,0 +1,49 @@
+// Canvas change events emitted by the backend after mutating commands
+import { listen, type UnlistenFn } from '@tauri-apps/api/event';
+
+export interface DocumentChanged {
+  project_id: string;
+  width: number;
+  height: number;
+}
+
+export interface HistoryChanged {
+  project_id: string;
+  can_undo: boolean;
+  can_redo: boolean;
+  undo_count: number;
+  redo_count: number;
+}
+
+export interface SelectionBounds {
+  min_x: number;
+  max_x: number;
+  min_y: number;
+  max_y: number;
+}
+
+// Fetch the mask with get_selection when needed; ignore fetches started before a later revision
+export interface SelectionChanged {
+  project_id: string;
+  bounds: SelectionBounds | null;
+  revision: number;
+}
+
+function forProject<T extends { project_id: string }>(
+  event: string,
+  projectId: string,
+  handler: (payload: T) => void
+): Promise<UnlistenFn> {
+  return listen<T>(event, ({ payload }) => {
+    if (payload.project_id === projectId) handler(payload);
+  });
+}
+
+export const onDocumentChanged = (projectId: string, handler: (payload: DocumentChanged) => void) =>
+  forProject('document:changed', projectId, handler);
+
+export const onHistoryChanged = (projectId: string, handler: (payload: HistoryChanged) => void) =>
+  forProject('history:changed', projectId, handler);
+
+export const onSelectionChanged = (projectId: string, handler: (payload: SelectionChanged) => void) =>
+  forProject('selection:changed', projectId, handler);
@@ -0,0 +1,50 @@
+// Background canvas jobs
+//
+// Heavy canvas commands return a job id immediately and report completion
+// through the `canvas-job-finished` event instead of blocking the IPC call.
+import { invoke } from '@tauri-apps/api/core';
+import { listen } from '@tauri-apps/api/event';
+import type { AipixError } from './errors';
+
+export const CANVAS_JOB_FINISHED = 'canvas-job-finished';
+
+export interface CanvasJobFinished<T> {
+  job_id: string;
+  project_id: string;
+  operation: string;
+  result: T | null;
+  error: AipixError | null;
+}
+
+/**
+ * Invoke a job command and resolve once its completion event arrives
+ */
+export async function runCanvasJob<T = null>(
+  command: string,
+  args: Record<string, unknown>
+): Promise<T> {
+  let jobId: string | null = null;
+  // Events that arrive before the command has returned its id
+  const early = new Map<string, CanvasJobFinished<T>>();
+  let settle: (job: CanvasJobFinished<T>) => void = () => {};
+  const finished = new Promise<CanvasJobFinished<T>>((resolve) => {
+    settle = resolve;
+  });
+
+  const unlisten = await listen<CanvasJobFinished<T>>(CANVAS_JOB_FINISHED, ({ payload }) => {
+    if (jobId === null) {
+      early.set(payload.job_id, payload);
+    } else if (payload.job_id === jobId) {
+      settle(payload);
+    }
+  });
+
+  try {
+    jobId = await invoke<string>(command, args);
+    const job = early.get(jobId) ?? (await finished);
+    if (job.error) throw job.error;
+    return job.result as T;
+  } finally {
+    unlisten();
+  }
+}
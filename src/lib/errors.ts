@@ -0,0 +1,91 @@
+// Errors returned by Tauri commands
+//
+// The backend serializes failures as `{ code, params, message }` so callers
+// can branch on `code` instead of matching message text. `code` is also the
+// key into the message catalog below; `message` is the backend's English
+// fallback for codes the catalog doesn't know yet.
+
+export type AipixErrorCode =
+  | 'not_found'
+  | 'out_of_bounds'
+  | 'invalid_input'
+  | 'invalid_state'
+  | 'conflict'
+  | 'cancelled'
+  | 'database_not_initialized'
+  | 'db_error'
+  | 'renderer_not_initialized'
+  | 'renderer_error'
+  | 'provider_not_configured'
+  | 'provider_error'
+  | 'plugin_error'
+  | 'io_error'
+  | 'image_error'
+  | 'serialization_error'
+  | 'internal';
+
+export interface AipixError {
+  code: AipixErrorCode;
+  params?: Record<string, string>;
+  message: string;
+}
+
+/**
+ * Messages by error code; `{name}` is replaced by the matching param
+ */
+export type MessageCatalog = Partial<Record<AipixErrorCode, string>>;
+
+const ENGLISH: MessageCatalog = {
+  not_found: '{item} not found',
+  out_of_bounds: 'Coordinates out of bounds',
+  invalid_input: '{detail}',
+  invalid_state: '{detail}',
+  conflict: '{detail}',
+  cancelled: 'Operation cancelled',
+  database_not_initialized: 'Database not initialized',
+  db_error: 'The database could not complete the request',
+  renderer_not_initialized: 'Renderer not initialized',
+  renderer_error: 'The canvas could not be rendered',
+  provider_not_configured: 'AI provider not configured',
+  provider_error: 'AI provider error: {detail}',
+  plugin_error: 'Plugin error: {detail}',
+  io_error: 'A file could not be read or written',
+  image_error: 'The image could not be read or written',
+  serialization_error: 'The data could not be read or written',
+  internal: 'Something went wrong',
+};
+
+let catalog: MessageCatalog = ENGLISH;
+
+/**
+ * Use `messages` for error text, falling back to English for missing codes
+ */
+export function setErrorCatalog(messages: MessageCatalog) {
+  catalog = { ...ENGLISH, ...messages };
+}
+
+export function isAipixError(error: unknown): error is AipixError {
+  return (
+    typeof error === 'object' &&
+    error !== null &&
+    'code' in error &&
+    'message' in error
+  );
+}
+
+function localize(error: AipixError): string {
+  const template = catalog[error.code];
+  if (!template) return error.message;
+  return template.replace(/\{(\w+)\}/g, (placeholder, name: string) =>
+    error.params?.[name] ?? placeholder
+  );
+}
+
+/**
+ * Human-readable message for any error thrown by `invoke`
+ */
+export function errorMessage(error: unknown): string {
+  if (isAipixError(error)) return localize(error);
+  if (error instanceof Error) return error.message;
+  return String(error);
+}
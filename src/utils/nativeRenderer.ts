@@ -74,10 +74,7 @@ export class NativeSkiaRenderer {
     }
 
     await invoke('fill_rect', {
-      x,
-      y,
-      width,
-      height,
+      rect: { x, y, width, height },
       color,
       opacity
     });